@@ -0,0 +1,147 @@
+//! Capabilities of this version of the wallet, so frontends shipping against
+//! several versions can check for features at runtime instead of parsing the version.
+//!
+//! Each capability is declared next to the feature it describes and listed in
+//! [`REGISTRY`]. The registry is additive only: capabilities are never removed or
+//! renamed, flags are never turned off, versions only go up and items are never
+//! removed from lists. A test compares the registry against the committed snapshot
+//! in `test_vectors/capabilities.json`, which has to be updated for every addition.
+
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// The value of a capability.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CapabilityValue {
+    Flag(bool),
+    Version(u32),
+    List(&'static [&'static str]),
+}
+
+/// A feature the frontend can check for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capability {
+    pub name: &'static str,
+    pub value: CapabilityValue,
+}
+
+impl Capability {
+    /// A feature that is supported
+    pub const fn flag(name: &'static str) -> Self {
+        Self {
+            name,
+            value: CapabilityValue::Flag(true),
+        }
+    }
+
+    pub const fn version(name: &'static str, version: u32) -> Self {
+        Self {
+            name,
+            value: CapabilityValue::Version(version),
+        }
+    }
+
+    pub const fn list(name: &'static str, items: &'static [&'static str]) -> Self {
+        Self {
+            name,
+            value: CapabilityValue::List(items),
+        }
+    }
+}
+
+impl From<CapabilityValue> for Value {
+    fn from(value: CapabilityValue) -> Self {
+        match value {
+            CapabilityValue::Flag(flag) => Value::Bool(flag),
+            CapabilityValue::Version(version) => Value::from(version),
+            CapabilityValue::List(items) => Value::from(items.to_vec()),
+        }
+    }
+}
+
+/// Every capability, add new features here.
+const REGISTRY: &[Capability] = &[
+    crate::NETWORKS_CAPABILITY,
+    crate::error::ERROR_CODES_CAPABILITY,
+    crate::freshness::STRICT_MODE_CAPABILITY,
+    crate::lifecycle::LIFECYCLE_CAPABILITY,
+    crate::congestion::CONGESTION_BREAKER_CAPABILITY,
+    crate::lnurlauth::LNURL_AUTH_CAPABILITY,
+    crate::nostr::nwc::NWC_CAPABILITY,
+    crate::payment_privacy::PAYMENT_PRIVACY_CAPABILITY,
+    crate::redshift::REDSHIFT_CAPABILITY,
+    crate::recovery::RECOVERY_CAPABILITY,
+    crate::scb::SCB_FORMAT_CAPABILITY,
+    crate::scb::SCB_FEATURES_CAPABILITY,
+    crate::search::SEARCH_CAPABILITY,
+    crate::trace::TRACE_CAPABILITY,
+    crate::webhooks::WEBHOOKS_CAPABILITY,
+];
+
+/// Returns the capabilities by name, with their flag, version or list as JSON.
+pub fn capabilities() -> BTreeMap<&'static str, Value> {
+    REGISTRY.iter().map(|c| (c.name, c.value.into())).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    const SNAPSHOT: &str = include_str!("test_vectors/capabilities.json");
+
+    #[test]
+    fn test_capability_names_are_unique() {
+        let test_name = "test_capability_names_are_unique";
+        log!("{}", test_name);
+
+        assert_eq!(capabilities().len(), REGISTRY.len());
+    }
+
+    #[test]
+    fn test_capabilities_are_additive() {
+        let test_name = "test_capabilities_are_additive";
+        log!("{}", test_name);
+
+        let snapshot: BTreeMap<String, Value> = serde_json::from_str(SNAPSHOT).unwrap();
+        let current = capabilities();
+
+        for (name, old) in snapshot.iter() {
+            let new = current.get(name.as_str()).unwrap_or_else(|| {
+                panic!("capability {name} was removed or renamed, frontends rely on it")
+            });
+            match (old, new) {
+                (Value::Bool(old), Value::Bool(new)) => {
+                    assert!(!old || *new, "capability {name} was turned off")
+                }
+                (Value::Number(old), Value::Number(new)) => assert!(
+                    new.as_u64() >= old.as_u64(),
+                    "capability {name} went from version {old} to {new}"
+                ),
+                (Value::Array(old), Value::Array(new)) => {
+                    for item in old {
+                        assert!(
+                            new.contains(item),
+                            "{item} was removed from capability {name}"
+                        )
+                    }
+                }
+                (old, new) => panic!("capability {name} changed from {old} to {new}"),
+            }
+        }
+
+        // additions are fine, but have to be recorded so they are kept from now on
+        let current = serde_json::to_value(&current).unwrap();
+        let snapshot = serde_json::to_value(&snapshot).unwrap();
+        assert_eq!(
+            current,
+            snapshot,
+            "update test_vectors/capabilities.json to:\n{}",
+            serde_json::to_string_pretty(&current).unwrap()
+        );
+    }
+}
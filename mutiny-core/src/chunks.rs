@@ -0,0 +1,439 @@
+//! Chunked storage for large values that change a little at a time.
+//!
+//! The network graph and the scorer are several megabytes but only change in a
+//! few places between writes. Storing them whole makes IndexedDB copy the full
+//! value on every write. Above [`CHUNK_THRESHOLD`] they are instead split into
+//! content addressed chunks under [`CHUNK_PREFIX`], and the key holds a manifest
+//! listing them. Writes only store the chunks that are not in the previous
+//! manifest, reads put the value back together. This is done by
+//! [`MutinyStorage::set_data`] and [`MutinyStorage::get_data`], so callers never
+//! see the chunks.
+//!
+//! A write stores the new chunks first, then the manifest, then deletes the chunks
+//! only the previous manifest used. A crash in between leaves chunks no manifest
+//! refers to, they are removed by [`gc_chunks`]. Channel monitors are never chunked.
+
+use crate::error::{MutinyError, MutinyStorageError};
+use crate::gossip::{NETWORK_GRAPH_KEY, PROB_SCORER_KEY};
+use crate::storage::MutinyStorage;
+use anyhow::anyhow;
+use bitcoin::hashes::hex::ToHex;
+use bitcoin::hashes::{sha256, Hash};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+/// Chunks are stored under `chunk/<key>/<sha256 of the chunk>`.
+pub const CHUNK_PREFIX: &str = "chunk/";
+
+/// Values of chunked keys are only split once their JSON is larger than this.
+pub const CHUNK_THRESHOLD: usize = 256 * 1024;
+
+const MIN_CHUNK_LEN: usize = 16 * 1024;
+const MAX_CHUNK_LEN: usize = 256 * 1024;
+
+/// A chunk ends where the top 15 bits of the rolling hash are zero, so chunks
+/// are about 48 KiB on average with the minimum length.
+const BOUNDARY_MASK: u64 = ((1 << 15) - 1) << 49;
+
+/// The keys whose values are chunked once they are large enough.
+pub(crate) fn is_chunked_key(key: &str) -> bool {
+    matches!(key, NETWORK_GRAPH_KEY | PROB_SCORER_KEY)
+}
+
+/// If the key holds a chunk of a larger value.
+pub fn is_chunk_key(key: &str) -> bool {
+    key.starts_with(CHUNK_PREFIX)
+}
+
+fn chunk_key(key: &str, hash: &str) -> String {
+    format!("{CHUNK_PREFIX}{key}/{hash}")
+}
+
+/// Stored under the key of a chunked value, the hashes of its chunks in order.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ChunkManifest {
+    pub chunks: Vec<String>,
+    /// The length of the JSON of the whole value
+    pub len: usize,
+}
+
+impl ChunkManifest {
+    /// Chunked values are strings, so a stored object is always a manifest.
+    fn from_value(value: &Value) -> Option<Self> {
+        if value.is_object() {
+            serde_json::from_value(value.clone()).ok()
+        } else {
+            None
+        }
+    }
+}
+
+fn gear(byte: u8) -> u64 {
+    // splitmix64, any well mixed value per byte will do
+    let mut z = (byte as u64).wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// Splits the text where a rolling hash of the last 64 bytes hits a boundary.
+/// An insertion only changes the chunks around it instead of moving the
+/// boundaries of every chunk after it.
+fn split_chunks(text: &str) -> Vec<&str> {
+    let mut chunks = vec![];
+    let mut start = 0;
+    let mut hash: u64 = 0;
+    for (i, byte) in text.bytes().enumerate() {
+        hash = (hash << 1).wrapping_add(gear(byte));
+        let end = i + 1;
+        let len = end - start;
+        if len >= MIN_CHUNK_LEN
+            && (hash & BOUNDARY_MASK == 0 || len >= MAX_CHUNK_LEN)
+            && text.is_char_boundary(end)
+        {
+            chunks.push(&text[start..end]);
+            start = end;
+            hash = 0;
+        }
+    }
+    if start < text.len() {
+        chunks.push(&text[start..]);
+    }
+    chunks
+}
+
+fn read_manifest<S: MutinyStorage>(
+    storage: &S,
+    key: &str,
+) -> Result<Option<ChunkManifest>, MutinyError> {
+    Ok(storage
+        .get::<Value>(key)?
+        .and_then(|value| ChunkManifest::from_value(&value)))
+}
+
+/// Stores the chunks of the value that the previous manifest does not have,
+/// returning the new manifest and how many chunks were written.
+fn write_chunks<S: MutinyStorage>(
+    storage: &S,
+    key: &str,
+    text: &str,
+    previous: Option<&ChunkManifest>,
+) -> Result<(ChunkManifest, usize), MutinyError> {
+    let existing: HashSet<&String> = previous
+        .map(|m| m.chunks.iter().collect())
+        .unwrap_or_default();
+
+    let mut manifest = ChunkManifest {
+        chunks: vec![],
+        len: text.len(),
+    };
+    let mut written = HashSet::new();
+    for chunk in split_chunks(text) {
+        let hash = sha256::Hash::hash(chunk.as_bytes()).to_hex();
+        if !existing.contains(&hash) && written.insert(hash.clone()) {
+            storage.set(chunk_key(key, &hash), chunk)?;
+        }
+        manifest.chunks.push(hash);
+    }
+
+    Ok((manifest, written.len()))
+}
+
+/// Stores the value of a chunked key, returning how many chunks were written.
+/// Values below [`CHUNK_THRESHOLD`] are stored whole.
+pub(crate) fn set_chunked<S: MutinyStorage>(
+    storage: &S,
+    key: &str,
+    value: Value,
+) -> Result<usize, MutinyError> {
+    let previous = read_manifest(storage, key)?;
+    let text = serde_json::to_string(&value)?;
+
+    let (manifest, written) = if text.len() > CHUNK_THRESHOLD {
+        let (manifest, written) = write_chunks(storage, key, &text, previous.as_ref())?;
+        storage.set(key, &manifest)?;
+        (Some(manifest), written)
+    } else {
+        storage.set(key, value)?;
+        (None, 0)
+    };
+
+    if let Some(previous) = previous {
+        let used: HashSet<&String> = manifest.iter().flat_map(|m| &m.chunks).collect();
+        let unused: HashSet<String> = previous
+            .chunks
+            .iter()
+            .filter(|hash| !used.contains(hash))
+            .map(|hash| chunk_key(key, hash))
+            .collect();
+        let unused: Vec<String> = unused.into_iter().collect();
+        storage.delete(&unused)?;
+    }
+
+    Ok(written)
+}
+
+/// Puts a chunked value back together if the stored value is a manifest,
+/// otherwise returns the stored value as is.
+pub(crate) fn reassemble<S: MutinyStorage>(
+    storage: &S,
+    key: &str,
+    value: Value,
+) -> Result<Value, MutinyError> {
+    if !is_chunked_key(key) {
+        return Ok(value);
+    }
+    let Some(manifest) = ChunkManifest::from_value(&value) else {
+        return Ok(value);
+    };
+
+    // a chunk can be used more than once, but storage may only hand it out once
+    let mut chunks: HashMap<&str, String> = HashMap::new();
+    let mut text = String::with_capacity(manifest.len);
+    for hash in &manifest.chunks {
+        if !chunks.contains_key(hash.as_str()) {
+            let chunk: String = storage.get(chunk_key(key, hash))?.ok_or_else(|| {
+                MutinyError::read_err(MutinyStorageError::Other(anyhow!(
+                    "Missing chunk {hash} of {key}"
+                )))
+            })?;
+            chunks.insert(hash.as_str(), chunk);
+        }
+        text.push_str(&chunks[hash.as_str()]);
+    }
+
+    if text.len() != manifest.len {
+        return Err(MutinyError::read_err(MutinyStorageError::Other(anyhow!(
+            "Chunks of {key} do not add up to its length"
+        ))));
+    }
+    Ok(serde_json::from_str(&text)?)
+}
+
+/// Deletes the chunks no manifest refers to, left behind by a write that did not
+/// finish or by deleting a chunked key. Returns how many were deleted.
+pub(crate) fn gc_chunks<S: MutinyStorage>(storage: &S) -> Result<usize, MutinyError> {
+    // the chunks each key refers to, None if its value could not be read
+    let mut referenced: HashMap<String, Option<HashSet<String>>> = HashMap::new();
+    let mut orphans = vec![];
+    for chunk_key in storage.scan_keys(CHUNK_PREFIX, None)? {
+        let Some((key, hash)) = chunk_key[CHUNK_PREFIX.len()..].rsplit_once('/') else {
+            orphans.push(chunk_key);
+            continue;
+        };
+        let hashes = referenced.entry(key.to_string()).or_insert_with(|| {
+            read_manifest(storage, key).ok().map(|m| {
+                m.map(|m| m.chunks.into_iter().collect())
+                    .unwrap_or_default()
+            })
+        });
+        if hashes.as_ref().map_or(false, |h| !h.contains(hash)) {
+            orphans.push(chunk_key);
+        }
+    }
+
+    storage.delete(&orphans)?;
+    Ok(orphans.len())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ldkstorage::MONITORS_PREFIX_KEY;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    /// Random looking hex, like an encoded network graph.
+    fn graph_hex(len: usize, seed: u8) -> String {
+        let mut hash = sha256::Hash::hash(&[seed]);
+        let mut hex = String::with_capacity(len + 64);
+        while hex.len() < len {
+            hex.push_str(&hash.to_hex());
+            hash = sha256::Hash::hash(&hash[..]);
+        }
+        hex.truncate(len);
+        hex
+    }
+
+    fn chunk_count(storage: &MemoryStorage) -> usize {
+        storage.scan_keys(CHUNK_PREFIX, None).unwrap().len()
+    }
+
+    #[test]
+    fn test_chunked_round_trip() {
+        let test_name = "test_chunked_round_trip";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+
+        // small values are stored whole
+        storage.set_data(NETWORK_GRAPH_KEY, "00ff").unwrap();
+        assert_eq!(
+            storage.get::<String>(NETWORK_GRAPH_KEY).unwrap(),
+            Some("00ff".to_string())
+        );
+        assert_eq!(chunk_count(&storage), 0);
+
+        let graph = graph_hex(1_000_000, 1);
+        storage.set_data(NETWORK_GRAPH_KEY, &graph).unwrap();
+        let manifest = read_manifest(&storage, NETWORK_GRAPH_KEY).unwrap().unwrap();
+        assert!(manifest.chunks.len() > 10);
+        assert_eq!(chunk_count(&storage), manifest.chunks.len());
+        assert_eq!(
+            storage.get_data::<String>(NETWORK_GRAPH_KEY).unwrap(),
+            Some(graph.clone())
+        );
+        // and through a scan
+        let scanned = storage.scan::<String>(NETWORK_GRAPH_KEY, None).unwrap();
+        assert_eq!(scanned.get(NETWORK_GRAPH_KEY), Some(&graph));
+
+        // going back below the threshold removes the chunks
+        storage.set_data(NETWORK_GRAPH_KEY, "00ff").unwrap();
+        assert_eq!(
+            storage.get_data::<String>(NETWORK_GRAPH_KEY).unwrap(),
+            Some("00ff".to_string())
+        );
+        assert_eq!(chunk_count(&storage), 0);
+
+        // other keys are never chunked, monitors included
+        let monitor_key = format!("{MONITORS_PREFIX_KEY}abc");
+        assert!(!is_chunked_key(&monitor_key));
+        storage.set_data(&monitor_key, &graph).unwrap();
+        assert_eq!(storage.get::<String>(&monitor_key).unwrap(), Some(graph));
+        assert_eq!(chunk_count(&storage), 0);
+
+        // a missing chunk fails the read
+        let graph = graph_hex(500_000, 2);
+        storage.set_data(PROB_SCORER_KEY, &graph).unwrap();
+        let manifest = read_manifest(&storage, PROB_SCORER_KEY).unwrap().unwrap();
+        storage
+            .delete(&[chunk_key(PROB_SCORER_KEY, &manifest.chunks[1])])
+            .unwrap();
+        assert!(storage.get_data::<String>(PROB_SCORER_KEY).is_err());
+    }
+
+    #[test]
+    fn test_chunked_partial_update() {
+        let test_name = "test_chunked_partial_update";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let graph = graph_hex(2_000_000, 3);
+        let whole = set_chunked(&storage, NETWORK_GRAPH_KEY, Value::String(graph.clone())).unwrap();
+        assert_eq!(whole, chunk_count(&storage));
+        assert!(whole > 20);
+
+        // rewriting the same value writes nothing
+        let written =
+            set_chunked(&storage, NETWORK_GRAPH_KEY, Value::String(graph.clone())).unwrap();
+        assert_eq!(written, 0);
+
+        // a change in place only rewrites the chunks around it
+        let mut changed = graph.clone();
+        changed.replace_range(1_000_000..1_000_008, "deadbeef");
+        let written =
+            set_chunked(&storage, NETWORK_GRAPH_KEY, Value::String(changed.clone())).unwrap();
+        assert!((1..=2).contains(&written), "{written} of {whole}");
+        assert_eq!(
+            storage.get_data::<String>(NETWORK_GRAPH_KEY).unwrap(),
+            Some(changed.clone())
+        );
+
+        // so does an insertion, the chunks after it keep their boundaries
+        let mut inserted = changed.clone();
+        inserted.insert_str(500_000, &graph_hex(1_000, 4));
+        let written =
+            set_chunked(&storage, NETWORK_GRAPH_KEY, Value::String(inserted.clone())).unwrap();
+        assert!((1..=3).contains(&written), "{written} of {whole}");
+        assert_eq!(
+            storage.get_data::<String>(NETWORK_GRAPH_KEY).unwrap(),
+            Some(inserted)
+        );
+
+        // the replaced chunks were deleted right away
+        let manifest = read_manifest(&storage, NETWORK_GRAPH_KEY).unwrap().unwrap();
+        let unique: HashSet<&String> = manifest.chunks.iter().collect();
+        assert_eq!(chunk_count(&storage), unique.len());
+    }
+
+    #[test]
+    fn test_chunk_gc() {
+        let test_name = "test_chunk_gc";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let graph = graph_hex(1_000_000, 5);
+        storage.set_data(NETWORK_GRAPH_KEY, &graph).unwrap();
+        storage
+            .set_data(PROB_SCORER_KEY, graph_hex(400_000, 6))
+            .unwrap();
+        let chunks = chunk_count(&storage);
+        assert_eq!(gc_chunks(&storage).unwrap(), 0);
+        assert_eq!(chunk_count(&storage), chunks);
+
+        // deleting a chunked key orphans its chunks
+        storage.delete(&[PROB_SCORER_KEY]).unwrap();
+        let graph_chunks = read_manifest(&storage, NETWORK_GRAPH_KEY)
+            .unwrap()
+            .unwrap()
+            .chunks
+            .len();
+        assert!(gc_chunks(&storage).unwrap() > 0);
+        assert_eq!(chunk_count(&storage), graph_chunks);
+        assert_eq!(
+            storage.get_data::<String>(NETWORK_GRAPH_KEY).unwrap(),
+            Some(graph)
+        );
+
+        // so does storing the key whole
+        storage.set(chunk_key("other", "0000"), "chunk").unwrap();
+        storage.set_data("other", "not a manifest").unwrap();
+        assert_eq!(gc_chunks(&storage).unwrap(), 1);
+        assert_eq!(chunk_count(&storage), graph_chunks);
+    }
+
+    #[test]
+    fn test_chunked_write_crash_safety() {
+        let test_name = "test_chunked_write_crash_safety";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let old = graph_hex(1_000_000, 7);
+        storage.set_data(NETWORK_GRAPH_KEY, &old).unwrap();
+        let old_manifest = read_manifest(&storage, NETWORK_GRAPH_KEY).unwrap().unwrap();
+        let old_chunks = chunk_count(&storage);
+
+        // the tab dies after the new chunks were written, before the manifest
+        let new = graph_hex(1_000_000, 8);
+        let text = serde_json::to_string(&new).unwrap();
+        let (new_manifest, written) =
+            write_chunks(&storage, NETWORK_GRAPH_KEY, &text, Some(&old_manifest)).unwrap();
+        assert!(written > 0);
+        // the old value is still read in full
+        assert_eq!(
+            storage.get_data::<String>(NETWORK_GRAPH_KEY).unwrap(),
+            Some(old.clone())
+        );
+        // and the new chunks are collected
+        assert_eq!(gc_chunks(&storage).unwrap(), written);
+        assert_eq!(chunk_count(&storage), old_chunks);
+
+        // the tab dies after the manifest was written, before the old chunks were deleted
+        write_chunks(&storage, NETWORK_GRAPH_KEY, &text, Some(&old_manifest)).unwrap();
+        storage.set(NETWORK_GRAPH_KEY, &new_manifest).unwrap();
+        assert_eq!(
+            storage.get_data::<String>(NETWORK_GRAPH_KEY).unwrap(),
+            Some(new.clone())
+        );
+        assert_eq!(gc_chunks(&storage).unwrap(), old_chunks);
+        assert_eq!(
+            storage.get_data::<String>(NETWORK_GRAPH_KEY).unwrap(),
+            Some(new)
+        );
+    }
+}
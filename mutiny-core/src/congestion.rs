@@ -0,0 +1,356 @@
+use crate::capabilities::Capability;
+use crate::error::MutinyError;
+use crate::utils;
+use lightning::chain::chaininterface::ConfirmationTarget;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+pub(crate) const CONGESTION_BREAKER_CAPABILITY: Capability = Capability::flag("congestion_breaker");
+
+/// JIT channels above this size are deferred by default while fees are high.
+pub const DEFAULT_LARGE_JIT_CHANNEL_SATS: u64 = 1_000_000;
+
+/// Number of deferred operations that are kept in the status.
+const MAX_DEFERRED_OPERATIONS: usize = 20;
+
+/// Channel operations that the congestion breaker knows about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelOperation {
+    /// Opening a new outbound channel
+    OpenChannel,
+    /// Accepting an inbound zero-conf channel from the LSP
+    AcceptJitChannel { amount_sats: u64 },
+    /// Sweeping the outputs of a force closed channel
+    ForceCloseHandling,
+    /// Punishing a counterparty that broadcast a revoked state
+    Justice,
+}
+
+impl ChannelOperation {
+    /// Essential operations protect funds that are already in channels,
+    /// so they are never deferred.
+    pub fn is_essential(&self) -> bool {
+        matches!(self, Self::ForceCloseHandling | Self::Justice)
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::OpenChannel => "open_channel",
+            Self::AcceptJitChannel { .. } => "accept_jit_channel",
+            Self::ForceCloseHandling => "force_close_handling",
+            Self::Justice => "justice",
+        }
+    }
+}
+
+/// The fee estimate the breaker watches.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CongestionFeeTarget {
+    Background,
+    #[default]
+    Normal,
+    HighPriority,
+}
+
+impl From<CongestionFeeTarget> for ConfirmationTarget {
+    fn from(target: CongestionFeeTarget) -> Self {
+        match target {
+            CongestionFeeTarget::Background => ConfirmationTarget::Background,
+            CongestionFeeTarget::Normal => ConfirmationTarget::Normal,
+            CongestionFeeTarget::HighPriority => ConfirmationTarget::HighPriority,
+        }
+    }
+}
+
+/// Settings for deferring new channels while on-chain fees are high. The breaker
+/// trips once the fee estimate reaches `trip_sats_per_vbyte` and only resumes once
+/// it falls to `resume_sats_per_vbyte`, so it does not flap around the threshold.
+/// `None` for the trip rate disables the breaker, which is the default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CongestionPolicy {
+    pub trip_sats_per_vbyte: Option<u32>,
+    /// Defaults to three quarters of the trip rate
+    pub resume_sats_per_vbyte: Option<u32>,
+    pub fee_target: CongestionFeeTarget,
+    /// JIT channels up to this size are still accepted while tripped
+    pub large_jit_channel_sats: u64,
+}
+
+impl Default for CongestionPolicy {
+    fn default() -> Self {
+        Self {
+            trip_sats_per_vbyte: None,
+            resume_sats_per_vbyte: None,
+            fee_target: CongestionFeeTarget::default(),
+            large_jit_channel_sats: DEFAULT_LARGE_JIT_CHANNEL_SATS,
+        }
+    }
+}
+
+impl CongestionPolicy {
+    pub fn is_enabled(&self) -> bool {
+        self.trip_sats_per_vbyte.is_some()
+    }
+
+    /// The fee rate the estimate has to fall to before resuming, never above the trip rate.
+    pub fn resume_rate(&self) -> Option<u32> {
+        let trip = self.trip_sats_per_vbyte?;
+        Some(self.resume_sats_per_vbyte.unwrap_or(trip * 3 / 4).min(trip))
+    }
+
+    /// Returns true if the operation has to wait while the breaker is tripped
+    pub fn defers(&self, op: ChannelOperation) -> bool {
+        if op.is_essential() {
+            return false;
+        }
+        match op {
+            ChannelOperation::OpenChannel => true,
+            ChannelOperation::AcceptJitChannel { amount_sats } => {
+                amount_sats > self.large_jit_channel_sats
+            }
+            ChannelOperation::ForceCloseHandling | ChannelOperation::Justice => false,
+        }
+    }
+}
+
+/// An operation that was deferred by the congestion breaker.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeferredOperation {
+    pub operation: String,
+    pub reason: String,
+    /// Epoch time in seconds
+    pub deferred_at: u64,
+}
+
+/// The current state of the congestion breaker.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CongestionStatus {
+    pub tripped: bool,
+    /// The last fee estimate that was checked, in sats per vbyte
+    pub sats_per_vbyte: Option<u32>,
+    /// Epoch time in seconds of when the breaker last tripped
+    pub tripped_at: Option<u64>,
+    /// The latest deferred operations, oldest first
+    pub deferred: Vec<DeferredOperation>,
+}
+
+/// Defers non-essential channel operations while the fee estimate is high.
+#[derive(Clone)]
+pub(crate) struct CongestionBreaker {
+    policy: CongestionPolicy,
+    status: Arc<utils::Mutex<CongestionStatus>>,
+}
+
+impl CongestionBreaker {
+    pub fn new(policy: CongestionPolicy) -> Self {
+        Self {
+            policy,
+            status: Arc::new(utils::Mutex::new(CongestionStatus::default())),
+        }
+    }
+
+    pub fn fee_target(&self) -> ConfirmationTarget {
+        self.policy.fee_target.into()
+    }
+
+    /// Updates the breaker with a new fee estimate in sats per 1000 weight units.
+    /// Returns the new tripped state if it changed.
+    pub fn update(&self, sats_per_kw: u32, now: u64) -> Option<bool> {
+        let (Some(trip), Some(resume)) =
+            (self.policy.trip_sats_per_vbyte, self.policy.resume_rate())
+        else {
+            return None;
+        };
+
+        let sats_per_vbyte = sats_per_kw / 250;
+        let mut status = self
+            .status
+            .lock()
+            .expect("Failed to lock congestion status");
+        status.sats_per_vbyte = Some(sats_per_vbyte);
+
+        if !status.tripped && sats_per_vbyte >= trip {
+            status.tripped = true;
+            status.tripped_at = Some(now);
+            Some(true)
+        } else if status.tripped && sats_per_vbyte <= resume {
+            status.tripped = false;
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    /// Checks if the given operation can be performed now. Returns
+    /// [`MutinyError::ChannelOperationDeferred`] and records the deferral if it
+    /// has to wait until fees come down.
+    pub fn check(&self, op: ChannelOperation, now: u64) -> Result<(), MutinyError> {
+        if !self.policy.defers(op) {
+            return Ok(());
+        }
+
+        let mut status = self
+            .status
+            .lock()
+            .expect("Failed to lock congestion status");
+        if !status.tripped {
+            return Ok(());
+        }
+
+        let reason = format!(
+            "fee estimate of {} sats/vbyte, waiting for it to fall to {} sats/vbyte",
+            status.sats_per_vbyte.unwrap_or_default(),
+            self.policy.resume_rate().unwrap_or_default(),
+        );
+        if status.deferred.len() >= MAX_DEFERRED_OPERATIONS {
+            status.deferred.remove(0);
+        }
+        status.deferred.push(DeferredOperation {
+            operation: op.name().to_string(),
+            reason,
+            deferred_at: now,
+        });
+
+        Err(MutinyError::ChannelOperationDeferred)
+    }
+
+    /// Same as [`CongestionBreaker::check`] using the current time.
+    pub fn check_now(&self, op: ChannelOperation) -> Result<(), MutinyError> {
+        self.check(op, utils::now().as_secs())
+    }
+
+    pub fn status(&self) -> CongestionStatus {
+        self.status
+            .lock()
+            .expect("Failed to lock congestion status")
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn breaker() -> CongestionBreaker {
+        CongestionBreaker::new(CongestionPolicy {
+            trip_sats_per_vbyte: Some(100),
+            resume_sats_per_vbyte: Some(60),
+            fee_target: CongestionFeeTarget::Normal,
+            large_jit_channel_sats: 500_000,
+        })
+    }
+
+    fn kw(sats_per_vbyte: u32) -> u32 {
+        sats_per_vbyte * 250
+    }
+
+    #[test]
+    fn test_congestion_breaker_off_by_default() {
+        let test_name = "test_congestion_breaker_off_by_default";
+        log!("{}", test_name);
+
+        let breaker = CongestionBreaker::new(CongestionPolicy::default());
+        assert_eq!(breaker.update(kw(1_000), NOW), None);
+        assert!(breaker.check(ChannelOperation::OpenChannel, NOW).is_ok());
+        assert_eq!(breaker.status(), CongestionStatus::default());
+    }
+
+    #[test]
+    fn test_congestion_breaker_hysteresis() {
+        let test_name = "test_congestion_breaker_hysteresis";
+        log!("{}", test_name);
+
+        let breaker = breaker();
+        let open = ChannelOperation::OpenChannel;
+
+        // below the threshold nothing is deferred
+        assert_eq!(breaker.update(kw(50), NOW), None);
+        assert_eq!(breaker.update(kw(99), NOW + 1), None);
+        assert!(breaker.check(open, NOW + 1).is_ok());
+
+        // trips at the threshold
+        assert_eq!(breaker.update(kw(100), NOW + 2), Some(true));
+        assert!(matches!(
+            breaker.check(open, NOW + 2),
+            Err(MutinyError::ChannelOperationDeferred)
+        ));
+
+        // stays tripped between the resume and trip rates
+        assert_eq!(breaker.update(kw(80), NOW + 3), None);
+        assert_eq!(breaker.update(kw(61), NOW + 4), None);
+        assert!(breaker.check(open, NOW + 4).is_err());
+
+        // resumes once fees come down far enough
+        assert_eq!(breaker.update(kw(60), NOW + 5), Some(false));
+        assert!(breaker.check(open, NOW + 5).is_ok());
+
+        // and does not trip again until the threshold is reached again
+        assert_eq!(breaker.update(kw(99), NOW + 6), None);
+        assert!(breaker.check(open, NOW + 6).is_ok());
+        assert_eq!(breaker.update(kw(150), NOW + 7), Some(true));
+
+        let status = breaker.status();
+        assert!(status.tripped);
+        assert_eq!(status.sats_per_vbyte, Some(150));
+        assert_eq!(status.tripped_at, Some(NOW + 7));
+        assert_eq!(status.deferred.len(), 2);
+        assert_eq!(status.deferred[0].operation, "open_channel");
+        assert_eq!(status.deferred[0].deferred_at, NOW + 2);
+        assert!(status.deferred[1].reason.contains("61 sats/vbyte"));
+    }
+
+    #[test]
+    fn test_congestion_breaker_never_defers_essential() {
+        let test_name = "test_congestion_breaker_never_defers_essential";
+        log!("{}", test_name);
+
+        let breaker = breaker();
+        assert_eq!(breaker.update(kw(500), NOW), Some(true));
+
+        assert!(breaker
+            .check(ChannelOperation::ForceCloseHandling, NOW)
+            .is_ok());
+        assert!(breaker.check(ChannelOperation::Justice, NOW).is_ok());
+
+        // small JIT channels are still accepted, large ones wait
+        let small = ChannelOperation::AcceptJitChannel {
+            amount_sats: 500_000,
+        };
+        let large = ChannelOperation::AcceptJitChannel {
+            amount_sats: 500_001,
+        };
+        assert!(breaker.check(small, NOW).is_ok());
+        assert!(breaker.check(large, NOW).is_err());
+
+        let deferred: Vec<String> = breaker
+            .status()
+            .deferred
+            .into_iter()
+            .map(|d| d.operation)
+            .collect();
+        assert_eq!(deferred, vec!["accept_jit_channel"]);
+    }
+
+    #[test]
+    fn test_congestion_breaker_default_resume_rate() {
+        let test_name = "test_congestion_breaker_default_resume_rate";
+        log!("{}", test_name);
+
+        let mut policy = CongestionPolicy {
+            trip_sats_per_vbyte: Some(100),
+            ..Default::default()
+        };
+        assert_eq!(policy.resume_rate(), Some(75));
+
+        // a resume rate above the trip rate would never leave the tripped state
+        policy.resume_sats_per_vbyte = Some(200);
+        assert_eq!(policy.resume_rate(), Some(100));
+    }
+}
@@ -0,0 +1,306 @@
+use bitcoin::{OutPoint, Txid};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The last consolidation that was broadcast, used for the rate limit.
+pub(crate) const LAST_CONSOLIDATION_KEY: &str = "last_consolidation";
+
+/// Label of the output of a consolidation, it shows up on the activity item.
+pub const CONSOLIDATION_LABEL: &str = "Consolidation";
+
+/// Utxos below this value count as small by default.
+pub const DEFAULT_SMALL_UTXO_SATS: u64 = 50_000;
+
+/// Consolidate by default once there are more than this many small utxos.
+pub const DEFAULT_MIN_SMALL_UTXOS: usize = 10;
+
+/// By default consolidate at most once a week.
+pub const DEFAULT_CONSOLIDATION_PERIOD_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// By default spend at most this many utxos in one consolidation.
+pub const DEFAULT_MAX_CONSOLIDATION_INPUTS: usize = 50;
+
+/// Size in vbytes of spending a taproot key path input, rounded up.
+const TR_INPUT_VBYTES: u64 = 58;
+
+/// Settings for consolidating small utxos while on-chain fees are low. Once the
+/// background fee estimate falls to `max_sats_per_vbyte` and more than
+/// `min_small_utxos` utxos below `small_utxo_sats` can be spent together, they are
+/// spent to a single new output of the wallet, at most once every `period_secs`.
+/// `None` for the fee rate disables consolidation, which is the default.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConsolidationPolicy {
+    pub max_sats_per_vbyte: Option<u32>,
+    pub small_utxo_sats: u64,
+    pub min_small_utxos: usize,
+    pub period_secs: u64,
+    /// The most utxos spent by one consolidation
+    pub max_inputs: usize,
+    /// Kept in utxos outside of the consolidation, so there is something to spend
+    /// or bump fees with while it is unconfirmed
+    pub reserve_sats: u64,
+    /// Utxos on addresses with any of these labels are never consolidated
+    pub excluded_labels: Vec<String>,
+}
+
+impl Default for ConsolidationPolicy {
+    fn default() -> Self {
+        Self {
+            max_sats_per_vbyte: None,
+            small_utxo_sats: DEFAULT_SMALL_UTXO_SATS,
+            min_small_utxos: DEFAULT_MIN_SMALL_UTXOS,
+            period_secs: DEFAULT_CONSOLIDATION_PERIOD_SECS,
+            max_inputs: DEFAULT_MAX_CONSOLIDATION_INPUTS,
+            reserve_sats: 0,
+            excluded_labels: vec![],
+        }
+    }
+}
+
+/// A utxo of the wallet as seen by the consolidation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsolidationCandidate {
+    pub outpoint: OutPoint,
+    pub value: u64,
+    pub confirmed: bool,
+    /// Labels of the address the utxo is on
+    pub labels: Vec<String>,
+}
+
+/// A consolidation that was broadcast.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Consolidation {
+    pub txid: Txid,
+    /// Number of utxos that were spent
+    pub inputs: usize,
+    /// Total value of the utxos that were spent
+    pub amount_sats: u64,
+    pub sats_per_vbyte: u32,
+    /// Epoch time in seconds
+    pub timestamp: u64,
+}
+
+impl ConsolidationPolicy {
+    pub fn is_enabled(&self) -> bool {
+        self.max_sats_per_vbyte.is_some()
+    }
+
+    /// Returns true if fees are low enough and the period since the last
+    /// consolidation has passed.
+    pub fn is_due(&self, sats_per_vbyte: u32, last: Option<u64>, now: u64) -> bool {
+        let Some(max) = self.max_sats_per_vbyte else {
+            return false;
+        };
+        if sats_per_vbyte > max {
+            return false;
+        }
+        match last {
+            Some(last) => now.saturating_sub(last) >= self.period_secs,
+            None => true,
+        }
+    }
+
+    /// A utxo is small if it is below the threshold but still worth more than
+    /// the fee of spending it at the given rate.
+    fn is_small(&self, utxo: &ConsolidationCandidate, sats_per_vbyte: u32) -> bool {
+        utxo.value < self.small_utxo_sats && utxo.value > TR_INPUT_VBYTES * sats_per_vbyte as u64
+    }
+
+    fn is_excluded(&self, utxo: &ConsolidationCandidate) -> bool {
+        utxo.labels.iter().any(|l| self.excluded_labels.contains(l))
+    }
+
+    /// Picks the utxos to consolidate at the given fee rate, `None` unless more than
+    /// `min_small_utxos` are picked.
+    ///
+    /// Only confirmed utxos with the same labels are spent together, so utxos that
+    /// were kept apart are never linked. Of those, the largest group is picked,
+    /// smallest utxos first. Utxos are dropped from the selection, largest first,
+    /// until `reserve_sats` are left outside of it.
+    pub fn select(
+        &self,
+        utxos: &[ConsolidationCandidate],
+        sats_per_vbyte: u32,
+    ) -> Option<Vec<OutPoint>> {
+        let small: Vec<&ConsolidationCandidate> = utxos
+            .iter()
+            .filter(|u| u.confirmed && !self.is_excluded(u) && self.is_small(u, sats_per_vbyte))
+            .collect();
+
+        let mut groups: BTreeMap<Vec<String>, Vec<&ConsolidationCandidate>> = BTreeMap::new();
+        for utxo in small {
+            let mut labels = utxo.labels.clone();
+            labels.sort();
+            labels.dedup();
+            groups.entry(labels).or_default().push(utxo);
+        }
+
+        // the first of the largest groups, so the pick does not depend on the utxo order
+        let mut selected = groups.into_values().rev().max_by_key(|group| group.len())?;
+        selected.sort_by_key(|u| (u.value, u.outpoint));
+        selected.truncate(self.max_inputs);
+
+        let total: u64 = utxos.iter().map(|u| u.value).sum();
+        let mut outside = total - selected.iter().map(|u| u.value).sum::<u64>();
+        while outside < self.reserve_sats {
+            let Some(utxo) = selected.pop() else { break };
+            outside += utxo.value;
+        }
+
+        // the threshold counts what is spent, not the small utxos of other groups
+        // or the ones left out for the input limit and the reserve
+        if selected.len() < 2 || selected.len() <= self.min_small_utxos {
+            return None;
+        }
+        Some(selected.into_iter().map(|u| u.outpoint).collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::*;
+    use bitcoin::hashes::Hash;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn policy() -> ConsolidationPolicy {
+        ConsolidationPolicy {
+            max_sats_per_vbyte: Some(5),
+            small_utxo_sats: 10_000,
+            min_small_utxos: 3,
+            period_secs: 86_400,
+            max_inputs: 10,
+            reserve_sats: 0,
+            excluded_labels: vec!["do not spend".to_string()],
+        }
+    }
+
+    fn utxo(vout: u32, value: u64, labels: &[&str]) -> ConsolidationCandidate {
+        ConsolidationCandidate {
+            outpoint: OutPoint::new(Txid::all_zeros(), vout),
+            value,
+            confirmed: true,
+            labels: labels.iter().map(|l| l.to_string()).collect(),
+        }
+    }
+
+    fn vouts(selected: Option<Vec<OutPoint>>) -> Vec<u32> {
+        selected.unwrap().into_iter().map(|o| o.vout).collect()
+    }
+
+    #[test]
+    fn test_consolidation_trigger() {
+        let test_name = "test_consolidation_trigger";
+        log!("{}", test_name);
+
+        assert!(!ConsolidationPolicy::default().is_due(1, None, NOW));
+
+        let policy = policy();
+        // fee sequence coming down, only due once at or below the threshold
+        let due: Vec<bool> = [40, 12, 6, 5, 1]
+            .into_iter()
+            .map(|rate| policy.is_due(rate, None, NOW))
+            .collect();
+        assert_eq!(due, vec![false, false, false, true, true]);
+
+        // at most once per period
+        assert!(!policy.is_due(1, Some(NOW), NOW));
+        assert!(!policy.is_due(1, Some(NOW), NOW + 86_399));
+        assert!(policy.is_due(1, Some(NOW), NOW + 86_400));
+        // and fees still have to be low after the period
+        assert!(!policy.is_due(6, Some(NOW), NOW + 86_400));
+    }
+
+    #[test]
+    fn test_consolidation_needs_enough_small_utxos() {
+        let test_name = "test_consolidation_needs_enough_small_utxos";
+        log!("{}", test_name);
+
+        let policy = policy();
+        let mut utxos = vec![
+            utxo(0, 1_000, &[]),
+            utxo(1, 2_000, &[]),
+            utxo(2, 3_000, &[]),
+            // large utxos don't count
+            utxo(3, 10_000, &[]),
+            utxo(4, 500_000, &[]),
+        ];
+        assert_eq!(policy.select(&utxos, 1), None);
+
+        utxos.push(utxo(5, 4_000, &[]));
+        assert_eq!(vouts(policy.select(&utxos, 1)), vec![0, 1, 2, 5]);
+
+        // unconfirmed utxos are left alone
+        utxos[0].confirmed = false;
+        assert_eq!(policy.select(&utxos, 1), None);
+        utxos[0].confirmed = true;
+
+        // so are utxos that cost more to spend than they are worth
+        assert_eq!(policy.select(&utxos, 20), None);
+    }
+
+    #[test]
+    fn test_consolidation_never_mixes_labels() {
+        let test_name = "test_consolidation_never_mixes_labels";
+        log!("{}", test_name);
+
+        let policy = policy();
+        let utxos = vec![
+            utxo(0, 1_000, &["exchange"]),
+            utxo(1, 2_000, &[]),
+            utxo(2, 3_000, &["exchange"]),
+            utxo(3, 4_000, &["do not spend"]),
+            utxo(4, 5_000, &["exchange"]),
+            utxo(5, 6_000, &[]),
+            utxo(6, 7_000, &["do not spend", "exchange"]),
+        ];
+
+        // the groups only have enough small utxos together
+        assert_eq!(policy.select(&utxos, 1), None);
+
+        // the largest group is spent on its own, excluded utxos never are
+        let mut utxos = utxos;
+        utxos.push(utxo(7, 8_000, &["exchange"]));
+        let selected = vouts(policy.select(&utxos, 1));
+        assert_eq!(selected, vec![0, 2, 4, 7]);
+
+        // the group does not depend on the order of the utxos
+        let mut reversed = utxos.clone();
+        reversed.reverse();
+        assert_eq!(vouts(policy.select(&reversed, 1)), selected);
+    }
+
+    #[test]
+    fn test_consolidation_limits() {
+        let test_name = "test_consolidation_limits";
+        log!("{}", test_name);
+
+        let mut policy = policy();
+        policy.max_inputs = 4;
+        let mut utxos: Vec<ConsolidationCandidate> = (0..6)
+            .map(|i| utxo(i, 1_000 * (6 - i as u64), &[]))
+            .collect();
+
+        // the smallest utxos are spent first
+        assert_eq!(vouts(policy.select(&utxos, 1)), vec![5, 4, 3, 2]);
+
+        // the input limit can leave too few to consolidate
+        policy.max_inputs = 3;
+        assert_eq!(policy.select(&utxos, 1), None);
+
+        // the reserve is kept outside of the consolidation
+        policy.max_inputs = 10;
+        utxos.push(utxo(6, 20_000, &[]));
+        policy.reserve_sats = 20_000 + 6_000 + 5_000;
+        assert_eq!(vouts(policy.select(&utxos, 1)), vec![5, 4, 3, 2]);
+
+        // nothing is consolidated if the reserve leaves too few utxos
+        policy.reserve_sats = 20_000 + 6_000 + 5_000 + 4_000;
+        assert_eq!(policy.select(&utxos, 1), None);
+    }
+}
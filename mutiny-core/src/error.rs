@@ -1,4 +1,8 @@
+use crate::capabilities::Capability;
 use crate::esplora::TxSyncError;
+use crate::lifecycle::WalletComponent;
+use crate::messages::Message;
+use crate::safe_int;
 use bitcoin::Network;
 use lightning::ln::peer_handler::PeerHandleError;
 use lightning_invoice::payment::PaymentError;
@@ -6,6 +10,13 @@ use lightning_invoice::ParseOrSemanticError;
 use lightning_rapid_gossip_sync::GraphSyncError;
 use thiserror::Error;
 
+/// Bump this when an existing error changes meaning, new errors can be added
+/// without a bump.
+pub const ERROR_CODES_VERSION: u32 = 1;
+
+pub(crate) const ERROR_CODES_CAPABILITY: Capability =
+    Capability::version("error_codes_version", ERROR_CODES_VERSION);
+
 #[derive(Error, Debug)]
 #[allow(dead_code)]
 // copied from LDK lite
@@ -26,9 +37,15 @@ pub enum MutinyError {
     /// A network connection has been closed.
     #[error("Network connection closed.")]
     ConnectionFailed,
+    /// A host name could not be resolved
+    #[error("Failed to resolve the host name.")]
+    DnsResolutionFailed,
     /// The invoice or address is on a different network
     #[error("The invoice or address is on a different network.")]
     IncorrectNetwork(Network),
+    /// The backup was created on a different network than the wallet
+    #[error("The backup was created on {found} but this wallet is on {expected}.")]
+    NetworkMismatch { expected: Network, found: Network },
     /// Payment of the given invoice has already been initiated.
     #[error("An invoice must not get payed twice.")]
     NonUniquePaymentHash,
@@ -38,6 +55,12 @@ pub enum MutinyError {
     /// The given invoice is invalid.
     #[error("The given invoice is invalid.")]
     InvoiceInvalid,
+    /// The given preimage does not hash to the payment hash.
+    #[error("The preimage does not match the payment hash.")]
+    PreimageMismatch,
+    /// The invoice was already paid.
+    #[error("The invoice has already been settled.")]
+    InvoiceAlreadySettled,
     /// Invoice creation failed.
     #[error("Failed to create invoice.")]
     InvoiceCreationFailed,
@@ -47,7 +70,10 @@ pub enum MutinyError {
     ReserveAmountError,
     /// We do not have enough balance to pay the given amount.
     #[error("We do not have enough balance to pay the given amount.")]
-    InsufficientBalance,
+    InsufficientBalance {
+        needed_sats: u64,
+        available_sats: u64,
+    },
     /// Failed to call on the given LNURL
     #[error("Failed to call on the given LNURL.")]
     LnUrlFailure,
@@ -69,6 +95,9 @@ pub enum MutinyError {
     /// No route for the given target could be found.
     #[error("Failed to find route.")]
     RoutingFailed,
+    /// The payee has no public channels and none of the hinted peers are routable.
+    #[error("Destination unreachable: no public channels and hinted peers not routable.")]
+    DestinationUnreachable,
     /// A given peer info could not be parsed.
     #[error("Failed to parse the given peer information.")]
     PeerInfoParseFailed,
@@ -94,6 +123,16 @@ pub enum MutinyError {
     /// User provided invalid mnemonic.
     #[error("Invalid mnemonic")]
     InvalidMnemonic,
+    /// The backup is encrypted with a passphrase instead of the seed.
+    #[error("This backup requires a passphrase to decrypt.")]
+    PassphraseRequired,
+    /// User provided the wrong passphrase for a backup.
+    #[error("Incorrect passphrase")]
+    IncorrectPassphrase,
+    /// An imported backup could not be decrypted, the key is wrong or the backup
+    /// was modified.
+    #[error("Failed to decrypt the backup.")]
+    BackupDecryptionFailed,
     /// A wallet operation failed.
     #[error("Failed to conduct wallet operation.")]
     WalletOperationFailed,
@@ -106,6 +145,70 @@ pub enum MutinyError {
     /// A failure to sync the on-chain wallet
     #[error("Failed to to sync on-chain wallet.")]
     WalletSyncError,
+    /// Strict mode requires a more recent chain sync for this operation
+    #[error("The chain has not been synced recently, try again after the next sync.")]
+    ChainSyncStale,
+    /// The congestion breaker deferred this operation until fees come down
+    #[error("On-chain fees are too high right now, try again once they come down.")]
+    ChannelOperationDeferred,
+    /// Too many messages are queued for a disconnected peer
+    #[error("Too many messages are queued for this peer, try again once it reconnects.")]
+    PeerMessageQueueFull { max: usize },
+    /// The backup does not fit in a message to a peer
+    #[error("The backup is too large to be stored with a peer.")]
+    PeerBackupTooLarge { size: usize, max: usize },
+    /// None of the peers had a backup for us
+    #[error("No backup was found with the given peer.")]
+    PeerBackupNotFound,
+    /// Backups are not exchanged with the peer
+    #[error("Backups are not exchanged with this peer.")]
+    PeerBackupNotPermitted,
+    /// None of the relays accepted the backup
+    #[error("No relay accepted the backup.")]
+    NostrBackupFailed,
+    /// None of the relays had a backup for us
+    #[error("No backup was found on the given relays.")]
+    NostrBackupNotFound,
+    /// The remote backup endpoint did not store or return the backup
+    #[error("The remote backup endpoint failed.")]
+    RemoteBackupFailed,
+    /// The remote backup endpoint has no backup for us yet
+    #[error("No backup was found on the remote endpoint.")]
+    RemoteBackupNotFound,
+    /// The remote backup endpoint has a newer backup than the one sent
+    #[error("The remote endpoint has a newer backup.")]
+    RemoteBackupStale,
+    /// The webhook endpoint is not a public HTTPS url
+    #[error("Webhooks can only be sent to public HTTPS endpoints.")]
+    WebhookUrlRejected,
+    /// The webhook endpoint could not be reached or did not accept the event
+    #[error("Failed to deliver the webhook.")]
+    WebhookFailed,
+    /// The backup diff was not created from this backup
+    #[error("The backup diff does not apply to this backup.")]
+    ScbDiffConflict,
+    /// The backup could not be parsed, with the position of the first invalid
+    /// character if a single one is to blame
+    #[error(
+        "The backup could not be parsed{}.",
+        .position.map(|p| format!(", check the character at position {p}")).unwrap_or_default()
+    )]
+    ScbParse { position: Option<usize> },
+    /// The wallet has not finished starting up
+    #[error("The wallet is still starting up ({completed_steps}/{total_steps} steps done).")]
+    WalletInitializing {
+        completed_steps: usize,
+        total_steps: usize,
+    },
+    /// The wallet is being stopped
+    #[error("The wallet is shutting down.")]
+    WalletStopping,
+    /// The wallet was stopped and has to be started again
+    #[error("The wallet has been stopped, start it again first.")]
+    WalletStopped,
+    /// A part of the wallet failed to start, the rest is still usable
+    #[error("{0} is unavailable because it failed to start.")]
+    ComponentUnavailable(WalletComponent),
     /// An error with rapid gossip sync
     #[error("Failed to execute a rapid gossip sync function")]
     RapidGossipSyncError,
@@ -150,13 +253,120 @@ impl MutinyError {
     pub fn write_err(e: MutinyStorageError) -> Self {
         MutinyError::PersistenceFailed { source: e }
     }
+
+    /// The message to show the user for this error, see [`crate::messages`].
+    pub fn message(&self) -> Message {
+        match self {
+            MutinyError::AlreadyRunning => Message::new("error.already_running"),
+            MutinyError::NotRunning => Message::new("error.not_running"),
+            MutinyError::NotFound => Message::new("error.not_found"),
+            MutinyError::FundingTxCreationFailed => {
+                Message::new("error.funding_tx_creation_failed")
+            }
+            MutinyError::ConnectionFailed => Message::new("error.connection_failed"),
+            MutinyError::DnsResolutionFailed => Message::new("error.dns_resolution_failed"),
+            MutinyError::IncorrectNetwork(network) => {
+                Message::new("error.incorrect_network").with("network", network.to_string())
+            }
+            MutinyError::NetworkMismatch { expected, found } => {
+                Message::new("error.network_mismatch")
+                    .with("expected", expected.to_string())
+                    .with("found", found.to_string())
+            }
+            MutinyError::NonUniquePaymentHash => Message::new("error.non_unique_payment_hash"),
+            MutinyError::PaymentTimeout => Message::new("error.payment_timeout"),
+            MutinyError::InvoiceInvalid => Message::new("error.invoice_invalid"),
+            MutinyError::PreimageMismatch => Message::new("error.preimage_mismatch"),
+            MutinyError::InvoiceAlreadySettled => Message::new("error.invoice_already_settled"),
+            MutinyError::InvoiceCreationFailed => Message::new("error.invoice_creation_failed"),
+            MutinyError::ReserveAmountError => Message::new("error.reserve_amount"),
+            MutinyError::InsufficientBalance {
+                needed_sats,
+                available_sats,
+            } => Message::new("error.insufficient_balance")
+                .with("needed_sats", safe_int::to_json_value(*needed_sats))
+                .with("available_sats", safe_int::to_json_value(*available_sats)),
+            MutinyError::LnUrlFailure => Message::new("error.lnurl_failure"),
+            MutinyError::LspGenericError => Message::new("error.lsp_generic"),
+            MutinyError::LspFundingError => Message::new("error.lsp_funding"),
+            MutinyError::LspConnectionError => Message::new("error.lsp_connection"),
+            MutinyError::SubscriptionClientNotConfigured => {
+                Message::new("error.subscription_client_not_configured")
+            }
+            MutinyError::InvalidArgumentsError => Message::new("error.invalid_arguments"),
+            MutinyError::RoutingFailed => Message::new("error.routing_failed"),
+            MutinyError::DestinationUnreachable => Message::new("error.destination_unreachable"),
+            MutinyError::PeerInfoParseFailed => Message::new("error.peer_info_parse_failed"),
+            MutinyError::ChannelCreationFailed => Message::new("error.channel_creation_failed"),
+            MutinyError::ChannelClosingFailed => Message::new("error.channel_closing_failed"),
+            MutinyError::PersistenceFailed { .. } => Message::new("error.persistence_failed"),
+            MutinyError::ReadError { .. } => Message::new("error.read_failed"),
+            MutinyError::LnDecodeError => Message::new("error.ln_decode"),
+            MutinyError::SeedGenerationFailed => Message::new("error.seed_generation_failed"),
+            MutinyError::InvalidMnemonic => Message::new("error.invalid_mnemonic"),
+            MutinyError::PassphraseRequired => Message::new("error.passphrase_required"),
+            MutinyError::IncorrectPassphrase => Message::new("error.incorrect_passphrase"),
+            MutinyError::BackupDecryptionFailed => Message::new("error.backup_decryption_failed"),
+            MutinyError::WalletOperationFailed => Message::new("error.wallet_operation_failed"),
+            MutinyError::WalletSigningFailed => Message::new("error.wallet_signing_failed"),
+            MutinyError::ChainAccessFailed => Message::new("error.chain_access_failed"),
+            MutinyError::WalletSyncError => Message::new("error.wallet_sync"),
+            MutinyError::ChainSyncStale => Message::new("error.chain_sync_stale"),
+            MutinyError::ChannelOperationDeferred => {
+                Message::new("error.channel_operation_deferred")
+            }
+            MutinyError::PeerMessageQueueFull { max } => {
+                Message::new("error.peer_message_queue_full").with("max", *max)
+            }
+            MutinyError::PeerBackupTooLarge { size, max } => {
+                Message::new("error.peer_backup_too_large")
+                    .with("size", *size)
+                    .with("max", *max)
+            }
+            MutinyError::PeerBackupNotFound => Message::new("error.peer_backup_not_found"),
+            MutinyError::PeerBackupNotPermitted => Message::new("error.peer_backup_not_permitted"),
+            MutinyError::NostrBackupFailed => Message::new("error.nostr_backup_failed"),
+            MutinyError::NostrBackupNotFound => Message::new("error.nostr_backup_not_found"),
+            MutinyError::RemoteBackupFailed => Message::new("error.remote_backup_failed"),
+            MutinyError::RemoteBackupNotFound => Message::new("error.remote_backup_not_found"),
+            MutinyError::RemoteBackupStale => Message::new("error.remote_backup_stale"),
+            MutinyError::WebhookUrlRejected => Message::new("error.webhook_url_rejected"),
+            MutinyError::WebhookFailed => Message::new("error.webhook_failed"),
+            MutinyError::ScbDiffConflict => Message::new("error.scb_diff_conflict"),
+            MutinyError::ScbParse { position: None } => Message::new("error.scb_parse"),
+            MutinyError::ScbParse {
+                position: Some(position),
+            } => Message::new("error.scb_parse_at").with("position", *position),
+            MutinyError::WalletInitializing {
+                completed_steps,
+                total_steps,
+            } => Message::new("error.wallet_initializing")
+                .with("completed_steps", *completed_steps)
+                .with("total_steps", *total_steps),
+            MutinyError::WalletStopping => Message::new("error.wallet_stopping"),
+            MutinyError::WalletStopped => Message::new("error.wallet_stopped"),
+            MutinyError::ComponentUnavailable(component) => {
+                Message::new("error.component_unavailable").with("component", component.to_string())
+            }
+            MutinyError::RapidGossipSyncError => Message::new("error.rapid_gossip_sync"),
+            MutinyError::DLCManagerError => Message::new("error.dlc_manager"),
+            MutinyError::PubkeyInvalid => Message::new("error.pubkey_invalid"),
+            MutinyError::IncorrectLnUrlFunction => Message::new("error.incorrect_lnurl_function"),
+            MutinyError::BadAmountError => Message::new("error.bad_amount"),
+            MutinyError::BitcoinPriceError => Message::new("error.bitcoin_price"),
+            MutinyError::Other(_) => Message::new("error.unknown"),
+        }
+    }
 }
 
 impl From<bdk::Error> for MutinyError {
     fn from(e: bdk::Error) -> Self {
         match e {
             bdk::Error::Signer(_) => Self::WalletSigningFailed,
-            bdk::Error::InsufficientFunds { .. } => Self::InsufficientBalance,
+            bdk::Error::InsufficientFunds { needed, available } => Self::InsufficientBalance {
+                needed_sats: needed,
+                available_sats: available,
+            },
             _ => Self::WalletOperationFailed,
         }
     }
@@ -1,12 +1,17 @@
+use crate::congestion::{ChannelOperation, CongestionBreaker};
 use crate::fees::MutinyFeeEstimator;
+use crate::freshness::{ChainOperation, DeferredClaims, StrictModeConfig};
 use crate::keymanager::PhantomKeysManager;
 use crate::ldkstorage::{MutinyNodePersister, PhantomChannelManager};
 use crate::logging::MutinyLogger;
 use crate::nodemanager::ChannelClosure;
 use crate::onchain::OnChainWallet;
+use crate::payment_privacy::PaymentPrivacy;
+use crate::recovery::RecoverySweep;
 use crate::redshift::RedshiftStorage;
 use crate::storage::MutinyStorage;
-use crate::utils::sleep;
+use crate::utils::{self, sleep};
+use crate::webhooks::{enqueue_webhook_event, WebhookEvent};
 use anyhow::anyhow;
 use bitcoin::hashes::hex::ToHex;
 use bitcoin::secp256k1::PublicKey;
@@ -39,6 +44,16 @@ pub(crate) struct PaymentInfo {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub payee_pubkey: Option<PublicKey>,
     pub last_update: u64,
+    /// Set if the invoice was paid out of band, no funds moved into our channels for it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub external_settlement: Option<ExternalSettlement>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct ExternalSettlement {
+    pub note: String,
+    /// Epoch time in seconds of when the invoice was marked as settled
+    pub settled_at: u64,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -50,8 +65,9 @@ impl MillisatAmount {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub(crate) enum HTLCStatus {
+/// The status of a lightning payment.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum HTLCStatus {
     Pending,
     InFlight,
     Succeeded,
@@ -66,6 +82,10 @@ pub struct EventHandler<S: MutinyStorage> {
     keys_manager: Arc<PhantomKeysManager<S>>,
     persister: Arc<MutinyNodePersister<S>>,
     lsp_client_pubkey: Option<PublicKey>,
+    strict_mode: StrictModeConfig,
+    congestion: CongestionBreaker,
+    payment_privacy: PaymentPrivacy,
+    deferred_claims: DeferredClaims,
     logger: Arc<MutinyLogger>,
 }
 
@@ -77,6 +97,10 @@ impl<S: MutinyStorage> EventHandler<S> {
         keys_manager: Arc<PhantomKeysManager<S>>,
         persister: Arc<MutinyNodePersister<S>>,
         lsp_client_pubkey: Option<PublicKey>,
+        strict_mode: StrictModeConfig,
+        congestion: CongestionBreaker,
+        payment_privacy: PaymentPrivacy,
+        deferred_claims: DeferredClaims,
         logger: Arc<MutinyLogger>,
     ) -> Self {
         Self {
@@ -86,10 +110,24 @@ impl<S: MutinyStorage> EventHandler<S> {
             keys_manager,
             lsp_client_pubkey,
             persister,
+            strict_mode,
+            congestion,
+            payment_privacy,
+            deferred_claims,
             logger,
         }
     }
 
+    fn last_chain_sync(&self) -> Option<u64> {
+        match self.persister.storage.get_last_chain_sync() {
+            Ok(last_sync) => last_sync,
+            Err(e) => {
+                log_error!(self.logger, "ERROR: could not read last chain sync: {e}");
+                None
+            }
+        }
+    }
+
     pub async fn handle_event(&self, event: Event) {
         match event {
             Event::FundingGenerationReady {
@@ -211,6 +249,16 @@ impl<S: MutinyStorage> EventHandler<S> {
                     } => payment_preimage,
                     PaymentPurpose::SpontaneousPayment(preimage) => Some(preimage),
                 } {
+                    let op = ChainOperation::ClaimPayment { amount_msat };
+                    if self
+                        .strict_mode
+                        .check_now(op, self.last_chain_sync())
+                        .is_err()
+                    {
+                        log_warn!(self.logger, "EVENT: PaymentClaimable deferring claim of payment hash {} until the chain is synced", payment_hash.0.to_hex());
+                        self.deferred_claims.push(payment_preimage);
+                        return;
+                    }
                     self.channel_manager.claim_funds(payment_preimage);
                 } else {
                     log_error!(self.logger, "ERROR: No payment preimage found");
@@ -270,6 +318,7 @@ impl<S: MutinyStorage> EventHandler<S> {
                             payee_pubkey: receiver_node_id,
                             bolt11: None,
                             last_update,
+                            external_settlement: None,
                         };
                         match self.persister.persist_payment_info(
                             &payment_hash,
@@ -284,6 +333,13 @@ impl<S: MutinyStorage> EventHandler<S> {
                         }
                     }
                 }
+
+                let event = WebhookEvent::payment_received(
+                    payment_hash.0,
+                    amount_msat,
+                    utils::now().as_secs(),
+                );
+                enqueue_webhook_event(&self.persister.storage, &self.logger, event);
             }
             Event::PaymentSent {
                 payment_preimage,
@@ -326,10 +382,18 @@ impl<S: MutinyStorage> EventHandler<S> {
                         );
                     }
                 }
+
+                let event = WebhookEvent::payment_sent(
+                    payment_hash.0,
+                    fee_paid_msat,
+                    utils::now().as_secs(),
+                );
+                enqueue_webhook_event(&self.persister.storage, &self.logger, event);
             }
             Event::OpenChannelRequest {
                 temporary_channel_id,
                 counterparty_node_id,
+                funding_satoshis,
                 ..
             } => {
                 log_debug!(
@@ -360,6 +424,34 @@ impl<S: MutinyStorage> EventHandler<S> {
                     );
                     log_result(result);
                 } else {
+                    let op = ChainOperation::AcceptZeroConfOpen;
+                    if self
+                        .strict_mode
+                        .check_now(op, self.last_chain_sync())
+                        .is_err()
+                    {
+                        log_warn!(self.logger, "EVENT: OpenChannelRequest rejecting 0 conf channel, the chain has not been synced recently");
+                        let result = self.channel_manager.force_close_without_broadcasting_txn(
+                            &temporary_channel_id,
+                            &counterparty_node_id,
+                        );
+                        log_result(result);
+                        return;
+                    }
+
+                    let op = ChannelOperation::AcceptJitChannel {
+                        amount_sats: funding_satoshis,
+                    };
+                    if let Err(e) = self.congestion.check_now(op) {
+                        log_warn!(self.logger, "EVENT: OpenChannelRequest rejecting {funding_satoshis} sat 0 conf channel: {e}");
+                        let result = self.channel_manager.force_close_without_broadcasting_txn(
+                            &temporary_channel_id,
+                            &counterparty_node_id,
+                        );
+                        log_result(result);
+                        return;
+                    }
+
                     // matched lsp pubkey, accept 0 conf
                     let result = self
                         .channel_manager
@@ -371,8 +463,14 @@ impl<S: MutinyStorage> EventHandler<S> {
                     log_result(result);
                 }
             }
-            Event::PaymentPathSuccessful { .. } => {
-                log_debug!(self.logger, "EVENT: PaymentPathSuccessful, ignored");
+            Event::PaymentPathSuccessful {
+                payment_id, path, ..
+            } => {
+                log_debug!(self.logger, "EVENT: PaymentPathSuccessful");
+                if let Some(first_hop) = path.hops.first() {
+                    self.payment_privacy
+                        .path_successful(&payment_id, first_hop.short_channel_id);
+                }
             }
             Event::PaymentPathFailed { .. } => {
                 log_debug!(self.logger, "EVENT: PaymentPathFailed, ignored");
@@ -383,12 +481,17 @@ impl<S: MutinyStorage> EventHandler<S> {
             Event::ProbeFailed { .. } => {
                 log_debug!(self.logger, "EVENT: ProbeFailed, ignored");
             }
-            Event::PaymentFailed { payment_hash, .. } => {
+            Event::PaymentFailed {
+                payment_id,
+                payment_hash,
+                ..
+            } => {
                 log_error!(
                     self.logger,
                     "EVENT: PaymentFailed: {}",
                     payment_hash.0.to_hex()
                 );
+                self.payment_privacy.payment_failed(&payment_id);
 
                 match self
                     .persister
@@ -476,6 +579,8 @@ impl<S: MutinyStorage> EventHandler<S> {
                     }
                 });
 
+                let event =
+                    WebhookEvent::channel_closed(user_channel_id, &reason, utils::now().as_secs());
                 let closure = ChannelClosure::new(user_channel_id, channel_id, node_id, reason);
                 if let Err(e) = self
                     .persister
@@ -483,6 +588,8 @@ impl<S: MutinyStorage> EventHandler<S> {
                 {
                     log_error!(self.logger, "Failed to persist channel closure: {e}");
                 }
+
+                enqueue_webhook_event(&self.persister.storage, &self.logger, event);
             }
             Event::DiscardFunding { .. } => {
                 // A "real" node should probably "lock" the UTXOs spent in funding transactions until
@@ -594,8 +701,18 @@ impl<S: MutinyStorage> EventHandler<S> {
             )
             .map_err(|_| anyhow!("Failed to spend spendable outputs"))?;
 
+        let sweep = RecoverySweep {
+            txid: spending_tx.txid(),
+            amount_sats: spending_tx.output.iter().map(|o| o.value).sum(),
+        };
         self.wallet.broadcast_transaction(spending_tx).await?;
 
+        // the funds are no longer in the channel monitor, keep showing
+        // them as pending until the sweep confirms
+        if let Err(e) = self.persister.persist_recovery_sweep(sweep) {
+            log_error!(self.logger, "Failed to persist recovery sweep: {e}");
+        }
+
         Ok(())
     }
 }
@@ -627,6 +744,7 @@ mod test {
             payee_pubkey: Some(pubkey),
             secret: None,
             last_update: utils::now().as_secs(),
+            external_settlement: None,
         };
 
         let serialized = serde_json::to_string(&payment_info).unwrap();
@@ -0,0 +1,254 @@
+use crate::capabilities::Capability;
+use crate::error::MutinyError;
+use crate::utils;
+use lightning::ln::PaymentPreimage;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+pub(crate) const STRICT_MODE_CAPABILITY: Capability = Capability::flag("strict_mode");
+
+/// Claims above this amount are considered large by default.
+pub const DEFAULT_LARGE_CLAIM_THRESHOLD_SATS: u64 = 100_000;
+
+/// Operations that can be dangerous to perform without a recent chain sync,
+/// because we may be blind to a competing spend or a reorg.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChainOperation {
+    /// Accepting an inbound zero-conf channel
+    AcceptZeroConfOpen,
+    /// Claiming an incoming payment
+    ClaimPayment { amount_msat: u64 },
+    /// Cooperatively closing a channel
+    CooperativeClose,
+}
+
+/// Strict mode settings, each field is the max age in seconds of the last
+/// successful chain sync for that class of operation. `None` disables the check,
+/// which is the default for everything.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StrictModeConfig {
+    pub zero_conf_open_max_age: Option<u64>,
+    pub large_claim_max_age: Option<u64>,
+    pub large_claim_threshold_sats: u64,
+    pub cooperative_close_max_age: Option<u64>,
+}
+
+impl Default for StrictModeConfig {
+    fn default() -> Self {
+        Self {
+            zero_conf_open_max_age: None,
+            large_claim_max_age: None,
+            large_claim_threshold_sats: DEFAULT_LARGE_CLAIM_THRESHOLD_SATS,
+            cooperative_close_max_age: None,
+        }
+    }
+}
+
+impl StrictModeConfig {
+    /// Returns true if any operation requires a recent chain sync
+    pub fn is_enabled(&self) -> bool {
+        self.zero_conf_open_max_age.is_some()
+            || self.large_claim_max_age.is_some()
+            || self.cooperative_close_max_age.is_some()
+    }
+
+    /// The max age in seconds of the last chain sync for the given operation,
+    /// if there is a requirement for it.
+    pub fn max_sync_age(&self, op: ChainOperation) -> Option<u64> {
+        match op {
+            ChainOperation::AcceptZeroConfOpen => self.zero_conf_open_max_age,
+            ChainOperation::ClaimPayment { amount_msat } => {
+                if amount_msat / 1_000 > self.large_claim_threshold_sats {
+                    self.large_claim_max_age
+                } else {
+                    None
+                }
+            }
+            ChainOperation::CooperativeClose => self.cooperative_close_max_age,
+        }
+    }
+
+    /// Checks if the given operation can be performed, given the time of the
+    /// last successful chain sync. Returns [`MutinyError::ChainSyncStale`] if
+    /// the operation should wait until the next sync completes.
+    pub fn check(
+        &self,
+        op: ChainOperation,
+        last_sync: Option<u64>,
+        now: u64,
+    ) -> Result<(), MutinyError> {
+        let Some(max_age) = self.max_sync_age(op) else {
+            return Ok(());
+        };
+
+        match last_sync {
+            Some(last_sync) if now.saturating_sub(last_sync) <= max_age => Ok(()),
+            _ => Err(MutinyError::ChainSyncStale),
+        }
+    }
+
+    /// Same as [`StrictModeConfig::check`] using the current time.
+    pub fn check_now(&self, op: ChainOperation, last_sync: Option<u64>) -> Result<(), MutinyError> {
+        self.check(op, last_sync, utils::now().as_secs())
+    }
+}
+
+/// Claims that were deferred by strict mode, they are claimed
+/// once the next chain sync completes.
+#[derive(Clone)]
+pub(crate) struct DeferredClaims {
+    claims: Arc<utils::Mutex<Vec<PaymentPreimage>>>,
+}
+
+impl Default for DeferredClaims {
+    fn default() -> Self {
+        Self {
+            claims: Arc::new(utils::Mutex::new(vec![])),
+        }
+    }
+}
+
+impl DeferredClaims {
+    pub fn push(&self, preimage: PaymentPreimage) {
+        let mut claims = self.claims.lock().expect("Failed to lock deferred claims");
+        if !claims.contains(&preimage) {
+            claims.push(preimage);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.claims
+            .lock()
+            .expect("Failed to lock deferred claims")
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes and returns all of the deferred claims
+    pub fn drain(&self) -> Vec<PaymentPreimage> {
+        let mut claims = self.claims.lock().expect("Failed to lock deferred claims");
+        std::mem::take(&mut *claims)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn strict() -> StrictModeConfig {
+        StrictModeConfig {
+            zero_conf_open_max_age: Some(600),
+            large_claim_max_age: Some(3_600),
+            large_claim_threshold_sats: 50_000,
+            cooperative_close_max_age: Some(600),
+        }
+    }
+
+    #[test]
+    fn test_strict_mode_off_by_default() {
+        let test_name = "test_strict_mode_off_by_default";
+        log!("{}", test_name);
+
+        let config = StrictModeConfig::default();
+        assert!(!config.is_enabled());
+
+        let ops = [
+            ChainOperation::AcceptZeroConfOpen,
+            ChainOperation::ClaimPayment {
+                amount_msat: 1_000_000_000,
+            },
+            ChainOperation::CooperativeClose,
+        ];
+        for op in ops {
+            assert!(config.check(op, None, NOW).is_ok());
+            assert!(config.check(op, Some(0), NOW).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_strict_mode_stale_sync() {
+        let test_name = "test_strict_mode_stale_sync";
+        log!("{}", test_name);
+
+        let config = strict();
+        assert!(config.is_enabled());
+
+        let open = ChainOperation::AcceptZeroConfOpen;
+        assert!(config.check(open, Some(NOW - 60), NOW).is_ok());
+        assert!(config.check(open, Some(NOW - 600), NOW).is_ok());
+        assert!(matches!(
+            config.check(open, Some(NOW - 601), NOW),
+            Err(MutinyError::ChainSyncStale)
+        ));
+        // never synced
+        assert!(matches!(
+            config.check(open, None, NOW),
+            Err(MutinyError::ChainSyncStale)
+        ));
+
+        // small claims are not affected
+        let small = ChainOperation::ClaimPayment {
+            amount_msat: 50_000_000,
+        };
+        assert!(config.check(small, None, NOW).is_ok());
+
+        let large = ChainOperation::ClaimPayment {
+            amount_msat: 50_001_000,
+        };
+        assert!(config.check(large, Some(NOW - 3_600), NOW).is_ok());
+        assert!(matches!(
+            config.check(large, Some(NOW - 7_200), NOW),
+            Err(MutinyError::ChainSyncStale)
+        ));
+
+        let close = ChainOperation::CooperativeClose;
+        assert!(matches!(
+            config.check(close, Some(NOW - 3_600), NOW),
+            Err(MutinyError::ChainSyncStale)
+        ));
+    }
+
+    #[test]
+    fn test_deferred_claims_drain() {
+        let test_name = "test_deferred_claims_drain";
+        log!("{}", test_name);
+
+        let config = strict();
+        let deferred = DeferredClaims::default();
+        let large = ChainOperation::ClaimPayment {
+            amount_msat: 100_000_000,
+        };
+
+        // stale sync, the claims are queued
+        let last_sync = Some(NOW - 7_200);
+        for preimage in [PaymentPreimage([1; 32]), PaymentPreimage([2; 32])] {
+            if config.check(large, last_sync, NOW).is_err() {
+                deferred.push(preimage);
+            }
+        }
+        // duplicates are ignored
+        deferred.push(PaymentPreimage([1; 32]));
+        assert_eq!(deferred.len(), 2);
+
+        // after a sync the queue is drained and the claims are allowed
+        let last_sync = Some(NOW);
+        assert!(config.check(large, last_sync, NOW).is_ok());
+        let drained = deferred.drain();
+        assert_eq!(
+            drained,
+            vec![PaymentPreimage([1; 32]), PaymentPreimage([2; 32])]
+        );
+        assert!(deferred.is_empty());
+        assert!(deferred.drain().is_empty());
+    }
+}
@@ -17,6 +17,7 @@ use serde::{Deserialize, Serialize};
 use crate::error::MutinyError;
 use crate::logging::MutinyLogger;
 use crate::node::{NetworkGraph, ProbScorer, RapidGossipSync};
+use crate::scb::message_handler::PeerBackupPermissions;
 use crate::storage::MutinyStorage;
 use crate::utils;
 
@@ -233,6 +234,15 @@ pub struct LnPeerMetadata {
     /// Our nodes' uuids that are connected to this node
     #[serde(default)]
     pub nodes: Vec<String>,
+    /// When we last connected to this node, in seconds since the unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_connected: Option<u64>,
+    /// If our static channel backup is sent to this node to store
+    #[serde(default)]
+    pub send_backup_to: bool,
+    /// If this node may store its static channel backup with us
+    #[serde(default)]
+    pub accept_backup_from: bool,
 }
 
 impl LnPeerMetadata {
@@ -264,6 +274,13 @@ impl LnPeerMetadata {
         }
     }
 
+    pub(crate) fn backup_permissions(&self) -> PeerBackupPermissions {
+        PeerBackupPermissions {
+            send_backup_to: self.send_backup_to,
+            accept_backup_from: self.accept_backup_from,
+        }
+    }
+
     pub(crate) fn merge_opt(&self, other: &Option<LnPeerMetadata>) -> LnPeerMetadata {
         match other {
             Some(other) => self.merge(other),
@@ -296,6 +313,11 @@ impl LnPeerMetadata {
             label: primary.label.or(secondary.label),
             timestamp: primary.timestamp.or(secondary.timestamp),
             nodes,
+            last_connected: primary.last_connected.max(secondary.last_connected),
+            // gossip never carries these, they are only turned off
+            // by set_peer_backup_permissions
+            send_backup_to: primary.send_backup_to || secondary.send_backup_to,
+            accept_backup_from: primary.accept_backup_from || secondary.accept_backup_from,
         }
     }
 }
@@ -309,6 +331,9 @@ impl From<NodeAnnouncement> for LnPeerMetadata {
             label: None,
             timestamp: Some(value.contents.timestamp),
             nodes: vec![],
+            last_connected: None,
+            send_backup_to: false,
+            accept_backup_from: false,
         }
     }
 }
@@ -366,6 +391,26 @@ pub(crate) fn save_peer_connection_info(
     Ok(())
 }
 
+/// Records when we connected to the node, if we have saved info for it.
+pub(crate) fn set_peer_last_connected(
+    storage: &impl MutinyStorage,
+    node_id: &NodeId,
+    now: u64,
+) -> Result<(), MutinyError> {
+    let key = format!("{LN_PEER_METADATA_KEY_PREFIX}{node_id}");
+
+    let current: Option<LnPeerMetadata> = storage.get_data(&key)?;
+    if let Some(current) = current {
+        let new_info = LnPeerMetadata {
+            last_connected: Some(now),
+            ..current
+        };
+        storage.set_data(key, new_info)?;
+    }
+
+    Ok(())
+}
+
 pub(crate) fn set_peer_label(
     storage: &impl MutinyStorage,
     node_id: &NodeId,
@@ -392,6 +437,28 @@ pub(crate) fn set_peer_label(
     Ok(())
 }
 
+/// Sets which static channel backups are exchanged with the node.
+pub(crate) fn set_peer_backup_permissions(
+    storage: &impl MutinyStorage,
+    node_id: &NodeId,
+    permissions: PeerBackupPermissions,
+) -> Result<(), MutinyError> {
+    let key = format!("{LN_PEER_METADATA_KEY_PREFIX}{node_id}");
+
+    let current: Option<LnPeerMetadata> = storage.get_data(&key)?;
+    let new_info = LnPeerMetadata {
+        send_backup_to: permissions.send_backup_to,
+        accept_backup_from: permissions.accept_backup_from,
+        ..current.unwrap_or_else(|| LnPeerMetadata {
+            timestamp: Some(utils::now().as_secs() as u32),
+            ..Default::default()
+        })
+    };
+
+    storage.set_data(key, new_info)?;
+    Ok(())
+}
+
 pub(crate) fn delete_peer_info(
     storage: &impl MutinyStorage,
     uuid: &str,
@@ -487,6 +554,9 @@ mod test {
             label: Some("test label".to_string()),
             timestamp: Some(utils::now().as_secs() as u32),
             nodes: vec![uuid],
+            last_connected: None,
+            send_backup_to: false,
+            accept_backup_from: false,
         };
 
         (node_id, data)
@@ -555,6 +625,76 @@ mod test {
         assert!(read.is_none());
     }
 
+    #[test]
+    fn test_peer_last_connected() {
+        let storage = MemoryStorage::default();
+        let (node_id, data) = dummy_peer_info();
+        save_ln_peer_info(&storage, &node_id, &data).unwrap();
+
+        set_peer_last_connected(&storage, &node_id, 1_690_000_000).unwrap();
+        let read = read_peer_info(&storage, &node_id).unwrap().unwrap();
+        assert_eq!(read.last_connected, Some(1_690_000_000));
+
+        // newer gossip does not forget when we connected
+        let announced = LnPeerMetadata {
+            timestamp: Some(u32::MAX),
+            ..Default::default()
+        };
+        assert_eq!(announced.merge(&read).last_connected, Some(1_690_000_000));
+
+        // nothing is saved for peers we do not know
+        let unknown = dummy_node_id();
+        set_peer_last_connected(&storage, &unknown, 1_690_000_000).unwrap();
+        assert!(read_peer_info(&storage, &unknown).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_peer_backup_permissions() {
+        let storage = MemoryStorage::default();
+        let (node_id, data) = dummy_peer_info();
+        save_ln_peer_info(&storage, &node_id, &data).unwrap();
+        assert_eq!(
+            read_peer_info(&storage, &node_id)
+                .unwrap()
+                .unwrap()
+                .backup_permissions(),
+            PeerBackupPermissions::default()
+        );
+
+        let permissions = PeerBackupPermissions {
+            send_backup_to: true,
+            accept_backup_from: false,
+        };
+        set_peer_backup_permissions(&storage, &node_id, permissions).unwrap();
+        let read = read_peer_info(&storage, &node_id).unwrap().unwrap();
+        assert_eq!(read.backup_permissions(), permissions);
+        assert_eq!(read.alias, data.alias);
+
+        // newer gossip does not reset them
+        let announced = LnPeerMetadata {
+            timestamp: Some(u32::MAX),
+            ..Default::default()
+        };
+        save_ln_peer_info(&storage, &node_id, &announced).unwrap();
+        let read = read_peer_info(&storage, &node_id).unwrap().unwrap();
+        assert_eq!(read.backup_permissions(), permissions);
+
+        // revoking is saved
+        set_peer_backup_permissions(&storage, &node_id, PeerBackupPermissions::default()).unwrap();
+        let all = get_all_peers(&storage).unwrap();
+        assert_eq!(
+            all.get(&node_id).unwrap().backup_permissions(),
+            PeerBackupPermissions::default()
+        );
+
+        // metadata saved before the permissions existed reads as opted out
+        let old: LnPeerMetadata = serde_json::from_str(
+            r#"{"connection_string":null,"alias":"old","color":null,"label":null,"timestamp":1}"#,
+        )
+        .unwrap();
+        assert_eq!(old.backup_permissions(), PeerBackupPermissions::default());
+    }
+
     #[test]
     fn test_delete_label() {
         let storage = MemoryStorage::default();
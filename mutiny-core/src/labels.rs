@@ -1,16 +1,20 @@
 use crate::error::MutinyError;
 use crate::nodemanager::NodeManager;
+use crate::search::{update_search_index, SearchDocument, SearchResultKind};
 use crate::storage::MutinyStorage;
-use bitcoin::{Address, XOnlyPublicKey};
+use bitcoin::hashes::hex::ToHex;
+use bitcoin::{Address, OutPoint, XOnlyPublicKey};
 use lightning_invoice::Invoice;
 use lnurl::lightning_address::LightningAddress;
 use lnurl::lnurl::LnUrl;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::str::FromStr;
 use uuid::Uuid;
 
 const ADDRESS_LABELS_MAP_KEY: &str = "address_labels";
 const INVOICE_LABELS_MAP_KEY: &str = "invoice_labels";
+const CHANNEL_LABELS_MAP_KEY: &str = "channel_labels";
 const LABEL_PREFIX: &str = "label/";
 const CONTACT_PREFIX: &str = "contact/";
 
@@ -69,6 +73,14 @@ pub trait LabelStorage {
     /// If you do not want to replace any existing labels, use `get_invoice_labels` to get the existing labels,
     /// add the new labels, and then use `set_invoice_labels` to set the new labels
     fn set_invoice_labels(&self, invoice: Invoice, labels: Vec<String>) -> Result<(), MutinyError>;
+    /// Get a map of channel funding outpoints to the label of the channel
+    fn get_channel_labels(&self) -> Result<HashMap<OutPoint, String>, MutinyError>;
+    /// Set the label for a channel, replacing the existing label. An empty or `None` label removes it
+    fn set_channel_label(
+        &self,
+        outpoint: OutPoint,
+        label: Option<String>,
+    ) -> Result<(), MutinyError>;
     /// Get all the existing contacts
     fn get_contacts(&self) -> Result<HashMap<String, Contact>, MutinyError>;
     /// Get a contact by label, the label should be a uuid
@@ -127,7 +139,7 @@ impl<S: MutinyStorage> LabelStorage for S {
         let now = crate::utils::now().as_secs();
         for label in labels {
             let key = get_label_item_key(&label);
-            match self.get_label(label)? {
+            match self.get_label(&label)? {
                 Some(mut label_item) => {
                     // Add the address to the label item
                     // and sort so we can dedup the addresses
@@ -138,7 +150,10 @@ impl<S: MutinyStorage> LabelStorage for S {
                     // Update the last used timestamp
                     label_item.last_used_time = now;
 
-                    self.set_data(key, label_item)?;
+                    self.set_data(key, &label_item)?;
+                    update_search_index(self, |index| {
+                        index.insert(SearchDocument::label(label, &label_item))
+                    })?;
                 }
                 None => {
                     // Create a new label item
@@ -147,7 +162,10 @@ impl<S: MutinyStorage> LabelStorage for S {
                         invoices: vec![],
                         last_used_time: now,
                     };
-                    self.set_data(key, label_item)?;
+                    self.set_data(key, &label_item)?;
+                    update_search_index(self, |index| {
+                        index.insert(SearchDocument::label(label, &label_item))
+                    })?;
                 }
             }
         }
@@ -161,11 +179,20 @@ impl<S: MutinyStorage> LabelStorage for S {
         invoice_labels.insert(invoice.clone(), labels.clone());
         self.set_data(INVOICE_LABELS_MAP_KEY, invoice_labels)?;
 
-        // update the label items
+        // update the labels of the payment in the search index
         let now = crate::utils::now().as_secs();
+        update_search_index(self, |index| {
+            index.insert(SearchDocument::lightning_labels(
+                invoice.payment_hash().to_hex(),
+                labels.clone(),
+                now,
+            ))
+        })?;
+
+        // update the label items
         for label in labels {
             let key = get_label_item_key(&label);
-            match self.get_label(label)? {
+            match self.get_label(&label)? {
                 Some(mut label_item) => {
                     // Add the invoice to the label item
                     // and sort so we can dedup the invoices
@@ -176,7 +203,10 @@ impl<S: MutinyStorage> LabelStorage for S {
                     // Update the last used timestamp
                     label_item.last_used_time = now;
 
-                    self.set_data(key, label_item)?;
+                    self.set_data(key, &label_item)?;
+                    update_search_index(self, |index| {
+                        index.insert(SearchDocument::label(label, &label_item))
+                    })?;
                 }
                 None => {
                     // Create a new label item
@@ -185,7 +215,10 @@ impl<S: MutinyStorage> LabelStorage for S {
                         invoices: vec![invoice.to_string()],
                         last_used_time: now,
                     };
-                    self.set_data(key, label_item)?;
+                    self.set_data(key, &label_item)?;
+                    update_search_index(self, |index| {
+                        index.insert(SearchDocument::label(label, &label_item))
+                    })?;
                 }
             }
         }
@@ -193,6 +226,34 @@ impl<S: MutinyStorage> LabelStorage for S {
         Ok(())
     }
 
+    fn get_channel_labels(&self) -> Result<HashMap<OutPoint, String>, MutinyError> {
+        // keyed by the `txid:vout` string so the map is a JSON object
+        let res: Option<HashMap<String, String>> = self.get_data(CHANNEL_LABELS_MAP_KEY)?;
+        let labels = res
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|(outpoint, label)| Some((OutPoint::from_str(&outpoint).ok()?, label)))
+            .collect();
+        Ok(labels)
+    }
+
+    fn set_channel_label(
+        &self,
+        outpoint: OutPoint,
+        label: Option<String>,
+    ) -> Result<(), MutinyError> {
+        let mut channel_labels = self.get_channel_labels()?;
+        match label.filter(|l| !l.is_empty()) {
+            Some(label) => channel_labels.insert(outpoint, label),
+            None => channel_labels.remove(&outpoint),
+        };
+        let channel_labels: HashMap<String, String> = channel_labels
+            .into_iter()
+            .map(|(outpoint, label)| (outpoint.to_string(), label))
+            .collect();
+        self.set_data(CHANNEL_LABELS_MAP_KEY, channel_labels)
+    }
+
     fn get_contacts(&self) -> Result<HashMap<String, Contact>, MutinyError> {
         let all = self.scan::<Contact>(CONTACT_PREFIX, None)?;
         // remove the prefix from the keys
@@ -262,10 +323,15 @@ impl<S: MutinyStorage> LabelStorage for S {
 
                 // create the contact
                 let key = get_contact_key(&id);
-                self.set_data(key, contact)?;
+                self.set_data(key, &contact)?;
 
                 // delete old label item
                 self.delete(&[get_label_item_key(&label)])?;
+
+                update_search_index(self, |index| {
+                    index.remove(SearchResultKind::Label, label.as_ref());
+                    index.insert(SearchDocument::contact(id.clone(), &contact));
+                })?;
                 Ok(id)
             }
         }
@@ -276,7 +342,10 @@ impl<S: MutinyStorage> LabelStorage for S {
         // generate a uuid, this will be the "label" that we use to store the contact
         let id = Uuid::new_v4().to_string();
         let key = get_contact_key(&id);
-        self.set_data(key, contact)?;
+        self.set_data(key, &contact)?;
+        update_search_index(self, |index| {
+            index.insert(SearchDocument::contact(id.clone(), &contact))
+        })?;
 
         let key = get_label_item_key(&id);
         let label_item = LabelItem {
@@ -292,12 +361,18 @@ impl<S: MutinyStorage> LabelStorage for S {
         if let Some(mut contact) = contact {
             contact.archived = Some(true);
             self.set(get_contact_key(&id), contact)?;
+            update_search_index(self, |index| {
+                index.remove(SearchResultKind::Contact, id.as_ref())
+            })?;
         }
         Ok(())
     }
 
     fn edit_contact(&self, id: impl AsRef<str>, contact: Contact) -> Result<(), MutinyError> {
-        self.set(get_contact_key(&id), contact)
+        self.set(get_contact_key(&id), &contact)?;
+        update_search_index(self, |index| {
+            index.insert(SearchDocument::contact(id.as_ref().to_string(), &contact))
+        })
     }
 
     fn get_tag_items(&self) -> Result<Vec<TagItem>, MutinyError> {
@@ -354,6 +429,18 @@ impl<S: MutinyStorage> LabelStorage for NodeManager<S> {
         self.storage.set_invoice_labels(invoice, labels)
     }
 
+    fn get_channel_labels(&self) -> Result<HashMap<OutPoint, String>, MutinyError> {
+        self.storage.get_channel_labels()
+    }
+
+    fn set_channel_label(
+        &self,
+        outpoint: OutPoint,
+        label: Option<String>,
+    ) -> Result<(), MutinyError> {
+        self.storage.set_channel_label(outpoint, label)
+    }
+
     fn get_contacts(&self) -> Result<HashMap<String, Contact>, MutinyError> {
         self.storage.get_contacts()
     }
@@ -706,6 +793,30 @@ mod tests {
         assert!(contacts.get(&id).is_none());
     }
 
+    #[test]
+    fn test_set_channel_label() {
+        let test_name = "test_set_channel_label";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let outpoint = OutPoint::from_str(
+            "830b1c110ef6c78312a8f4c798da0bfbacdfc9c80c7d458ca614e7b1543f5b03:1",
+        )
+        .unwrap();
+
+        storage
+            .set_channel_label(outpoint, Some("savings".to_string()))
+            .unwrap();
+        let labels = storage.get_channel_labels().unwrap();
+        assert_eq!(labels.get(&outpoint), Some(&"savings".to_string()));
+
+        // an empty label removes it
+        storage
+            .set_channel_label(outpoint, Some(String::new()))
+            .unwrap();
+        assert!(storage.get_channel_labels().unwrap().is_empty());
+    }
+
     #[test]
     async fn test_create_contact_from_label() {
         let test_name = "test_create_contact_from_label";
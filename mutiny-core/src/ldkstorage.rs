@@ -1,20 +1,23 @@
 use crate::chain::MutinyChain;
 use crate::error::{MutinyError, MutinyStorageError};
-use crate::event::PaymentInfo;
+use crate::event::{ExternalSettlement, HTLCStatus, PaymentInfo};
 use crate::fees::MutinyFeeEstimator;
 use crate::gossip::{NETWORK_GRAPH_KEY, PROB_SCORER_KEY};
 use crate::keymanager::PhantomKeysManager;
 use crate::logging::MutinyLogger;
 use crate::node::{default_user_config, ChainMonitor, ProbScorer};
 use crate::node::{NetworkGraph, Router};
-use crate::nodemanager::ChannelClosure;
-use crate::storage::MutinyStorage;
+use crate::nodemanager::{ChannelClosure, MutinyInvoice};
+use crate::recovery::RecoverySweep;
+use crate::search::{update_search_index, SearchDocument};
+use crate::storage::{MutinyStorage, QuarantineReport, RepairReport, QUARANTINE_PREFIX};
 use crate::utils;
 use anyhow::anyhow;
 use bdk_esplora::esplora_client::AsyncClient;
 use bitcoin::hashes::hex::{FromHex, ToHex};
+use bitcoin::hashes::{sha256, Hash};
 use bitcoin::Network;
-use bitcoin::{BlockHash, Transaction};
+use bitcoin::{BlockHash, Transaction, Txid};
 use futures::{try_join, TryFutureExt};
 use lightning::chain::channelmonitor::{ChannelMonitor, ChannelMonitorUpdate};
 use lightning::chain::transaction::OutPoint;
@@ -28,23 +31,28 @@ use lightning::sign::{InMemorySigner, SpendableOutputDescriptor, WriteableEcdsaC
 use lightning::util::logger::Logger;
 use lightning::util::persist::Persister;
 use lightning::util::ser::{Readable, ReadableArgs, Writeable};
-use lightning::{chain, log_trace};
+use lightning::{chain, log_trace, log_warn};
 use lightning::{
     chain::chainmonitor::{MonitorUpdateId, Persist},
     log_error,
 };
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::collections::HashMap;
 use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 
 pub const CHANNEL_MANAGER_KEY: &str = "manager";
 pub const MONITORS_PREFIX_KEY: &str = "monitors/";
+/// Channel managers of odd persistence epochs, even epochs use [`CHANNEL_MANAGER_KEY`]
+pub const CHANNEL_MANAGER_ALT_KEY: &str = "manager_alt";
 const PAYMENT_INBOUND_PREFIX_KEY: &str = "payment_inbound/";
 const PAYMENT_OUTBOUND_PREFIX_KEY: &str = "payment_outbound/";
 const CHANNEL_OPENING_PARAMS_PREFIX: &str = "chan_open_params/";
 const CHANNEL_CLOSURE_PREFIX: &str = "channel_closure/";
 const FAILED_SPENDABLE_OUTPUT_DESCRIPTOR_KEY: &str = "failed_spendable_outputs";
+const RECOVERY_SWEEPS_KEY: &str = "recovery_sweeps";
 
 pub(crate) type PhantomChannelManager<S: MutinyStorage> = LdkChannelManager<
     Arc<ChainMonitor<S>>,
@@ -62,6 +70,55 @@ pub struct MutinyNodePersister<S: MutinyStorage> {
     node_id: String,
     pub(crate) storage: S,
     logger: Arc<MutinyLogger>,
+    /// Set when a channel monitor is persisted, starts out set so the
+    /// monitors loaded at startup count as changed
+    monitors_changed: Arc<AtomicBool>,
+    /// The open persistence epoch, channel manager and monitor writes are
+    /// tagged with it and each channel manager write opens the next one
+    epoch: Arc<AtomicU64>,
+    /// The epochs of the channel monitors read at startup, by key
+    monitor_epochs: Arc<utils::Mutex<HashMap<String, u64>>>,
+}
+
+/// The encoding of a channel manager or monitor, tagged with the persistence
+/// epoch it was written in.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+struct EpochValue {
+    epoch: u64,
+    value: Vec<u8>,
+}
+
+/// Values stored before persistence epochs count as epoch 0.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredEpochValue {
+    Tagged(EpochValue),
+    Legacy(Vec<u8>),
+}
+
+impl From<StoredEpochValue> for EpochValue {
+    fn from(stored: StoredEpochValue) -> Self {
+        match stored {
+            StoredEpochValue::Tagged(value) => value,
+            StoredEpochValue::Legacy(value) => EpochValue { epoch: 0, value },
+        }
+    }
+}
+
+/// What was rolled back at startup because the newest channel manager could not
+/// be read or decoded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct PersistenceRollback {
+    /// The epoch of the newest channel manager that was discarded, `None` if its
+    /// slot could not be read at all
+    pub discarded_epoch: Option<u64>,
+    /// The epoch of the older channel manager that was loaded instead
+    pub restored_epoch: u64,
+    /// Channel monitors written after the restored epoch. The restored channel
+    /// manager may be stale against them
+    pub newer_monitors: Vec<String>,
+    /// Why the newer channel manager was not loaded
+    pub reason: String,
 }
 
 pub(crate) struct ReadChannelManager<S: MutinyStorage> {
@@ -76,64 +133,198 @@ impl<S: MutinyStorage> MutinyNodePersister<S> {
             node_id,
             storage,
             logger,
+            monitors_changed: Arc::new(AtomicBool::new(true)),
+            epoch: Arc::new(AtomicU64::new(1)),
+            monitor_epochs: Arc::new(utils::Mutex::new(HashMap::new())),
         }
     }
 
+    /// Returns whether a channel monitor was persisted since the last call.
+    pub(crate) fn take_monitors_changed(&self) -> bool {
+        self.monitors_changed.swap(false, Ordering::Relaxed)
+    }
+
+    /// Called whenever a channel monitor is persisted, like when a channel is
+    /// opened, updated or closed.
+    pub(crate) fn monitor_persisted(&self) {
+        self.monitors_changed.store(true, Ordering::Relaxed);
+    }
+
     fn get_key(&self, key: &str) -> String {
         format!("{}_{}", key, self.node_id)
     }
 
-    fn persist_local_storage<W: Writeable>(
+    fn persist_local_storage<T: Serialize>(
         &self,
         key: &str,
-        object: &W,
+        value: T,
     ) -> Result<(), lightning::io::Error> {
         let key_with_node = self.get_key(key);
-        self.storage
-            .set_data(key_with_node, object.encode())
-            .map_err(|e| {
-                match e {
-                    MutinyError::PersistenceFailed { source } => {
-                        log_error!(self.logger, "Persistence failed on {key}: {source}");
-                    }
-                    _ => {
-                        log_error!(self.logger, "Error storing {key}: {e}");
-                    }
-                };
-                lightning::io::ErrorKind::Other.into()
-            })
+        self.storage.set_data(key_with_node, value).map_err(|e| {
+            match e {
+                MutinyError::PersistenceFailed { source } => {
+                    log_error!(self.logger, "Persistence failed on {key}: {source}");
+                }
+                _ => {
+                    log_error!(self.logger, "Error storing {key}: {e}");
+                }
+            };
+            lightning::io::ErrorKind::Other.into()
+        })
     }
 
-    // name this param _key so it is not confused with the key
-    // that has the concatenated node_id
-    fn read_value(&self, _key: &str) -> Result<Vec<u8>, MutinyError> {
-        let key = self.get_key(_key);
-        match self.storage.get_data(&key) {
-            Ok(Some(value)) => Ok(value),
-            Ok(None) => Err(MutinyError::read_err(MutinyStorageError::Other(anyhow!(
-                "No value found for key: {key}"
-            )))),
-            Err(e) => Err(e),
-        }
+    /// Writes a channel monitor tagged with the open epoch. This is the only
+    /// write for a monitor update, the epoch piggybacks on it.
+    fn persist_monitor(&self, key: &str, value: Vec<u8>) -> Result<(), lightning::io::Error> {
+        let epoch = self.epoch.load(Ordering::Relaxed);
+        self.persist_local_storage(key, EpochValue { epoch, value })
     }
 
-    pub fn read_channel_monitors(
+    /// Writes the channel manager into the slot of the open epoch and opens the
+    /// next one. The two slots take turns, so the manager written before this one
+    /// is kept in the other slot in case this one can not be decoded.
+    fn write_manager(&self, value: Vec<u8>) -> Result<u64, lightning::io::Error> {
+        let epoch = self.epoch.load(Ordering::Relaxed);
+        self.persist_local_storage(manager_slot_key(epoch), EpochValue { epoch, value })?;
+        self.epoch.store(epoch + 1, Ordering::Relaxed);
+        Ok(epoch)
+    }
+
+    /// Deletes both stored channel managers, for nodes that start from an empty
+    /// state so an old manager is never paired with new monitors.
+    pub(crate) fn reset_channel_manager(&self) -> Result<(), MutinyError> {
+        let keys = [CHANNEL_MANAGER_KEY, CHANNEL_MANAGER_ALT_KEY].map(|key| self.get_key(key));
+        self.storage.delete(&keys)?;
+        self.epoch.store(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Loads the newest stored channel manager that can be decoded. Returns `None`
+    /// only if no channel manager was ever stored.
+    ///
+    /// Channel monitors are persisted before the channel manager, so the newest
+    /// manager that was written is never older than its monitors. Loading an older
+    /// manager would make it stale against the monitors and LDK would force close
+    /// those channels, so the other slot is only loaded if the newest one can not be
+    /// read or decoded. That rollback is reported.
+    /// Channel monitors are read before this so the ones newer than a restored
+    /// manager are reported with it.
+    fn load_newest_manager<T>(
         &self,
-        keys_manager: Arc<PhantomKeysManager<S>>,
-    ) -> Result<Vec<(BlockHash, ChannelMonitor<InMemorySigner>)>, io::Error> {
-        // Get all the channel monitor buffers that exist for this node
+        mut decode: impl FnMut(Vec<u8>) -> Result<T, MutinyError>,
+    ) -> Result<Option<(T, Option<PersistenceRollback>)>, MutinyError> {
+        // a slot that holds something we can not read still counts as stored,
+        // so a new channel manager never replaces it
+        let mut failures: Vec<String> = vec![];
+        let mut slots: Vec<EpochValue> = vec![];
+        for key in [CHANNEL_MANAGER_KEY, CHANNEL_MANAGER_ALT_KEY] {
+            match self.storage.get_data::<StoredEpochValue>(self.get_key(key)) {
+                Ok(stored) => slots.extend(stored.map(EpochValue::from)),
+                Err(e) => {
+                    log_warn!(self.logger, "Could not read channel manager {key}: {e}");
+                    failures.push(format!("{key}: {e}"));
+                }
+            }
+        }
+        if slots.is_empty() && failures.is_empty() {
+            return Ok(None);
+        }
+
+        slots.sort_by_key(|slot| std::cmp::Reverse(slot.epoch));
+        let newest_epoch = slots.first().map(|slot| slot.epoch);
+        for slot in slots {
+            let epoch = slot.epoch;
+            let manager = match decode(slot.value) {
+                Ok(manager) => manager,
+                Err(e) => {
+                    log_warn!(
+                        self.logger,
+                        "Could not decode channel manager of epoch {epoch}: {e}"
+                    );
+                    failures.push(format!("epoch {epoch}: {e}"));
+                    continue;
+                }
+            };
+
+            let rollback = (!failures.is_empty()).then(|| {
+                let mut newer_monitors: Vec<String> = self
+                    .monitor_epochs
+                    .lock()
+                    .expect("Failed to lock monitor epochs")
+                    .iter()
+                    .filter(|(_, monitor_epoch)| **monitor_epoch > epoch)
+                    .map(|(key, _)| key.clone())
+                    .collect();
+                newer_monitors.sort();
+                PersistenceRollback {
+                    discarded_epoch: newest_epoch.filter(|newest| *newest != epoch),
+                    restored_epoch: epoch,
+                    newer_monitors,
+                    reason: failures.join(", "),
+                }
+            });
+            if let Some(rollback) = rollback.as_ref() {
+                log_warn!(
+                    self.logger,
+                    "Newest channel manager could not be decoded ({}), rolled back to epoch {}, newer monitors: {:?}",
+                    rollback.reason,
+                    rollback.restored_epoch,
+                    rollback.newer_monitors
+                );
+            }
+
+            let after = newest_epoch.unwrap_or(epoch);
+            self.epoch
+                .store(next_epoch(after, epoch), Ordering::Relaxed);
+            return Ok(Some((manager, rollback)));
+        }
+
+        Err(MutinyError::read_err(MutinyStorageError::Other(anyhow!(
+            "No channel manager could be read for node {}: {}",
+            self.node_id,
+            failures.join(", ")
+        ))))
+    }
+
+    /// Gets all the channel monitor buffers that exist for this node,
+    /// remembering their epochs.
+    fn read_monitor_values(&self) -> Result<HashMap<String, EpochValue>, io::Error> {
         let suffix = self.node_id.as_str();
-        let channel_monitor_list: HashMap<String, Vec<u8>> = self
+        let channel_monitor_list: HashMap<String, StoredEpochValue> = self
             .storage
             .scan(MONITORS_PREFIX_KEY, Some(suffix))
             .map_err(|_| io::ErrorKind::Other)?;
+        let channel_monitor_list: HashMap<String, EpochValue> = channel_monitor_list
+            .into_iter()
+            .map(|(key, stored)| (key, stored.into()))
+            .collect();
+
+        // remembered to report the monitors that are newer than the channel manager
+        let mut monitor_epochs = self
+            .monitor_epochs
+            .lock()
+            .expect("Failed to lock monitor epochs");
+        let node_suffix = format!("_{suffix}");
+        for (key, data) in channel_monitor_list.iter() {
+            let key = key.strip_suffix(&node_suffix).unwrap_or(key);
+            monitor_epochs.insert(key.to_string(), data.epoch);
+        }
+
+        Ok(channel_monitor_list)
+    }
+
+    pub fn read_channel_monitors(
+        &self,
+        keys_manager: Arc<PhantomKeysManager<S>>,
+    ) -> Result<Vec<(BlockHash, ChannelMonitor<InMemorySigner>)>, io::Error> {
+        let channel_monitor_list = self.read_monitor_values()?;
 
         let res = channel_monitor_list
             .iter()
             .fold(Ok(Vec::new()), |current_res, (_, data)| match current_res {
                 Err(e) => Err(e),
                 Ok(mut accum) => {
-                    let mut buffer = lightning::io::Cursor::new(data);
+                    let mut buffer = lightning::io::Cursor::new(&data.value);
                     match <(BlockHash, ChannelMonitor<InMemorySigner>)>::read(
                         &mut buffer,
                         (keys_manager.as_ref(), keys_manager.as_ref()),
@@ -166,37 +357,39 @@ impl<S: MutinyStorage> MutinyNodePersister<S> {
         mut channel_monitors: Vec<(BlockHash, ChannelMonitor<InMemorySigner>)>,
         esplora: Arc<AsyncClient>,
     ) -> Result<ReadChannelManager<S>, MutinyError> {
-        match self.read_value(CHANNEL_MANAGER_KEY) {
-            Ok(kv_value) => {
-                let mut channel_monitor_mut_references = Vec::new();
-                for (_, channel_monitor) in channel_monitors.iter_mut() {
-                    channel_monitor_mut_references.push(channel_monitor);
-                }
-                let read_args = ChannelManagerReadArgs::new(
-                    keys_manager.clone(),
-                    keys_manager.clone(),
-                    keys_manager,
-                    fee_estimator,
-                    chain_monitor,
-                    mutiny_chain,
-                    router,
-                    mutiny_logger,
-                    default_user_config(),
-                    channel_monitor_mut_references,
-                );
-                let mut readable_kv_value = Cursor::new(kv_value);
-                let Ok((_, channel_manager)) = <(BlockHash, PhantomChannelManager<S>)>::read(&mut readable_kv_value, read_args) else {
-                    return Err(MutinyError::ReadError { source: MutinyStorageError::Other(anyhow!("could not read manager")) })
-                };
-                Ok(ReadChannelManager {
-                    channel_manager,
-                    is_restarting: true,
-                    channel_monitors,
+        let loaded = self.load_newest_manager(|value| {
+            let channel_monitor_mut_references = channel_monitors
+                .iter_mut()
+                .map(|(_, channel_monitor)| channel_monitor)
+                .collect();
+            let read_args = ChannelManagerReadArgs::new(
+                keys_manager.clone(),
+                keys_manager.clone(),
+                keys_manager.clone(),
+                fee_estimator.clone(),
+                chain_monitor.clone(),
+                mutiny_chain.clone(),
+                router.clone(),
+                mutiny_logger.clone(),
+                default_user_config(),
+                channel_monitor_mut_references,
+            );
+            let mut readable_kv_value = Cursor::new(value);
+            <(BlockHash, PhantomChannelManager<S>)>::read(&mut readable_kv_value, read_args)
+                .map(|(_, channel_manager)| channel_manager)
+                .map_err(|e| MutinyError::ReadError {
+                    source: MutinyStorageError::Other(anyhow!("could not read manager: {e:?}")),
                 })
-            }
-            Err(_) => {
-                // no key manager stored, start a new one
-
+        })?;
+
+        match loaded {
+            Some((channel_manager, _)) => Ok(ReadChannelManager {
+                channel_manager,
+                is_restarting: true,
+                channel_monitors,
+            }),
+            None => {
+                // no channel manager was ever stored, start a new one
                 Self::create_new_channel_manager(
                     network,
                     chain_monitor,
@@ -274,7 +467,20 @@ impl<S: MutinyStorage> MutinyNodePersister<S> {
         let key = self.get_key(payment_key(inbound, payment_hash).as_str());
         self.storage
             .set_data(key, payment_info)
-            .map_err(io::Error::other)
+            .map_err(io::Error::other)?;
+
+        // keep the search index up to date, this should not fail the payment
+        if let Ok(invoice) =
+            MutinyInvoice::from(payment_info.clone(), *payment_hash, inbound, vec![])
+        {
+            if let Err(e) = update_search_index(&self.storage, |index| {
+                index.insert(SearchDocument::lightning(&invoice))
+            }) {
+                log_warn!(self.logger, "Failed to update search index: {e}");
+            }
+        }
+
+        Ok(())
     }
 
     pub(crate) fn read_payment_info(
@@ -290,27 +496,120 @@ impl<S: MutinyStorage> MutinyNodePersister<S> {
         deserialized_value.ok().flatten()
     }
 
+    /// Marks an inbound payment as paid out of band with the given preimage. Fails if
+    /// the preimage does not match the payment hash or the payment already succeeded.
+    pub(crate) fn settle_payment_externally(
+        &self,
+        payment_hash: &PaymentHash,
+        preimage: [u8; 32],
+        note: String,
+    ) -> Result<PaymentInfo, MutinyError> {
+        let mut payment_info = self
+            .read_payment_info(payment_hash, true, &self.logger)
+            .ok_or(MutinyError::NotFound)?;
+        if sha256::Hash::hash(&preimage).into_inner() != payment_hash.0 {
+            return Err(MutinyError::PreimageMismatch);
+        }
+        if payment_info.status == HTLCStatus::Succeeded {
+            return Err(MutinyError::InvoiceAlreadySettled);
+        }
+
+        let now = utils::now().as_secs();
+        payment_info.preimage = Some(preimage);
+        payment_info.status = HTLCStatus::Succeeded;
+        payment_info.last_update = now;
+        payment_info.external_settlement = Some(ExternalSettlement {
+            note,
+            settled_at: now,
+        });
+        self.persist_payment_info(payment_hash, &payment_info, true)?;
+
+        Ok(payment_info)
+    }
+
     pub(crate) fn list_payment_info(
         &self,
         inbound: bool,
     ) -> Result<Vec<(PaymentHash, PaymentInfo)>, MutinyError> {
+        let (payments, report) = self.list_payment_info_with_report(inbound)?;
+        if !report.is_empty() {
+            log_warn!(
+                self.logger,
+                "Skipped {} corrupt payment records, run repair_storage to fix them",
+                report.count()
+            );
+        }
+        Ok(payments)
+    }
+
+    /// Lists the payment info, corrupt records are skipped and returned in the report
+    /// instead of failing the whole listing.
+    pub(crate) fn list_payment_info_with_report(
+        &self,
+        inbound: bool,
+    ) -> Result<(Vec<(PaymentHash, PaymentInfo)>, QuarantineReport), MutinyError> {
         let prefix = match inbound {
             true => PAYMENT_INBOUND_PREFIX_KEY,
             false => PAYMENT_OUTBOUND_PREFIX_KEY,
         };
         let suffix = format!("_{}", self.node_id);
-        let map: HashMap<String, PaymentInfo> = self.storage.scan(prefix, Some(&suffix))?;
+        let (map, mut report): (HashMap<String, PaymentInfo>, _) =
+            self.storage.scan_with_quarantine(prefix, Some(&suffix))?;
 
         // convert keys to PaymentHash
-        Ok(map
-            .into_iter()
-            .map(|(key, value)| {
-                let payment_hash_str = key.trim_start_matches(prefix).trim_end_matches(&suffix);
-                let hash: [u8; 32] =
-                    FromHex::from_hex(payment_hash_str).expect("key should be a sha256 hash");
-                (PaymentHash(hash), value)
-            })
-            .collect())
+        let mut payments = Vec::with_capacity(map.len());
+        for (key, value) in map {
+            match payment_hash_from_key(&key, prefix, &suffix) {
+                Some(hash) => payments.push((hash, value)),
+                None => report.corrupt_keys.push(key),
+            }
+        }
+
+        Ok((payments, report))
+    }
+
+    /// Gets the keys of all the payment records that can not be read.
+    pub(crate) fn payment_quarantine_report(&self) -> Result<QuarantineReport, MutinyError> {
+        let (_, mut report) = self.list_payment_info_with_report(true)?;
+        let (_, outbound) = self.list_payment_info_with_report(false)?;
+        report.corrupt_keys.extend(outbound.corrupt_keys);
+        Ok(report)
+    }
+
+    /// Tries to re-parse corrupt payment records leniently, rewriting them in the
+    /// current format. Records that still can not be read are moved under
+    /// [`QUARANTINE_PREFIX`] with their raw value preserved.
+    pub(crate) fn repair_payment_info(&self) -> Result<RepairReport, MutinyError> {
+        let suffix = format!("_{}", self.node_id);
+        let mut repair = RepairReport::default();
+        for key in self.payment_quarantine_report()?.corrupt_keys {
+            let raw: Value = match self.storage.get(&key)? {
+                Some(raw) => raw,
+                None => continue,
+            };
+
+            let prefix = if key.starts_with(PAYMENT_INBOUND_PREFIX_KEY) {
+                PAYMENT_INBOUND_PREFIX_KEY
+            } else {
+                PAYMENT_OUTBOUND_PREFIX_KEY
+            };
+            let repaired = payment_hash_from_key(&key, prefix, &suffix)
+                .and_then(|_| lenient_payment_info(raw.clone()));
+
+            match repaired {
+                Some(info) => {
+                    self.storage.set_data(&key, info)?;
+                    repair.repaired.push(key);
+                }
+                None => {
+                    self.storage.set(format!("{QUARANTINE_PREFIX}{key}"), raw)?;
+                    self.storage.delete(&[&key])?;
+                    repair.quarantined.push(key);
+                }
+            }
+        }
+
+        Ok(repair)
     }
 
     pub(crate) fn persist_channel_closure(
@@ -416,6 +715,27 @@ impl<S: MutinyStorage> MutinyNodePersister<S> {
         Ok(())
     }
 
+    /// Saves a sweep of closed channel funds, so it is shown as pending until it confirms
+    pub(crate) fn persist_recovery_sweep(&self, sweep: RecoverySweep) -> Result<(), MutinyError> {
+        let key = self.get_key(RECOVERY_SWEEPS_KEY);
+        let mut sweeps: Vec<RecoverySweep> = self.storage.get_data(&key)?.unwrap_or_default();
+        sweeps.push(sweep);
+        self.storage.set_data(key, sweeps)
+    }
+
+    pub(crate) fn get_recovery_sweeps(&self) -> Result<Vec<RecoverySweep>, MutinyError> {
+        let key = self.get_key(RECOVERY_SWEEPS_KEY);
+        Ok(self.storage.get_data(&key)?.unwrap_or_default())
+    }
+
+    /// Forgets the sweeps with the given txids, once they confirmed
+    pub(crate) fn remove_recovery_sweeps(&self, txids: &[Txid]) -> Result<(), MutinyError> {
+        let key = self.get_key(RECOVERY_SWEEPS_KEY);
+        let mut sweeps = self.get_recovery_sweeps()?;
+        sweeps.retain(|s| !txids.contains(&s.txid));
+        self.storage.set_data(key, sweeps)
+    }
+
     pub(crate) fn persist_channel_open_params(
         &self,
         id: u128,
@@ -439,6 +759,25 @@ impl<S: MutinyStorage> MutinyNodePersister<S> {
     }
 }
 
+/// The first epoch after `after` that writes into the other slot than the channel
+/// manager of epoch `loaded`, so the loaded manager is never overwritten.
+fn next_epoch(after: u64, loaded: u64) -> u64 {
+    let next = after + 1;
+    if manager_slot_key(next) == manager_slot_key(loaded) {
+        next + 1
+    } else {
+        next
+    }
+}
+
+fn manager_slot_key(epoch: u64) -> &'static str {
+    if epoch % 2 == 0 {
+        CHANNEL_MANAGER_KEY
+    } else {
+        CHANNEL_MANAGER_ALT_KEY
+    }
+}
+
 fn channel_open_params_key(id: u128) -> String {
     format!("{CHANNEL_OPENING_PARAMS_PREFIX}{id}")
 }
@@ -482,6 +821,31 @@ impl ChannelOpenParams {
     }
 }
 
+fn payment_hash_from_key(key: &str, prefix: &str, suffix: &str) -> Option<PaymentHash> {
+    let payment_hash_str = key.trim_start_matches(prefix).trim_end_matches(suffix);
+    let hash: [u8; 32] = FromHex::from_hex(payment_hash_str).ok()?;
+    Some(PaymentHash(hash))
+}
+
+/// Tries to read a payment info written in an older shape,
+/// filling in the fields that older versions did not write.
+fn lenient_payment_info(mut value: Value) -> Option<PaymentInfo> {
+    let obj = value.as_object_mut()?;
+
+    // older records did not have a last update time
+    obj.entry("last_update").or_insert(Value::from(0u64));
+
+    // older records could have the status in lowercase
+    if let Some(Value::String(status)) = obj.get_mut("status") {
+        let mut chars = status.chars();
+        if let Some(first) = chars.next() {
+            *status = first.to_uppercase().chain(chars).collect();
+        }
+    }
+
+    serde_json::from_value(value).ok()
+}
+
 fn payment_key(inbound: bool, payment_hash: &PaymentHash) -> String {
     if inbound {
         format!(
@@ -516,7 +880,8 @@ impl<S: MutinyStorage>
         &self,
         channel_manager: &PhantomChannelManager<S>,
     ) -> Result<(), lightning::io::Error> {
-        self.persist_local_storage(CHANNEL_MANAGER_KEY, channel_manager)
+        self.write_manager(channel_manager.encode())?;
+        Ok(())
     }
 
     fn persist_graph(&self, network_graph: &NetworkGraph) -> Result<(), lightning::io::Error> {
@@ -550,8 +915,11 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner, S: MutinyStorage> Persist<Chann
             funding_txo.txid.to_hex(),
             funding_txo.index
         );
-        match self.persist_local_storage(&key, monitor) {
-            Ok(()) => chain::ChannelMonitorUpdateStatus::Completed,
+        match self.persist_monitor(&key, monitor.encode()) {
+            Ok(()) => {
+                self.monitor_persisted();
+                chain::ChannelMonitorUpdateStatus::Completed
+            }
             Err(_) => chain::ChannelMonitorUpdateStatus::PermanentFailure,
         }
     }
@@ -568,8 +936,11 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner, S: MutinyStorage> Persist<Chann
             funding_txo.txid.to_hex(),
             funding_txo.index
         );
-        match self.persist_local_storage(&key, monitor) {
-            Ok(()) => chain::ChannelMonitorUpdateStatus::Completed,
+        match self.persist_monitor(&key, monitor.encode()) {
+            Ok(()) => {
+                self.monitor_persisted();
+                chain::ChannelMonitorUpdateStatus::Completed
+            }
             Err(_) => chain::ChannelMonitorUpdateStatus::PermanentFailure,
         }
     }
@@ -620,6 +991,7 @@ mod test {
             payee_pubkey: Some(pubkey),
             secret: None,
             last_update: utils::now().as_secs(),
+            external_settlement: None,
         };
         let result = persister.persist_payment_info(&payment_hash, &payment_info, true);
         assert!(result.is_ok());
@@ -647,6 +1019,70 @@ mod test {
         assert_eq!(list[0].1.preimage, Some(preimage));
     }
 
+    #[test]
+    fn test_settle_payment_externally() {
+        let test_name = "test_settle_payment_externally";
+        log!("{}", test_name);
+
+        let persister = get_test_persister();
+        let preimage = [1; 32];
+        let payment_hash = PaymentHash(sha256::Hash::hash(&preimage).into_inner());
+        let note = "verified by support".to_string();
+
+        // unknown invoices can not be settled
+        let result = persister.settle_payment_externally(&payment_hash, preimage, note.clone());
+        assert!(matches!(result, Err(MutinyError::NotFound)));
+
+        let payment_info = PaymentInfo {
+            preimage: None,
+            status: HTLCStatus::Failed,
+            amt_msat: MillisatAmount(Some(10_000)),
+            fee_paid_msat: None,
+            bolt11: None,
+            payee_pubkey: None,
+            secret: None,
+            last_update: 0,
+            external_settlement: None,
+        };
+        persister
+            .persist_payment_info(&payment_hash, &payment_info, true)
+            .unwrap();
+
+        // the preimage has to match
+        let result = persister.settle_payment_externally(&payment_hash, [2; 32], note.clone());
+        assert!(matches!(result, Err(MutinyError::PreimageMismatch)));
+
+        let settled = persister
+            .settle_payment_externally(&payment_hash, preimage, note.clone())
+            .unwrap();
+        assert_eq!(settled.status, HTLCStatus::Succeeded);
+        assert_eq!(settled.preimage, Some(preimage));
+        assert_eq!(settled.external_settlement.as_ref().unwrap().note, note);
+
+        let stored = persister
+            .read_payment_info(&payment_hash, true, &MutinyLogger::default())
+            .unwrap();
+        assert_eq!(stored, settled);
+
+        // can not be settled twice
+        let result = persister.settle_payment_externally(&payment_hash, preimage, note.clone());
+        assert!(matches!(result, Err(MutinyError::InvoiceAlreadySettled)));
+
+        // invoices that were paid normally can not be settled externally
+        let paid_preimage = [3; 32];
+        let paid_hash = PaymentHash(sha256::Hash::hash(&paid_preimage).into_inner());
+        let paid = PaymentInfo {
+            preimage: Some(paid_preimage),
+            status: HTLCStatus::Succeeded,
+            ..payment_info
+        };
+        persister
+            .persist_payment_info(&paid_hash, &paid, true)
+            .unwrap();
+        let result = persister.settle_payment_externally(&paid_hash, paid_preimage, note);
+        assert!(matches!(result, Err(MutinyError::InvoiceAlreadySettled)));
+    }
+
     #[test]
     fn test_persist_channel_closure() {
         let test_name = "test_persist_channel_closure";
@@ -711,4 +1147,292 @@ mod test {
         let result = persister.get_failed_spendable_outputs().unwrap();
         assert!(result.is_empty());
     }
+
+    #[test]
+    fn test_list_payment_info_with_corrupt_records() {
+        let test_name = "test_list_payment_info_with_corrupt_records";
+        log!("{}", test_name);
+
+        let persister = get_test_persister();
+        let payment_info = PaymentInfo {
+            preimage: Some([1; 32]),
+            status: HTLCStatus::Succeeded,
+            amt_msat: MillisatAmount(Some(420)),
+            fee_paid_msat: None,
+            bolt11: None,
+            payee_pubkey: None,
+            secret: None,
+            last_update: utils::now().as_secs(),
+            external_settlement: None,
+        };
+        for i in 0..3 {
+            persister
+                .persist_payment_info(&PaymentHash([i; 32]), &payment_info, true)
+                .unwrap();
+        }
+
+        // an older record without a last update and with a lowercase status
+        let old_key = persister.get_key(&payment_key(true, &PaymentHash([3; 32])));
+        let mut old_value = serde_json::to_value(&payment_info).unwrap();
+        let obj = old_value.as_object_mut().unwrap();
+        obj.remove("last_update");
+        obj.insert("status".to_string(), Value::from("succeeded"));
+        persister.storage.set(&old_key, old_value).unwrap();
+
+        // garbage that can not be repaired
+        let garbage_key = persister.get_key(&payment_key(true, &PaymentHash([4; 32])));
+        persister.storage.set(&garbage_key, "garbage").unwrap();
+
+        // a key that is not a payment hash
+        let bad_key = persister.get_key(&format!("{PAYMENT_INBOUND_PREFIX_KEY}nothex"));
+        persister.storage.set_data(&bad_key, &payment_info).unwrap();
+
+        // the listing still contains all the valid records
+        let list = persister.list_payment_info(true).unwrap();
+        assert_eq!(list.len(), 3);
+
+        let mut report = persister.payment_quarantine_report().unwrap();
+        report.corrupt_keys.sort();
+        let mut expected = vec![old_key.clone(), garbage_key.clone(), bad_key.clone()];
+        expected.sort();
+        assert_eq!(report.corrupt_keys, expected);
+
+        // repair the old record and quarantine the rest
+        let mut repair = persister.repair_payment_info().unwrap();
+        repair.quarantined.sort();
+        assert_eq!(repair.repaired, vec![old_key]);
+        let mut quarantined = vec![garbage_key.clone(), bad_key];
+        quarantined.sort();
+        assert_eq!(repair.quarantined, quarantined);
+
+        assert_eq!(persister.list_payment_info(true).unwrap().len(), 4);
+        assert!(persister.payment_quarantine_report().unwrap().is_empty());
+
+        // the raw value is preserved under the quarantine prefix
+        let raw: Option<Value> = persister
+            .storage
+            .get(format!("{QUARANTINE_PREFIX}{garbage_key}"))
+            .unwrap();
+        assert_eq!(raw, Some(Value::from("garbage")));
+    }
+
+    /// One write of the persister, a crash can fall between any two of them
+    #[derive(Clone, Copy, Debug)]
+    enum Write {
+        /// A monitor update of the channel, with its update id
+        Monitor(usize, u8),
+        /// A channel manager, with the monitor update ids it knows of
+        Manager([u8; 2]),
+    }
+
+    fn monitor_key(channel: usize) -> String {
+        format!("{MONITORS_PREFIX_KEY}{channel}")
+    }
+
+    /// Test channel managers are their bytes, empty ones do not decode
+    fn decode_test_manager(value: Vec<u8>) -> Result<Vec<u8>, MutinyError> {
+        if value.is_empty() {
+            return Err(MutinyError::read_err(MutinyStorageError::Other(anyhow!(
+                "empty manager"
+            ))));
+        }
+        Ok(value)
+    }
+
+    fn test_persister(
+        node_id: &str,
+        storage: &MemoryStorage,
+    ) -> MutinyNodePersister<MemoryStorage> {
+        MutinyNodePersister::new(
+            node_id.to_string(),
+            storage.clone(),
+            Arc::new(MutinyLogger::default()),
+        )
+    }
+
+    /// Restarts the persister on the stored state, returning the update ids of
+    /// the loaded channel manager and monitors and what was rolled back.
+    /// `None` for the manager if no channel manager was stored.
+    fn restart(
+        node_id: &str,
+        storage: &MemoryStorage,
+    ) -> (
+        MutinyNodePersister<MemoryStorage>,
+        Option<Vec<u8>>,
+        [u8; 2],
+        Option<PersistenceRollback>,
+    ) {
+        let persister = test_persister(node_id, storage);
+        let monitors = persister.read_monitor_values().unwrap();
+        let monitors = [0, 1].map(|channel| {
+            let key = format!("{}_{node_id}", monitor_key(channel));
+            monitors.get(&key).map_or(0, |m| m.value[0])
+        });
+        let loaded = persister.load_newest_manager(decode_test_manager).unwrap();
+        let (manager, rollback) = match loaded {
+            Some((manager, rollback)) => (Some(manager), rollback),
+            None => (None, None),
+        };
+        (persister, manager, monitors, rollback)
+    }
+
+    #[test]
+    fn test_persistence_converges_after_crash() {
+        let test_name = "test_persistence_converges_after_crash";
+        log!("{}", test_name);
+
+        let history = [
+            Write::Monitor(0, 1),
+            Write::Manager([1, 0]),
+            Write::Monitor(0, 2),
+            Write::Monitor(1, 1),
+            Write::Manager([2, 1]),
+            Write::Monitor(0, 3),
+            Write::Manager([3, 1]),
+            Write::Monitor(1, 2),
+        ];
+
+        // writes complete in order, a crash can fall between any two of them
+        for crash in 0..=history.len() {
+            let node_id = Uuid::new_v4().to_string();
+            let storage = MemoryStorage::default();
+            let persister = test_persister(&node_id, &storage);
+            let mut last_manager = None;
+            for write in history[..crash].iter() {
+                match write {
+                    Write::Monitor(channel, id) => persister
+                        .persist_monitor(&monitor_key(*channel), vec![*id])
+                        .unwrap(),
+                    Write::Manager(ids) => {
+                        persister.write_manager(ids.to_vec()).unwrap();
+                        last_manager = Some(ids.to_vec());
+                    }
+                }
+            }
+
+            // the newest manager is loaded and no monitor is older
+            let (restarted, manager, monitors, rollback) = restart(&node_id, &storage);
+            assert_eq!(manager, last_manager, "crash after {crash} writes");
+            assert_eq!(rollback, None, "crash after {crash} writes");
+            let known = manager.clone().unwrap_or(vec![0, 0]);
+            for (channel, (monitor, known)) in monitors.iter().zip(known).enumerate() {
+                assert!(
+                    *monitor >= known,
+                    "crash after {crash} writes: monitor {channel} is at {monitor} but the manager at {known}"
+                );
+            }
+
+            // the next channel manager write goes on top of the loaded pair
+            restarted.write_manager(monitors.to_vec()).unwrap();
+            let (_, manager, _, rollback) = restart(&node_id, &storage);
+            assert_eq!(manager, Some(monitors.to_vec()));
+            assert_eq!(rollback, None);
+        }
+    }
+
+    #[test]
+    fn test_channel_manager_slots_alternate() {
+        let test_name = "test_channel_manager_slots_alternate";
+        log!("{}", test_name);
+
+        let node_id = Uuid::new_v4().to_string();
+        let storage = MemoryStorage::default();
+        let persister = test_persister(&node_id, &storage);
+        assert_eq!(persister.write_manager(vec![1, 0]).unwrap(), 1);
+        persister.persist_monitor(&monitor_key(0), vec![2]).unwrap();
+        assert_eq!(persister.write_manager(vec![2, 0]).unwrap(), 2);
+
+        let (restarted, manager, _, rollback) = restart(&node_id, &storage);
+        assert_eq!(manager, Some(vec![2, 0]));
+        assert_eq!(rollback, None);
+        restarted.persist_monitor(&monitor_key(1), vec![1]).unwrap();
+        assert_eq!(restarted.write_manager(vec![2, 1]).unwrap(), 3);
+
+        let (restarted, manager, _, rollback) = restart(&node_id, &storage);
+        assert_eq!(manager, Some(vec![2, 1]));
+        assert_eq!(rollback, None);
+
+        // the next write goes into the other slot, the loaded manager is kept
+        assert_eq!(restarted.write_manager(vec![3, 1]).unwrap(), 4);
+        let (_, manager, _, _) = restart(&node_id, &storage);
+        assert_eq!(manager, Some(vec![3, 1]));
+    }
+
+    #[test]
+    fn test_legacy_channel_manager_is_loaded() {
+        let test_name = "test_legacy_channel_manager_is_loaded";
+        log!("{}", test_name);
+
+        let node_id = Uuid::new_v4().to_string();
+        let storage = MemoryStorage::default();
+        let persister = test_persister(&node_id, &storage);
+        let legacy_key = persister.get_key(CHANNEL_MANAGER_KEY);
+        persister.storage.set_data(&legacy_key, vec![7u8]).unwrap();
+        let (manager, rollback) = persister
+            .load_newest_manager(decode_test_manager)
+            .unwrap()
+            .unwrap();
+        assert_eq!(manager, vec![7]);
+        assert_eq!(rollback, None);
+
+        // crash right after the first tagged write over the legacy manager
+        let epoch = persister.write_manager(vec![8]).unwrap();
+        assert_eq!(epoch, 1);
+        let (restarted, manager, _, rollback) = restart(&node_id, &storage);
+        assert_eq!(manager, Some(vec![8]));
+        assert_eq!(rollback, None);
+
+        // the legacy manager is only replaced by the write after that
+        let epoch = restarted.write_manager(vec![9]).unwrap();
+        assert_eq!(epoch, 2);
+        let stored: EpochValue = storage.get_data(&legacy_key).unwrap().unwrap();
+        assert_eq!(stored.epoch, 2);
+        let (_, manager, _, _) = restart(&node_id, &storage);
+        assert_eq!(manager, Some(vec![9]));
+
+        // starting from an empty state forgets the stored managers
+        persister.reset_channel_manager().unwrap();
+        let (_, manager, _, _) = restart(&node_id, &storage);
+        assert_eq!(manager, None);
+    }
+
+    #[test]
+    fn test_channel_manager_rolls_back_only_when_undecodable() {
+        let test_name = "test_channel_manager_rolls_back_only_when_undecodable";
+        log!("{}", test_name);
+
+        let node_id = Uuid::new_v4().to_string();
+        let storage = MemoryStorage::default();
+        let persister = test_persister(&node_id, &storage);
+        persister.write_manager(vec![1, 0]).unwrap();
+        persister.persist_monitor(&monitor_key(1), vec![1]).unwrap();
+        // a manager that was written but can not be decoded
+        persister.write_manager(vec![]).unwrap();
+
+        let (restarted, manager, _, rollback) = restart(&node_id, &storage);
+        assert_eq!(manager, Some(vec![1, 0]));
+        let rollback = rollback.unwrap();
+        assert_eq!(rollback.discarded_epoch, Some(2));
+        assert_eq!(rollback.restored_epoch, 1);
+        assert_eq!(rollback.newer_monitors, vec![monitor_key(1)]);
+        assert!(rollback.reason.contains("epoch 2"));
+
+        // the next write replaces the undecodable manager, not the restored one
+        assert_eq!(restarted.write_manager(vec![1, 1]).unwrap(), 4);
+        let (_, manager, _, rollback) = restart(&node_id, &storage);
+        assert_eq!(manager, Some(vec![1, 1]));
+        assert_eq!(rollback, None);
+
+        // if no stored manager can be read it is an error, never a new manager
+        let node_id = Uuid::new_v4().to_string();
+        let storage = MemoryStorage::default();
+        let persister = test_persister(&node_id, &storage);
+        persister.write_manager(vec![]).unwrap();
+        assert!(persister.load_newest_manager(decode_test_manager).is_err());
+
+        let persister = test_persister(&Uuid::new_v4().to_string(), &storage);
+        let key = persister.get_key(CHANNEL_MANAGER_ALT_KEY);
+        storage.set_data(&key, "not a channel manager").unwrap();
+        assert!(persister.load_newest_manager(decode_test_manager).is_err());
+    }
 }
@@ -11,40 +11,66 @@
 mod background;
 
 mod auth;
+pub mod capabilities;
 mod chain;
+pub mod chunks;
+pub mod congestion;
+pub mod consolidation;
 pub mod encrypt;
 pub mod error;
 pub mod esplora;
 mod event;
 mod fees;
+pub mod freshness;
 mod gossip;
 mod keymanager;
 pub mod labels;
 mod ldkstorage;
+pub mod lifecycle;
 mod lnurlauth;
 pub mod logging;
 mod lspclient;
+pub mod messages;
 mod networking;
 mod node;
 pub mod nodemanager;
 pub mod nostr;
 mod onchain;
+pub mod payment_privacy;
 mod peermanager;
+pub mod recovery;
+pub mod recovery_kit;
 pub mod redshift;
+pub mod safe_int;
 pub mod scb;
+pub mod search;
+mod snapshot;
 pub mod storage;
 mod subscription;
+pub mod trace;
 
 #[cfg(any(test, feature = "test-utils"))]
 pub mod test_utils;
 mod utils;
+pub mod webhooks;
 
+pub use crate::event::HTLCStatus;
 pub use crate::gossip::{GOSSIP_SYNC_TIME_KEY, NETWORK_GRAPH_KEY, PROB_SCORER_KEY};
 pub use crate::keymanager::generate_seed;
-pub use crate::ldkstorage::{CHANNEL_MANAGER_KEY, MONITORS_PREFIX_KEY};
-
+pub use crate::ldkstorage::{CHANNEL_MANAGER_ALT_KEY, CHANNEL_MANAGER_KEY, MONITORS_PREFIX_KEY};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::networking::dns::{DnsConfig, DohConfig, DohFallback, IpPreference};
+
+use crate::capabilities::Capability;
+use crate::congestion::CongestionPolicy;
+use crate::consolidation::ConsolidationPolicy;
+use crate::freshness::StrictModeConfig;
+use crate::lifecycle::Lifecycle;
 use crate::nostr::NostrManager;
+use crate::payment_privacy::PaymentPrivacyConfig;
+use crate::scb::{ScbPrivacyConfig, DEFAULT_AUTO_BACKUP_INTERVAL_SECS};
 use crate::storage::MutinyStorage;
+use crate::trace::TraceVerbosity;
 use crate::{error::MutinyError, nostr::ReservedProfile};
 use crate::{nodemanager::NodeManager, nostr::ProfileType};
 use ::nostr::Kind;
@@ -61,6 +87,12 @@ use nostr_sdk::{Client, RelayPoolNotification};
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
+/// The networks a wallet can be created on.
+pub(crate) const NETWORKS_CAPABILITY: Capability = Capability::list(
+    "supported_networks",
+    &["bitcoin", "testnet", "signet", "regtest"],
+);
+
 #[derive(Clone)]
 pub struct MutinyWalletConfig {
     mnemonic: Option<Mnemonic>,
@@ -73,6 +105,19 @@ pub struct MutinyWalletConfig {
     auth_url: Option<String>,
     subscription_url: Option<String>,
     do_not_connect_peers: bool,
+    strict_mode: StrictModeConfig,
+    congestion_policy: CongestionPolicy,
+    consolidation_policy: ConsolidationPolicy,
+    payment_privacy: PaymentPrivacyConfig,
+    trace_verbosity: TraceVerbosity,
+    auto_backup_interval_secs: u64,
+    scb_privacy: ScbPrivacyConfig,
+    scb_account: Option<u32>,
+    scb_remote_url: Option<String>,
+    scb_lsp_backup: bool,
+    lifecycle: Lifecycle,
+    #[cfg(not(target_arch = "wasm32"))]
+    dns: DnsConfig,
 }
 
 impl MutinyWalletConfig {
@@ -98,6 +143,19 @@ impl MutinyWalletConfig {
             auth_url,
             subscription_url,
             do_not_connect_peers: false,
+            strict_mode: StrictModeConfig::default(),
+            congestion_policy: CongestionPolicy::default(),
+            consolidation_policy: ConsolidationPolicy::default(),
+            payment_privacy: PaymentPrivacyConfig::default(),
+            trace_verbosity: TraceVerbosity::default(),
+            auto_backup_interval_secs: DEFAULT_AUTO_BACKUP_INTERVAL_SECS,
+            scb_privacy: ScbPrivacyConfig::default(),
+            scb_account: None,
+            scb_remote_url: None,
+            scb_lsp_backup: false,
+            lifecycle: Lifecycle::new(),
+            #[cfg(not(target_arch = "wasm32"))]
+            dns: DnsConfig::default(),
         }
     }
 
@@ -105,6 +163,82 @@ impl MutinyWalletConfig {
         self.do_not_connect_peers = true;
         self
     }
+
+    /// Require a recent chain sync before performing risky channel operations
+    pub fn with_strict_mode(mut self, strict_mode: StrictModeConfig) -> Self {
+        self.strict_mode = strict_mode;
+        self
+    }
+
+    /// Defer new channels while on-chain fees are high
+    pub fn with_congestion_policy(mut self, congestion_policy: CongestionPolicy) -> Self {
+        self.congestion_policy = congestion_policy;
+        self
+    }
+
+    /// Consolidate small utxos while on-chain fees are low
+    pub fn with_consolidation_policy(mut self, consolidation_policy: ConsolidationPolicy) -> Self {
+        self.consolidation_policy = consolidation_policy;
+        self
+    }
+
+    /// Randomize outgoing payments so they are harder to fingerprint for routing nodes
+    pub fn with_payment_privacy(mut self, payment_privacy: PaymentPrivacyConfig) -> Self {
+        self.payment_privacy = payment_privacy;
+        self
+    }
+
+    /// Sets how much is recorded in the operation traces
+    pub fn with_trace_verbosity(mut self, trace_verbosity: TraceVerbosity) -> Self {
+        self.trace_verbosity = trace_verbosity;
+        self
+    }
+
+    /// Sets the minimum time in seconds between two automatic static channel backups
+    pub fn with_auto_backup_interval(mut self, auto_backup_interval_secs: u64) -> Self {
+        self.auto_backup_interval_secs = auto_backup_interval_secs;
+        self
+    }
+
+    /// Pad and delay the static channel backups sent to peers and relays
+    pub fn with_scb_privacy(mut self, scb_privacy: ScbPrivacyConfig) -> Self {
+        self.scb_privacy = scb_privacy;
+        self
+    }
+
+    /// Encrypt static channel backups with the seed key of the given account, so
+    /// wallets sharing a seed do not share a backup key. Accounts go up to 2^31 - 1.
+    pub fn with_scb_account(mut self, scb_account: u32) -> Self {
+        self.scb_account = Some(scb_account);
+        self
+    }
+
+    /// Also send the automatic static channel backups to the given HTTPS endpoint
+    pub fn with_scb_remote_backup(mut self, scb_remote_url: String) -> Self {
+        self.scb_remote_url = Some(scb_remote_url);
+        self
+    }
+
+    /// Store the static channel backup of new nodes with the LSP they are set up
+    /// with. Otherwise backups are only exchanged with peers opted in one by one.
+    pub fn with_scb_lsp_backup(mut self) -> Self {
+        self.scb_lsp_backup = true;
+        self
+    }
+
+    /// Shares the lifecycle of the wallet with the caller, so its startup
+    /// can be followed before the wallet is constructed
+    pub fn with_lifecycle(mut self, lifecycle: Lifecycle) -> Self {
+        self.lifecycle = lifecycle;
+        self
+    }
+
+    /// Sets how host names of peers and the LSP are resolved, such as over DoH
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_dns(mut self, dns: DnsConfig) -> Self {
+        self.dns = dns;
+        self
+    }
 }
 
 #[derive(Clone)]
@@ -126,14 +260,16 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         let node_manager = Arc::new(NodeManager::new(config.clone(), storage.clone()).await?);
 
         // if we don't have any nodes, create one
-        let first_node = {
-            match node_manager.list_nodes().await?.pop() {
-                Some(node) => node,
-                None => node_manager.new_node().await?.pubkey,
-            }
+        // unless lightning failed to start, then there is no node to run nwc with
+        let first_node = match node_manager.list_nodes().await?.pop() {
+            Some(node) => Some(node),
+            None if node_manager.check_lightning().is_err() => None,
+            None => Some(node_manager.new_node().await?.pubkey),
         };
 
         NodeManager::start_sync(node_manager.clone());
+        NodeManager::start_auto_backup(node_manager.clone());
+        NodeManager::start_webhook_delivery(node_manager.clone());
 
         // create nostr manager
         let seed = node_manager.show_seed().to_seed("");
@@ -148,7 +284,9 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         };
 
         // start the nostr wallet connect background process
-        mw.start_nostr_wallet_connect(first_node).await;
+        if let Some(first_node) = first_node {
+            mw.start_nostr_wallet_connect(first_node).await;
+        }
 
         Ok(mw)
     }
@@ -160,6 +298,8 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         self.node_manager =
             Arc::new(NodeManager::new(self.config.clone(), self.storage.clone()).await?);
         NodeManager::start_sync(self.node_manager.clone());
+        NodeManager::start_auto_backup(self.node_manager.clone());
+        NodeManager::start_webhook_delivery(self.node_manager.clone());
         NodeManager::start_redshifts(self.node_manager.clone());
         Ok(())
     }
@@ -0,0 +1,248 @@
+use crate::capabilities::Capability;
+use crate::error::MutinyError;
+use crate::utils;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+pub(crate) const LIFECYCLE_CAPABILITY: Capability = Capability::flag("lifecycle_state");
+
+/// A part of the wallet that can fail to start without taking the rest down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum WalletComponent {
+    /// The lightning nodes
+    Lightning,
+}
+
+impl Display for WalletComponent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WalletComponent::Lightning => write!(f, "Lightning"),
+        }
+    }
+}
+
+/// Why a component failed to start.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ComponentFailure {
+    pub component: WalletComponent,
+    /// The id of the node that failed to start, for lightning
+    pub node_id: Option<String>,
+    pub error: String,
+}
+
+/// How far along the startup of the wallet is.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReadinessProgress {
+    pub completed_steps: usize,
+    pub total_steps: usize,
+}
+
+/// Where the wallet is in its lifecycle.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LifecycleState {
+    /// The wallet is starting up
+    Initializing(ReadinessProgress),
+    /// Everything started
+    Ready,
+    /// The wallet is running, but some components failed to start
+    Degraded(Vec<ComponentFailure>),
+    /// The wallet is shutting down
+    Stopping,
+    /// The wallet was shut down and has to be started again
+    Stopped,
+}
+
+/// The shared lifecycle state of the wallet, moved along by startup and stop.
+///
+/// It is created before the wallet and passed in with
+/// [`crate::MutinyWalletConfig::with_lifecycle`], so startup can be followed
+/// before the wallet is constructed. Restarts reuse it.
+#[derive(Clone)]
+pub struct Lifecycle {
+    state: Arc<utils::Mutex<LifecycleState>>,
+}
+
+impl Lifecycle {
+    /// Starts out initializing, the number of steps is known once startup begins
+    pub fn new() -> Self {
+        let initializing = LifecycleState::Initializing(ReadinessProgress::default());
+        Self {
+            state: Arc::new(utils::Mutex::new(initializing)),
+        }
+    }
+
+    pub fn state(&self) -> LifecycleState {
+        self.state.lock().expect("Failed to lock lifecycle").clone()
+    }
+
+    fn set(&self, state: LifecycleState) {
+        *self.state.lock().expect("Failed to lock lifecycle") = state;
+    }
+
+    /// Starts over initializing with the given number of startup steps
+    pub(crate) fn initializing(&self, total_steps: usize) {
+        self.set(LifecycleState::Initializing(ReadinessProgress {
+            completed_steps: 0,
+            total_steps,
+        }))
+    }
+
+    /// Marks a startup step as done, only while initializing
+    pub(crate) fn step_completed(&self) {
+        let mut state = self.state.lock().expect("Failed to lock lifecycle");
+        if let LifecycleState::Initializing(progress) = &mut *state {
+            progress.completed_steps = (progress.completed_steps + 1).min(progress.total_steps);
+        }
+    }
+
+    /// Finishes startup, degraded if any of the components failed
+    pub(crate) fn started(&self, failed: Vec<ComponentFailure>) {
+        if failed.is_empty() {
+            self.set(LifecycleState::Ready)
+        } else {
+            self.set(LifecycleState::Degraded(failed))
+        }
+    }
+
+    pub(crate) fn stopping(&self) {
+        self.set(LifecycleState::Stopping)
+    }
+
+    pub(crate) fn stopped(&self) {
+        self.set(LifecycleState::Stopped)
+    }
+
+    /// Checks that the wallet is running, degraded components are fine.
+    pub fn check_running(&self) -> Result<(), MutinyError> {
+        match self.state() {
+            LifecycleState::Initializing(progress) => Err(MutinyError::WalletInitializing {
+                completed_steps: progress.completed_steps,
+                total_steps: progress.total_steps,
+            }),
+            LifecycleState::Ready | LifecycleState::Degraded(_) => Ok(()),
+            LifecycleState::Stopping => Err(MutinyError::WalletStopping),
+            LifecycleState::Stopped => Err(MutinyError::WalletStopped),
+        }
+    }
+
+    /// Checks that the wallet is running and the given component started.
+    pub fn check_component(&self, component: WalletComponent) -> Result<(), MutinyError> {
+        self.check_running()?;
+        match self.state() {
+            LifecycleState::Degraded(failed) if failed.iter().any(|f| f.component == component) => {
+                Err(MutinyError::ComponentUnavailable(component))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Default for Lifecycle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_lifecycle_transitions() {
+        let test_name = "test_lifecycle_transitions";
+        log!("{}", test_name);
+
+        let lifecycle = Lifecycle::new();
+        assert!(matches!(
+            lifecycle.check_running(),
+            Err(MutinyError::WalletInitializing {
+                completed_steps: 0,
+                total_steps: 0
+            })
+        ));
+
+        lifecycle.initializing(3);
+        lifecycle.step_completed();
+        assert!(matches!(
+            lifecycle.check_running(),
+            Err(MutinyError::WalletInitializing {
+                completed_steps: 1,
+                total_steps: 3
+            })
+        ));
+        assert!(matches!(
+            lifecycle.check_component(WalletComponent::Lightning),
+            Err(MutinyError::WalletInitializing { .. })
+        ));
+
+        lifecycle.started(vec![]);
+        assert_eq!(lifecycle.state(), LifecycleState::Ready);
+        assert!(lifecycle.check_running().is_ok());
+        assert!(lifecycle
+            .check_component(WalletComponent::Lightning)
+            .is_ok());
+
+        // progress only moves while initializing
+        lifecycle.step_completed();
+        assert_eq!(lifecycle.state(), LifecycleState::Ready);
+
+        lifecycle.stopping();
+        assert!(matches!(
+            lifecycle.check_running(),
+            Err(MutinyError::WalletStopping)
+        ));
+        lifecycle.stopped();
+        assert!(matches!(
+            lifecycle.check_running(),
+            Err(MutinyError::WalletStopped)
+        ));
+        assert!(matches!(
+            lifecycle.check_component(WalletComponent::Lightning),
+            Err(MutinyError::WalletStopped)
+        ));
+
+        // a restart starts over, visible to every clone
+        let handle = lifecycle.clone();
+        lifecycle.initializing(2);
+        assert!(matches!(
+            handle.check_running(),
+            Err(MutinyError::WalletInitializing {
+                completed_steps: 0,
+                total_steps: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn test_lifecycle_degraded() {
+        let test_name = "test_lifecycle_degraded";
+        log!("{}", test_name);
+
+        let lifecycle = Lifecycle::new();
+        lifecycle.initializing(2);
+        lifecycle.step_completed();
+        lifecycle.step_completed();
+        let failure = ComponentFailure {
+            component: WalletComponent::Lightning,
+            node_id: Some("node".to_string()),
+            error: "Failed to read data from storage.".to_string(),
+        };
+        lifecycle.started(vec![failure.clone()]);
+        assert_eq!(lifecycle.state(), LifecycleState::Degraded(vec![failure]));
+
+        // on-chain still works while lightning errors specifically
+        assert!(lifecycle.check_running().is_ok());
+        assert!(matches!(
+            lifecycle.check_component(WalletComponent::Lightning),
+            Err(MutinyError::ComponentUnavailable(
+                WalletComponent::Lightning
+            ))
+        ));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::capabilities::Capability;
 use crate::storage::MutinyStorage;
 use crate::{error::MutinyError, logging::MutinyLogger};
 use anyhow::anyhow;
@@ -14,6 +15,8 @@ use std::str::FromStr;
 use std::sync::{Arc, RwLock};
 use url::Url;
 
+pub(crate) const LNURL_AUTH_CAPABILITY: Capability = Capability::flag("lnurl_auth");
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AuthProfile {
     pub index: u32,
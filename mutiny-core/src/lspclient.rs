@@ -74,8 +74,7 @@ const PROPOSAL_PATH: &str = "/api/v1/proposal";
 const FEE_PATH: &str = "/api/v1/fee";
 
 impl LspClient {
-    pub async fn new(url: &str) -> Result<Self, MutinyError> {
-        let http_client = Client::new();
+    pub async fn new(url: &str, http_client: Client) -> Result<Self, MutinyError> {
         let get_info_response: GetInfoResponse = http_client
             .get(format!("{}{}", url, GET_INFO_PATH))
             .send()
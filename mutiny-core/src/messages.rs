@@ -0,0 +1,575 @@
+//! Message templates for the errors and events shown to the user.
+//!
+//! Every [`MutinyError`](crate::error::MutinyError) and
+//! [`WebhookEventKind`](crate::webhooks::WebhookEventKind) maps to a [`Message`]: the
+//! id of a template in [`CATALOG`] and the typed parameters to fill it with. Frontends
+//! translate from the id and format the parameters themselves instead of matching the
+//! English text, which changes between releases. The English text stays in the
+//! catalog and is rendered with [`render_default`], mostly for logs.
+//!
+//! Template ids are stable, a template that changes its meaning or its parameters
+//! gets a new id. Amounts follow [`safe_int`](crate::safe_int), too large ones are
+//! strings.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// The type of a template parameter.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ParamKind {
+    /// An amount in sats, a number or a string of one if it is too large for JS
+    Amount,
+    /// A count, size or position, written the same as an amount
+    Count,
+    /// Text that is not translated, like a network or the reason of a channel closure
+    Text,
+}
+
+impl ParamKind {
+    fn matches(&self, value: &Value) -> bool {
+        match self {
+            ParamKind::Amount | ParamKind::Count => {
+                value.is_u64() || value.as_str().map_or(false, |s| s.parse::<u64>().is_ok())
+            }
+            ParamKind::Text => value.is_string(),
+        }
+    }
+}
+
+/// A message in the catalog, with its English text. Parameters are written as
+/// `{name}` in the text.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MessageTemplate {
+    pub id: &'static str,
+    pub default: &'static str,
+    pub params: &'static [(&'static str, ParamKind)],
+}
+
+/// A message to show the user, the template id and its parameters.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Message {
+    pub id: String,
+    pub params: BTreeMap<String, Value>,
+}
+
+impl Message {
+    pub(crate) fn new(id: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            params: BTreeMap::new(),
+        }
+    }
+
+    pub(crate) fn with(mut self, name: &str, value: impl Into<Value>) -> Self {
+        self.params.insert(name.to_string(), value.into());
+        self
+    }
+
+    /// The English text of the message.
+    pub fn render_default(&self) -> String {
+        render_default(&self.id, &self.params).unwrap_or_else(|| self.id.clone())
+    }
+
+    /// Returns true if the message is in the catalog and has exactly the
+    /// parameters of its template, each of the right kind.
+    pub fn matches_template(&self) -> bool {
+        let Some(template) = template(&self.id) else {
+            return false;
+        };
+        self.params.len() == template.params.len()
+            && template.params.iter().all(|(name, kind)| {
+                self.params
+                    .get(*name)
+                    .map_or(false, |value| kind.matches(value))
+            })
+    }
+}
+
+/// Returns the template with the given id.
+pub fn template(id: &str) -> Option<&'static MessageTemplate> {
+    CATALOG.iter().find(|t| t.id == id)
+}
+
+/// Renders the English text of a template, `None` if the id is not in the catalog.
+/// Parameters that are not given are left as `{name}`.
+pub fn render_default(template_id: &str, params: &BTreeMap<String, Value>) -> Option<String> {
+    let template = template(template_id)?;
+    let mut text = template.default.to_string();
+    for (name, _) in template.params {
+        let Some(value) = params.get(*name) else {
+            continue;
+        };
+        let value = match value {
+            Value::String(s) => s.clone(),
+            v => v.to_string(),
+        };
+        text = text.replace(&format!("{{{name}}}"), &value);
+    }
+    Some(text)
+}
+
+const fn msg(id: &'static str, default: &'static str) -> MessageTemplate {
+    MessageTemplate {
+        id,
+        default,
+        params: &[],
+    }
+}
+
+const fn msg_with(
+    id: &'static str,
+    default: &'static str,
+    params: &'static [(&'static str, ParamKind)],
+) -> MessageTemplate {
+    MessageTemplate {
+        id,
+        default,
+        params,
+    }
+}
+
+/// Every message template, add new errors and events here.
+pub const CATALOG: &[MessageTemplate] = &[
+    msg("error.already_running", "Mutiny is already running."),
+    msg("error.not_running", "Mutiny is not running."),
+    msg("error.not_found", "Resource Not found."),
+    msg(
+        "error.funding_tx_creation_failed",
+        "Funding transaction could not be created.",
+    ),
+    msg("error.connection_failed", "Network connection closed."),
+    msg(
+        "error.dns_resolution_failed",
+        "Failed to resolve the host name.",
+    ),
+    msg_with(
+        "error.incorrect_network",
+        "The invoice or address is on {network}, a different network.",
+        &[("network", ParamKind::Text)],
+    ),
+    msg_with(
+        "error.network_mismatch",
+        "The backup was created on {found} but this wallet is on {expected}.",
+        &[("expected", ParamKind::Text), ("found", ParamKind::Text)],
+    ),
+    msg(
+        "error.non_unique_payment_hash",
+        "An invoice must not get payed twice.",
+    ),
+    msg("error.payment_timeout", "Payment timed out."),
+    msg("error.invoice_invalid", "The given invoice is invalid."),
+    msg(
+        "error.preimage_mismatch",
+        "The preimage does not match the payment hash.",
+    ),
+    msg(
+        "error.invoice_already_settled",
+        "The invoice has already been settled.",
+    ),
+    msg("error.invoice_creation_failed", "Failed to create invoice."),
+    msg(
+        "error.reserve_amount",
+        "Channel reserve amount is too high.",
+    ),
+    msg_with(
+        "error.insufficient_balance",
+        "Paying {needed_sats} sats needs more than the {available_sats} sats available.",
+        &[
+            ("needed_sats", ParamKind::Amount),
+            ("available_sats", ParamKind::Amount),
+        ],
+    ),
+    msg("error.lnurl_failure", "Failed to call on the given LNURL."),
+    msg("error.lsp_generic", "Failed to make a request to the LSP."),
+    msg(
+        "error.lsp_funding",
+        "Failed to request channel from LSP due to funding error.",
+    ),
+    msg(
+        "error.lsp_connection",
+        "Failed to have a connection to the LSP node.",
+    ),
+    msg(
+        "error.subscription_client_not_configured",
+        "Subscription Client Not Configured",
+    ),
+    msg("error.invalid_arguments", "Invalid Arguments were given"),
+    msg("error.routing_failed", "Failed to find route."),
+    msg(
+        "error.destination_unreachable",
+        "Destination unreachable: no public channels and hinted peers not routable.",
+    ),
+    msg(
+        "error.peer_info_parse_failed",
+        "Failed to parse the given peer information.",
+    ),
+    msg("error.channel_creation_failed", "Failed to create channel."),
+    msg("error.channel_closing_failed", "Failed to close channel."),
+    msg("error.persistence_failed", "Failed to persist data."),
+    msg("error.read_failed", "Failed to read data from storage."),
+    msg("error.ln_decode", "Failed to decode lightning data."),
+    msg("error.seed_generation_failed", "Failed to generate seed"),
+    msg("error.invalid_mnemonic", "Invalid mnemonic"),
+    msg(
+        "error.passphrase_required",
+        "This backup requires a passphrase to decrypt.",
+    ),
+    msg("error.incorrect_passphrase", "Incorrect passphrase"),
+    msg(
+        "error.backup_decryption_failed",
+        "Failed to decrypt the backup.",
+    ),
+    msg(
+        "error.wallet_operation_failed",
+        "Failed to conduct wallet operation.",
+    ),
+    msg(
+        "error.wallet_signing_failed",
+        "Failed to sign given transaction.",
+    ),
+    msg(
+        "error.chain_access_failed",
+        "Failed to conduct chain access operation.",
+    ),
+    msg("error.wallet_sync", "Failed to to sync on-chain wallet."),
+    msg(
+        "error.chain_sync_stale",
+        "The chain has not been synced recently, try again after the next sync.",
+    ),
+    msg(
+        "error.channel_operation_deferred",
+        "On-chain fees are too high right now, try again once they come down.",
+    ),
+    msg_with(
+        "error.peer_message_queue_full",
+        "{max} messages are already queued for this peer, try again once it reconnects.",
+        &[("max", ParamKind::Count)],
+    ),
+    msg_with(
+        "error.peer_backup_too_large",
+        "The backup is {size} bytes but at most {max} bytes can be stored with a peer.",
+        &[("size", ParamKind::Count), ("max", ParamKind::Count)],
+    ),
+    msg(
+        "error.peer_backup_not_found",
+        "No backup was found with the given peer.",
+    ),
+    msg(
+        "error.peer_backup_not_permitted",
+        "Backups are not exchanged with this peer.",
+    ),
+    msg("error.nostr_backup_failed", "No relay accepted the backup."),
+    msg(
+        "error.nostr_backup_not_found",
+        "No backup was found on the given relays.",
+    ),
+    msg(
+        "error.remote_backup_failed",
+        "The remote backup endpoint failed.",
+    ),
+    msg(
+        "error.remote_backup_not_found",
+        "No backup was found on the remote endpoint.",
+    ),
+    msg(
+        "error.remote_backup_stale",
+        "The remote endpoint has a newer backup.",
+    ),
+    msg(
+        "error.webhook_url_rejected",
+        "Webhooks can only be sent to public HTTPS endpoints.",
+    ),
+    msg("error.webhook_failed", "Failed to deliver the webhook."),
+    msg(
+        "error.scb_diff_conflict",
+        "The backup diff does not apply to this backup.",
+    ),
+    msg("error.scb_parse", "The backup could not be parsed."),
+    msg_with(
+        "error.scb_parse_at",
+        "The backup could not be parsed, check the character at position {position}.",
+        &[("position", ParamKind::Count)],
+    ),
+    msg_with(
+        "error.wallet_initializing",
+        "The wallet is still starting up ({completed_steps}/{total_steps} steps done).",
+        &[
+            ("completed_steps", ParamKind::Count),
+            ("total_steps", ParamKind::Count),
+        ],
+    ),
+    msg("error.wallet_stopping", "The wallet is shutting down."),
+    msg(
+        "error.wallet_stopped",
+        "The wallet has been stopped, start it again first.",
+    ),
+    msg_with(
+        "error.component_unavailable",
+        "{component} is unavailable because it failed to start.",
+        &[("component", ParamKind::Text)],
+    ),
+    msg(
+        "error.rapid_gossip_sync",
+        "Failed to execute a rapid gossip sync function",
+    ),
+    msg("error.dlc_manager", "Failed to execute a dlc function"),
+    msg("error.pubkey_invalid", "The given node pubkey is invalid."),
+    msg(
+        "error.incorrect_lnurl_function",
+        "Called incorrect lnurl function.",
+    ),
+    msg("error.bad_amount", "Satoshi amount is invalid"),
+    msg("error.bitcoin_price", "Failed to get the bitcoin price."),
+    msg("error.unknown", "An unknown error occurred."),
+    msg_with(
+        "event.payment_received",
+        "Received {amount_sats} sats.",
+        &[("amount_sats", ParamKind::Amount)],
+    ),
+    msg("event.payment_sent", "Payment sent."),
+    msg_with(
+        "event.payment_sent_fee",
+        "Payment sent for a fee of {fee_sats} sats.",
+        &[("fee_sats", ParamKind::Amount)],
+    ),
+    msg_with(
+        "event.channel_closed",
+        "A channel was closed ({reason}).",
+        &[("reason", ParamKind::Text)],
+    ),
+    msg_with(
+        "event.backup_stale",
+        "The backup of generation {generation} is out of date.",
+        &[("generation", ParamKind::Count)],
+    ),
+    msg("event.ping", "Test event from Mutiny."),
+];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::{MutinyError, MutinyStorageError};
+    use crate::lifecycle::WalletComponent;
+    use crate::safe_int::{self, MAX_SAFE_INTEGER};
+    use crate::webhooks::{WebhookEvent, WebhookEventKind};
+    use bitcoin::Network;
+    use lightning::events::ClosureReason;
+    use std::collections::HashSet;
+
+    const NOW: u64 = 1_690_000_000;
+
+    /// One of every error, with context where the variant has any.
+    fn all_errors() -> Vec<MutinyError> {
+        vec![
+            MutinyError::AlreadyRunning,
+            MutinyError::NotRunning,
+            MutinyError::NotFound,
+            MutinyError::FundingTxCreationFailed,
+            MutinyError::ConnectionFailed,
+            MutinyError::DnsResolutionFailed,
+            MutinyError::IncorrectNetwork(Network::Testnet),
+            MutinyError::NetworkMismatch {
+                expected: Network::Bitcoin,
+                found: Network::Signet,
+            },
+            MutinyError::NonUniquePaymentHash,
+            MutinyError::PaymentTimeout,
+            MutinyError::InvoiceInvalid,
+            MutinyError::PreimageMismatch,
+            MutinyError::InvoiceAlreadySettled,
+            MutinyError::InvoiceCreationFailed,
+            MutinyError::ReserveAmountError,
+            MutinyError::InsufficientBalance {
+                needed_sats: MAX_SAFE_INTEGER + 1,
+                available_sats: 1_000,
+            },
+            MutinyError::LnUrlFailure,
+            MutinyError::LspGenericError,
+            MutinyError::LspFundingError,
+            MutinyError::LspConnectionError,
+            MutinyError::SubscriptionClientNotConfigured,
+            MutinyError::InvalidArgumentsError,
+            MutinyError::RoutingFailed,
+            MutinyError::DestinationUnreachable,
+            MutinyError::PeerInfoParseFailed,
+            MutinyError::ChannelCreationFailed,
+            MutinyError::ChannelClosingFailed,
+            MutinyError::PersistenceFailed {
+                source: MutinyStorageError::LockError,
+            },
+            MutinyError::ReadError {
+                source: MutinyStorageError::IndexedDBError,
+            },
+            MutinyError::LnDecodeError,
+            MutinyError::SeedGenerationFailed,
+            MutinyError::InvalidMnemonic,
+            MutinyError::PassphraseRequired,
+            MutinyError::IncorrectPassphrase,
+            MutinyError::BackupDecryptionFailed,
+            MutinyError::WalletOperationFailed,
+            MutinyError::WalletSigningFailed,
+            MutinyError::ChainAccessFailed,
+            MutinyError::WalletSyncError,
+            MutinyError::ChainSyncStale,
+            MutinyError::ChannelOperationDeferred,
+            MutinyError::PeerMessageQueueFull { max: 10 },
+            MutinyError::PeerBackupTooLarge {
+                size: 70_000,
+                max: 65_000,
+            },
+            MutinyError::PeerBackupNotFound,
+            MutinyError::PeerBackupNotPermitted,
+            MutinyError::NostrBackupFailed,
+            MutinyError::NostrBackupNotFound,
+            MutinyError::RemoteBackupFailed,
+            MutinyError::RemoteBackupNotFound,
+            MutinyError::RemoteBackupStale,
+            MutinyError::WebhookUrlRejected,
+            MutinyError::WebhookFailed,
+            MutinyError::ScbDiffConflict,
+            MutinyError::ScbParse { position: None },
+            MutinyError::ScbParse { position: Some(42) },
+            MutinyError::WalletInitializing {
+                completed_steps: 1,
+                total_steps: 3,
+            },
+            MutinyError::WalletStopping,
+            MutinyError::WalletStopped,
+            MutinyError::ComponentUnavailable(WalletComponent::Lightning),
+            MutinyError::RapidGossipSyncError,
+            MutinyError::DLCManagerError,
+            MutinyError::PubkeyInvalid,
+            MutinyError::IncorrectLnUrlFunction,
+            MutinyError::BadAmountError,
+            MutinyError::BitcoinPriceError,
+            MutinyError::Other(anyhow::anyhow!("something else")),
+        ]
+    }
+
+    /// One of every event kind, with and without optional data.
+    fn all_events() -> Vec<WebhookEvent> {
+        vec![
+            WebhookEvent::payment_received([1; 32], 21_000, NOW),
+            WebhookEvent::payment_sent([2; 32], None, NOW),
+            WebhookEvent::payment_sent([2; 32], Some(3_000), NOW),
+            WebhookEvent::channel_closed(1, &ClosureReason::CooperativeClosure, NOW),
+            WebhookEvent::backup_stale(7, NOW),
+            WebhookEvent::ping(NOW),
+        ]
+    }
+
+    #[test]
+    fn test_catalog_ids_unique() {
+        let ids: HashSet<&str> = CATALOG.iter().map(|t| t.id).collect();
+        assert_eq!(ids.len(), CATALOG.len());
+
+        // every parameter shows up in the english text
+        for template in CATALOG {
+            for (name, _) in template.params {
+                assert!(
+                    template.default.contains(&format!("{{{name}}}")),
+                    "{} does not use {name}",
+                    template.id
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_every_error_and_event_in_catalog() {
+        for error in all_errors() {
+            let message = error.message();
+            assert!(message.matches_template(), "{error:?} -> {message:?}");
+        }
+
+        let mut kinds = vec![];
+        for event in all_events() {
+            let message = event.message.clone().unwrap();
+            assert!(message.matches_template(), "{event:?}");
+            kinds.push(event.kind);
+        }
+        for kind in [
+            WebhookEventKind::PaymentReceived,
+            WebhookEventKind::PaymentSent,
+            WebhookEventKind::ChannelClosed,
+            WebhookEventKind::BackupStale,
+            WebhookEventKind::Ping,
+        ] {
+            assert!(kinds.contains(&kind), "{kind:?} has no sample event");
+        }
+
+        // every template is used by an error or an event
+        let used: HashSet<String> = all_errors()
+            .iter()
+            .map(|e| e.message().id)
+            .chain(all_events().into_iter().map(|e| e.message.unwrap().id))
+            .collect();
+        for template in CATALOG {
+            assert!(used.contains(template.id), "{} is never used", template.id);
+        }
+    }
+
+    #[test]
+    fn test_render_default() {
+        let message = MutinyError::WalletInitializing {
+            completed_steps: 1,
+            total_steps: 3,
+        }
+        .message();
+        assert_eq!(
+            message.render_default(),
+            "The wallet is still starting up (1/3 steps done)."
+        );
+
+        // the english text matches the error for messages without parameters
+        assert_eq!(
+            MutinyError::ChainSyncStale.message().render_default(),
+            MutinyError::ChainSyncStale.to_string()
+        );
+
+        // large amounts are strings, they are written the same
+        let message = MutinyError::InsufficientBalance {
+            needed_sats: MAX_SAFE_INTEGER + 1,
+            available_sats: 1_000,
+        }
+        .message();
+        assert_eq!(
+            message.params["needed_sats"],
+            safe_int::to_json_value(MAX_SAFE_INTEGER + 1)
+        );
+        assert_eq!(
+            message.render_default(),
+            "Paying 9007199254740992 sats needs more than the 1000 sats available."
+        );
+
+        let event = WebhookEvent::payment_received([1; 32], 21_000, NOW);
+        assert_eq!(event.message.unwrap().render_default(), "Received 21 sats.");
+
+        assert_eq!(render_default("error.unknown_id", &BTreeMap::new()), None);
+        // missing parameters are left in place
+        assert_eq!(
+            render_default("error.scb_parse_at", &BTreeMap::new()).unwrap(),
+            "The backup could not be parsed, check the character at position {position}."
+        );
+    }
+
+    #[test]
+    fn test_message_param_kinds() {
+        let message = Message::new("error.wallet_initializing")
+            .with("completed_steps", 1)
+            .with("total_steps", "3");
+        assert!(!message.matches_template());
+
+        let message = Message::new("error.wallet_initializing").with("completed_steps", 1);
+        assert!(!message.matches_template());
+
+        let message = Message::new("event.payment_received").with("amount_sats", "12");
+        assert!(message.matches_template());
+        let message = Message::new("event.payment_received").with("amount_sats", "12 sats");
+        assert!(!message.matches_template());
+
+        assert!(!Message::new("error.unknown_id").matches_template());
+    }
+}
@@ -0,0 +1,505 @@
+//! Host name resolution for native peer and LSP connections.
+//!
+//! Resolving through the system resolver tells the local network which
+//! lightning nodes we connect to. With a [`DohConfig`] names are resolved
+//! over DNS-over-HTTPS instead, using the JSON API most providers offer.
+
+use crate::error::MutinyError;
+use crate::logging::MutinyLogger;
+use crate::utils;
+use lightning::util::logger::Logger;
+use lightning::{log_debug, log_warn};
+use reqwest::Client;
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+/// A DoH provider that is addressed by IP, so reaching it needs no lookup itself
+pub const DEFAULT_DOH_PROVIDER: &str = "https://1.1.1.1/dns-query";
+
+/// DoH queries allowed per minute by default, lookups past it fail over
+pub const DEFAULT_DOH_QUERIES_PER_MINUTE: u32 = 60;
+
+/// Upper bound for how long an answer is cached, whatever its TTL says
+const MAX_CACHE_SECS: u64 = 60 * 60 * 24;
+
+const RECORD_A: u16 = 1;
+const RECORD_AAAA: u16 = 28;
+
+/// What to do when a DoH lookup fails.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DohFallback {
+    /// Resolve through the system resolver instead
+    #[default]
+    SystemDns,
+    /// Fail the lookup, so names never reach the system resolver
+    HardFail,
+}
+
+/// Which address families are used, and in what order.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IpPreference {
+    /// Addresses in the order they were resolved
+    #[default]
+    Any,
+    PreferIpv4,
+    PreferIpv6,
+    Ipv4Only,
+    Ipv6Only,
+}
+
+impl IpPreference {
+    fn record_types(&self) -> &'static [u16] {
+        match self {
+            IpPreference::Any | IpPreference::PreferIpv4 => &[RECORD_A, RECORD_AAAA],
+            IpPreference::PreferIpv6 => &[RECORD_AAAA, RECORD_A],
+            IpPreference::Ipv4Only => &[RECORD_A],
+            IpPreference::Ipv6Only => &[RECORD_AAAA],
+        }
+    }
+
+    /// Filters and orders the addresses, keeping the order within a family
+    fn apply(&self, mut addrs: Vec<IpAddr>) -> Vec<IpAddr> {
+        match self {
+            IpPreference::Any => {}
+            IpPreference::PreferIpv4 => addrs.sort_by_key(|a| a.is_ipv6()),
+            IpPreference::PreferIpv6 => addrs.sort_by_key(|a| a.is_ipv4()),
+            IpPreference::Ipv4Only => addrs.retain(|a| a.is_ipv4()),
+            IpPreference::Ipv6Only => addrs.retain(|a| a.is_ipv6()),
+        }
+        addrs
+    }
+}
+
+/// Settings for resolving host names over DNS-over-HTTPS.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DohConfig {
+    /// The JSON API endpoint of the provider, queried with `name` and `type`
+    pub provider_url: String,
+    pub fallback: DohFallback,
+    pub max_queries_per_minute: u32,
+}
+
+impl Default for DohConfig {
+    fn default() -> Self {
+        Self::new(DEFAULT_DOH_PROVIDER)
+    }
+}
+
+impl DohConfig {
+    pub fn new(provider_url: &str) -> Self {
+        Self {
+            provider_url: provider_url.to_string(),
+            fallback: DohFallback::default(),
+            max_queries_per_minute: DEFAULT_DOH_QUERIES_PER_MINUTE,
+        }
+    }
+
+    /// Fail lookups the provider can not answer instead of using the system resolver
+    pub fn with_hard_fail(mut self) -> Self {
+        self.fallback = DohFallback::HardFail;
+        self
+    }
+
+    pub fn with_max_queries_per_minute(mut self, max_queries_per_minute: u32) -> Self {
+        self.max_queries_per_minute = max_queries_per_minute;
+        self
+    }
+}
+
+/// How host names are resolved, the system resolver unless DoH is set.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DnsConfig {
+    pub doh: Option<DohConfig>,
+    pub ip_preference: IpPreference,
+}
+
+#[derive(Deserialize)]
+struct DohResponse {
+    #[serde(rename = "Status")]
+    status: u32,
+    #[serde(rename = "Answer", default)]
+    answer: Vec<DohAnswer>,
+}
+
+#[derive(Deserialize)]
+struct DohAnswer {
+    #[serde(rename = "type")]
+    record_type: u16,
+    #[serde(rename = "TTL")]
+    ttl: u64,
+    data: String,
+}
+
+struct CacheEntry {
+    addrs: Vec<IpAddr>,
+    expires_at: u64,
+}
+
+#[derive(Default)]
+struct DohState {
+    cache: HashMap<String, CacheEntry>,
+    /// When the queries of the last minute were made
+    queries: VecDeque<u64>,
+}
+
+/// Resolves host names as configured in the [`DnsConfig`], caching DoH answers
+/// for as long as their TTL allows.
+#[derive(Clone)]
+pub struct DnsResolver {
+    config: DnsConfig,
+    http_client: Client,
+    state: Arc<utils::Mutex<DohState>>,
+    logger: Arc<MutinyLogger>,
+}
+
+impl DnsResolver {
+    pub fn new(config: DnsConfig, logger: Arc<MutinyLogger>) -> Self {
+        Self {
+            config,
+            http_client: Client::new(),
+            state: Arc::new(utils::Mutex::new(DohState::default())),
+            logger,
+        }
+    }
+
+    /// A HTTP client that resolves the hosts of its requests with this resolver
+    pub fn http_client(&self) -> Result<Client, MutinyError> {
+        Client::builder()
+            .dns_resolver(Arc::new(self.clone()))
+            .build()
+            .map_err(|_| MutinyError::InvalidArgumentsError)
+    }
+
+    /// Resolves a `host:port` string, IP addresses are returned as they are.
+    pub async fn resolve_addr(&self, addr: &str) -> Result<Vec<SocketAddr>, MutinyError> {
+        if let Ok(socket_addr) = addr.parse::<SocketAddr>() {
+            return Ok(vec![socket_addr]);
+        }
+
+        let (host, port) = addr
+            .rsplit_once(':')
+            .ok_or(MutinyError::PeerInfoParseFailed)?;
+        let port = port
+            .parse::<u16>()
+            .map_err(|_| MutinyError::PeerInfoParseFailed)?;
+        self.resolve_host(host, port).await
+    }
+
+    /// Resolves a host name to the addresses to connect to.
+    pub async fn resolve_host(
+        &self,
+        host: &str,
+        port: u16,
+    ) -> Result<Vec<SocketAddr>, MutinyError> {
+        self.resolve_at(host, port, utils::now().as_secs()).await
+    }
+
+    pub(crate) async fn resolve_at(
+        &self,
+        host: &str,
+        port: u16,
+        now: u64,
+    ) -> Result<Vec<SocketAddr>, MutinyError> {
+        let host = host.trim_end_matches('.').to_lowercase();
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return Ok(vec![SocketAddr::new(ip, port)]);
+        }
+
+        // onion addresses only exist within tor, asking a resolver leaks them
+        if host.ends_with(".onion") {
+            return Err(MutinyError::DnsResolutionFailed);
+        }
+
+        let doh_addrs = match self.config.doh {
+            Some(ref doh) => match self.doh_lookup(doh, &host, now).await {
+                Ok(addrs) => Some(addrs),
+                Err(e) => {
+                    log_warn!(self.logger, "DoH lookup of {host} failed: {e}");
+                    if doh.fallback == DohFallback::HardFail {
+                        return Err(MutinyError::DnsResolutionFailed);
+                    }
+                    None
+                }
+            },
+            None => None,
+        };
+        let addrs = match doh_addrs {
+            Some(addrs) => addrs,
+            None => self.system_lookup(&host).await?,
+        };
+
+        let addrs = self.config.ip_preference.apply(addrs);
+        if addrs.is_empty() {
+            return Err(MutinyError::DnsResolutionFailed);
+        }
+
+        Ok(addrs
+            .into_iter()
+            .map(|ip| SocketAddr::new(ip, port))
+            .collect())
+    }
+
+    async fn system_lookup(&self, host: &str) -> Result<Vec<IpAddr>, MutinyError> {
+        let addrs = tokio::net::lookup_host((host, 0))
+            .await
+            .map_err(|_| MutinyError::DnsResolutionFailed)?;
+        Ok(addrs.map(|a| a.ip()).collect())
+    }
+
+    async fn doh_lookup(
+        &self,
+        doh: &DohConfig,
+        host: &str,
+        now: u64,
+    ) -> Result<Vec<IpAddr>, MutinyError> {
+        if let Some(entry) = self
+            .state
+            .lock()
+            .expect("Failed to lock dns state")
+            .cache
+            .get(host)
+        {
+            if entry.expires_at > now {
+                return Ok(entry.addrs.clone());
+            }
+        }
+
+        let mut addrs = vec![];
+        let mut ttl = MAX_CACHE_SECS;
+        for record_type in self.config.ip_preference.record_types() {
+            self.count_query(doh, now)?;
+            let response = self.query(doh, host, *record_type).await?;
+            for answer in response.answer {
+                // skips the CNAMEs that lead to the addresses
+                if answer.record_type != *record_type {
+                    continue;
+                }
+                if let Ok(ip) = answer.data.parse::<IpAddr>() {
+                    ttl = ttl.min(answer.ttl);
+                    addrs.push(ip);
+                }
+            }
+        }
+
+        if addrs.is_empty() {
+            return Err(MutinyError::DnsResolutionFailed);
+        }
+
+        log_debug!(self.logger, "resolved {host} over DoH, cached for {ttl}s");
+        self.state
+            .lock()
+            .expect("Failed to lock dns state")
+            .cache
+            .insert(
+                host.to_string(),
+                CacheEntry {
+                    addrs: addrs.clone(),
+                    expires_at: now + ttl,
+                },
+            );
+
+        Ok(addrs)
+    }
+
+    /// Counts a query against the limit, failing once the limit is reached
+    fn count_query(&self, doh: &DohConfig, now: u64) -> Result<(), MutinyError> {
+        let mut state = self.state.lock().expect("Failed to lock dns state");
+        while state.queries.front().map_or(false, |t| *t + 60 <= now) {
+            state.queries.pop_front();
+        }
+        if state.queries.len() >= doh.max_queries_per_minute as usize {
+            return Err(MutinyError::DnsResolutionFailed);
+        }
+        state.queries.push_back(now);
+        Ok(())
+    }
+
+    async fn query(
+        &self,
+        doh: &DohConfig,
+        host: &str,
+        record_type: u16,
+    ) -> Result<DohResponse, MutinyError> {
+        let record_type = record_type.to_string();
+        let response: DohResponse = self
+            .http_client
+            .get(&doh.provider_url)
+            .query(&[("name", host), ("type", record_type.as_str())])
+            .header("Accept", "application/dns-json")
+            .send()
+            .await
+            .map_err(|_| MutinyError::DnsResolutionFailed)?
+            .error_for_status()
+            .map_err(|_| MutinyError::DnsResolutionFailed)?
+            .json()
+            .await
+            .map_err(|_| MutinyError::DnsResolutionFailed)?;
+
+        // anything but NOERROR, like NXDOMAIN
+        if response.status != 0 {
+            return Err(MutinyError::DnsResolutionFailed);
+        }
+
+        Ok(response)
+    }
+}
+
+impl reqwest::dns::Resolve for DnsResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let resolver = self.clone();
+        Box::pin(async move {
+            let addrs = resolver
+                .resolve_host(name.as_str(), 0)
+                .await
+                .map_err(|e| e.to_string())?;
+            let addrs: reqwest::dns::Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const NOW: u64 = 1_690_000_000;
+
+    /// Starts a DoH endpoint answering every name with fixed addresses,
+    /// returns its url and the number of queries it got
+    async fn start_doh_server() -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/dns-query", listener.local_addr().unwrap());
+        let hits = Arc::new(AtomicUsize::new(0));
+
+        let server_hits = hits.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0; 4096];
+                let n = stream.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]);
+                server_hits.fetch_add(1, Ordering::SeqCst);
+
+                let body = if request.contains("type=28") {
+                    r#"{"Status":0,"Answer":[{"type":28,"TTL":120,"data":"2001:db8::1"}]}"#
+                } else {
+                    r#"{"Status":0,"Answer":[{"type":5,"TTL":300,"data":"node.example."},{"type":1,"TTL":60,"data":"203.0.113.1"}]}"#
+                };
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/dns-json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        (url, hits)
+    }
+
+    fn doh_resolver(doh: DohConfig, ip_preference: IpPreference) -> DnsResolver {
+        let config = DnsConfig {
+            doh: Some(doh),
+            ip_preference,
+        };
+        DnsResolver::new(config, Arc::new(MutinyLogger::default()))
+    }
+
+    #[tokio::test]
+    async fn test_doh_caches_within_ttl() {
+        let test_name = "test_doh_caches_within_ttl";
+        log!("{}", test_name);
+
+        let (url, hits) = start_doh_server().await;
+        let resolver = doh_resolver(DohConfig::new(&url), IpPreference::PreferIpv6);
+
+        let addrs = resolver
+            .resolve_at("Node.Example", 9735, NOW)
+            .await
+            .unwrap();
+        assert_eq!(
+            addrs,
+            vec![
+                "[2001:db8::1]:9735".parse().unwrap(),
+                "203.0.113.1:9735".parse().unwrap()
+            ]
+        );
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+        // the answer with the shortest TTL decides how long it is cached
+        let cached = resolver.resolve_at("node.example", 9735, NOW + 59).await;
+        assert_eq!(cached.unwrap(), addrs);
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+        resolver
+            .resolve_at("node.example", 9735, NOW + 60)
+            .await
+            .unwrap();
+        assert_eq!(hits.load(Ordering::SeqCst), 4);
+
+        // only the preferred family is queried
+        let resolver = doh_resolver(DohConfig::new(&url), IpPreference::Ipv4Only);
+        let addrs = resolver.resolve_at("node.example", 80, NOW).await.unwrap();
+        assert_eq!(addrs, vec!["203.0.113.1:80".parse().unwrap()]);
+        assert_eq!(hits.load(Ordering::SeqCst), 5);
+    }
+
+    #[tokio::test]
+    async fn test_doh_fallback_and_hard_fail() {
+        let test_name = "test_doh_fallback_and_hard_fail";
+        log!("{}", test_name);
+
+        // nothing listens on the port once the listener is dropped
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/dns-query", listener.local_addr().unwrap());
+        drop(listener);
+
+        let fallback = doh_resolver(DohConfig::new(&url), IpPreference::Ipv4Only);
+        let addrs = fallback.resolve_at("localhost", 9735, NOW).await.unwrap();
+        assert!(addrs.contains(&"127.0.0.1:9735".parse().unwrap()));
+        assert!(addrs.iter().all(|a| a.is_ipv4()));
+
+        let hard_fail = doh_resolver(DohConfig::new(&url).with_hard_fail(), IpPreference::Any);
+        let res = hard_fail.resolve_at("localhost", 9735, NOW).await;
+        assert!(matches!(res, Err(MutinyError::DnsResolutionFailed)));
+
+        // going over the rate limit fails like an unreachable provider
+        let (url, hits) = start_doh_server().await;
+        let doh = DohConfig::new(&url)
+            .with_hard_fail()
+            .with_max_queries_per_minute(1);
+        let limited = doh_resolver(doh, IpPreference::Ipv4Only);
+        assert!(limited.resolve_at("a.example", 80, NOW).await.is_ok());
+        let res = limited.resolve_at("b.example", 80, NOW + 30).await;
+        assert!(matches!(res, Err(MutinyError::DnsResolutionFailed)));
+        assert!(limited.resolve_at("b.example", 80, NOW + 60).await.is_ok());
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_onion_and_ips_skip_resolver() {
+        let test_name = "test_onion_and_ips_skip_resolver";
+        log!("{}", test_name);
+
+        let (url, hits) = start_doh_server().await;
+        let resolver = doh_resolver(DohConfig::new(&url), IpPreference::Any);
+
+        let onion = "vww6ybal4bd7szmgncyruucpgfkqahzddi37ktceo3ah7ngmcopnpyyd.onion";
+        let res = resolver.resolve_at(onion, 9735, NOW).await;
+        assert!(matches!(res, Err(MutinyError::DnsResolutionFailed)));
+        let res = resolver.resolve_addr(&format!("{onion}:9735")).await;
+        assert!(matches!(res, Err(MutinyError::DnsResolutionFailed)));
+
+        let addrs = resolver.resolve_addr("[::1]:9735").await.unwrap();
+        assert_eq!(addrs, vec!["[::1]:9735".parse().unwrap()]);
+        let addrs = resolver.resolve_addr("127.0.0.1:9735").await.unwrap();
+        assert_eq!(addrs, vec!["127.0.0.1:9735".parse().unwrap()]);
+
+        assert_eq!(hits.load(Ordering::SeqCst), 0);
+    }
+}
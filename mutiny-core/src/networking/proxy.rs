@@ -7,6 +7,7 @@ use futures::{lock::Mutex, stream::SplitSink, SinkExt, StreamExt};
 use gloo_net::websocket::{futures::WebSocket, Message, State};
 use lightning::{log_debug, log_trace};
 use lightning::{log_error, util::logger::Logger};
+use std::future::Future;
 use std::sync::Arc;
 
 use crate::logging::MutinyLogger;
@@ -21,6 +22,51 @@ pub trait Proxy {
     async fn close(&self);
 }
 
+/// Default delay before the first reconnection attempt.
+pub const DEFAULT_RECONNECT_BASE_DELAY_MS: u64 = 1_000;
+
+/// Default upper bound for the delay between reconnection attempts.
+pub const DEFAULT_RECONNECT_MAX_DELAY_MS: u64 = 30_000;
+
+/// Default number of reconnection attempts before giving up.
+pub const DEFAULT_RECONNECT_MAX_RETRIES: u32 = 3;
+
+/// How [`WsProxy`] retries connecting to the websocket proxy.
+///
+/// The delay doubles with each attempt up to `max_delay_ms`, and a random
+/// jitter of up to half the delay is taken off so clients that lost their
+/// connection at the same time do not reconnect in lockstep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+    /// Number of retries after the first attempt, `None` retries until connected
+    pub max_retries: Option<u32>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            base_delay_ms: DEFAULT_RECONNECT_BASE_DELAY_MS,
+            max_delay_ms: DEFAULT_RECONNECT_MAX_DELAY_MS,
+            max_retries: Some(DEFAULT_RECONNECT_MAX_RETRIES),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// The delay before the given retry, starting at zero. The result is between
+    /// half and all of the capped exponential delay, depending on the jitter.
+    pub fn delay_ms(&self, retry: u32, jitter: u32) -> u64 {
+        let delay = self
+            .base_delay_ms
+            .saturating_mul(2u64.saturating_pow(retry))
+            .min(self.max_delay_ms);
+        let half = delay / 2;
+        half + u64::from(jitter) % (delay - half + 1)
+    }
+}
+
 pub struct WsProxy {
     write: WsSplit,
     read: ReadSplit,
@@ -34,30 +80,16 @@ impl WsProxy {
     pub async fn new(
         proxy_url: &str,
         peer_connection_info: PubkeyConnectionInfo,
+        reconnect_policy: ReconnectPolicy,
         logger: Arc<MutinyLogger>,
     ) -> Result<Self, MutinyError> {
-        let ws = match peer_connection_info.connection_type {
-            ConnectionType::Tcp(s) => WebSocket::open(&tcp_proxy_to_url(proxy_url, &s)?)
-                .map_err(|_| MutinyError::ConnectionFailed)?,
-        };
-
-        // wait for connected status or time out at 10s
-        let mut retries = 10;
-        while retries > 0 {
-            match ws.state() {
-                State::Open => break,
-                State::Closed => break,
-                _ => {
-                    sleep(1_000).await;
-                    retries -= 1;
-                }
-            }
-        }
-
-        match ws.state() {
-            State::Open => {}
-            _ => return Err(MutinyError::ConnectionFailed),
-        }
+        let ws = connect_with_backoff(
+            &reconnect_policy,
+            &logger,
+            || open_websocket(proxy_url, &peer_connection_info),
+            |delay| sleep(delay as i32),
+        )
+        .await?;
 
         // TODO wait until we get an OK response from websocket.
         // A connection to the proxy for connections just means that
@@ -77,6 +109,81 @@ impl WsProxy {
     }
 }
 
+async fn open_websocket(
+    proxy_url: &str,
+    peer_connection_info: &PubkeyConnectionInfo,
+) -> Result<WebSocket, MutinyError> {
+    let ws = match peer_connection_info.connection_type {
+        ConnectionType::Tcp(ref s) => WebSocket::open(&tcp_proxy_to_url(proxy_url, s)?)
+            .map_err(|_| MutinyError::ConnectionFailed)?,
+    };
+
+    // wait for connected status or time out at 10s
+    let mut retries = 10;
+    while retries > 0 {
+        match ws.state() {
+            State::Open => break,
+            State::Closed => break,
+            _ => {
+                sleep(1_000).await;
+                retries -= 1;
+            }
+        }
+    }
+
+    match ws.state() {
+        State::Open => Ok(ws),
+        _ => Err(MutinyError::ConnectionFailed),
+    }
+}
+
+/// Calls `connect` until it succeeds, sleeping between attempts as given by the
+/// policy. Returns the last error once the retries run out.
+async fn connect_with_backoff<T, C, CF, S, SF>(
+    policy: &ReconnectPolicy,
+    logger: &MutinyLogger,
+    mut connect: C,
+    mut sleep: S,
+) -> Result<T, MutinyError>
+where
+    C: FnMut() -> CF,
+    CF: Future<Output = Result<T, MutinyError>>,
+    S: FnMut(u64) -> SF,
+    SF: Future<Output = ()>,
+{
+    let mut retry = 0;
+    loop {
+        let e = match connect().await {
+            Ok(conn) => return Ok(conn),
+            Err(e) => e,
+        };
+
+        if policy.max_retries.is_some_and(|max| retry >= max) {
+            log_error!(
+                logger,
+                "giving up connecting to ws after {} attempts: {e}",
+                retry + 1
+            );
+            return Err(e);
+        }
+
+        let delay = policy.delay_ms(retry, random_jitter());
+        retry += 1;
+        log_debug!(
+            logger,
+            "could not connect to ws: {e}, retry {retry} in {delay}ms"
+        );
+        sleep(delay).await;
+    }
+}
+
+fn random_jitter() -> u32 {
+    let mut bytes = [0u8; 4];
+    // without randomness we just reconnect without jitter
+    let _ = getrandom::getrandom(&mut bytes);
+    u32::from_be_bytes(bytes)
+}
+
 #[async_trait(?Send)]
 impl Proxy for WsProxy {
     fn send(&self, data: Message) {
@@ -126,7 +233,11 @@ mod tests {
 
     use crate::test_utils::*;
 
+    use crate::error::MutinyError;
+    use crate::logging::MutinyLogger;
     use crate::networking::proxy::tcp_proxy_to_url;
+    use crate::networking::proxy::{connect_with_backoff, ReconnectPolicy};
+    use futures::future::ready;
 
     use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
 
@@ -146,6 +257,7 @@ mod tests {
         let proxy = WsProxy::new(
             "wss://p.mutinywallet.com",
             PubkeyConnectionInfo::new(&format!("{}@{}", PEER_PUBKEY, "3.33.236.230:9735")).unwrap(),
+            ReconnectPolicy::default(),
             logger,
         )
         .await
@@ -163,4 +275,85 @@ mod tests {
             tcp_proxy_to_url("ws://127.0.0.1:3001", "127.0.0.1:4000").unwrap()
         );
     }
+    #[test]
+    fn test_reconnect_delay_bounds() {
+        log!("test reconnect delay bounds");
+
+        let policy = ReconnectPolicy::default();
+        assert_eq!(policy.delay_ms(0, 0), 500);
+        assert_eq!(policy.delay_ms(0, u32::MAX), 500 + u32::MAX as u64 % 501);
+        assert_eq!(policy.delay_ms(3, 4_000), 8_000);
+
+        // capped, also for retries that would overflow
+        for retry in [5, 30, 64, u32::MAX] {
+            for jitter in [0, 1, 12_345, u32::MAX] {
+                let delay = policy.delay_ms(retry, jitter);
+                assert!(delay >= policy.max_delay_ms / 2);
+                assert!(delay <= policy.max_delay_ms);
+            }
+        }
+    }
+
+    #[test]
+    async fn test_reconnect_with_backoff() {
+        log!("test reconnect with backoff");
+        let logger = MutinyLogger::default();
+
+        // fails 4 times before connecting
+        let mut attempts = 0;
+        let mut delays = vec![];
+        let policy = ReconnectPolicy {
+            max_retries: None,
+            ..Default::default()
+        };
+        let conn = connect_with_backoff(
+            &policy,
+            &logger,
+            || {
+                attempts += 1;
+                ready(if attempts <= 4 {
+                    Err(MutinyError::ConnectionFailed)
+                } else {
+                    Ok(attempts)
+                })
+            },
+            |delay| {
+                delays.push(delay);
+                ready(())
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(conn, 5);
+        assert_eq!(delays.len(), 4);
+        assert!(delays.windows(2).all(|d| d[0] <= d[1]));
+        assert!(delays[0] >= 500 && delays[0] <= 1_000);
+        assert!(delays[3] >= 4_000 && delays[3] <= 8_000);
+
+        // bounded retries give up with the last error
+        let mut attempts = 0;
+        let mut delays = vec![];
+        let policy = ReconnectPolicy {
+            max_retries: Some(2),
+            ..Default::default()
+        };
+        let res: Result<(), MutinyError> = connect_with_backoff(
+            &policy,
+            &logger,
+            || {
+                attempts += 1;
+                ready(Err(MutinyError::ConnectionFailed))
+            },
+            |delay| {
+                delays.push(delay);
+                ready(())
+            },
+        )
+        .await;
+
+        assert!(matches!(res, Err(MutinyError::ConnectionFailed)));
+        assert_eq!(attempts, 3);
+        assert_eq!(delays.len(), 2);
+    }
 }
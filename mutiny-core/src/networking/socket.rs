@@ -11,7 +11,9 @@ use std::sync::Arc;
 use crate::networking::ws_socket::WsTcpSocketDescriptor;
 
 #[cfg(not(target_arch = "wasm32"))]
-use crate::networking::tcp_socket::TcpSocketDescriptor;
+use crate::networking::socks_socket::SocksSocketDescriptor;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::networking::tcp_socket::{TcpSocketDescriptor, TlsSocketDescriptor};
 
 pub trait ReadDescriptor {
     async fn read(&self) -> Option<Result<Vec<u8>, MutinyError>>;
@@ -23,6 +25,10 @@ pub enum MutinySocketDescriptor {
     Tcp(WsTcpSocketDescriptor),
     #[cfg(not(target_arch = "wasm32"))]
     Native(TcpSocketDescriptor),
+    #[cfg(not(target_arch = "wasm32"))]
+    NativeTls(TlsSocketDescriptor),
+    #[cfg(not(target_arch = "wasm32"))]
+    NativeSocks(SocksSocketDescriptor),
 }
 
 impl ReadDescriptor for MutinySocketDescriptor {
@@ -32,6 +38,10 @@ impl ReadDescriptor for MutinySocketDescriptor {
             MutinySocketDescriptor::Tcp(s) => s.read().await,
             #[cfg(not(target_arch = "wasm32"))]
             MutinySocketDescriptor::Native(s) => s.read().await,
+            #[cfg(not(target_arch = "wasm32"))]
+            MutinySocketDescriptor::NativeTls(s) => s.read().await,
+            #[cfg(not(target_arch = "wasm32"))]
+            MutinySocketDescriptor::NativeSocks(s) => s.read().await,
         }
     }
 }
@@ -43,6 +53,10 @@ impl peer_handler::SocketDescriptor for MutinySocketDescriptor {
             MutinySocketDescriptor::Tcp(s) => s.send_data(data, resume_read),
             #[cfg(not(target_arch = "wasm32"))]
             MutinySocketDescriptor::Native(s) => s.send_data(data, resume_read),
+            #[cfg(not(target_arch = "wasm32"))]
+            MutinySocketDescriptor::NativeTls(s) => s.send_data(data, resume_read),
+            #[cfg(not(target_arch = "wasm32"))]
+            MutinySocketDescriptor::NativeSocks(s) => s.send_data(data, resume_read),
         }
     }
 
@@ -52,6 +66,10 @@ impl peer_handler::SocketDescriptor for MutinySocketDescriptor {
             MutinySocketDescriptor::Tcp(s) => s.disconnect_socket(),
             #[cfg(not(target_arch = "wasm32"))]
             MutinySocketDescriptor::Native(s) => s.disconnect_socket(),
+            #[cfg(not(target_arch = "wasm32"))]
+            MutinySocketDescriptor::NativeTls(s) => s.disconnect_socket(),
+            #[cfg(not(target_arch = "wasm32"))]
+            MutinySocketDescriptor::NativeSocks(s) => s.disconnect_socket(),
         }
     }
 }
@@ -0,0 +1,400 @@
+use crate::error::MutinyError;
+use crate::networking::socket::ReadDescriptor;
+use lightning::ln::peer_handler;
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+use tokio::time;
+
+static ID_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+const SOCKS5_VERSION: u8 = 0x05;
+const SOCKS5_AUTH_NONE: u8 = 0x00;
+const SOCKS5_AUTH_PASSWORD: u8 = 0x02;
+const SOCKS5_PASSWORD_VERSION: u8 = 0x01;
+const SOCKS5_CMD_CONNECT: u8 = 0x01;
+const SOCKS5_ATYP_IPV4: u8 = 0x01;
+const SOCKS5_ATYP_DOMAIN: u8 = 0x03;
+const SOCKS5_ATYP_IPV6: u8 = 0x04;
+const SOCKS5_SUCCEEDED: u8 = 0x00;
+
+/// A SOCKS5 proxy to dial peers through, like a local Tor daemon
+/// for peers that are only reachable as onion services.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Socks5Proxy {
+    /// The `host:port` of the proxy
+    pub addr: String,
+    /// Username and password for the proxy, Tor uses them to isolate circuits
+    pub credentials: Option<(String, String)>,
+}
+
+/// A connection made through a SOCKS5 proxy.
+/// The stream is split so a pending read does not block writes.
+pub struct SocksSocketDescriptor {
+    read: Arc<Mutex<ReadHalf<TcpStream>>>,
+    write: Arc<Mutex<WriteHalf<TcpStream>>>,
+    id: u64,
+}
+
+impl SocksSocketDescriptor {
+    fn new(stream: TcpStream) -> Self {
+        let id = ID_COUNTER.fetch_add(1, Ordering::AcqRel);
+        let (read, write) = tokio::io::split(stream);
+        Self {
+            read: Arc::new(Mutex::new(read)),
+            write: Arc::new(Mutex::new(write)),
+            id,
+        }
+    }
+}
+
+/// Connects to `host:port` through the given proxy. The proxy resolves host
+/// names itself, so onion addresses can be dialed.
+pub async fn connect_socks5(
+    proxy: &Socks5Proxy,
+    host: &str,
+    port: u16,
+) -> Result<SocksSocketDescriptor, MutinyError> {
+    let mut stream = time::timeout(Duration::from_secs(10), TcpStream::connect(&proxy.addr))
+        .await
+        .map_err(|_| MutinyError::ConnectionFailed)?
+        .map_err(|_| MutinyError::ConnectionFailed)?;
+
+    // building a tor circuit can take a while
+    time::timeout(
+        Duration::from_secs(30),
+        socks5_handshake(&mut stream, proxy, host, port),
+    )
+    .await
+    .map_err(|_| MutinyError::ConnectionFailed)??;
+
+    Ok(SocksSocketDescriptor::new(stream))
+}
+
+/// Negotiates the authentication method and asks the proxy to connect to the destination.
+async fn socks5_handshake(
+    stream: &mut TcpStream,
+    proxy: &Socks5Proxy,
+    host: &str,
+    port: u16,
+) -> Result<(), MutinyError> {
+    let method = if proxy.credentials.is_some() {
+        SOCKS5_AUTH_PASSWORD
+    } else {
+        SOCKS5_AUTH_NONE
+    };
+    write(stream, &[SOCKS5_VERSION, 1, method]).await?;
+    let reply = read::<2>(stream).await?;
+    if reply != [SOCKS5_VERSION, method] {
+        return Err(MutinyError::ConnectionFailed);
+    }
+
+    if let Some((user, pass)) = &proxy.credentials {
+        if user.is_empty() || user.len() > 255 || pass.is_empty() || pass.len() > 255 {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        let mut auth = vec![SOCKS5_PASSWORD_VERSION, user.len() as u8];
+        auth.extend_from_slice(user.as_bytes());
+        auth.push(pass.len() as u8);
+        auth.extend_from_slice(pass.as_bytes());
+        write(stream, &auth).await?;
+        let reply = read::<2>(stream).await?;
+        if reply[1] != SOCKS5_SUCCEEDED {
+            return Err(MutinyError::ConnectionFailed);
+        }
+    }
+
+    let mut request = vec![SOCKS5_VERSION, SOCKS5_CMD_CONNECT, 0];
+    match host.trim_start_matches('[').trim_end_matches(']').parse() {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(SOCKS5_ATYP_IPV4);
+            request.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            request.push(SOCKS5_ATYP_IPV6);
+            request.extend_from_slice(&ip.octets());
+        }
+        Err(_) => {
+            if host.is_empty() || host.len() > 255 {
+                return Err(MutinyError::InvalidArgumentsError);
+            }
+            request.push(SOCKS5_ATYP_DOMAIN);
+            request.push(host.len() as u8);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    write(stream, &request).await?;
+
+    // the reply ends with the address the proxy bound, which we do not need
+    let reply = read::<4>(stream).await?;
+    if reply[0] != SOCKS5_VERSION || reply[1] != SOCKS5_SUCCEEDED {
+        return Err(MutinyError::ConnectionFailed);
+    }
+    let addr_len = match reply[3] {
+        SOCKS5_ATYP_IPV4 => 4,
+        SOCKS5_ATYP_IPV6 => 16,
+        SOCKS5_ATYP_DOMAIN => read::<1>(stream).await?[0] as usize,
+        _ => return Err(MutinyError::ConnectionFailed),
+    };
+    let mut bound = vec![0; addr_len + 2];
+    stream
+        .read_exact(&mut bound)
+        .await
+        .map_err(|_| MutinyError::ConnectionFailed)?;
+
+    Ok(())
+}
+
+async fn write(stream: &mut TcpStream, bytes: &[u8]) -> Result<(), MutinyError> {
+    stream
+        .write_all(bytes)
+        .await
+        .map_err(|_| MutinyError::ConnectionFailed)
+}
+
+async fn read<const N: usize>(stream: &mut TcpStream) -> Result<[u8; N], MutinyError> {
+    let mut buf = [0; N];
+    stream
+        .read_exact(&mut buf)
+        .await
+        .map_err(|_| MutinyError::ConnectionFailed)?;
+    Ok(buf)
+}
+
+impl ReadDescriptor for SocksSocketDescriptor {
+    async fn read(&self) -> Option<Result<Vec<u8>, MutinyError>> {
+        let mut buf = [0; 4096];
+        match self.read.lock().await.read(&mut buf).await {
+            // the peer or the proxy closed the connection
+            Ok(0) => Some(Err(MutinyError::ConnectionFailed)),
+            Ok(n) => Some(Ok(buf[..n].to_vec())),
+            Err(_) => Some(Err(MutinyError::ConnectionFailed)),
+        }
+    }
+}
+
+unsafe impl Send for SocksSocketDescriptor {}
+unsafe impl Sync for SocksSocketDescriptor {}
+
+impl peer_handler::SocketDescriptor for SocksSocketDescriptor {
+    fn send_data(&mut self, data: &[u8], _resume_read: bool) -> usize {
+        let cloned_data = Vec::from(data);
+        let cloned_conn = self.write.clone();
+        // the stream is registered with the tokio runtime, so it is written from there
+        tokio::spawn(async move {
+            let mut write = cloned_conn.lock().await;
+            let _ = write.write_all(&cloned_data).await;
+        });
+        data.len()
+    }
+
+    fn disconnect_socket(&mut self) {
+        let cloned_conn = self.write.clone();
+        tokio::spawn(async move {
+            let _ = cloned_conn.lock().await.shutdown().await;
+        });
+    }
+}
+impl Clone for SocksSocketDescriptor {
+    fn clone(&self) -> Self {
+        Self {
+            read: Arc::clone(&self.read),
+            write: Arc::clone(&self.write),
+            id: self.id,
+        }
+    }
+}
+impl Eq for SocksSocketDescriptor {}
+impl PartialEq for SocksSocketDescriptor {
+    fn eq(&self, o: &Self) -> bool {
+        self.id == o.id
+    }
+}
+impl Hash for SocksSocketDescriptor {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl std::fmt::Debug for SocksSocketDescriptor {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "(socks {})", self.id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::*;
+    use lightning::ln::peer_handler::SocketDescriptor;
+    use tokio::net::TcpListener;
+
+    /// Starts a plain TCP echo server, returns its port
+    async fn start_echo_server() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 1024];
+            loop {
+                match stream.read(&mut buf).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => stream.write_all(&buf[..n]).await.unwrap(),
+                }
+            }
+        });
+        port
+    }
+
+    /// Starts a SOCKS5 proxy that accepts one connection, requiring the given
+    /// credentials if there are any, and forwards it to the requested destination.
+    async fn start_mock_proxy(credentials: Option<(String, String)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (mut client, _) = listener.accept().await.unwrap();
+
+            let mut greeting = [0u8; 2];
+            client.read_exact(&mut greeting).await.unwrap();
+            let mut methods = vec![0u8; greeting[1] as usize];
+            client.read_exact(&mut methods).await.unwrap();
+            let method = if credentials.is_some() {
+                SOCKS5_AUTH_PASSWORD
+            } else {
+                SOCKS5_AUTH_NONE
+            };
+            client.write_all(&[SOCKS5_VERSION, method]).await.unwrap();
+
+            if let Some((user, pass)) = credentials {
+                let mut header = [0u8; 2];
+                client.read_exact(&mut header).await.unwrap();
+                let mut got_user = vec![0u8; header[1] as usize];
+                client.read_exact(&mut got_user).await.unwrap();
+                let mut pass_len = [0u8; 1];
+                client.read_exact(&mut pass_len).await.unwrap();
+                let mut got_pass = vec![0u8; pass_len[0] as usize];
+                client.read_exact(&mut got_pass).await.unwrap();
+                if got_user != user.as_bytes() || got_pass != pass.as_bytes() {
+                    client
+                        .write_all(&[SOCKS5_PASSWORD_VERSION, 1])
+                        .await
+                        .unwrap();
+                    return;
+                }
+                client
+                    .write_all(&[SOCKS5_PASSWORD_VERSION, SOCKS5_SUCCEEDED])
+                    .await
+                    .unwrap();
+            }
+
+            let mut request = [0u8; 4];
+            client.read_exact(&mut request).await.unwrap();
+            let host = match request[3] {
+                SOCKS5_ATYP_IPV4 => {
+                    let mut ip = [0u8; 4];
+                    client.read_exact(&mut ip).await.unwrap();
+                    std::net::Ipv4Addr::from(ip).to_string()
+                }
+                SOCKS5_ATYP_DOMAIN => {
+                    let mut len = [0u8; 1];
+                    client.read_exact(&mut len).await.unwrap();
+                    let mut domain = vec![0u8; len[0] as usize];
+                    client.read_exact(&mut domain).await.unwrap();
+                    String::from_utf8(domain).unwrap()
+                }
+                _ => panic!("unexpected address type"),
+            };
+            let mut port = [0u8; 2];
+            client.read_exact(&mut port).await.unwrap();
+            let port = u16::from_be_bytes(port);
+
+            let mut target = TcpStream::connect((host.as_str(), port)).await.unwrap();
+            client
+                .write_all(&[
+                    SOCKS5_VERSION,
+                    SOCKS5_SUCCEEDED,
+                    0,
+                    SOCKS5_ATYP_IPV4,
+                    0,
+                    0,
+                    0,
+                    0,
+                    0,
+                    0,
+                ])
+                .await
+                .unwrap();
+            let _ = tokio::io::copy_bidirectional(&mut client, &mut target).await;
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_socks5_echo() {
+        let test_name = "test_socks5_echo";
+        log!("{}", test_name);
+
+        let port = start_echo_server().await;
+        let proxy = Socks5Proxy {
+            addr: start_mock_proxy(None).await,
+            credentials: None,
+        };
+        let mut descriptor = connect_socks5(&proxy, "127.0.0.1", port).await.unwrap();
+
+        assert_eq!(descriptor.send_data(b"hello", true), 5);
+        let echoed = descriptor.read().await.unwrap().unwrap();
+        assert_eq!(echoed, b"hello".to_vec());
+
+        descriptor.disconnect_socket();
+        assert!(descriptor.read().await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_socks5_credentials() {
+        let test_name = "test_socks5_credentials";
+        log!("{}", test_name);
+
+        let credentials = Some(("mutiny".to_string(), "hunter2".to_string()));
+
+        // host names are resolved by the proxy
+        let port = start_echo_server().await;
+        let proxy = Socks5Proxy {
+            addr: start_mock_proxy(credentials.clone()).await,
+            credentials: credentials.clone(),
+        };
+        let mut descriptor = connect_socks5(&proxy, "localhost", port).await.unwrap();
+        descriptor.send_data(b"onion", true);
+        assert_eq!(descriptor.read().await.unwrap().unwrap(), b"onion".to_vec());
+
+        let proxy = Socks5Proxy {
+            addr: start_mock_proxy(credentials).await,
+            credentials: Some(("mutiny".to_string(), "wrong".to_string())),
+        };
+        let res = connect_socks5(&proxy, "localhost", port).await;
+        assert!(matches!(res, Err(MutinyError::ConnectionFailed)));
+    }
+
+    #[tokio::test]
+    async fn test_socks5_proxy_unreachable() {
+        let test_name = "test_socks5_proxy_unreachable";
+        log!("{}", test_name);
+
+        // bind and drop a listener so nothing is listening on the port
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        drop(listener);
+
+        let proxy = Socks5Proxy {
+            addr,
+            credentials: None,
+        };
+        let res = connect_socks5(&proxy, "127.0.0.1", 9735).await;
+        assert!(matches!(res, Err(MutinyError::ConnectionFailed)));
+    }
+}
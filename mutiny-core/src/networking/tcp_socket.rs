@@ -4,9 +4,15 @@ use crate::utils;
 use lightning::ln::peer_handler;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 use std::{hash::Hash, io::Read};
 use std::{io::Write, net::TcpStream};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf};
 use tokio::sync::Mutex;
+use tokio::time;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls::{ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName};
+use tokio_rustls::TlsConnector;
 
 static ID_COUNTER: AtomicU64 = AtomicU64::new(0);
 
@@ -82,3 +88,211 @@ impl std::fmt::Debug for TcpSocketDescriptor {
         write!(f, "({})", self.id)
     }
 }
+
+type TlsRead = ReadHalf<TlsStream<tokio::net::TcpStream>>;
+type TlsWrite = WriteHalf<TlsStream<tokio::net::TcpStream>>;
+
+/// A TLS connection, for peers and relays that are only reachable over TLS.
+/// The stream is split so a pending read does not block writes.
+pub struct TlsSocketDescriptor {
+    read: Arc<Mutex<TlsRead>>,
+    write: Arc<Mutex<TlsWrite>>,
+    id: u64,
+}
+
+impl TlsSocketDescriptor {
+    pub fn new(stream: TlsStream<tokio::net::TcpStream>) -> Self {
+        let id = ID_COUNTER.fetch_add(1, Ordering::AcqRel);
+        let (read, write) = tokio::io::split(stream);
+        Self {
+            read: Arc::new(Mutex::new(read)),
+            write: Arc::new(Mutex::new(write)),
+            id,
+        }
+    }
+}
+
+/// Connects to `addr` over TLS, the certificate has to be valid for `domain`
+/// and is verified against the webpki root certificates.
+pub async fn connect_tls(addr: &str, domain: &str) -> Result<TlsSocketDescriptor, MutinyError> {
+    let mut roots = RootCertStore::empty();
+    roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+        OwnedTrustAnchor::from_subject_spki_name_constraints(
+            ta.subject,
+            ta.spki,
+            ta.name_constraints,
+        )
+    }));
+    let config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    connect_tls_with_config(addr, domain, Arc::new(config)).await
+}
+
+/// Like [`connect_tls`], with a custom TLS config such as other root certificates.
+pub async fn connect_tls_with_config(
+    addr: &str,
+    domain: &str,
+    config: Arc<ClientConfig>,
+) -> Result<TlsSocketDescriptor, MutinyError> {
+    let server_name =
+        ServerName::try_from(domain).map_err(|_| MutinyError::InvalidArgumentsError)?;
+
+    let stream = time::timeout(
+        Duration::from_secs(10),
+        tokio::net::TcpStream::connect(addr),
+    )
+    .await
+    .map_err(|_| MutinyError::ConnectionFailed)?
+    .map_err(|_| MutinyError::ConnectionFailed)?;
+
+    let stream = TlsConnector::from(config)
+        .connect(server_name, stream)
+        .await
+        .map_err(|_| MutinyError::ConnectionFailed)?;
+
+    Ok(TlsSocketDescriptor::new(stream))
+}
+
+impl ReadDescriptor for TlsSocketDescriptor {
+    async fn read(&self) -> Option<Result<Vec<u8>, MutinyError>> {
+        let mut buf = [0; 4096];
+        match self.read.lock().await.read(&mut buf).await {
+            // the peer closed the connection
+            Ok(0) => Some(Err(MutinyError::ConnectionFailed)),
+            Ok(n) => Some(Ok(buf[..n].to_vec())),
+            Err(_) => Some(Err(MutinyError::ConnectionFailed)),
+        }
+    }
+}
+
+unsafe impl Send for TlsSocketDescriptor {}
+unsafe impl Sync for TlsSocketDescriptor {}
+
+impl peer_handler::SocketDescriptor for TlsSocketDescriptor {
+    fn send_data(&mut self, data: &[u8], _resume_read: bool) -> usize {
+        let cloned_data = Vec::from(data);
+        let cloned_conn = self.write.clone();
+        // the stream is registered with the tokio runtime, so it is written from there
+        tokio::spawn(async move {
+            let mut write = cloned_conn.lock().await;
+            let _ = write.write_all(&cloned_data).await;
+        });
+        data.len()
+    }
+
+    fn disconnect_socket(&mut self) {
+        let cloned_conn = self.write.clone();
+        tokio::spawn(async move {
+            let _ = cloned_conn.lock().await.shutdown().await;
+        });
+    }
+}
+impl Clone for TlsSocketDescriptor {
+    fn clone(&self) -> Self {
+        Self {
+            read: Arc::clone(&self.read),
+            write: Arc::clone(&self.write),
+            id: self.id,
+        }
+    }
+}
+impl Eq for TlsSocketDescriptor {}
+impl PartialEq for TlsSocketDescriptor {
+    fn eq(&self, o: &Self) -> bool {
+        self.id == o.id
+    }
+}
+impl Hash for TlsSocketDescriptor {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl std::fmt::Debug for TlsSocketDescriptor {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "(tls {})", self.id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::*;
+    use lightning::ln::peer_handler::SocketDescriptor;
+    use tokio::net::TcpListener;
+    use tokio_rustls::rustls::{Certificate, PrivateKey, ServerConfig};
+    use tokio_rustls::TlsAcceptor;
+
+    /// Starts a TLS echo server for localhost, returns its address and certificate
+    async fn start_echo_server() -> (String, Certificate) {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let der = Certificate(cert.serialize_der().unwrap());
+        let key = PrivateKey(cert.serialize_private_key_der());
+        let config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(vec![der.clone()], key)
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = acceptor.accept(stream).await.unwrap();
+            let mut buf = [0; 1024];
+            loop {
+                match stream.read(&mut buf).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => stream.write_all(&buf[..n]).await.unwrap(),
+                }
+            }
+        });
+
+        (addr, der)
+    }
+
+    fn client_config(cert: &Certificate) -> Arc<ClientConfig> {
+        let mut roots = RootCertStore::empty();
+        roots.add(cert).unwrap();
+        let config = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        Arc::new(config)
+    }
+
+    #[tokio::test]
+    async fn test_tls_echo() {
+        let test_name = "test_tls_echo";
+        log!("{}", test_name);
+
+        let (addr, cert) = start_echo_server().await;
+        let mut descriptor = connect_tls_with_config(&addr, "localhost", client_config(&cert))
+            .await
+            .unwrap();
+
+        assert_eq!(descriptor.send_data(b"hello", true), 5);
+        let echoed = descriptor.read().await.unwrap().unwrap();
+        assert_eq!(echoed, b"hello".to_vec());
+
+        descriptor.disconnect_socket();
+        assert!(descriptor.read().await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tls_rejects_wrong_domain() {
+        let test_name = "test_tls_rejects_wrong_domain";
+        log!("{}", test_name);
+
+        let (addr, cert) = start_echo_server().await;
+        let res = connect_tls_with_config(&addr, "example.com", client_config(&cert)).await;
+        assert!(matches!(res, Err(MutinyError::ConnectionFailed)));
+
+        let res = connect_tls_with_config(&addr, "not a domain!", client_config(&cert)).await;
+        assert!(matches!(res, Err(MutinyError::InvalidArgumentsError)));
+    }
+}
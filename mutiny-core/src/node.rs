@@ -1,15 +1,25 @@
+use crate::congestion::CongestionBreaker;
+use crate::freshness::{DeferredClaims, StrictModeConfig};
 use crate::keymanager::PhantomKeysManager;
 use crate::labels::LabelStorage;
 use crate::ldkstorage::ChannelOpenParams;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::networking::dns::DnsResolver;
 use crate::nodemanager::ChannelClosure;
-use crate::scb::StaticChannelBackup;
+use crate::payment_privacy::{
+    PaymentPrivacy, PaymentPrivacyConfig, PrivacyEffect, KEYSEND_FINAL_CLTV_EXPIRY_DELTA,
+};
+use crate::scb::{
+    scb_sweep_status, ScbRestoreProgress, ScbSkippedChannel, ScbSweepFailure, ScbSweepResult,
+    ScbSweepStatus, StaticChannelBackup,
+};
 use crate::{
     background::process_events_async,
     chain::MutinyChain,
     error::{MutinyError, MutinyStorageError},
     event::{EventHandler, HTLCStatus, MillisatAmount, PaymentInfo},
     fees::MutinyFeeEstimator,
-    gossip::{get_all_peers, read_peer_info, save_peer_connection_info},
+    gossip::{get_all_peers, read_peer_info, save_peer_connection_info, set_peer_last_connected},
     keymanager::{create_keys_manager, pubkey_from_keys_manager},
     ldkstorage::{MutinyNodePersister, PhantomChannelManager},
     logging::MutinyLogger,
@@ -31,6 +41,7 @@ use bitcoin::hashes::{hex::ToHex, sha256::Hash as Sha256};
 use bitcoin::secp256k1::rand;
 use bitcoin::{hashes::Hash, secp256k1::PublicKey, BlockHash, Network, OutPoint};
 use core::time::Duration;
+use futures::{pin_mut, select, FutureExt};
 use lightning::chain::channelmonitor::ChannelMonitor;
 use lightning::util::ser::{ReadableArgs, Writeable};
 use lightning::{
@@ -51,7 +62,7 @@ use lightning::{
     log_debug, log_error, log_info, log_trace, log_warn,
     routing::{
         gossip,
-        gossip::NodeId,
+        gossip::{NodeId, ReadOnlyNetworkGraph},
         router::{DefaultRouter, PaymentParameters, RouteParameters},
         scoring::ProbabilisticScorer,
     },
@@ -75,9 +86,11 @@ use std::{
     },
 };
 
-const DEFAULT_PAYMENT_TIMEOUT: u64 = 30;
+pub(crate) const DEFAULT_PAYMENT_TIMEOUT: u64 = 30;
 const INITIAL_RECONNECTION_DELAY: u64 = 5;
 const MAX_RECONNECTION_DELAY: u64 = 60;
+/// How long the connection attempts to reach a payee no route was found to may take
+const DIRECT_CONNECT_TIMEOUT_MS: i32 = 3_000;
 
 pub(crate) type RapidGossipSync =
     lightning_rapid_gossip_sync::RapidGossipSync<Arc<NetworkGraph>, Arc<MutinyLogger>>;
@@ -148,15 +161,27 @@ pub(crate) struct Node<S: MutinyStorage> {
     pub channel_manager: Arc<PhantomChannelManager<S>>,
     pub chain_monitor: Arc<ChainMonitor<S>>,
     pub fee_estimator: Arc<MutinyFeeEstimator<S>>,
+    /// Broadcasts the commitments of channels swept from a backup
+    chain: Arc<MutinyChain<S>>,
     pub scb_message_handler: Arc<SCBMessageHandler>,
+    /// Claims deferred by strict mode, claimed after the next chain sync
+    pub(crate) deferred_claims: DeferredClaims,
+    payment_privacy: PaymentPrivacy,
+    network_graph: Arc<NetworkGraph>,
     network: Network,
     pub persister: Arc<MutinyNodePersister<S>>,
     wallet: Arc<OnChainWallet<S>>,
     logger: Arc<MutinyLogger>,
     pub(crate) lsp_client: Option<LspClient>,
+    /// Fields of the [`NodeIndex`] the node does not use itself, kept so they are
+    /// saved and backed up again
+    created_at: Option<u64>,
+    alias: Option<String>,
     stop: Arc<AtomicBool>,
     #[cfg(target_arch = "wasm32")]
     websocket_proxy_addr: String,
+    #[cfg(not(target_arch = "wasm32"))]
+    dns_resolver: DnsResolver,
 }
 
 impl<S: MutinyStorage> Node<S> {
@@ -176,8 +201,12 @@ impl<S: MutinyStorage> Node<S> {
         lsp_clients: &[LspClient],
         logger: Arc<MutinyLogger>,
         do_not_connect_peers: bool,
+        strict_mode: StrictModeConfig,
+        congestion: CongestionBreaker,
+        payment_privacy: PaymentPrivacyConfig,
         empty_state: bool,
         #[cfg(target_arch = "wasm32")] websocket_proxy_addr: String,
+        #[cfg(not(target_arch = "wasm32"))] dns_resolver: DnsResolver,
     ) -> Result<Self, MutinyError> {
         log_info!(logger, "initializing a new node: {uuid}");
 
@@ -233,6 +262,7 @@ impl<S: MutinyStorage> Node<S> {
 
         // init channel manager
         let mut read_channel_manager = if empty_state {
+            persister.reset_channel_manager()?;
             MutinyNodePersister::create_new_channel_manager(
                 network,
                 chain_monitor.clone(),
@@ -302,6 +332,8 @@ impl<S: MutinyStorage> Node<S> {
 
         // init peer manager
         let scb_message_handler = Arc::new(SCBMessageHandler::new());
+        scb_message_handler.load_stored_backups(&persister.storage, &pubkey)?;
+        scb_message_handler.load_backup_permissions(&persister.storage)?;
         let ln_msg_handler = MessageHandler {
             chan_handler: channel_manager.clone(),
             route_handler,
@@ -329,6 +361,8 @@ impl<S: MutinyStorage> Node<S> {
         let lsp_client_pubkey = lsp_client.clone().map(|lsp| lsp.pubkey);
 
         // init event handler
+        let deferred_claims = DeferredClaims::default();
+        let payment_privacy = PaymentPrivacy::new(payment_privacy);
         let event_handler = EventHandler::new(
             channel_manager.clone(),
             fee_estimator.clone(),
@@ -336,6 +370,10 @@ impl<S: MutinyStorage> Node<S> {
             keys_manager.clone(),
             persister.clone(),
             lsp_client_pubkey,
+            strict_mode,
+            congestion,
+            payment_privacy.clone(),
+            deferred_claims.clone(),
             logger.clone(),
         );
 
@@ -473,6 +511,8 @@ impl<S: MutinyStorage> Node<S> {
         if !do_not_connect_peers {
             #[cfg(target_arch = "wasm32")]
             let reconnection_proxy_addr = websocket_proxy_addr.clone();
+            #[cfg(not(target_arch = "wasm32"))]
+            let reconnection_dns_resolver = dns_resolver.clone();
 
             let reconnection_storage = persister.storage.clone();
             let reconnection_pubkey = pubkey;
@@ -481,6 +521,7 @@ impl<S: MutinyStorage> Node<S> {
             let reconnection_logger = logger.clone();
             let reconnection_uuid = uuid.clone();
             let reconnection_lsp_client = lsp_client.clone();
+            let reconnection_scb_message_handler = scb_message_handler.clone();
             let reconnection_stop = stop.clone();
             let reconnection_stopped_comp = stopped_components.clone();
             reconnection_stopped_comp.try_write()?.push(false);
@@ -490,11 +531,14 @@ impl<S: MutinyStorage> Node<S> {
                     reconnection_pubkey,
                     #[cfg(target_arch = "wasm32")]
                     reconnection_proxy_addr,
+                    #[cfg(not(target_arch = "wasm32"))]
+                    reconnection_dns_resolver,
                     reconnection_peer_man,
                     reconnection_fee,
                     &reconnection_logger,
                     reconnection_uuid,
                     &reconnection_lsp_client,
+                    reconnection_scb_message_handler,
                     reconnection_stop,
                     reconnection_stopped_comp,
                     network == Network::Regtest,
@@ -513,15 +557,23 @@ impl<S: MutinyStorage> Node<S> {
             channel_manager,
             chain_monitor,
             fee_estimator,
+            chain,
             scb_message_handler,
+            deferred_claims,
+            payment_privacy,
+            network_graph: gossip_sync.network_graph().clone(),
             network,
             persister,
             wallet,
             logger,
             lsp_client,
+            created_at: node_index.created_at,
+            alias: node_index.alias.clone(),
             stop,
             #[cfg(target_arch = "wasm32")]
             websocket_proxy_addr,
+            #[cfg(not(target_arch = "wasm32"))]
+            dns_resolver,
         })
     }
 
@@ -556,6 +608,8 @@ impl<S: MutinyStorage> Node<S> {
             child_index: self.child_index,
             lsp: self.lsp_client.clone().map(|l| l.url),
             archived: Some(false),
+            created_at: self.created_at,
+            alias: self.alias.clone(),
         }
     }
 
@@ -567,6 +621,8 @@ impl<S: MutinyStorage> Node<S> {
         let connect_res = connect_peer_if_necessary(
             #[cfg(target_arch = "wasm32")]
             &self.websocket_proxy_addr,
+            #[cfg(not(target_arch = "wasm32"))]
+            &self.dns_resolver,
             &peer_connection_info,
             self.logger.clone(),
             self.peer_manager.clone(),
@@ -614,6 +670,17 @@ impl<S: MutinyStorage> Node<S> {
                     }
                 }
 
+                if let Err(e) = set_peer_last_connected(
+                    &self.persister.storage,
+                    &node_id,
+                    utils::now().as_secs(),
+                ) {
+                    log_warn!(
+                        self.logger,
+                        "WARN: could not store peer last connected: {e}"
+                    );
+                }
+
                 Ok(())
             }
             Err(e) => Err(e),
@@ -779,6 +846,7 @@ impl<S: MutinyStorage> Node<S> {
             bolt11: Some(invoice.clone()),
             payee_pubkey: None,
             last_update,
+            external_settlement: None,
         };
         self.persister
             .persist_payment_info(&payment_hash, &payment_info, true)
@@ -817,6 +885,34 @@ impl<S: MutinyStorage> Node<S> {
         )
     }
 
+    /// Marks one of our invoices as paid out of band, the preimage has to match
+    /// the payment hash. No funds moved into our channels for it.
+    pub fn mark_invoice_settled_external(
+        &self,
+        payment_hash: &Sha256,
+        preimage: [u8; 32],
+        note: String,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        let payment_hash = PaymentHash(payment_hash.into_inner());
+        let payment_info =
+            self.persister
+                .settle_payment_externally(&payment_hash, preimage, note)?;
+        log_info!(
+            self.logger,
+            "marked invoice {} as settled externally",
+            payment_hash.0.to_hex()
+        );
+
+        let labels_map = self.persister.storage.get_invoice_labels()?;
+        let labels = payment_info
+            .bolt11
+            .as_ref()
+            .and_then(|inv| labels_map.get(inv).cloned())
+            .unwrap_or_default();
+
+        MutinyInvoice::from(payment_info, payment_hash, true, labels)
+    }
+
     pub fn list_invoices(&self) -> Result<Vec<MutinyInvoice>, MutinyError> {
         let mut inbound_invoices = self.list_payment_info_from_persisters(true)?;
         let mut outbound_invoices = self.list_payment_info_from_persisters(false)?;
@@ -901,12 +997,15 @@ impl<S: MutinyStorage> Node<S> {
 
     /// init_invoice_payment sends off the payment but does not wait for results
     /// use pay_invoice_with_timeout to wait for results
+    /// `self_payment` is set for invoices of our own nodes, which the payment
+    /// privacy settings may overpay. Also returns what those settings changed.
     pub async fn init_invoice_payment(
         &self,
         invoice: &Invoice,
         amt_sats: Option<u64>,
         labels: Vec<String>,
-    ) -> Result<PaymentHash, MutinyError> {
+        self_payment: bool,
+    ) -> Result<(PaymentHash, PrivacyEffect), MutinyError> {
         let payment_hash = PaymentHash(invoice.payment_hash().into_inner());
 
         if self
@@ -932,35 +1031,47 @@ impl<S: MutinyStorage> Node<S> {
             if self.stop.load(Ordering::Relaxed) {
                 return Err(MutinyError::NotRunning);
             }
-            if !self.peer_manager.get_peer_node_ids().is_empty() {
+            // without channels a connection would not help
+            if !self.peer_manager.get_peer_node_ids().is_empty()
+                || self.channel_manager.list_channels().is_empty()
+            {
                 break;
             }
             sleep(1_000).await;
         }
 
-        let (pay_result, amt_msat) = if invoice.amount_milli_satoshis().is_none() {
-            if amt_sats.is_none() {
-                return Err(MutinyError::InvoiceInvalid);
-            }
-            let amt_msats = amt_sats.unwrap() * 1_000;
-            (
-                pay_zero_value_invoice(
-                    invoice,
-                    amt_msats,
-                    Retry::Attempts(5),
-                    self.channel_manager.as_ref(),
-                ),
-                amt_msats,
-            )
-        } else {
-            if amt_sats.is_some() {
-                return Err(MutinyError::InvoiceInvalid);
+        let payee = invoice.recover_payee_pub_key();
+        let (mut pay_result, mut amt_msat, mut privacy) =
+            self.send_invoice_payment(invoice, amt_sats, &payee, self_payment)?;
+
+        // if no route was found, but not for lack of funds, try to reach the payee over
+        // a direct channel as a last resort and pay again if one became usable
+        if let Err(PaymentError::Sending(RetryableSendFailure::RouteNotFound)) = pay_result {
+            let hint_nodes = route_hint_nodes(invoice);
+            if matches!(
+                self.route_not_found_error(&payee, &hint_nodes, amt_msat),
+                MutinyError::DestinationUnreachable | MutinyError::RoutingFailed
+            ) && self
+                .connect_unreachable_destination(&payee, &hint_nodes)
+                .await
+            {
+                log_info!(
+                    self.logger,
+                    "retrying payment {} over a direct channel",
+                    payment_hash.0.to_hex()
+                );
+                (pay_result, amt_msat, privacy) =
+                    self.send_invoice_payment(invoice, amt_sats, &payee, self_payment)?;
             }
-            (
-                pay_invoice(invoice, Retry::Attempts(5), self.channel_manager.as_ref()),
-                invoice.amount_milli_satoshis().unwrap(),
-            )
-        };
+        }
+
+        if !privacy.is_empty() {
+            log_debug!(
+                self.logger,
+                "payment privacy for {}: {privacy:?}",
+                payment_hash.0.to_hex()
+            );
+        }
 
         if let Err(e) = self
             .persister
@@ -980,13 +1091,14 @@ impl<S: MutinyStorage> Node<S> {
             bolt11: Some(invoice.clone()),
             payee_pubkey: None,
             last_update,
+            external_settlement: None,
         };
 
         self.persister
             .persist_payment_info(&payment_hash, &payment_info, false)?;
 
         match pay_result {
-            Ok(_) => Ok(payment_hash),
+            Ok(_) => Ok((payment_hash, privacy)),
             Err(e) => {
                 log_error!(self.logger, "failed to make payment: {:?}", e);
                 // call list channels to see what our channels are
@@ -1004,29 +1116,290 @@ impl<S: MutinyStorage> Node<S> {
                 // If the payment failed because of a route not found, check if the amount was
                 // valid and return the correct error
                 if let PaymentError::Sending(RetryableSendFailure::RouteNotFound) = e {
-                    // If the amount was greater than our balance, return an InsufficientBalance error
-                    let ln_balance: u64 = current_channels.iter().map(|c| c.balance_msat).sum();
-                    if amt_msat > ln_balance {
-                        return Err(MutinyError::InsufficientBalance);
+                    let error =
+                        self.route_not_found_error(&payee, &route_hint_nodes(invoice), amt_msat);
+                    if let MutinyError::DestinationUnreachable = error {
+                        log_warn!(
+                            self.logger,
+                            "destination unreachable: no public channels and hinted peers not routable"
+                        );
                     }
+                    return Err(error);
+                }
 
-                    // If the amount was within our balance but we couldn't pay because of
-                    // the channel reserve, return a ReserveAmountError
-                    let reserved_amt: u64 = current_channels
-                        .iter()
-                        .flat_map(|c| c.unspendable_punishment_reserve)
-                        .sum();
-                    if ln_balance - reserved_amt < amt_msat {
-                        return Err(MutinyError::ReserveAmountError);
+                Err(MutinyError::RoutingFailed)
+            }
+        }
+    }
+
+    /// Sends the invoice payment, returning the amount sent and the payment privacy
+    /// measures that were applied to it.
+    fn send_invoice_payment(
+        &self,
+        invoice: &Invoice,
+        amt_sats: Option<u64>,
+        payee: &PublicKey,
+        self_payment: bool,
+    ) -> Result<(Result<PaymentId, PaymentError>, u64, PrivacyEffect), MutinyError> {
+        if self.payment_privacy.is_enabled() {
+            let amt_msat = match (invoice.amount_milli_satoshis(), amt_sats) {
+                (None, Some(amt_sats)) => amt_sats * 1_000,
+                (Some(amt_msat), None) => amt_msat,
+                _ => return Err(MutinyError::InvoiceInvalid),
+            };
+            let mut privacy = self.payment_privacy_effect(payee, amt_msat, self_payment);
+            Ok((
+                self.send_private_invoice_payment(invoice, amt_msat, &mut privacy),
+                amt_msat + privacy.jitter_msat,
+                privacy,
+            ))
+        } else if invoice.amount_milli_satoshis().is_none() {
+            if amt_sats.is_none() {
+                return Err(MutinyError::InvoiceInvalid);
+            }
+            let amt_msats = amt_sats.unwrap() * 1_000;
+            Ok((
+                pay_zero_value_invoice(
+                    invoice,
+                    amt_msats,
+                    Retry::Attempts(5),
+                    self.channel_manager.as_ref(),
+                ),
+                amt_msats,
+                PrivacyEffect::default(),
+            ))
+        } else {
+            if amt_sats.is_some() {
+                return Err(MutinyError::InvoiceInvalid);
+            }
+            Ok((
+                pay_invoice(invoice, Retry::Attempts(5), self.channel_manager.as_ref()),
+                invoice.amount_milli_satoshis().unwrap(),
+                PrivacyEffect::default(),
+            ))
+        }
+    }
+
+    /// The error for a payment of the given amount no route was found for,
+    /// see [`route_not_found_error`].
+    fn route_not_found_error(
+        &self,
+        payee: &PublicKey,
+        hint_nodes: &[PublicKey],
+        amt_msat: u64,
+    ) -> MutinyError {
+        let channels = self.channel_manager.list_channels();
+        let ln_balance: u64 = channels.iter().map(|c| c.balance_msat).sum();
+        let reserved_amt: u64 = channels
+            .iter()
+            .flat_map(|c| c.unspendable_punishment_reserve)
+            .sum();
+        let direct_peers: Vec<PublicKey> =
+            channels.iter().map(|c| c.counterparty.node_id).collect();
+        let reachable = is_destination_reachable(
+            payee,
+            hint_nodes,
+            &self.network_graph.read_only(),
+            &direct_peers,
+        );
+
+        route_not_found_error(amt_msat, ln_balance, reserved_amt, reachable)
+    }
+
+    /// Picks the payment privacy measures for a payment of the given amount
+    fn payment_privacy_effect(
+        &self,
+        destination: &PublicKey,
+        amt_msat: u64,
+        self_payment: bool,
+    ) -> PrivacyEffect {
+        if !self.payment_privacy.is_enabled() {
+            return PrivacyEffect::default();
+        }
+
+        let jitter_msat =
+            self.payment_privacy
+                .jitter_msat(amt_msat, self_payment, rand::random::<u64>());
+        let channels: Vec<(u64, u64)> = self
+            .channel_manager
+            .list_usable_channels()
+            .iter()
+            .filter_map(|c| Some((c.get_outbound_payment_scid()?, c.outbound_capacity_msat)))
+            .collect();
+
+        PrivacyEffect {
+            cltv_offset: self.payment_privacy.cltv_offset(rand::random::<u32>()),
+            jitter_msat,
+            avoided_first_hop: self.payment_privacy.first_hop_to_avoid(
+                destination,
+                &channels,
+                amt_msat + jitter_msat,
+            ),
+        }
+    }
+
+    /// Pays the invoice with the payment privacy measures applied, instead of
+    /// the route parameters LDK would derive from the invoice
+    fn send_private_invoice_payment(
+        &self,
+        invoice: &Invoice,
+        amt_msat: u64,
+        privacy: &mut PrivacyEffect,
+    ) -> Result<PaymentId, PaymentError> {
+        let payment_hash = PaymentHash(invoice.payment_hash().into_inner());
+        let payment_id = PaymentId(payment_hash.0);
+        let payee = invoice.recover_payee_pub_key();
+
+        let final_cltv_expiry_delta =
+            invoice.min_final_cltv_expiry_delta() as u32 + privacy.cltv_offset;
+        let expiry_time = (invoice.duration_since_epoch() + invoice.expiry_time()).as_secs();
+        let mut payment_params = PaymentParameters::from_node_id(payee, final_cltv_expiry_delta)
+            .with_expiry_time(expiry_time)
+            .with_route_hints(invoice.route_hints())
+            .map_err(|_| PaymentError::Invoice("Invalid route hints"))?;
+        if let Some(features) = invoice.features() {
+            payment_params = payment_params
+                .with_bolt11_features(features.clone())
+                .map_err(|_| PaymentError::Invoice("Invalid features"))?;
+        }
+        let route_params = RouteParameters {
+            final_value_msat: amt_msat + privacy.jitter_msat,
+            payment_params,
+        };
+
+        let mut recipient_onion = RecipientOnionFields::secret_only(*invoice.payment_secret());
+        recipient_onion.payment_metadata = invoice.payment_metadata().cloned();
+
+        self.send_avoiding_first_hop(route_params, privacy, |route_params| {
+            self.channel_manager.send_payment(
+                payment_hash,
+                recipient_onion.clone(),
+                payment_id,
+                route_params,
+                Retry::Attempts(5),
+            )
+        })
+        .map_err(PaymentError::Sending)?;
+        self.payment_privacy.payment_sent(payment_id, payee);
+
+        Ok(payment_id)
+    }
+
+    /// Sends a payment without the first hop the payment privacy settings avoid,
+    /// and again with it if no route could be found that way.
+    fn send_avoiding_first_hop<T>(
+        &self,
+        route_params: RouteParameters,
+        privacy: &mut PrivacyEffect,
+        send: impl Fn(RouteParameters) -> Result<T, RetryableSendFailure>,
+    ) -> Result<T, RetryableSendFailure> {
+        if let Some(scid) = privacy.avoided_first_hop {
+            let mut avoiding = route_params.clone();
+            avoiding
+                .payment_params
+                .previously_failed_channels
+                .push(scid);
+            match send(avoiding) {
+                Err(RetryableSendFailure::RouteNotFound) => {
+                    log_debug!(
+                        self.logger,
+                        "no route avoiding first hop {scid}, trying all first hops"
+                    );
+                    privacy.avoided_first_hop = None;
+                }
+                res => return res,
+            }
+        }
+
+        send(route_params)
+    }
+
+    /// Last resort for a payment no route was found for: reconnects disconnected direct
+    /// channels with the payee or its hinted nodes, and connects to a payee we have no
+    /// channel with, using the connection info from our peer storage. Returns true if a
+    /// direct channel with one of them became usable, so the payment is worth retrying.
+    /// Gives up after [`DIRECT_CONNECT_TIMEOUT_MS`].
+    async fn connect_unreachable_destination(
+        &self,
+        payee: &PublicKey,
+        hint_nodes: &[PublicKey],
+    ) -> bool {
+        let channels: Vec<(PublicKey, bool)> = self
+            .channel_manager
+            .list_channels()
+            .iter()
+            .map(|c| (c.counterparty.node_id, c.is_usable))
+            .collect();
+        let candidates = direct_connect_candidates(payee, hint_nodes, &channels);
+        if candidates.is_empty() {
+            return false;
+        }
+
+        let connect = async {
+            for node_id in candidates.iter() {
+                let connection_string =
+                    match read_peer_info(&self.persister.storage, &NodeId::from_pubkey(node_id)) {
+                        Ok(Some(info)) => info.connection_string,
+                        _ => None,
+                    };
+                let Some(connection_string) = connection_string else {
+                    log_debug!(self.logger, "no connection info to reach {node_id}");
+                    continue;
+                };
+                let peer_connection_info = match PubkeyConnectionInfo::new(&connection_string) {
+                    Ok(p) => p,
+                    Err(e) => {
+                        log_error!(self.logger, "could not parse connection info: {e}");
+                        continue;
                     }
+                };
+
+                log_info!(
+                    self.logger,
+                    "connecting to {node_id} to reach payee {payee}"
+                );
+                if let Err(e) = self.connect_peer(peer_connection_info, None).await {
+                    log_warn!(self.logger, "could not connect to {node_id}: {e}");
                 }
+            }
 
-                Err(MutinyError::RoutingFailed)
+            // wait for a direct channel to be reestablished
+            loop {
+                if self.stop.load(Ordering::Relaxed) {
+                    return false;
+                }
+                let usable = self
+                    .channel_manager
+                    .list_usable_channels()
+                    .iter()
+                    .any(|c| candidates.contains(&c.counterparty.node_id));
+                if usable {
+                    return true;
+                }
+                if !channels
+                    .iter()
+                    .any(|(node_id, _)| candidates.contains(node_id))
+                {
+                    // only connected to a payee we have no channel with
+                    return false;
+                }
+                sleep(250).await;
+            }
+        }
+        .fuse();
+        let timeout = Box::pin(sleep(DIRECT_CONNECT_TIMEOUT_MS)).fuse();
+        pin_mut!(connect);
+        pin_mut!(timeout);
+        select! {
+            usable = connect => usable,
+            _ = timeout => {
+                log_debug!(self.logger, "timed out trying to reach payee {payee}");
+                false
             }
         }
     }
 
-    async fn await_payment(
+    pub(crate) async fn await_payment(
         &self,
         payment_hash: PaymentHash,
         timeout: u64,
@@ -1065,10 +1438,11 @@ impl<S: MutinyStorage> Node<S> {
         amt_sats: Option<u64>,
         timeout_secs: Option<u64>,
         labels: Vec<String>,
+        self_payment: bool,
     ) -> Result<MutinyInvoice, MutinyError> {
         // initiate payment
-        let payment_hash = self
-            .init_invoice_payment(invoice, amt_sats, labels.clone())
+        let (payment_hash, _) = self
+            .init_invoice_payment(invoice, amt_sats, labels.clone(), self_payment)
             .await?;
         let timeout: u64 = timeout_secs.unwrap_or(DEFAULT_PAYMENT_TIMEOUT);
 
@@ -1093,20 +1467,31 @@ impl<S: MutinyStorage> Node<S> {
 
         let amt_msats = amt_sats * 1000;
 
+        let mut privacy = self.payment_privacy_effect(&to_node, amt_msats, false);
+        let final_cltv_expiry_delta = KEYSEND_FINAL_CLTV_EXPIRY_DELTA + privacy.cltv_offset;
+
         // TODO retry with allow_mpp false just in case recipient does not support
-        let payment_params = PaymentParameters::for_keysend(to_node, 40, true);
+        let payment_params = PaymentParameters::for_keysend(to_node, final_cltv_expiry_delta, true);
         let route_params: RouteParameters = RouteParameters {
             final_value_msat: amt_msats,
             payment_params,
         };
 
-        let pay_result = self.channel_manager.send_spontaneous_payment_with_retry(
-            Some(preimage),
-            RecipientOnionFields::spontaneous_empty(),
-            payment_id,
-            route_params,
-            Retry::Attempts(5),
-        );
+        let pay_result = self.send_avoiding_first_hop(route_params, &mut privacy, |route_params| {
+            self.channel_manager.send_spontaneous_payment_with_retry(
+                Some(preimage),
+                RecipientOnionFields::spontaneous_empty(),
+                payment_id,
+                route_params,
+                Retry::Attempts(5),
+            )
+        });
+        if pay_result.is_ok() {
+            self.payment_privacy.payment_sent(payment_id, to_node);
+        }
+        if !privacy.is_empty() {
+            log_debug!(self.logger, "payment privacy for keysend: {privacy:?}");
+        }
 
         let payment_hash = PaymentHash(Sha256::hash(&preimage.0).into_inner());
 
@@ -1120,6 +1505,7 @@ impl<S: MutinyStorage> Node<S> {
             bolt11: None,
             payee_pubkey: Some(to_node),
             last_update,
+            external_settlement: None,
         };
 
         self.persister
@@ -1156,7 +1542,7 @@ impl<S: MutinyStorage> Node<S> {
         self.await_payment(payment_hash, timeout, labels).await
     }
 
-    async fn await_chan_funding_tx(
+    pub(crate) async fn await_chan_funding_tx(
         &self,
         user_channel_id: u128,
         pubkey: &PublicKey,
@@ -1410,41 +1796,169 @@ impl<S: MutinyStorage> Node<S> {
         Ok(StaticChannelBackup { monitors })
     }
 
+    /// The update ids of the channel monitors of the node.
+    fn monitor_update_ids(&self) -> HashMap<OutPoint, u64> {
+        self.chain_monitor
+            .list_monitors()
+            .into_iter()
+            .filter_map(|outpoint| {
+                let monitor = self.chain_monitor.get_monitor(outpoint).ok()?;
+                Some((
+                    outpoint.into_bitcoin_outpoint(),
+                    monitor.get_latest_update_id(),
+                ))
+            })
+            .collect()
+    }
+
+    /// Recovers the channels of the backup. Channels the node has a newer monitor
+    /// for are skipped unless `force`, they are returned.
     pub async fn recover_from_static_channel_backup(
         &self,
-        scb: StaticChannelBackup,
-        peer_connections: &HashMap<PublicKey, String>,
-    ) -> Result<(), MutinyError> {
-        for (outpoint, monitor_bytes) in scb.monitors {
+        mut scb: StaticChannelBackup,
+        peer_connections: &HashMap<PublicKey, Vec<String>>,
+        peer_last_connected: &HashMap<PublicKey, u64>,
+        force: bool,
+        on_progress: &dyn Fn(ScbRestoreProgress),
+    ) -> Result<Vec<ScbSkippedChannel>, MutinyError> {
+        let skipped = scb.remove_outdated(&self.monitor_update_ids(), force);
+        for channel in skipped.iter() {
+            log_warn!(
+                self.logger,
+                "not restoring {}, we have a newer monitor with update id {}",
+                channel.funding_txo,
+                channel.stored_update_id
+            );
+        }
+
+        let mut reconnected = vec![];
+        for (outpoint, monitor) in self.read_backup_monitors(scb, peer_last_connected) {
+            self.restore_monitor(
+                outpoint,
+                monitor?,
+                peer_connections,
+                &mut reconnected,
+                on_progress,
+            )
+            .await?;
+        }
+
+        // fire off all the send events
+        if self.scb_message_handler.has_pending_messages() {
+            self.peer_manager.process_events();
+        }
+
+        Ok(skipped)
+    }
+
+    /// Recovers the channels like [`Node::recover_from_static_channel_backup`].
+    /// Channels the node has a newer monitor for are skipped and reported as
+    /// [`ScbSweepStatus::NewerLocalState`]. Channels whose peer can not be reached
+    /// are left to the queued close request, unless `broadcast_unreachable` is set
+    /// and the backup is not stale, then our latest commitment from the backup is
+    /// broadcast instead. Returns the sweep status of each channel.
+    pub async fn sweep_from_static_channel_backup(
+        &self,
+        mut scb: StaticChannelBackup,
+        peer_connections: &HashMap<PublicKey, Vec<String>>,
+        peer_last_connected: &HashMap<PublicKey, u64>,
+        broadcast_unreachable: bool,
+        is_stale: bool,
+    ) -> Vec<ScbSweepResult> {
+        let skipped = scb.remove_outdated(&self.monitor_update_ids(), false);
+        let mut results = Vec::with_capacity(scb.monitors.len() + skipped.len());
+        for channel in skipped {
+            log_warn!(
+                self.logger,
+                "not sweeping {}, we have a newer monitor with update id {}",
+                channel.funding_txo,
+                channel.stored_update_id
+            );
+            let ln_outpoint = lightning::chain::transaction::OutPoint {
+                txid: channel.funding_txo.txid,
+                index: channel.funding_txo.vout as u16,
+            };
+            let peer = self
+                .chain_monitor
+                .get_monitor(ln_outpoint)
+                .ok()
+                .and_then(|monitor| monitor.get_counterparty_node_id());
+            results.push(ScbSweepResult {
+                funding_txo: channel.funding_txo,
+                peer,
+                status: ScbSweepStatus::NewerLocalState(channel),
+            });
+        }
+
+        let mut reconnected = vec![];
+        for (outpoint, monitor) in self.read_backup_monitors(scb, peer_last_connected) {
+            let restored = match monitor {
+                Ok(monitor) => {
+                    self.restore_monitor(
+                        outpoint,
+                        monitor,
+                        peer_connections,
+                        &mut reconnected,
+                        &|_| {},
+                    )
+                    .await
+                }
+                Err(e) => Err(e),
+            };
             let ln_outpoint = lightning::chain::transaction::OutPoint {
                 txid: outpoint.txid,
                 index: outpoint.vout as u16,
             };
+            let restored = match restored {
+                Ok(peer) => self
+                    .chain_monitor
+                    .get_monitor(ln_outpoint)
+                    .ok()
+                    .map(|monitor| (peer, monitor)),
+                Err(e) => {
+                    log_warn!(
+                        self.logger,
+                        "could not restore the monitor of {outpoint}: {e}"
+                    );
+                    None
+                }
+            };
+            let Some((peer, monitor)) = restored else {
+                results.push(ScbSweepResult {
+                    funding_txo: outpoint,
+                    peer: None,
+                    status: ScbSweepStatus::Failed(ScbSweepFailure::MonitorUnreadable),
+                });
+                continue;
+            };
 
-            let reader = &mut lightning::io::Cursor::new(&monitor_bytes);
-            let (_, monitor) = <(BlockHash, ChannelMonitor<InMemorySigner>)>::read(
-                reader,
-                (self.keys_manager.as_ref(), self.keys_manager.as_ref()),
-            )?;
-
-            // unwrap is safe for ldk > 0.0.110
-            let node_id = monitor
-                .get_counterparty_node_id()
-                .expect("failed to get node id");
-
-            // watch the channel in the case peer tries to cheat us
-            self.chain_monitor.watch_channel(ln_outpoint, monitor);
+            if !self.peer_manager.get_peer_node_ids().contains(&peer) {
+                if let Some(status) = unreachable_sweep_status(broadcast_unreachable, is_stale) {
+                    results.push(ScbSweepResult {
+                        funding_txo: outpoint,
+                        peer: Some(peer),
+                        status,
+                    });
+                    continue;
+                }
 
-            // connect to peer if we have a connection string
-            if let Some(connection_string) = peer_connections.get(&node_id) {
-                let connect = PubkeyConnectionInfo::new(connection_string)
-                    .expect("invalid connection string");
-                self.connect_peer(connect, None).await?;
+                // we close the channel ourselves, the peer must not be asked to as well
+                log_info!(
+                    self.logger,
+                    "peer {peer} is unreachable, broadcasting our commitment for {outpoint}"
+                );
+                self.scb_message_handler
+                    .cancel_channel_close(&peer, &ln_outpoint.to_channel_id());
+                monitor.broadcast_latest_holder_commitment_txn(&self.chain, &self.logger);
             }
 
-            // then ask peer to force close the channel
-            self.scb_message_handler
-                .request_channel_close(node_id, ln_outpoint.to_channel_id());
+            let tip_height = self.channel_manager.current_best_block().height();
+            let balances = monitor.get_claimable_balances();
+            results.push(ScbSweepResult {
+                funding_txo: outpoint,
+                peer: Some(peer),
+                status: scb_sweep_status(outpoint, &balances, tip_height),
+            });
         }
 
         // fire off all the send events
@@ -1452,7 +1966,111 @@ impl<S: MutinyStorage> Node<S> {
             self.peer_manager.process_events();
         }
 
-        Ok(())
+        results
+    }
+
+    /// Reads the channel monitors of a backup, the ones whose peer we connected to
+    /// most recently first, so the peers that are still reachable are tried first.
+    fn read_backup_monitors(
+        &self,
+        scb: StaticChannelBackup,
+        peer_last_connected: &HashMap<PublicKey, u64>,
+    ) -> Vec<(
+        OutPoint,
+        Result<ChannelMonitor<InMemorySigner>, MutinyError>,
+    )> {
+        let mut monitors: Vec<_> = scb
+            .monitors
+            .into_iter()
+            .map(|(outpoint, monitor_bytes)| {
+                let reader = &mut lightning::io::Cursor::new(&monitor_bytes);
+                let monitor = <(BlockHash, ChannelMonitor<InMemorySigner>)>::read(
+                    reader,
+                    (self.keys_manager.as_ref(), self.keys_manager.as_ref()),
+                )
+                .map(|(_, monitor)| monitor)
+                .map_err(MutinyError::from);
+                (outpoint, monitor)
+            })
+            .collect();
+
+        // peers we never connected to, or that are unknown, go last
+        monitors.sort_by_key(|(_, monitor)| {
+            let last_connected = monitor
+                .as_ref()
+                .ok()
+                .and_then(|m| m.get_counterparty_node_id())
+                .and_then(|node_id| peer_last_connected.get(&node_id).copied());
+            std::cmp::Reverse(last_connected)
+        });
+        monitors
+    }
+
+    /// Watches the channel monitor from a backup again, reconnects to its peer and
+    /// asks it to force close. Returns the peer of the channel.
+    async fn restore_monitor(
+        &self,
+        outpoint: OutPoint,
+        monitor: ChannelMonitor<InMemorySigner>,
+        peer_connections: &HashMap<PublicKey, Vec<String>>,
+        reconnected: &mut Vec<PublicKey>,
+        on_progress: &dyn Fn(ScbRestoreProgress),
+    ) -> Result<PublicKey, MutinyError> {
+        let ln_outpoint = lightning::chain::transaction::OutPoint {
+            txid: outpoint.txid,
+            index: outpoint.vout as u16,
+        };
+
+        // unwrap is safe for ldk > 0.0.110
+        let node_id = monitor
+            .get_counterparty_node_id()
+            .expect("failed to get node id");
+
+        // watch the channel in the case peer tries to cheat us
+        self.chain_monitor.watch_channel(ln_outpoint, monitor);
+        on_progress(ScbRestoreProgress::MonitorLoaded(outpoint));
+
+        // connect to peer, trying each of its connection strings in order
+        let connection_strings = peer_connections.get(&node_id).cloned();
+        for connection_string in connection_strings.unwrap_or_default() {
+            let connect = match PubkeyConnectionInfo::new(&connection_string) {
+                Ok(connect) => connect,
+                Err(e) => {
+                    log_warn!(self.logger, "invalid connection string for {node_id}: {e}");
+                    continue;
+                }
+            };
+            match self.connect_peer(connect, None).await {
+                Ok(_) => {
+                    // peers with multiple channels are only reported once
+                    if !reconnected.contains(&node_id) {
+                        reconnected.push(node_id);
+                        on_progress(ScbRestoreProgress::PeerReconnected(node_id));
+                    }
+                    break;
+                }
+                Err(e) => log_warn!(
+                    self.logger,
+                    "could not connect to peer {node_id} at {connection_string}: {e}"
+                ),
+            }
+        }
+
+        // then ask peer to force close the channel, if the peer is not
+        // connected the request is sent once the peer reconnects
+        let channel_id = ln_outpoint.to_channel_id();
+        if self.peer_manager.get_peer_node_ids().contains(&node_id) {
+            self.scb_message_handler
+                .request_channel_close(node_id, channel_id);
+        } else {
+            self.scb_message_handler.queue_channel_close(
+                node_id,
+                channel_id,
+                utils::now().as_secs(),
+            )?;
+        }
+
+        Ok(node_id)
     }
 }
 
@@ -1461,11 +2079,13 @@ async fn start_reconnection_handling<S: MutinyStorage>(
     storage: &impl MutinyStorage,
     node_pubkey: PublicKey,
     #[cfg(target_arch = "wasm32")] websocket_proxy_addr: String,
+    #[cfg(not(target_arch = "wasm32"))] dns_resolver: DnsResolver,
     peer_man: Arc<dyn PeerManager>,
     fee_estimator: Arc<MutinyFeeEstimator<S>>,
     logger: &Arc<MutinyLogger>,
     uuid: String,
     lsp_client: &Option<LspClient>,
+    scb_message_handler: Arc<SCBMessageHandler>,
     stop: Arc<AtomicBool>,
     stopped_components: Arc<RwLock<Vec<bool>>>,
     skip_fee_estimates: bool,
@@ -1492,6 +2112,8 @@ async fn start_reconnection_handling<S: MutinyStorage>(
     // Attempt initial connections first in the background
     #[cfg(target_arch = "wasm32")]
     let websocket_proxy_addr_copy_proxy = websocket_proxy_addr.clone();
+    #[cfg(not(target_arch = "wasm32"))]
+    let dns_resolver_copy_proxy = dns_resolver.clone();
 
     let proxy_logger = logger.clone();
     let peer_man_proxy = peer_man.clone();
@@ -1508,6 +2130,8 @@ async fn start_reconnection_handling<S: MutinyStorage>(
             let connect_res = connect_peer_if_necessary(
                 #[cfg(target_arch = "wasm32")]
                 &websocket_proxy_addr_copy_proxy,
+                #[cfg(not(target_arch = "wasm32"))]
+                &dns_resolver_copy_proxy,
                 &PubkeyConnectionInfo::new(lsp.connection_string.as_str()).unwrap(),
                 proxy_logger.clone(),
                 peer_man_proxy.clone(),
@@ -1515,7 +2139,7 @@ async fn start_reconnection_handling<S: MutinyStorage>(
                 stop_copy.clone(),
             )
             .await;
-            match connect_res {
+            match connect_res.as_ref() {
                 Ok(_) => {
                     log_trace!(proxy_logger, "auto connected lsp: {node_id}");
                 }
@@ -1533,6 +2157,14 @@ async fn start_reconnection_handling<S: MutinyStorage>(
             ) {
                 log_error!(proxy_logger, "could not save connection to lsp: {e}");
             }
+
+            if connect_res.is_ok() {
+                if let Err(e) =
+                    set_peer_last_connected(&storage_copy, &node_id, utils::now().as_secs())
+                {
+                    log_error!(proxy_logger, "could not save last connection to lsp: {e}");
+                }
+            }
         };
     });
 
@@ -1610,6 +2242,8 @@ async fn start_reconnection_handling<S: MutinyStorage>(
                 let connect_res = connect_peer_if_necessary(
                     #[cfg(target_arch = "wasm32")]
                     &websocket_proxy_addr,
+                    #[cfg(not(target_arch = "wasm32"))]
+                    &dns_resolver,
                     &peer_connection_info,
                     connect_logger.clone(),
                     connect_peer_man.clone(),
@@ -1622,6 +2256,13 @@ async fn start_reconnection_handling<S: MutinyStorage>(
                         log_trace!(connect_logger, "auto connected peer: {pubkey}");
                         // reset backoff time to initial value if connection is successful
                         backoff_entry.0 = INITIAL_RECONNECTION_DELAY;
+                        if let Err(e) = set_peer_last_connected(
+                            &connect_storage,
+                            &pubkey,
+                            utils::now().as_secs(),
+                        ) {
+                            log_warn!(connect_logger, "could not store peer last connected: {e}");
+                        }
                     }
                     Err(e) => {
                         log_warn!(connect_logger, "could not auto connect peer: {e}");
@@ -1630,6 +2271,30 @@ async fn start_reconnection_handling<S: MutinyStorage>(
                     }
                 }
             }
+
+            // send the messages that were queued while their peer was disconnected
+            if scb_message_handler.has_queued_messages() {
+                let connected = connect_peer_man.get_peer_node_ids();
+                let expired =
+                    scb_message_handler.flush_queued_messages(&connected, utils::now().as_secs());
+                for msg in expired {
+                    log_warn!(
+                        connect_logger,
+                        "dropped queued channel close for {}, peer did not reconnect in time",
+                        msg.node_id
+                    );
+                }
+                if scb_message_handler.has_pending_messages() {
+                    connect_peer_man.process_events();
+                }
+            }
+
+            // persist the backups our peers stored with us
+            if let Err(e) =
+                scb_message_handler.persist_stored_backups(&connect_storage, &node_pubkey)
+            {
+                log_error!(connect_logger, "could not persist peer backups: {e}");
+            }
         }
     });
 }
@@ -1661,6 +2326,113 @@ pub(crate) fn create_peer_manager<S: MutinyStorage>(
     )
 }
 
+/// Returns true if we have a direct channel with the payee that is currently not usable,
+/// given a list of our channels as (counterparty, is_usable) pairs.
+pub(crate) fn has_unusable_direct_channel(
+    payee: &PublicKey,
+    channels: &[(PublicKey, bool)],
+) -> bool {
+    let mut direct = channels
+        .iter()
+        .filter(|(node_id, _)| node_id == payee)
+        .peekable();
+    direct.peek().is_some() && direct.all(|(_, usable)| !usable)
+}
+
+/// The status of a swept channel whose peer can not be reached, or None if our
+/// commitment from the backup should be broadcast. That is only done when the caller
+/// opted in and no newer backup exists, since a revoked commitment loses the channel
+/// funds to a penalty.
+pub(crate) fn unreachable_sweep_status(
+    broadcast_unreachable: bool,
+    is_stale: bool,
+) -> Option<ScbSweepStatus> {
+    match (broadcast_unreachable, is_stale) {
+        (false, _) => Some(ScbSweepStatus::PeerUnreachable),
+        (true, true) => Some(ScbSweepStatus::Failed(ScbSweepFailure::StaleBackup)),
+        (true, false) => None,
+    }
+}
+
+/// The peers to connect to when no route to the payee was found: the payee and hinted
+/// nodes we have a disconnected direct channel with first, then the payee itself if we
+/// have no channel with it.
+pub(crate) fn direct_connect_candidates(
+    payee: &PublicKey,
+    hint_nodes: &[PublicKey],
+    channels: &[(PublicKey, bool)],
+) -> Vec<PublicKey> {
+    let mut candidates: Vec<PublicKey> = Vec::new();
+    for node_id in std::iter::once(payee).chain(hint_nodes) {
+        if has_unusable_direct_channel(node_id, channels) && !candidates.contains(node_id) {
+            candidates.push(*node_id);
+        }
+    }
+    if !channels.iter().any(|(node_id, _)| node_id == payee) {
+        candidates.push(*payee);
+    }
+    candidates
+}
+
+/// The source nodes of the route hints of the invoice.
+fn route_hint_nodes(invoice: &Invoice) -> Vec<PublicKey> {
+    invoice
+        .route_hints()
+        .iter()
+        .flat_map(|hint| hint.0.iter().map(|hop| hop.src_node_id))
+        .collect()
+}
+
+/// Explains why no route was found for a payment of the given amount: the lightning
+/// balance or what is left of it after the channel reserves is too low, or the payee
+/// has no public channels and none of the hinted nodes can be routed to.
+pub(crate) fn route_not_found_error(
+    amt_msat: u64,
+    ln_balance: u64,
+    reserved_amt: u64,
+    destination_reachable: bool,
+) -> MutinyError {
+    // If the amount was greater than our balance, return an InsufficientBalance error
+    if amt_msat > ln_balance {
+        return MutinyError::InsufficientBalance {
+            needed_sats: amt_msat / 1_000,
+            available_sats: ln_balance / 1_000,
+        };
+    }
+
+    // If the amount was within our balance but we couldn't pay because of
+    // the channel reserve, return a ReserveAmountError
+    if ln_balance - reserved_amt < amt_msat {
+        return MutinyError::ReserveAmountError;
+    }
+
+    // If the payee has no public channels and none of the route hints
+    // point at a node we can route to, say so explicitly
+    if !destination_reachable {
+        return MutinyError::DestinationUnreachable;
+    }
+
+    MutinyError::RoutingFailed
+}
+
+/// Checks if there is any plausible way to reach the payee: a direct channel, public
+/// channels in the network graph, or a route hint whose source node we can route to.
+pub(crate) fn is_destination_reachable(
+    payee: &PublicKey,
+    hint_nodes: &[PublicKey],
+    graph: &ReadOnlyNetworkGraph,
+    direct_peers: &[PublicKey],
+) -> bool {
+    let routable = |pk: &PublicKey| {
+        direct_peers.contains(pk)
+            || graph
+                .node(&NodeId::from_pubkey(pk))
+                .is_some_and(|n| !n.channels.is_empty())
+    };
+
+    routable(payee) || hint_nodes.iter().any(routable)
+}
+
 pub(crate) fn parse_peer_info(
     peer_pubkey_and_ip_addr: &str,
 ) -> Result<(PublicKey, String), MutinyError> {
@@ -1722,7 +2494,15 @@ mod tests {
     use bitcoin::secp256k1::PublicKey;
     use std::str::FromStr;
 
-    use crate::node::parse_peer_info;
+    use crate::logging::MutinyLogger;
+    use crate::node::{
+        direct_connect_candidates, has_unusable_direct_channel, is_destination_reachable,
+        parse_peer_info, route_not_found_error, unreachable_sweep_status, NetworkGraph,
+    };
+    use crate::scb::{ScbSweepFailure, ScbSweepStatus};
+    use crate::MutinyError;
+    use bitcoin::Network;
+    use std::sync::Arc;
 
     use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
 
@@ -1760,4 +2540,149 @@ mod tests {
         assert_eq!(pub_key, peer_pubkey);
         assert_eq!(format!("{addr}:{port}"), peer_addr);
     }
+
+    #[test]
+    async fn test_direct_channel_reconnect() {
+        log!("test direct channel reconnect");
+
+        let payee = PublicKey::from_str(
+            "0218845781f631c48f1c9709e23092067d06837f30aa0cd0544ac887fe91ddd166",
+        )
+        .unwrap();
+        let other = PublicKey::from_str(
+            "02cae09cf2c8842ace44068a5bf3117a494ebbf69a99e79712483c36f97cdb7b54",
+        )
+        .unwrap();
+
+        // no direct channel, nothing to reconnect
+        assert!(!has_unusable_direct_channel(&payee, &[]));
+        assert!(!has_unusable_direct_channel(&payee, &[(other, false)]));
+
+        // direct channel that is disconnected should be reconnected
+        assert!(has_unusable_direct_channel(
+            &payee,
+            &[(other, true), (payee, false)]
+        ));
+
+        // if any direct channel is usable, no need to reconnect
+        assert!(!has_unusable_direct_channel(
+            &payee,
+            &[(payee, false), (payee, true)]
+        ));
+    }
+
+    #[test]
+    async fn test_destination_reachable() {
+        log!("test destination reachable");
+
+        let payee = PublicKey::from_str(
+            "0218845781f631c48f1c9709e23092067d06837f30aa0cd0544ac887fe91ddd166",
+        )
+        .unwrap();
+        let hint = PublicKey::from_str(
+            "02cae09cf2c8842ace44068a5bf3117a494ebbf69a99e79712483c36f97cdb7b54",
+        )
+        .unwrap();
+
+        let logger = Arc::new(MutinyLogger::default());
+        let network_graph = NetworkGraph::new(Network::Regtest, logger);
+        let graph = network_graph.read_only();
+
+        // payee not in graph, hint not routable
+        assert!(!is_destination_reachable(&payee, &[hint], &graph, &[]));
+        assert!(!is_destination_reachable(&payee, &[], &graph, &[]));
+
+        // direct channel with the payee
+        assert!(is_destination_reachable(&payee, &[], &graph, &[payee]));
+
+        // direct channel with the hinted peer
+        assert!(is_destination_reachable(&payee, &[hint], &graph, &[hint]));
+    }
+
+    #[test]
+    async fn test_direct_connect_candidates() {
+        log!("test direct connect candidates");
+
+        let payee = PublicKey::from_str(
+            "0218845781f631c48f1c9709e23092067d06837f30aa0cd0544ac887fe91ddd166",
+        )
+        .unwrap();
+        let hint = PublicKey::from_str(
+            "02cae09cf2c8842ace44068a5bf3117a494ebbf69a99e79712483c36f97cdb7b54",
+        )
+        .unwrap();
+
+        // no channel with the payee, try to connect to them directly
+        assert_eq!(direct_connect_candidates(&payee, &[hint], &[]), vec![payee]);
+
+        // a disconnected channel with the payee is reconnected
+        assert_eq!(
+            direct_connect_candidates(&payee, &[hint], &[(payee, false)]),
+            vec![payee]
+        );
+
+        // disconnected channels with the payee and hinted nodes come first
+        assert_eq!(
+            direct_connect_candidates(&payee, &[hint, hint], &[(hint, false)]),
+            vec![hint, payee]
+        );
+
+        // usable channels need no connection
+        assert!(
+            direct_connect_candidates(&payee, &[hint], &[(payee, true), (hint, true)]).is_empty()
+        );
+    }
+
+    #[test]
+    async fn test_route_not_found_error() {
+        log!("test route not found error");
+
+        assert!(matches!(
+            route_not_found_error(2_000_000, 1_000_000, 0, true),
+            MutinyError::InsufficientBalance {
+                needed_sats: 2_000,
+                available_sats: 1_000
+            }
+        ));
+        assert!(matches!(
+            route_not_found_error(1_000_000, 1_000_000, 10_000, true),
+            MutinyError::ReserveAmountError
+        ));
+        assert!(matches!(
+            route_not_found_error(500_000, 1_000_000, 10_000, false),
+            MutinyError::DestinationUnreachable
+        ));
+        assert!(matches!(
+            route_not_found_error(500_000, 1_000_000, 10_000, true),
+            MutinyError::RoutingFailed
+        ));
+
+        // a balance problem is reported before an unreachable destination
+        assert!(matches!(
+            route_not_found_error(2_000_000, 1_000_000, 0, false),
+            MutinyError::InsufficientBalance { .. }
+        ));
+    }
+
+    #[test]
+    async fn test_unreachable_sweep_status() {
+        log!("test unreachable sweep status");
+
+        // by default the commitment is never broadcast
+        assert_eq!(
+            unreachable_sweep_status(false, false),
+            Some(ScbSweepStatus::PeerUnreachable)
+        );
+        assert_eq!(
+            unreachable_sweep_status(false, true),
+            Some(ScbSweepStatus::PeerUnreachable)
+        );
+
+        // opting in still refuses to broadcast a backup known to be outdated
+        assert_eq!(
+            unreachable_sweep_status(true, true),
+            Some(ScbSweepStatus::Failed(ScbSweepFailure::StaleBackup))
+        );
+        assert_eq!(unreachable_sweep_status(true, false), None);
+    }
 }
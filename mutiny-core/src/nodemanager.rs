@@ -1,16 +1,59 @@
 use anyhow::anyhow;
-use lightning::sign::{NodeSigner, Recipient};
+use lightning::sign::{InMemorySigner, NodeSigner, Recipient};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::{collections::HashMap, ops::Deref, sync::Arc};
 
+use crate::chunks::{gc_chunks, is_chunk_key};
+use crate::congestion::{ChannelOperation, CongestionBreaker, CongestionStatus};
+use crate::consolidation::{
+    Consolidation, ConsolidationCandidate, ConsolidationPolicy, CONSOLIDATION_LABEL,
+    LAST_CONSOLIDATION_KEY,
+};
+use crate::freshness::{ChainOperation, StrictModeConfig};
+use crate::lifecycle::{ComponentFailure, Lifecycle, LifecycleState, WalletComponent};
 use crate::logging::LOGGING_KEY;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::networking::dns::DnsResolver;
+use crate::payment_privacy::PaymentPrivacyConfig;
+use crate::recovery::{
+    channel_recoveries, sweep_recovery, PendingRecovery, RecoverySweep, SweepStatus,
+};
+use crate::recovery_kit::{
+    validate_recovery_kit, RecoveryKit, RecoveryKitNode, RecoveryKitStep, RecoveryKitValidation,
+    RECOVERY_KIT_VERSION,
+};
 use crate::redshift::{RedshiftManager, RedshiftStatus, RedshiftStorage};
+use crate::scb::lnd::{import_lnd_backup, lnd_backup_key, LndBackupImport};
+use crate::scb::message_handler::PeerBackupPermissions;
+use crate::scb::nostr_backup::{
+    fetch_latest_backup, publish_backup, NostrSdkTransport, SCB_NOSTR_KEY_DERIVATION_PATH,
+};
+use crate::scb::remote_backup::{
+    HttpScbTransport, ScbRemoteBackup, SCB_REMOTE_KEY_DERIVATION_PATH,
+};
+use crate::scb::scb_format::{
+    LengthPrefix, MAX_NODE_INDEX_LEN, MAX_STRING_LEN, NODE_INDEX_ALIAS_TYPE,
+    NODE_INDEX_ARCHIVED_TYPE, NODE_INDEX_CHILD_INDEX_TYPE, NODE_INDEX_CREATED_AT_TYPE,
+    NODE_INDEX_KNOWN_TYPES, NODE_INDEX_LSP_TYPE, NODE_INDEX_TLV_MARKER,
+};
 use crate::scb::{
-    EncryptedSCB, StaticChannelBackup, StaticChannelBackupStorage,
-    SCB_ENCRYPTION_KEY_DERIVATION_PATH,
+    inspect_scb_metadata, scb_key_derivation_path, validate_connection_string, AutoBackup,
+    AutoBackupDebouncer, ChannelRecoveryInfo, EncryptedSCB, ScbGeneration, ScbMetadata,
+    ScbPeerMetadata, ScbRestoreProgress, ScbRestoreResult, ScbSummary, ScbSweepFailure,
+    ScbSweepResult, ScbSweepStatus, StaticChannelBackup, StaticChannelBackupStorage,
+    PEER_BACKUP_TIMEOUT_SECS, SCB_AUTO_BACKUP_KEY, SCB_BACKUP_PEERS_KEY, SCB_LATEST_GENERATION_KEY,
 };
-use crate::storage::{MutinyStorage, KEYCHAIN_STORE_KEY};
+use crate::search::{
+    load_search_index, rebuild_search_index, update_search_index, SearchDocument, SearchResult,
+};
+use crate::snapshot::{LastSnapshot, Snapshot, SnapshotSequence};
+use crate::storage::{MutinyStorage, QuarantineReport, RepairReport, KEYCHAIN_STORE_KEY};
+use crate::trace::{OperationSpan, OperationTrace, OperationTracer, DEFAULT_TRACE_CAPACITY};
 use crate::utils::sleep;
+use crate::webhooks::{
+    enqueue_webhook_event, HttpWebhookTransport, WebhookDelivery, WebhookEndpoint, WebhookEvent,
+    WebhookEventKind, WebhookStats, Webhooks,
+};
 use crate::{auth::MutinyAuthClient, gossip::*};
 use crate::{
     chain::MutinyChain,
@@ -20,9 +63,9 @@ use crate::{
     gossip, keymanager,
     logging::MutinyLogger,
     lspclient::LspClient,
-    node::{Node, ProbScorer, PubkeyConnectionInfo, RapidGossipSync},
-    onchain::get_esplora_url,
+    node::{Node, ProbScorer, PubkeyConnectionInfo, RapidGossipSync, DEFAULT_PAYMENT_TIMEOUT},
     onchain::OnChainWallet,
+    onchain::{get_esplora_url, get_tr_public_descriptors},
     utils,
 };
 use crate::{
@@ -43,10 +86,11 @@ use bitcoin::hashes::hex::ToHex;
 use bitcoin::hashes::{sha256, Hash};
 use bitcoin::secp256k1::{rand, PublicKey, Secp256k1, SecretKey};
 use bitcoin::util::bip32::{DerivationPath, ExtendedPrivKey};
-use bitcoin::{Address, Network, OutPoint, Transaction, Txid};
+use bitcoin::{Address, BlockHash, Network, OutPoint, Transaction, Txid};
 use core::time::Duration;
 use futures::{future::join_all, lock::Mutex};
 use lightning::chain::chaininterface::{ConfirmationTarget, FeeEstimator};
+use lightning::chain::channelmonitor::{Balance, ChannelMonitor};
 use lightning::chain::Confirm;
 use lightning::events::ClosureReason;
 use lightning::io::Read;
@@ -55,11 +99,12 @@ use lightning::ln::msgs::DecodeError;
 use lightning::ln::PaymentHash;
 use lightning::routing::gossip::NodeId;
 use lightning::util::logger::*;
-use lightning::util::ser::{Readable, Writeable, Writer};
+use lightning::util::ser::{Readable, ReadableArgs, Writeable, Writer};
 use lightning::{log_debug, log_error, log_info, log_warn};
 use lightning_invoice::{Invoice, InvoiceDescription};
 use lnurl::lnurl::LnUrl;
 use lnurl::{AsyncClient as LnUrlClient, LnUrlResponse, Response};
+use nostr::Keys;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -67,6 +112,10 @@ use std::str::FromStr;
 use uuid::Uuid;
 
 const BITCOIN_PRICE_CACHE_SEC: u64 = 300;
+/// How often to check if the channels need to be backed up
+const AUTO_BACKUP_POLL_SECS: u64 = 5;
+/// How often to check for webhook deliveries that are due
+const WEBHOOK_POLL_SECS: u64 = 5;
 
 // This is the NodeStorage object saved to the DB
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
@@ -80,16 +129,203 @@ pub struct NodeIndex {
     pub child_index: u32,
     pub lsp: Option<String>,
     pub archived: Option<bool>,
+    /// When the node was created, in seconds since the unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
 }
 
 impl NodeIndex {
+    /// The index the node keys are derived at from the seed
+    pub fn child_index(&self) -> u32 {
+        self.child_index
+    }
+
+    /// The url of the LSP the node was using, if any
+    pub fn lsp(&self) -> Option<&str> {
+        self.lsp.as_deref()
+    }
+
     pub fn is_archived(&self) -> bool {
         self.archived.unwrap_or(false)
     }
+
+    /// Whether both refer to the same node, ignoring the fields that
+    /// older backups do not have.
+    pub(crate) fn same_node(&self, other: &NodeIndex) -> bool {
+        self.child_index == other.child_index
+            && self.lsp == other.lsp
+            && self.archived == other.archived
+    }
+
+    /// Whether the node index has fields the fixed layout can not hold,
+    /// so it has to be written as TLV records.
+    pub(crate) fn needs_tlv(&self) -> bool {
+        self.created_at.is_some() || self.alias.is_some()
+    }
+
+    fn write_tlv<W: Writer>(&self, writer: &mut W) -> Result<(), lightning::io::Error> {
+        fn record(records: &mut Vec<u8>, tlv_type: u16, value: &[u8]) {
+            records.extend_from_slice(&tlv_type.to_be_bytes());
+            records.extend_from_slice(&(value.len() as LengthPrefix).to_be_bytes());
+            records.extend_from_slice(value);
+        }
+
+        // records have to be in increasing type order
+        let mut records = vec![];
+        record(
+            &mut records,
+            NODE_INDEX_CHILD_INDEX_TYPE,
+            &self.child_index.to_be_bytes(),
+        );
+        if let Some(archived) = self.archived {
+            record(&mut records, NODE_INDEX_ARCHIVED_TYPE, &[archived as u8]);
+        }
+        if let Some(ref lsp) = self.lsp {
+            record(&mut records, NODE_INDEX_LSP_TYPE, lsp.as_bytes());
+        }
+        if let Some(created_at) = self.created_at {
+            record(
+                &mut records,
+                NODE_INDEX_CREATED_AT_TYPE,
+                &created_at.to_be_bytes(),
+            );
+        }
+        if let Some(ref alias) = self.alias {
+            record(&mut records, NODE_INDEX_ALIAS_TYPE, alias.as_bytes());
+        }
+
+        writer.write_all(&[NODE_INDEX_TLV_MARKER])?;
+        writer.write_all(&(records.len() as LengthPrefix).to_be_bytes())?;
+        writer.write_all(&records)
+    }
+
+    /// Reads a node index, skipping the TLV records that are not in `known_types`
+    /// like a version from before they were added would.
+    pub(crate) fn read_with_known_types<R: Read>(
+        reader: &mut R,
+        known_types: &[u16],
+    ) -> Result<Self, DecodeError> {
+        // Read the archived flag, or the marker of the TLV encoding
+        let mut archived = [0; 1];
+        reader.read_exact(&mut archived)?;
+        if archived[0] != NODE_INDEX_TLV_MARKER {
+            return Self::read_fixed(archived[0] == 1, reader);
+        }
+
+        let len: LengthPrefix = Readable::read(reader)?;
+        if len > MAX_NODE_INDEX_LEN {
+            return Err(DecodeError::InvalidValue);
+        }
+        let mut records = Vec::new();
+        reader.take(len as u64).read_to_end(&mut records)?;
+        if records.len() != len as usize {
+            return Err(DecodeError::ShortRead);
+        }
+
+        fn read_string(value: &[u8]) -> Result<String, DecodeError> {
+            if value.len() > MAX_STRING_LEN as usize {
+                return Err(DecodeError::InvalidValue);
+            }
+            String::from_utf8(value.to_vec()).map_err(|_| DecodeError::InvalidValue)
+        }
+
+        let mut child_index = None;
+        let mut node_index = NodeIndex {
+            child_index: 0,
+            lsp: None,
+            archived: None,
+            created_at: None,
+            alias: None,
+        };
+        let mut last_type = None;
+        let mut rest = records.as_slice();
+        while !rest.is_empty() {
+            let tlv_type: u16 = Readable::read(&mut rest)?;
+            let len: LengthPrefix = Readable::read(&mut rest)?;
+            if last_type.map_or(false, |last| tlv_type <= last) {
+                return Err(DecodeError::InvalidValue);
+            }
+            last_type = Some(tlv_type);
+            if rest.len() < len as usize {
+                return Err(DecodeError::ShortRead);
+            }
+            let (value, tail) = rest.split_at(len as usize);
+            rest = tail;
+
+            if !known_types.contains(&tlv_type) {
+                // it's ok to be odd
+                if tlv_type % 2 == 0 {
+                    return Err(DecodeError::UnknownRequiredFeature);
+                }
+                continue;
+            }
+
+            match tlv_type {
+                NODE_INDEX_CHILD_INDEX_TYPE => {
+                    let bytes = value.try_into().map_err(|_| DecodeError::InvalidValue)?;
+                    child_index = Some(u32::from_be_bytes(bytes));
+                }
+                NODE_INDEX_ARCHIVED_TYPE => {
+                    node_index.archived = match value {
+                        [0] => Some(false),
+                        [1] => Some(true),
+                        _ => return Err(DecodeError::InvalidValue),
+                    };
+                }
+                NODE_INDEX_LSP_TYPE => node_index.lsp = Some(read_string(value)?),
+                NODE_INDEX_CREATED_AT_TYPE => {
+                    let bytes = value.try_into().map_err(|_| DecodeError::InvalidValue)?;
+                    node_index.created_at = Some(u64::from_be_bytes(bytes));
+                }
+                NODE_INDEX_ALIAS_TYPE => node_index.alias = Some(read_string(value)?),
+                _ => {}
+            }
+        }
+
+        node_index.child_index = child_index.ok_or(DecodeError::InvalidValue)?;
+        Ok(node_index)
+    }
+
+    /// Reads the rest of a node index in the fixed layout, after the archived flag.
+    fn read_fixed<R: Read>(archived: bool, reader: &mut R) -> Result<Self, DecodeError> {
+        // Read the child index
+        let child_index: u32 = Readable::read(reader)?;
+
+        // Read the lsp
+        let lsp_len: u32 = Readable::read(reader)?;
+        if lsp_len > MAX_STRING_LEN {
+            return Err(DecodeError::InvalidValue);
+        }
+        let lsp = if lsp_len > 0 {
+            let mut lsp = Vec::new();
+            reader.take(lsp_len as u64).read_to_end(&mut lsp)?;
+            if lsp.len() != lsp_len as usize {
+                return Err(DecodeError::ShortRead);
+            }
+            Some(String::from_utf8(lsp).map_err(|_| DecodeError::InvalidValue)?)
+        } else {
+            None
+        };
+
+        Ok(NodeIndex {
+            child_index,
+            lsp,
+            archived: Some(archived),
+            created_at: None,
+            alias: None,
+        })
+    }
 }
 
 impl Writeable for NodeIndex {
     fn write<W: Writer>(&self, writer: &mut W) -> Result<(), lightning::io::Error> {
+        // Without the new fields the fixed layout is kept, so older versions can read it
+        if self.needs_tlv() {
+            return self.write_tlv(writer);
+        }
+
         // Write the archived flag, 1 if archived, 0 if not
         if self.archived.unwrap_or(false) {
             writer.write_all(&[1])?;
@@ -119,29 +355,7 @@ impl Writeable for NodeIndex {
 
 impl Readable for NodeIndex {
     fn read<R: Read>(reader: &mut R) -> Result<Self, DecodeError> {
-        // Read the archived flag
-        let mut archived = [0; 1];
-        reader.read_exact(&mut archived)?;
-        let archived = archived[0] == 1;
-
-        // Read the child index
-        let child_index: u32 = Readable::read(reader)?;
-
-        // Read the lsp
-        let lsp_len: u32 = Readable::read(reader)?;
-        let lsp = if lsp_len > 0 {
-            let mut lsp = Vec::with_capacity(lsp_len as usize);
-            reader.take(lsp_len as u64).read_to_end(&mut lsp)?;
-            Some(String::from_utf8(lsp).unwrap())
-        } else {
-            None
-        };
-
-        Ok(NodeIndex {
-            child_index,
-            lsp,
-            archived: Some(archived),
-        })
+        Self::read_with_known_types(reader, NODE_INDEX_KNOWN_TYPES)
     }
 }
 
@@ -169,7 +383,14 @@ pub struct MutinyInvoice {
     pub payee_pubkey: Option<PublicKey>,
     pub amount_sats: Option<u64>,
     pub expire: u64,
+    pub status: HTLCStatus,
     pub paid: bool,
+    /// The invoice was marked as paid out of band, no funds moved into our channels
+    #[serde(default)]
+    pub settled_externally: bool,
+    /// The note given when marking the invoice as paid out of band
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub external_settlement_note: Option<String>,
     pub fees_paid: Option<u64>,
     pub inbound: bool,
     pub labels: Vec<String>,
@@ -204,7 +425,10 @@ impl From<Invoice> for MutinyInvoice {
             payee_pubkey,
             amount_sats,
             expire: expiry,
+            status: HTLCStatus::Pending,
             paid: false,
+            settled_externally: false,
+            external_settlement_note: None,
             fees_paid: None,
             inbound: true,
             labels: vec![],
@@ -235,7 +459,10 @@ impl MutinyInvoice {
                 Ok(MutinyInvoice {
                     inbound,
                     last_updated: i.last_update,
+                    status: i.status,
                     paid: i.status == HTLCStatus::Succeeded,
+                    settled_externally: i.external_settlement.is_some(),
+                    external_settlement_note: i.external_settlement.map(|e| e.note),
                     labels,
                     amount_sats,
                     payee_pubkey: i.payee_pubkey,
@@ -246,6 +473,7 @@ impl MutinyInvoice {
             }
             None => {
                 let paid = i.status == HTLCStatus::Succeeded;
+                let external_settlement_note = i.external_settlement.map(|e| e.note);
                 let amount_sats: Option<u64> = i.amt_msat.0.map(|s| s / 1_000);
                 let fees_paid = i.fee_paid_msat.map(|f| f / 1_000);
                 let preimage = i.preimage.map(|p| p.to_hex());
@@ -258,7 +486,10 @@ impl MutinyInvoice {
                     payee_pubkey: i.payee_pubkey,
                     amount_sats,
                     expire: i.last_update,
+                    status: i.status,
                     paid,
+                    settled_externally: external_settlement_note.is_some(),
+                    external_settlement_note,
                     fees_paid,
                     inbound,
                     labels,
@@ -278,6 +509,9 @@ pub struct MutinyPeer {
     pub color: Option<String>,
     pub label: Option<String>,
     pub is_connected: bool,
+    /// Number of our messages waiting for the peer to reconnect
+    #[serde(default)]
+    pub queued_messages: usize,
 }
 
 impl PartialOrd for MutinyPeer {
@@ -469,11 +703,140 @@ impl Ord for ActivityItem {
     }
 }
 
+#[derive(Clone, Copy)]
 pub struct MutinyBalance {
     pub confirmed: u64,
     pub unconfirmed: u64,
     pub lightning: u64,
+    /// Sats in channels that are closing but not yet spendable on-chain
     pub force_close: u64,
+    /// The force close sats plus the sweeps of closed channels that are not
+    /// confirmed yet, see [`NodeManager::list_pending_recoveries`]. This includes
+    /// `force_close`, so it is shown instead of it.
+    pub pending_recovery: u64,
+    /// The snapshot the balance was read from, a balance with a lower sequence is
+    /// older and can be discarded
+    pub sequence: u64,
+    /// Epoch time in seconds the balance was read at
+    pub taken_at: u64,
+}
+
+impl MutinyBalance {
+    /// Combines the on-chain balance with the lightning balance and the claimable
+    /// balances of the closing channels. Funds of a closing channel only show up in
+    /// the on-chain balance once they are swept to the wallet.
+    pub(crate) fn new(
+        onchain: &bdk_chain::keychain::Balance,
+        lightning_msats: u64,
+        closing: &[Balance],
+    ) -> Self {
+        let force_close = closing.iter().map(|b| b.claimable_amount_satoshis()).sum();
+        MutinyBalance {
+            confirmed: onchain.confirmed + onchain.trusted_pending,
+            unconfirmed: onchain.untrusted_pending + onchain.immature,
+            lightning: lightning_msats / 1_000,
+            force_close,
+            pending_recovery: force_close,
+            sequence: 0,
+            taken_at: 0,
+        }
+    }
+
+    /// Adds the sweeps that are not confirmed yet to the pending recovery. What the
+    /// wallet already counts as unconfirmed from them is taken out of the unconfirmed
+    /// balance, so it is not counted twice.
+    pub(crate) fn with_sweeps(mut self, sweeps: &[(RecoverySweep, SweepStatus)]) -> Self {
+        for (sweep, status) in sweeps {
+            match status {
+                SweepStatus::Broadcast => {}
+                SweepStatus::Unconfirmed { received } => {
+                    self.unconfirmed = self.unconfirmed.saturating_sub(*received);
+                }
+                SweepStatus::Confirmed => continue,
+            }
+            self.pending_recovery += sweep.amount_sats;
+        }
+        self
+    }
+
+    fn from_snapshot(snapshot: Snapshot<Self>) -> Self {
+        MutinyBalance {
+            sequence: snapshot.sequence,
+            taken_at: snapshot.taken_at,
+            ..snapshot.value
+        }
+    }
+}
+
+/// The claimable balances of the monitors of the closed channels of the nodes, with
+/// the funding outpoint of the channel and the tip height of the node.
+fn closed_channel_balances<S: MutinyStorage>(
+    nodes: &HashMap<PublicKey, Arc<Node<S>>>,
+) -> Vec<(OutPoint, Vec<Balance>, u32)> {
+    nodes
+        .values()
+        .flat_map(|n| {
+            let open: Vec<_> = n
+                .channel_manager
+                .list_channels()
+                .into_iter()
+                .filter_map(|c| c.funding_txo)
+                .collect();
+            let tip_height = n.channel_manager.current_best_block().height();
+            n.chain_monitor
+                .list_monitors()
+                .into_iter()
+                .filter(|funding_txo| !open.contains(funding_txo))
+                .filter_map(|funding_txo| {
+                    let monitor = n.chain_monitor.get_monitor(funding_txo).ok()?;
+                    let balances = monitor.get_claimable_balances();
+                    (!balances.is_empty())
+                        .then(|| (funding_txo.into_bitcoin_outpoint(), balances, tip_height))
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Totals of the settled lightning payments, in sats.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LightningPaymentTotals {
+    pub received: u64,
+    pub sent: u64,
+    pub fees_paid: u64,
+    /// The part of `received` that was settled out of band
+    pub settled_externally: u64,
+    /// The snapshot the totals were read from, see [`MutinyBalance::sequence`]
+    #[serde(default)]
+    pub sequence: u64,
+    /// Epoch time in seconds the totals were read at
+    #[serde(default)]
+    pub taken_at: u64,
+}
+
+impl LightningPaymentTotals {
+    pub fn from_invoices(invoices: &[MutinyInvoice]) -> Self {
+        let mut totals = Self::default();
+        for invoice in invoices.iter().filter(|i| i.paid) {
+            let amount = invoice.amount_sats.unwrap_or_default();
+            if invoice.inbound {
+                totals.received += amount;
+                if invoice.settled_externally {
+                    totals.settled_externally += amount;
+                }
+            } else {
+                totals.sent += amount;
+                totals.fees_paid += invoice.fees_paid.unwrap_or_default();
+            }
+        }
+        totals
+    }
+
+    /// The net amount these payments moved into our channels, to reconcile with
+    /// the lightning balance. Invoices settled out of band did not move any funds.
+    pub fn channel_balance_change(&self) -> i64 {
+        (self.received - self.settled_externally) as i64 - (self.sent + self.fees_paid) as i64
+    }
 }
 
 pub struct LnUrlParams {
@@ -506,7 +869,10 @@ pub struct NodeManager<S: MutinyStorage> {
     network: Network,
     #[cfg(target_arch = "wasm32")]
     websocket_proxy_addr: String,
+    #[cfg(not(target_arch = "wasm32"))]
+    dns_resolver: DnsResolver,
     esplora: Arc<AsyncClient>,
+    esplora_url: String,
     wallet: Arc<OnChainWallet<S>>,
     gossip_sync: Arc<RapidGossipSync>,
     scorer: Arc<utils::Mutex<ProbScorer>>,
@@ -521,7 +887,28 @@ pub struct NodeManager<S: MutinyStorage> {
     pub(crate) subscription_client: Option<Arc<MutinySubscriptionClient<S>>>,
     pub(crate) logger: Arc<MutinyLogger>,
     bitcoin_price_cache: Arc<Mutex<Option<(f32, Duration)>>>,
+    auto_backup: Arc<Mutex<AutoBackupDebouncer>>,
+    scb_padding_buckets: Vec<u32>,
+    /// The account of the seed key new backups are encrypted with
+    scb_account: Option<u32>,
+    /// The endpoint the automatic backups are also sent to
+    scb_remote_url: Option<String>,
+    /// If new nodes send their backup to the LSP they are set up with
+    scb_lsp_backup: bool,
     do_not_connect_peers: bool,
+    strict_mode: StrictModeConfig,
+    congestion: CongestionBreaker,
+    consolidation: ConsolidationPolicy,
+    payment_privacy: PaymentPrivacyConfig,
+    tracer: OperationTracer,
+    lifecycle: Lifecycle,
+    /// Moved on by changes that move funds between the on-chain wallet and the
+    /// channels, so balance reads never see half of one
+    balance_sequence: SnapshotSequence,
+    /// The last balance read, returned while a channel funding takes longer than
+    /// readers wait for
+    last_balance: LastSnapshot<MutinyBalance>,
+    last_payment_totals: LastSnapshot<LightningPaymentTotals>,
 }
 
 impl<S: MutinyStorage> NodeManager<S> {
@@ -534,7 +921,20 @@ impl<S: MutinyStorage> NodeManager<S> {
     /// Creates a new [NodeManager] with the given parameters.
     /// The mnemonic seed is read from storage, unless one is provided.
     /// If no mnemonic is provided, a new one is generated and stored.
+    ///
+    /// The lifecycle of the config is initializing until this returns,
+    /// and stopped if it fails.
     pub async fn new(c: MutinyWalletConfig, storage: S) -> Result<NodeManager<S>, MutinyError> {
+        let lifecycle = c.lifecycle.clone();
+        lifecycle.initializing(0);
+        let nm = Self::start_up(c, storage).await;
+        if nm.is_err() {
+            lifecycle.stopped();
+        }
+        nm
+    }
+
+    async fn start_up(c: MutinyWalletConfig, storage: S) -> Result<NodeManager<S>, MutinyError> {
         let stop = Arc::new(AtomicBool::new(false));
 
         #[cfg(target_arch = "wasm32")]
@@ -544,6 +944,9 @@ impl<S: MutinyStorage> NodeManager<S> {
 
         let network: Network = c.network.unwrap_or(Network::Bitcoin);
 
+        // make sure the SCB key can be derived for the account
+        scb_key_derivation_path(c.scb_account)?;
+
         let mnemonic = match c.mnemonic {
             Some(seed) => storage.insert_mnemonic(seed)?,
             None => match storage.get_mnemonic() {
@@ -557,8 +960,28 @@ impl<S: MutinyStorage> NodeManager<S> {
 
         let logger = Arc::new(MutinyLogger::with_writer(stop.clone(), storage.clone()));
 
+        #[cfg(not(target_arch = "wasm32"))]
+        let dns_resolver = DnsResolver::new(c.dns.clone(), logger.clone());
+
+        let node_storage = storage.get_nodes()?;
+
+        // Remove the archived nodes, we don't need to start them up.
+        let unarchived_nodes: Vec<(String, NodeIndex)> = node_storage
+            .clone()
+            .nodes
+            .into_iter()
+            .filter(|(_, n)| !n.is_archived())
+            .collect();
+
+        // starting the on-chain wallet and gossip, then each of the nodes
+        let lifecycle = c.lifecycle.clone();
+        lifecycle.initializing(2 + unarchived_nodes.len());
+
         let esplora_server_url = get_esplora_url(network, c.user_esplora_url);
-        let tx_sync = Arc::new(EsploraSyncClient::new(esplora_server_url, logger.clone()));
+        let tx_sync = Arc::new(EsploraSyncClient::new(
+            esplora_server_url.clone(),
+            logger.clone(),
+        ));
 
         let esplora = Arc::new(tx_sync.client().clone());
         let fee_estimator = Arc::new(MutinyFeeEstimator::new(
@@ -578,9 +1001,11 @@ impl<S: MutinyStorage> NodeManager<S> {
         )?);
 
         let chain = Arc::new(MutinyChain::new(tx_sync, wallet.clone(), logger.clone()));
+        lifecycle.step_completed();
 
         let (gossip_sync, scorer) =
             gossip::get_gossip_sync(&storage, c.user_rgs_url, network, logger.clone()).await?;
+        lifecycle.step_completed();
 
         let scorer = Arc::new(utils::Mutex::new(scorer));
 
@@ -592,7 +1017,14 @@ impl<S: MutinyStorage> NodeManager<S> {
             Some(lsp_urls) if !lsp_urls.is_empty() => {
                 let urls: Vec<&str> = lsp_urls.split(',').collect();
 
-                let futs = urls.into_iter().map(|url| LspClient::new(url.trim()));
+                #[cfg(target_arch = "wasm32")]
+                let lsp_http_client = Client::new();
+                #[cfg(not(target_arch = "wasm32"))]
+                let lsp_http_client = dns_resolver.http_client()?;
+
+                let futs = urls
+                    .into_iter()
+                    .map(|url| LspClient::new(url.trim(), lsp_http_client.clone()));
 
                 let results = futures::future::join_all(futs).await;
 
@@ -610,20 +1042,19 @@ impl<S: MutinyStorage> NodeManager<S> {
             _ => Vec::new(),
         };
 
-        let node_storage = storage.get_nodes()?;
+        let congestion = CongestionBreaker::new(c.congestion_policy);
 
-        // Remove the archived nodes, we don't need to start them up.
-        let unarchived_nodes = node_storage
-            .clone()
-            .nodes
-            .into_iter()
-            .filter(|(_, n)| !n.is_archived());
+        // the nodes load their backup permissions when they start
+        migrate_backup_peers(&storage)?;
 
         let mut nodes_map = HashMap::new();
+        // nodes that failed to start are kept in storage, on-chain keeps working
+        let mut failed_nodes = HashMap::new();
+        let mut failures = Vec::new();
 
         for node_item in unarchived_nodes {
-            let node = Node::new(
-                node_item.0,
+            let node = match Node::new(
+                node_item.0.clone(),
                 &node_item.1,
                 &mnemonic,
                 storage.clone(),
@@ -637,11 +1068,36 @@ impl<S: MutinyStorage> NodeManager<S> {
                 &lsp_clients,
                 logger.clone(),
                 c.do_not_connect_peers,
+                c.strict_mode,
+                congestion.clone(),
+                c.payment_privacy,
                 false,
                 #[cfg(target_arch = "wasm32")]
                 websocket_proxy_addr.clone(),
+                #[cfg(not(target_arch = "wasm32"))]
+                dns_resolver.clone(),
             )
-            .await?;
+            .await
+            {
+                Ok(node) => node,
+                Err(e) => {
+                    // keep the cause, such as which data could not be read
+                    let error = match std::error::Error::source(&e) {
+                        Some(source) => format!("{e} {source}"),
+                        None => e.to_string(),
+                    };
+                    log_error!(logger, "failed to start node {}: {error}", node_item.0);
+                    failures.push(ComponentFailure {
+                        component: WalletComponent::Lightning,
+                        node_id: Some(node_item.0.clone()),
+                        error,
+                    });
+                    failed_nodes.insert(node_item.0, node_item.1);
+                    lifecycle.step_completed();
+                    continue;
+                }
+            };
+            lifecycle.step_completed();
 
             let id = node
                 .keys_manager
@@ -657,6 +1113,7 @@ impl<S: MutinyStorage> NodeManager<S> {
         let updated_nodes: HashMap<String, NodeIndex> = nodes_map
             .values()
             .map(|n| (n._uuid.clone(), n.node_index()))
+            .chain(failed_nodes)
             .collect();
 
         log_info!(logger, "inserting updated nodes");
@@ -709,6 +1166,13 @@ impl<S: MutinyStorage> NodeManager<S> {
             None
         };
 
+        let last_auto_backup: Option<AutoBackup> = storage.get_data(SCB_AUTO_BACKUP_KEY)?;
+        let auto_backup = AutoBackupDebouncer::new(
+            c.auto_backup_interval_secs,
+            c.scb_privacy.jitter_window_secs,
+            last_auto_backup.map(|b| b.created_at),
+        );
+
         let nm = NodeManager {
             stop,
             mnemonic,
@@ -723,19 +1187,53 @@ impl<S: MutinyStorage> NodeManager<S> {
             nodes,
             #[cfg(target_arch = "wasm32")]
             websocket_proxy_addr,
+            #[cfg(not(target_arch = "wasm32"))]
+            dns_resolver,
             esplora,
+            esplora_url: esplora_server_url,
             auth,
             lnurl_client,
             lsp_clients,
             subscription_client,
             logger,
             bitcoin_price_cache: Arc::new(Mutex::new(None)),
+            auto_backup: Arc::new(Mutex::new(auto_backup)),
+            scb_padding_buckets: c.scb_privacy.padding_buckets,
+            scb_account: c.scb_account,
+            scb_remote_url: c.scb_remote_url,
+            scb_lsp_backup: c.scb_lsp_backup,
             do_not_connect_peers: c.do_not_connect_peers,
+            strict_mode: c.strict_mode,
+            congestion,
+            consolidation: c.consolidation_policy,
+            payment_privacy: c.payment_privacy,
+            tracer: OperationTracer::new(c.trace_verbosity, DEFAULT_TRACE_CAPACITY),
+            lifecycle,
+            balance_sequence: SnapshotSequence::default(),
+            last_balance: LastSnapshot::default(),
+            last_payment_totals: LastSnapshot::default(),
         };
 
+        nm.lifecycle.started(failures);
+
         Ok(nm)
     }
 
+    /// Returns where the wallet is in its lifecycle.
+    pub fn lifecycle_state(&self) -> LifecycleState {
+        self.lifecycle.state()
+    }
+
+    /// Fails fast with the current lifecycle state unless the wallet is running.
+    pub fn check_running(&self) -> Result<(), MutinyError> {
+        self.lifecycle.check_running()
+    }
+
+    /// Like [`NodeManager::check_running`], but also fails if lightning did not start.
+    pub fn check_lightning(&self) -> Result<(), MutinyError> {
+        self.lifecycle.check_component(WalletComponent::Lightning)
+    }
+
     /// Returns the node with the given pubkey
     pub(crate) async fn get_node(&self, pk: &PublicKey) -> Result<Arc<Node<S>>, MutinyError> {
         let nodes = self.nodes.lock().await;
@@ -746,6 +1244,7 @@ impl<S: MutinyStorage> NodeManager<S> {
     /// Stops all of the nodes and background processes.
     /// Returns after node has been stopped.
     pub async fn stop(&self) -> Result<(), MutinyError> {
+        self.lifecycle.stopping();
         self.stop.swap(true, Ordering::Relaxed);
         let mut nodes = self.nodes.lock().await;
         let node_futures = nodes.iter().map(|(_, n)| async {
@@ -773,6 +1272,7 @@ impl<S: MutinyStorage> NodeManager<S> {
             self.storage.stop();
             log_debug!(self.logger, "stopped storage");
         }
+        self.lifecycle.stopped();
 
         Ok(())
     }
@@ -868,6 +1368,19 @@ impl<S: MutinyStorage> NodeManager<S> {
                     log_error!(nm.logger, "Failed to update fee estimates: {e}");
                 } else {
                     log_info!(nm.logger, "Updated fee estimates!");
+                    nm.update_congestion_breaker();
+                }
+
+                match nm.consolidate_if_due().await {
+                    Ok(Some(c)) => log_info!(
+                        nm.logger,
+                        "Consolidated {} utxos at {} sats/vbyte: {}",
+                        c.inputs,
+                        c.sats_per_vbyte,
+                        c.txid
+                    ),
+                    Ok(None) => {}
+                    Err(e) => log_warn!(nm.logger, "Failed to consolidate utxos: {e}"),
                 }
 
                 if let Err(e) = nm.sync().await {
@@ -878,6 +1391,16 @@ impl<S: MutinyStorage> NodeManager<S> {
                     synced = true;
                 }
 
+                if let Err(e) = nm.push_backup_to_peers().await {
+                    log_warn!(nm.logger, "Failed to send backup to peers: {e}");
+                }
+
+                match gc_chunks(&nm.storage) {
+                    Ok(0) => {}
+                    Ok(n) => log_debug!(nm.logger, "Deleted {n} unused storage chunks"),
+                    Err(e) => log_warn!(nm.logger, "Failed to delete unused storage chunks: {e}"),
+                }
+
                 // sleep for 1 minute, checking graceful shutdown check each 1s.
                 for _ in 0..60 {
                     if nm.stop.load(Ordering::Relaxed) {
@@ -889,6 +1412,59 @@ impl<S: MutinyStorage> NodeManager<S> {
         });
     }
 
+    /// Creates a background process that backs up the channels after a channel
+    /// monitor changed, and sends the new backup to the backup peers.
+    pub fn start_auto_backup(nm: Arc<NodeManager<S>>) {
+        utils::spawn(async move {
+            loop {
+                // check for changes every 5 seconds, checking graceful shutdown each 1s.
+                for _ in 0..AUTO_BACKUP_POLL_SECS {
+                    if nm.stop.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    sleep(1_000).await;
+                }
+
+                match nm.run_auto_backup(utils::now().as_secs()).await {
+                    Ok(true) => {
+                        if let Err(e) = nm.push_backup_to_peers().await {
+                            log_warn!(nm.logger, "Failed to send backup to peers: {e}");
+                        }
+                        if let Err(e) = nm.push_backup_to_remote().await {
+                            log_warn!(nm.logger, "Failed to send backup to remote: {e}");
+                        }
+                    }
+                    Ok(false) => {}
+                    Err(e) => log_error!(nm.logger, "Failed to create automatic backup: {e}"),
+                }
+            }
+        });
+    }
+
+    /// Creates a background process that delivers the queued webhook events and
+    /// retries the failed ones once their backoff passed.
+    pub fn start_webhook_delivery(nm: Arc<NodeManager<S>>) {
+        utils::spawn(async move {
+            let transport = HttpWebhookTransport::new();
+            loop {
+                for _ in 0..WEBHOOK_POLL_SECS {
+                    if nm.stop.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    sleep(1_000).await;
+                }
+
+                if let Err(e) = nm
+                    .webhooks()
+                    .deliver_due(&transport, utils::now().as_secs())
+                    .await
+                {
+                    log_warn!(nm.logger, "Failed to deliver webhooks: {e}");
+                }
+            }
+        });
+    }
+
     /// Broadcast a transaction to the network.
     /// The transaction is broadcast through the configured esplora server.
     pub async fn broadcast_transaction(&self, tx: Transaction) -> Result<(), MutinyError> {
@@ -1179,6 +1755,68 @@ impl<S: MutinyStorage> NodeManager<S> {
         Ok(activity)
     }
 
+    /// Searches the activity, labels, and contacts of the wallet, newest first.
+    ///
+    /// Matches descriptions, labels, contact names, and amounts by word, and payment
+    /// hashes and txids by prefix. If the search index is missing or corrupt it is
+    /// rebuilt from the full history.
+    pub async fn search_activity(
+        &self,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<SearchResult>, MutinyError> {
+        let index = match load_search_index(&self.storage) {
+            Some(index) => index,
+            None => {
+                log_warn!(self.logger, "Search index missing or corrupt, rebuilding");
+                let docs = self.search_documents().await?;
+                rebuild_search_index(&self.storage, docs)?
+            }
+        };
+
+        Ok(index.search(query, limit))
+    }
+
+    /// All the searchable documents, used to rebuild the search index
+    async fn search_documents(&self) -> Result<Vec<SearchDocument>, MutinyError> {
+        let mut docs = vec![];
+        for invoice in self.list_invoices().await? {
+            docs.push(SearchDocument::lightning_labels(
+                invoice.payment_hash.to_hex(),
+                invoice.labels.clone(),
+                invoice.last_updated,
+            ));
+            docs.push(SearchDocument::lightning(&invoice));
+        }
+        for tx in self.list_onchain()? {
+            docs.push(SearchDocument::onchain(&tx));
+        }
+
+        let contacts = self.get_contacts()?;
+        let labels = self.get_labels()?;
+        for (label, item) in labels {
+            if !contacts.contains_key(&label) {
+                docs.push(SearchDocument::label(label, &item));
+            }
+        }
+        for (id, contact) in contacts {
+            docs.push(SearchDocument::contact(id, &contact));
+        }
+
+        Ok(docs)
+    }
+
+    /// Indexes the on-chain transactions, this is done after each sync
+    /// because bdk does not give us a hook when new transactions are found.
+    fn index_onchain_activity(&self) -> Result<(), MutinyError> {
+        let onchain = self.list_onchain()?;
+        update_search_index(&self.storage, |index| {
+            for tx in onchain.iter() {
+                index.insert(SearchDocument::onchain(tx));
+            }
+        })
+    }
+
     /// Adds labels to the TransactionDetails based on the address labels.
     /// This will panic if the TransactionDetails does not have a transaction.
     /// Make sure you flag `include_raw` when calling `list_transactions` to
@@ -1240,7 +1878,19 @@ impl<S: MutinyStorage> NodeManager<S> {
     /// This includes both on-chain and lightning funds.
     ///
     /// This will not include any funds in an unconfirmed lightning channel.
+    ///
+    /// The balance is read between channel fundings, so funds are never counted
+    /// both on-chain and in a channel. While a funding takes longer than a moment,
+    /// the balance read before it is returned, with its older sequence and time.
     pub async fn get_balance(&self) -> Result<MutinyBalance, MutinyError> {
+        let snapshot = self
+            .balance_sequence
+            .read(&self.last_balance, || self.read_balance())
+            .await?;
+        Ok(MutinyBalance::from_snapshot(snapshot))
+    }
+
+    async fn read_balance(&self) -> Result<MutinyBalance, MutinyError> {
         let onchain = if let Ok(wallet) = self.wallet.wallet.try_read() {
             wallet.get_balance()
         } else {
@@ -1256,22 +1906,66 @@ impl<S: MutinyStorage> NodeManager<S> {
             .sum();
 
         // get the amount in limbo from force closes
-        let force_close: u64 = nodes
-            .iter()
-            .flat_map(|(_, n)| {
-                let channels = n.channel_manager.list_channels();
-                let ignored_channels: Vec<&ChannelDetails> = channels.iter().collect();
-                n.chain_monitor.get_claimable_balances(&ignored_channels)
+        let closing: Vec<Balance> = closed_channel_balances(&nodes)
+            .into_iter()
+            .flat_map(|(_, balances, _)| balances)
+            .collect();
+        let sweeps = self.recovery_sweeps(&nodes)?;
+
+        Ok(MutinyBalance::new(&onchain, lightning_msats, &closing).with_sweeps(&sweeps))
+    }
+
+    /// Lists the funds of closed channels that are not spendable on-chain yet, like
+    /// a force closed channel waiting for its timelock, with an estimate of when
+    /// they are available. These add up to [`MutinyBalance::pending_recovery`].
+    pub async fn list_pending_recoveries(&self) -> Result<Vec<PendingRecovery>, MutinyError> {
+        let nodes = self.nodes.lock().await;
+        let now = utils::now().as_secs();
+
+        let mut recoveries: Vec<PendingRecovery> = closed_channel_balances(&nodes)
+            .into_iter()
+            .flat_map(|(funding_txo, balances, tip_height)| {
+                channel_recoveries(funding_txo, &balances, tip_height, now)
             })
-            .map(|bal| bal.claimable_amount_satoshis())
-            .sum();
+            .collect();
+        recoveries.extend(
+            self.recovery_sweeps(&nodes)?
+                .into_iter()
+                .filter_map(|(sweep, status)| sweep_recovery(&sweep, status, now)),
+        );
 
-        Ok(MutinyBalance {
-            confirmed: onchain.confirmed + onchain.trusted_pending,
-            unconfirmed: onchain.untrusted_pending + onchain.immature,
-            lightning: lightning_msats / 1_000,
-            force_close,
-        })
+        Ok(recoveries)
+    }
+
+    /// The sweeps of closed channel funds of the nodes and where the wallet sees
+    /// them. Sweeps that confirmed are forgotten afterwards.
+    fn recovery_sweeps(
+        &self,
+        nodes: &HashMap<PublicKey, Arc<Node<S>>>,
+    ) -> Result<Vec<(RecoverySweep, SweepStatus)>, MutinyError> {
+        let mut sweeps = vec![];
+        for node in nodes.values() {
+            let mut confirmed = vec![];
+            for sweep in node.persister.get_recovery_sweeps()? {
+                let status = match self.wallet.get_transaction(sweep.txid, false)? {
+                    None => SweepStatus::Broadcast,
+                    Some(tx) => match tx.confirmation_time {
+                        ConfirmationTime::Confirmed { .. } => {
+                            confirmed.push(sweep.txid);
+                            SweepStatus::Confirmed
+                        }
+                        ConfirmationTime::Unconfirmed { .. } => SweepStatus::Unconfirmed {
+                            received: tx.received,
+                        },
+                    },
+                };
+                sweeps.push((sweep, status));
+            }
+            if !confirmed.is_empty() {
+                node.persister.remove_recovery_sweeps(&confirmed)?;
+            }
+        }
+        Ok(sweeps)
     }
 
     /// Lists all the UTXOs in the wallet.
@@ -1316,21 +2010,55 @@ impl<S: MutinyStorage> NodeManager<S> {
             return Ok(());
         }
 
+        let mut span = self.tracer.start("sync", None);
+        let res = self.sync_phases(&mut span).await;
+        span.finish(&res);
+        res
+    }
+
+    async fn sync_phases(&self, span: &mut OperationSpan) -> Result<(), MutinyError> {
         // Sync ldk first because it may broadcast transactions
         // to addresses that are in our bdk wallet. This way
         // they are found on this iteration of syncing instead
         // of the next one.
-        if let Err(e) = self.sync_ldk().await {
+        if let Err(e) = span.phase("lightning_sync", self.sync_ldk()).await {
             log_error!(self.logger, "Failed to sync ldk: {e}");
             return Err(e);
         }
 
         // sync bdk wallet
-        match self.wallet.sync().await {
-            Ok(()) => Ok(log_info!(self.logger, "We are synced!")),
+        match span.phase("onchain_sync", self.wallet.sync()).await {
+            Ok(()) => log_info!(self.logger, "We are synced!"),
             Err(e) => {
                 log_error!(self.logger, "Failed to sync on-chain wallet: {e}");
-                Err(e)
+                return Err(e);
+            }
+        }
+
+        self.storage.set_last_chain_sync(utils::now().as_secs())?;
+        self.claim_deferred_payments().await;
+
+        span.enter("index_onchain_activity");
+        let res = self.index_onchain_activity();
+        span.exit(&res);
+        if let Err(e) = res {
+            log_warn!(self.logger, "Failed to index on-chain activity: {e}");
+        }
+
+        Ok(())
+    }
+
+    /// Claims the payments that strict mode deferred until the chain was synced
+    async fn claim_deferred_payments(&self) {
+        let nodes = self.nodes.lock().await;
+        for node in nodes.values() {
+            for preimage in node.deferred_claims.drain() {
+                log_info!(
+                    self.logger,
+                    "Claiming deferred payment from payment hash {}",
+                    sha256::Hash::hash(&preimage.0).to_hex()
+                );
+                node.channel_manager.claim_funds(preimage);
             }
         }
     }
@@ -1349,6 +2077,103 @@ impl<S: MutinyStorage> NodeManager<S> {
             .get_est_sat_per_1000_weight(ConfirmationTarget::HighPriority)
     }
 
+    /// Checks the fee estimate against the congestion policy, new channels are
+    /// deferred while the breaker is tripped.
+    pub(crate) fn update_congestion_breaker(&self) {
+        let sats_per_kw = self
+            .fee_estimator
+            .get_est_sat_per_1000_weight(self.congestion.fee_target());
+        match self.congestion.update(sats_per_kw, utils::now().as_secs()) {
+            Some(true) => log_warn!(
+                self.logger,
+                "Fees are high, deferring new channels until they come down"
+            ),
+            Some(false) => log_info!(self.logger, "Fees came down, resuming new channels"),
+            None => {}
+        }
+    }
+
+    /// Returns the state of the congestion breaker and the operations it deferred.
+    pub fn congestion_status(&self) -> CongestionStatus {
+        self.congestion.status()
+    }
+
+    /// Consolidates the small utxos of the wallet if the consolidation policy is
+    /// enabled, the background fee estimate is low enough, and the last
+    /// consolidation was long enough ago. Returns the consolidation if one was
+    /// broadcast, it is labeled as one in the activity.
+    pub(crate) async fn consolidate_if_due(&self) -> Result<Option<Consolidation>, MutinyError> {
+        if !self.consolidation.is_enabled() {
+            return Ok(None);
+        }
+
+        let sats_per_vbyte = self
+            .fee_estimator
+            .get_est_sat_per_1000_weight(ConfirmationTarget::Background)
+            / 250;
+        let now = utils::now().as_secs();
+        let last = self.last_consolidation()?;
+        if !self
+            .consolidation
+            .is_due(sats_per_vbyte, last.map(|c| c.timestamp), now)
+        {
+            return Ok(None);
+        }
+
+        let address_labels = self.get_address_labels()?;
+        let candidates: Vec<ConsolidationCandidate> = self
+            .list_utxos()?
+            .into_iter()
+            .map(|utxo| {
+                let labels = Address::from_script(&utxo.txout.script_pubkey, self.network)
+                    .ok()
+                    .and_then(|addr| address_labels.get(&addr.to_string()).cloned())
+                    .unwrap_or_default();
+                ConsolidationCandidate {
+                    outpoint: utxo.outpoint,
+                    value: utxo.txout.value,
+                    confirmed: matches!(utxo.confirmation_time, ConfirmationTime::Confirmed { .. }),
+                    labels,
+                }
+            })
+            .collect();
+
+        let Some(utxos) = self.consolidation.select(&candidates, sats_per_vbyte) else {
+            return Ok(None);
+        };
+        let amount_sats = candidates
+            .iter()
+            .filter(|c| utxos.contains(&c.outpoint))
+            .map(|c| c.value)
+            .sum();
+
+        let txid = self
+            .wallet
+            .consolidate(
+                &utxos,
+                vec![CONSOLIDATION_LABEL.to_string()],
+                sats_per_vbyte as f32,
+            )
+            .await?;
+
+        let consolidation = Consolidation {
+            txid,
+            inputs: utxos.len(),
+            amount_sats,
+            sats_per_vbyte,
+            timestamp: now,
+        };
+        self.storage
+            .set_data(LAST_CONSOLIDATION_KEY, &consolidation)?;
+
+        Ok(Some(consolidation))
+    }
+
+    /// Returns the last automatic consolidation of small utxos.
+    pub fn last_consolidation(&self) -> Result<Option<Consolidation>, MutinyError> {
+        self.storage.get_data(LAST_CONSOLIDATION_KEY)
+    }
+
     /// Creates a new lightning node and adds it to the manager.
     pub async fn new_node(&self) -> Result<NodeIdentity, MutinyError> {
         create_new_node_from_node_manager(self).await
@@ -1461,8 +2286,19 @@ impl<S: MutinyStorage> NodeManager<S> {
         self_node_pubkey: &PublicKey,
         peer: &NodeId,
     ) -> Result<(), MutinyError> {
-        if let Some(node) = self.nodes.lock().await.get(self_node_pubkey) {
+        let nodes = self.nodes.lock().await;
+        if let Some(node) = nodes.get(self_node_pubkey) {
             gossip::delete_peer_info(&self.storage, &node._uuid, peer)?;
+
+            // the backup permissions are gone with the last of the metadata
+            if gossip::read_peer_info(&self.storage, peer)?.is_none() {
+                if let Ok(pubkey) = peer.as_pubkey() {
+                    for node in nodes.values() {
+                        node.scb_message_handler
+                            .set_backup_permissions(pubkey, PeerBackupPermissions::default());
+                    }
+                }
+            }
             Ok(())
         } else {
             log_error!(
@@ -1514,11 +2350,14 @@ impl<S: MutinyStorage> NodeManager<S> {
         } else {
             return Err(MutinyError::WalletOperationFailed);
         };
-        let invoice = first_node
-            .create_invoice(amount, labels, route_hints)
-            .await?;
+        let mut span = self.tracer.start("create_invoice", None);
+        let res = first_node.create_invoice(amount, labels, route_hints).await;
+        if let Ok(invoice) = res.as_ref() {
+            span.set_operation_id(invoice.payment_hash().to_hex());
+        }
+        span.finish(&res);
 
-        Ok(invoice.into())
+        Ok(res?.into())
     }
 
     /// Pays a lightning invoice from the selected node.
@@ -1536,8 +2375,35 @@ impl<S: MutinyStorage> NodeManager<S> {
         }
 
         let node = self.get_node(from_node).await?;
-        node.pay_invoice_with_timeout(invoice, amt_sats, None, labels)
+        let self_payment = self
+            .nodes
+            .lock()
+            .await
+            .contains_key(&invoice.recover_payee_pub_key());
+
+        // trace with the payment hash, which is what the caller gets back
+        let mut span = self
+            .tracer
+            .start("pay_invoice", Some(invoice.payment_hash().to_hex()));
+        let res = async {
+            let (payment_hash, privacy) = span
+                .phase(
+                    "send_payment",
+                    node.init_invoice_payment(invoice, amt_sats, labels.clone(), self_payment),
+                )
+                .await?;
+            for (key, value) in privacy.annotations() {
+                span.annotate(key, value);
+            }
+            span.phase(
+                "await_payment",
+                node.await_payment(payment_hash, DEFAULT_PAYMENT_TIMEOUT, labels),
+            )
             .await
+        }
+        .await;
+        span.finish(&res);
+        res
     }
 
     /// Sends a spontaneous payment to a node from the selected node.
@@ -1706,6 +2572,45 @@ impl<S: MutinyStorage> NodeManager<S> {
         Err(MutinyError::NotFound)
     }
 
+    /// Marks one of our invoices as paid out of band, like when support verified a
+    /// payment by hand. The preimage has to match the payment hash and the invoice
+    /// must not already be paid. The invoice then shows as paid, but is excluded
+    /// from the lightning balance in [`LightningPaymentTotals`].
+    pub async fn mark_invoice_settled_external(
+        &self,
+        payment_hash: &sha256::Hash,
+        preimage: [u8; 32],
+        note: String,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        let nodes = self.nodes.lock().await;
+        for (_, node) in nodes.iter() {
+            match node.mark_invoice_settled_external(payment_hash, preimage, note.clone()) {
+                Err(MutinyError::NotFound) => continue,
+                res => return res,
+            }
+        }
+
+        Err(MutinyError::NotFound)
+    }
+
+    /// Gets the totals of the settled lightning payments of all the nodes.
+    pub async fn get_lightning_payment_totals(
+        &self,
+    ) -> Result<LightningPaymentTotals, MutinyError> {
+        let snapshot = self
+            .balance_sequence
+            .read(&self.last_payment_totals, || async move {
+                let invoices = self.list_invoices().await?;
+                Ok(LightningPaymentTotals::from_invoices(&invoices))
+            })
+            .await?;
+        Ok(LightningPaymentTotals {
+            sequence: snapshot.sequence,
+            taken_at: snapshot.taken_at,
+            ..snapshot.value
+        })
+    }
+
     /// Gets an invoice from the node manager.
     /// This includes sent and received invoices.
     pub async fn list_invoices(&self) -> Result<Vec<MutinyInvoice>, MutinyError> {
@@ -1719,6 +2624,37 @@ impl<S: MutinyStorage> NodeManager<S> {
         Ok(invoices)
     }
 
+    /// Gets the keys of the payment records that could not be read for all the nodes.
+    /// These are skipped when listing invoices and the activity.
+    pub async fn get_quarantine_report(&self) -> Result<QuarantineReport, MutinyError> {
+        let mut report = QuarantineReport::default();
+        let nodes = self.nodes.lock().await;
+        for (_, node) in nodes.iter() {
+            let node_report = node.persister.payment_quarantine_report()?;
+            report.corrupt_keys.extend(node_report.corrupt_keys);
+        }
+        Ok(report)
+    }
+
+    /// Tries to repair the payment records that could not be read for all the nodes.
+    /// Records that can not be repaired are moved under a quarantine prefix.
+    pub async fn repair_storage(&self) -> Result<RepairReport, MutinyError> {
+        let mut report = RepairReport::default();
+        let nodes = self.nodes.lock().await;
+        for (_, node) in nodes.iter() {
+            let node_report = node.persister.repair_payment_info()?;
+            report.repaired.extend(node_report.repaired);
+            report.quarantined.extend(node_report.quarantined);
+        }
+        log_info!(
+            self.logger,
+            "Repaired {} payment records, quarantined {}",
+            report.repaired.len(),
+            report.quarantined.len()
+        );
+        Ok(report)
+    }
+
     pub async fn get_channel_closure(
         &self,
         user_channel_id: u128,
@@ -1757,7 +2693,12 @@ impl<S: MutinyStorage> NodeManager<S> {
         fee_rate: Option<f32>,
         user_channel_id: Option<u128>,
     ) -> Result<MutinyChannel, MutinyError> {
+        self.congestion.check_now(ChannelOperation::OpenChannel)?;
         let node = self.get_node(from_node).await?;
+        // the funds show up in the channel once it is created, but only leave the
+        // wallet once the peer signed and the funding is broadcast. Balance reads
+        // return the balance from before meanwhile.
+        let _transition = self.balance_sequence.begin();
 
         let to_pubkey = match to_pubkey {
             Some(pubkey) => pubkey,
@@ -1769,9 +2710,25 @@ impl<S: MutinyStorage> NodeManager<S> {
             }
         };
 
-        let outpoint = node
-            .open_channel_with_timeout(to_pubkey, amount, fee_rate, user_channel_id, 60)
-            .await?;
+        let mut span = self.tracer.start("open_channel", None);
+        let res = async {
+            let user_channel_id = span
+                .phase(
+                    "init_open_channel",
+                    node.init_open_channel(to_pubkey, amount, fee_rate, user_channel_id),
+                )
+                .await?;
+            // trace with the user channel id, which is what the caller gets back
+            span.set_operation_id(user_channel_id.to_hex());
+            span.phase(
+                "await_funding_tx",
+                node.await_chan_funding_tx(user_channel_id, &to_pubkey, 60),
+            )
+            .await
+        }
+        .await;
+        span.finish(&res);
+        let outpoint = res?;
 
         let all_channels = node.channel_manager.list_channels();
         let found_channel = all_channels
@@ -1796,7 +2753,12 @@ impl<S: MutinyStorage> NodeManager<S> {
         utxos: &[OutPoint],
         to_pubkey: Option<PublicKey>,
     ) -> Result<MutinyChannel, MutinyError> {
+        self.congestion.check_now(ChannelOperation::OpenChannel)?;
         let node = self.get_node(from_node).await?;
+        // the funds show up in the channel once it is created, but only leave the
+        // wallet once the peer signed and the funding is broadcast. Balance reads
+        // return the balance from before meanwhile.
+        let _transition = self.balance_sequence.begin();
 
         let to_pubkey = match to_pubkey {
             Some(pubkey) => pubkey,
@@ -1905,6 +2867,19 @@ impl<S: MutinyStorage> NodeManager<S> {
                             MutinyError::ChannelClosingFailed
                         })?;
                 } else {
+                    let last_sync = self.storage.get_last_chain_sync()?;
+                    if let Err(e) = self
+                        .strict_mode
+                        .check_now(ChainOperation::CooperativeClose, last_sync)
+                    {
+                        log_warn!(
+                            self.logger,
+                            "Deferring close of channel {}, the chain has not been synced recently",
+                            &channel.channel_id.to_hex()
+                        );
+                        return Err(e);
+                    }
+
                     node.channel_manager
                         .close_channel(&channel.channel_id, &channel.counterparty.node_id)
                         .map_err(|e| {
@@ -1945,178 +2920,1078 @@ impl<S: MutinyStorage> NodeManager<S> {
     }
 
     fn get_scb_key(&self) -> SecretKey {
+        self.get_scb_key_for(self.scb_account)
+    }
+
+    /// The SCB key of the given account, see [`scb_key_derivation_path`].
+    fn get_scb_key_for(&self, account: Option<u32>) -> SecretKey {
         let seed = self.mnemonic.to_seed("");
         let xprivkey = ExtendedPrivKey::new_master(self.network, &seed).unwrap();
-        let path = DerivationPath::from_str(SCB_ENCRYPTION_KEY_DERIVATION_PATH).unwrap();
+        // the configured account is checked on startup and read accounts when decoding
+        let path = scb_key_derivation_path(account).unwrap();
         let context = Secp256k1::new();
 
         xprivkey.derive_priv(&context, &path).unwrap().private_key
     }
 
+    /// Encrypts the backup with the SCB key of the configured account. With the
+    /// privacy padding enabled the backup has no fingerprint, so the peers and relays
+    /// it is sent to can not tell which copies hold the same backup.
+    fn encrypt_scb(&self, scb: &StaticChannelBackupStorage) -> EncryptedSCB {
+        let with_fingerprint = self.scb_padding_buckets.is_empty();
+        scb.encrypt_for(&self.get_scb_key(), self.scb_account, with_fingerprint)
+    }
+
+    /// Decrypts the backup with the SCB key of the account it was created with,
+    /// which may not be the configured one.
+    fn decrypt_scb(&self, scb: &EncryptedSCB) -> Result<StaticChannelBackupStorage, MutinyError> {
+        scb.decrypt(&self.get_scb_key_for(scb.account()))
+    }
+
     /// Creates a static channel backup for all the nodes in the node manager.
     /// The backup is encrypted with the SCB key.
     pub async fn create_static_channel_backup(&self) -> Result<EncryptedSCB, MutinyError> {
+        let mut span = self.tracer.start("static_channel_backup", None);
+        let res = self.create_static_channel_backup_phases(&mut span).await;
+        span.finish(&res);
+        res
+    }
+
+    /// Creates a static channel backup as an armored text block that can be saved
+    /// to a file, next to the bech32 string. See [`EncryptedSCB::to_armored`].
+    pub async fn create_static_channel_backup_armored(&self) -> Result<String, MutinyError> {
+        Ok(self.create_static_channel_backup().await?.to_armored())
+    }
+
+    async fn create_static_channel_backup_phases(
+        &self,
+        span: &mut OperationSpan,
+    ) -> Result<EncryptedSCB, MutinyError> {
+        let scb = self.build_static_channel_backup(span).await?;
+
+        // encrypt
+        span.enter("encrypt");
+        let scb = self.encrypt_scb(&scb);
+        span.exit::<_, MutinyError>(&Ok(()));
+        log_debug!(
+            self.logger,
+            "Created SCB with a size of {} bytes",
+            scb.encode().len()
+        );
+        Ok(scb)
+    }
+
+    /// Creates the unencrypted backup of all the nodes and bumps the latest generation.
+    async fn build_static_channel_backup(
+        &self,
+        span: &mut OperationSpan,
+    ) -> Result<StaticChannelBackupStorage, MutinyError> {
         let nodes = self.nodes.lock().await;
-        let mut backups: HashMap<PublicKey, (NodeIndex, StaticChannelBackup)> = HashMap::new();
-        for (_, node) in nodes.iter() {
-            let scb = node.create_static_channel_backup()?;
-            backups.insert(node.pubkey, (node.node_index(), scb));
-        }
+        span.enter("backup_nodes");
+        let backups: Result<HashMap<PublicKey, (NodeIndex, StaticChannelBackup)>, MutinyError> =
+            nodes
+                .iter()
+                .map(|(_, node)| {
+                    let scb = node.create_static_channel_backup()?;
+                    let mut node_index = node.node_index();
+                    // an alias that is too long to be read back is left out
+                    node_index.alias = node_index
+                        .alias
+                        .filter(|a| a.len() <= MAX_STRING_LEN as usize);
+                    Ok((node.pubkey, (node_index, scb)))
+                })
+                .collect();
+        span.exit(&backups);
+        let backups = backups?;
 
         let peers = get_all_peers(&self.storage).unwrap_or_default();
 
+        // strings that are too long to be read back are left out
+        let fits = |s: &String| s.len() <= MAX_STRING_LEN as usize;
+
         let peer_connections = peers
+            .iter()
+            .filter_map(|(n, p)| {
+                let pubkey = n.as_pubkey().unwrap();
+                // malformed connection strings could not be read back either
+                p.connection_string
+                    .clone()
+                    .filter(|c| validate_connection_string(&pubkey, c).is_ok())
+                    .map(|str| (pubkey, vec![str]))
+            })
+            .collect::<HashMap<_, _>>();
+
+        // so a restore tries the peers that were reachable most recently first
+        let peer_last_connected = peers
+            .iter()
+            .filter_map(|(n, p)| {
+                let pubkey = n.as_pubkey().unwrap();
+                p.last_connected
+                    .filter(|_| peer_connections.contains_key(&pubkey))
+                    .map(|last| (pubkey, last))
+            })
+            .collect::<HashMap<_, _>>();
+
+        let peer_metadata = peers
+            .into_iter()
+            .map(|(n, p)| {
+                let metadata = ScbPeerMetadata {
+                    alias: p.alias.filter(fits),
+                    color: p.color.filter(fits),
+                    label: p.label.filter(fits),
+                };
+                (n.as_pubkey().unwrap(), metadata)
+            })
+            .filter(|(_, metadata)| !metadata.is_empty())
+            .collect::<HashMap<_, _>>();
+
+        // only the labels of the channels in the backup
+        let channel_labels = self
+            .storage
+            .get_channel_labels()
+            .unwrap_or_default()
             .into_iter()
-            .filter_map(|(n, p)| p.connection_string.map(|str| (n.as_pubkey().unwrap(), str)))
+            .filter(|(outpoint, label)| {
+                fits(label)
+                    && backups
+                        .values()
+                        .any(|(_, b)| b.monitors.contains_key(outpoint))
+            })
             .collect::<HashMap<_, _>>();
 
+        let latest = self.latest_scb_generation()?;
+        let created_at = utils::now().as_secs();
+        let generation = latest.generation + 1;
         let scb = StaticChannelBackupStorage {
             backups,
             peer_connections,
+            created_at,
+            generation,
+            network: Some(self.network),
+            channel_labels,
+            peer_metadata,
+            peer_last_connected,
         };
+        self.storage
+            .set_data(SCB_LATEST_GENERATION_KEY, scb.generation_info())?;
 
-        // encrypt
-        let encryption_key = self.get_scb_key();
-        let scb = scb.encrypt(&encryption_key);
+        Ok(scb)
+    }
+
+    /// Creates a new static channel backup and returns only what changed since the
+    /// given previous backup, so a remote that holds the previous backup does not
+    /// need the full backup again. The diff is encrypted with the SCB key like a
+    /// full backup, but can only be restored applied to the backup it was created from.
+    pub async fn create_static_channel_backup_diff(
+        &self,
+        previous: &EncryptedSCB,
+    ) -> Result<EncryptedSCB, MutinyError> {
+        let previous = self.decrypt_scb(previous)?;
+        let mut span = self.tracer.start("static_channel_backup_diff", None);
+        let res = self.build_static_channel_backup(&mut span).await;
+        span.finish(&res);
+
+        let diff = res?.diff(&previous);
         log_debug!(
             self.logger,
-            "Created SCB with a size of {} bytes",
-            scb.encode().len()
+            "Created SCB diff with {} added, {} changed and {} removed monitors",
+            diff.added.len(),
+            diff.changed.len(),
+            diff.removed.len()
         );
-        Ok(scb)
+        Ok(diff.encrypt(&self.get_scb_key(), self.scb_account))
     }
 
-    /// Takes an encrypted static channel backup and recovers the channels from it.
-    /// If the backup is encrypted with a different key than the current key, it will fail.
-    pub async fn recover_from_static_channel_backup(
+    /// Applies a diff from [`NodeManager::create_static_channel_backup_diff`] to the
+    /// backup it was created from and returns the updated backup, encrypted again.
+    pub fn apply_static_channel_backup_diff(
         &self,
-        scb: EncryptedSCB,
-    ) -> Result<(), MutinyError> {
-        // decrypt
-        let encryption_key = self.get_scb_key();
-        let scb = scb.decrypt(&encryption_key)?;
+        previous: &EncryptedSCB,
+        diff: &EncryptedSCB,
+    ) -> Result<EncryptedSCB, MutinyError> {
+        let mut scb = self.decrypt_scb(previous)?;
+        let diff = diff.decrypt_diff(&self.get_scb_key_for(diff.account()))?;
+        scb.apply_diff(&diff)?;
+        Ok(self.encrypt_scb(&scb))
+    }
 
-        // stop all nodes, todo stop in parallel
-        for node in self.nodes.lock().await.values() {
-            node.stop().await?;
-        }
+    /// Re-encrypts a static channel backup created with the current SCB key under
+    /// the given new key. Backups are not persisted by the node manager, so the
+    /// rotated backup is returned for the caller to store in place of the old one.
+    pub fn rotate_static_channel_backup_key(
+        &self,
+        scb: &EncryptedSCB,
+        new_key: &SecretKey,
+    ) -> Result<EncryptedSCB, MutinyError> {
+        scb.rotate_key(&self.get_scb_key_for(scb.account()), new_key)
+    }
 
-        for (pubkey, (node_index, backup)) in scb.backups {
-            // find the uuid if we have it, otherwise create a new one and save it
-            let uuid = {
-                let mut node_mutex = self.node_storage.lock().await;
-                let current = node_mutex
-                    .nodes
-                    .iter()
-                    .find(|(_, n)| *n == &node_index)
-                    .map(|(uuid, _)| uuid.clone());
-
-                match current {
-                    Some(uuid) => uuid,
-                    None => {
-                        let mut existing_nodes = self.storage.get_nodes()?;
-                        let new_uuid = Uuid::new_v4().to_string();
-                        existing_nodes
-                            .nodes
-                            .insert(new_uuid.clone(), node_index.clone());
-
-                        self.storage.insert_nodes(existing_nodes.clone())?;
-                        node_mutex.nodes = existing_nodes.nodes.clone();
-
-                        new_uuid
-                    }
-                }
-            };
+    /// Decrypts the given static channel backup and returns a summary of what would be
+    /// recovered from it. This does not modify any state, so it is safe to call before
+    /// [`NodeManager::recover_from_static_channel_backup`].
+    pub fn preview_static_channel_backup(&self, scb: &str) -> Result<ScbSummary, MutinyError> {
+        let scb = EncryptedSCB::parse(scb)?;
+        Ok(ScbSummary::from(&self.decrypt_scb(&scb)?))
+    }
 
-            // create a fresh instance of each node
-            let new_node = Node::new(
-                uuid,
-                &node_index,
-                &self.mnemonic,
-                self.storage.clone(),
-                self.gossip_sync.clone(),
-                self.scorer.clone(),
-                self.chain.clone(),
-                self.fee_estimator.clone(),
+    /// Decrypts the given static channel backup and reports for each channel whether
+    /// it can be recovered, see [`StaticChannelBackupStorage::recovery_report`]. Like
+    /// [`NodeManager::preview_static_channel_backup`] this does not modify any state.
+    pub fn static_channel_backup_recovery_report(
+        &self,
+        scb: &str,
+    ) -> Result<Vec<ChannelRecoveryInfo>, MutinyError> {
+        let scb = EncryptedSCB::parse(scb)?;
+        let storage = self.decrypt_scb(&scb)?;
+        Ok(storage.recovery_report(&self.scb_channel_peers(&storage)))
+    }
+
+    /// The peers of the channels of the backup, read from their monitors with the
+    /// keys of the node they belong to. Monitors that can not be read are left out.
+    fn scb_channel_peers(
+        &self,
+        storage: &StaticChannelBackupStorage,
+    ) -> HashMap<OutPoint, PublicKey> {
+        let mut peers = HashMap::new();
+        for (node_index, backup) in storage.backups.values() {
+            let keys_manager = match keymanager::create_keys_manager(
                 self.wallet.clone(),
-                self.network,
-                self.esplora.clone(),
-                &self.lsp_clients,
+                &self.mnemonic,
+                node_index.child_index,
                 self.logger.clone(),
-                true,
-                true,
-                #[cfg(target_arch = "wasm32")]
-                self.websocket_proxy_addr.clone(),
-            )
-            .await;
-
-            match new_node {
-                Ok(node) => {
-                    debug_assert!(node.node_index().child_index == node_index.child_index);
-                    log_info!(
+            ) {
+                Ok(keys_manager) => keys_manager,
+                Err(e) => {
+                    log_warn!(
                         self.logger,
-                        "Recovering node {pubkey} from static channel backup"
+                        "could not derive the keys of a backup node: {e}"
                     );
-                    node.recover_from_static_channel_backup(backup, &scb.peer_connections)
-                        .await?;
+                    continue;
                 }
-                Err(_) => {
-                    log_error!(
-                        self.logger,
-                        "Could not find node with pubkey {pubkey} to recover from backup",
-                    );
+            };
+
+            for (outpoint, monitor) in backup.monitors.iter() {
+                let read = <(BlockHash, ChannelMonitor<InMemorySigner>)>::read(
+                    &mut lightning::io::Cursor::new(monitor),
+                    (&keys_manager, &keys_manager),
+                );
+                match read.map(|(_, monitor)| monitor.get_counterparty_node_id()) {
+                    Ok(Some(peer)) => {
+                        peers.insert(*outpoint, peer);
+                    }
+                    _ => log_warn!(self.logger, "could not read the peer of {outpoint}"),
                 }
             }
         }
+        peers
+    }
 
-        Ok(())
+    /// Decrypts the given static channel backup and returns the node indexes in it,
+    /// sorted by child index, so a recovered node can use the LSP it was using.
+    pub fn list_scb_node_indexes(
+        &self,
+        scb: &str,
+    ) -> Result<Vec<(PublicKey, NodeIndex)>, MutinyError> {
+        let scb = EncryptedSCB::parse(scb)?;
+        let mut nodes: Vec<(PublicKey, NodeIndex)> = self
+            .decrypt_scb(&scb)?
+            .backups
+            .into_iter()
+            .map(|(pubkey, (node_index, _))| (pubkey, node_index))
+            .collect();
+        nodes.sort_by_key(|(_, node_index)| node_index.child_index());
+        Ok(nodes)
     }
 
-    /// Lists all the peers for all the nodes in the node manager.
-    pub async fn list_peers(&self) -> Result<Vec<MutinyPeer>, MutinyError> {
-        let peer_data = gossip::get_all_peers(&self.storage)?;
+    /// Generates a recovery kit, a document with everything needed to recover the
+    /// wallet besides the seed, meant to be printed. It contains no secrets. With
+    /// `include_scb` a new static channel backup is created and included, it is
+    /// encrypted with a key derived from the seed.
+    pub async fn generate_recovery_kit(
+        &self,
+        include_scb: bool,
+    ) -> Result<RecoveryKit, MutinyError> {
+        let xprivkey = ExtendedPrivKey::new_master(self.network, &self.mnemonic.to_seed(""))?;
+        let wallet_id = xprivkey.fingerprint(&Secp256k1::new()).to_string();
 
-        // get peers saved in storage
-        let mut storage_peers: Vec<MutinyPeer> = peer_data
-            .iter()
-            .map(|(node_id, metadata)| MutinyPeer {
-                // node id should be safe here
-                pubkey: PublicKey::from_slice(node_id.as_slice()).expect("Invalid pubkey"),
-                connection_string: metadata.connection_string.clone(),
-                alias: metadata.alias.clone(),
-                color: metadata.color.clone(),
-                label: metadata.label.clone(),
-                is_connected: false,
+        let scb = if include_scb {
+            Some(self.create_static_channel_backup().await?.to_string())
+        } else {
+            None
+        };
+
+        let pubkeys: HashMap<u32, PublicKey> = self
+            .nodes
+            .lock()
+            .await
+            .values()
+            .map(|n| (n.child_index, n.pubkey))
+            .collect();
+        let mut nodes: Vec<RecoveryKitNode> = self
+            .node_storage
+            .lock()
+            .await
+            .nodes
+            .values()
+            .map(|n| RecoveryKitNode {
+                pubkey: pubkeys.get(&n.child_index).copied(),
+                child_index: n.child_index,
+                key_path: format!("m/0'/{}'", n.child_index),
+                lsp: n.lsp.clone(),
+                archived: n.is_archived(),
             })
             .collect();
+        nodes.sort_by_key(|n| n.child_index);
 
-        let nodes = self.nodes.lock().await;
+        let mut instructions = vec![RecoveryKitStep::RestoreSeed, RecoveryKitStep::SyncOnChain];
+        if scb.is_some() {
+            instructions.push(RecoveryKitStep::RecoverChannels);
+        }
 
-        // get peers we are connected to
-        let connected_peers: Vec<PublicKey> = nodes
-            .iter()
-            .flat_map(|(_, n)| n.peer_manager.get_peer_node_ids())
-            .collect();
+        let kit = RecoveryKit {
+            version: RECOVERY_KIT_VERSION,
+            wallet_id,
+            created_at: utils::now().as_secs(),
+            network: self.network,
+            scb,
+            scb_key_path: scb_key_derivation_path(self.scb_account)?.to_string(),
+            onchain_descriptors: get_tr_public_descriptors(xprivkey, self.network, 0)?,
+            nodes,
+            esplora_url: self.esplora_url.clone(),
+            lsp_urls: self.lsp_clients.iter().map(|l| l.url.clone()).collect(),
+            instructions,
+            integrity_hash: String::new(),
+        };
+        Ok(kit.seal())
+    }
 
-        // correctly set is_connected
-        for mut peer in &mut storage_peers {
-            if connected_peers.contains(&peer.pubkey) {
-                peer.is_connected = true;
-            }
+    /// Checks the integrity hash of a recovery kit and reports which of its
+    /// components can be used for recovery.
+    pub fn validate_recovery_kit(&self, json: &str) -> Result<RecoveryKitValidation, MutinyError> {
+        validate_recovery_kit(json)
+    }
+
+    /// Returns the metadata of the given static channel backup that can be read
+    /// without decrypting it, such as the network it was created on.
+    pub fn inspect_scb_metadata(&self, scb: &str) -> Result<ScbMetadata, MutinyError> {
+        inspect_scb_metadata(scb)
+    }
+
+    /// Creates a new static channel backup if a channel monitor changed and the
+    /// last automatic backup is older than the configured interval.
+    /// Returns whether a backup was created.
+    pub(crate) async fn run_auto_backup(&self, now: u64) -> Result<bool, MutinyError> {
+        // check every node, so all of their flags are cleared
+        let changed = self.nodes.lock().await.values().fold(false, |changed, n| {
+            n.persister.take_monitors_changed() || changed
+        });
+
+        let mut auto_backup = self.auto_backup.lock().await;
+        if changed {
+            auto_backup.mark_changed(now);
+        }
+        if !auto_backup.should_backup(now) {
+            return Ok(false);
         }
 
-        // add any connected peers that weren't in our storage,
-        // likely new or inbound connections
-        let mut missing: Vec<MutinyPeer> = Vec::new();
-        for peer in connected_peers {
-            if !storage_peers.iter().any(|p| p.pubkey == peer) {
-                let new = MutinyPeer {
-                    pubkey: peer,
-                    connection_string: None,
-                    alias: None,
-                    color: None,
-                    label: None,
+        // this is the backup sent to the backup peers
+        let scb = self
+            .create_static_channel_backup()
+            .await?
+            .pad_to_bucket(&self.scb_padding_buckets);
+        let backup = AutoBackup {
+            backup: scb.to_string(),
+            created_at: now,
+        };
+        self.storage.set_data(SCB_AUTO_BACKUP_KEY, backup)?;
+        auto_backup.backed_up(now);
+        log_debug!(self.logger, "Created automatic SCB");
+
+        Ok(true)
+    }
+
+    /// Returns the latest automatic static channel backup.
+    fn latest_auto_backup(&self) -> Result<Option<EncryptedSCB>, MutinyError> {
+        let backup: Option<AutoBackup> = self.storage.get_data(SCB_AUTO_BACKUP_KEY)?;
+        backup
+            .map(|b| {
+                EncryptedSCB::from_str(&b.backup).map_err(|_| MutinyError::InvalidArgumentsError)
+            })
+            .transpose()
+    }
+
+    /// Returns the epoch time in seconds of the last successful automatic static
+    /// channel backup, if there was one.
+    pub fn last_auto_backup_time(&self) -> Result<Option<u64>, MutinyError> {
+        let backup: Option<AutoBackup> = self.storage.get_data(SCB_AUTO_BACKUP_KEY)?;
+        Ok(backup.map(|b| b.created_at))
+    }
+
+    /// The generation of the latest backup created or restored on this device
+    fn latest_scb_generation(&self) -> Result<ScbGeneration, MutinyError> {
+        Ok(self
+            .storage
+            .get_data(SCB_LATEST_GENERATION_KEY)?
+            .unwrap_or_default())
+    }
+
+    /// Returns which static channel backups are exchanged with the peer.
+    pub fn get_peer_backup_permissions(
+        &self,
+        peer: &PublicKey,
+    ) -> Result<PeerBackupPermissions, MutinyError> {
+        Ok(
+            gossip::read_peer_info(&self.storage, &NodeId::from_pubkey(peer))?
+                .map(|p| p.backup_permissions())
+                .unwrap_or_default(),
+        )
+    }
+
+    /// Sets which static channel backups are exchanged with the peer, nothing is
+    /// exchanged with a peer unless opted in. Revoking deletes the backup we store
+    /// for the peer, and asks the peer to delete ours if it is connected.
+    pub async fn set_peer_backup_permissions(
+        &self,
+        peer: PublicKey,
+        permissions: PeerBackupPermissions,
+    ) -> Result<(), MutinyError> {
+        gossip::set_peer_backup_permissions(
+            &self.storage,
+            &NodeId::from_pubkey(&peer),
+            permissions,
+        )?;
+
+        {
+            let nodes = self.nodes.lock().await;
+            for node in nodes.values() {
+                node.scb_message_handler
+                    .set_backup_permissions(peer, permissions);
+                if node.scb_message_handler.has_pending_messages() {
+                    node.peer_manager.process_events();
+                }
+            }
+        }
+
+        if permissions.send_backup_to {
+            if let Err(e) = self.push_backup_to_peers().await {
+                log_warn!(self.logger, "could not send our backup to {peer}: {e}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Designates a peer to store our encrypted static channel backup. The backup is
+    /// sent to it whenever a channel monitor changes and a node is connected to it.
+    pub async fn add_backup_peer(&self, peer: PublicKey) -> Result<(), MutinyError> {
+        let permissions = PeerBackupPermissions {
+            send_backup_to: true,
+            ..self.get_peer_backup_permissions(&peer)?
+        };
+        self.set_peer_backup_permissions(peer, permissions).await
+    }
+
+    /// Stops sending our static channel backup to the given peer, and asks it to
+    /// delete the last backup it was sent.
+    pub async fn remove_backup_peer(&self, peer: &PublicKey) -> Result<(), MutinyError> {
+        let permissions = PeerBackupPermissions {
+            send_backup_to: false,
+            ..self.get_peer_backup_permissions(peer)?
+        };
+        self.set_peer_backup_permissions(*peer, permissions).await
+    }
+
+    /// Lists the peers designated to store our static channel backup.
+    pub fn list_backup_peers(&self) -> Result<Vec<PublicKey>, MutinyError> {
+        let mut peers: Vec<PublicKey> = gossip::get_all_peers(&self.storage)?
+            .into_iter()
+            .filter(|(_, p)| p.send_backup_to)
+            .filter_map(|(n, _)| n.as_pubkey().ok())
+            .collect();
+        peers.sort();
+        Ok(peers)
+    }
+
+    /// Lists the peers that confirmed storing a static channel backup for one of
+    /// our nodes since it started.
+    pub async fn list_backup_holders(&self) -> Vec<PublicKey> {
+        let nodes = self.nodes.lock().await;
+        let mut holders: Vec<PublicKey> = nodes
+            .values()
+            .flat_map(|n| n.scb_message_handler.backup_holders())
+            .collect();
+        holders.sort();
+        holders.dedup();
+        holders
+    }
+
+    /// Sends the latest automatic static channel backup to the connected backup
+    /// peers that do not hold it yet.
+    pub(crate) async fn push_backup_to_peers(&self) -> Result<(), MutinyError> {
+        let backup_peers = self.list_backup_peers()?;
+        if backup_peers.is_empty() {
+            return Ok(());
+        }
+
+        let Some(backup) = self.latest_auto_backup()? else {
+            return Ok(());
+        };
+        let backup = backup.encode();
+        let digest = sha256::Hash::hash(&backup).into_inner();
+
+        let nodes = self.nodes.lock().await;
+        for node in nodes.values() {
+            let connected = node.peer_manager.get_peer_node_ids();
+            let mut sent = false;
+            for peer in backup_peers.iter().filter(|p| connected.contains(p)) {
+                if !node.scb_message_handler.holds_backup(peer, &digest) {
+                    node.scb_message_handler
+                        .send_backup(*peer, backup.clone())?;
+                    sent = true;
+                }
+            }
+            if sent {
+                node.peer_manager.process_events();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Asks the given peer for the static channel backup it stores for one of our
+    /// nodes. Each node connects to the peer and proves it owns its node id by doing
+    /// so, the peer only returns the backup stored for that node id.
+    /// The returned backup can be restored with
+    /// [`NodeManager::recover_from_static_channel_backup`].
+    pub async fn retrieve_backup_from_peer(
+        &self,
+        connection_string: &str,
+    ) -> Result<EncryptedSCB, MutinyError> {
+        let connect_info = PubkeyConnectionInfo::new(connection_string)?;
+        let peer = connect_info.pubkey;
+        let nodes: Vec<Arc<Node<S>>> = self.nodes.lock().await.values().cloned().collect();
+
+        for node in nodes.iter() {
+            node.connect_peer(connect_info.clone(), None).await?;
+            node.scb_message_handler.request_backup(peer);
+            node.peer_manager.process_events();
+        }
+
+        let mut answered = 0;
+        for _ in 0..PEER_BACKUP_TIMEOUT_SECS {
+            for node in nodes.iter() {
+                match node.scb_message_handler.take_retrieved_backup(&peer) {
+                    Some(Some(backup)) => {
+                        return EncryptedSCB::read(&mut lightning::io::Cursor::new(backup))
+                            .map_err(|_| MutinyError::InvalidArgumentsError);
+                    }
+                    Some(None) => answered += 1,
+                    None => {}
+                }
+            }
+            if answered == nodes.len() {
+                break;
+            }
+            sleep(1_000).await;
+        }
+
+        log_warn!(self.logger, "peer {peer} did not return a backup");
+        Err(MutinyError::PeerBackupNotFound)
+    }
+
+    fn get_scb_nostr_keys(&self) -> Keys {
+        let seed = self.mnemonic.to_seed("");
+        let xprivkey = ExtendedPrivKey::new_master(self.network, &seed).unwrap();
+        let path = DerivationPath::from_str(SCB_NOSTR_KEY_DERIVATION_PATH).unwrap();
+        let context = Secp256k1::new();
+        let key = xprivkey.derive_priv(&context, &path).unwrap().private_key;
+
+        // just converting to nostr secret key, unwrap is safe
+        let secret_key = nostr::key::SecretKey::from_slice(&key.secret_bytes()).unwrap();
+        Keys::new(secret_key)
+    }
+
+    /// Creates a static channel backup and publishes it to the given Nostr relays,
+    /// replacing the previous backup on each relay. Succeeds if at least one of
+    /// the relays accepted it, and returns the relays that did.
+    pub async fn backup_scb_to_nostr(
+        &self,
+        relays: Vec<String>,
+    ) -> Result<Vec<String>, MutinyError> {
+        let backup = self
+            .create_static_channel_backup()
+            .await?
+            .pad_to_bucket(&self.scb_padding_buckets);
+        let keys = self.get_scb_nostr_keys();
+        let transport = NostrSdkTransport::new(keys.clone());
+
+        publish_backup(&transport, &keys, &backup, &relays, &self.logger).await
+    }
+
+    /// Fetches the latest static channel backup of this wallet from the given Nostr
+    /// relays, relays that fail or do not have it are skipped. The backup can
+    /// then be restored with [`NodeManager::recover_from_static_channel_backup`].
+    pub async fn restore_scb_from_nostr(
+        &self,
+        relays: Vec<String>,
+    ) -> Result<EncryptedSCB, MutinyError> {
+        let keys = self.get_scb_nostr_keys();
+        let transport = NostrSdkTransport::new(keys.clone());
+
+        fetch_latest_backup(&transport, keys.public_key(), &relays, &self.logger).await
+    }
+
+    fn scb_remote_backup(
+        &self,
+        url: &str,
+    ) -> Result<ScbRemoteBackup<HttpScbTransport>, MutinyError> {
+        let seed = self.mnemonic.to_seed("");
+        let xprivkey = ExtendedPrivKey::new_master(self.network, &seed).unwrap();
+        let path = DerivationPath::from_str(SCB_REMOTE_KEY_DERIVATION_PATH).unwrap();
+        let context = Secp256k1::new();
+        let key = xprivkey.derive_priv(&context, &path).unwrap().private_key;
+
+        let transport = HttpScbTransport::new(url)?;
+        Ok(ScbRemoteBackup::new(transport, key, self.logger.clone()))
+    }
+
+    /// Creates a static channel backup and stores it on the given HTTPS endpoint.
+    /// Fails if the endpoint already has a newer backup, such as one created on
+    /// another device.
+    pub async fn backup_scb_to_remote(&self, url: &str) -> Result<(), MutinyError> {
+        let remote = self.scb_remote_backup(url)?;
+        let backup = self
+            .create_static_channel_backup()
+            .await?
+            .pad_to_bucket(&self.scb_padding_buckets);
+        let generation = self.latest_scb_generation()?.generation;
+
+        remote
+            .store(&backup, generation, utils::now().as_secs())
+            .await
+    }
+
+    /// Sends the latest automatic static channel backup to the configured endpoint.
+    pub(crate) async fn push_backup_to_remote(&self) -> Result<(), MutinyError> {
+        let Some(url) = self.scb_remote_url.as_ref() else {
+            return Ok(());
+        };
+        let Some(backup) = self.latest_auto_backup()? else {
+            return Ok(());
+        };
+        let generation = self.decrypt_scb(&backup)?.generation;
+
+        let now = utils::now().as_secs();
+        let res = self
+            .scb_remote_backup(url)?
+            .store(&backup, generation, now)
+            .await;
+        if let Err(MutinyError::RemoteBackupStale) = res {
+            let event = WebhookEvent::backup_stale(generation, now);
+            enqueue_webhook_event(&self.storage, &self.logger, event);
+        }
+        res
+    }
+
+    /// Fetches the static channel backup of this wallet from the given HTTPS
+    /// endpoint. The backup can then be restored with
+    /// [`NodeManager::recover_from_static_channel_backup`].
+    pub async fn restore_scb_from_remote(&self, url: &str) -> Result<EncryptedSCB, MutinyError> {
+        let (backup, _) = self
+            .scb_remote_backup(url)?
+            .fetch(utils::now().as_secs())
+            .await?;
+        Ok(backup)
+    }
+
+    /// Reads the channels from the `channel.backup` file of an LND node, decrypted
+    /// with the LND root key. See [`crate::scb::lnd`] for what can be recovered.
+    pub fn import_lnd_channel_backup(
+        &self,
+        backup: &[u8],
+        root_key: &ExtendedPrivKey,
+    ) -> Result<LndBackupImport, MutinyError> {
+        import_lnd_backup(backup, &lnd_backup_key(root_key), self.network)
+    }
+
+    fn webhooks(&self) -> Webhooks<S> {
+        Webhooks::new(self.storage.clone(), self.logger.clone())
+    }
+
+    /// Lists the webhook endpoints, with the secrets their deliveries are signed with.
+    pub fn list_webhooks(&self) -> Result<Vec<WebhookEndpoint>, MutinyError> {
+        self.webhooks().endpoints()
+    }
+
+    /// Adds a webhook endpoint that is sent the given kinds of events.
+    /// The url has to be a public HTTPS url.
+    pub fn add_webhook(
+        &self,
+        url: &str,
+        kinds: Vec<WebhookEventKind>,
+    ) -> Result<WebhookEndpoint, MutinyError> {
+        self.webhooks().add_endpoint(url, kinds)
+    }
+
+    /// Removes the webhook endpoint, dropping the events still queued for it.
+    pub fn remove_webhook(&self, id: &str) -> Result<(), MutinyError> {
+        self.webhooks().remove_endpoint(id)
+    }
+
+    /// Sends a ping to the webhook endpoint, failing if it did not accept it.
+    pub async fn test_webhook(&self, id: &str) -> Result<(), MutinyError> {
+        self.webhooks()
+            .test_endpoint(&HttpWebhookTransport::new(), id, utils::now().as_secs())
+            .await
+    }
+
+    /// Returns how the deliveries to the webhook endpoint went.
+    pub fn get_webhook_stats(&self, id: &str) -> Result<WebhookStats, MutinyError> {
+        self.webhooks().stats(id)
+    }
+
+    /// Returns the events that could not be delivered to the webhook endpoint.
+    pub fn get_webhook_dead_letters(&self, id: &str) -> Result<Vec<WebhookDelivery>, MutinyError> {
+        self.webhooks().dead_letters(id)
+    }
+
+    /// Takes an encrypted static channel backup and recovers the channels from it.
+    /// If the backup is encrypted with a different key than the current key, it will fail.
+    ///
+    /// The result is flagged as stale if a newer backup has already been created or
+    /// restored on this device.
+    ///
+    /// Channels this wallet already has a newer monitor for are skipped and listed in
+    /// the result, restoring them would replace it with an older state. With `force`
+    /// they are restored anyway.
+    pub async fn recover_from_static_channel_backup(
+        &self,
+        scb: EncryptedSCB,
+        force: bool,
+    ) -> Result<ScbRestoreResult, MutinyError> {
+        self.recover_from_static_channel_backup_with_progress(scb, force, &|_| {})
+            .await
+    }
+
+    /// Like [`NodeManager::recover_from_static_channel_backup`], calling `on_progress`
+    /// as the backup is decrypted, each node and monitor is restored and peers are
+    /// reconnected, and with [`ScbRestoreProgress::Done`] once it finished.
+    pub async fn recover_from_static_channel_backup_with_progress(
+        &self,
+        scb: EncryptedSCB,
+        force: bool,
+        on_progress: &dyn Fn(ScbRestoreProgress),
+    ) -> Result<ScbRestoreResult, MutinyError> {
+        // decrypt
+        let scb = self.decrypt_scb(&scb)?;
+        on_progress(ScbRestoreProgress::Decrypted);
+
+        self.restore_static_channel_backup_storage(scb, force, on_progress)
+            .await
+    }
+
+    /// Recovers only the selected nodes from the given static channel backup,
+    /// the other nodes in the backup are skipped and nothing is stored for them.
+    /// Use [`NodeManager::preview_static_channel_backup`] to list the nodes in a backup.
+    pub async fn restore_scb_selected(
+        &self,
+        scb: &str,
+        selected_nodes: &[PublicKey],
+    ) -> Result<ScbRestoreResult, MutinyError> {
+        let scb = EncryptedSCB::parse(scb)?;
+        let scb = self.decrypt_scb(&scb)?;
+        let selected = scb.select_nodes(selected_nodes)?;
+
+        self.restore_static_channel_backup_storage(selected, false, &|_| {})
+            .await
+    }
+
+    /// Writes the channel labels and peer metadata of the backup back into storage.
+    /// Labels that were set on this device since are kept.
+    fn restore_scb_labels(&self, scb: &StaticChannelBackupStorage) -> Result<(), MutinyError> {
+        let existing = self.storage.get_channel_labels()?;
+        for (outpoint, label) in scb.channel_labels.iter() {
+            if !existing.contains_key(outpoint) {
+                self.storage
+                    .set_channel_label(*outpoint, Some(label.clone()))?;
+            }
+        }
+
+        for (pubkey, metadata) in scb.peer_metadata.iter() {
+            // without a timestamp the metadata we already have takes precedence
+            let info = LnPeerMetadata {
+                alias: metadata.alias.clone(),
+                color: metadata.color.clone(),
+                label: metadata.label.clone(),
+                last_connected: scb.peer_last_connected.get(pubkey).copied(),
+                ..Default::default()
+            };
+            gossip::save_ln_peer_info(&self.storage, &NodeId::from_pubkey(pubkey), &info)?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks the network and generation of a backup that is about to be restored,
+    /// and makes sure the next backup created is newer than it.
+    fn check_scb_restore(
+        &self,
+        scb: &StaticChannelBackupStorage,
+    ) -> Result<ScbRestoreResult, MutinyError> {
+        let missing_network = !scb.check_network(self.network)?;
+        if missing_network {
+            log_warn!(
+                self.logger,
+                "Restoring a backup that does not record its network, expected {}",
+                self.network
+            );
+        }
+
+        let latest = self.latest_scb_generation()?;
+        let is_stale = latest.is_newer_than(&scb.generation_info());
+        if is_stale {
+            log_warn!(
+                self.logger,
+                "Restoring backup generation {} but generation {} already exists",
+                scb.generation,
+                latest.generation
+            );
+        } else {
+            // make sure the next backup we create is newer than this one
+            self.storage
+                .set_data(SCB_LATEST_GENERATION_KEY, scb.generation_info())?;
+        }
+
+        Ok(ScbRestoreResult {
+            is_stale,
+            missing_network,
+            skipped_channels: vec![],
+        })
+    }
+
+    /// Creates a fresh instance of the node from a backup, saving the node if we
+    /// do not have it yet.
+    async fn restore_scb_node(&self, node_index: &NodeIndex) -> Result<Node<S>, MutinyError> {
+        // find the uuid if we have it, otherwise create a new one and save it
+        let uuid = {
+            let mut node_mutex = self.node_storage.lock().await;
+            let current = node_mutex
+                .nodes
+                .iter()
+                .find(|(_, n)| n.same_node(node_index))
+                .map(|(uuid, _)| uuid.clone());
+
+            match current {
+                Some(uuid) => uuid,
+                None => {
+                    let mut existing_nodes = self.storage.get_nodes()?;
+                    let new_uuid = Uuid::new_v4().to_string();
+                    existing_nodes
+                        .nodes
+                        .insert(new_uuid.clone(), node_index.clone());
+
+                    self.storage.insert_nodes(existing_nodes.clone())?;
+                    node_mutex.nodes = existing_nodes.nodes.clone();
+
+                    new_uuid
+                }
+            }
+        };
+
+        let node = Node::new(
+            uuid,
+            node_index,
+            &self.mnemonic,
+            self.storage.clone(),
+            self.gossip_sync.clone(),
+            self.scorer.clone(),
+            self.chain.clone(),
+            self.fee_estimator.clone(),
+            self.wallet.clone(),
+            self.network,
+            self.esplora.clone(),
+            &self.lsp_clients,
+            self.logger.clone(),
+            true,
+            self.strict_mode,
+            self.congestion.clone(),
+            self.payment_privacy,
+            true,
+            #[cfg(target_arch = "wasm32")]
+            self.websocket_proxy_addr.clone(),
+            #[cfg(not(target_arch = "wasm32"))]
+            self.dns_resolver.clone(),
+        )
+        .await?;
+        debug_assert!(node.node_index().child_index == node_index.child_index);
+
+        Ok(node)
+    }
+
+    async fn restore_static_channel_backup_storage(
+        &self,
+        scb: StaticChannelBackupStorage,
+        force: bool,
+        on_progress: &dyn Fn(ScbRestoreProgress),
+    ) -> Result<ScbRestoreResult, MutinyError> {
+        let mut result = self.check_scb_restore(&scb)?;
+        self.restore_scb_labels(&scb)?;
+
+        // stop all nodes, todo stop in parallel
+        for node in self.nodes.lock().await.values() {
+            node.stop().await?;
+        }
+
+        for (pubkey, (node_index, backup)) in scb.backups {
+            match self.restore_scb_node(&node_index).await {
+                Ok(node) => {
+                    log_info!(
+                        self.logger,
+                        "Recovering node {pubkey} from static channel backup"
+                    );
+                    let skipped = node
+                        .recover_from_static_channel_backup(
+                            backup,
+                            &scb.peer_connections,
+                            &scb.peer_last_connected,
+                            force,
+                            on_progress,
+                        )
+                        .await?;
+                    result.skipped_channels.extend(skipped);
+                    on_progress(ScbRestoreProgress::NodeRestored(pubkey));
+                }
+                Err(_) => {
+                    log_error!(
+                        self.logger,
+                        "Could not find node with pubkey {pubkey} to recover from backup",
+                    );
+                }
+            }
+        }
+
+        on_progress(ScbRestoreProgress::Done);
+        Ok(result)
+    }
+
+    /// Recovers only the funds of the channels in the given static channel backup,
+    /// for when there is nothing else to restore the nodes from.
+    ///
+    /// Channels this wallet has a newer monitor for are not swept, like when
+    /// restoring, and are reported as [`ScbSweepStatus::NewerLocalState`].
+    ///
+    /// Peers that can be reached are asked to force close. Cooperative closes need
+    /// channel state a backup does not have. The other peers are asked to once they
+    /// reconnect, and their channels are reported as [`ScbSweepStatus::PeerUnreachable`].
+    ///
+    /// With `broadcast_unreachable` our latest commitment from the backup is broadcast
+    /// for those channels instead. Only a backup newer than any created or restored on
+    /// this device is broadcast, but a fresh device can not tell whether an old copy of
+    /// a backup is revoked, and broadcasting a revoked commitment loses the channel
+    /// funds to a penalty. The funds are swept to the on-chain wallet once they can be
+    /// claimed. Returns the status of each channel.
+    pub async fn sweep_from_scb(
+        &self,
+        scb: &str,
+        broadcast_unreachable: bool,
+    ) -> Result<Vec<ScbSweepResult>, MutinyError> {
+        let scb = EncryptedSCB::parse(scb)?;
+        let scb = self.decrypt_scb(&scb)?;
+        let restore = self.check_scb_restore(&scb)?;
+        self.restore_scb_labels(&scb)?;
+
+        for node in self.nodes.lock().await.values() {
+            node.stop().await?;
+        }
+
+        let mut results = vec![];
+        for (pubkey, (node_index, backup)) in scb.backups {
+            match self.restore_scb_node(&node_index).await {
+                Ok(node) => {
+                    log_info!(
+                        self.logger,
+                        "Sweeping node {pubkey} from static channel backup"
+                    );
+                    let swept = node
+                        .sweep_from_static_channel_backup(
+                            backup,
+                            &scb.peer_connections,
+                            &scb.peer_last_connected,
+                            broadcast_unreachable,
+                            restore.is_stale,
+                        )
+                        .await;
+                    results.extend(swept);
+                }
+                Err(e) => {
+                    log_error!(self.logger, "Could not start node {pubkey} to sweep: {e}");
+                    results.extend(
+                        backup
+                            .monitors
+                            .into_keys()
+                            .map(|funding_txo| ScbSweepResult {
+                                funding_txo,
+                                peer: None,
+                                status: ScbSweepStatus::Failed(ScbSweepFailure::NodeUnavailable),
+                            }),
+                    );
+                }
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// Lists all the peers for all the nodes in the node manager.
+    pub async fn list_peers(&self) -> Result<Vec<MutinyPeer>, MutinyError> {
+        let peer_data = gossip::get_all_peers(&self.storage)?;
+
+        // get peers saved in storage
+        let mut storage_peers: Vec<MutinyPeer> = peer_data
+            .iter()
+            .map(|(node_id, metadata)| MutinyPeer {
+                // node id should be safe here
+                pubkey: PublicKey::from_slice(node_id.as_slice()).expect("Invalid pubkey"),
+                connection_string: metadata.connection_string.clone(),
+                alias: metadata.alias.clone(),
+                color: metadata.color.clone(),
+                label: metadata.label.clone(),
+                is_connected: false,
+                queued_messages: 0,
+            })
+            .collect();
+
+        let nodes = self.nodes.lock().await;
+
+        // get peers we are connected to
+        let connected_peers: Vec<PublicKey> = nodes
+            .iter()
+            .flat_map(|(_, n)| n.peer_manager.get_peer_node_ids())
+            .collect();
+
+        // correctly set is_connected and the queued messages
+        for mut peer in &mut storage_peers {
+            if connected_peers.contains(&peer.pubkey) {
+                peer.is_connected = true;
+            }
+            peer.queued_messages = nodes
+                .values()
+                .map(|n| n.scb_message_handler.queued_message_count(&peer.pubkey))
+                .sum();
+        }
+
+        // add any connected peers that weren't in our storage,
+        // likely new or inbound connections
+        let mut missing: Vec<MutinyPeer> = Vec::new();
+        for peer in connected_peers {
+            if !storage_peers.iter().any(|p| p.pubkey == peer) {
+                let new = MutinyPeer {
+                    pubkey: peer,
+                    connection_string: None,
+                    alias: None,
+                    color: None,
+                    label: None,
                     is_connected: true,
+                    queued_messages: 0,
                 };
                 missing.push(new);
             }
@@ -2228,6 +4103,18 @@ impl<S: MutinyStorage> NodeManager<S> {
         logger.get_logs(&storage)
     }
 
+    /// Returns the latest trace of the operation with the given id, like the
+    /// payment hash of a payment or the user channel id of a channel open.
+    /// Operations without an id, like a sync, use their name.
+    pub fn get_operation_trace(&self, operation_id: &str) -> Option<OperationTrace> {
+        self.tracer.get(operation_id)
+    }
+
+    /// Returns the traces of the most recent operations, oldest first.
+    pub fn recent_operation_traces(&self) -> Vec<OperationTrace> {
+        self.tracer.recent()
+    }
+
     /// Resets the scorer and network graph. This can be useful if you get stuck in a bad state.
     pub async fn reset_router(&self) -> Result<(), MutinyError> {
         // if we're not connected to the db, start it up
@@ -2281,7 +4168,8 @@ impl<S: MutinyStorage> NodeManager<S> {
         let serde_map = serde_json::map::Map::from_iter(map.into_iter().filter(|(k, _)| {
             // filter out logs and network graph
             // these are really big and not needed for export
-            !matches!(k.as_str(), LOGGING_KEY | NETWORK_GRAPH_KEY)
+            // chunked values are already read whole, so their chunks are left out
+            !matches!(k.as_str(), LOGGING_KEY | NETWORK_GRAPH_KEY) && !is_chunk_key(k)
         }));
 
         // shut back down after reading if it was already closed
@@ -2298,6 +4186,28 @@ struct CoingeckoResponse {
     pub bitcoin: CoingeckoPrice,
 }
 
+/// Moves the backup peers saved before there were per-peer backup permissions
+/// into the peer metadata.
+fn migrate_backup_peers(storage: &impl MutinyStorage) -> Result<(), MutinyError> {
+    let peers: Option<Vec<PublicKey>> = storage.get_data(SCB_BACKUP_PEERS_KEY)?;
+    let Some(peers) = peers else {
+        return Ok(());
+    };
+
+    for peer in peers {
+        let node_id = NodeId::from_pubkey(&peer);
+        let current = gossip::read_peer_info(storage, &node_id)?
+            .map(|p| p.backup_permissions())
+            .unwrap_or_default();
+        let permissions = PeerBackupPermissions {
+            send_backup_to: true,
+            ..current
+        };
+        gossip::set_peer_backup_permissions(storage, &node_id, permissions)?;
+    }
+    storage.delete(&[SCB_BACKUP_PEERS_KEY])
+}
+
 #[derive(Deserialize, Clone, Copy, Debug)]
 struct CoingeckoPrice {
     pub usd: f32,
@@ -2347,6 +4257,8 @@ pub(crate) async fn create_new_node_from_node_manager<S: MutinyStorage>(
         child_index: next_node_index,
         lsp,
         archived: Some(false),
+        created_at: Some(utils::now().as_secs()),
+        alias: None,
     };
 
     existing_nodes
@@ -2372,9 +4284,14 @@ pub(crate) async fn create_new_node_from_node_manager<S: MutinyStorage>(
         &node_manager.lsp_clients,
         node_manager.logger.clone(),
         node_manager.do_not_connect_peers,
+        node_manager.strict_mode,
+        node_manager.congestion.clone(),
+        node_manager.payment_privacy,
         false,
         #[cfg(target_arch = "wasm32")]
         node_manager.websocket_proxy_addr.clone(),
+        #[cfg(not(target_arch = "wasm32"))]
+        node_manager.dns_resolver.clone(),
     )
     .await;
 
@@ -2384,6 +4301,7 @@ pub(crate) async fn create_new_node_from_node_manager<S: MutinyStorage>(
     };
 
     let node_pubkey = new_node.pubkey;
+    let lsp_pubkey = new_node.lsp_client.as_ref().map(|l| l.pubkey);
     node_manager
         .nodes
         .clone()
@@ -2391,47 +4309,291 @@ pub(crate) async fn create_new_node_from_node_manager<S: MutinyStorage>(
         .await
         .insert(node_pubkey, Arc::new(new_node));
 
+    // opted in to storing our backup with the LSP when it was configured
+    if let Some(lsp_pubkey) = lsp_pubkey.filter(|_| node_manager.scb_lsp_backup) {
+        node_manager.add_backup_peer(lsp_pubkey).await?;
+    }
+
     Ok(NodeIdentity {
         uuid: next_node_uuid.clone(),
         pubkey: node_pubkey,
     })
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::nodemanager::{
-        ActivityItem, ChannelClosure, MutinyInvoice, NodeManager, TransactionDetails,
-    };
-    use crate::{keymanager::generate_seed, MutinyWalletConfig};
-    use bdk::chain::ConfirmationTime;
-    use bitcoin::hashes::hex::{FromHex, ToHex};
-    use bitcoin::hashes::{sha256, Hash};
-    use bitcoin::secp256k1::PublicKey;
-    use bitcoin::{Network, PackedLockTime, Transaction, TxOut, Txid};
-    use lightning::ln::PaymentHash;
-    use lightning_invoice::Invoice;
-    use std::str::FromStr;
+#[cfg(test)]
+mod tests {
+    use crate::nodemanager::{
+        ActivityItem, ChannelClosure, LightningPaymentTotals, MutinyInvoice, NodeIndex,
+        NodeManager, TransactionDetails,
+    };
+    use crate::recovery_kit::{RecoveryKitComponent, RecoveryKitStep};
+    use crate::scb::{
+        EncryptedSCB, ScbPrivacyConfig, ScbRestoreProgress, StaticChannelBackup,
+        StaticChannelBackupStorage,
+    };
+    use crate::{keymanager::generate_seed, MutinyWalletConfig};
+    use bdk::chain::ConfirmationTime;
+    use bitcoin::hashes::hex::{FromHex, ToHex};
+    use bitcoin::hashes::{sha256, Hash};
+    use bitcoin::secp256k1::{PublicKey, Secp256k1};
+    use bitcoin::{Network, PackedLockTime, Transaction, TxOut, Txid};
+    use lightning::ln::PaymentHash;
+    use lightning::util::ser::Writeable;
+    use lightning_invoice::Invoice;
+    use std::cell::RefCell;
+    use std::str::FromStr;
+
+    use crate::test_utils::*;
+
+    use crate::error::MutinyError;
+    use crate::event::{ExternalSettlement, HTLCStatus, MillisatAmount, PaymentInfo};
+    use crate::ldkstorage::{CHANNEL_MANAGER_ALT_KEY, CHANNEL_MANAGER_KEY};
+    use crate::lifecycle::{Lifecycle, LifecycleState, WalletComponent};
+    use crate::logging::MutinyLogger;
+    use crate::storage::{MemoryStorage, MutinyStorage};
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    const BOLT_11: &str = "lntbs1m1pjrmuu3pp52hk0j956d7s8azaps87amadshnrcvqtkvk06y2nue2w69g6e5vasdqqcqzpgxqyz5vqsp5wu3py6257pa3yzarw0et2200c08r5fu6k3u94yfwmlnc8skdkc9s9qyyssqc783940p82c64qq9pu3xczt4tdxzex9wpjn54486y866aayft2cxxusl9eags4cs3kcmuqdrvhvs0gudpj5r2a6awu4wcq29crpesjcqhdju55";
+
+    #[test]
+    async fn create_node_manager() {
+        let test_name = "create_node_manager";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::new(Some(uuid::Uuid::new_v4().to_string()));
+
+        assert!(!NodeManager::has_node_manager(storage.clone()));
+        let c = MutinyWalletConfig::new(
+            None,
+            #[cfg(target_arch = "wasm32")]
+            None,
+            Some(Network::Regtest),
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        NodeManager::new(c, storage.clone())
+            .await
+            .expect("node manager should initialize");
+        assert!(NodeManager::has_node_manager(storage));
+    }
+
+    #[test]
+    async fn correctly_show_seed() {
+        let test_name = "correctly_show_seed";
+        log!("{}", test_name);
+
+        let seed = generate_seed(12).expect("Failed to gen seed");
+        let c = MutinyWalletConfig::new(
+            Some(seed.clone()),
+            #[cfg(target_arch = "wasm32")]
+            None,
+            Some(Network::Regtest),
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        let nm = NodeManager::new(c, ()).await.unwrap();
+
+        assert_eq!(seed, nm.show_seed());
+    }
+
+    #[test]
+    async fn lifecycle_state_transitions() {
+        let test_name = "lifecycle_state_transitions";
+        log!("{}", test_name);
+
+        // the lifecycle is created before the node manager, so startup can be followed
+        let lifecycle = Lifecycle::new();
+        assert!(matches!(
+            lifecycle.check_running(),
+            Err(MutinyError::WalletInitializing {
+                completed_steps: 0,
+                total_steps: 0
+            })
+        ));
+
+        let storage = MemoryStorage::new(Some(uuid::Uuid::new_v4().to_string()));
+        let seed = generate_seed(12).expect("Failed to gen seed");
+        let c = MutinyWalletConfig::new(
+            Some(seed),
+            #[cfg(target_arch = "wasm32")]
+            None,
+            Some(Network::Regtest),
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .with_lifecycle(lifecycle.clone());
+        let nm = NodeManager::new(c.clone(), storage.clone())
+            .await
+            .expect("node manager should initialize");
+        assert_eq!(lifecycle.state(), LifecycleState::Ready);
+        assert!(nm.check_running().is_ok());
+        assert!(nm.check_lightning().is_ok());
+
+        let node = nm.new_node().await.expect("should create new node");
+        nm.stop().await.expect("should stop");
+        assert_eq!(nm.lifecycle_state(), LifecycleState::Stopped);
+        assert!(matches!(
+            nm.check_running(),
+            Err(MutinyError::WalletStopped)
+        ));
+        assert!(matches!(
+            nm.check_lightning(),
+            Err(MutinyError::WalletStopped)
+        ));
+
+        // a node that fails to start only takes lightning down
+        for key in [CHANNEL_MANAGER_KEY, CHANNEL_MANAGER_ALT_KEY] {
+            storage
+                .set_data(format!("{key}_{}", node.uuid), "not a channel manager")
+                .unwrap();
+        }
+        let nm = NodeManager::new(c.clone(), storage.clone())
+            .await
+            .expect("on-chain should still start");
+        let LifecycleState::Degraded(failures) = lifecycle.state() else {
+            panic!("expected the wallet to be degraded");
+        };
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].component, WalletComponent::Lightning);
+        assert_eq!(failures[0].node_id, Some(node.uuid.clone()));
+        assert!(failures[0]
+            .error
+            .contains("No channel manager could be read"));
+        assert!(nm.check_running().is_ok());
+        assert!(matches!(
+            nm.check_lightning(),
+            Err(MutinyError::ComponentUnavailable(
+                WalletComponent::Lightning
+            ))
+        ));
+        // the node that failed is kept
+        assert!(storage.get_nodes().unwrap().nodes.contains_key(&node.uuid));
+        nm.stop().await.expect("should stop");
+
+        // failing to start at all leaves the wallet stopped
+        let c = c.with_scb_account(1 << 31);
+        assert!(NodeManager::new(c, storage).await.is_err());
+        assert!(matches!(
+            lifecycle.check_running(),
+            Err(MutinyError::WalletStopped)
+        ));
+    }
+
+    #[test]
+    async fn created_new_nodes() {
+        let test_name = "created_new_nodes";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::new(Some(uuid::Uuid::new_v4().to_string()));
+        let seed = generate_seed(12).expect("Failed to gen seed");
+        let c = MutinyWalletConfig::new(
+            Some(seed),
+            #[cfg(target_arch = "wasm32")]
+            None,
+            Some(Network::Regtest),
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        let nm = NodeManager::new(c, storage)
+            .await
+            .expect("node manager should initialize");
+
+        {
+            let node_identity = nm.new_node().await.expect("should create new node");
+            let node_storage = nm.node_storage.lock().await;
+            assert_ne!("", node_identity.uuid);
+            assert_ne!("", node_identity.pubkey.to_string());
+            assert_eq!(1, node_storage.nodes.len());
 
-    use crate::test_utils::*;
+            let retrieved_node = node_storage.nodes.get(&node_identity.uuid).unwrap();
+            assert_eq!(0, retrieved_node.child_index);
+        }
 
-    use crate::event::{HTLCStatus, MillisatAmount, PaymentInfo};
-    use crate::storage::MemoryStorage;
-    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+        {
+            let node_identity = nm.new_node().await.expect("node manager should initialize");
+            let node_storage = nm.node_storage.lock().await;
 
-    wasm_bindgen_test_configure!(run_in_browser);
+            assert_ne!("", node_identity.uuid);
+            assert_ne!("", node_identity.pubkey.to_string());
+            assert_eq!(2, node_storage.nodes.len());
 
-    const BOLT_11: &str = "lntbs1m1pjrmuu3pp52hk0j956d7s8azaps87amadshnrcvqtkvk06y2nue2w69g6e5vasdqqcqzpgxqyz5vqsp5wu3py6257pa3yzarw0et2200c08r5fu6k3u94yfwmlnc8skdkc9s9qyyssqc783940p82c64qq9pu3xczt4tdxzex9wpjn54486y866aayft2cxxusl9eags4cs3kcmuqdrvhvs0gudpj5r2a6awu4wcq29crpesjcqhdju55";
+            let retrieved_node = node_storage.nodes.get(&node_identity.uuid).unwrap();
+            assert_eq!(1, retrieved_node.child_index);
+        }
+    }
 
     #[test]
-    async fn create_node_manager() {
-        let test_name = "create_node_manager";
+    async fn auto_backup_is_debounced() {
+        let test_name = "auto_backup_is_debounced";
         log!("{}", test_name);
 
         let storage = MemoryStorage::new(Some(uuid::Uuid::new_v4().to_string()));
-
-        assert!(!NodeManager::has_node_manager(storage.clone()));
+        let seed = generate_seed(12).expect("Failed to gen seed");
         let c = MutinyWalletConfig::new(
+            Some(seed),
+            #[cfg(target_arch = "wasm32")]
+            None,
+            Some(Network::Regtest),
             None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .with_auto_backup_interval(60);
+        let nm = NodeManager::new(c, storage)
+            .await
+            .expect("node manager should initialize");
+        let node = nm.new_node().await.expect("should create new node");
+        let node = nm.get_node(&node.pubkey).await.unwrap();
+        let now = crate::utils::now().as_secs();
+
+        // the monitors loaded at startup count as a change
+        assert_eq!(nm.last_auto_backup_time().unwrap(), None);
+        assert!(nm.run_auto_backup(now).await.unwrap());
+        assert_eq!(nm.last_auto_backup_time().unwrap(), Some(now));
+        let first = nm.latest_auto_backup().unwrap().unwrap();
+
+        // nothing changed, no backup
+        assert!(!nm.run_auto_backup(now + 120).await.unwrap());
+
+        // two monitor updates inside the debounce window only produce one backup
+        node.persister.monitor_persisted();
+        assert!(!nm.run_auto_backup(now + 10).await.unwrap());
+        node.persister.monitor_persisted();
+        assert!(!nm.run_auto_backup(now + 20).await.unwrap());
+        assert_eq!(nm.last_auto_backup_time().unwrap(), Some(now));
+        assert_eq!(nm.latest_auto_backup().unwrap().unwrap(), first);
+
+        // the pending change is backed up once the window passed
+        assert!(nm.run_auto_backup(now + 60).await.unwrap());
+        assert_eq!(nm.last_auto_backup_time().unwrap(), Some(now + 60));
+        assert!(!nm.run_auto_backup(now + 61).await.unwrap());
+    }
+
+    #[test]
+    async fn balance_during_slow_channel_funding() {
+        let test_name = "balance_during_slow_channel_funding";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::new(Some(uuid::Uuid::new_v4().to_string()));
+        let seed = generate_seed(12).expect("Failed to gen seed");
+        let c = MutinyWalletConfig::new(
+            Some(seed),
             #[cfg(target_arch = "wasm32")]
             None,
             Some(Network::Regtest),
@@ -2441,20 +4603,36 @@ mod tests {
             None,
             None,
         );
-        NodeManager::new(c, storage.clone())
+        let nm = NodeManager::new(c, storage)
             .await
             .expect("node manager should initialize");
-        assert!(NodeManager::has_node_manager(storage));
+        let before = nm.get_balance().await.unwrap();
+        let totals = nm.get_lightning_payment_totals().await.unwrap();
+
+        // a funding waiting for the peer outlasts the retries of the readers,
+        // they get the last reads instead of failing
+        let transition = nm.balance_sequence.begin();
+        let during = nm.get_balance().await.unwrap();
+        assert_eq!(during.sequence, before.sequence);
+        assert_eq!(during.taken_at, before.taken_at);
+        let totals_during = nm.get_lightning_payment_totals().await.unwrap();
+        assert_eq!(totals_during, totals);
+
+        drop(transition);
+        let after = nm.get_balance().await.unwrap();
+        assert!(after.sequence > before.sequence);
     }
 
     #[test]
-    async fn correctly_show_seed() {
-        let test_name = "correctly_show_seed";
+    async fn pay_invoice_without_route_fails_fast() {
+        let test_name = "pay_invoice_without_route_fails_fast";
         log!("{}", test_name);
 
+        let storage = MemoryStorage::new(Some(uuid::Uuid::new_v4().to_string()));
+        storage.set_done_first_sync().unwrap();
         let seed = generate_seed(12).expect("Failed to gen seed");
         let c = MutinyWalletConfig::new(
-            Some(seed.clone()),
+            Some(seed),
             #[cfg(target_arch = "wasm32")]
             None,
             Some(Network::Regtest),
@@ -2464,14 +4642,46 @@ mod tests {
             None,
             None,
         );
-        let nm = NodeManager::new(c, ()).await.unwrap();
+        let nm = NodeManager::new(c, storage)
+            .await
+            .expect("node manager should initialize");
+        let payer = nm.new_node().await.expect("should create new node");
+        let payer = nm.get_node(&payer.pubkey).await.unwrap();
+        let payee = nm.new_node().await.expect("should create new node");
+        let payee = nm.get_node(&payee.pubkey).await.unwrap();
 
-        assert_eq!(seed, nm.show_seed());
+        let invoice = payee
+            .create_invoice(Some(1_000), vec![], None)
+            .await
+            .expect("should create invoice");
+        let payment_hash = PaymentHash(invoice.payment_hash().into_inner());
+
+        // without channels the payment fails right away instead of waiting for peers
+        // or a direct connection, and says why
+        let start = crate::utils::now();
+        let result = payer
+            .init_invoice_payment(&invoice, None, vec![], false)
+            .await;
+        assert!(matches!(
+            result,
+            Err(MutinyError::InsufficientBalance {
+                needed_sats: 1_000,
+                available_sats: 0
+            })
+        ));
+        assert!(crate::utils::now() - start < core::time::Duration::from_secs(5));
+
+        let logger = MutinyLogger::default();
+        let info = payer
+            .persister
+            .read_payment_info(&payment_hash, false, &logger)
+            .expect("payment should be persisted");
+        assert_eq!(info.status, HTLCStatus::Failed);
     }
 
     #[test]
-    async fn created_new_nodes() {
-        let test_name = "created_new_nodes";
+    async fn generate_and_validate_recovery_kit() {
+        let test_name = "generate_and_validate_recovery_kit";
         log!("{}", test_name);
 
         let storage = MemoryStorage::new(Some(uuid::Uuid::new_v4().to_string()));
@@ -2490,29 +4700,295 @@ mod tests {
         let nm = NodeManager::new(c, storage)
             .await
             .expect("node manager should initialize");
+        let node = nm.new_node().await.expect("should create new node");
+
+        let kit = nm.generate_recovery_kit(false).await.unwrap();
+        assert_eq!(kit.network, Network::Regtest);
+        assert_eq!(kit.scb, None);
+        assert_eq!(kit.onchain_descriptors.len(), 2);
+        assert_eq!(kit.nodes.len(), 1);
+        assert_eq!(kit.nodes[0].pubkey, Some(node.pubkey));
+        assert_eq!(kit.nodes[0].key_path, "m/0'/0'");
+        assert!(!kit.instructions.contains(&RecoveryKitStep::RecoverChannels));
+
+        // the kit identifies the wallet without containing the seed
+        let json = serde_json::to_string(&kit).unwrap();
+        assert!(!json.contains(&nm.show_seed().to_string()));
+        assert!(!json.contains("prv"));
+        assert_eq!(
+            nm.generate_recovery_kit(false).await.unwrap().wallet_id,
+            kit.wallet_id
+        );
 
-        {
-            let node_identity = nm.new_node().await.expect("should create new node");
-            let node_storage = nm.node_storage.lock().await;
-            assert_ne!("", node_identity.uuid);
-            assert_ne!("", node_identity.pubkey.to_string());
-            assert_eq!(1, node_storage.nodes.len());
+        let validation = nm.validate_recovery_kit(&json).unwrap();
+        assert!(validation.intact);
+        assert!(!validation.usable.contains(&RecoveryKitComponent::Scb));
+        assert!(validation
+            .usable
+            .contains(&RecoveryKitComponent::OnChainDescriptors));
+
+        // with the backup it can be used to recover the channels
+        let kit = nm.generate_recovery_kit(true).await.unwrap();
+        let scb = kit.scb.clone().unwrap();
+        assert!(nm.decrypt_scb(&EncryptedSCB::parse(&scb).unwrap()).is_ok());
+        assert_eq!(
+            kit.instructions.last(),
+            Some(&RecoveryKitStep::RecoverChannels)
+        );
+        let validation = nm
+            .validate_recovery_kit(&serde_json::to_string_pretty(&kit).unwrap())
+            .unwrap();
+        assert!(validation.intact);
+        assert!(validation.usable.contains(&RecoveryKitComponent::Scb));
+    }
 
-            let retrieved_node = node_storage.nodes.get(&node_identity.uuid).unwrap();
-            assert_eq!(0, retrieved_node.child_index);
+    #[test]
+    async fn auto_backup_is_jittered_and_padded() {
+        let test_name = "auto_backup_is_jittered_and_padded";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::new(Some(uuid::Uuid::new_v4().to_string()));
+        let seed = generate_seed(12).expect("Failed to gen seed");
+        let c = MutinyWalletConfig::new(
+            Some(seed),
+            #[cfg(target_arch = "wasm32")]
+            None,
+            Some(Network::Regtest),
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .with_auto_backup_interval(0)
+        .with_scb_privacy(ScbPrivacyConfig {
+            padding_buckets: vec![64 * 1024],
+            jitter_window_secs: 30,
+        });
+        let nm = NodeManager::new(c, storage)
+            .await
+            .expect("node manager should initialize");
+        let node = nm.new_node().await.expect("should create new node");
+        let node = nm.get_node(&node.pubkey).await.unwrap();
+        let now = crate::utils::now().as_secs();
+
+        // rapid changes are batched into one backup somewhere in the jitter window
+        let mut backups = 0;
+        for secs in 0..=30 {
+            node.persister.monitor_persisted();
+            if nm.run_auto_backup(now + secs).await.unwrap() {
+                backups += 1;
+                break;
+            }
         }
+        assert_eq!(backups, 1);
+        let backed_up_at = nm.last_auto_backup_time().unwrap().unwrap();
+        assert!(backed_up_at >= now && backed_up_at <= now + 30);
+        assert!(!nm.run_auto_backup(now + 31).await.unwrap());
+
+        // the backup sent to peers is padded to the bucket and can not be linked
+        // to other copies by its fingerprint
+        let backup = nm.latest_auto_backup().unwrap().unwrap();
+        assert_eq!(backup.encode().len(), 64 * 1024);
+        assert_eq!(backup.fingerprint(), None);
+        let restored = backup.decrypt(&nm.get_scb_key()).unwrap();
+        assert_eq!(restored.backups.len(), 1);
+    }
 
-        {
-            let node_identity = nm.new_node().await.expect("node manager should initialize");
-            let node_storage = nm.node_storage.lock().await;
+    #[test]
+    async fn scb_account_keys_are_independent() {
+        let test_name = "scb_account_keys_are_independent";
+        log!("{}", test_name);
 
-            assert_ne!("", node_identity.uuid);
-            assert_ne!("", node_identity.pubkey.to_string());
-            assert_eq!(2, node_storage.nodes.len());
+        let seed = generate_seed(12).expect("Failed to gen seed");
+        let config = |account: Option<u32>| {
+            let c = MutinyWalletConfig::new(
+                Some(seed.clone()),
+                #[cfg(target_arch = "wasm32")]
+                None,
+                Some(Network::Regtest),
+                None,
+                None,
+                None,
+                None,
+                None,
+            );
+            match account {
+                Some(account) => c.with_scb_account(account),
+                None => c,
+            }
+        };
+        let storage = || MemoryStorage::new(Some(uuid::Uuid::new_v4().to_string()));
+        let first = NodeManager::new(config(Some(0)), storage())
+            .await
+            .expect("node manager should initialize");
+        let second = NodeManager::new(config(Some(1)), storage())
+            .await
+            .expect("node manager should initialize");
 
-            let retrieved_node = node_storage.nodes.get(&node_identity.uuid).unwrap();
-            assert_eq!(1, retrieved_node.child_index);
-        }
+        // the same seed derives a different key for every account
+        let default_key = first.get_scb_key_for(None);
+        assert_ne!(first.get_scb_key(), second.get_scb_key());
+        assert_ne!(first.get_scb_key(), default_key);
+        assert_ne!(second.get_scb_key(), default_key);
+
+        let storage = StaticChannelBackupStorage::default();
+        let backup = second.encrypt_scb(&storage);
+        assert_eq!(backup.account(), Some(1));
+        assert!(backup.decrypt(&first.get_scb_key()).is_err());
+        assert!(backup.decrypt(&default_key).is_err());
+        assert!(first
+            .encrypt_scb(&storage)
+            .decrypt(&second.get_scb_key())
+            .is_err());
+
+        // restoring derives the key of the account in the backup
+        let scb = backup.to_string();
+        assert!(first.preview_static_channel_backup(&scb).is_ok());
+        assert!(first.decrypt_scb(&backup).unwrap() == storage);
+
+        // accounts that are not hardened indexes are rejected
+        assert!(matches!(
+            NodeManager::new(config(Some(1 << 31)), MemoryStorage::default()).await,
+            Err(MutinyError::InvalidArgumentsError)
+        ));
+    }
+
+    #[test]
+    async fn restore_scb_reports_progress() {
+        let test_name = "restore_scb_reports_progress";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::new(Some(uuid::Uuid::new_v4().to_string()));
+        let seed = generate_seed(12).expect("Failed to gen seed");
+        let c = MutinyWalletConfig::new(
+            Some(seed),
+            #[cfg(target_arch = "wasm32")]
+            None,
+            Some(Network::Regtest),
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        let nm = NodeManager::new(c, storage)
+            .await
+            .expect("node manager should initialize");
+
+        let first = PublicKey::from_str(
+            "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        )
+        .unwrap();
+        let second = PublicKey::from_str(
+            "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5",
+        )
+        .unwrap();
+        let node_index = |child_index| NodeIndex {
+            child_index,
+            lsp: None,
+            archived: Some(false),
+            created_at: None,
+            alias: None,
+        };
+        let scb = StaticChannelBackupStorage {
+            backups: vec![
+                (first, (node_index(3), StaticChannelBackup::default())),
+                (second, (node_index(4), StaticChannelBackup::default())),
+            ]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+        let scb = scb.encrypt(&nm.get_scb_key());
+
+        let events = RefCell::new(vec![]);
+        nm.recover_from_static_channel_backup_with_progress(scb, false, &|event| {
+            events.borrow_mut().push(event)
+        })
+        .await
+        .unwrap();
+
+        // the nodes are restored in any order between decrypting and finishing
+        let events = events.into_inner();
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[0], ScbRestoreProgress::Decrypted);
+        assert!(events[1..3].contains(&ScbRestoreProgress::NodeRestored(first)));
+        assert!(events[1..3].contains(&ScbRestoreProgress::NodeRestored(second)));
+        assert_eq!(events[3], ScbRestoreProgress::Done);
+    }
+
+    #[test]
+    async fn restore_selected_nodes_from_scb() {
+        let test_name = "restore_selected_nodes_from_scb";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::new(Some(uuid::Uuid::new_v4().to_string()));
+        let seed = generate_seed(12).expect("Failed to gen seed");
+        let c = MutinyWalletConfig::new(
+            Some(seed),
+            #[cfg(target_arch = "wasm32")]
+            None,
+            Some(Network::Regtest),
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        let nm = NodeManager::new(c, storage.clone())
+            .await
+            .expect("node manager should initialize");
+
+        let selected = PublicKey::from_str(
+            "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        )
+        .unwrap();
+        let skipped = PublicKey::from_str(
+            "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5",
+        )
+        .unwrap();
+        let node_index = |child_index, archived| NodeIndex {
+            child_index,
+            lsp: None,
+            archived: Some(archived),
+            created_at: None,
+            alias: None,
+        };
+        let scb = StaticChannelBackupStorage {
+            backups: vec![
+                (
+                    selected,
+                    (node_index(3, false), StaticChannelBackup::default()),
+                ),
+                (
+                    skipped,
+                    (node_index(4, true), StaticChannelBackup::default()),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+        let scb = scb.encrypt(&nm.get_scb_key()).to_string();
+
+        let summary = nm.preview_static_channel_backup(&scb).unwrap();
+        assert_eq!(summary.node_count(), 2);
+        assert!(summary.nodes[1].archived);
+
+        // selecting a node that is not in the backup fails before touching storage
+        let unknown = nm.get_scb_key().public_key(&Secp256k1::new());
+        assert!(nm.restore_scb_selected(&scb, &[unknown]).await.is_err());
+        assert!(storage.get_nodes().unwrap().nodes.is_empty());
+
+        let result = nm.restore_scb_selected(&scb, &[selected]).await.unwrap();
+        assert!(!result.is_stale);
+
+        // only the selected node was recreated, the skipped one left no trace
+        let nodes = storage.get_nodes().unwrap().nodes;
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(nodes.values().next().unwrap(), &node_index(3, false));
+        assert_eq!(nm.node_storage.lock().await.nodes, nodes);
     }
 
     #[test]
@@ -2608,6 +5084,7 @@ mod tests {
             bolt11: Some(invoice.clone()),
             payee_pubkey: None,
             last_update: 1681781585,
+            external_settlement: None,
         };
 
         let expected: MutinyInvoice = MutinyInvoice {
@@ -2618,7 +5095,10 @@ mod tests {
             payee_pubkey: None,
             amount_sats: Some(100_000),
             expire: 1681781649 + 86400,
+            status: HTLCStatus::Succeeded,
             paid: true,
+            settled_externally: false,
+            external_settlement_note: None,
             fees_paid: None,
             inbound: true,
             labels: labels.clone(),
@@ -2661,6 +5141,7 @@ mod tests {
             bolt11: None,
             payee_pubkey: Some(pubkey),
             last_update: 1681781585,
+            external_settlement: None,
         };
 
         let expected: MutinyInvoice = MutinyInvoice {
@@ -2671,7 +5152,10 @@ mod tests {
             payee_pubkey: Some(pubkey),
             amount_sats: Some(100),
             expire: 1681781585,
+            status: HTLCStatus::Succeeded,
             paid: true,
+            settled_externally: false,
+            external_settlement_note: None,
             fees_paid: Some(1),
             inbound: false,
             labels: vec![],
@@ -2689,6 +5173,105 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn test_force_close_balance() {
+        let onchain = bdk_chain::keychain::Balance {
+            immature: 0,
+            trusted_pending: 1_000,
+            untrusted_pending: 2_000,
+            confirmed: 10_000,
+        };
+        // a force closed channel with the to_self output still timelocked
+        let closing = vec![Balance::ClaimableAwaitingConfirmations {
+            claimable_amount_satoshis: 50_000,
+            confirmation_height: 800_144,
+        }];
+
+        let balance = MutinyBalance::new(&onchain, 3_000_000, &closing);
+        assert_eq!(balance.force_close, 50_000);
+        assert_eq!(balance.confirmed, 11_000);
+        assert_eq!(balance.unconfirmed, 2_000);
+        assert_eq!(balance.lightning, 3_000);
+
+        let balance = MutinyBalance::new(&onchain, 3_000_000, &[]);
+        assert_eq!(balance.force_close, 0);
+        assert_eq!(balance.confirmed, 11_000);
+    }
+
+    #[test]
+    fn test_external_settlement_excluded_from_channel_balance() {
+        let payment_info = |status: HTLCStatus, amt_msat: u64, note: Option<&str>| PaymentInfo {
+            preimage: Some([1; 32]),
+            secret: None,
+            status,
+            amt_msat: MillisatAmount(Some(amt_msat)),
+            fee_paid_msat: None,
+            bolt11: None,
+            payee_pubkey: None,
+            last_update: 1681781585,
+            external_settlement: note.map(|note| ExternalSettlement {
+                note: note.to_string(),
+                settled_at: 1681781585,
+            }),
+        };
+
+        let external = MutinyInvoice::from(
+            payment_info(HTLCStatus::Succeeded, 50_000, Some("paid in person")),
+            PaymentHash([1; 32]),
+            true,
+            vec![],
+        )
+        .unwrap();
+        assert!(external.paid);
+        assert!(external.settled_externally);
+        assert_eq!(
+            external.external_settlement_note,
+            Some("paid in person".to_string())
+        );
+
+        let received = MutinyInvoice::from(
+            payment_info(HTLCStatus::Succeeded, 20_000, None),
+            PaymentHash([2; 32]),
+            true,
+            vec![],
+        )
+        .unwrap();
+        assert!(!received.settled_externally);
+
+        let pending = MutinyInvoice::from(
+            payment_info(HTLCStatus::Pending, 7_000, None),
+            PaymentHash([3; 32]),
+            true,
+            vec![],
+        )
+        .unwrap();
+
+        let sent = MutinyInvoice {
+            fees_paid: Some(1),
+            ..MutinyInvoice::from(
+                payment_info(HTLCStatus::Succeeded, 5_000, None),
+                PaymentHash([4; 32]),
+                false,
+                vec![],
+            )
+            .unwrap()
+        };
+
+        let totals = LightningPaymentTotals::from_invoices(&[external, received, pending, sent]);
+        assert_eq!(
+            totals,
+            LightningPaymentTotals {
+                received: 70,
+                sent: 5,
+                fees_paid: 1,
+                settled_externally: 50,
+                ..Default::default()
+            }
+        );
+        // only the normally received invoice moved funds into our channels
+        assert_eq!(totals.channel_balance_change(), 20 - 5 - 1);
+    }
+
     #[test]
     fn test_sort_activity_item() {
         let preimage: [u8; 32] =
@@ -2746,7 +5329,10 @@ mod tests {
             payee_pubkey: Some(pubkey),
             amount_sats: Some(100),
             expire: 1681781585,
+            status: HTLCStatus::Succeeded,
             paid: true,
+            settled_externally: false,
+            external_settlement_note: None,
             fees_paid: Some(1),
             inbound: false,
             labels: vec![],
@@ -2761,7 +5347,10 @@ mod tests {
             payee_pubkey: Some(pubkey),
             amount_sats: Some(100),
             expire: 1681781585,
+            status: HTLCStatus::Succeeded,
             paid: true,
+            settled_externally: false,
+            external_settlement_note: None,
             fees_paid: Some(1),
             inbound: false,
             labels: vec![],
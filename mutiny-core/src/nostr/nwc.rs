@@ -1,3 +1,4 @@
+use crate::capabilities::Capability;
 use crate::error::MutinyError;
 use crate::nodemanager::NodeManager;
 use crate::nostr::NostrManager;
@@ -20,6 +21,8 @@ use std::str::FromStr;
 
 pub(crate) const PENDING_NWC_EVENTS_KEY: &str = "pending_nwc_events";
 
+pub(crate) const NWC_CAPABILITY: Capability = Capability::flag("nostr_wallet_connect");
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub(crate) struct Profile {
     pub name: String,
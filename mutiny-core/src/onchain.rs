@@ -7,6 +7,7 @@ use std::sync::{Arc, RwLock};
 use bdk::chain::{BlockId, ConfirmationTime};
 use bdk::psbt::PsbtUtils;
 use bdk::template::DescriptorTemplateOut;
+use bdk::wallet::AddressIndex;
 use bdk::{FeeRate, LocalUtxo, SignOptions, TransactionDetails, Wallet};
 use bdk_esplora::{esplora_client, EsploraAsyncExt};
 use bip39::Mnemonic;
@@ -456,6 +457,53 @@ impl<S: MutinyStorage> OnChainWallet<S> {
         Ok(txid)
     }
 
+    /// Creates a PSBT that spends all the selected utxos to a new change address
+    /// of the wallet.
+    pub(crate) fn create_consolidation_psbt(
+        &self,
+        utxos: &[OutPoint],
+        fee_rate: f32,
+    ) -> Result<PartiallySignedTransaction, MutinyError> {
+        let mut wallet = self.wallet.try_write()?;
+        let spk = wallet
+            .get_internal_address(AddressIndex::New)
+            .address
+            .script_pubkey();
+        let (mut psbt, details) = {
+            let mut builder = wallet.build_tx();
+            builder
+                .manually_selected_only()
+                .add_utxos(utxos)?
+                .drain_to(spk)
+                .enable_rbf()
+                .fee_rate(FeeRate::from_sat_per_vb(fee_rate));
+            builder.finish()?
+        };
+        log_debug!(self.logger, "Transaction details: {details:#?}");
+        log_debug!(self.logger, "Unsigned PSBT: {psbt}");
+        let finalized = wallet.sign(&mut psbt, SignOptions::default())?;
+        log_debug!(self.logger, "finalized: {finalized}");
+        Ok(psbt)
+    }
+
+    /// Spends the selected utxos to a single output of the wallet.
+    pub(crate) async fn consolidate(
+        &self,
+        utxos: &[OutPoint],
+        labels: Vec<String>,
+        fee_rate: f32,
+    ) -> Result<Txid, MutinyError> {
+        let psbt = self.create_consolidation_psbt(utxos, fee_rate)?;
+        self.label_psbt(&psbt, labels)?;
+
+        let raw_transaction = psbt.extract_tx();
+        let txid = raw_transaction.txid();
+
+        self.broadcast_transaction(raw_transaction).await?;
+        log_debug!(self.logger, "Consolidation broadcast! TXID: {txid}");
+        Ok(txid)
+    }
+
     /// Creates a PSBT that spends all the selected utxos a given output.
     /// A fee rate is not specified because it should be precalculated
     /// in the output's amount.
@@ -536,6 +584,17 @@ fn get_tr_descriptors_for_extended_key(
     Ok((receive_descriptor_template, change_descriptor_template))
 }
 
+/// The public receive and change descriptors of the wallet, without the keys.
+pub(crate) fn get_tr_public_descriptors(
+    master_xprv: ExtendedPrivKey,
+    network: Network,
+    account_number: u32,
+) -> Result<Vec<String>, MutinyError> {
+    let (receive, change) =
+        get_tr_descriptors_for_extended_key(master_xprv, network, account_number)?;
+    Ok(vec![receive.0.to_string(), change.0.to_string()])
+}
+
 pub(crate) fn get_esplora_url(network: Network, user_provided_url: Option<String>) -> String {
     if let Some(url) = user_provided_url {
         url
@@ -0,0 +1,382 @@
+use crate::capabilities::Capability;
+use crate::utils;
+use bitcoin::secp256k1::PublicKey;
+use lightning::ln::channelmanager::PaymentId;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+pub(crate) const PAYMENT_PRIVACY_CAPABILITY: Capability = Capability::flag("payment_privacy");
+
+/// The final CLTV expiry delta we use for keysend payments, where we pick it ourselves.
+pub const KEYSEND_FINAL_CLTV_EXPIRY_DELTA: u32 = 40;
+
+/// Most blocks that can be added to the final CLTV expiry delta, keeps the total
+/// well under the 1008 blocks LDK allows for a whole route.
+pub const MAX_CLTV_OFFSET: u32 = 144;
+
+/// Default upper bound of the random amount added to self-payments.
+pub const DEFAULT_MAX_SELF_PAYMENT_JITTER_MSAT: u64 = 10_000;
+
+/// Number of destinations we remember the last first hop for.
+const MAX_REMEMBERED_DESTINATIONS: usize = 100;
+
+/// Number of sent payments we wait on to learn their first hop.
+const MAX_PENDING_PAYMENTS: usize = 100;
+
+/// Settings that make repeated payments harder to fingerprint for routing nodes.
+/// Everything is off by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaymentPrivacyConfig {
+    /// Add a random number of blocks, up to `max_cltv_offset`, on top of the
+    /// final CLTV expiry delta the payee asks for
+    pub randomize_cltv: bool,
+    /// Capped at [`MAX_CLTV_OFFSET`]
+    pub max_cltv_offset: u32,
+    /// Overpay payments between our own nodes, like redshifts, by a random amount
+    /// up to this many msats. The jitter ends up back with us. `None` disables it
+    pub self_payment_jitter_msat: Option<u64>,
+    /// Avoid the first hop of the last payment to the same destination when
+    /// another channel can carry the payment
+    pub first_hop_diversity: bool,
+}
+
+impl Default for PaymentPrivacyConfig {
+    fn default() -> Self {
+        Self {
+            randomize_cltv: false,
+            max_cltv_offset: MAX_CLTV_OFFSET,
+            self_payment_jitter_msat: None,
+            first_hop_diversity: false,
+        }
+    }
+}
+
+impl PaymentPrivacyConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.randomize_cltv || self.self_payment_jitter_msat.is_some() || self.first_hop_diversity
+    }
+}
+
+/// What the privacy settings changed for a single payment.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct PrivacyEffect {
+    /// Blocks added to the final CLTV expiry delta
+    pub cltv_offset: u32,
+    /// Msats paid on top of the amount
+    pub jitter_msat: u64,
+    /// The short channel id of the first hop that was avoided
+    pub avoided_first_hop: Option<u64>,
+}
+
+impl PrivacyEffect {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The effect as trace annotations, empty if nothing was changed
+    pub fn annotations(&self) -> Vec<(&'static str, String)> {
+        let mut annotations = vec![];
+        if self.cltv_offset > 0 {
+            annotations.push(("privacy.cltv_offset", self.cltv_offset.to_string()));
+        }
+        if self.jitter_msat > 0 {
+            annotations.push(("privacy.jitter_msat", self.jitter_msat.to_string()));
+        }
+        if let Some(scid) = self.avoided_first_hop {
+            annotations.push(("privacy.avoided_first_hop", scid.to_string()));
+        }
+        annotations
+    }
+}
+
+#[derive(Default)]
+struct FirstHops {
+    /// Destinations of payments that are in flight
+    pending: VecDeque<(PaymentId, PublicKey)>,
+    /// The first hop of the last successful payment to each destination, oldest first
+    last: VecDeque<(PublicKey, u64)>,
+}
+
+/// Applies the [`PaymentPrivacyConfig`] to outgoing payments of a node and
+/// remembers the first hops of its payments.
+#[derive(Clone)]
+pub(crate) struct PaymentPrivacy {
+    config: PaymentPrivacyConfig,
+    first_hops: Arc<utils::Mutex<FirstHops>>,
+}
+
+impl PaymentPrivacy {
+    pub fn new(config: PaymentPrivacyConfig) -> Self {
+        Self {
+            config,
+            first_hops: Arc::new(utils::Mutex::new(FirstHops::default())),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.is_enabled()
+    }
+
+    /// Number of blocks to add to the final CLTV expiry delta, given a random number.
+    pub fn cltv_offset(&self, random: u32) -> u32 {
+        if !self.config.randomize_cltv {
+            return 0;
+        }
+        let max = self.config.max_cltv_offset.min(MAX_CLTV_OFFSET);
+        random % (max + 1)
+    }
+
+    /// Msats to pay on top of `amount_msat`, given a random number. Only self-payments
+    /// are jittered, and never by more than the amount itself since BOLT 11 only
+    /// lets payees accept up to twice the amount they asked for.
+    pub fn jitter_msat(&self, amount_msat: u64, self_payment: bool, random: u64) -> u64 {
+        match self.config.self_payment_jitter_msat {
+            Some(max) if self_payment => random % (max.min(amount_msat) + 1),
+            _ => 0,
+        }
+    }
+
+    /// The first hop to avoid for a payment to the destination. `channels` are the
+    /// short channel ids and outbound capacity in msats of our usable channels. The
+    /// last first hop is only avoided if another channel can carry the whole amount,
+    /// so it is a preference and never the reason a payment can not be sent.
+    pub fn first_hop_to_avoid(
+        &self,
+        destination: &PublicKey,
+        channels: &[(u64, u64)],
+        amount_msat: u64,
+    ) -> Option<u64> {
+        if !self.config.first_hop_diversity {
+            return None;
+        }
+
+        let last = {
+            let first_hops = self.first_hops.lock().expect("Failed to lock first hops");
+            first_hops
+                .last
+                .iter()
+                .find(|(d, _)| d == destination)
+                .map(|(_, scid)| *scid)?
+        };
+
+        let has_alternative = channels
+            .iter()
+            .any(|(scid, outbound_msat)| *scid != last && *outbound_msat >= amount_msat);
+        has_alternative.then_some(last)
+    }
+
+    /// Remembers the destination of a sent payment, to learn its first hop once it succeeds
+    pub fn payment_sent(&self, payment_id: PaymentId, destination: PublicKey) {
+        if !self.config.first_hop_diversity {
+            return;
+        }
+        let mut first_hops = self.first_hops.lock().expect("Failed to lock first hops");
+        while first_hops.pending.len() >= MAX_PENDING_PAYMENTS {
+            first_hops.pending.pop_front();
+        }
+        first_hops.pending.push_back((payment_id, destination));
+    }
+
+    /// Records the first hop of a successful path of a sent payment
+    pub fn path_successful(&self, payment_id: &PaymentId, first_hop: u64) {
+        let mut first_hops = self.first_hops.lock().expect("Failed to lock first hops");
+        let Some(index) = first_hops
+            .pending
+            .iter()
+            .position(|(id, _)| id == payment_id)
+        else {
+            return;
+        };
+        let (_, destination) = first_hops.pending.remove(index).expect("index is in range");
+
+        first_hops.last.retain(|(d, _)| *d != destination);
+        while first_hops.last.len() >= MAX_REMEMBERED_DESTINATIONS {
+            first_hops.last.pop_front();
+        }
+        first_hops.last.push_back((destination, first_hop));
+    }
+
+    /// Forgets a sent payment that failed
+    pub fn payment_failed(&self, payment_id: &PaymentId) {
+        let mut first_hops = self.first_hops.lock().expect("Failed to lock first hops");
+        first_hops.pending.retain(|(id, _)| id != payment_id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::*;
+
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn pubkey(byte: u8) -> PublicKey {
+        let secret = SecretKey::from_slice(&[byte; 32]).unwrap();
+        PublicKey::from_secret_key(&Secp256k1::new(), &secret)
+    }
+
+    fn enabled() -> PaymentPrivacyConfig {
+        PaymentPrivacyConfig {
+            randomize_cltv: true,
+            max_cltv_offset: MAX_CLTV_OFFSET,
+            self_payment_jitter_msat: Some(DEFAULT_MAX_SELF_PAYMENT_JITTER_MSAT),
+            first_hop_diversity: true,
+        }
+    }
+
+    #[test]
+    fn test_default_is_off() {
+        let test_name = "test_default_is_off";
+        log!("{}", test_name);
+
+        let config = PaymentPrivacyConfig::default();
+        assert!(!config.is_enabled());
+
+        let privacy = PaymentPrivacy::new(config);
+        assert_eq!(privacy.cltv_offset(u32::MAX), 0);
+        assert_eq!(privacy.jitter_msat(1_000_000, true, u64::MAX), 0);
+
+        let destination = pubkey(1);
+        privacy.payment_sent(PaymentId([1; 32]), destination);
+        privacy.path_successful(&PaymentId([1; 32]), 1);
+        assert_eq!(
+            privacy.first_hop_to_avoid(&destination, &[(1, 1_000), (2, 1_000)], 1),
+            None
+        );
+    }
+
+    #[test]
+    fn test_cltv_offset_bounds() {
+        let test_name = "test_cltv_offset_bounds";
+        log!("{}", test_name);
+
+        let privacy = PaymentPrivacy::new(enabled());
+        for random in [0, 1, 143, 144, 145, 1_000, u32::MAX - 1, u32::MAX] {
+            assert!(privacy.cltv_offset(random) <= MAX_CLTV_OFFSET);
+        }
+        assert_eq!(privacy.cltv_offset(0), 0);
+        assert_eq!(privacy.cltv_offset(MAX_CLTV_OFFSET), MAX_CLTV_OFFSET);
+
+        // a smaller max is respected, a larger one is capped
+        let privacy = PaymentPrivacy::new(PaymentPrivacyConfig {
+            max_cltv_offset: 6,
+            ..enabled()
+        });
+        assert!((0..1_000).all(|random| privacy.cltv_offset(random) <= 6));
+        assert_eq!(privacy.cltv_offset(6), 6);
+
+        let privacy = PaymentPrivacy::new(PaymentPrivacyConfig {
+            max_cltv_offset: 10_000,
+            ..enabled()
+        });
+        assert!((0..10_000).all(|random| privacy.cltv_offset(random) <= MAX_CLTV_OFFSET));
+    }
+
+    #[test]
+    fn test_self_payment_jitter() {
+        let test_name = "test_self_payment_jitter";
+        log!("{}", test_name);
+
+        let privacy = PaymentPrivacy::new(enabled());
+
+        // only self-payments are jittered
+        assert_eq!(privacy.jitter_msat(1_000_000, false, 1_234), 0);
+        assert_eq!(privacy.jitter_msat(1_000_000, true, 1_234), 1_234);
+
+        for random in [0, 1, 9_999, 10_000, 10_001, u64::MAX] {
+            let jitter = privacy.jitter_msat(1_000_000, true, random);
+            assert!(jitter <= DEFAULT_MAX_SELF_PAYMENT_JITTER_MSAT);
+        }
+
+        // never more than the amount itself
+        for random in [0, 1, 500, 501, u64::MAX] {
+            assert!(privacy.jitter_msat(500, true, random) <= 500);
+        }
+        assert_eq!(privacy.jitter_msat(0, true, u64::MAX), 0);
+    }
+
+    #[test]
+    fn test_first_hop_diversity() {
+        let test_name = "test_first_hop_diversity";
+        log!("{}", test_name);
+
+        let privacy = PaymentPrivacy::new(enabled());
+        let destination = pubkey(1);
+        let other = pubkey(2);
+        let channels = [(1, 500_000), (2, 500_000)];
+
+        // nothing to avoid for the first payment
+        assert_eq!(
+            privacy.first_hop_to_avoid(&destination, &channels, 100_000),
+            None
+        );
+        privacy.payment_sent(PaymentId([1; 32]), destination);
+        privacy.path_successful(&PaymentId([1; 32]), 1);
+
+        // the next payment avoids the first hop of the last one
+        assert_eq!(
+            privacy.first_hop_to_avoid(&destination, &channels, 100_000),
+            Some(1)
+        );
+        privacy.payment_sent(PaymentId([2; 32]), destination);
+        privacy.path_successful(&PaymentId([2; 32]), 2);
+        assert_eq!(
+            privacy.first_hop_to_avoid(&destination, &channels, 100_000),
+            Some(2)
+        );
+
+        // other destinations are not affected
+        assert_eq!(privacy.first_hop_to_avoid(&other, &channels, 100_000), None);
+
+        // the last first hop is still used if no other channel can carry the payment
+        assert_eq!(
+            privacy.first_hop_to_avoid(&destination, &channels, 600_000),
+            None
+        );
+        assert_eq!(
+            privacy.first_hop_to_avoid(&destination, &[(2, 500_000)], 100_000),
+            None
+        );
+        assert_eq!(
+            privacy.first_hop_to_avoid(&destination, &[(1, 50_000), (2, 500_000)], 100_000),
+            None
+        );
+
+        // failed payments do not change the last first hop
+        privacy.payment_sent(PaymentId([3; 32]), destination);
+        privacy.payment_failed(&PaymentId([3; 32]));
+        privacy.path_successful(&PaymentId([3; 32]), 1);
+        assert_eq!(
+            privacy.first_hop_to_avoid(&destination, &channels, 100_000),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn test_privacy_effect_annotations() {
+        let test_name = "test_privacy_effect_annotations";
+        log!("{}", test_name);
+
+        let effect = PrivacyEffect::default();
+        assert!(effect.is_empty());
+        assert!(effect.annotations().is_empty());
+
+        let effect = PrivacyEffect {
+            cltv_offset: 12,
+            jitter_msat: 0,
+            avoided_first_hop: Some(42),
+        };
+        assert!(!effect.is_empty());
+        assert_eq!(
+            effect.annotations(),
+            vec![
+                ("privacy.cltv_offset", "12".to_string()),
+                ("privacy.avoided_first_hop", "42".to_string()),
+            ]
+        );
+    }
+}
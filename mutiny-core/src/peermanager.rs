@@ -30,7 +30,7 @@ use std::sync::Arc;
 use crate::networking::ws_socket::WsTcpSocketDescriptor;
 
 #[cfg(target_arch = "wasm32")]
-use crate::networking::proxy::WsProxy;
+use crate::networking::proxy::{ReconnectPolicy, WsProxy};
 
 #[cfg(not(target_arch = "wasm32"))]
 use tokio::time;
@@ -44,6 +44,9 @@ use tokio::net::TcpStream;
 #[cfg(not(target_arch = "wasm32"))]
 use crate::networking::tcp_socket::TcpSocketDescriptor;
 
+#[cfg(not(target_arch = "wasm32"))]
+use crate::networking::dns::DnsResolver;
+
 pub trait PeerManager {
     fn get_peer_node_ids(&self) -> Vec<PublicKey>;
 
@@ -308,6 +311,7 @@ impl<S: MutinyStorage> RoutingMessageHandler for GossipMessageHandler<S> {
 
 pub(crate) async fn connect_peer_if_necessary<S: MutinyStorage>(
     #[cfg(target_arch = "wasm32")] websocket_proxy_addr: &str,
+    #[cfg(not(target_arch = "wasm32"))] dns_resolver: &DnsResolver,
     peer_connection_info: &PubkeyConnectionInfo,
     logger: Arc<MutinyLogger>,
     peer_manager: Arc<dyn PeerManager>,
@@ -328,6 +332,8 @@ pub(crate) async fn connect_peer_if_necessary<S: MutinyStorage>(
         connect_peer(
             #[cfg(target_arch = "wasm32")]
             websocket_proxy_addr,
+            #[cfg(not(target_arch = "wasm32"))]
+            dns_resolver,
             peer_connection_info,
             logger,
             peer_manager,
@@ -339,6 +345,7 @@ pub(crate) async fn connect_peer_if_necessary<S: MutinyStorage>(
 
 async fn connect_peer(
     #[cfg(target_arch = "wasm32")] websocket_proxy_addr: &str,
+    #[cfg(not(target_arch = "wasm32"))] dns_resolver: &DnsResolver,
     peer_connection_info: &PubkeyConnectionInfo,
     logger: Arc<MutinyLogger>,
     peer_manager: Arc<dyn PeerManager>,
@@ -351,6 +358,7 @@ async fn connect_peer(
                 let proxy = WsProxy::new(
                     websocket_proxy_addr,
                     peer_connection_info.clone(),
+                    ReconnectPolicy::default(),
                     logger.clone(),
                 )
                 .await?;
@@ -362,14 +370,18 @@ async fn connect_peer(
             }
             #[cfg(not(target_arch = "wasm32"))]
             {
-                let (socket_addr, net_addr) = try_parse_addr_string(t);
-                let socket_addr = socket_addr.ok_or(MutinyError::ConnectionFailed)?;
-
-                let stream =
-                    time::timeout(Duration::from_secs(10), TcpStream::connect(&socket_addr))
-                        .await
-                        .map_err(|_| MutinyError::ConnectionFailed)?
-                        .map_err(|_| MutinyError::ConnectionFailed)?;
+                // host names are resolved as configured, the peer manager only
+                // learns the address when it was given as one
+                let (_, net_addr) = try_parse_addr_string(t);
+                let socket_addrs = dns_resolver.resolve_addr(t).await?;
+
+                let stream = time::timeout(
+                    Duration::from_secs(10),
+                    TcpStream::connect(socket_addrs.as_slice()),
+                )
+                .await
+                .map_err(|_| MutinyError::ConnectionFailed)?
+                .map_err(|_| MutinyError::ConnectionFailed)?;
 
                 let stream = stream.into_std().unwrap();
                 (
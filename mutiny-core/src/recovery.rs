@@ -0,0 +1,269 @@
+//! Funds of closed channels that are on their way back to the on-chain wallet.
+//!
+//! After a force close the funds of a channel are locked in the channel monitor
+//! until the timelocks mature and they are swept to the wallet. These are listed
+//! separately so they do not look lost in the meantime.
+
+use crate::capabilities::Capability;
+use bitcoin::{OutPoint, Txid};
+use lightning::chain::channelmonitor::Balance;
+use serde::{Deserialize, Serialize};
+
+pub(crate) const RECOVERY_CAPABILITY: Capability = Capability::flag("pending_recovery");
+
+/// Seconds between two blocks, used to estimate when funds are available.
+pub const EXPECTED_BLOCK_SECS: u64 = 600;
+
+/// Why the funds of a closed channel are not spendable yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RecoveryReason {
+    /// The closing transaction is not confirmed yet
+    ClosePending,
+    /// Our output of the closing transaction, spendable once its timelock matured
+    ToSelfTimelock,
+    /// An HTLC that is claimed on-chain, or times out back to us
+    HtlcClaim,
+    /// An output of a revoked commitment that is claimed as a penalty
+    PenaltyClaim,
+    /// Swept to the wallet, waiting for the sweep to confirm
+    AwaitingSweepConfirmation,
+}
+
+/// Funds of a closed channel that are not spendable on-chain yet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingRecovery {
+    /// The funding outpoint of the closed channel. None for sweeps, which can
+    /// spend the outputs of multiple channels at once.
+    pub funding_txo: Option<OutPoint>,
+    pub amount_sats: u64,
+    pub reason: RecoveryReason,
+    /// Blocks until the funds are available, None if that does not only
+    /// depend on blocks, like before the closing transaction confirmed
+    pub blocks_remaining: Option<u32>,
+    /// Estimated time the funds are available, in seconds since the unix epoch
+    pub available_at: Option<u64>,
+    pub sweep_txid: Option<Txid>,
+}
+
+/// A transaction that swept funds of closed channels to the wallet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecoverySweep {
+    pub txid: Txid,
+    pub amount_sats: u64,
+}
+
+/// Where a sweep is, as seen by the on-chain wallet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SweepStatus {
+    /// Broadcast, but the wallet did not see it yet
+    Broadcast,
+    /// The wallet counts what it receives from the sweep as unconfirmed
+    Unconfirmed { received: u64 },
+    /// The wallet counts it as confirmed, so it is no longer pending
+    Confirmed,
+}
+
+fn pending(
+    funding_txo: Option<OutPoint>,
+    amount_sats: u64,
+    reason: RecoveryReason,
+    blocks_remaining: Option<u32>,
+    now: u64,
+) -> PendingRecovery {
+    PendingRecovery {
+        funding_txo,
+        amount_sats,
+        reason,
+        blocks_remaining,
+        available_at: blocks_remaining.map(|b| now + b as u64 * EXPECTED_BLOCK_SECS),
+        sweep_txid: None,
+    }
+}
+
+/// Categorizes the claimable balances of the monitor of a closed channel,
+/// with the blocks remaining counted from the given tip.
+pub(crate) fn channel_recoveries(
+    funding_txo: OutPoint,
+    balances: &[Balance],
+    tip_height: u32,
+    now: u64,
+) -> Vec<PendingRecovery> {
+    let blocks_until = |height: u32| Some(height.saturating_sub(tip_height));
+    balances
+        .iter()
+        .map(|balance| {
+            let (reason, blocks_remaining) = match balance {
+                Balance::ClaimableOnChannelClose { .. } => (RecoveryReason::ClosePending, None),
+                Balance::ClaimableAwaitingConfirmations {
+                    confirmation_height,
+                    ..
+                } => (
+                    RecoveryReason::ToSelfTimelock,
+                    blocks_until(*confirmation_height),
+                ),
+                // we have the preimage, so it is claimed right away
+                Balance::ContentiousClaimable { .. } => (RecoveryReason::HtlcClaim, Some(0)),
+                Balance::MaybeTimeoutClaimableHTLC {
+                    claimable_height, ..
+                } => (RecoveryReason::HtlcClaim, blocks_until(*claimable_height)),
+                // only ours if we learn the preimage before it expires
+                Balance::MaybePreimageClaimableHTLC { .. } => (RecoveryReason::HtlcClaim, None),
+                Balance::CounterpartyRevokedOutputClaimable { .. } => {
+                    (RecoveryReason::PenaltyClaim, Some(0))
+                }
+            };
+            pending(
+                Some(funding_txo),
+                balance.claimable_amount_satoshis(),
+                reason,
+                blocks_remaining,
+                now,
+            )
+        })
+        .collect()
+}
+
+/// The pending recovery of a sweep, None once it confirmed.
+pub(crate) fn sweep_recovery(
+    sweep: &RecoverySweep,
+    status: SweepStatus,
+    now: u64,
+) -> Option<PendingRecovery> {
+    if status == SweepStatus::Confirmed {
+        return None;
+    }
+
+    let mut recovery = pending(
+        None,
+        sweep.amount_sats,
+        RecoveryReason::AwaitingSweepConfirmation,
+        Some(1),
+        now,
+    );
+    recovery.sweep_txid = Some(sweep.txid);
+    Some(recovery)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::nodemanager::MutinyBalance;
+    use bitcoin::hashes::Hash;
+    use lightning::ln::PaymentHash;
+
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    const NOW: u64 = 1_690_000_000;
+
+    fn funding_txo() -> OutPoint {
+        OutPoint {
+            txid: Txid::all_zeros(),
+            vout: 1,
+        }
+    }
+
+    fn onchain(confirmed: u64, untrusted_pending: u64) -> bdk_chain::keychain::Balance {
+        bdk_chain::keychain::Balance {
+            immature: 0,
+            trusted_pending: 0,
+            untrusted_pending,
+            confirmed,
+        }
+    }
+
+    #[test]
+    fn test_recoveries_across_close() {
+        let test_name = "test_recoveries_across_close";
+        log!("{}", test_name);
+
+        // the commitment transaction is broadcast but not confirmed
+        let closing = vec![Balance::ClaimableOnChannelClose {
+            claimable_amount_satoshis: 90_000,
+        }];
+        let recoveries = channel_recoveries(funding_txo(), &closing, 800_000, NOW);
+        assert_eq!(recoveries.len(), 1);
+        assert_eq!(recoveries[0].funding_txo, Some(funding_txo()));
+        assert_eq!(recoveries[0].reason, RecoveryReason::ClosePending);
+        assert_eq!(recoveries[0].blocks_remaining, None);
+        assert_eq!(recoveries[0].available_at, None);
+
+        // it confirmed, our output is timelocked and an HTLC times out back to us
+        let closing = vec![
+            Balance::ClaimableAwaitingConfirmations {
+                claimable_amount_satoshis: 80_000,
+                confirmation_height: 800_144,
+            },
+            Balance::MaybeTimeoutClaimableHTLC {
+                claimable_amount_satoshis: 10_000,
+                claimable_height: 800_040,
+                payment_hash: PaymentHash([1; 32]),
+            },
+        ];
+        let recoveries = channel_recoveries(funding_txo(), &closing, 800_000, NOW);
+        assert_eq!(recoveries[0].reason, RecoveryReason::ToSelfTimelock);
+        assert_eq!(recoveries[0].amount_sats, 80_000);
+        assert_eq!(recoveries[0].blocks_remaining, Some(144));
+        assert_eq!(
+            recoveries[0].available_at,
+            Some(NOW + 144 * EXPECTED_BLOCK_SECS)
+        );
+        assert_eq!(recoveries[1].reason, RecoveryReason::HtlcClaim);
+        assert_eq!(recoveries[1].blocks_remaining, Some(40));
+
+        let balance = MutinyBalance::new(&onchain(10_000, 0), 0, &closing);
+        assert_eq!(balance.pending_recovery, 90_000);
+        assert_eq!(balance.force_close, 90_000);
+
+        // blocks arrive, past heights count as available now
+        let recoveries = channel_recoveries(funding_txo(), &closing, 800_100, NOW);
+        assert_eq!(recoveries[0].blocks_remaining, Some(44));
+        assert_eq!(recoveries[1].blocks_remaining, Some(0));
+        assert_eq!(recoveries[1].available_at, Some(NOW));
+    }
+
+    #[test]
+    fn test_sweep_reconciles_with_onchain() {
+        let test_name = "test_sweep_reconciles_with_onchain";
+        log!("{}", test_name);
+
+        let sweep = RecoverySweep {
+            txid: Txid::all_zeros(),
+            amount_sats: 89_000,
+        };
+
+        // the monitor no longer has the funds once they are swept,
+        // and the wallet did not see the sweep yet
+        let status = SweepStatus::Broadcast;
+        let recovery = sweep_recovery(&sweep, status, NOW).unwrap();
+        assert_eq!(recovery.reason, RecoveryReason::AwaitingSweepConfirmation);
+        assert_eq!(recovery.sweep_txid, Some(sweep.txid));
+        assert_eq!(recovery.funding_txo, None);
+        assert_eq!(recovery.blocks_remaining, Some(1));
+        let balance =
+            MutinyBalance::new(&onchain(10_000, 0), 0, &[]).with_sweeps(&[(sweep.clone(), status)]);
+        assert_eq!(balance.pending_recovery, 89_000);
+        assert_eq!(balance.force_close, 0);
+        assert_eq!(balance.unconfirmed, 0);
+
+        // the wallet sees it unconfirmed, it is only counted once
+        let status = SweepStatus::Unconfirmed { received: 89_000 };
+        assert!(sweep_recovery(&sweep, status, NOW).is_some());
+        let balance = MutinyBalance::new(&onchain(10_000, 89_500), 0, &[])
+            .with_sweeps(&[(sweep.clone(), status)]);
+        assert_eq!(balance.pending_recovery, 89_000);
+        assert_eq!(balance.unconfirmed, 500);
+        assert_eq!(balance.confirmed, 10_000);
+
+        // once it confirmed it is part of the on-chain balance only
+        let status = SweepStatus::Confirmed;
+        assert!(sweep_recovery(&sweep, status, NOW).is_none());
+        let balance =
+            MutinyBalance::new(&onchain(99_000, 0), 0, &[]).with_sweeps(&[(sweep, status)]);
+        assert_eq!(balance.pending_recovery, 0);
+        assert_eq!(balance.confirmed, 99_000);
+    }
+}
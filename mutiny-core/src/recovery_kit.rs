@@ -0,0 +1,340 @@
+use crate::error::MutinyError;
+use crate::scb::EncryptedSCB;
+use bitcoin::hashes::hex::ToHex;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::util::bip32::DerivationPath;
+use bitcoin::Network;
+use miniscript::{Descriptor, DescriptorPublicKey};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::str::FromStr;
+
+/// The version of the recovery kit document, bumped on incompatible changes.
+pub const RECOVERY_KIT_VERSION: u32 = 1;
+
+/// Name of the field holding the integrity hash, it is left out of the hash.
+const INTEGRITY_HASH_FIELD: &str = "integrity_hash";
+
+/// A document with everything needed to recover the wallet besides the seed,
+/// meant to be printed. It contains no secrets, the static channel backup is
+/// encrypted with a key derived from the seed.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RecoveryKit {
+    pub version: u32,
+    /// The fingerprint of the master key of the seed, identifies the wallet
+    pub wallet_id: String,
+    /// Epoch time in seconds of when the kit was generated
+    pub created_at: u64,
+    pub network: Network,
+    /// The static channel backup at the time the kit was generated, if included
+    pub scb: Option<String>,
+    /// The derivation path of the key the static channel backup is encrypted with
+    pub scb_key_path: String,
+    /// The public receive and change descriptors of the on-chain wallet
+    pub onchain_descriptors: Vec<String>,
+    pub nodes: Vec<RecoveryKitNode>,
+    pub esplora_url: String,
+    pub lsp_urls: Vec<String>,
+    /// The steps to recover the wallet with this kit, in order
+    pub instructions: Vec<RecoveryKitStep>,
+    /// Hex sha256 of the canonical JSON of the kit without this field,
+    /// see [`canonical_json`]
+    pub integrity_hash: String,
+}
+
+/// A lightning node of the wallet.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RecoveryKitNode {
+    /// None if the node was not running when the kit was generated
+    pub pubkey: Option<PublicKey>,
+    pub child_index: u32,
+    /// The derivation path of the seed of the node's keys
+    pub key_path: String,
+    pub lsp: Option<String>,
+    pub archived: bool,
+}
+
+/// A step of recovering the wallet, the text shown for it is up to the UI.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecoveryKitStep {
+    /// Restore the wallet from the seed words
+    RestoreSeed,
+    /// Let the on-chain wallet sync
+    SyncOnChain,
+    /// Recover the channels from the static channel backup in the kit
+    RecoverChannels,
+}
+
+/// A part of a recovery kit that can be used for recovery.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecoveryKitComponent {
+    /// The static channel backup parses
+    Scb,
+    /// The derivation path of the backup key parses
+    ScbKeyPath,
+    /// All of the on-chain descriptors parse
+    OnChainDescriptors,
+    /// There are nodes and all of their key paths parse
+    Nodes,
+    /// The esplora url and all of the LSP urls parse
+    Endpoints,
+}
+
+/// The result of validating a recovery kit.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RecoveryKitValidation {
+    pub version: Option<u32>,
+    pub network: Option<Network>,
+    /// The integrity hash matches the contents
+    pub intact: bool,
+    /// The components that can be used for recovery. They are checked even if the
+    /// kit is not intact, the backup and descriptors have their own checksums.
+    pub usable: Vec<RecoveryKitComponent>,
+}
+
+impl RecoveryKit {
+    /// Computes the integrity hash of the kit and sets it.
+    pub(crate) fn seal(mut self) -> Self {
+        let value = serde_json::to_value(&self).expect("recovery kit serializes");
+        self.integrity_hash = integrity_hash(&value);
+        self
+    }
+
+    /// The components of the kit that can be used for recovery.
+    fn usable_components(&self) -> Vec<RecoveryKitComponent> {
+        let mut usable = vec![];
+        if self
+            .scb
+            .as_ref()
+            .map_or(false, |scb| EncryptedSCB::parse(scb).is_ok())
+        {
+            usable.push(RecoveryKitComponent::Scb);
+        }
+        if DerivationPath::from_str(&self.scb_key_path).is_ok() {
+            usable.push(RecoveryKitComponent::ScbKeyPath);
+        }
+        if !self.onchain_descriptors.is_empty()
+            && self
+                .onchain_descriptors
+                .iter()
+                .all(|d| Descriptor::<DescriptorPublicKey>::from_str(d).is_ok())
+        {
+            usable.push(RecoveryKitComponent::OnChainDescriptors);
+        }
+        if !self.nodes.is_empty()
+            && self
+                .nodes
+                .iter()
+                .all(|n| DerivationPath::from_str(&n.key_path).is_ok())
+        {
+            usable.push(RecoveryKitComponent::Nodes);
+        }
+        if url::Url::parse(&self.esplora_url).is_ok()
+            && self.lsp_urls.iter().all(|u| url::Url::parse(u).is_ok())
+        {
+            usable.push(RecoveryKitComponent::Endpoints);
+        }
+        usable
+    }
+}
+
+/// Writes the JSON value with the keys of every object sorted and no whitespace,
+/// so the same document always hashes the same no matter how it was formatted.
+pub fn canonical_json(value: &Value) -> String {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<(&String, &Value)> = map.iter().collect();
+            entries.sort_by_key(|(k, _)| *k);
+            let fields: Vec<String> = entries
+                .into_iter()
+                .map(|(k, v)| format!("{}:{}", Value::from(k.as_str()), canonical_json(v)))
+                .collect();
+            format!("{{{}}}", fields.join(","))
+        }
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(canonical_json).collect();
+            format!("[{}]", items.join(","))
+        }
+        _ => value.to_string(),
+    }
+}
+
+/// Hex sha256 of the canonical JSON of the document without its integrity hash.
+fn integrity_hash(value: &Value) -> String {
+    let mut value = value.clone();
+    if let Value::Object(map) = &mut value {
+        map.remove(INTEGRITY_HASH_FIELD);
+    }
+    sha256::Hash::hash(canonical_json(&value).as_bytes()).to_hex()
+}
+
+/// Checks the integrity hash of a recovery kit and which of its components can be
+/// used for recovery. Fails if the kit is not a JSON object.
+pub fn validate_recovery_kit(json: &str) -> Result<RecoveryKitValidation, MutinyError> {
+    let value: Value = serde_json::from_str(json)?;
+    if !value.is_object() {
+        return Err(MutinyError::InvalidArgumentsError);
+    }
+
+    let intact = value
+        .get(INTEGRITY_HASH_FIELD)
+        .and_then(|h| h.as_str())
+        .map_or(false, |h| h.eq_ignore_ascii_case(&integrity_hash(&value)));
+    let version = value
+        .get("version")
+        .and_then(|v| v.as_u64())
+        .and_then(|v| u32::try_from(v).ok());
+    let network = value
+        .get("network")
+        .and_then(|n| serde_json::from_value(n.clone()).ok());
+
+    // newer kits may be laid out differently, only the hash is checked
+    let usable = match serde_json::from_value::<RecoveryKit>(value) {
+        Ok(kit) if kit.version <= RECOVERY_KIT_VERSION => kit.usable_components(),
+        _ => vec![],
+    };
+
+    Ok(RecoveryKitValidation {
+        version,
+        network,
+        intact,
+        usable,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::onchain::get_tr_public_descriptors;
+    use crate::test_utils::*;
+    use bitcoin::secp256k1::Secp256k1;
+    use bitcoin::util::bip32::ExtendedPrivKey;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn kit() -> RecoveryKit {
+        let xprv = ExtendedPrivKey::new_master(Network::Signet, &[1u8; 32]).unwrap();
+        RecoveryKit {
+            version: RECOVERY_KIT_VERSION,
+            wallet_id: xprv.fingerprint(&Secp256k1::new()).to_string(),
+            created_at: 1_700_000_000,
+            network: Network::Signet,
+            scb: None,
+            scb_key_path: "m/444'/444'/444'".to_string(),
+            onchain_descriptors: get_tr_public_descriptors(xprv, Network::Signet, 0).unwrap(),
+            nodes: vec![RecoveryKitNode {
+                pubkey: None,
+                child_index: 0,
+                key_path: "m/0'/0'".to_string(),
+                lsp: Some("https://signet-lsp.mutinywallet.com".to_string()),
+                archived: false,
+            }],
+            esplora_url: "https://mutinynet.com/api".to_string(),
+            lsp_urls: vec!["https://signet-lsp.mutinywallet.com".to_string()],
+            instructions: vec![RecoveryKitStep::RestoreSeed, RecoveryKitStep::SyncOnChain],
+            integrity_hash: String::new(),
+        }
+        .seal()
+    }
+
+    #[test]
+    fn test_canonical_json() {
+        let test_name = "test_canonical_json";
+        log!("{}", test_name);
+
+        let a: Value =
+            serde_json::from_str(r#"{"b": [1, {"d": "x", "c": null}], "a": true}"#).unwrap();
+        let b: Value =
+            serde_json::from_str("{\n  \"a\": true,\n  \"b\": [1, {\"c\": null, \"d\": \"x\"}]\n}")
+                .unwrap();
+        let canonical = r#"{"a":true,"b":[1,{"c":null,"d":"x"}]}"#;
+        assert_eq!(canonical_json(&a), canonical);
+        assert_eq!(canonical_json(&b), canonical);
+        assert_eq!(canonical_json(&Value::from("\"q\"")), r#""\"q\"""#);
+    }
+
+    #[test]
+    fn test_recovery_kit_hash_is_stable() {
+        let test_name = "test_recovery_kit_hash_is_stable";
+        log!("{}", test_name);
+
+        let kit = kit();
+        assert_eq!(kit.integrity_hash.len(), 64);
+        assert_eq!(kit.clone().seal(), kit);
+
+        // pretty printing or reordering the document does not change the hash
+        let pretty = serde_json::to_string_pretty(&kit).unwrap();
+        let validation = validate_recovery_kit(&pretty).unwrap();
+        assert!(validation.intact);
+        assert_eq!(validation.version, Some(RECOVERY_KIT_VERSION));
+        assert_eq!(validation.network, Some(Network::Signet));
+        let mut value = serde_json::to_value(&kit).unwrap();
+        value.as_object_mut().unwrap().insert(
+            "integrity_hash".to_string(),
+            kit.integrity_hash.to_uppercase().into(),
+        );
+        assert!(
+            validate_recovery_kit(&canonical_json(&value))
+                .unwrap()
+                .intact
+        );
+    }
+
+    #[test]
+    fn test_validate_tampered_recovery_kit() {
+        let test_name = "test_validate_tampered_recovery_kit";
+        log!("{}", test_name);
+
+        let kit = kit();
+        let validation = validate_recovery_kit(&serde_json::to_string(&kit).unwrap()).unwrap();
+        assert!(validation.intact);
+        assert_eq!(
+            validation.usable,
+            vec![
+                RecoveryKitComponent::ScbKeyPath,
+                RecoveryKitComponent::OnChainDescriptors,
+                RecoveryKitComponent::Nodes,
+                RecoveryKitComponent::Endpoints,
+            ]
+        );
+
+        // a character misread when scanning a printed kit breaks the hash and the
+        // component it hit, the rest can still be used
+        let mut tampered = kit.clone();
+        tampered.onchain_descriptors[0] =
+            tampered.onchain_descriptors[0].replacen("tpub", "tpuc", 1);
+        let validation = validate_recovery_kit(&serde_json::to_string(&tampered).unwrap()).unwrap();
+        assert!(!validation.intact);
+        assert!(!validation
+            .usable
+            .contains(&RecoveryKitComponent::OnChainDescriptors));
+        assert!(validation.usable.contains(&RecoveryKitComponent::Nodes));
+
+        let mut tampered = kit.clone();
+        tampered.created_at += 1;
+        let validation = validate_recovery_kit(&serde_json::to_string(&tampered).unwrap()).unwrap();
+        assert!(!validation.intact);
+        assert_eq!(validation.usable.len(), 4);
+
+        // a missing hash is not intact
+        let mut value = serde_json::to_value(&kit).unwrap();
+        value.as_object_mut().unwrap().remove("integrity_hash");
+        assert!(!validate_recovery_kit(&value.to_string()).unwrap().intact);
+
+        // newer kits are only checked for integrity
+        let newer = RecoveryKit {
+            version: RECOVERY_KIT_VERSION + 1,
+            ..kit
+        }
+        .seal();
+        let validation = validate_recovery_kit(&serde_json::to_string(&newer).unwrap()).unwrap();
+        assert!(validation.intact);
+        assert!(validation.usable.is_empty());
+
+        assert!(validate_recovery_kit("not json").is_err());
+        assert!(validate_recovery_kit("[]").is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::capabilities::Capability;
 use crate::error::MutinyError;
 use crate::nodemanager::NodeManager;
 use crate::storage::MutinyStorage;
@@ -16,6 +17,8 @@ use std::sync::atomic::Ordering;
 // When creating a new node sleep for 5 seconds to give it time to start up.
 const NEW_NODE_SLEEP_DURATION: i32 = 5_000;
 
+pub(crate) const REDSHIFT_CAPABILITY: Capability = Capability::flag("redshift");
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum RedshiftStatus {
     /// The channel to the introduction node is opening.
@@ -364,7 +367,7 @@ impl<S: MutinyStorage> RedshiftManager for NodeManager<S> {
             let label = format!("Redshift: {}", rs.id.to_hex());
             // make attempts to pay it
             match sending_node
-                .pay_invoice_with_timeout(&invoice, None, None, vec![label])
+                .pay_invoice_with_timeout(&invoice, None, None, vec![label], true)
                 .await
             {
                 Ok(i) => {
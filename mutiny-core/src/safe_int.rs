@@ -0,0 +1,150 @@
+//! Integers that survive being parsed as JSON in JS.
+//!
+//! JS numbers are doubles, so integers above [`MAX_SAFE_INTEGER`] silently lose
+//! precision when a JSON payload is parsed. Fields that can get that large, like
+//! amounts and expiries taken from an invoice, are serialized with
+//! `#[serde(with = "safe_int")]`: small values stay numbers, larger ones are written
+//! as strings. Either form is read back.
+
+use serde::de::{self, Deserializer, Visitor};
+use serde::{Deserialize, Serializer};
+use serde_json::Value;
+use std::fmt;
+
+/// The largest integer a JS number holds exactly, `Number.MAX_SAFE_INTEGER`.
+pub const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+/// If the integer can be written as a JSON number without JS losing precision.
+pub fn is_safe(n: u64) -> bool {
+    n <= MAX_SAFE_INTEGER
+}
+
+/// The integer as a JSON value, a string if it is too large for a JS number.
+pub fn to_json_value(n: u64) -> Value {
+    if is_safe(n) {
+        Value::from(n)
+    } else {
+        Value::String(n.to_string())
+    }
+}
+
+pub fn serialize<S: Serializer>(n: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+    if is_safe(*n) {
+        serializer.serialize_u64(*n)
+    } else {
+        serializer.collect_str(n)
+    }
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    deserializer.deserialize_any(SafeIntVisitor)
+}
+
+struct SafeIntVisitor;
+
+impl<'de> Visitor<'de> for SafeIntVisitor {
+    type Value = u64;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an integer or a string of one")
+    }
+
+    fn visit_u64<E: de::Error>(self, n: u64) -> Result<u64, E> {
+        Ok(n)
+    }
+
+    fn visit_i64<E: de::Error>(self, n: i64) -> Result<u64, E> {
+        u64::try_from(n).map_err(|_| E::invalid_value(de::Unexpected::Signed(n), &self))
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<u64, E> {
+        s.parse()
+            .map_err(|_| E::invalid_value(de::Unexpected::Str(s), &self))
+    }
+}
+
+/// The same for optional integers, `#[serde(with = "safe_int::option")]`.
+pub mod option {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(n: &Option<u64>, serializer: S) -> Result<S::Ok, S::Error> {
+        match n {
+            Some(n) => super::serialize(n, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<u64>, D::Error> {
+        #[derive(Deserialize)]
+        struct SafeInt(#[serde(with = "super")] u64);
+
+        Ok(Option::<SafeInt>::deserialize(deserializer)?.map(|n| n.0))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde::Serialize;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Amounts {
+        #[serde(with = "crate::safe_int")]
+        amount: u64,
+        #[serde(with = "crate::safe_int::option")]
+        fee: Option<u64>,
+    }
+
+    #[test]
+    fn test_safe_int_boundary() {
+        let below = MAX_SAFE_INTEGER - 1;
+        let above = MAX_SAFE_INTEGER + 1;
+        assert_eq!(MAX_SAFE_INTEGER, 9_007_199_254_740_991);
+
+        assert_eq!(to_json_value(below), Value::from(below));
+        assert_eq!(
+            to_json_value(MAX_SAFE_INTEGER),
+            Value::from(MAX_SAFE_INTEGER)
+        );
+        assert_eq!(
+            to_json_value(above),
+            Value::String("9007199254740992".to_string())
+        );
+        assert_eq!(to_json_value(u64::MAX), Value::String(u64::MAX.to_string()));
+    }
+
+    #[test]
+    fn test_safe_int_serde() {
+        for n in [
+            0,
+            MAX_SAFE_INTEGER - 1,
+            MAX_SAFE_INTEGER,
+            MAX_SAFE_INTEGER + 1,
+            MAX_SAFE_INTEGER + 2,
+        ] {
+            let amounts = Amounts {
+                amount: n,
+                fee: Some(n),
+            };
+            let json = serde_json::to_value(&amounts).unwrap();
+            assert_eq!(json["amount"], to_json_value(n));
+            assert_eq!(json["fee"], to_json_value(n));
+            assert_eq!(serde_json::from_value::<Amounts>(json).unwrap(), amounts);
+        }
+
+        let none = Amounts {
+            amount: 1,
+            fee: None,
+        };
+        let json = serde_json::to_string(&none).unwrap();
+        assert_eq!(json, r#"{"amount":1,"fee":null}"#);
+        assert_eq!(serde_json::from_str::<Amounts>(&json).unwrap(), none);
+
+        // what was stored as a number before is still read
+        let old: Amounts = serde_json::from_str(r#"{"amount":9007199254740993,"fee":2}"#).unwrap();
+        assert_eq!(old.amount, MAX_SAFE_INTEGER + 2);
+        assert!(serde_json::from_str::<Amounts>(r#"{"amount":"-1","fee":null}"#).is_err());
+    }
+}
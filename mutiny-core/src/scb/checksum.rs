@@ -0,0 +1,174 @@
+//! Locating typos in bech32m encoded backups.
+//!
+//! The bech32m checksum is a BCH code, so besides detecting that a backup was
+//! mistyped it can point to the character that is wrong when only one is.
+
+use super::scb_format::SCB_HRP;
+
+const CHARSET: &str = "qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+
+/// What the checksum of a valid bech32m string works out to.
+const BECH32M_CONST: u32 = 0x2bc830a3;
+
+fn polymod_step(chk: u32, value: u8) -> u32 {
+    let top = chk >> 25;
+    let mut chk = (chk & 0x1ffffff) << 5 ^ value as u32;
+    for (i, gen) in GENERATOR.iter().enumerate() {
+        if (top >> i) & 1 == 1 {
+            chk ^= gen;
+        }
+    }
+    chk
+}
+
+/// Returns the position of the first invalid character of a bech32m encoded
+/// backup, or `None` if no single character can be blamed, like when there
+/// are multiple typos or the checksum is valid.
+pub(crate) fn locate_error(s: &str) -> Option<usize> {
+    let chars: Vec<char> = s.chars().collect();
+
+    // bech32 strings are either all lowercase or all uppercase
+    let first_upper = chars.iter().find(|c| c.is_alphabetic())?.is_uppercase();
+    if let Some(pos) = chars
+        .iter()
+        .position(|c| c.is_alphabetic() && c.is_uppercase() != first_upper)
+    {
+        return Some(pos);
+    }
+    let chars: Vec<char> = chars.iter().map(|c| c.to_ascii_lowercase()).collect();
+
+    let Some(separator) = chars.iter().rposition(|c| *c == '1') else {
+        return Some(0);
+    };
+    let hrp: Vec<char> = SCB_HRP.chars().collect();
+    if let Some(pos) = (0..separator.max(hrp.len())).find(|&i| chars.get(i) != hrp.get(i)) {
+        return Some(pos.min(separator));
+    }
+
+    let mut values = Vec::with_capacity(chars.len() - separator - 1);
+    for (pos, c) in chars.iter().enumerate().skip(separator + 1) {
+        match CHARSET.find(*c) {
+            Some(value) => values.push(value as u8),
+            None => return Some(pos),
+        }
+    }
+
+    let hrp_expanded = SCB_HRP
+        .bytes()
+        .map(|b| b >> 5)
+        .chain([0])
+        .chain(SCB_HRP.bytes().map(|b| b & 31));
+    let chk = hrp_expanded
+        .chain(values.iter().copied())
+        .fold(1, polymod_step);
+    let residue = chk ^ BECH32M_CONST;
+    if residue == 0 {
+        return None;
+    }
+
+    // The checksum is linear, so a typo changes it by the typo run through the
+    // checksum of the characters after it. `basis` holds that for each bit of a
+    // typo at the current position, working back from the last character.
+    let mut basis: [u32; 5] = [1, 2, 4, 8, 16];
+    let mut found = None;
+    for i in (0..values.len()).rev() {
+        for typo in 1..32u8 {
+            let effect = (0..5)
+                .filter(|b| (typo >> b) & 1 == 1)
+                .fold(0, |acc, b| acc ^ basis[b]);
+            if effect == residue {
+                if found.is_some() {
+                    // long strings can match a typo in more than one place
+                    return None;
+                }
+                found = Some(separator + 1 + i);
+            }
+        }
+        basis = basis.map(|b| polymod_step(b, 0));
+    }
+
+    found
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::MutinyError;
+    use crate::scb::{EncryptedSCB, StaticChannelBackupStorage};
+    use bitcoin::secp256k1::SecretKey;
+
+    fn encoded_backup() -> String {
+        let encryption_key = SecretKey::from_slice(&[7; 32]).unwrap();
+        StaticChannelBackupStorage::default()
+            .encrypt(&encryption_key)
+            .to_string()
+    }
+
+    /// Swaps the character at the given position for another valid one.
+    fn corrupt(s: &str, pos: usize) -> String {
+        let mut chars: Vec<char> = s.chars().collect();
+        chars[pos] = CHARSET
+            .chars()
+            .find(|c| *c != chars[pos])
+            .expect("charset has more than one character");
+        chars.into_iter().collect()
+    }
+
+    fn parse_position(s: &str) -> Option<usize> {
+        match EncryptedSCB::parse(s) {
+            Err(MutinyError::ScbParse { position }) => position,
+            other => panic!("expected a parse error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_locate_single_typo() {
+        let encoded = encoded_backup();
+        assert!(EncryptedSCB::parse(&encoded).is_ok());
+
+        let data_start = SCB_HRP.len() + 1;
+        for pos in [
+            data_start,
+            data_start + 10,
+            encoded.len() / 2,
+            encoded.len() - 1,
+        ] {
+            assert_eq!(parse_position(&corrupt(&encoded, pos)), Some(pos));
+        }
+    }
+
+    #[test]
+    fn test_locate_invalid_characters() {
+        let encoded = encoded_backup();
+
+        // 'b' is not in the bech32 charset
+        let mut chars: Vec<char> = encoded.chars().collect();
+        chars[12] = 'b';
+        let invalid: String = chars.into_iter().collect();
+        assert_eq!(parse_position(&invalid), Some(12));
+
+        // a wrong human readable part
+        assert_eq!(parse_position(&encoded.replacen("scb", "sxb", 1)), Some(1));
+
+        // mixed case
+        let pos = encoded
+            .char_indices()
+            .skip(SCB_HRP.len() + 1)
+            .find(|(_, c)| c.is_alphabetic())
+            .expect("backup has letters")
+            .0;
+        let mut chars: Vec<char> = encoded.chars().collect();
+        chars[pos] = chars[pos].to_ascii_uppercase();
+        let mixed: String = chars.into_iter().collect();
+        assert_eq!(parse_position(&mixed), Some(pos));
+
+        // two typos can not be blamed on a single character
+        let twice = corrupt(&corrupt(&encoded, 10), 30);
+        assert!(matches!(
+            EncryptedSCB::parse(&twice),
+            Err(MutinyError::ScbParse { .. })
+        ));
+    }
+}
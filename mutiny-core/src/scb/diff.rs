@@ -0,0 +1,740 @@
+//! Incremental static channel backups.
+//!
+//! A [`ScbDiff`] holds only the monitors that changed between two backups, so a
+//! remote that already has the previous backup does not need the full backup again.
+//! The changed and removed monitors carry the hash of the monitor they replace, so
+//! a diff applied to a different backup than it was created from is rejected
+//! instead of silently mixing two backups.
+//!
+//! A diff is encrypted like a full backup, with [`SCB_VERSION_DIFF`] as the version
+//! byte, so a client can keep one full backup and the small diffs after it.
+
+use super::{
+    check_count, gunzip, read_count, read_length_prefixed, read_network, read_optional_string,
+    read_string, write_length_prefixed, write_optional_string, CbcWriter, EncryptedSCB, GzipWriter,
+    ScbGeneration, ScbPeerMetadata, StaticChannelBackup, StaticChannelBackupHashes,
+    StaticChannelBackupStorage,
+};
+use crate::error::MutinyError;
+use crate::nodemanager::NodeIndex;
+use crate::scb::scb_format::*;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::secp256k1::{PublicKey, SecretKey};
+use bitcoin::{secp256k1, Network, OutPoint};
+use flate2::{write::GzEncoder, Compression};
+use lightning::io::{Cursor, Read};
+use lightning::ln::msgs::DecodeError;
+use lightning::util::ser::{Readable, Writeable, Writer};
+use std::collections::HashMap;
+
+/// A channel monitor that was added to the backup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct AddedMonitor {
+    pub node_id: PublicKey,
+    pub outpoint: OutPoint,
+    pub monitor: Vec<u8>,
+}
+
+/// A channel monitor that was updated since the previous backup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ChangedMonitor {
+    pub node_id: PublicKey,
+    pub outpoint: OutPoint,
+    /// Hash of the monitor in the previous backup
+    pub previous: sha256::Hash,
+    pub monitor: Vec<u8>,
+}
+
+/// A channel monitor that is no longer in the backup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct RemovedMonitor {
+    pub node_id: PublicKey,
+    pub outpoint: OutPoint,
+    /// Hash of the monitor in the previous backup
+    pub previous: sha256::Hash,
+}
+
+/// The changes between two [`StaticChannelBackupStorage`]s, created with
+/// [`StaticChannelBackupStorage::diff`] and applied with
+/// [`StaticChannelBackupStorage::apply_diff`].
+///
+/// Only the monitors are diffed, the node indexes, peer connections and labels
+/// are small so the diff always carries all of them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScbDiff {
+    /// The generation of the backup the diff applies to
+    pub(crate) base: ScbGeneration,
+    /// The generation of the backup after applying the diff
+    pub(crate) generation: ScbGeneration,
+    pub(crate) network: Option<Network>,
+    pub(crate) nodes: HashMap<PublicKey, NodeIndex>,
+    pub(crate) peer_connections: HashMap<PublicKey, Vec<String>>,
+    pub(crate) channel_labels: HashMap<OutPoint, String>,
+    pub(crate) peer_metadata: HashMap<PublicKey, ScbPeerMetadata>,
+    pub(crate) peer_last_connected: HashMap<PublicKey, u64>,
+    pub(crate) added: Vec<AddedMonitor>,
+    pub(crate) changed: Vec<ChangedMonitor>,
+    pub(crate) removed: Vec<RemovedMonitor>,
+}
+
+impl ScbDiff {
+    /// Returns true if no monitor changed.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+
+    /// Encrypts the diff with the seed key of the given account, like
+    /// [`StaticChannelBackupStorage::encrypt_for`] does for a full backup.
+    pub(crate) fn encrypt(&self, secret_key: &SecretKey, account: Option<u32>) -> EncryptedSCB {
+        let iv: [u8; IV_LEN] = secp256k1::rand::random();
+        let key = secret_key.secret_bytes();
+        let mut cipher = CbcWriter::new(&key, iv);
+        std::io::Write::write_all(&mut cipher, &[SCB_VERSION_DIFF])
+            .expect("writing to a vec succeeds");
+        let mut writer = GzipWriter(GzEncoder::new(cipher, Compression::best()));
+        Writeable::write(self, &mut writer).expect("writing to a vec succeeds");
+        let cipher = writer.0.finish().expect("writing to a vec succeeds");
+
+        EncryptedSCB {
+            encrypted_scb: cipher.finish(),
+            iv,
+            kdf: None,
+            mac: None,
+            network: self.network,
+            padding: None,
+            account,
+            fingerprint: None,
+        }
+        .with_mac(&key)
+    }
+}
+
+impl EncryptedSCB {
+    /// Decrypts a diff created with [`ScbDiff::encrypt`]. Full backups are
+    /// rejected, like diffs are rejected by [`EncryptedSCB::decrypt`].
+    pub(crate) fn decrypt_diff(&self, secret_key: &SecretKey) -> Result<ScbDiff, MutinyError> {
+        if self.is_passphrase_encrypted() {
+            return Err(MutinyError::PassphraseRequired);
+        }
+
+        let bytes = self
+            .decrypt_payload(&secret_key.secret_bytes())
+            .ok_or(MutinyError::InvalidMnemonic)?;
+        if bytes.first() != Some(&SCB_VERSION_DIFF) {
+            return Err(MutinyError::LnDecodeError);
+        }
+        let diff = ScbDiff::read(&mut Cursor::new(gunzip(&bytes[1..])?))?;
+
+        // the cleartext network is not authenticated
+        if self.network.is_some() && self.network != diff.network {
+            return Err(MutinyError::LnDecodeError);
+        }
+        Ok(diff)
+    }
+}
+
+impl StaticChannelBackupStorage {
+    /// Returns the changes from the previous backup to this one.
+    pub fn diff(&self, previous: &Self) -> ScbDiff {
+        self.diff_from_hashes(previous.generation_info(), &previous.monitor_hashes())
+    }
+
+    /// Returns the changes from a backup with the given generation and monitor
+    /// hashes to this one, so the previous monitors do not have to be kept.
+    pub(crate) fn diff_from_hashes(
+        &self,
+        base: ScbGeneration,
+        previous: &HashMap<PublicKey, StaticChannelBackupHashes>,
+    ) -> ScbDiff {
+        let empty = StaticChannelBackupHashes::default();
+        let mut added = vec![];
+        let mut changed = vec![];
+        let mut removed = vec![];
+
+        for (node_id, (_, backup)) in self.backups.iter() {
+            let previous = previous.get(node_id).unwrap_or(&empty);
+            for (outpoint, monitor) in backup.monitors.iter() {
+                match previous.monitors.get(outpoint) {
+                    None => added.push(AddedMonitor {
+                        node_id: *node_id,
+                        outpoint: *outpoint,
+                        monitor: monitor.clone(),
+                    }),
+                    Some(hash) if *hash != sha256::Hash::hash(monitor) => {
+                        changed.push(ChangedMonitor {
+                            node_id: *node_id,
+                            outpoint: *outpoint,
+                            previous: *hash,
+                            monitor: monitor.clone(),
+                        })
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+
+        for (node_id, hashes) in previous.iter() {
+            let current = self.backups.get(node_id).map(|(_, b)| &b.monitors);
+            for (outpoint, hash) in hashes.monitors.iter() {
+                if !current.is_some_and(|c| c.contains_key(outpoint)) {
+                    removed.push(RemovedMonitor {
+                        node_id: *node_id,
+                        outpoint: *outpoint,
+                        previous: *hash,
+                    });
+                }
+            }
+        }
+
+        ScbDiff {
+            base,
+            generation: self.generation_info(),
+            network: self.network,
+            nodes: self
+                .backups
+                .iter()
+                .map(|(pk, (node_index, _))| (*pk, node_index.clone()))
+                .collect(),
+            peer_connections: self.peer_connections.clone(),
+            channel_labels: self.channel_labels.clone(),
+            peer_metadata: self.peer_metadata.clone(),
+            peer_last_connected: self.peer_last_connected.clone(),
+            added,
+            changed,
+            removed,
+        }
+    }
+
+    /// Applies a diff created from this backup. Errors with
+    /// [`MutinyError::ScbDiffConflict`] if the diff was created from another
+    /// backup, in which case this backup is left unchanged.
+    pub fn apply_diff(&mut self, diff: &ScbDiff) -> Result<(), MutinyError> {
+        if self.generation_info() != diff.base {
+            return Err(MutinyError::ScbDiffConflict);
+        }
+
+        let mut backups: HashMap<PublicKey, StaticChannelBackup> = self
+            .backups
+            .iter()
+            .map(|(pk, (_, backup))| (*pk, backup.clone()))
+            .collect();
+
+        for removed in diff.removed.iter() {
+            let monitors = backups
+                .get_mut(&removed.node_id)
+                .map(|b| &mut b.monitors)
+                .ok_or(MutinyError::ScbDiffConflict)?;
+            match monitors.remove(&removed.outpoint) {
+                Some(monitor) if sha256::Hash::hash(&monitor) == removed.previous => {}
+                _ => return Err(MutinyError::ScbDiffConflict),
+            }
+        }
+
+        for changed in diff.changed.iter() {
+            let monitor = backups
+                .get_mut(&changed.node_id)
+                .and_then(|b| b.monitors.get_mut(&changed.outpoint))
+                .ok_or(MutinyError::ScbDiffConflict)?;
+            if sha256::Hash::hash(monitor) != changed.previous {
+                return Err(MutinyError::ScbDiffConflict);
+            }
+            *monitor = changed.monitor.clone();
+        }
+
+        for added in diff.added.iter() {
+            let backup = backups.entry(added.node_id).or_default();
+            if backup
+                .monitors
+                .insert(added.outpoint, added.monitor.clone())
+                .is_some()
+            {
+                return Err(MutinyError::ScbDiffConflict);
+            }
+        }
+
+        // nodes that are no longer in the backup must not have monitors left
+        let mut new_backups = HashMap::with_capacity(diff.nodes.len());
+        for (node_id, backup) in backups {
+            match diff.nodes.get(&node_id) {
+                Some(node_index) => {
+                    new_backups.insert(node_id, (node_index.clone(), backup));
+                }
+                None if backup.monitors.is_empty() => {}
+                None => return Err(MutinyError::ScbDiffConflict),
+            }
+        }
+        for (node_id, node_index) in diff.nodes.iter() {
+            new_backups
+                .entry(*node_id)
+                .or_insert_with(|| (node_index.clone(), StaticChannelBackup::default()));
+        }
+
+        self.backups = new_backups;
+        self.peer_connections = diff.peer_connections.clone();
+        self.channel_labels = diff.channel_labels.clone();
+        self.peer_metadata = diff.peer_metadata.clone();
+        self.peer_last_connected = diff.peer_last_connected.clone();
+        self.created_at = diff.generation.created_at;
+        self.generation = diff.generation.generation;
+        self.network = diff.network;
+
+        Ok(())
+    }
+}
+
+fn write_generation<W: Writer>(
+    writer: &mut W,
+    generation: &ScbGeneration,
+) -> Result<(), lightning::io::Error> {
+    writer.write_all(&generation.created_at.to_be_bytes())?;
+    writer.write_all(&generation.generation.to_be_bytes())
+}
+
+fn read_generation<R: Read>(reader: &mut R) -> Result<ScbGeneration, DecodeError> {
+    Ok(ScbGeneration {
+        created_at: Readable::read(reader)?,
+        generation: Readable::read(reader)?,
+    })
+}
+
+fn write_monitor_key<W: Writer>(
+    writer: &mut W,
+    node_id: &PublicKey,
+    outpoint: &OutPoint,
+) -> Result<(), lightning::io::Error> {
+    writer.write_all(&node_id.serialize())?;
+    writer.write_all(&outpoint.txid[..])?;
+    writer.write_all(&outpoint.vout.to_be_bytes())
+}
+
+fn read_public_key<R: Read>(reader: &mut R) -> Result<PublicKey, DecodeError> {
+    let mut pk = [0u8; PUBKEY_LEN];
+    reader.read_exact(&mut pk)?;
+    PublicKey::from_slice(&pk).map_err(|_| DecodeError::InvalidValue)
+}
+
+fn read_outpoint<R: Read>(reader: &mut R) -> Result<OutPoint, DecodeError> {
+    let mut txid = [0u8; TXID_LEN];
+    reader.read_exact(&mut txid)?;
+    let vout: u32 = Readable::read(reader)?;
+    Ok(OutPoint {
+        txid: bitcoin::Txid::from_slice(&txid).map_err(|_| DecodeError::InvalidValue)?,
+        vout,
+    })
+}
+
+fn read_monitor_key<R: Read>(reader: &mut R) -> Result<(PublicKey, OutPoint), DecodeError> {
+    let node_id = read_public_key(reader)?;
+    let outpoint = read_outpoint(reader)?;
+    Ok((node_id, outpoint))
+}
+
+fn read_hash<R: Read>(reader: &mut R) -> Result<sha256::Hash, DecodeError> {
+    let mut hash = [0u8; 32];
+    reader.read_exact(&mut hash)?;
+    Ok(sha256::Hash::from_inner(hash))
+}
+
+impl Writeable for ScbDiff {
+    fn write<W: Writer>(&self, writer: &mut W) -> Result<(), lightning::io::Error> {
+        write_generation(writer, &self.base)?;
+        write_generation(writer, &self.generation)?;
+        let magic = self.network.map_or(UNKNOWN_NETWORK_MAGIC, |n| n.magic());
+        writer.write_all(&magic.to_be_bytes())?;
+
+        let len = self.nodes.len() as LengthPrefix;
+        writer.write_all(&len.to_be_bytes())?;
+        for (node_id, node_index) in self.nodes.iter() {
+            writer.write_all(&node_id.serialize())?;
+            node_index.write(writer)?;
+        }
+
+        let len = self.peer_connections.len() as LengthPrefix;
+        writer.write_all(&len.to_be_bytes())?;
+        for (node_id, connections) in self.peer_connections.iter() {
+            writer.write_all(&node_id.serialize())?;
+            let len = connections.len() as LengthPrefix;
+            writer.write_all(&len.to_be_bytes())?;
+            for connection in connections {
+                write_length_prefixed(writer, connection.as_bytes())?;
+            }
+        }
+
+        let len = self.channel_labels.len() as LengthPrefix;
+        writer.write_all(&len.to_be_bytes())?;
+        for (outpoint, label) in self.channel_labels.iter() {
+            writer.write_all(&outpoint.txid[..])?;
+            writer.write_all(&outpoint.vout.to_be_bytes())?;
+            write_length_prefixed(writer, label.as_bytes())?;
+        }
+
+        let len = self.peer_metadata.len() as LengthPrefix;
+        writer.write_all(&len.to_be_bytes())?;
+        for (node_id, metadata) in self.peer_metadata.iter() {
+            writer.write_all(&node_id.serialize())?;
+            write_optional_string(writer, &metadata.alias)?;
+            write_optional_string(writer, &metadata.color)?;
+            write_optional_string(writer, &metadata.label)?;
+        }
+
+        let len = self.added.len() as LengthPrefix;
+        writer.write_all(&len.to_be_bytes())?;
+        for added in self.added.iter() {
+            write_monitor_key(writer, &added.node_id, &added.outpoint)?;
+            write_length_prefixed(writer, &added.monitor)?;
+        }
+
+        let len = self.changed.len() as LengthPrefix;
+        writer.write_all(&len.to_be_bytes())?;
+        for changed in self.changed.iter() {
+            write_monitor_key(writer, &changed.node_id, &changed.outpoint)?;
+            writer.write_all(&changed.previous[..])?;
+            write_length_prefixed(writer, &changed.monitor)?;
+        }
+
+        let len = self.removed.len() as LengthPrefix;
+        writer.write_all(&len.to_be_bytes())?;
+        for removed in self.removed.iter() {
+            write_monitor_key(writer, &removed.node_id, &removed.outpoint)?;
+            writer.write_all(&removed.previous[..])?;
+        }
+
+        // added after the first diffs, so it is only written when there is any
+        if !self.peer_last_connected.is_empty() {
+            let len = self.peer_last_connected.len() as LengthPrefix;
+            writer.write_all(&len.to_be_bytes())?;
+            for (node_id, last_connected) in self.peer_last_connected.iter() {
+                writer.write_all(&node_id.serialize())?;
+                writer.write_all(&last_connected.to_be_bytes())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Readable for ScbDiff {
+    fn read<R: Read>(reader: &mut R) -> Result<Self, DecodeError> {
+        let base = read_generation(reader)?;
+        let generation = read_generation(reader)?;
+        let mut magic = [0u8; NETWORK_MAGIC_LEN];
+        reader.read_exact(&mut magic)?;
+        let network = if u32::from_be_bytes(magic) == UNKNOWN_NETWORK_MAGIC {
+            None
+        } else {
+            Some(read_network(magic)?)
+        };
+
+        let len = read_count(reader)?;
+        let mut nodes = HashMap::new();
+        for _ in 0..len {
+            let node_id = read_public_key(reader)?;
+            let node_index = Readable::read(reader)?;
+            nodes.insert(node_id, node_index);
+        }
+
+        let len = read_count(reader)?;
+        let mut peer_connections = HashMap::new();
+        for _ in 0..len {
+            let node_id = read_public_key(reader)?;
+            let count = read_count(reader)?;
+            let mut connections = vec![];
+            for _ in 0..count {
+                connections.push(read_string(reader)?);
+            }
+            peer_connections.insert(node_id, connections);
+        }
+
+        let len = read_count(reader)?;
+        let mut channel_labels = HashMap::new();
+        for _ in 0..len {
+            let outpoint = read_outpoint(reader)?;
+            channel_labels.insert(outpoint, read_string(reader)?);
+        }
+
+        let len = read_count(reader)?;
+        let mut peer_metadata = HashMap::new();
+        for _ in 0..len {
+            let node_id = read_public_key(reader)?;
+            let metadata = ScbPeerMetadata {
+                alias: read_optional_string(reader)?,
+                color: read_optional_string(reader)?,
+                label: read_optional_string(reader)?,
+            };
+            peer_metadata.insert(node_id, metadata);
+        }
+
+        let len = read_count(reader)?;
+        let mut added = vec![];
+        for _ in 0..len {
+            let (node_id, outpoint) = read_monitor_key(reader)?;
+            let monitor = read_length_prefixed(reader, MAX_MONITOR_LEN)?;
+            added.push(AddedMonitor {
+                node_id,
+                outpoint,
+                monitor,
+            });
+        }
+
+        let len = read_count(reader)?;
+        let mut changed = vec![];
+        for _ in 0..len {
+            let (node_id, outpoint) = read_monitor_key(reader)?;
+            let previous = read_hash(reader)?;
+            let monitor = read_length_prefixed(reader, MAX_MONITOR_LEN)?;
+            changed.push(ChangedMonitor {
+                node_id,
+                outpoint,
+                previous,
+                monitor,
+            });
+        }
+
+        let len = read_count(reader)?;
+        let mut removed = vec![];
+        for _ in 0..len {
+            let (node_id, outpoint) = read_monitor_key(reader)?;
+            let previous = read_hash(reader)?;
+            removed.push(RemovedMonitor {
+                node_id,
+                outpoint,
+                previous,
+            });
+        }
+
+        let mut peer_last_connected = HashMap::new();
+        let mut first = [0u8; 1];
+        if reader.read(&mut first)? != 0 {
+            let mut rest = [0u8; LENGTH_PREFIX_LEN - 1];
+            reader.read_exact(&mut rest)?;
+            let len = check_count(LengthPrefix::from_be_bytes([
+                first[0], rest[0], rest[1], rest[2],
+            ]))?;
+            for _ in 0..len {
+                let node_id = read_public_key(reader)?;
+                let last_connected: u64 = Readable::read(reader)?;
+                peer_last_connected.insert(node_id, last_connected);
+            }
+        }
+
+        Ok(Self {
+            base,
+            generation,
+            network,
+            nodes,
+            peer_connections,
+            channel_labels,
+            peer_metadata,
+            peer_last_connected,
+            added,
+            changed,
+            removed,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bitcoin::hashes::hex::FromHex;
+    use lightning::io::Cursor;
+    use std::str::FromStr;
+
+    fn node_id() -> PublicKey {
+        PublicKey::from_str("02cae09cf2c8842ace44068a5bf3117a494ebbf69a99e79712483c36f97cdb7b54")
+            .unwrap()
+    }
+
+    fn outpoint(vout: u32) -> OutPoint {
+        OutPoint {
+            txid: bitcoin::Txid::from_hex(
+                "830b1c110ef6c78312a8f4c798da0bfbacdfc9c80c7d458ca614e7b1543f5b03",
+            )
+            .unwrap(),
+            vout,
+        }
+    }
+
+    fn storage(monitors: &[(u32, &[u8])], generation: u64) -> StaticChannelBackupStorage {
+        let backup = StaticChannelBackup {
+            monitors: monitors
+                .iter()
+                .map(|(vout, monitor)| (outpoint(*vout), monitor.to_vec()))
+                .collect(),
+        };
+        let node_index = NodeIndex {
+            child_index: 0,
+            lsp: None,
+            archived: Some(false),
+            created_at: None,
+            alias: None,
+        };
+        StaticChannelBackupStorage {
+            backups: vec![(node_id(), (node_index, backup))]
+                .into_iter()
+                .collect(),
+            peer_connections: vec![(node_id(), vec!["127.0.0.1:9735".to_string()])]
+                .into_iter()
+                .collect(),
+            created_at: 1_700_000_000 + generation,
+            generation,
+            network: Some(Network::Signet),
+            ..Default::default()
+        }
+    }
+
+    fn round_trip(diff: &ScbDiff) -> ScbDiff {
+        let read = ScbDiff::read(&mut Cursor::new(diff.encode())).unwrap();
+        assert_eq!(&read, diff);
+        read
+    }
+
+    #[test]
+    fn test_empty_scb_diff() {
+        let previous = storage(&[(0, &[1, 2, 3])], 1);
+        let mut current = previous.clone();
+        current.generation = 2;
+
+        let diff = round_trip(&current.diff(&previous));
+        assert!(diff.is_empty());
+
+        let mut applied = previous.clone();
+        applied.apply_diff(&diff).unwrap();
+        assert!(applied == current);
+
+        // a diff from an empty backup adds everything
+        let diff = round_trip(&current.diff(&StaticChannelBackupStorage::default()));
+        assert_eq!(diff.added.len(), 1);
+        let mut applied = StaticChannelBackupStorage::default();
+        applied.apply_diff(&diff).unwrap();
+        assert!(applied == current);
+    }
+
+    #[test]
+    fn test_scb_diff_changes_and_removals() {
+        let previous = storage(&[(0, &[1]), (1, &[2]), (2, &[3])], 1);
+        let mut current = storage(&[(0, &[1]), (1, &[4]), (3, &[5])], 2);
+        current.peer_last_connected.insert(node_id(), 1_700_000_002);
+
+        let diff = round_trip(&current.diff(&previous));
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0].outpoint, outpoint(3));
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].outpoint, outpoint(1));
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.removed[0].outpoint, outpoint(2));
+
+        // the unchanged monitor is not sent again
+        assert!(diff.encode().len() < current.encode().len());
+
+        let mut applied = previous.clone();
+        applied.apply_diff(&diff).unwrap();
+        assert!(applied == current);
+
+        // removing the node removes all of its monitors
+        let empty = StaticChannelBackupStorage {
+            generation: 3,
+            ..Default::default()
+        };
+        let diff = round_trip(&empty.diff(&current));
+        assert_eq!(diff.removed.len(), 3);
+        applied.apply_diff(&diff).unwrap();
+        assert!(applied.backups.is_empty());
+        assert!(applied == empty);
+    }
+
+    #[test]
+    fn test_scb_diff_conflicting_apply() {
+        let previous = storage(&[(0, &[1]), (1, &[2])], 1);
+        let current = storage(&[(0, &[3])], 2);
+        let diff = current.diff(&previous);
+
+        // a backup of another generation
+        let mut other = storage(&[(0, &[1]), (1, &[2])], 5);
+        assert!(matches!(
+            other.apply_diff(&diff),
+            Err(MutinyError::ScbDiffConflict)
+        ));
+
+        // the same generation but a different monitor than the diff was created from
+        let mut diverged = storage(&[(0, &[9]), (1, &[2])], 1);
+        let before = diverged.clone();
+        assert!(matches!(
+            diverged.apply_diff(&diff),
+            Err(MutinyError::ScbDiffConflict)
+        ));
+        // nothing was applied
+        assert!(diverged == before);
+
+        // applying the same diff twice
+        let mut applied = previous.clone();
+        applied.apply_diff(&diff).unwrap();
+        assert!(matches!(
+            applied.apply_diff(&diff),
+            Err(MutinyError::ScbDiffConflict)
+        ));
+
+        // adding a monitor that already exists
+        let mut added = diff.clone();
+        added.added.push(AddedMonitor {
+            node_id: node_id(),
+            outpoint: outpoint(0),
+            monitor: vec![7],
+        });
+        let mut base = previous.clone();
+        assert!(matches!(
+            base.apply_diff(&added),
+            Err(MutinyError::ScbDiffConflict)
+        ));
+    }
+
+    #[test]
+    fn test_encrypted_scb_diff() {
+        let key = SecretKey::from_slice(&[3; 32]).unwrap();
+        // random, so it does not compress away
+        let mut large = vec![0u8; 10_000];
+        getrandom::getrandom(&mut large).unwrap();
+        let previous = storage(&[(0, large.as_slice()), (1, &[2]), (2, &[3])], 1);
+        let current = storage(&[(0, large.as_slice()), (1, &[4]), (3, &[5])], 2);
+        let diff = current.diff(&previous);
+
+        let encrypted = diff.encrypt(&key, None);
+        let read = EncryptedSCB::from_str(&encrypted.to_string()).unwrap();
+        assert_eq!(read.network, Some(Network::Signet));
+        let decrypted = read.decrypt_diff(&key).unwrap();
+        assert_eq!(decrypted, diff);
+
+        // the added channel, the updated monitor and the removed channel
+        let mut applied = previous.clone();
+        applied.apply_diff(&decrypted).unwrap();
+        assert!(applied == current);
+        assert!(applied.backups[&node_id()]
+            .1
+            .monitors
+            .contains_key(&outpoint(3)));
+        assert_eq!(
+            applied.backups[&node_id()].1.monitors[&outpoint(1)],
+            vec![4]
+        );
+        assert!(!applied.backups[&node_id()]
+            .1
+            .monitors
+            .contains_key(&outpoint(2)));
+
+        // the unchanged monitor is not encrypted again
+        assert!(encrypted.to_string().len() < current.encrypt(&key).to_string().len() / 2);
+
+        // a diff is never read as a full backup, nor the other way around
+        assert!(encrypted.decrypt(&key).is_err());
+        assert!(matches!(
+            current.encrypt(&key).decrypt_diff(&key),
+            Err(MutinyError::LnDecodeError)
+        ));
+
+        let wrong_key = SecretKey::from_slice(&[4; 32]).unwrap();
+        assert!(matches!(
+            encrypted.decrypt_diff(&wrong_key),
+            Err(MutinyError::InvalidMnemonic)
+        ));
+    }
+}
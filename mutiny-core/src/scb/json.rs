@@ -0,0 +1,198 @@
+//! The human readable JSON representation of static channel backups.
+//!
+//! This is only meant for debugging and support, the binary format in
+//! [`super::scb_format`] is what gets exported. Monitors are hex strings and
+//! outpoints are `txid:vout` strings, so the maps can be used as JSON objects.
+
+use super::{ScbPeerMetadata, StaticChannelBackup, StaticChannelBackupStorage};
+use crate::nodemanager::NodeIndex;
+use bitcoin::hashes::hex::{FromHex, ToHex};
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::{Network, OutPoint};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct StaticChannelBackupJson {
+    monitors: BTreeMap<String, String>,
+}
+
+impl From<StaticChannelBackup> for StaticChannelBackupJson {
+    fn from(backup: StaticChannelBackup) -> Self {
+        let monitors = backup
+            .monitors
+            .iter()
+            .map(|(outpoint, monitor)| (outpoint.to_string(), monitor.to_hex()))
+            .collect();
+        Self { monitors }
+    }
+}
+
+impl TryFrom<StaticChannelBackupJson> for StaticChannelBackup {
+    type Error = String;
+
+    fn try_from(json: StaticChannelBackupJson) -> Result<Self, Self::Error> {
+        let mut backup = StaticChannelBackup::default();
+        for (outpoint, monitor) in json.monitors {
+            let parsed = parse_outpoint(&outpoint)?;
+            let monitor = Vec::<u8>::from_hex(&monitor)
+                .map_err(|_| format!("Invalid monitor hex for {outpoint}"))?;
+            // an outpoint can be repeated in different case, keep the newest monitor
+            backup.insert_monitor(parsed, monitor);
+        }
+        Ok(backup)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct NodeBackupJson {
+    node_index: NodeIndex,
+    backup: StaticChannelBackup,
+}
+
+/// The connection strings of a peer, with when it was last connected to.
+/// Older exports have only the strings, or a single string.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+enum PeerConnectionsJson {
+    Recent {
+        connections: Vec<String>,
+        last_connected: u64,
+    },
+    Many(Vec<String>),
+    One(String),
+}
+
+impl PeerConnectionsJson {
+    fn new(connections: Vec<String>, last_connected: Option<u64>) -> Self {
+        match last_connected {
+            Some(last_connected) => PeerConnectionsJson::Recent {
+                connections,
+                last_connected,
+            },
+            None => PeerConnectionsJson::Many(connections),
+        }
+    }
+
+    fn into_parts(self) -> (Vec<String>, Option<u64>) {
+        match self {
+            PeerConnectionsJson::Recent {
+                connections,
+                last_connected,
+            } => (connections, Some(last_connected)),
+            PeerConnectionsJson::Many(connections) => (connections, None),
+            PeerConnectionsJson::One(connection) => (vec![connection], None),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct StaticChannelBackupStorageJson {
+    backups: BTreeMap<String, NodeBackupJson>,
+    peer_connections: BTreeMap<String, PeerConnectionsJson>,
+    #[serde(default)]
+    created_at: u64,
+    #[serde(default)]
+    generation: u64,
+    #[serde(default)]
+    network: Option<Network>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    channel_labels: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    peer_metadata: BTreeMap<String, ScbPeerMetadata>,
+}
+
+impl From<StaticChannelBackupStorage> for StaticChannelBackupStorageJson {
+    fn from(storage: StaticChannelBackupStorage) -> Self {
+        let backups = storage
+            .backups
+            .into_iter()
+            .map(|(pubkey, (node_index, backup))| {
+                (pubkey.to_string(), NodeBackupJson { node_index, backup })
+            })
+            .collect();
+        let peer_last_connected = storage.peer_last_connected;
+        let peer_connections = storage
+            .peer_connections
+            .into_iter()
+            .map(|(pubkey, conns)| {
+                let last_connected = peer_last_connected.get(&pubkey).copied();
+                let conns = PeerConnectionsJson::new(conns, last_connected);
+                (pubkey.to_string(), conns)
+            })
+            .collect();
+        let channel_labels = storage
+            .channel_labels
+            .into_iter()
+            .map(|(outpoint, label)| (outpoint.to_string(), label))
+            .collect();
+        let peer_metadata = storage
+            .peer_metadata
+            .into_iter()
+            .map(|(pubkey, metadata)| (pubkey.to_string(), metadata))
+            .collect();
+
+        Self {
+            backups,
+            peer_connections,
+            created_at: storage.created_at,
+            generation: storage.generation,
+            network: storage.network,
+            channel_labels,
+            peer_metadata,
+        }
+    }
+}
+
+impl TryFrom<StaticChannelBackupStorageJson> for StaticChannelBackupStorage {
+    type Error = String;
+
+    fn try_from(json: StaticChannelBackupStorageJson) -> Result<Self, Self::Error> {
+        let mut backups = HashMap::with_capacity(json.backups.len());
+        for (pubkey, node) in json.backups {
+            backups.insert(parse_pubkey(&pubkey)?, (node.node_index, node.backup));
+        }
+
+        let mut peer_connections = HashMap::with_capacity(json.peer_connections.len());
+        let mut peer_last_connected = HashMap::new();
+        for (pubkey, conns) in json.peer_connections {
+            let pubkey = parse_pubkey(&pubkey)?;
+            let (conns, last_connected) = conns.into_parts();
+            peer_connections.insert(pubkey, conns);
+            if let Some(last_connected) = last_connected {
+                peer_last_connected.insert(pubkey, last_connected);
+            }
+        }
+
+        let mut channel_labels = HashMap::with_capacity(json.channel_labels.len());
+        for (outpoint, label) in json.channel_labels {
+            channel_labels.insert(parse_outpoint(&outpoint)?, label);
+        }
+
+        let mut peer_metadata = HashMap::with_capacity(json.peer_metadata.len());
+        for (pubkey, metadata) in json.peer_metadata {
+            peer_metadata.insert(parse_pubkey(&pubkey)?, metadata);
+        }
+
+        Ok(Self {
+            backups,
+            peer_connections,
+            created_at: json.created_at,
+            generation: json.generation,
+            network: json.network,
+            channel_labels,
+            peer_metadata,
+            peer_last_connected,
+        })
+    }
+}
+
+/// Parses a `txid:vout` string, the txid has to be the full 64 hex characters.
+fn parse_outpoint(s: &str) -> Result<OutPoint, String> {
+    OutPoint::from_str(s).map_err(|_| format!("Invalid outpoint {s}, expected txid:vout"))
+}
+
+fn parse_pubkey(s: &str) -> Result<PublicKey, String> {
+    PublicKey::from_str(s).map_err(|_| format!("Invalid public key {s}"))
+}
@@ -0,0 +1,419 @@
+//! Reading the `channel.backup` file of an LND node, for users moving to Mutiny.
+//!
+//! The file is a multi channel backup encrypted with XChaCha20-Poly1305, under the
+//! sha256 of the LND static backup public key, `m/1017'/<coin>'/7'/0/0` from the LND
+//! root key. `chantools showrootkey` prints that root key from the LND seed.
+//!
+//! The funds of these channels are locked to keys of the LND node, so Mutiny can't
+//! restore them as its own channels. The import lists each channel with the peer and
+//! how to reach it, which is what is needed to ask the peer to force close it with
+//! a recovery tool like chantools.
+//! Channels that can't be read are reported on their own so the rest still imports.
+
+use crate::error::MutinyError;
+use bitcoin::blockdata::constants::genesis_block;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::secp256k1::{PublicKey, Secp256k1};
+use bitcoin::util::bip32::{ChildNumber, ExtendedPrivKey, ExtendedPubKey};
+use bitcoin::{Network, OutPoint, Txid};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+const LND_PURPOSE: u32 = 1017;
+const LND_STATIC_BACKUP_FAMILY: u32 = 7;
+const NONCE_LEN: usize = 24;
+const MULTI_VERSION: u8 = 0;
+/// The key families and indexes of the multisig key and four base points of the channel
+const LOCAL_CONFIG_LEN: usize = 5 * 8;
+/// The multisig key and four base points of the peer, after its csv delay
+const REMOTE_KEYS_LEN: usize = 5 * 33;
+/// The shachain root public key with its key family and index
+const SHACHAIN_ROOT_LEN: usize = 33 + 8;
+
+/// The commitment type of an LND channel, the version of its backup.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LndChannelType {
+    Legacy,
+    Tweakless,
+    Anchors,
+    AnchorsZeroFeeHtlc,
+    ScriptEnforcedLease,
+}
+
+impl LndChannelType {
+    fn from_version(version: u8) -> Option<Self> {
+        match version {
+            0 => Some(Self::Legacy),
+            1 => Some(Self::Tweakless),
+            2 => Some(Self::Anchors),
+            3 => Some(Self::AnchorsZeroFeeHtlc),
+            4 => Some(Self::ScriptEnforcedLease),
+            _ => None,
+        }
+    }
+}
+
+/// A channel read from an LND backup.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LndChannel {
+    pub channel_type: LndChannelType,
+    pub is_initiator: bool,
+    pub funding_outpoint: OutPoint,
+    pub short_channel_id: u64,
+    pub remote_pubkey: PublicKey,
+    /// The addresses of the peer, as `host:port`
+    pub addresses: Vec<String>,
+    pub csv_delay: u16,
+    /// The block height until which the channel is leased, for lease channels
+    pub lease_expiry: Option<u32>,
+}
+
+/// A channel in the backup that could not be imported.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LndUnsupportedChannel {
+    /// The position of the channel in the backup
+    pub index: usize,
+    pub version: u8,
+    pub reason: String,
+}
+
+/// The channels of an LND backup.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LndBackupImport {
+    pub channels: Vec<LndChannel>,
+    pub unsupported: Vec<LndUnsupportedChannel>,
+}
+
+impl LndBackupImport {
+    /// The connection strings of each peer, like
+    /// [`StaticChannelBackupStorage`](super::StaticChannelBackupStorage) keeps them.
+    /// Only clearnet addresses are included, onion addresses can't be reached from the wallet.
+    pub fn peer_connections(&self) -> HashMap<PublicKey, Vec<String>> {
+        let mut peer_connections: HashMap<PublicKey, Vec<String>> = HashMap::new();
+        for channel in self.channels.iter() {
+            let connections = peer_connections.entry(channel.remote_pubkey).or_default();
+            for address in channel.addresses.iter() {
+                let conn = format!("{}@{address}", channel.remote_pubkey);
+                if !address.contains(".onion:") && !connections.contains(&conn) {
+                    connections.push(conn);
+                }
+            }
+        }
+        peer_connections
+    }
+}
+
+/// Derives the key LND encrypts its channel backups with from the LND root key.
+pub fn lnd_backup_key(root: &ExtendedPrivKey) -> [u8; 32] {
+    let coin_type = match root.network {
+        Network::Bitcoin => 0,
+        _ => 1,
+    };
+    let path = [
+        ChildNumber::Hardened { index: LND_PURPOSE },
+        ChildNumber::Hardened { index: coin_type },
+        ChildNumber::Hardened {
+            index: LND_STATIC_BACKUP_FAMILY,
+        },
+        ChildNumber::Normal { index: 0 },
+        ChildNumber::Normal { index: 0 },
+    ];
+
+    let secp = Secp256k1::new();
+    let key = root
+        .derive_priv(&secp, &path)
+        .expect("derivation can only fail for invalid keys");
+    let pubkey = ExtendedPubKey::from_priv(&secp, &key).public_key;
+    sha256::Hash::hash(&pubkey.serialize()).into_inner()
+}
+
+/// Decrypts and reads an LND `channel.backup` file with the key from [`lnd_backup_key`].
+/// Channels on another network than the given one are reported as unsupported.
+pub fn import_lnd_backup(
+    backup: &[u8],
+    key: &[u8; 32],
+    network: Network,
+) -> Result<LndBackupImport, MutinyError> {
+    let multi = decrypt_lnd_backup(backup, key)?;
+    let mut reader = SliceReader(&multi);
+
+    if reader.read_u8()? != MULTI_VERSION {
+        return Err(MutinyError::LnDecodeError);
+    }
+    let count = reader.read_u32()?;
+
+    let chain_hash = genesis_block(network).block_hash().into_inner();
+    let mut import = LndBackupImport::default();
+    for index in 0..count as usize {
+        let version = reader.read_u8()?;
+        let len = reader.read_u16()?;
+        let body = reader.take(len as usize)?;
+
+        let unsupported = |reason: &str| LndUnsupportedChannel {
+            index,
+            version,
+            reason: reason.to_string(),
+        };
+        let Some(channel_type) = LndChannelType::from_version(version) else {
+            import
+                .unsupported
+                .push(unsupported("unsupported channel type"));
+            continue;
+        };
+        match read_channel(channel_type, body) {
+            Ok((chain, _)) if chain != chain_hash => import
+                .unsupported
+                .push(unsupported("channel is on a different network")),
+            Ok((_, channel)) => import.channels.push(channel),
+            Err(_) => import
+                .unsupported
+                .push(unsupported("channel could not be read")),
+        }
+    }
+
+    Ok(import)
+}
+
+/// The backup is the nonce followed by the ciphertext, with the nonce as associated data.
+fn decrypt_lnd_backup(backup: &[u8], key: &[u8; 32]) -> Result<Vec<u8>, MutinyError> {
+    if backup.len() < NONCE_LEN {
+        return Err(MutinyError::LnDecodeError);
+    }
+    let (nonce, ciphertext) = backup.split_at(NONCE_LEN);
+    let payload = Payload {
+        msg: ciphertext,
+        aad: nonce,
+    };
+    XChaCha20Poly1305::new(key.into())
+        .decrypt(XNonce::from_slice(nonce), payload)
+        .map_err(|_| MutinyError::BackupDecryptionFailed)
+}
+
+/// Reads a single channel backup, laid out like LND's `chanbackup.Single`,
+/// returning the chain hash with the channel.
+fn read_channel(
+    channel_type: LndChannelType,
+    body: &[u8],
+) -> Result<([u8; 32], LndChannel), MutinyError> {
+    let mut reader = SliceReader(body);
+    let is_initiator = reader.read_u8()? != 0;
+    let chain_hash = reader.read_array::<32>()?;
+    let txid = Txid::from_slice(reader.take(32)?).map_err(|_| MutinyError::LnDecodeError)?;
+    let vout = reader.read_u16()? as u32;
+    let short_channel_id = reader.read_u64()?;
+    let remote_pubkey =
+        PublicKey::from_slice(reader.take(33)?).map_err(|_| MutinyError::LnDecodeError)?;
+    let num_addrs = reader.read_u16()?;
+    let addresses = (0..num_addrs)
+        .map(|_| read_address(&mut reader))
+        .collect::<Result<Vec<_>, _>>()?;
+    let csv_delay = reader.read_u16()?;
+
+    // the keys of the channel and the csv delay of the peer are only needed by LND itself
+    reader.take(LOCAL_CONFIG_LEN)?;
+    let _remote_csv_delay = reader.read_u16()?;
+    reader.take(REMOTE_KEYS_LEN + SHACHAIN_ROOT_LEN)?;
+    let lease_expiry = match channel_type {
+        LndChannelType::ScriptEnforcedLease => Some(reader.read_u32()?),
+        _ => None,
+    };
+
+    let channel = LndChannel {
+        channel_type,
+        is_initiator,
+        funding_outpoint: OutPoint { txid, vout },
+        short_channel_id,
+        remote_pubkey,
+        addresses,
+        csv_delay,
+        lease_expiry,
+    };
+    Ok((chain_hash, channel))
+}
+
+/// Reads an address of the peer. The length of an address type we don't know is
+/// unknown, so the rest of the channel can't be read after one.
+fn read_address(reader: &mut SliceReader) -> Result<String, MutinyError> {
+    let host = match reader.read_u8()? {
+        1 => Ipv4Addr::from(reader.read_array::<4>()?).to_string(),
+        2 => format!("[{}]", Ipv6Addr::from(reader.read_array::<16>()?)),
+        3 => format!("{}.onion", base32(reader.take(10)?)),
+        4 => format!("{}.onion", base32(reader.take(35)?)),
+        _ => return Err(MutinyError::LnDecodeError),
+    };
+    let port = reader.read_u16()?;
+    Ok(format!("{host}:{port}"))
+}
+
+/// Lowercase RFC 4648 base32 without padding, as onion addresses are written.
+fn base32(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+    let mut out = String::new();
+    let mut buffer = 0u16;
+    let mut bits = 0;
+    for byte in bytes {
+        buffer = (buffer << 8) | *byte as u16;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    out
+}
+
+/// Reads the big endian integers LND serializes with.
+struct SliceReader<'a>(&'a [u8]);
+
+impl<'a> SliceReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], MutinyError> {
+        if self.0.len() < len {
+            return Err(MutinyError::LnDecodeError);
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], MutinyError> {
+        let mut array = [0u8; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, MutinyError> {
+        Ok(self.read_array::<1>()?[0])
+    }
+
+    fn read_u16(&mut self) -> Result<u16, MutinyError> {
+        Ok(u16::from_be_bytes(self.read_array()?))
+    }
+
+    fn read_u32(&mut self) -> Result<u32, MutinyError> {
+        Ok(u32::from_be_bytes(self.read_array()?))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, MutinyError> {
+        Ok(u64::from_be_bytes(self.read_array()?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bitcoin::hashes::hex::FromHex;
+    use std::str::FromStr;
+
+    /// A backup of the LND wallet with seed `[7; 16]`, with three testnet channels,
+    /// a simple taproot channel, a mainnet channel and a channel with a peer address
+    /// of an unknown type. Written by `test_vectors/lnd_channel_backup.py`, which
+    /// follows LND's serialization as no LND binary was available to export one.
+    fn fixture() -> Vec<u8> {
+        Vec::<u8>::from_hex(include_str!("test_vectors/lnd_channel_backup.hex").trim()).unwrap()
+    }
+
+    fn fixture_key() -> [u8; 32] {
+        let root = ExtendedPrivKey::new_master(Network::Testnet, &[7; 16]).unwrap();
+        lnd_backup_key(&root)
+    }
+
+    #[test]
+    fn test_import_lnd_backup() {
+        let import = import_lnd_backup(&fixture(), &fixture_key(), Network::Testnet).unwrap();
+        assert_eq!(import.channels.len(), 3);
+
+        let first = &import.channels[0];
+        assert_eq!(first.channel_type, LndChannelType::Tweakless);
+        assert!(first.is_initiator);
+        assert_eq!(
+            first.funding_outpoint.txid,
+            Txid::from_slice(&[0x11; 32]).unwrap()
+        );
+        assert_eq!(first.funding_outpoint.vout, 0);
+        assert_eq!(first.short_channel_id, (800_000 << 40) | (12 << 16) | 1);
+        assert_eq!(
+            first.remote_pubkey,
+            PublicKey::from_str(
+                "039d1abaec9f5715a15c7628244170951e0f85e87f68ca5393d3f9fc3fa23a69c8"
+            )
+            .unwrap()
+        );
+        assert_eq!(first.addresses, vec!["1.2.3.4:9735"]);
+        assert_eq!(first.csv_delay, 144);
+        assert_eq!(first.lease_expiry, None);
+
+        let second = &import.channels[1];
+        assert_eq!(second.channel_type, LndChannelType::AnchorsZeroFeeHtlc);
+        assert!(!second.is_initiator);
+        assert_eq!(second.funding_outpoint.vout, 1);
+        assert_eq!(second.csv_delay, 720);
+        assert_eq!(second.addresses.len(), 2);
+        assert_eq!(second.addresses[0], "[2001:db8::1]:9736");
+        assert_eq!(
+            second.addresses[1],
+            format!("{}.onion:9735", base32(&[0xab; 35]))
+        );
+
+        // the lease expiry comes after the csv delay and keys of the peer
+        let third = &import.channels[2];
+        assert_eq!(third.channel_type, LndChannelType::ScriptEnforcedLease);
+        assert!(third.addresses.is_empty());
+        assert_eq!(third.csv_delay, 4032);
+        assert_eq!(third.lease_expiry, Some(800_500));
+
+        // the other channels are reported without failing the import
+        assert_eq!(import.unsupported.len(), 3);
+        assert_eq!(import.unsupported[0].index, 3);
+        assert_eq!(import.unsupported[0].version, 5);
+        assert_eq!(import.unsupported[1].index, 4);
+        assert_eq!(
+            import.unsupported[1].reason,
+            "channel is on a different network"
+        );
+        assert_eq!(import.unsupported[2].index, 5);
+        assert_eq!(import.unsupported[2].reason, "channel could not be read");
+
+        // onion addresses can't be connected to
+        let peer_connections = import.peer_connections();
+        assert_eq!(peer_connections.len(), 3);
+        assert_eq!(
+            peer_connections[&second.remote_pubkey],
+            vec![format!("{}@[2001:db8::1]:9736", second.remote_pubkey)]
+        );
+        assert!(peer_connections[&third.remote_pubkey].is_empty());
+    }
+
+    #[test]
+    fn test_import_lnd_backup_wrong_key() {
+        let root = ExtendedPrivKey::new_master(Network::Testnet, &[8; 16]).unwrap();
+        let res = import_lnd_backup(&fixture(), &lnd_backup_key(&root), Network::Testnet);
+        assert!(matches!(res, Err(MutinyError::BackupDecryptionFailed)));
+
+        // the key depends on the network of the root key
+        let root = ExtendedPrivKey::new_master(Network::Bitcoin, &[7; 16]).unwrap();
+        assert_ne!(lnd_backup_key(&root), fixture_key());
+
+        let mut tampered = fixture();
+        *tampered.last_mut().unwrap() ^= 1;
+        let res = import_lnd_backup(&tampered, &fixture_key(), Network::Testnet);
+        assert!(matches!(res, Err(MutinyError::BackupDecryptionFailed)));
+
+        let res = import_lnd_backup(&fixture()[..10], &fixture_key(), Network::Testnet);
+        assert!(matches!(res, Err(MutinyError::LnDecodeError)));
+    }
+
+    #[test]
+    fn test_onion_base32() {
+        assert_eq!(base32(b""), "");
+        assert_eq!(base32(b"f"), "my");
+        assert_eq!(base32(b"foobar"), "mzxw6ytboi");
+        assert_eq!(base32(&[0xab; 35]).len(), 56);
+    }
+}
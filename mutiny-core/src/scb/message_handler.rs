@@ -1,20 +1,169 @@
+use crate::error::{MutinyError, MutinyStorageError};
+use crate::gossip::get_all_peers;
+use crate::storage::MutinyStorage;
 use crate::utils::Mutex;
+use anyhow::anyhow;
+use bitcoin::hashes::hex::{FromHex, ToHex};
+use bitcoin::hashes::{sha256, Hash};
 use bitcoin::secp256k1::PublicKey;
+use lightning::io::Read;
 use lightning::ln::peer_handler::CustomMessageHandler;
-use lightning::ln::wire::CustomMessageReader;
+use lightning::ln::wire::{CustomMessageReader, Type};
 use lightning::ln::{
     features::InitFeatures,
     msgs::{DecodeError, LightningError},
 };
 use lightning::ln::{features::NodeFeatures, msgs::ChannelReestablish};
-use std::collections::VecDeque;
+use lightning::util::ser::{Readable, Writeable, Writer};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Message type for a peer asking us to store its backup. All the backup
+/// message types are odd, so peers that do not know them ignore them.
+pub const BACKUP_STORE_TYPE: u16 = 41_001;
+
+/// Message type for confirming a backup was stored, with its sha256 digest.
+pub const BACKUP_STORED_TYPE: u16 = 41_003;
+
+/// Message type for a peer asking for the backup we store for it.
+pub const BACKUP_RETRIEVE_TYPE: u16 = 41_005;
+
+/// Message type for the answer to a [`BACKUP_RETRIEVE_TYPE`] message.
+pub const BACKUP_RESPONSE_TYPE: u16 = 41_007;
+
+/// Message type for a peer asking us to delete the backup we store for it.
+/// It is not answered, deleting is best-effort.
+pub const BACKUP_DELETE_TYPE: u16 = 41_009;
+
+/// Max size in bytes of a backup stored with a peer, so it fits in a single
+/// lightning message.
+pub const MAX_PEER_BACKUP_LEN: usize = 65_000;
+
+/// Max number of peers we store backups for.
+pub const MAX_STORED_PEER_BACKUPS: usize = 16;
+
+/// Prefix of the key the backups we store for our peers are persisted under.
+pub(crate) const PEER_BACKUPS_KEY: &str = "peer_backups";
+
+/// Max number of messages queued for a single disconnected peer.
+pub const MAX_QUEUED_MESSAGES_PER_PEER: usize = 16;
+
+/// How long in seconds a message is queued for a disconnected peer before it is dropped.
+pub const QUEUED_MESSAGE_TTL_SECS: u64 = 60 * 60;
+
+/// Which backups are exchanged with a peer, nothing is exchanged unless opted in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerBackupPermissions {
+    /// Our backup is sent to the peer to store
+    pub send_backup_to: bool,
+    /// The peer may store its backup with us
+    pub accept_backup_from: bool,
+}
+
+struct QueuedMessage {
+    msg: ChannelReestablish,
+    expires_at: u64,
+}
+
+/// The messages sent and received by [`SCBMessageHandler`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SCBMessage {
+    /// A bogus channel reestablish, this is a regular lightning message
+    ChannelReestablish(ChannelReestablish),
+    BackupStore(Vec<u8>),
+    BackupStored([u8; 32]),
+    BackupRetrieve,
+    /// The backup stored for the requesting peer, if there is one
+    BackupResponse(Option<Vec<u8>>),
+    BackupDelete,
+}
+
+impl Type for SCBMessage {
+    fn type_id(&self) -> u16 {
+        match self {
+            SCBMessage::ChannelReestablish(msg) => msg.type_id(),
+            SCBMessage::BackupStore(_) => BACKUP_STORE_TYPE,
+            SCBMessage::BackupStored(_) => BACKUP_STORED_TYPE,
+            SCBMessage::BackupRetrieve => BACKUP_RETRIEVE_TYPE,
+            SCBMessage::BackupResponse(_) => BACKUP_RESPONSE_TYPE,
+            SCBMessage::BackupDelete => BACKUP_DELETE_TYPE,
+        }
+    }
+}
+
+impl Writeable for SCBMessage {
+    fn write<W: Writer>(&self, w: &mut W) -> Result<(), lightning::io::Error> {
+        match self {
+            SCBMessage::ChannelReestablish(msg) => msg.write(w),
+            SCBMessage::BackupStore(backup) => write_backup(w, backup),
+            SCBMessage::BackupStored(digest) => digest.write(w),
+            SCBMessage::BackupRetrieve => Ok(()),
+            SCBMessage::BackupResponse(None) => 0u8.write(w),
+            SCBMessage::BackupResponse(Some(backup)) => {
+                1u8.write(w)?;
+                write_backup(w, backup)
+            }
+            SCBMessage::BackupDelete => Ok(()),
+        }
+    }
+}
+
+fn write_backup<W: Writer>(w: &mut W, backup: &[u8]) -> Result<(), lightning::io::Error> {
+    (backup.len() as u16).write(w)?;
+    w.write_all(backup)
+}
+
+fn read_backup<R: Read>(r: &mut R) -> Result<Vec<u8>, DecodeError> {
+    let len: u16 = Readable::read(r)?;
+    let mut backup = vec![0; len as usize];
+    r.read_exact(&mut backup)
+        .map_err(|_| DecodeError::ShortRead)?;
+    Ok(backup)
+}
+
+/// A queued message that was dropped because the peer did not reconnect in time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpiredMessage {
+    pub node_id: PublicKey,
+    pub channel_id: [u8; 32],
+}
 
 /// Custom message handler for Static Channel Backups.
 ///
 /// This will send bogus channel reestablish messages to the peer, which will
 /// trigger the peer to close the channel on our behalf.
+///
+/// Messages for peers that are not connected can be queued with
+/// [`SCBMessageHandler::queue_channel_close`], they are sent in order once
+/// the peer reconnects. Only our own custom messages are queued, LDK handles
+/// retransmission of its protocol messages itself.
+///
+/// It also exchanges encrypted backups with peers. A peer can store one backup
+/// with us and retrieve it later. Backups are stored under the node id of the
+/// connection, which the peer proved to own during the handshake, so a peer can
+/// only ever retrieve its own backup.
+///
+/// Backups are only exchanged with peers that were opted in with
+/// [`SCBMessageHandler::set_backup_permissions`]. The size caps apply on top.
+/// Confirmations and answers from peers are only accepted for the backup we sent
+/// them last and the requests we made, anything else is ignored.
 pub struct SCBMessageHandler {
-    msg_events: Mutex<VecDeque<(PublicKey, ChannelReestablish)>>,
+    msg_events: Mutex<VecDeque<(PublicKey, SCBMessage)>>,
+    queued: Mutex<HashMap<PublicKey, VecDeque<QueuedMessage>>>,
+    /// The backups we store for our peers
+    stored_backups: Mutex<HashMap<PublicKey, Vec<u8>>>,
+    stored_backups_changed: AtomicBool,
+    /// The digest of the last backup each peer confirmed storing for us
+    backup_holders: Mutex<HashMap<PublicKey, [u8; 32]>>,
+    /// The digest of the last backup sent to each peer that it did not confirm yet
+    sent_backups: Mutex<HashMap<PublicKey, [u8; 32]>>,
+    /// The peers we asked for our backup that did not answer yet
+    backup_requests: Mutex<HashSet<PublicKey>>,
+    /// The answers to our backup requests, `None` if the peer had no backup
+    retrieved_backups: Mutex<HashMap<PublicKey, Option<Vec<u8>>>>,
+    /// The peers backups are exchanged with, missing peers are not opted in
+    backup_permissions: Mutex<HashMap<PublicKey, PeerBackupPermissions>>,
 }
 
 impl Default for SCBMessageHandler {
@@ -28,6 +177,14 @@ impl SCBMessageHandler {
     pub fn new() -> Self {
         SCBMessageHandler {
             msg_events: Mutex::new(VecDeque::new()),
+            queued: Mutex::new(HashMap::new()),
+            stored_backups: Mutex::new(HashMap::new()),
+            stored_backups_changed: AtomicBool::new(false),
+            backup_holders: Mutex::new(HashMap::new()),
+            sent_backups: Mutex::new(HashMap::new()),
+            backup_requests: Mutex::new(HashSet::new()),
+            retrieved_backups: Mutex::new(HashMap::new()),
+            backup_permissions: Mutex::new(HashMap::new()),
         }
     }
 
@@ -35,48 +192,384 @@ impl SCBMessageHandler {
     /// sent right away, but only when the LDK
     /// [`lightning::ln::peer_handler::PeerManager::process_events`] is next called.
     pub fn request_channel_close(&self, node_id: PublicKey, channel_id: [u8; 32]) {
-        let mut pk = [2; 33];
-        pk[1] = 0xff;
-        let dummy_pubkey = PublicKey::from_slice(&pk).unwrap();
-        let msg = ChannelReestablish {
-            channel_id,
-            next_local_commitment_number: 0,
-            next_remote_commitment_number: 0,
-            your_last_per_commitment_secret: [0; 32],
-            my_current_per_commitment_point: dummy_pubkey,
-            next_funding_txid: None,
-        };
+        let msg = SCBMessage::ChannelReestablish(channel_close_message(channel_id));
         self.msg_events.lock().unwrap().push_back((node_id, msg));
     }
 
+    /// Queues a channel close request for a peer that is not connected right now.
+    /// It is dropped if the peer does not reconnect within [`QUEUED_MESSAGE_TTL_SECS`],
+    /// and at most [`MAX_QUEUED_MESSAGES_PER_PEER`] messages are queued per peer.
+    pub fn queue_channel_close(
+        &self,
+        node_id: PublicKey,
+        channel_id: [u8; 32],
+        now: u64,
+    ) -> Result<(), MutinyError> {
+        let mut queued = self.queued.lock().unwrap();
+        let queue = queued.entry(node_id).or_default();
+        if queue.len() >= MAX_QUEUED_MESSAGES_PER_PEER {
+            return Err(MutinyError::PeerMessageQueueFull {
+                max: MAX_QUEUED_MESSAGES_PER_PEER,
+            });
+        }
+
+        queue.push_back(QueuedMessage {
+            msg: channel_close_message(channel_id),
+            expires_at: now + QUEUED_MESSAGE_TTL_SECS,
+        });
+        Ok(())
+    }
+
+    /// Drops the queued close request for the given channel, for when the channel
+    /// is closed some other way before the peer reconnects.
+    pub fn cancel_channel_close(&self, node_id: &PublicKey, channel_id: &[u8; 32]) {
+        let mut queued = self.queued.lock().unwrap();
+        if let Some(queue) = queued.get_mut(node_id) {
+            queue.retain(|queued_msg| &queued_msg.msg.channel_id != channel_id);
+            if queue.is_empty() {
+                queued.remove(node_id);
+            }
+        }
+    }
+
+    /// Moves the queued messages of the connected peers to the pending messages,
+    /// in the order they were queued, and drops the expired ones. Returns the
+    /// messages that expired.
+    pub fn flush_queued_messages(
+        &self,
+        connected_peers: &[PublicKey],
+        now: u64,
+    ) -> Vec<ExpiredMessage> {
+        let mut queued = self.queued.lock().unwrap();
+        let mut msg_events = self.msg_events.lock().unwrap();
+        let mut expired = vec![];
+
+        for (node_id, queue) in queued.iter_mut() {
+            let connected = connected_peers.contains(node_id);
+            let mut remaining = VecDeque::new();
+            for queued_msg in queue.drain(..) {
+                if queued_msg.expires_at <= now {
+                    expired.push(ExpiredMessage {
+                        node_id: *node_id,
+                        channel_id: queued_msg.msg.channel_id,
+                    });
+                } else if connected {
+                    let msg = SCBMessage::ChannelReestablish(queued_msg.msg);
+                    msg_events.push_back((*node_id, msg));
+                } else {
+                    remaining.push_back(queued_msg);
+                }
+            }
+            *queue = remaining;
+        }
+        queued.retain(|_, queue| !queue.is_empty());
+
+        expired
+    }
+
     /// Returns whether the message handler has any message to be sent.
     pub fn has_pending_messages(&self) -> bool {
         !self.msg_events.lock().unwrap().is_empty()
     }
+
+    /// Returns the number of messages queued for the given peer.
+    pub fn queued_message_count(&self, node_id: &PublicKey) -> usize {
+        self.queued
+            .lock()
+            .unwrap()
+            .get(node_id)
+            .map_or(0, |queue| queue.len())
+    }
+
+    /// Returns whether there are messages queued for any peer.
+    pub fn has_queued_messages(&self) -> bool {
+        !self.queued.lock().unwrap().is_empty()
+    }
+
+    /// Asks the peer to store the given encrypted backup, replacing the one it
+    /// stored before. Like [`SCBMessageHandler::request_channel_close`], it is
+    /// sent when the peer manager next processes events.
+    /// Fails if our backup is not sent to the peer.
+    pub fn send_backup(&self, node_id: PublicKey, backup: Vec<u8>) -> Result<(), MutinyError> {
+        if !self.backup_permissions(&node_id).send_backup_to {
+            return Err(MutinyError::PeerBackupNotPermitted);
+        }
+        if backup.len() > MAX_PEER_BACKUP_LEN {
+            return Err(MutinyError::PeerBackupTooLarge {
+                size: backup.len(),
+                max: MAX_PEER_BACKUP_LEN,
+            });
+        }
+        let digest = sha256::Hash::hash(&backup).into_inner();
+        self.sent_backups.lock().unwrap().insert(node_id, digest);
+        let msg = SCBMessage::BackupStore(backup);
+        self.msg_events.lock().unwrap().push_back((node_id, msg));
+        Ok(())
+    }
+
+    /// Returns which backups are exchanged with the peer.
+    pub fn backup_permissions(&self, node_id: &PublicKey) -> PeerBackupPermissions {
+        self.backup_permissions
+            .lock()
+            .unwrap()
+            .get(node_id)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Sets which backups are exchanged with the peer. Revoking deletes the
+    /// backup we store for the peer, and asks the peer to delete ours. The peer
+    /// may not be connected or may ignore it, so that is best-effort.
+    pub fn set_backup_permissions(&self, node_id: PublicKey, permissions: PeerBackupPermissions) {
+        let previous = {
+            let mut backup_permissions = self.backup_permissions.lock().unwrap();
+            let previous = backup_permissions.get(&node_id).copied();
+            if permissions == PeerBackupPermissions::default() {
+                backup_permissions.remove(&node_id);
+            } else {
+                backup_permissions.insert(node_id, permissions);
+            }
+            previous.unwrap_or_default()
+        };
+
+        if previous.accept_backup_from
+            && !permissions.accept_backup_from
+            && self
+                .stored_backups
+                .lock()
+                .unwrap()
+                .remove(&node_id)
+                .is_some()
+        {
+            self.stored_backups_changed.store(true, Ordering::Relaxed);
+        }
+
+        if previous.send_backup_to && !permissions.send_backup_to {
+            self.backup_holders.lock().unwrap().remove(&node_id);
+            self.sent_backups.lock().unwrap().remove(&node_id);
+            let msg = SCBMessage::BackupDelete;
+            self.msg_events.lock().unwrap().push_back((node_id, msg));
+        }
+    }
+
+    /// Loads which backups are exchanged with each peer from the peer metadata.
+    pub(crate) fn load_backup_permissions(
+        &self,
+        storage: &impl MutinyStorage,
+    ) -> Result<(), MutinyError> {
+        let mut backup_permissions = self.backup_permissions.lock().unwrap();
+        for (node_id, metadata) in get_all_peers(storage)? {
+            let permissions = metadata.backup_permissions();
+            if let Ok(pubkey) = node_id.as_pubkey() {
+                if permissions != PeerBackupPermissions::default() {
+                    backup_permissions.insert(pubkey, permissions);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns whether the peer confirmed storing the backup with the given digest.
+    pub fn holds_backup(&self, node_id: &PublicKey, digest: &[u8; 32]) -> bool {
+        self.backup_holders.lock().unwrap().get(node_id) == Some(digest)
+    }
+
+    /// Returns the peers that confirmed storing a backup for us.
+    pub fn backup_holders(&self) -> Vec<PublicKey> {
+        self.backup_holders
+            .lock()
+            .unwrap()
+            .keys()
+            .copied()
+            .collect()
+    }
+
+    /// Asks the peer for the backup it stores for us. The answer can be taken
+    /// with [`SCBMessageHandler::take_retrieved_backup`] once it arrives.
+    pub fn request_backup(&self, node_id: PublicKey) {
+        self.retrieved_backups.lock().unwrap().remove(&node_id);
+        self.backup_requests.lock().unwrap().insert(node_id);
+        let msg = SCBMessage::BackupRetrieve;
+        self.msg_events.lock().unwrap().push_back((node_id, msg));
+    }
+
+    /// Takes the answer of the peer to our backup request. Returns `None` while
+    /// there is no answer, and `Some(None)` if the peer does not have a backup.
+    pub fn take_retrieved_backup(&self, node_id: &PublicKey) -> Option<Option<Vec<u8>>> {
+        self.retrieved_backups.lock().unwrap().remove(node_id)
+    }
+
+    /// Returns the backup we store for the given peer.
+    pub fn stored_backup(&self, node_id: &PublicKey) -> Option<Vec<u8>> {
+        self.stored_backups.lock().unwrap().get(node_id).cloned()
+    }
+
+    /// Loads the backups we store for our peers, persisted by
+    /// [`SCBMessageHandler::persist_stored_backups`].
+    pub(crate) fn load_stored_backups(
+        &self,
+        storage: &impl MutinyStorage,
+        our_node_id: &PublicKey,
+    ) -> Result<(), MutinyError> {
+        let stored: Option<HashMap<PublicKey, String>> =
+            storage.get_data(stored_backups_key(our_node_id))?;
+        let mut stored_backups = self.stored_backups.lock().unwrap();
+        for (node_id, backup) in stored.unwrap_or_default() {
+            let backup = Vec::from_hex(&backup).map_err(|_| {
+                MutinyError::read_err(MutinyStorageError::Other(anyhow!(
+                    "Invalid peer backup for {node_id}"
+                )))
+            })?;
+            stored_backups.insert(node_id, backup);
+        }
+        Ok(())
+    }
+
+    /// Persists the backups we store for our peers if they changed.
+    pub(crate) fn persist_stored_backups(
+        &self,
+        storage: &impl MutinyStorage,
+        our_node_id: &PublicKey,
+    ) -> Result<(), MutinyError> {
+        if !self.stored_backups_changed.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        let stored: HashMap<PublicKey, String> = self
+            .stored_backups
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(node_id, backup)| (*node_id, backup.to_hex()))
+            .collect();
+        let res = storage.set_data(stored_backups_key(our_node_id), stored);
+        if res.is_err() {
+            // try again next time
+            self.stored_backups_changed.store(true, Ordering::Relaxed);
+        }
+        res
+    }
+
+    fn handle_backup_store(&self, backup: Vec<u8>, node_id: &PublicKey) {
+        if !self.backup_permissions(node_id).accept_backup_from {
+            return;
+        }
+
+        let mut stored_backups = self.stored_backups.lock().unwrap();
+        if backup.len() > MAX_PEER_BACKUP_LEN
+            || (stored_backups.len() >= MAX_STORED_PEER_BACKUPS
+                && !stored_backups.contains_key(node_id))
+        {
+            // not confirming it, so the peer knows we don't hold it
+            return;
+        }
+
+        let digest = sha256::Hash::hash(&backup).into_inner();
+        stored_backups.insert(*node_id, backup);
+        self.stored_backups_changed.store(true, Ordering::Relaxed);
+
+        let msg = SCBMessage::BackupStored(digest);
+        self.msg_events.lock().unwrap().push_back((*node_id, msg));
+    }
+
+    fn handle_backup_stored(&self, digest: [u8; 32], node_id: &PublicKey) {
+        // a confirmation of an older backup, or of one we never sent, is ignored
+        let mut sent_backups = self.sent_backups.lock().unwrap();
+        if sent_backups.get(node_id) != Some(&digest) {
+            return;
+        }
+        sent_backups.remove(node_id);
+        self.backup_holders.lock().unwrap().insert(*node_id, digest);
+    }
+
+    fn handle_backup_response(&self, backup: Option<Vec<u8>>, node_id: &PublicKey) {
+        if !self.backup_requests.lock().unwrap().remove(node_id) {
+            return;
+        }
+        self.retrieved_backups
+            .lock()
+            .unwrap()
+            .insert(*node_id, backup);
+    }
+
+    fn handle_backup_delete(&self, node_id: &PublicKey) {
+        if self
+            .stored_backups
+            .lock()
+            .unwrap()
+            .remove(node_id)
+            .is_some()
+        {
+            self.stored_backups_changed.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+fn stored_backups_key(our_node_id: &PublicKey) -> String {
+    format!("{PEER_BACKUPS_KEY}_{our_node_id}")
 }
 
-/// Dummy implementation of [`CustomMessageReader`] for [`SCBMessageHandler`].
-/// We are just sending [`ChannelReestablish`] messages, which are not
-/// custom messages, but we need to implement this trait to be able to use
-/// the [`CustomMessageHandler`] trait.
+/// Creates a bogus channel reestablish message, which the peer answers
+/// by force closing the channel.
+fn channel_close_message(channel_id: [u8; 32]) -> ChannelReestablish {
+    let mut pk = [2; 33];
+    pk[1] = 0xff;
+    let dummy_pubkey = PublicKey::from_slice(&pk).unwrap();
+    ChannelReestablish {
+        channel_id,
+        next_local_commitment_number: 0,
+        next_remote_commitment_number: 0,
+        your_last_per_commitment_secret: [0; 32],
+        my_current_per_commitment_point: dummy_pubkey,
+        next_funding_txid: None,
+    }
+}
+
+/// Implementation of [`CustomMessageReader`] for [`SCBMessageHandler`].
+/// The [`ChannelReestablish`] messages we send are not custom messages and
+/// are read by LDK itself, so only the backup messages are read here.
 impl CustomMessageReader for SCBMessageHandler {
-    type CustomMessage = ChannelReestablish;
-    fn read<R: lightning::io::Read>(
+    type CustomMessage = SCBMessage;
+    fn read<R: Read>(
         &self,
-        _msg_type: u16,
-        _buffer: &mut R,
+        msg_type: u16,
+        buffer: &mut R,
     ) -> Result<Option<Self::CustomMessage>, DecodeError> {
-        Ok(None)
+        match msg_type {
+            BACKUP_STORE_TYPE => Ok(Some(SCBMessage::BackupStore(read_backup(buffer)?))),
+            BACKUP_STORED_TYPE => Ok(Some(SCBMessage::BackupStored(Readable::read(buffer)?))),
+            BACKUP_RETRIEVE_TYPE => Ok(Some(SCBMessage::BackupRetrieve)),
+            BACKUP_RESPONSE_TYPE => {
+                let has_backup: u8 = Readable::read(buffer)?;
+                match has_backup {
+                    0 => Ok(Some(SCBMessage::BackupResponse(None))),
+                    1 => Ok(Some(SCBMessage::BackupResponse(Some(read_backup(buffer)?)))),
+                    _ => Err(DecodeError::InvalidValue),
+                }
+            }
+            BACKUP_DELETE_TYPE => Ok(Some(SCBMessage::BackupDelete)),
+            _ => Ok(None),
+        }
     }
 }
 
 impl CustomMessageHandler for SCBMessageHandler {
     fn handle_custom_message(
         &self,
-        _msg: ChannelReestablish,
-        _org: &PublicKey,
+        msg: SCBMessage,
+        org: &PublicKey,
     ) -> Result<(), LightningError> {
-        // We don't need to do anything here, since we are just sending
+        match msg {
+            // We don't need to do anything here, since we are just sending
+            SCBMessage::ChannelReestablish(_) => {}
+            SCBMessage::BackupStore(backup) => self.handle_backup_store(backup, org),
+            SCBMessage::BackupStored(digest) => self.handle_backup_stored(digest, org),
+            SCBMessage::BackupRetrieve => {
+                let msg = SCBMessage::BackupResponse(self.stored_backup(org));
+                self.msg_events.lock().unwrap().push_back((*org, msg));
+            }
+            SCBMessage::BackupResponse(backup) => self.handle_backup_response(backup, org),
+            SCBMessage::BackupDelete => self.handle_backup_delete(org),
+        }
         Ok(())
     }
 
@@ -95,3 +588,466 @@ impl CustomMessageHandler for SCBMessageHandler {
         InitFeatures::empty()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::*;
+    use std::str::FromStr;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn peer() -> PublicKey {
+        PublicKey::from_str("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798")
+            .unwrap()
+    }
+
+    fn other_peer() -> PublicKey {
+        PublicKey::from_str("02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5")
+            .unwrap()
+    }
+
+    #[test]
+    fn test_queued_messages_sent_in_order_on_reconnect() {
+        let test_name = "test_queued_messages_sent_in_order_on_reconnect";
+        log!("{}", test_name);
+
+        let handler = SCBMessageHandler::new();
+        for i in 0..3 {
+            handler.queue_channel_close(peer(), [i; 32], NOW).unwrap();
+        }
+        handler
+            .queue_channel_close(other_peer(), [9; 32], NOW)
+            .unwrap();
+        assert_eq!(handler.queued_message_count(&peer()), 3);
+        assert_eq!(handler.queued_message_count(&other_peer()), 1);
+
+        // still disconnected, nothing is sent
+        assert!(handler.flush_queued_messages(&[], NOW + 1).is_empty());
+        assert!(!handler.has_pending_messages());
+        assert_eq!(handler.queued_message_count(&peer()), 3);
+
+        // peer reconnects, its messages are sent in order
+        assert!(handler.flush_queued_messages(&[peer()], NOW + 2).is_empty());
+        let sent = handler.get_and_clear_pending_msg();
+        let channel_ids: Vec<[u8; 32]> = sent
+            .iter()
+            .map(|(_, m)| match m {
+                SCBMessage::ChannelReestablish(msg) => msg.channel_id,
+                _ => panic!("unexpected message {m:?}"),
+            })
+            .collect();
+        assert!(sent.iter().all(|(pk, _)| *pk == peer()));
+        assert_eq!(channel_ids, vec![[0; 32], [1; 32], [2; 32]]);
+        assert_eq!(handler.queued_message_count(&peer()), 0);
+
+        // the other peer is still queued
+        assert_eq!(handler.queued_message_count(&other_peer()), 1);
+        assert!(handler.has_queued_messages());
+    }
+
+    #[test]
+    fn test_queued_messages_expire() {
+        let test_name = "test_queued_messages_expire";
+        log!("{}", test_name);
+
+        let handler = SCBMessageHandler::new();
+        handler.queue_channel_close(peer(), [1; 32], NOW).unwrap();
+        handler
+            .queue_channel_close(peer(), [2; 32], NOW + 10)
+            .unwrap();
+
+        let expired = handler.flush_queued_messages(&[], NOW + QUEUED_MESSAGE_TTL_SECS);
+        assert_eq!(
+            expired,
+            vec![ExpiredMessage {
+                node_id: peer(),
+                channel_id: [1; 32],
+            }]
+        );
+        assert_eq!(handler.queued_message_count(&peer()), 1);
+
+        // expired messages are not sent when the peer reconnects
+        let expired = handler.flush_queued_messages(&[peer()], NOW + QUEUED_MESSAGE_TTL_SECS + 10);
+        assert_eq!(expired.len(), 1);
+        assert!(!handler.has_pending_messages());
+        assert!(!handler.has_queued_messages());
+    }
+
+    #[test]
+    fn test_cancel_queued_channel_close() {
+        let test_name = "test_cancel_queued_channel_close";
+        log!("{}", test_name);
+
+        let handler = SCBMessageHandler::new();
+        handler.queue_channel_close(peer(), [1; 32], NOW).unwrap();
+        handler.queue_channel_close(peer(), [2; 32], NOW).unwrap();
+
+        // unknown channels and peers are ignored
+        handler.cancel_channel_close(&peer(), &[3; 32]);
+        handler.cancel_channel_close(&other_peer(), &[1; 32]);
+        assert_eq!(handler.queued_message_count(&peer()), 2);
+
+        handler.cancel_channel_close(&peer(), &[1; 32]);
+        assert_eq!(handler.queued_message_count(&peer()), 1);
+        handler.flush_queued_messages(&[peer()], NOW + 1);
+        let sent = handler.get_and_clear_pending_msg();
+        assert!(matches!(
+            &sent[..],
+            [(_, SCBMessage::ChannelReestablish(msg))] if msg.channel_id == [2; 32]
+        ));
+
+        handler.queue_channel_close(peer(), [4; 32], NOW).unwrap();
+        handler.cancel_channel_close(&peer(), &[4; 32]);
+        assert!(!handler.has_queued_messages());
+    }
+
+    #[test]
+    fn test_queued_messages_bound() {
+        let test_name = "test_queued_messages_bound";
+        log!("{}", test_name);
+
+        let handler = SCBMessageHandler::new();
+        for i in 0..MAX_QUEUED_MESSAGES_PER_PEER {
+            handler
+                .queue_channel_close(peer(), [i as u8; 32], NOW)
+                .unwrap();
+        }
+        assert!(matches!(
+            handler.queue_channel_close(peer(), [0xff; 32], NOW),
+            Err(MutinyError::PeerMessageQueueFull { .. })
+        ));
+        assert_eq!(
+            handler.queued_message_count(&peer()),
+            MAX_QUEUED_MESSAGES_PER_PEER
+        );
+
+        // the bound is per peer
+        handler
+            .queue_channel_close(other_peer(), [0; 32], NOW)
+            .unwrap();
+
+        // once flushed there is room again
+        handler.flush_queued_messages(&[peer()], NOW);
+        handler
+            .queue_channel_close(peer(), [0xff; 32], NOW)
+            .unwrap();
+    }
+    /// Delivers the pending messages of one node to the other, going through
+    /// the wire encoding like the peer manager does.
+    fn deliver(
+        from: &SCBMessageHandler,
+        from_id: PublicKey,
+        to: &SCBMessageHandler,
+        to_id: PublicKey,
+    ) -> usize {
+        let msgs = from.get_and_clear_pending_msg();
+        for (node_id, msg) in msgs.iter() {
+            assert_eq!(*node_id, to_id);
+            let bytes = msg.encode();
+            let read = to
+                .read(msg.type_id(), &mut lightning::io::Cursor::new(bytes))
+                .unwrap()
+                .unwrap();
+            assert_eq!(&read, msg);
+            to.handle_custom_message(read, &from_id).unwrap();
+        }
+        msgs.len()
+    }
+
+    const SEND: PeerBackupPermissions = PeerBackupPermissions {
+        send_backup_to: true,
+        accept_backup_from: false,
+    };
+
+    const ACCEPT: PeerBackupPermissions = PeerBackupPermissions {
+        send_backup_to: false,
+        accept_backup_from: true,
+    };
+
+    #[test]
+    fn test_peer_backup_store_and_retrieve() {
+        let test_name = "test_peer_backup_store_and_retrieve";
+        log!("{}", test_name);
+
+        let node = SCBMessageHandler::new();
+        node.set_backup_permissions(other_peer(), SEND);
+        let holder = SCBMessageHandler::new();
+        holder.set_backup_permissions(peer(), ACCEPT);
+        let backup = vec![7u8; 1_000];
+        let digest = sha256::Hash::hash(&backup).into_inner();
+
+        // store the backup and get it confirmed
+        node.send_backup(other_peer(), backup.clone()).unwrap();
+        assert_eq!(deliver(&node, peer(), &holder, other_peer()), 1);
+        assert_eq!(holder.stored_backup(&peer()), Some(backup.clone()));
+        assert!(!node.holds_backup(&other_peer(), &digest));
+        assert_eq!(deliver(&holder, other_peer(), &node, peer()), 1);
+        assert!(node.holds_backup(&other_peer(), &digest));
+        assert_eq!(node.backup_holders(), vec![other_peer()]);
+
+        // a newer backup replaces the old one
+        let newer = vec![8u8; 2_000];
+        node.send_backup(other_peer(), newer.clone()).unwrap();
+        deliver(&node, peer(), &holder, other_peer());
+        deliver(&holder, other_peer(), &node, peer());
+        assert_eq!(holder.stored_backup(&peer()), Some(newer.clone()));
+        assert!(!node.holds_backup(&other_peer(), &digest));
+
+        // a recovering node gets its backup back
+        let recovering = SCBMessageHandler::new();
+        recovering.request_backup(other_peer());
+        assert!(recovering.take_retrieved_backup(&other_peer()).is_none());
+        deliver(&recovering, peer(), &holder, other_peer());
+        deliver(&holder, other_peer(), &recovering, peer());
+        assert_eq!(
+            recovering.take_retrieved_backup(&other_peer()),
+            Some(Some(newer))
+        );
+
+        // the holder only hands out the backup of the requesting node
+        holder.request_backup(peer());
+        deliver(&holder, other_peer(), &node, peer());
+        deliver(&node, peer(), &holder, other_peer());
+        assert_eq!(holder.take_retrieved_backup(&peer()), Some(None));
+    }
+
+    #[test]
+    fn test_unsolicited_backup_messages_ignored() {
+        let test_name = "test_unsolicited_backup_messages_ignored";
+        log!("{}", test_name);
+
+        let node = SCBMessageHandler::new();
+        node.set_backup_permissions(other_peer(), SEND);
+        let backup = vec![7u8; 100];
+        let digest = sha256::Hash::hash(&backup).into_inner();
+        let stored = |digest: [u8; 32], from: PublicKey| {
+            node.handle_custom_message(SCBMessage::BackupStored(digest), &from)
+                .unwrap();
+        };
+
+        // a confirmation for a backup we never sent
+        stored(digest, other_peer());
+        assert!(node.backup_holders().is_empty());
+
+        // or for another backup than the one we sent, or from another peer
+        node.send_backup(other_peer(), backup).unwrap();
+        stored([1; 32], other_peer());
+        stored(digest, peer());
+        assert!(node.backup_holders().is_empty());
+        stored(digest, other_peer());
+        assert!(node.holds_backup(&other_peer(), &digest));
+
+        // a confirmation of an older backup does not replace the newer one
+        let newer = vec![8u8; 100];
+        let newer_digest = sha256::Hash::hash(&newer).into_inner();
+        node.send_backup(other_peer(), newer).unwrap();
+        stored(newer_digest, other_peer());
+        stored(digest, other_peer());
+        assert!(node.holds_backup(&other_peer(), &newer_digest));
+
+        // nor is a confirmation after revoking
+        node.set_backup_permissions(other_peer(), SEND);
+        node.send_backup(other_peer(), vec![9u8; 100]).unwrap();
+        node.set_backup_permissions(other_peer(), PeerBackupPermissions::default());
+        stored(sha256::Hash::hash(&[9u8; 100]).into_inner(), other_peer());
+        assert!(node.backup_holders().is_empty());
+
+        // an answer we did not ask for
+        let response = |backup: Option<Vec<u8>>| {
+            node.handle_custom_message(SCBMessage::BackupResponse(backup), &other_peer())
+                .unwrap();
+        };
+        response(Some(vec![1; 10]));
+        assert_eq!(node.take_retrieved_backup(&other_peer()), None);
+
+        // only the first answer to a request is taken
+        node.request_backup(other_peer());
+        response(Some(vec![2; 10]));
+        response(Some(vec![3; 10]));
+        assert_eq!(
+            node.take_retrieved_backup(&other_peer()),
+            Some(Some(vec![2; 10]))
+        );
+        assert_eq!(node.take_retrieved_backup(&other_peer()), None);
+    }
+
+    #[test]
+    fn test_peer_backup_limits() {
+        let test_name = "test_peer_backup_limits";
+        log!("{}", test_name);
+
+        let node = SCBMessageHandler::new();
+        node.set_backup_permissions(other_peer(), SEND);
+        assert!(matches!(
+            node.send_backup(other_peer(), vec![0; MAX_PEER_BACKUP_LEN + 1]),
+            Err(MutinyError::PeerBackupTooLarge { .. })
+        ));
+        assert!(!node.has_pending_messages());
+
+        // backups from too many peers are not stored nor confirmed
+        let holder = SCBMessageHandler::new();
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        for i in 0..=MAX_STORED_PEER_BACKUPS {
+            let key = bitcoin::secp256k1::SecretKey::from_slice(&[i as u8 + 1; 32]).unwrap();
+            let node_id = PublicKey::from_secret_key(&secp, &key);
+            holder.set_backup_permissions(node_id, ACCEPT);
+            holder
+                .handle_custom_message(SCBMessage::BackupStore(vec![i as u8]), &node_id)
+                .unwrap();
+        }
+        assert_eq!(
+            holder.get_and_clear_pending_msg().len(),
+            MAX_STORED_PEER_BACKUPS
+        );
+    }
+
+    #[test]
+    fn test_peer_backups_persisted() {
+        let test_name = "test_peer_backups_persisted";
+        log!("{}", test_name);
+
+        let storage = crate::storage::MemoryStorage::default();
+        let holder = SCBMessageHandler::new();
+        holder.set_backup_permissions(peer(), ACCEPT);
+        holder
+            .handle_custom_message(SCBMessage::BackupStore(vec![1, 2, 3]), &peer())
+            .unwrap();
+        holder
+            .persist_stored_backups(&storage, &other_peer())
+            .unwrap();
+
+        let restarted = SCBMessageHandler::new();
+        restarted
+            .load_stored_backups(&storage, &other_peer())
+            .unwrap();
+        assert_eq!(restarted.stored_backup(&peer()), Some(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn test_peer_backup_permissions_enforced() {
+        let test_name = "test_peer_backup_permissions_enforced";
+        log!("{}", test_name);
+
+        let node = SCBMessageHandler::new();
+        let holder = SCBMessageHandler::new();
+
+        // nothing is sent to a peer that was not opted in
+        assert!(matches!(
+            node.send_backup(other_peer(), vec![1; 100]),
+            Err(MutinyError::PeerBackupNotPermitted)
+        ));
+        assert!(!node.has_pending_messages());
+
+        // accepting backups does not mean sending ours
+        node.set_backup_permissions(other_peer(), ACCEPT);
+        assert!(node.send_backup(other_peer(), vec![1; 100]).is_err());
+
+        // nothing is stored for a peer that was not opted in
+        node.set_backup_permissions(other_peer(), SEND);
+        node.send_backup(other_peer(), vec![1; 100]).unwrap();
+        assert_eq!(deliver(&node, peer(), &holder, other_peer()), 1);
+        assert_eq!(holder.stored_backup(&peer()), None);
+        assert!(!holder.has_pending_messages());
+
+        // sending ours does not mean accepting theirs
+        holder.set_backup_permissions(peer(), SEND);
+        node.send_backup(other_peer(), vec![1; 100]).unwrap();
+        deliver(&node, peer(), &holder, other_peer());
+        assert_eq!(holder.stored_backup(&peer()), None);
+
+        holder.set_backup_permissions(
+            peer(),
+            PeerBackupPermissions {
+                send_backup_to: true,
+                accept_backup_from: true,
+            },
+        );
+        node.send_backup(other_peer(), vec![1; 100]).unwrap();
+        deliver(&node, peer(), &holder, other_peer());
+        assert_eq!(holder.stored_backup(&peer()), Some(vec![1; 100]));
+        assert_eq!(deliver(&holder, other_peer(), &node, peer()), 1);
+        assert_eq!(node.backup_holders(), vec![other_peer()]);
+
+        // the size cap still applies to opted in peers
+        holder
+            .handle_custom_message(
+                SCBMessage::BackupStore(vec![0; MAX_PEER_BACKUP_LEN + 1]),
+                &peer(),
+            )
+            .unwrap();
+        assert_eq!(holder.stored_backup(&peer()), Some(vec![1; 100]));
+        assert!(!holder.has_pending_messages());
+    }
+
+    #[test]
+    fn test_peer_backup_revoke() {
+        let test_name = "test_peer_backup_revoke";
+        log!("{}", test_name);
+
+        let storage = crate::storage::MemoryStorage::default();
+        let node = SCBMessageHandler::new();
+        node.set_backup_permissions(other_peer(), SEND);
+        let holder = SCBMessageHandler::new();
+        holder.set_backup_permissions(peer(), ACCEPT);
+
+        node.send_backup(other_peer(), vec![2; 100]).unwrap();
+        deliver(&node, peer(), &holder, other_peer());
+        deliver(&holder, other_peer(), &node, peer());
+        holder
+            .persist_stored_backups(&storage, &other_peer())
+            .unwrap();
+
+        // revoking on our side asks the holder to delete our backup
+        node.set_backup_permissions(other_peer(), PeerBackupPermissions::default());
+        assert!(node.backup_holders().is_empty());
+        assert_eq!(deliver(&node, peer(), &holder, other_peer()), 1);
+        assert_eq!(holder.stored_backup(&peer()), None);
+        assert!(!holder.has_pending_messages());
+
+        // revoking on the holder side deletes the backup it stores
+        node.set_backup_permissions(other_peer(), SEND);
+        node.send_backup(other_peer(), vec![3; 100]).unwrap();
+        deliver(&node, peer(), &holder, other_peer());
+        assert_eq!(holder.stored_backup(&peer()), Some(vec![3; 100]));
+        holder.set_backup_permissions(peer(), PeerBackupPermissions::default());
+        assert_eq!(holder.stored_backup(&peer()), None);
+        assert_eq!(holder.backup_permissions(&peer()), Default::default());
+
+        // and the deletion is persisted
+        holder
+            .persist_stored_backups(&storage, &other_peer())
+            .unwrap();
+        let restarted = SCBMessageHandler::new();
+        restarted
+            .load_stored_backups(&storage, &other_peer())
+            .unwrap();
+        assert_eq!(restarted.stored_backup(&peer()), None);
+    }
+
+    #[test]
+    fn test_peer_backup_permissions_loaded() {
+        let test_name = "test_peer_backup_permissions_loaded";
+        log!("{}", test_name);
+
+        let storage = crate::storage::MemoryStorage::default();
+        crate::gossip::set_peer_backup_permissions(
+            &storage,
+            &lightning::routing::gossip::NodeId::from_pubkey(&peer()),
+            ACCEPT,
+        )
+        .unwrap();
+
+        let restarted = SCBMessageHandler::new();
+        restarted.load_backup_permissions(&storage).unwrap();
+        assert_eq!(restarted.backup_permissions(&peer()), ACCEPT);
+        assert_eq!(
+            restarted.backup_permissions(&other_peer()),
+            PeerBackupPermissions::default()
+        );
+    }
+}
@@ -1,31 +1,98 @@
+mod checksum;
+mod diff;
+mod json;
+pub mod lnd;
 pub mod message_handler;
+pub mod nostr_backup;
+pub mod remote_backup;
+pub mod scb_format;
 
+use crate::capabilities::Capability;
 use crate::error::MutinyError;
+use crate::node::split_peer_connection_string;
 use crate::nodemanager::NodeIndex;
+use crate::recovery::channel_recoveries;
+pub use crate::scb::diff::ScbDiff;
+use crate::scb::json::{StaticChannelBackupJson, StaticChannelBackupStorageJson};
+use crate::scb::scb_format::*;
 use aes::cipher::block_padding::Pkcs7;
 use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
 use aes::Aes256;
+use argon2::{Algorithm, Argon2, Params, Version};
 use bitcoin::bech32::{FromBase32, ToBase32, Variant};
-use bitcoin::hashes::Hash;
+use bitcoin::hashes::hex::ToHex;
+use bitcoin::hashes::hmac::{Hmac, HmacEngine};
+use bitcoin::hashes::{sha256, Hash, HashEngine};
 use bitcoin::secp256k1::{PublicKey, SecretKey};
-use bitcoin::{bech32, secp256k1, OutPoint};
+use bitcoin::util::bip32::{ChildNumber, DerivationPath};
+use bitcoin::{bech32, secp256k1, Network, OutPoint};
 use cbc::{Decryptor, Encryptor};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use lightning::chain::channelmonitor::Balance;
 use lightning::io::{Cursor, Read};
 use lightning::ln::msgs::DecodeError;
 use lightning::util::ser::{Readable, Writeable, Writer};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt::Formatter;
 use std::str::FromStr;
+use thiserror::Error;
 
 type Aes256CbcEnc = Encryptor<Aes256>;
 type Aes256CbcDec = Decryptor<Aes256>;
 
 pub const SCB_ENCRYPTION_KEY_DERIVATION_PATH: &str = "m/444'/444'/444'";
 
+/// The derivation path of the seed key backups are encrypted with. Without an
+/// account this is [`SCB_ENCRYPTION_KEY_DERIVATION_PATH`], otherwise the account
+/// is appended as a hardened child, so wallets sharing a seed get their own keys.
+pub fn scb_key_derivation_path(account: Option<u32>) -> Result<DerivationPath, MutinyError> {
+    let path = DerivationPath::from_str(SCB_ENCRYPTION_KEY_DERIVATION_PATH)
+        .expect("SCB key derivation path is valid");
+    match account {
+        None => Ok(path),
+        Some(account) => {
+            let child = ChildNumber::from_hardened_idx(account)
+                .map_err(|_| MutinyError::InvalidArgumentsError)?;
+            Ok(path.child(child))
+        }
+    }
+}
+
+/// The latest backup format version that is created.
+pub(crate) const SCB_FORMAT_CAPABILITY: Capability =
+    Capability::version("scb_format", SCB_VERSION_TLV_NODE_INDEX as u32);
+
+pub(crate) const SCB_FEATURES_CAPABILITY: Capability = Capability::list(
+    "scb_features",
+    &[
+        "passphrase",
+        "integrity_tag",
+        "network",
+        "multi_part",
+        "json",
+        "peer_backup",
+        "auto_backup",
+        "nostr_backup",
+        "diff",
+        "labels",
+        "padding",
+        "restore_progress",
+        "armored",
+        "key_account",
+        "remote_backup",
+        "sweep",
+        "last_connected",
+        "lnd_import",
+        "fingerprint",
+    ],
+);
+
 /// A static channel backup is a backup for the channels for a given node.
 /// These are backups of the channel monitors, which store the necessary
 /// information to recover the channel in case of a failure.
-#[derive(Default, PartialEq, Eq, Clone)]
+#[derive(Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(into = "StaticChannelBackupJson", try_from = "StaticChannelBackupJson")]
 pub struct StaticChannelBackup {
     /// Map of the channel outpoint to the channel monitor
     /// This is a Vec<u8> because we can't implement Readable for ChannelMonitor
@@ -33,14 +100,93 @@ pub struct StaticChannelBackup {
     pub(crate) monitors: HashMap<OutPoint, Vec<u8>>,
 }
 
+impl StaticChannelBackup {
+    /// Returns the update id of the monitor for the given channel, if the
+    /// backup has it and the id can be read.
+    pub fn update_id_for(&self, outpoint: &OutPoint) -> Option<u64> {
+        self.monitors
+            .get(outpoint)
+            .and_then(|monitor| monitor_update_id(monitor))
+    }
+
+    /// Inserts the monitor unless the backup already has a newer one for the channel.
+    /// Returns whether the monitor was inserted.
+    pub(crate) fn insert_monitor(&mut self, outpoint: OutPoint, monitor: Vec<u8>) -> bool {
+        // monitors whose update id can't be read lose against any that can
+        let is_newer = self.monitors.get(&outpoint).map_or(true, |current| {
+            monitor_update_id(&monitor) > monitor_update_id(current)
+        });
+        if is_newer {
+            self.monitors.insert(outpoint, monitor);
+        }
+        is_newer
+    }
+
+    /// Adds the monitors of the other backup, keeping the newest monitor of each channel.
+    pub fn merge(&mut self, other: StaticChannelBackup) {
+        for (outpoint, monitor) in other.monitors {
+            self.insert_monitor(outpoint, monitor);
+        }
+    }
+
+    /// Removes the channels this wallet has a newer monitor for, given the update ids
+    /// of its monitors, so restoring does not replace them with an older state.
+    /// With `force` every channel is kept. Returns the removed channels.
+    pub(crate) fn remove_outdated(
+        &mut self,
+        stored: &HashMap<OutPoint, u64>,
+        force: bool,
+    ) -> Vec<ScbSkippedChannel> {
+        if force {
+            return vec![];
+        }
+
+        let mut skipped = vec![];
+        self.monitors.retain(|funding_txo, monitor| {
+            let Some(stored_update_id) = stored.get(funding_txo).copied() else {
+                return true;
+            };
+            // like in insert_monitor, a monitor whose update id can't be read is older
+            let backup_update_id = monitor_update_id(monitor);
+            if backup_update_id.map_or(false, |id| id >= stored_update_id) {
+                return true;
+            }
+            skipped.push(ScbSkippedChannel {
+                funding_txo: *funding_txo,
+                stored_update_id,
+                backup_update_id,
+            });
+            false
+        });
+        skipped.sort_unstable_by_key(|s| s.funding_txo);
+        skipped
+    }
+
+    pub fn monitor_count(&self) -> usize {
+        self.monitors.len()
+    }
+
+    /// The length of the largest serialized monitor, zero without monitors.
+    pub fn largest_monitor_len(&self) -> usize {
+        self.monitors.values().map(|m| m.len()).max().unwrap_or(0)
+    }
+}
+
+/// Reads the `latest_update_id` of a serialized channel monitor. It directly follows
+/// the version prefix in LDK's encoding, so this does not need a `KeysManager`.
+pub(crate) fn monitor_update_id(monitor: &[u8]) -> Option<u64> {
+    let bytes = monitor.get(MONITOR_VERSION_PREFIX_LEN..MONITOR_VERSION_PREFIX_LEN + 8)?;
+    Some(u64::from_be_bytes(bytes.try_into().expect("8 bytes")))
+}
+
 impl Writeable for StaticChannelBackup {
     fn write<W: Writer>(&self, writer: &mut W) -> Result<(), lightning::io::Error> {
-        let len: u32 = self.monitors.len() as u32;
+        let len = self.monitors.len() as LengthPrefix;
         writer.write_all(&len.to_be_bytes())?;
-        for (outpoint, monitor) in self.monitors.iter() {
+        for (outpoint, monitor) in sorted(&self.monitors) {
             writer.write_all(&outpoint.txid[..])?;
             writer.write_all(&outpoint.vout.to_be_bytes())?;
-            let mon_len: u32 = monitor.len() as u32;
+            let mon_len = monitor.len() as LengthPrefix;
             writer.write_all(&mon_len.to_be_bytes())?;
             writer.write_all(monitor)?;
         }
@@ -50,23 +196,251 @@ impl Writeable for StaticChannelBackup {
 
 impl Readable for StaticChannelBackup {
     fn read<R: Read>(reader: &mut R) -> Result<Self, DecodeError> {
-        let len: u32 = Readable::read(reader)?;
-        let mut monitors = HashMap::new();
+        let len = read_count(reader)?;
+        let mut backup = StaticChannelBackup::default();
         for _ in 0..len {
-            let mut txid = [0u8; 32];
+            let mut txid = [0u8; TXID_LEN];
             reader.read_exact(&mut txid)?;
             let vout: u32 = Readable::read(reader)?;
             let outpoint = OutPoint {
-                txid: bitcoin::Txid::from_slice(&txid).expect("txid is 32 bytes"),
+                txid: bitcoin::Txid::from_slice(&txid).map_err(|_| DecodeError::InvalidValue)?,
                 vout,
             };
-            let mon_len: u32 = Readable::read(reader)?;
-            let mut monitor = vec![0u8; mon_len as usize];
-            reader.read_exact(&mut monitor)?;
-            monitors.insert(outpoint, monitor);
+            let monitor = read_length_prefixed(reader, MAX_MONITOR_LEN)?;
+            // a channel that is in the backup twice keeps its newest monitor
+            backup.insert_monitor(outpoint, monitor);
+        }
+
+        Ok(backup)
+    }
+}
+
+/// The sha256 of each monitor in a [`StaticChannelBackup`], enough to tell which
+/// channels changed since that backup without keeping the monitors around.
+#[derive(Default, PartialEq, Eq, Clone, Debug)]
+pub struct StaticChannelBackupHashes {
+    pub(crate) monitors: HashMap<OutPoint, sha256::Hash>,
+}
+
+impl From<&StaticChannelBackup> for StaticChannelBackupHashes {
+    fn from(backup: &StaticChannelBackup) -> Self {
+        let monitors = backup
+            .monitors
+            .iter()
+            .map(|(outpoint, monitor)| (*outpoint, sha256::Hash::hash(monitor)))
+            .collect();
+        Self { monitors }
+    }
+}
+
+/// Writes a length prefixed byte vector.
+fn write_length_prefixed<W: Writer>(
+    writer: &mut W,
+    bytes: &[u8],
+) -> Result<(), lightning::io::Error> {
+    let len = bytes.len() as LengthPrefix;
+    writer.write_all(&len.to_be_bytes())?;
+    writer.write_all(bytes)
+}
+
+/// Reads a length prefixed byte vector of at most `max` bytes. The claimed length is
+/// not trusted for the allocation, so a corrupt length fails with a short read instead
+/// of aborting.
+fn read_length_prefixed<R: Read>(
+    reader: &mut R,
+    max: LengthPrefix,
+) -> Result<Vec<u8>, DecodeError> {
+    let len: LengthPrefix = Readable::read(reader)?;
+    if len > max {
+        return Err(DecodeError::InvalidValue);
+    }
+    read_bytes(reader, len)
+}
+
+/// Reads a length prefixed utf8 string of at most [`MAX_STRING_LEN`] bytes.
+fn read_string<R: Read>(reader: &mut R) -> Result<String, DecodeError> {
+    let string = read_length_prefixed(reader, MAX_STRING_LEN)?;
+    String::from_utf8(string).map_err(|_| DecodeError::InvalidValue)
+}
+
+/// Reads a connection string of the given peer, rejecting malformed ones so a
+/// corrupt backup fails here instead of when connecting to the peer.
+fn read_connection_string<R: Read>(
+    reader: &mut R,
+    pubkey: &PublicKey,
+) -> Result<String, DecodeError> {
+    let connection = read_string(reader)?;
+    validate_connection_string(pubkey, &connection).map_err(|_| DecodeError::InvalidValue)?;
+    Ok(connection)
+}
+
+/// Reads a count of at most [`MAX_ENTRIES`].
+fn read_count<R: Read>(reader: &mut R) -> Result<LengthPrefix, DecodeError> {
+    check_count(Readable::read(reader)?)
+}
+
+fn check_count(count: LengthPrefix) -> Result<LengthPrefix, DecodeError> {
+    if count > MAX_ENTRIES {
+        return Err(DecodeError::InvalidValue);
+    }
+    Ok(count)
+}
+
+/// Reads `len` bytes without trusting the claimed length for the allocation.
+fn read_bytes<R: Read>(reader: &mut R, len: LengthPrefix) -> Result<Vec<u8>, DecodeError> {
+    let mut bytes = Vec::new();
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len as usize {
+        return Err(DecodeError::ShortRead);
+    }
+    Ok(bytes)
+}
+
+/// Storage key for the generation of the latest backup created or restored
+pub(crate) const SCB_LATEST_GENERATION_KEY: &str = "scb_latest_generation";
+
+/// Storage key for the peers our backup is stored with
+pub(crate) const SCB_BACKUP_PEERS_KEY: &str = "scb_backup_peers";
+
+/// How long in seconds to wait for a peer to return our backup
+pub(crate) const PEER_BACKUP_TIMEOUT_SECS: u64 = 30;
+
+/// Storage key for the latest automatic backup
+pub(crate) const SCB_AUTO_BACKUP_KEY: &str = "scb_auto_backup";
+
+/// Default minimum time in seconds between two automatic backups.
+pub const DEFAULT_AUTO_BACKUP_INTERVAL_SECS: u64 = 60;
+
+/// Sizes in bytes backups are padded to by [`ScbPrivacyConfig::enabled`].
+pub const DEFAULT_SCB_PADDING_BUCKETS: [u32; 4] = [16 * 1024, 64 * 1024, 256 * 1024, 1024 * 1024];
+
+/// Longest random delay in seconds of an automatic backup by [`ScbPrivacyConfig::enabled`].
+pub const DEFAULT_SCB_JITTER_WINDOW_SECS: u64 = 120;
+
+/// The longest backup string a single QR code holds. Bech32 strings are encoded in
+/// the QR alphanumeric mode when uppercase, which holds this many characters at
+/// the lowest error correction level. Longer backups need an animated QR or a file.
+pub const SCB_SINGLE_QR_MAX_LEN: usize = 4296;
+
+/// The length of the bech32m string of `len` encoded bytes: the hrp, the separator,
+/// five bits per character and the checksum.
+fn bech32_len(len: usize) -> usize {
+    SCB_HRP.len() + 1 + (len * 8 + 4) / 5 + 6
+}
+
+/// Hides how many channels there are and when they change from the peers and
+/// relays the automatic backups are sent to. Everything is off by default.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScbPrivacyConfig {
+    /// Backups are padded to the smallest of these sizes in bytes they fit in,
+    /// or to a multiple of the largest one. Empty disables the padding.
+    pub padding_buckets: Vec<u32>,
+    /// Automatic backups wait a random time of up to this many seconds after the
+    /// first change, and include every change made while waiting.
+    pub jitter_window_secs: u64,
+}
+
+impl ScbPrivacyConfig {
+    /// Pads to [`DEFAULT_SCB_PADDING_BUCKETS`] and delays by up to [`DEFAULT_SCB_JITTER_WINDOW_SECS`]
+    pub fn enabled() -> Self {
+        Self {
+            padding_buckets: DEFAULT_SCB_PADDING_BUCKETS.to_vec(),
+            jitter_window_secs: DEFAULT_SCB_JITTER_WINDOW_SECS,
+        }
+    }
+}
+
+/// The size a backup of `len` bytes is padded to, the smallest bucket it
+/// fits in or a multiple of the largest bucket. None if there are no buckets.
+fn padded_len(len: usize, buckets: &[u32]) -> Option<usize> {
+    let mut buckets: Vec<usize> = buckets
+        .iter()
+        .filter(|b| **b > 0)
+        .map(|b| *b as usize)
+        .collect();
+    buckets.sort_unstable();
+    let largest = *buckets.last()?;
+    Some(
+        buckets
+            .into_iter()
+            .find(|b| *b >= len)
+            .unwrap_or_else(|| (len + largest - 1) / largest * largest),
+    )
+}
+
+/// The latest backup created automatically after a channel monitor changed.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct AutoBackup {
+    /// The bech32 encoded [`EncryptedSCB`]
+    pub backup: String,
+    /// Epoch time in seconds
+    pub created_at: u64,
+}
+
+/// Decides when to create an automatic backup. Changes are collected and backed
+/// up at most once per interval, so bursts of monitor updates only produce one backup.
+/// With a jitter window the backup also waits a random delay after the first change,
+/// so its timing does not reveal when the channel changed.
+#[derive(Debug, Clone)]
+pub(crate) struct AutoBackupDebouncer {
+    min_interval_secs: u64,
+    jitter_window_secs: u64,
+    last_backup_at: Option<u64>,
+    pending: bool,
+    /// Earliest time the pending changes are backed up, after the random delay
+    ready_at: u64,
+}
+
+impl AutoBackupDebouncer {
+    pub fn new(
+        min_interval_secs: u64,
+        jitter_window_secs: u64,
+        last_backup_at: Option<u64>,
+    ) -> Self {
+        Self {
+            min_interval_secs,
+            jitter_window_secs,
+            last_backup_at,
+            pending: false,
+            ready_at: 0,
+        }
+    }
+
+    /// Records that a channel monitor changed since the last backup. Only the
+    /// first change picks a delay, later ones wait for the same backup.
+    pub fn mark_changed(&mut self, now: u64) {
+        if !self.pending {
+            self.pending = true;
+            self.ready_at = now + secp256k1::rand::random::<u64>() % (self.jitter_window_secs + 1);
         }
+    }
+
+    pub fn should_backup(&self, now: u64) -> bool {
+        self.pending
+            && now >= self.ready_at
+            && self
+                .last_backup_at
+                .map_or(true, |last| now >= last + self.min_interval_secs)
+    }
+
+    pub fn backed_up(&mut self, now: u64) {
+        self.pending = false;
+        self.last_backup_at = Some(now);
+    }
+}
 
-        Ok(Self { monitors })
+/// When a backup was created and its generation, used to pick the latest backup.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ScbGeneration {
+    pub created_at: u64,
+    pub generation: u64,
+}
+
+impl ScbGeneration {
+    /// Compares by generation first, the creation time only breaks ties
+    /// since clocks on different devices may disagree.
+    pub fn is_newer_than(&self, other: &Self) -> bool {
+        (self.generation, self.created_at) > (other.generation, other.created_at)
     }
 }
 
@@ -75,84 +449,802 @@ impl Readable for StaticChannelBackup {
 ///
 /// This also has the NodeStorage, which contains the the necessary
 /// information to recover the node manager's nodes.
-#[derive(Default, PartialEq, Eq, Clone)]
+///
+/// The serde representation is human readable JSON for debugging and support,
+/// see [`StaticChannelBackupStorage::to_json`].
+#[derive(Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(
+    into = "StaticChannelBackupStorageJson",
+    try_from = "StaticChannelBackupStorageJson"
+)]
 pub struct StaticChannelBackupStorage {
     pub(crate) backups: HashMap<PublicKey, (NodeIndex, StaticChannelBackup)>,
-    pub(crate) peer_connections: HashMap<PublicKey, String>,
+    /// The connection strings of each peer, in the order they should be tried
+    pub(crate) peer_connections: HashMap<PublicKey, Vec<String>>,
+    /// Epoch time in seconds when the backup was created, zero for legacy backups
+    pub(crate) created_at: u64,
+    /// Bumped every time the node manager creates a backup, zero for legacy backups
+    pub(crate) generation: u64,
+    /// The network the backup was created on, legacy backups do not have one
+    pub(crate) network: Option<Network>,
+    /// The labels the user gave the channels, keyed by funding outpoint
+    pub(crate) channel_labels: HashMap<OutPoint, String>,
+    /// The alias, color and label of each peer
+    pub(crate) peer_metadata: HashMap<PublicKey, ScbPeerMetadata>,
+    /// When each peer was last connected to, in seconds since the unix epoch
+    pub(crate) peer_last_connected: HashMap<PublicKey, u64>,
+}
+
+/// Checks that the connection string is `pubkey@host:port` for the given peer.
+pub(crate) fn validate_connection_string(
+    pubkey: &PublicKey,
+    conn: &str,
+) -> Result<(), MutinyError> {
+    if conn.len() > MAX_STRING_LEN as usize || conn.matches('@').count() != 1 {
+        return Err(MutinyError::PeerInfoParseFailed);
+    }
+    let (conn_pubkey, addr) = split_peer_connection_string(conn)?;
+    let (host, port) = addr
+        .rsplit_once(':')
+        .ok_or(MutinyError::PeerInfoParseFailed)?;
+    if conn_pubkey != *pubkey || !is_valid_host(host) || port.parse::<u16>().is_err() {
+        return Err(MutinyError::PeerInfoParseFailed);
+    }
+    Ok(())
+}
+
+/// A host is an IPv6 address in brackets, or an IPv4 address or host name,
+/// which includes onion addresses.
+fn is_valid_host(host: &str) -> bool {
+    if let Some(ipv6) = host.strip_prefix('[').and_then(|h| h.strip_suffix(']')) {
+        return ipv6.parse::<std::net::Ipv6Addr>().is_ok();
+    }
+    host.split('.').all(|label| {
+        !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    })
+}
+
+/// What the wallet shows for a peer, restored with its channels so the
+/// peer is not anonymous after a recovery.
+#[derive(Default, PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
+pub struct ScbPeerMetadata {
+    /// The alias from the node announcement
+    pub alias: Option<String>,
+    /// The color from the node announcement
+    pub color: Option<String>,
+    /// The label set by the user
+    pub label: Option<String>,
+}
+
+impl ScbPeerMetadata {
+    pub fn is_empty(&self) -> bool {
+        self.alias.is_none() && self.color.is_none() && self.label.is_none()
+    }
 }
 
 impl StaticChannelBackupStorage {
+    /// Returns true if this backup was created after the other one.
+    /// Legacy backups without a generation are older than any newer backup.
+    pub fn is_newer_than(&self, other: &Self) -> bool {
+        self.generation_info()
+            .is_newer_than(&other.generation_info())
+    }
+
+    /// Returns a copy of the backup with only the given nodes. Errors if one of
+    /// the selected nodes is not in the backup.
+    pub fn select_nodes(&self, selected: &[PublicKey]) -> Result<Self, MutinyError> {
+        if selected.iter().any(|pk| !self.backups.contains_key(pk)) {
+            return Err(MutinyError::NotFound);
+        }
+
+        let backups = self
+            .backups
+            .iter()
+            .filter(|(pk, _)| selected.contains(pk))
+            .map(|(pk, backup)| (*pk, backup.clone()))
+            .collect();
+
+        Ok(Self {
+            backups,
+            ..self.clone()
+        })
+    }
+
+    /// Returns the backup as pretty printed JSON, with monitors as hex strings
+    /// and outpoints as `txid:vout`. This is for debugging and support, it is
+    /// not encrypted so it must be handled with the same care as the seed.
+    pub fn to_json(&self) -> Result<String, MutinyError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Parses a backup from the JSON created by [`StaticChannelBackupStorage::to_json`].
+    pub fn from_json(json: &str) -> Result<Self, MutinyError> {
+        serde_json::from_str(json).map_err(|_| MutinyError::InvalidArgumentsError)
+    }
+
+    /// Checks the backup was created on the given network. Returns false for
+    /// legacy backups that did not record their network, so the caller can warn.
+    pub fn check_network(&self, network: Network) -> Result<bool, MutinyError> {
+        match self.network {
+            Some(found) if found != network => Err(MutinyError::NetworkMismatch {
+                expected: network,
+                found,
+            }),
+            Some(_) => Ok(true),
+            None => Ok(false),
+        }
+    }
+
+    /// Returns the connection string that is tried first for the given peer.
+    pub fn connection_string(&self, pubkey: &PublicKey) -> Option<&str> {
+        self.peer_connections
+            .get(pubkey)
+            .and_then(|c| c.first())
+            .map(|c| c.as_str())
+    }
+
+    /// Sets the connection string that is tried first for the given peer, like when
+    /// the peer moved to a new address. The other known addresses are kept as
+    /// fallbacks. The string has to be `pubkey@host:port` with the peer's pubkey.
+    pub fn set_connection_string(
+        &mut self,
+        pubkey: PublicKey,
+        conn: String,
+    ) -> Result<(), MutinyError> {
+        validate_connection_string(&pubkey, &conn)?;
+
+        let connections = self.peer_connections.entry(pubkey).or_default();
+        connections.retain(|c| *c != conn);
+        connections.insert(0, conn);
+        Ok(())
+    }
+
+    /// The first bytes of the sha256 of the encoding. The encoding is deterministic,
+    /// so the fingerprint is the same for every encryption of the same backup.
+    pub fn fingerprint(&self) -> [u8; SCB_FINGERPRINT_LEN] {
+        let mut writer = HashWriter(sha256::Hash::engine());
+        Writeable::write(self, &mut writer).expect("hashing succeeds");
+        let hash = sha256::Hash::from_engine(writer.0);
+        hash[..SCB_FINGERPRINT_LEN]
+            .try_into()
+            .expect("hash is 32 bytes")
+    }
+
+    pub(crate) fn generation_info(&self) -> ScbGeneration {
+        ScbGeneration {
+            created_at: self.created_at,
+            generation: self.generation,
+        }
+    }
+
+    /// The hashes of the monitors of each node in the backup.
+    pub(crate) fn monitor_hashes(&self) -> HashMap<PublicKey, StaticChannelBackupHashes> {
+        self.backups
+            .iter()
+            .map(|(pk, (_, backup))| (*pk, StaticChannelBackupHashes::from(backup)))
+            .collect()
+    }
+
+    /// Reports what a restore could do for each channel of the backup, without
+    /// restoring anything, so the user can be warned before a force close recovery.
+    ///
+    /// The peer of a channel is only in its monitor, which can not be read without the
+    /// keys of its node, so the peers of the channels are given. Channels missing from
+    /// `channel_peers` are reported with an unknown peer and no connection.
+    pub fn recovery_report(
+        &self,
+        channel_peers: &HashMap<OutPoint, PublicKey>,
+    ) -> Vec<ChannelRecoveryInfo> {
+        let mut report: Vec<ChannelRecoveryInfo> = self
+            .backups
+            .iter()
+            .flat_map(|(node, (_, backup))| {
+                backup.monitors.iter().map(|(outpoint, monitor)| {
+                    let peer = channel_peers.get(outpoint).copied();
+                    ChannelRecoveryInfo {
+                        node: *node,
+                        funding_txo: *outpoint,
+                        latest_update_id: monitor_update_id(monitor),
+                        peer,
+                        has_peer_connection: peer
+                            .and_then(|p| self.peer_connections.get(&p))
+                            .is_some_and(|c| !c.is_empty()),
+                        peer_last_connected: peer
+                            .and_then(|p| self.peer_last_connected.get(&p))
+                            .copied(),
+                    }
+                })
+            })
+            .collect();
+        report.sort_by_key(|c| (c.node, c.funding_txo));
+        report
+    }
+
     pub(crate) fn encrypt(&self, secret_key: &SecretKey) -> EncryptedSCB {
-        let bytes = self.encode();
-        let iv: [u8; 16] = secp256k1::rand::random();
+        let iv: [u8; IV_LEN] = secp256k1::rand::random();
+        self.encrypt_with_iv(secret_key, iv)
+    }
+
+    /// Encrypts the backup with the seed key of the given account, see
+    /// [`scb_key_derivation_path`]. Without `with_fingerprint` the backup leaves out
+    /// the cleartext fingerprint, so copies of it can not be linked to each other.
+    pub(crate) fn encrypt_for(
+        &self,
+        secret_key: &SecretKey,
+        account: Option<u32>,
+        with_fingerprint: bool,
+    ) -> EncryptedSCB {
+        let key = secret_key.secret_bytes();
+        let iv: [u8; IV_LEN] = secp256k1::rand::random();
+        let mut encrypted = self.encrypt_streaming(&key, iv);
+        encrypted.account = account;
+        if !with_fingerprint {
+            encrypted.fingerprint = None;
+        }
+        encrypted.with_mac(&key)
+    }
+
+    /// Encrypts the backup with the given iv. This is the injection point for the
+    /// randomness in [`StaticChannelBackupStorage::encrypt`] so the test vectors
+    /// can be deterministic; the iv must never be reused for real backups.
+    fn encrypt_with_iv(&self, secret_key: &SecretKey, iv: [u8; IV_LEN]) -> EncryptedSCB {
+        let key = secret_key.secret_bytes();
+        self.encrypt_streaming(&key, iv).with_mac(&key)
+    }
+
+    /// Compresses and encrypts the encoding as it is written, so the plaintext is
+    /// never fully held in memory next to the monitors it is encoded from.
+    fn encrypt_streaming(&self, key: &[u8; 32], iv: [u8; IV_LEN]) -> EncryptedSCB {
+        // backups without TLV node indexes keep the version older wallets can read
+        let version = if self.backups.values().any(|(n, _)| n.needs_tlv()) {
+            SCB_VERSION_TLV_NODE_INDEX
+        } else {
+            SCB_VERSION_GZIP
+        };
+        let mut cipher = CbcWriter::new(key, iv);
+        std::io::Write::write_all(&mut cipher, &[version]).expect("writing to a vec succeeds");
+        let mut writer = GzipWriter(GzEncoder::new(cipher, Compression::best()));
+        Writeable::write(self, &mut writer).expect("writing to a vec succeeds");
+        let cipher = writer.0.finish().expect("writing to a vec succeeds");
+
+        EncryptedSCB {
+            encrypted_scb: cipher.finish(),
+            iv,
+            kdf: None,
+            mac: None,
+            network: self.network,
+            padding: None,
+            account: None,
+            fingerprint: Some(self.fingerprint()),
+        }
+    }
+
+    /// Estimates the length of the backup string once the backup is encrypted with
+    /// the seed key, see [`EncryptedSCB::encoded_len`]. The backup is compressed to
+    /// count its length but not encrypted. The estimate leaves out the account of the
+    /// key and padding to a bucket.
+    pub fn estimated_encrypted_len(&self) -> usize {
+        let mut writer = GzipWriter(GzEncoder::new(ByteCounter(0), Compression::best()));
+        Writeable::write(self, &mut writer).expect("counting bytes succeeds");
+        let compressed = writer.0.finish().expect("counting bytes succeeds").0;
+
+        // the version byte is encrypted too, PKCS7 always adds at least one byte
+        let ciphertext = (1 + compressed) / AES_BLOCK_LEN * AES_BLOCK_LEN + AES_BLOCK_LEN;
+        // the flags, the fingerprint, the length of the ciphertext, the iv and the mac
+        let mut len =
+            1 + SCB_FINGERPRINT_LEN + LENGTH_PREFIX_LEN + ciphertext + IV_LEN + SCB_MAC_LEN;
+        if self.network.is_some() {
+            len += NETWORK_MAGIC_LEN;
+        }
+        bech32_len(len)
+    }
+
+    /// Encrypts the backup with a key derived from the given passphrase instead of the
+    /// seed, so the backup can be restored by someone who does not have the seed.
+    ///
+    /// The salt and KDF parameters are stored in the [`EncryptedSCB`].
+    pub fn encrypt_with_passphrase(&self, pass: &str) -> EncryptedSCB {
+        let kdf = PassphraseKdf {
+            memory_kib: SCB_PASSPHRASE_KDF_MEMORY_KIB,
+            passes: SCB_PASSPHRASE_KDF_PASSES,
+            salt: secp256k1::rand::random(),
+        };
+        let iv: [u8; IV_LEN] = secp256k1::rand::random();
+        self.encrypt_with_kdf(pass, kdf, iv)
+    }
+
+    fn encrypt_with_kdf(&self, pass: &str, kdf: PassphraseKdf, iv: [u8; IV_LEN]) -> EncryptedSCB {
+        let key = kdf.derive_key(pass);
+        let mut encrypted = self.encrypt_streaming(&key, iv);
+        encrypted.kdf = Some(kdf);
+        encrypted.with_mac(&key)
+    }
+}
+
+/// Encrypts an already encoded payload. The backups are encrypted with
+/// [`CbcWriter`] instead, this is kept as the reference it is tested against
+/// and to create the unversioned test vectors.
+#[cfg(test)]
+fn encrypt_payload(bytes: &[u8], key: &[u8; 32], iv: [u8; IV_LEN]) -> EncryptedSCB {
+    let cipher = Aes256CbcEnc::new(key.into(), &iv.into());
+    let encrypted_scb: Vec<u8> = cipher.encrypt_padded_vec_mut::<Pkcs7>(bytes);
+
+    EncryptedSCB {
+        encrypted_scb,
+        iv,
+        kdf: None,
+        mac: None,
+        network: None,
+        padding: None,
+        account: None,
+        fingerprint: None,
+    }
+}
+
+/// A short fingerprint of the plaintext of a backup, and what it contains.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ScbFingerprint {
+    /// The first bytes of the sha256 of the plaintext
+    pub fingerprint: [u8; SCB_FINGERPRINT_LEN],
+    pub node_count: usize,
+    pub monitor_count: usize,
+}
+
+impl From<&StaticChannelBackupStorage> for ScbFingerprint {
+    fn from(storage: &StaticChannelBackupStorage) -> Self {
+        Self {
+            fingerprint: storage.fingerprint(),
+            node_count: storage.backups.len(),
+            monitor_count: storage
+                .backups
+                .values()
+                .map(|(_, backup)| backup.monitors.len())
+                .sum(),
+        }
+    }
+}
+
+/// The Argon2id parameters used to derive the encryption key of a passphrase
+/// encrypted backup. Backups are only read with costs in the bounds of
+/// [`PassphraseKdf::check_bounds`], so every key derivation can succeed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) struct PassphraseKdf {
+    memory_kib: u32,
+    passes: u32,
+    salt: [u8; PASSPHRASE_SALT_LEN],
+}
+
+impl PassphraseKdf {
+    /// The costs are read from the backup, so they are bounded below to keep the
+    /// passphrase hard to brute force, and above so a crafted backup can not hang
+    /// or exhaust the memory of the wallet.
+    fn check_bounds(&self) -> Result<(), DecodeError> {
+        let memory = SCB_PASSPHRASE_KDF_MEMORY_KIB..=SCB_PASSPHRASE_KDF_MAX_MEMORY_KIB;
+        let passes = SCB_PASSPHRASE_KDF_PASSES..=SCB_PASSPHRASE_KDF_MAX_PASSES;
+        if !memory.contains(&self.memory_kib) || !passes.contains(&self.passes) {
+            return Err(DecodeError::InvalidValue);
+        }
+        Ok(())
+    }
+
+    fn derive_key(&self, pass: &str) -> [u8; 32] {
+        let params = Params::new(self.memory_kib, self.passes, 1, Some(32))
+            .expect("kdf costs are within bounds");
+        let mut key = [0u8; 32];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(pass.as_bytes(), &self.salt, &mut key)
+            .expect("salt and output length are valid");
+        key
+    }
+}
+
+/// AES-256-CBC block size in bytes.
+const AES_BLOCK_LEN: usize = 16;
+
+/// Encrypts with AES-256-CBC and PKCS7 padding one block at a time as the
+/// plaintext is written, so only the ciphertext is kept in memory.
+struct CbcWriter {
+    cipher: Aes256CbcEnc,
+    block: [u8; AES_BLOCK_LEN],
+    block_len: usize,
+    ciphertext: Vec<u8>,
+}
+
+impl CbcWriter {
+    fn new(key: &[u8; 32], iv: [u8; IV_LEN]) -> Self {
+        Self {
+            cipher: Aes256CbcEnc::new(key.into(), &iv.into()),
+            block: [0; AES_BLOCK_LEN],
+            block_len: 0,
+            ciphertext: vec![],
+        }
+    }
+
+    /// Pads and encrypts the last block, returning the ciphertext.
+    fn finish(mut self) -> Vec<u8> {
+        let mut last = [0u8; AES_BLOCK_LEN];
+        last[..self.block_len].copy_from_slice(&self.block[..self.block_len]);
+        let last = self
+            .cipher
+            .encrypt_padded_mut::<Pkcs7>(&mut last, self.block_len)
+            .expect("a partial block always has room for the padding");
+        self.ciphertext.extend_from_slice(last);
+        self.ciphertext
+    }
+}
+
+impl std::io::Write for CbcWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut rest = buf;
+        while !rest.is_empty() {
+            let len = rest.len().min(AES_BLOCK_LEN - self.block_len);
+            self.block[self.block_len..self.block_len + len].copy_from_slice(&rest[..len]);
+            self.block_len += len;
+            rest = &rest[len..];
+
+            if self.block_len == AES_BLOCK_LEN {
+                self.cipher.encrypt_block_mut((&mut self.block).into());
+                self.ciphertext.extend_from_slice(&self.block);
+                self.block_len = 0;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Feeds the encoding from [`Writeable::write`] into the gzip encoder.
+struct GzipWriter<W: std::io::Write>(GzEncoder<W>);
+
+/// Hashes the bytes written to it, to fingerprint a backup without holding its encoding.
+struct HashWriter(sha256::HashEngine);
+
+impl lightning::io::Write for HashWriter {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, lightning::io::Error> {
+        self.0.input(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), lightning::io::Error> {
+        Ok(())
+    }
+}
+
+/// Counts the bytes written to it, to get the length of a compressed payload.
+struct ByteCounter(usize);
+
+impl std::io::Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<W: std::io::Write> lightning::io::Write for GzipWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, lightning::io::Error> {
+        std::io::Write::write(&mut self.0, buf)
+            .map_err(|_| lightning::io::Error::from(lightning::io::ErrorKind::Other))
+    }
+
+    fn flush(&mut self) -> Result<(), lightning::io::Error> {
+        std::io::Write::flush(&mut self.0)
+            .map_err(|_| lightning::io::Error::from(lightning::io::ErrorKind::Other))
+    }
+}
+
+/// Prefixes the encoded backup with [`SCB_VERSION_GZIP`] and gzip compresses it.
+/// Like [`encrypt_payload`] this is the buffered reference for the streaming encryption.
+#[cfg(test)]
+fn compress_payload(bytes: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(vec![SCB_VERSION_GZIP], Compression::best());
+    std::io::Write::write_all(&mut encoder, bytes).expect("writing to a vec succeeds");
+    encoder.finish().expect("writing to a vec succeeds")
+}
 
-        let cipher = Aes256CbcEnc::new(&secret_key.secret_bytes().into(), &iv.into());
-        let encrypted_scb: Vec<u8> = cipher.encrypt_padded_vec_mut::<Pkcs7>(&bytes);
+/// Returns the encoded backup from a decrypted payload, decompressing it
+/// based on the version byte. Unversioned payloads are returned as is.
+fn decompress_payload(bytes: Vec<u8>) -> Result<Vec<u8>, MutinyError> {
+    match bytes.first() {
+        Some(&SCB_VERSION_V0) => Ok(bytes),
+        Some(&SCB_VERSION_GZIP) | Some(&SCB_VERSION_TLV_NODE_INDEX) => gunzip(&bytes[1..]),
+        // diffs are tagged with SCB_VERSION_DIFF, they are not a full backup
+        _ => Err(MutinyError::LnDecodeError),
+    }
+}
 
-        EncryptedSCB { encrypted_scb, iv }
+/// Decompresses a gzip compressed payload, up to [`MAX_PAYLOAD_LEN`] bytes.
+fn gunzip(bytes: &[u8]) -> Result<Vec<u8>, MutinyError> {
+    let decoder = GzDecoder::new(bytes);
+    let mut decompressed = vec![];
+    std::io::Read::read_to_end(
+        &mut std::io::Read::take(decoder, MAX_PAYLOAD_LEN as u64 + 1),
+        &mut decompressed,
+    )
+    .map_err(|_| MutinyError::LnDecodeError)?;
+    if decompressed.len() > MAX_PAYLOAD_LEN as usize {
+        return Err(MutinyError::LnDecodeError);
     }
+    Ok(decompressed)
 }
 
 impl Writeable for StaticChannelBackupStorage {
     fn write<W: Writer>(&self, writer: &mut W) -> Result<(), lightning::io::Error> {
         // write backups
-        let len: u32 = self.backups.len() as u32;
+        let len = self.backups.len() as LengthPrefix;
         writer.write_all(&len.to_be_bytes())?;
-        for (public_key, (node_index, backup)) in self.backups.iter() {
+        for (public_key, (node_index, backup)) in sorted(&self.backups) {
             public_key.write(writer)?;
             node_index.write(writer)?;
             backup.write(writer)?;
         }
 
-        // write peer connections
-        let len: u32 = self.peer_connections.len() as u32;
+        // write peer connections, only the first address of each peer
+        // so older versions can still read the backup
+        let peers: Vec<(&PublicKey, &Vec<String>)> = sorted(&self.peer_connections)
+            .into_iter()
+            .filter(|(_, connections)| !connections.is_empty())
+            .collect();
+        let len = peers.len() as LengthPrefix;
         writer.write_all(&len.to_be_bytes())?;
-        for (public_key, peer_connection) in self.peer_connections.iter() {
+        for (public_key, connections) in peers.iter() {
             writer.write_all(&public_key.serialize())?;
-            let len: u32 = peer_connection.len() as u32;
+            write_length_prefixed(writer, connections[0].as_bytes())?;
+        }
+        let extra_addresses: Vec<(&PublicKey, &[String])> = peers
+            .into_iter()
+            .filter(|(_, connections)| connections.len() > 1)
+            .map(|(public_key, connections)| (public_key, &connections[1..]))
+            .collect();
+
+        // legacy backups have no generation, keep their encoding unchanged
+        let has_last_connected = !self.peer_last_connected.is_empty();
+        let has_metadata =
+            !self.channel_labels.is_empty() || !self.peer_metadata.is_empty() || has_last_connected;
+        let has_extra_addresses = !extra_addresses.is_empty() || has_metadata;
+        let has_network = self.network.is_some() || has_extra_addresses;
+        if self.generation > 0 || has_network {
+            writer.write_all(&self.created_at.to_be_bytes())?;
+            writer.write_all(&self.generation.to_be_bytes())?;
+        }
+        if has_network {
+            let magic = self.network.map_or(UNKNOWN_NETWORK_MAGIC, |n| n.magic());
+            writer.write_all(&magic.to_be_bytes())?;
+        }
+
+        // then the other addresses of the peers that have more than one
+        if has_extra_addresses {
+            let len = extra_addresses.len() as LengthPrefix;
             writer.write_all(&len.to_be_bytes())?;
-            writer.write_all(peer_connection.as_bytes())?;
+            for (public_key, connections) in extra_addresses {
+                writer.write_all(&public_key.serialize())?;
+                let len = connections.len() as LengthPrefix;
+                writer.write_all(&len.to_be_bytes())?;
+                for connection in connections {
+                    write_length_prefixed(writer, connection.as_bytes())?;
+                }
+            }
+        }
+
+        // and finally the channel labels and peer metadata
+        if has_metadata {
+            let len = self.channel_labels.len() as LengthPrefix;
+            writer.write_all(&len.to_be_bytes())?;
+            for (outpoint, label) in sorted(&self.channel_labels) {
+                writer.write_all(&outpoint.txid[..])?;
+                writer.write_all(&outpoint.vout.to_be_bytes())?;
+                write_length_prefixed(writer, label.as_bytes())?;
+            }
+
+            let len = self.peer_metadata.len() as LengthPrefix;
+            writer.write_all(&len.to_be_bytes())?;
+            for (public_key, metadata) in sorted(&self.peer_metadata) {
+                writer.write_all(&public_key.serialize())?;
+                write_optional_string(writer, &metadata.alias)?;
+                write_optional_string(writer, &metadata.color)?;
+                write_optional_string(writer, &metadata.label)?;
+            }
+        }
+
+        // then when each peer was last connected to
+        if has_last_connected {
+            let len = self.peer_last_connected.len() as LengthPrefix;
+            writer.write_all(&len.to_be_bytes())?;
+            for (public_key, last_connected) in sorted(&self.peer_last_connected) {
+                writer.write_all(&public_key.serialize())?;
+                writer.write_all(&last_connected.to_be_bytes())?;
+            }
         }
 
         Ok(())
     }
 }
 
+/// The entries of the map sorted by key, so the same backup is always encoded the
+/// same no matter the order the map was filled in.
+fn sorted<K: Ord, V>(map: &HashMap<K, V>) -> Vec<(&K, &V)> {
+    let mut entries: Vec<(&K, &V)> = map.iter().collect();
+    entries.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+    entries
+}
+
+/// Writes a presence byte, followed by the length prefixed string if there is one.
+fn write_optional_string<W: Writer>(
+    writer: &mut W,
+    string: &Option<String>,
+) -> Result<(), lightning::io::Error> {
+    match string {
+        None => writer.write_all(&[0]),
+        Some(string) => {
+            writer.write_all(&[1])?;
+            write_length_prefixed(writer, string.as_bytes())
+        }
+    }
+}
+
+fn read_optional_string<R: Read>(reader: &mut R) -> Result<Option<String>, DecodeError> {
+    let present: u8 = Readable::read(reader)?;
+    match present {
+        0 => Ok(None),
+        1 => Ok(Some(read_string(reader)?)),
+        _ => Err(DecodeError::InvalidValue),
+    }
+}
+
 impl Readable for StaticChannelBackupStorage {
     fn read<R: Read>(reader: &mut R) -> Result<Self, DecodeError> {
         // read backups
-        let len: u32 = Readable::read(reader)?;
+        let len = read_count(reader)?;
         let mut backups = HashMap::new();
         for _ in 0..len {
-            let mut pk = [0u8; 33];
+            let mut pk = [0u8; PUBKEY_LEN];
             reader.read_exact(&mut pk)?;
-            let public_key = PublicKey::from_slice(&pk).expect("public key is 33 bytes");
+            let public_key = PublicKey::from_slice(&pk).map_err(|_| DecodeError::InvalidValue)?;
             let node_index = Readable::read(reader)?;
             let backup = Readable::read(reader)?;
             backups.insert(public_key, (node_index, backup));
         }
 
         // read peer connections
-        let len: u32 = Readable::read(reader)?;
+        let len = read_count(reader)?;
         let mut peer_connections = HashMap::new();
         for _ in 0..len {
             // read public key
-            let mut public_key = [0u8; 33];
+            let mut public_key = [0u8; PUBKEY_LEN];
             reader.read_exact(&mut public_key)?;
-            let public_key = PublicKey::from_slice(&public_key).expect("public key is 33 bytes");
+            let public_key =
+                PublicKey::from_slice(&public_key).map_err(|_| DecodeError::InvalidValue)?;
 
             // read peer connection
-            let len: u32 = Readable::read(reader)?;
-            let mut peer_connection = vec![0u8; len as usize];
-            reader.read_exact(&mut peer_connection)?;
-            let peer_connection =
-                String::from_utf8(peer_connection).expect("peer connection is utf8");
-            peer_connections.insert(public_key, peer_connection);
+            let peer_connection = read_connection_string(reader, &public_key)?;
+            peer_connections.insert(public_key, vec![peer_connection]);
+        }
+
+        // newer backups end with the creation time and generation
+        let mut first = [0u8; 1];
+        let (created_at, generation) = match reader.read(&mut first)? {
+            0 => (0, 0),
+            _ => {
+                let mut rest = [0u8; SCB_GENERATION_LEN - 1];
+                reader.read_exact(&mut rest)?;
+                let mut created_at = [0u8; 8];
+                created_at[0] = first[0];
+                created_at[1..].copy_from_slice(&rest[..7]);
+                let generation: [u8; 8] = rest[7..].try_into().expect("8 bytes");
+                (
+                    u64::from_be_bytes(created_at),
+                    u64::from_be_bytes(generation),
+                )
+            }
+        };
+
+        // followed by the network, if it was recorded
+        let network = match reader.read(&mut first)? {
+            0 => None,
+            _ => {
+                let mut rest = [0u8; NETWORK_MAGIC_LEN - 1];
+                reader.read_exact(&mut rest)?;
+                let magic = [first[0], rest[0], rest[1], rest[2]];
+                if u32::from_be_bytes(magic) == UNKNOWN_NETWORK_MAGIC {
+                    None
+                } else {
+                    Some(read_network(magic)?)
+                }
+            }
+        };
+
+        // and the other addresses of the peers with more than one
+        if reader.read(&mut first)? != 0 {
+            let mut rest = [0u8; LENGTH_PREFIX_LEN - 1];
+            reader.read_exact(&mut rest)?;
+            let len = check_count(LengthPrefix::from_be_bytes([
+                first[0], rest[0], rest[1], rest[2],
+            ]))?;
+            for _ in 0..len {
+                let mut public_key = [0u8; PUBKEY_LEN];
+                reader.read_exact(&mut public_key)?;
+                let public_key =
+                    PublicKey::from_slice(&public_key).map_err(|_| DecodeError::InvalidValue)?;
+                let connections = peer_connections
+                    .get_mut(&public_key)
+                    .ok_or(DecodeError::InvalidValue)?;
+
+                let count = read_count(reader)?;
+                for _ in 0..count {
+                    connections.push(read_connection_string(reader, &public_key)?);
+                }
+            }
+        }
+
+        // followed by the channel labels and peer metadata
+        let mut channel_labels = HashMap::new();
+        let mut peer_metadata = HashMap::new();
+        if reader.read(&mut first)? != 0 {
+            let mut rest = [0u8; LENGTH_PREFIX_LEN - 1];
+            reader.read_exact(&mut rest)?;
+            let len = check_count(LengthPrefix::from_be_bytes([
+                first[0], rest[0], rest[1], rest[2],
+            ]))?;
+            for _ in 0..len {
+                let mut txid = [0u8; TXID_LEN];
+                reader.read_exact(&mut txid)?;
+                let vout: u32 = Readable::read(reader)?;
+                let outpoint = OutPoint {
+                    txid: bitcoin::Txid::from_slice(&txid)
+                        .map_err(|_| DecodeError::InvalidValue)?,
+                    vout,
+                };
+                channel_labels.insert(outpoint, read_string(reader)?);
+            }
+
+            let len = read_count(reader)?;
+            for _ in 0..len {
+                let mut public_key = [0u8; PUBKEY_LEN];
+                reader.read_exact(&mut public_key)?;
+                let public_key =
+                    PublicKey::from_slice(&public_key).map_err(|_| DecodeError::InvalidValue)?;
+                let metadata = ScbPeerMetadata {
+                    alias: read_optional_string(reader)?,
+                    color: read_optional_string(reader)?,
+                    label: read_optional_string(reader)?,
+                };
+                peer_metadata.insert(public_key, metadata);
+            }
+        }
+
+        // and when each peer was last connected to, older backups do not have it
+        let mut peer_last_connected = HashMap::new();
+        if reader.read(&mut first)? != 0 {
+            let mut rest = [0u8; LENGTH_PREFIX_LEN - 1];
+            reader.read_exact(&mut rest)?;
+            let len = check_count(LengthPrefix::from_be_bytes([
+                first[0], rest[0], rest[1], rest[2],
+            ]))?;
+            for _ in 0..len {
+                let mut public_key = [0u8; PUBKEY_LEN];
+                reader.read_exact(&mut public_key)?;
+                let public_key =
+                    PublicKey::from_slice(&public_key).map_err(|_| DecodeError::InvalidValue)?;
+                let last_connected: u64 = Readable::read(reader)?;
+                peer_last_connected.insert(public_key, last_connected);
+            }
         }
 
         Ok(Self {
             backups,
             peer_connections,
+            created_at,
+            generation,
+            network,
+            channel_labels,
+            peer_metadata,
+            peer_last_connected,
         })
     }
 }
@@ -160,72 +1252,945 @@ impl Readable for StaticChannelBackupStorage {
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct EncryptedSCB {
     pub(crate) encrypted_scb: Vec<u8>,
-    pub(crate) iv: [u8; 16],
+    pub(crate) iv: [u8; IV_LEN],
+    /// Cleartext fingerprint of the plaintext, the same for every encryption of the
+    /// same backup. Older backups do not have one.
+    pub(crate) fingerprint: Option<[u8; SCB_FINGERPRINT_LEN]>,
+    /// Set if the backup is encrypted with a passphrase instead of the seed
+    pub(crate) kdf: Option<PassphraseKdf>,
+    /// HMAC over the header, iv and ciphertext, older backups do not have one
+    pub(crate) mac: Option<[u8; SCB_MAC_LEN]>,
+    /// Cleartext copy of the network in the encrypted payload, so the network
+    /// can be checked before decrypting. Older backups do not have one.
+    pub(crate) network: Option<Network>,
+    /// Random bytes appended after encryption so the size only reveals a bucket,
+    /// see [`EncryptedSCB::pad_to_bucket`]. Ignored when decrypting.
+    pub(crate) padding: Option<Vec<u8>>,
+    /// The account of the seed key the backup is encrypted with, so it can be
+    /// derived when restoring. None for the default key.
+    pub(crate) account: Option<u32>,
 }
 
 impl EncryptedSCB {
-    pub(crate) fn decrypt(
-        &self,
-        secret_key: &SecretKey,
+    /// Returns true if the backup needs a passphrase to be decrypted,
+    /// rather than the key derived from the seed.
+    pub fn is_passphrase_encrypted(&self) -> bool {
+        self.kdf.is_some()
+    }
+
+    /// The account of the seed key the backup is encrypted with,
+    /// see [`scb_key_derivation_path`].
+    pub fn account(&self) -> Option<u32> {
+        self.account
+    }
+
+    /// The fingerprint of the plaintext, see [`StaticChannelBackupStorage::fingerprint`].
+    /// It is the same for every encryption of the same backup, so copies of a backup
+    /// can be found without decrypting them. Older backups do not have one.
+    pub fn fingerprint(&self) -> Option<[u8; SCB_FINGERPRINT_LEN]> {
+        self.fingerprint
+    }
+
+    /// The fingerprint as hex, for display.
+    pub fn fingerprint_hex(&self) -> Option<String> {
+        self.fingerprint.map(|f| f.to_hex())
+    }
+
+    /// The length of the backup string, to decide if it fits in a single QR code,
+    /// see [`SCB_SINGLE_QR_MAX_LEN`].
+    pub fn encoded_len(&self) -> usize {
+        bech32_len(self.serialized_length())
+    }
+
+    /// Pads the encoded backup with random bytes to the smallest of the given
+    /// sizes it fits in, replacing any previous padding. Without buckets the
+    /// backup is returned unpadded.
+    pub fn pad_to_bucket(mut self, buckets: &[u32]) -> Self {
+        // the flag and the padding length are part of the padded size
+        self.padding = Some(vec![]);
+        let len = self.serialized_length();
+        let Some(target) = padded_len(len, buckets) else {
+            self.padding = None;
+            return self;
+        };
+
+        let mut padding = vec![0u8; target - len];
+        getrandom::getrandom(&mut padding).expect("Failed to generate entropy");
+        self.padding = Some(padding);
+        self
+    }
+
+    pub(crate) fn decrypt(
+        &self,
+        secret_key: &SecretKey,
+    ) -> Result<StaticChannelBackupStorage, MutinyError> {
+        if self.is_passphrase_encrypted() {
+            return Err(MutinyError::PassphraseRequired);
+        }
+
+        let bytes = self
+            .decrypt_payload(&secret_key.secret_bytes())
+            .ok_or(MutinyError::InvalidMnemonic)?;
+        self.check_payload(read_payload(bytes)?)
+    }
+
+    /// Checks that the backup is intact and can be decrypted with the given key,
+    /// without restoring it. Returns a fingerprint of the plaintext and what it contains.
+    pub fn verify(&self, key: &SecretKey) -> Result<ScbFingerprint, MutinyError> {
+        let storage = self.decrypt(key)?;
+        Ok(ScbFingerprint::from(&storage))
+    }
+
+    /// Re-encrypts the backup under a new key with a fresh iv,
+    /// without exposing the plaintext to the caller.
+    pub fn rotate_key(
+        &self,
+        old_key: &SecretKey,
+        new_key: &SecretKey,
+    ) -> Result<EncryptedSCB, MutinyError> {
+        let storage = self.decrypt(old_key)?;
+        Ok(storage.encrypt(new_key))
+    }
+
+    /// Decrypts a backup created with [`StaticChannelBackupStorage::encrypt_with_passphrase`].
+    pub fn decrypt_with_passphrase(
+        &self,
+        pass: &str,
+    ) -> Result<StaticChannelBackupStorage, MutinyError> {
+        let kdf = self.kdf.ok_or(MutinyError::InvalidArgumentsError)?;
+
+        let bytes = self
+            .decrypt_payload(&kdf.derive_key(pass))
+            .ok_or(MutinyError::IncorrectPassphrase)?;
+        self.check_payload(read_payload(bytes)?)
+    }
+
+    /// Backups without a mac do not authenticate the cleartext metadata, so it has
+    /// to match the copy in the encrypted payload.
+    fn check_payload(
+        &self,
+        storage: StaticChannelBackupStorage,
     ) -> Result<StaticChannelBackupStorage, MutinyError> {
-        let cipher =
-            Aes256CbcDec::new(&secret_key.secret_bytes().into(), self.iv.as_slice().into());
-        let result = cipher
+        if self.network.is_some() && self.network != storage.network {
+            return Err(MutinyError::LnDecodeError);
+        }
+        Ok(storage)
+    }
+
+    /// Decrypts the payload, returns None if the mac or the padding is invalid,
+    /// which means the wrong key was used or the backup is corrupt. Only
+    /// [`SCB_VERSION_V0`] payloads predate the mac, any other payload without
+    /// one had it stripped and is rejected as well.
+    fn decrypt_payload(&self, key: &[u8; 32]) -> Option<Vec<u8>> {
+        // fail fast on corruption without decrypting
+        if let Some(mac) = self.mac {
+            if !mac_eq(&mac, &self.compute_mac(key)) {
+                return None;
+            }
+        }
+
+        let cipher = Aes256CbcDec::new(key.into(), self.iv.as_slice().into());
+        let bytes = cipher
             .decrypt_padded_vec_mut::<Pkcs7>(&self.encrypted_scb)
-            .map_err(|_| MutinyError::InvalidMnemonic)?;
+            .ok()?;
+        if self.mac.is_none() && bytes.first() != Some(&SCB_VERSION_V0) {
+            return None;
+        }
+        Some(bytes)
+    }
+
+    fn with_mac(mut self, key: &[u8; 32]) -> Self {
+        self.mac = Some(self.compute_mac(key));
+        self
+    }
+
+    /// HMAC-SHA256 over the header, iv and ciphertext, keyed with a tagged hash of
+    /// the encryption key so the same key is not used for both AES and the HMAC.
+    /// The header is the flags, with the mac flag set, followed by the cleartext
+    /// fields as they are written. The padding is added after the mac and ignored,
+    /// so its flag is left out.
+    fn compute_mac(&self, key: &[u8; 32]) -> [u8; SCB_MAC_LEN] {
+        let mut engine = sha256::Hash::engine();
+        engine.input(SCB_MAC_KEY_TAG);
+        engine.input(key);
+        let mac_key = sha256::Hash::from_engine(engine);
+
+        let flags = (self.flags() | SCB_MAC_FLAG) & !SCB_PADDING_FLAG;
+        let mut header = vec![flags];
+        self.write_header(&mut header)
+            .expect("writing to a vec succeeds");
+
+        let mut engine = HmacEngine::<sha256::Hash>::new(&mac_key[..]);
+        engine.input(&header);
+        engine.input(&self.iv);
+        engine.input(&self.encrypted_scb);
+        Hmac::<sha256::Hash>::from_engine(engine).into_inner()
+    }
+}
+
+/// Compares two macs in constant time, so how long a forged mac takes to be
+/// rejected does not reveal how much of it is correct.
+fn mac_eq(a: &[u8; SCB_MAC_LEN], b: &[u8; SCB_MAC_LEN]) -> bool {
+    a.iter()
+        .zip(b.iter())
+        .fold(0u8, |diff, (x, y)| diff | (x ^ y))
+        == 0
+}
+
+fn read_network(magic: [u8; NETWORK_MAGIC_LEN]) -> Result<Network, DecodeError> {
+    Network::from_magic(u32::from_be_bytes(magic)).ok_or(DecodeError::InvalidValue)
+}
+
+/// Reads the backup storage from a decrypted payload.
+fn read_payload(bytes: Vec<u8>) -> Result<StaticChannelBackupStorage, MutinyError> {
+    let mut cursor = Cursor::new(decompress_payload(bytes)?);
+    Ok(StaticChannelBackupStorage::read(&mut cursor)?)
+}
+
+impl EncryptedSCB {
+    /// The flags byte of the backup, zero for backups written without one.
+    fn flags(&self) -> u8 {
+        let mut flags = 0;
+        if self.kdf.is_some() {
+            flags |= SCB_PASSPHRASE_MODE;
+        }
+        if self.mac.is_some() {
+            flags |= SCB_MAC_FLAG;
+        }
+        if self.network.is_some() {
+            flags |= SCB_NETWORK_FLAG;
+        }
+        if self.padding.is_some() {
+            flags |= SCB_PADDING_FLAG;
+        }
+        if self.account.is_some() {
+            flags |= SCB_ACCOUNT_FLAG;
+        }
+        if self.fingerprint.is_some() {
+            flags |= SCB_FINGERPRINT_FLAG;
+        }
+        flags
+    }
+
+    /// Writes the cleartext fields that follow the flags.
+    fn write_header<W: Writer>(&self, writer: &mut W) -> Result<(), lightning::io::Error> {
+        if let Some(network) = self.network {
+            writer.write_all(&network.magic().to_be_bytes())?;
+        }
 
-        let mut cursor = Cursor::new(result);
-        Ok(StaticChannelBackupStorage::read(&mut cursor).expect("decoding succeeds"))
+        if let Some(account) = self.account {
+            writer.write_all(&account.to_be_bytes())?;
+        }
+
+        if let Some(fingerprint) = self.fingerprint {
+            writer.write_all(&fingerprint)?;
+        }
+
+        if let Some(kdf) = self.kdf {
+            writer.write_all(&kdf.memory_kib.to_be_bytes())?;
+            writer.write_all(&kdf.passes.to_be_bytes())?;
+            writer.write_all(&kdf.salt)?;
+        }
+        Ok(())
     }
 }
 
 impl Writeable for EncryptedSCB {
     fn write<W: Writer>(&self, writer: &mut W) -> Result<(), lightning::io::Error> {
-        let len = self.encrypted_scb.len() as u32;
+        let flags = self.flags();
+        if flags != 0 {
+            writer.write_all(&[flags])?;
+        }
+        self.write_header(writer)?;
+
+        let len = self.encrypted_scb.len() as LengthPrefix;
         writer.write_all(&len.to_be_bytes())?;
         writer.write_all(&self.encrypted_scb)?;
         writer.write_all(&self.iv)?;
+
+        if let Some(mac) = self.mac {
+            writer.write_all(&mac)?;
+        }
+
+        if let Some(padding) = &self.padding {
+            write_length_prefixed(writer, padding)?;
+        }
         Ok(())
     }
 }
 
 impl Readable for EncryptedSCB {
     fn read<R: Read>(reader: &mut R) -> Result<Self, DecodeError> {
-        let len: u32 = Readable::read(reader)?;
-        let mut encrypted_scb = vec![0u8; len as usize];
-        reader.read_exact(&mut encrypted_scb)?;
-        let mut iv = [0u8; 16];
+        // The first byte is either the flags or, for older backups,
+        // the first byte of the ciphertext length which is always zero.
+        let mut first = [0u8; 1];
+        reader.read_exact(&mut first)?;
+        let flags = first[0];
+        let known = SCB_PASSPHRASE_MODE
+            | SCB_MAC_FLAG
+            | SCB_NETWORK_FLAG
+            | SCB_PADDING_FLAG
+            | SCB_ACCOUNT_FLAG
+            | SCB_FINGERPRINT_FLAG;
+        if flags & !known != 0 {
+            return Err(DecodeError::UnknownVersion);
+        }
+        // every backup with flags is written with a mac, so a missing one was stripped
+        if flags != 0 && flags & SCB_MAC_FLAG == 0 {
+            return Err(DecodeError::InvalidValue);
+        }
+
+        let network = if flags & SCB_NETWORK_FLAG != 0 {
+            let mut magic = [0u8; NETWORK_MAGIC_LEN];
+            reader.read_exact(&mut magic)?;
+            Some(read_network(magic)?)
+        } else {
+            None
+        };
+
+        let account = if flags & SCB_ACCOUNT_FLAG != 0 {
+            let account: u32 = Readable::read(reader)?;
+            if ChildNumber::from_hardened_idx(account).is_err() {
+                return Err(DecodeError::InvalidValue);
+            }
+            Some(account)
+        } else {
+            None
+        };
+
+        let fingerprint = if flags & SCB_FINGERPRINT_FLAG != 0 {
+            let mut fingerprint = [0u8; SCB_FINGERPRINT_LEN];
+            reader.read_exact(&mut fingerprint)?;
+            Some(fingerprint)
+        } else {
+            None
+        };
+
+        let kdf = if flags & SCB_PASSPHRASE_MODE != 0 {
+            let memory_kib: u32 = Readable::read(reader)?;
+            let passes: u32 = Readable::read(reader)?;
+            let mut salt = [0u8; PASSPHRASE_SALT_LEN];
+            reader.read_exact(&mut salt)?;
+            let kdf = PassphraseKdf {
+                memory_kib,
+                passes,
+                salt,
+            };
+            kdf.check_bounds()?;
+            Some(kdf)
+        } else {
+            None
+        };
+
+        let len: LengthPrefix = if flags == 0 {
+            let mut rest = [0u8; LENGTH_PREFIX_LEN - 1];
+            reader.read_exact(&mut rest)?;
+            LengthPrefix::from_be_bytes([first[0], rest[0], rest[1], rest[2]])
+        } else {
+            Readable::read(reader)?
+        };
+        if len > MAX_PAYLOAD_LEN {
+            return Err(DecodeError::InvalidValue);
+        }
+        let encrypted_scb = read_bytes(reader, len)?;
+        let mut iv = [0u8; IV_LEN];
         reader.read_exact(&mut iv)?;
-        Ok(Self { encrypted_scb, iv })
+
+        let mac = if flags & SCB_MAC_FLAG != 0 {
+            let mut mac = [0u8; SCB_MAC_LEN];
+            reader.read_exact(&mut mac)?;
+            Some(mac)
+        } else {
+            None
+        };
+
+        let padding = if flags & SCB_PADDING_FLAG != 0 {
+            Some(read_length_prefixed(reader, MAX_PAYLOAD_LEN)?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            encrypted_scb,
+            iv,
+            fingerprint,
+            kdf,
+            mac,
+            network,
+            padding,
+            account,
+        })
     }
 }
 
+/// Errors that can occur when parsing a backup string.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum SCBParseError {
+    /// The string is not bech32 encoded.
+    #[error("The backup is not bech32 encoded.")]
+    InvalidEncoding,
+    /// The human readable part is not [`SCB_HRP`].
+    #[error("The string is not a backup.")]
+    InvalidHrp,
+    /// The checksum does not match, the backup was mistyped or cut off.
+    #[error("The backup checksum does not match.")]
+    InvalidChecksum,
+    /// The backup is bech32 instead of bech32m encoded.
+    #[error("The backup is not bech32m encoded.")]
+    InvalidVariant,
+    /// The checksum is valid but the encoded data is not a backup.
+    #[error("The backup data could not be decoded.")]
+    InvalidPayload,
+}
+
+/// Parses a backup string. Like BIP-173 it can be all uppercase, as QR scanners
+/// tend to return it, and surrounding whitespace and a [`SCB_URI_SCHEME`] prefix are
+/// ignored.
 impl FromStr for EncryptedSCB {
-    type Err = DecodeError;
+    type Err = SCBParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let (hrp, data, variant) = bech32::decode(s).map_err(|_| DecodeError::InvalidValue)?;
-        if hrp != "scb" || variant != Variant::Bech32m {
-            return Err(DecodeError::InvalidValue);
+        let (s, _) = Self::bech32_part(s);
+        let (hrp, _) = s.rsplit_once('1').ok_or(SCBParseError::InvalidEncoding)?;
+        if !hrp.eq_ignore_ascii_case(SCB_HRP) {
+            return Err(SCBParseError::InvalidHrp);
+        }
+
+        let (_, data, variant) = bech32::decode(s).map_err(|e| match e {
+            bech32::Error::InvalidChecksum => SCBParseError::InvalidChecksum,
+            _ => SCBParseError::InvalidEncoding,
+        })?;
+        if variant != Variant::Bech32m {
+            return Err(SCBParseError::InvalidVariant);
         }
-        let bytes = Vec::<u8>::from_base32(&data).map_err(|_| DecodeError::InvalidValue)?;
+
+        let bytes = Vec::<u8>::from_base32(&data).map_err(|_| SCBParseError::InvalidPayload)?;
         let mut reader = Cursor::new(bytes);
-        Readable::read(&mut reader)
+        Readable::read(&mut reader).map_err(|_| SCBParseError::InvalidPayload)
+    }
+}
+
+impl EncryptedSCB {
+    /// Parses a bech32m encoded backup like [`FromStr`], but the error points to
+    /// the first invalid character when the backup was mistyped.
+    /// Armored exports from [`EncryptedSCB::to_armored`] are parsed as well.
+    pub fn parse(s: &str) -> Result<Self, MutinyError> {
+        if s.trim_start().starts_with(SCB_ARMOR_BEGIN) {
+            return Self::from_armored(s).map_err(|_| MutinyError::ScbParse { position: None });
+        }
+
+        Self::from_str(s).map_err(|_| {
+            let (bech32, offset) = Self::bech32_part(s);
+            MutinyError::ScbParse {
+                position: checksum::locate_error(bech32).map(|p| p + offset),
+            }
+        })
+    }
+
+    /// Returns the bech32 part of a backup string, without surrounding whitespace
+    /// or the [`SCB_URI_SCHEME`], and the character position it starts at.
+    fn bech32_part(s: &str) -> (&str, usize) {
+        let trimmed = s.trim();
+        let bech32 = match trimmed.get(..SCB_URI_SCHEME.len()) {
+            Some(scheme) if scheme.eq_ignore_ascii_case(SCB_URI_SCHEME) => {
+                &trimmed[SCB_URI_SCHEME.len()..]
+            }
+            _ => trimmed,
+        };
+        let start = s.len() - s.trim_start().len() + trimmed.len() - bech32.len();
+        (bech32, s[..start].chars().count())
     }
 }
 
 impl core::fmt::Display for EncryptedSCB {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let bytes = self.encode();
-        let s = bech32::encode("scb", bytes.to_base32(), Variant::Bech32m)
+        let s = bech32::encode(SCB_HRP, bytes.to_base32(), Variant::Bech32m)
             .map_err(|_| std::fmt::Error)?;
         write!(f, "{}", s)
     }
 }
 
+/// A summary of what would be recovered from a static channel backup.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ScbSummary {
+    /// The nodes in the backup, sorted by child index
+    pub nodes: Vec<ScbNodeSummary>,
+    /// The peer connection strings in the backup
+    pub peer_connections: Vec<String>,
+    /// The peers in the backup, the most recently connected first
+    pub peers: Vec<ScbPeerSummary>,
+    /// When the backup was created, zero for legacy backups
+    pub generation: ScbGeneration,
+}
+
+impl ScbSummary {
+    /// The number of nodes that would be recovered
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+}
+
+/// A summary of a single node in a static channel backup.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ScbNodeSummary {
+    pub pubkey: PublicKey,
+    pub child_index: u32,
+    pub lsp: Option<String>,
+    /// Archived nodes are usually not worth recovering
+    pub archived: bool,
+    /// The funding outpoints of the channels that would be recovered
+    pub channels: Vec<OutPoint>,
+}
+
+/// A summary of a peer in a static channel backup.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ScbPeerSummary {
+    pub pubkey: PublicKey,
+    /// The connection strings, in the order they are tried
+    pub connections: Vec<String>,
+    /// When the peer was last connected to, in seconds since the unix epoch.
+    /// None for older backups, the older this is the more likely the addresses are stale.
+    pub last_connected: Option<u64>,
+}
+
+impl From<&StaticChannelBackupStorage> for ScbSummary {
+    fn from(storage: &StaticChannelBackupStorage) -> Self {
+        let mut nodes: Vec<ScbNodeSummary> = storage
+            .backups
+            .iter()
+            .map(|(pubkey, (node_index, backup))| {
+                let mut channels: Vec<OutPoint> = backup.monitors.keys().copied().collect();
+                channels.sort();
+                ScbNodeSummary {
+                    pubkey: *pubkey,
+                    child_index: node_index.child_index,
+                    lsp: node_index.lsp.clone(),
+                    archived: node_index.is_archived(),
+                    channels,
+                }
+            })
+            .collect();
+        nodes.sort_by_key(|n| n.child_index);
+
+        let mut peer_connections: Vec<String> = storage
+            .peer_connections
+            .values()
+            .flatten()
+            .cloned()
+            .collect();
+        peer_connections.sort();
+
+        let mut peers: Vec<ScbPeerSummary> = storage
+            .peer_connections
+            .iter()
+            .map(|(pubkey, connections)| ScbPeerSummary {
+                pubkey: *pubkey,
+                connections: connections.clone(),
+                last_connected: storage.peer_last_connected.get(pubkey).copied(),
+            })
+            .collect();
+        peers.sort_by_key(|p| (std::cmp::Reverse(p.last_connected), p.pubkey));
+
+        Self {
+            nodes,
+            peer_connections,
+            peers,
+            generation: storage.generation_info(),
+        }
+    }
+}
+
+/// What restoring a channel from a static channel backup could do, from
+/// [`StaticChannelBackupStorage::recovery_report`].
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ChannelRecoveryInfo {
+    /// Our node the channel belongs to
+    pub node: PublicKey,
+    pub funding_txo: OutPoint,
+    /// The update id of the monitor in the backup, it goes up with every new
+    /// commitment of the channel. None if the monitor is too short to read it.
+    pub latest_update_id: Option<u64>,
+    /// The channel peer, None if it is unknown
+    pub peer: Option<PublicKey>,
+    /// If the backup has an address of the peer, without one the peer can not be
+    /// asked to force close and we have to broadcast our own commitment
+    pub has_peer_connection: bool,
+    /// When the peer was last connected to, in seconds since the unix epoch
+    pub peer_last_connected: Option<u64>,
+}
+
+/// The result of restoring from a static channel backup.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ScbRestoreResult {
+    /// True if a newer backup had already been created or restored on this device,
+    /// restoring an older backup risks broadcasting revoked states.
+    pub is_stale: bool,
+    /// True if the backup did not record its network, so it could not be verified
+    /// to be from the same network as the wallet.
+    pub missing_network: bool,
+    /// The channels that were not restored because this wallet has a newer monitor
+    #[serde(default)]
+    pub skipped_channels: Vec<ScbSkippedChannel>,
+}
+
+/// A channel of a static channel backup that was not restored, because this wallet
+/// has a newer monitor for it that restoring would replace with an older state.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ScbSkippedChannel {
+    pub funding_txo: OutPoint,
+    /// The update id of the monitor of this wallet
+    pub stored_update_id: u64,
+    /// The update id of the monitor in the backup, None if it could not be read
+    pub backup_update_id: Option<u64>,
+}
+
+/// What happened to a channel when sweeping its funds from a static channel backup.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum ScbSweepStatus {
+    /// Nothing is left to claim, the funds went to the on-chain wallet
+    Swept,
+    /// The channel is closing and its funds are swept once they can be claimed.
+    /// None while the closing transaction is not confirmed.
+    PendingTimelock {
+        amount_sats: u64,
+        blocks_remaining: Option<u32>,
+    },
+    /// The peer could not be reached, our commitment from the backup was not broadcast
+    /// since it may be revoked. The peer is asked to force close once it reconnects.
+    PeerUnreachable,
+    /// This wallet has a newer monitor for the channel, so it was not swept with the
+    /// older state of the backup. The channel is left as it is.
+    NewerLocalState(ScbSkippedChannel),
+    /// The funds of the channel could not be swept
+    Failed(ScbSweepFailure),
+}
+
+/// Why the funds of a channel could not be swept from a static channel backup.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScbSweepFailure {
+    /// The channel monitor in the backup could not be read
+    MonitorUnreadable,
+    /// The node of the channel could not be started
+    NodeUnavailable,
+    /// The peer could not be reached and broadcasting our commitment was allowed, but
+    /// a newer backup exists, so it is revoked. The close is requested once the peer
+    /// reconnects.
+    StaleBackup,
+}
+
+/// The sweep status of a channel from a static channel backup.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ScbSweepResult {
+    pub funding_txo: OutPoint,
+    /// The channel peer, unknown if the monitor could not be read
+    pub peer: Option<PublicKey>,
+    pub status: ScbSweepStatus,
+}
+
+/// The sweep status of a closing channel from the claimable balances of its monitor.
+pub(crate) fn scb_sweep_status(
+    funding_txo: OutPoint,
+    balances: &[Balance],
+    tip_height: u32,
+) -> ScbSweepStatus {
+    let recoveries = channel_recoveries(funding_txo, balances, tip_height, 0);
+    if recoveries.is_empty() {
+        return ScbSweepStatus::Swept;
+    }
+
+    // everything is swept once the last of the funds can be claimed
+    let blocks_remaining = recoveries
+        .iter()
+        .map(|r| r.blocks_remaining)
+        .collect::<Option<Vec<u32>>>()
+        .map(|blocks| blocks.into_iter().max().unwrap_or_default());
+    ScbSweepStatus::PendingTimelock {
+        amount_sats: recoveries.iter().map(|r| r.amount_sats).sum(),
+        blocks_remaining,
+    }
+}
+
+/// A step of restoring a static channel backup, reported as the restore runs
+/// since recreating nodes and closing channels can take a while.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum ScbRestoreProgress {
+    /// The backup was decrypted and parsed
+    Decrypted,
+    /// A node from the backup was recreated and its channels recovered
+    NodeRestored(PublicKey),
+    /// The channel monitor of the channel with this funding outpoint is watched again
+    MonitorLoaded(OutPoint),
+    /// Reconnected to a channel peer so it can be asked to force close
+    PeerReconnected(PublicKey),
+    /// The restore finished
+    Done,
+}
+
+/// The metadata of a static channel backup that can be read without decrypting it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ScbMetadata {
+    /// The network the backup was created on, older backups do not record it
+    pub network: Option<Network>,
+    /// True if the backup is encrypted with a passphrase instead of the seed
+    pub passphrase_encrypted: bool,
+    /// True if the backup has an integrity tag
+    pub has_integrity_tag: bool,
+    /// How many bytes of random padding the backup ends with
+    pub padding_len: usize,
+    /// The account of the seed key the backup is encrypted with, None for the default
+    pub account: Option<u32>,
+    /// The fingerprint of the plaintext as hex, older backups do not have one
+    pub fingerprint: Option<String>,
+}
+
+/// Parses the given backup string and returns its cleartext metadata,
+/// no key is needed since nothing is decrypted.
+pub fn inspect_scb_metadata(scb: &str) -> Result<ScbMetadata, MutinyError> {
+    let encrypted = EncryptedSCB::parse(scb)?;
+    Ok(ScbMetadata {
+        network: encrypted.network,
+        passphrase_encrypted: encrypted.kdf.is_some(),
+        has_integrity_tag: encrypted.mac.is_some(),
+        padding_len: encrypted.padding.map_or(0, |p| p.len()),
+        account: encrypted.account,
+        fingerprint: encrypted.fingerprint_hex(),
+    })
+}
+
+/// Decrypts and parses the given backup string and returns what would be recovered
+/// from it, without touching storage or starting any nodes.
+pub fn preview_scb(scb: &str, key: &SecretKey) -> Result<ScbSummary, MutinyError> {
+    let encrypted = EncryptedSCB::parse(scb)?;
+    let storage = encrypted.decrypt(key)?;
+    Ok(ScbSummary::from(&storage))
+}
+
+/// Errors that can occur when splitting or reassembling a multi-part SCB export.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum SCBPartsError {
+    /// The maximum part length leaves no room for backup data after the part header.
+    #[error("SCB parts of {0} characters are too short.")]
+    PartTooShort(usize),
+    /// The backup would need more parts than can be numbered.
+    #[error("SCB needs more than {} parts.", u16::MAX)]
+    TooManyParts,
+    /// No parts were given.
+    #[error("No SCB parts were given.")]
+    Empty,
+    /// A part could not be decoded.
+    #[error("Failed to decode SCB part.")]
+    InvalidPart,
+    /// The parts do not belong to the same backup.
+    #[error("SCB parts are from different backups.")]
+    MismatchedParts,
+    /// The part with the given index is missing.
+    #[error("SCB part {0} is missing.")]
+    MissingPart(u16),
+    /// The reassembled backup could not be decoded.
+    #[error("Failed to decode reassembled SCB.")]
+    InvalidBackup,
+}
+
+impl EncryptedSCB {
+    /// Splits the backup into multiple bech32m strings that are each at most
+    /// `max_chars` long, so they can be displayed as an animated QR code.
+    ///
+    /// Each part carries its index, the total number of parts, and an id of the backup
+    /// so they can be reassembled with [`EncryptedSCB::from_parts`] in any order.
+    /// Fails if `max_chars` does not fit the part header and at least one byte of the
+    /// backup, or if the backup would need more than `u16::MAX` parts.
+    pub fn to_parts(&self, max_chars: usize) -> Result<Vec<String>, SCBPartsError> {
+        let bytes = self.encode();
+        let id = backup_id(&bytes);
+
+        let data_chars = max_chars.saturating_sub(SCB_PART_BECH32_OVERHEAD);
+        let chunk_size = (data_chars * 5 / 8).saturating_sub(SCB_PART_HEADER_LEN);
+        if chunk_size == 0 {
+            return Err(SCBPartsError::PartTooShort(max_chars));
+        }
+
+        let chunks: Vec<&[u8]> = bytes.chunks(chunk_size).collect();
+        let total = u16::try_from(chunks.len()).map_err(|_| SCBPartsError::TooManyParts)?;
+
+        Ok(chunks
+            .into_iter()
+            .enumerate()
+            .map(|(index, chunk)| {
+                let mut payload = Vec::with_capacity(SCB_PART_HEADER_LEN + chunk.len());
+                payload.extend_from_slice(&(index as u16).to_be_bytes());
+                payload.extend_from_slice(&total.to_be_bytes());
+                payload.extend_from_slice(&id);
+                payload.extend_from_slice(chunk);
+                bech32::encode(SCB_HRP, payload.to_base32(), Variant::Bech32m)
+                    .expect("scb is a valid hrp")
+            })
+            .collect())
+    }
+
+    /// Reassembles a backup from the parts created by [`EncryptedSCB::to_parts`].
+    /// The parts can be given in any order.
+    pub fn from_parts(parts: &[String]) -> Result<Self, SCBPartsError> {
+        if parts.is_empty() {
+            return Err(SCBPartsError::Empty);
+        }
+
+        let mut total: Option<u16> = None;
+        let mut id: Option<[u8; 4]> = None;
+        let mut chunks: HashMap<u16, Vec<u8>> = HashMap::new();
+        for part in parts {
+            let (hrp, data, variant) =
+                bech32::decode(part).map_err(|_| SCBPartsError::InvalidPart)?;
+            if hrp != SCB_HRP || variant != Variant::Bech32m {
+                return Err(SCBPartsError::InvalidPart);
+            }
+            let payload = Vec::<u8>::from_base32(&data).map_err(|_| SCBPartsError::InvalidPart)?;
+            if payload.len() < SCB_PART_HEADER_LEN {
+                return Err(SCBPartsError::InvalidPart);
+            }
+
+            let index = u16::from_be_bytes([payload[0], payload[1]]);
+            let part_total = u16::from_be_bytes([payload[2], payload[3]]);
+            let part_id: [u8; 4] = payload[4..8].try_into().expect("id is 4 bytes");
+            if index >= part_total {
+                return Err(SCBPartsError::InvalidPart);
+            }
+
+            if *total.get_or_insert(part_total) != part_total
+                || *id.get_or_insert(part_id) != part_id
+            {
+                return Err(SCBPartsError::MismatchedParts);
+            }
+
+            chunks.insert(index, payload[SCB_PART_HEADER_LEN..].to_vec());
+        }
+
+        let mut bytes = vec![];
+        for index in 0..total.expect("at least one part") {
+            let chunk = chunks
+                .get(&index)
+                .ok_or(SCBPartsError::MissingPart(index))?;
+            bytes.extend_from_slice(chunk);
+        }
+
+        if backup_id(&bytes) != id.expect("at least one part") {
+            return Err(SCBPartsError::InvalidBackup);
+        }
+
+        Readable::read(&mut Cursor::new(bytes)).map_err(|_| SCBPartsError::InvalidBackup)
+    }
+}
+
+/// Errors that can occur when reading an armored SCB export.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum SCBArmorError {
+    /// The export does not start with [`SCB_ARMOR_BEGIN`].
+    #[error("The armored backup is missing its header.")]
+    MissingHeader,
+    /// The export does not end with [`SCB_ARMOR_END`].
+    #[error("The armored backup is missing its footer.")]
+    MissingFooter,
+    /// The body or the checksum is not base64.
+    #[error("The armored backup is not base64 encoded.")]
+    InvalidEncoding,
+    /// The CRC24 does not match, the export was corrupted.
+    #[error("The armored backup checksum does not match.")]
+    InvalidChecksum,
+    /// The decoded backup could not be read.
+    #[error("Failed to decode armored backup.")]
+    InvalidBackup,
+}
+
+impl EncryptedSCB {
+    /// Encodes the backup as a PGP armor style text block that can be saved to a file,
+    /// with a CRC24 so corruption is detected. Read back with [`EncryptedSCB::from_armored`].
+    pub fn to_armored(&self) -> String {
+        let bytes = self.encode();
+        let body = base64::encode(&bytes);
+        let crc = crc24(&bytes).to_be_bytes();
+
+        let mut armored = format!("{SCB_ARMOR_BEGIN}\n");
+        for line in body.as_bytes().chunks(SCB_ARMOR_LINE_LEN) {
+            armored.push_str(std::str::from_utf8(line).expect("base64 is ascii"));
+            armored.push('\n');
+        }
+        armored.push('=');
+        armored.push_str(&base64::encode(&crc[1..]));
+        armored.push('\n');
+        armored.push_str(SCB_ARMOR_END);
+        armored.push('\n');
+        armored
+    }
+
+    /// Reads a backup exported with [`EncryptedSCB::to_armored`]. Line endings and
+    /// surrounding whitespace do not matter, but the checksum has to match.
+    pub fn from_armored(s: &str) -> Result<Self, SCBArmorError> {
+        let mut lines = s.lines().map(str::trim).filter(|l| !l.is_empty());
+        if lines.next() != Some(SCB_ARMOR_BEGIN) {
+            return Err(SCBArmorError::MissingHeader);
+        }
+
+        let mut body = String::new();
+        let mut checksum = None;
+        let mut ended = false;
+        for line in lines.by_ref() {
+            if line == SCB_ARMOR_END {
+                ended = true;
+                break;
+            }
+            match line.strip_prefix('=') {
+                Some(crc) => checksum = Some(crc),
+                None if checksum.is_none() => body.push_str(line),
+                // nothing but the footer can follow the checksum
+                None => return Err(SCBArmorError::InvalidEncoding),
+            }
+        }
+        if !ended {
+            return Err(SCBArmorError::MissingFooter);
+        }
+
+        let checksum = checksum.ok_or(SCBArmorError::InvalidChecksum)?;
+        let checksum: [u8; 3] = base64::decode(checksum)
+            .map_err(|_| SCBArmorError::InvalidEncoding)?
+            .try_into()
+            .map_err(|_| SCBArmorError::InvalidEncoding)?;
+        let bytes = base64::decode(body).map_err(|_| SCBArmorError::InvalidEncoding)?;
+        if crc24(&bytes).to_be_bytes()[1..] != checksum {
+            return Err(SCBArmorError::InvalidChecksum);
+        }
+
+        Readable::read(&mut Cursor::new(bytes)).map_err(|_| SCBArmorError::InvalidBackup)
+    }
+}
+
+/// The CRC24 checksum used by OpenPGP armor, see RFC 4880 section 6.1.
+fn crc24(bytes: &[u8]) -> u32 {
+    const CRC24_INIT: u32 = 0xB704CE;
+    const CRC24_POLY: u32 = 0x1864CFB;
+
+    let mut crc = CRC24_INIT;
+    for byte in bytes {
+        crc ^= (*byte as u32) << 16;
+        for _ in 0..8 {
+            crc <<= 1;
+            if crc & 0x1000000 != 0 {
+                crc ^= CRC24_POLY;
+            }
+        }
+    }
+    crc & 0xFFFFFF
+}
+
+/// Short identifier of an encoded backup, used to make sure all parts
+/// of a multi-part export belong to the same backup.
+fn backup_id(bytes: &[u8]) -> [u8; 4] {
+    let hash = sha256::Hash::hash(bytes);
+    hash[..4].try_into().expect("hash is 32 bytes")
+}
+
 #[cfg(test)]
 mod test {
-    use bitcoin::hashes::hex::FromHex;
+    use bitcoin::hashes::hex::{FromHex, ToHex};
     use std::str::FromStr;
 
     use super::*;
@@ -506,6 +2471,8 @@ mod test {
             child_index: 0,
             lsp: None,
             archived: Some(false),
+            created_at: None,
+            alias: None,
         };
 
         let pk = PublicKey::from_str(
@@ -517,6 +2484,7 @@ mod test {
                 .into_iter()
                 .collect(),
             peer_connections: HashMap::new(),
+            ..Default::default()
         };
 
         let bytes = storage.encode();
@@ -534,6 +2502,85 @@ mod test {
         assert_eq!(decoded.encode(), storage.encode());
     }
 
+    /// A backup of several nodes, peers and channels, with the maps filled in the
+    /// given order.
+    fn storage_inserted_in_order(order: &[u8]) -> StaticChannelBackupStorage {
+        let secp = secp256k1::Secp256k1::new();
+        let mut storage = StaticChannelBackupStorage {
+            network: Some(Network::Signet),
+            ..Default::default()
+        };
+        for i in order {
+            let pubkey =
+                PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&[*i; 32]).unwrap());
+            let outpoint = OutPoint {
+                txid: bitcoin::Txid::from_slice(&[*i; 32]).unwrap(),
+                vout: *i as u32,
+            };
+
+            let mut backup = StaticChannelBackup::default();
+            for vout in order {
+                let outpoint = OutPoint {
+                    vout: *vout as u32,
+                    ..outpoint
+                };
+                backup.monitors.insert(outpoint, vec![*i, *vout]);
+            }
+            let node_index = NodeIndex {
+                child_index: *i as u32,
+                lsp: None,
+                archived: Some(false),
+                created_at: None,
+                alias: None,
+            };
+            storage.backups.insert(pubkey, (node_index, backup));
+
+            storage.peer_connections.insert(
+                pubkey,
+                vec![
+                    format!("{pubkey}@203.0.113.{i}:9735"),
+                    format!("{pubkey}@198.51.100.{i}:9735"),
+                ],
+            );
+            storage
+                .channel_labels
+                .insert(outpoint, format!("channel {i}"));
+            storage.peer_metadata.insert(
+                pubkey,
+                ScbPeerMetadata {
+                    alias: Some(format!("peer {i}")),
+                    color: None,
+                    label: None,
+                },
+            );
+            storage.peer_last_connected.insert(pubkey, *i as u64);
+        }
+        storage
+    }
+
+    #[test]
+    fn test_static_channel_backup_deterministic_encoding() {
+        let order: Vec<u8> = (1..=16).collect();
+        let reversed: Vec<u8> = order.iter().rev().copied().collect();
+        let first = storage_inserted_in_order(&order);
+        let second = storage_inserted_in_order(&reversed);
+        assert!(first == second);
+
+        let bytes = first.encode();
+        assert_eq!(bytes, second.encode());
+        assert_eq!(bytes, first.clone().encode());
+
+        // reading it back fills new maps, which encode the same
+        let decoded = StaticChannelBackupStorage::read(&mut Cursor::new(&bytes)).unwrap();
+        assert_eq!(decoded.encode(), bytes);
+
+        // so the same backup encrypts to the same string with the same iv
+        assert_eq!(
+            first.encrypt_with_iv(&golden_key(), GOLDEN_IV).to_string(),
+            second.encrypt_with_iv(&golden_key(), GOLDEN_IV).to_string()
+        );
+    }
+
     #[test]
     fn test_empty_static_channel_backup_storage() {
         let storage = StaticChannelBackupStorage::default();
@@ -572,7 +2619,90 @@ mod test {
     }
 
     #[test]
-    fn test_static_channel_backup_storage() {
+    fn test_scb_recovery_report() {
+        let mut storage = golden_full_storage();
+        let (node, (_, backup)) = storage.backups.iter().next().unwrap();
+        let node = *node;
+        let outpoint = *backup.monitors.keys().next().unwrap();
+        let reachable = *storage.peer_connections.keys().next().unwrap();
+        storage.peer_last_connected.insert(reachable, 1_700_000_000);
+
+        let no_address = OutPoint {
+            vout: 7,
+            ..outpoint
+        };
+        let unknown = OutPoint {
+            vout: 8,
+            ..outpoint
+        };
+        let backup = &mut storage.backups.get_mut(&node).unwrap().1;
+        backup
+            .monitors
+            .insert(no_address, newer_chain_monitor_bytes());
+        // too short to read the update id
+        backup.monitors.insert(unknown, vec![0; 4]);
+
+        let other_peer = PublicKey::from_str(
+            "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        )
+        .unwrap();
+        let channel_peers: HashMap<OutPoint, PublicKey> =
+            vec![(outpoint, reachable), (no_address, other_peer)]
+                .into_iter()
+                .collect();
+
+        let report = storage.recovery_report(&channel_peers);
+        assert_eq!(
+            report,
+            vec![
+                ChannelRecoveryInfo {
+                    node,
+                    funding_txo: outpoint,
+                    latest_update_id: Some(19),
+                    peer: Some(reachable),
+                    has_peer_connection: true,
+                    peer_last_connected: Some(1_700_000_000),
+                },
+                ChannelRecoveryInfo {
+                    node,
+                    funding_txo: no_address,
+                    latest_update_id: Some(20),
+                    peer: Some(other_peer),
+                    has_peer_connection: false,
+                    peer_last_connected: None,
+                },
+                ChannelRecoveryInfo {
+                    node,
+                    funding_txo: unknown,
+                    latest_update_id: None,
+                    peer: None,
+                    has_peer_connection: false,
+                    peer_last_connected: None,
+                },
+            ]
+        );
+        assert_eq!(
+            report[0].latest_update_id,
+            monitor_update_id(&CHAIN_MONITOR_BYTES)
+        );
+
+        // without the peers nothing is known to be reachable
+        assert!(storage
+            .recovery_report(&HashMap::new())
+            .iter()
+            .all(|c| c.peer.is_none() && !c.has_peer_connection));
+    }
+
+    /// The test monitor with its update id bumped from 19 to 20
+    fn newer_chain_monitor_bytes() -> Vec<u8> {
+        let mut bytes = CHAIN_MONITOR_BYTES.to_vec();
+        bytes[MONITOR_VERSION_PREFIX_LEN..MONITOR_VERSION_PREFIX_LEN + 8]
+            .copy_from_slice(&20u64.to_be_bytes());
+        bytes
+    }
+
+    #[test]
+    fn test_static_channel_backup_remove_outdated() {
         let outpoint = OutPoint {
             txid: bitcoin::Txid::from_hex(
                 "830b1c110ef6c78312a8f4c798da0bfbacdfc9c80c7d458ca614e7b1543f5b03",
@@ -580,17 +2710,129 @@ mod test {
             .unwrap(),
             vout: 1,
         };
+        let other = OutPoint {
+            vout: 2,
+            ..outpoint
+        };
+        let unreadable = OutPoint {
+            vout: 3,
+            ..outpoint
+        };
+        let backup = StaticChannelBackup {
+            monitors: vec![
+                (outpoint, CHAIN_MONITOR_BYTES.to_vec()),
+                (other, newer_chain_monitor_bytes()),
+                (unreadable, vec![1, 1]),
+            ]
+            .into_iter()
+            .collect(),
+        };
 
-        let pubkey = PublicKey::from_str(
-            "02cae09cf2c8842ace44068a5bf3117a494ebbf69a99e79712483c36f97cdb7b54",
-        )
-        .unwrap();
+        // a fresh wallet restores every channel
+        let mut restored = backup.clone();
+        assert!(restored.remove_outdated(&HashMap::new(), false).is_empty());
+        assert!(restored == backup);
 
-        let connection_str =
-            "02cae09cf2c8842ace44068a5bf3117a494ebbf69a99e79712483c36f97cdb7b54@192.168.0.1:9735"
-                .to_string();
+        // channels the wallet has a newer monitor for are skipped, the same or an
+        // older monitor is restored
+        let stored: HashMap<OutPoint, u64> = vec![(outpoint, 20), (other, 20), (unreadable, 0)]
+            .into_iter()
+            .collect();
+        let mut restored = backup.clone();
+        let skipped = restored.remove_outdated(&stored, false);
+        assert_eq!(
+            skipped,
+            vec![
+                ScbSkippedChannel {
+                    funding_txo: outpoint,
+                    stored_update_id: 20,
+                    backup_update_id: Some(19),
+                },
+                ScbSkippedChannel {
+                    funding_txo: unreadable,
+                    stored_update_id: 0,
+                    backup_update_id: None,
+                },
+            ]
+        );
+        assert_eq!(restored.monitor_count(), 1);
+        assert_eq!(restored.update_id_for(&other), Some(20));
 
-        let backup = StaticChannelBackup {
+        // forcing the restore keeps them all
+        let mut forced = backup.clone();
+        assert!(forced.remove_outdated(&stored, true).is_empty());
+        assert!(forced == backup);
+    }
+
+    #[test]
+    fn test_static_channel_backup_keeps_newest_monitor() {
+        let outpoint = OutPoint {
+            txid: bitcoin::Txid::from_hex(
+                "830b1c110ef6c78312a8f4c798da0bfbacdfc9c80c7d458ca614e7b1543f5b03",
+            )
+            .unwrap(),
+            vout: 1,
+        };
+        let older = StaticChannelBackup {
+            monitors: vec![(outpoint, CHAIN_MONITOR_BYTES.to_vec())]
+                .into_iter()
+                .collect(),
+        };
+        let newer = StaticChannelBackup {
+            monitors: vec![(outpoint, newer_chain_monitor_bytes())]
+                .into_iter()
+                .collect(),
+        };
+        assert_eq!(older.update_id_for(&outpoint), Some(19));
+        assert_eq!(newer.update_id_for(&outpoint), Some(20));
+        assert_eq!(older.update_id_for(&OutPoint::default()), None);
+
+        // the newer monitor wins in either order
+        let mut merged = older.clone();
+        merged.merge(newer.clone());
+        assert_eq!(merged.update_id_for(&outpoint), Some(20));
+        let mut merged = newer.clone();
+        merged.merge(older.clone());
+        assert_eq!(merged.update_id_for(&outpoint), Some(20));
+
+        // a monitor too short to have an update id never replaces one
+        let mut backup = older.clone();
+        assert!(!backup.insert_monitor(outpoint, vec![1, 1]));
+        assert_eq!(backup.update_id_for(&outpoint), Some(19));
+
+        // decoding a backup with the channel twice keeps the newest monitor
+        let mut bytes = 2u32.to_be_bytes().to_vec();
+        for monitor in [newer_chain_monitor_bytes(), CHAIN_MONITOR_BYTES.to_vec()] {
+            bytes.extend_from_slice(&outpoint.txid[..]);
+            bytes.extend_from_slice(&outpoint.vout.to_be_bytes());
+            bytes.extend_from_slice(&(monitor.len() as LengthPrefix).to_be_bytes());
+            bytes.extend_from_slice(&monitor);
+        }
+        let read = StaticChannelBackup::read(&mut Cursor::new(&bytes)).unwrap();
+        assert_eq!(read.monitors.len(), 1);
+        assert_eq!(read.update_id_for(&outpoint), Some(20));
+    }
+
+    #[test]
+    fn test_static_channel_backup_storage() {
+        let outpoint = OutPoint {
+            txid: bitcoin::Txid::from_hex(
+                "830b1c110ef6c78312a8f4c798da0bfbacdfc9c80c7d458ca614e7b1543f5b03",
+            )
+            .unwrap(),
+            vout: 1,
+        };
+
+        let pubkey = PublicKey::from_str(
+            "02cae09cf2c8842ace44068a5bf3117a494ebbf69a99e79712483c36f97cdb7b54",
+        )
+        .unwrap();
+
+        let connection_str =
+            "02cae09cf2c8842ace44068a5bf3117a494ebbf69a99e79712483c36f97cdb7b54@192.168.0.1:9735"
+                .to_string();
+
+        let backup = StaticChannelBackup {
             monitors: vec![(outpoint, CHAIN_MONITOR_BYTES.to_vec())]
                 .into_iter()
                 .collect(),
@@ -600,11 +2842,14 @@ mod test {
             child_index: 0,
             lsp: Some("https://signet-lsp.mutinywallet.com".to_string()),
             archived: Some(false),
+            created_at: None,
+            alias: None,
         };
 
         let storage = StaticChannelBackupStorage {
             backups: vec![(pubkey, (node_index, backup))].into_iter().collect(),
-            peer_connections: vec![(pubkey, connection_str)].into_iter().collect(),
+            peer_connections: vec![(pubkey, vec![connection_str])].into_iter().collect(),
+            ..Default::default()
         };
 
         let storage_bytes = storage.encode();
@@ -642,11 +2887,14 @@ mod test {
             child_index: 0,
             lsp: Some("https://signet-lsp.mutinywallet.com".to_string()),
             archived: Some(false),
+            created_at: None,
+            alias: None,
         };
 
         let storage = StaticChannelBackupStorage {
             backups: vec![(pubkey, (node_index, backup))].into_iter().collect(),
-            peer_connections: vec![(pubkey, connection_str)].into_iter().collect(),
+            peer_connections: vec![(pubkey, vec![connection_str])].into_iter().collect(),
+            ..Default::default()
         };
 
         // gen key
@@ -661,4 +2909,1669 @@ mod test {
         let decrypted = encrypted.decrypt(&encryption_key).unwrap();
         assert!(decrypted == storage);
     }
+
+    fn encrypted_test_backup() -> EncryptedSCB {
+        let outpoint = OutPoint {
+            txid: bitcoin::Txid::from_hex(
+                "830b1c110ef6c78312a8f4c798da0bfbacdfc9c80c7d458ca614e7b1543f5b03",
+            )
+            .unwrap(),
+            vout: 1,
+        };
+
+        let pubkey = PublicKey::from_str(
+            "02cae09cf2c8842ace44068a5bf3117a494ebbf69a99e79712483c36f97cdb7b54",
+        )
+        .unwrap();
+
+        let backup = StaticChannelBackup {
+            monitors: vec![(outpoint, CHAIN_MONITOR_BYTES.to_vec())]
+                .into_iter()
+                .collect(),
+        };
+
+        let node_index = NodeIndex {
+            child_index: 0,
+            lsp: None,
+            archived: Some(false),
+            created_at: None,
+            alias: None,
+        };
+
+        let storage = StaticChannelBackupStorage {
+            backups: vec![(pubkey, (node_index, backup))].into_iter().collect(),
+            peer_connections: HashMap::new(),
+            ..Default::default()
+        };
+
+        let mut bytes = [0u8; 32];
+        getrandom::getrandom(&mut bytes).expect("Failed to generate entropy");
+        let encryption_key = SecretKey::from_slice(&bytes).unwrap();
+
+        storage.encrypt(&encryption_key)
+    }
+
+    #[test]
+    fn test_encrypted_scb_parts_shuffled() {
+        let encrypted = encrypted_test_backup();
+
+        let mut parts = encrypted.to_parts(500).unwrap();
+        assert!(parts.len() > 1);
+        assert!(parts.iter().all(|p| p.len() <= 500));
+
+        // reverse and rotate so the parts are out of order
+        parts.reverse();
+        parts.rotate_left(parts.len() / 2);
+
+        let reassembled = EncryptedSCB::from_parts(&parts).unwrap();
+        assert_eq!(reassembled, encrypted);
+    }
+
+    #[test]
+    fn test_encrypted_scb_parts_missing_chunk() {
+        let encrypted = encrypted_test_backup();
+
+        let mut parts = encrypted.to_parts(500).unwrap();
+        assert!(parts.len() > 3);
+        parts.remove(2);
+
+        let err = EncryptedSCB::from_parts(&parts).unwrap_err();
+        assert_eq!(err, SCBPartsError::MissingPart(2));
+
+        assert_eq!(
+            EncryptedSCB::from_parts(&[]).unwrap_err(),
+            SCBPartsError::Empty
+        );
+    }
+
+    #[test]
+    fn test_encrypted_scb_parts_limits() {
+        let encrypted = encrypted_test_backup();
+
+        // the smallest part fits the header and a single byte of the backup
+        let min_chars = SCB_PART_BECH32_OVERHEAD + ((SCB_PART_HEADER_LEN + 1) * 8 + 4) / 5;
+        assert_eq!(
+            encrypted.to_parts(min_chars - 1).unwrap_err(),
+            SCBPartsError::PartTooShort(min_chars - 1)
+        );
+        assert_eq!(
+            encrypted.to_parts(0).unwrap_err(),
+            SCBPartsError::PartTooShort(0)
+        );
+        let parts = encrypted.to_parts(min_chars).unwrap();
+        assert_eq!(parts.len(), encrypted.encode().len());
+        assert_eq!(EncryptedSCB::from_parts(&parts).unwrap(), encrypted);
+
+        // one byte per part can not number a backup larger than u16::MAX bytes
+        let mut large = encrypted.clone();
+        large.encrypted_scb = vec![0; u16::MAX as usize + 1];
+        assert_eq!(
+            large.to_parts(min_chars).unwrap_err(),
+            SCBPartsError::TooManyParts
+        );
+    }
+
+    #[test]
+    fn test_encrypted_scb_armored() {
+        assert_eq!(crc24(b""), 0xB704CE);
+        assert_eq!(crc24(b"123456789"), 0x21CF02);
+
+        let encrypted = encrypted_test_backup();
+        let armored = encrypted.to_armored();
+        let lines: Vec<&str> = armored.lines().collect();
+        assert_eq!(lines[0], SCB_ARMOR_BEGIN);
+        assert_eq!(lines[lines.len() - 1], SCB_ARMOR_END);
+        assert!(lines[lines.len() - 2].starts_with('='));
+        assert!(lines[1..lines.len() - 2]
+            .iter()
+            .all(|l| l.len() <= SCB_ARMOR_LINE_LEN));
+        assert_eq!(EncryptedSCB::from_armored(&armored).unwrap(), encrypted);
+
+        // windows line endings and surrounding whitespace
+        let crlf = format!("\r\n  {}\r\n", armored.replace('\n', "\r\n"));
+        assert_eq!(EncryptedSCB::from_armored(&crlf).unwrap(), encrypted);
+        assert_eq!(EncryptedSCB::parse(&crlf).unwrap(), encrypted);
+    }
+
+    #[test]
+    fn test_encrypted_scb_armored_errors() {
+        let armored = encrypted_test_backup().to_armored();
+        let mut lines: Vec<String> = armored.lines().map(|l| l.to_string()).collect();
+
+        // a changed checksum
+        let crc = lines.len() - 2;
+        let corrupted = if lines[crc] == "=AAAA" {
+            "=AAAB"
+        } else {
+            "=AAAA"
+        };
+        let mut bad_crc = lines.clone();
+        bad_crc[crc] = corrupted.to_string();
+        assert_eq!(
+            EncryptedSCB::from_armored(&bad_crc.join("\n")).unwrap_err(),
+            SCBArmorError::InvalidChecksum
+        );
+
+        // a changed body
+        let mut bad_body = lines.clone();
+        let first = bad_body[1].remove(0);
+        bad_body[1].insert(0, if first == 'A' { 'B' } else { 'A' });
+        assert_eq!(
+            EncryptedSCB::from_armored(&bad_body.join("\n")).unwrap_err(),
+            SCBArmorError::InvalidChecksum
+        );
+
+        // cut off before the footer
+        lines.pop();
+        let cut = lines.join("\n");
+        assert_eq!(
+            EncryptedSCB::from_armored(&cut).unwrap_err(),
+            SCBArmorError::MissingFooter
+        );
+        assert!(matches!(
+            EncryptedSCB::parse(&cut),
+            Err(MutinyError::ScbParse { position: None })
+        ));
+
+        assert_eq!(
+            EncryptedSCB::from_armored(&lines[1..].join("\n")).unwrap_err(),
+            SCBArmorError::MissingHeader
+        );
+    }
+
+    #[test]
+    fn test_encrypted_scb_from_str_lenient() {
+        let encrypted = encrypted_test_backup();
+        let encoded = encrypted.to_string();
+
+        // how a QR scanner in alphanumeric mode returns it
+        let uppercase = encoded.to_uppercase();
+        assert_eq!(EncryptedSCB::from_str(&uppercase).unwrap(), encrypted);
+
+        let padded = format!("  \n{encoded}\t ");
+        assert_eq!(EncryptedSCB::from_str(&padded).unwrap(), encrypted);
+
+        let uri = format!(" {SCB_URI_SCHEME}{encoded}\n");
+        assert_eq!(EncryptedSCB::from_str(&uri).unwrap(), encrypted);
+        let uri = format!("SCB:{uppercase}");
+        assert_eq!(EncryptedSCB::from_str(&uri).unwrap(), encrypted);
+
+        // the reported position is in the given string, a short backup so
+        // the typo can only be in one place
+        let short = StaticChannelBackupStorage::default()
+            .encrypt(&golden_key())
+            .to_string();
+        let prefix = format!(" {SCB_URI_SCHEME}");
+        let typo_at = prefix.len() + SCB_HRP.len() + 5;
+        let mut chars: Vec<char> = format!("{prefix}{short}").chars().collect();
+        chars[typo_at] = if chars[typo_at] == 'q' { 'p' } else { 'q' };
+        let typo: String = chars.into_iter().collect();
+        assert!(matches!(
+            EncryptedSCB::parse(&typo),
+            Err(MutinyError::ScbParse { position: Some(p) }) if p == typo_at
+        ));
+    }
+
+    #[test]
+    fn test_encrypted_scb_from_str_errors() {
+        let encrypted = encrypted_test_backup();
+        let bytes = encrypted.encode();
+
+        assert_eq!(
+            EncryptedSCB::from_str("not a backup").unwrap_err(),
+            SCBParseError::InvalidEncoding
+        );
+
+        let address = bech32::encode("bc", bytes.to_base32(), Variant::Bech32m).unwrap();
+        assert_eq!(
+            EncryptedSCB::from_str(&address).unwrap_err(),
+            SCBParseError::InvalidHrp
+        );
+
+        let mut chars: Vec<char> = encrypted.to_string().chars().collect();
+        let last = chars.len() - 1;
+        chars[last] = if chars[last] == 'q' { 'p' } else { 'q' };
+        let mistyped: String = chars.into_iter().collect();
+        assert_eq!(
+            EncryptedSCB::from_str(&mistyped).unwrap_err(),
+            SCBParseError::InvalidChecksum
+        );
+
+        let bech32 = bech32::encode(SCB_HRP, bytes.to_base32(), Variant::Bech32).unwrap();
+        assert_eq!(
+            EncryptedSCB::from_str(&bech32).unwrap_err(),
+            SCBParseError::InvalidVariant
+        );
+
+        let garbage = bech32::encode(SCB_HRP, [1u8, 2, 3].to_base32(), Variant::Bech32m).unwrap();
+        assert_eq!(
+            EncryptedSCB::from_str(&garbage).unwrap_err(),
+            SCBParseError::InvalidPayload
+        );
+    }
+
+    const GOLDEN_IV: [u8; IV_LEN] = [2u8; IV_LEN];
+
+    fn golden_key() -> SecretKey {
+        SecretKey::from_slice(&[1u8; 32]).unwrap()
+    }
+
+    fn golden_full_storage() -> StaticChannelBackupStorage {
+        let outpoint = OutPoint {
+            txid: bitcoin::Txid::from_hex(
+                "830b1c110ef6c78312a8f4c798da0bfbacdfc9c80c7d458ca614e7b1543f5b03",
+            )
+            .unwrap(),
+            vout: 1,
+        };
+
+        let pubkey = PublicKey::from_str(
+            "02cae09cf2c8842ace44068a5bf3117a494ebbf69a99e79712483c36f97cdb7b54",
+        )
+        .unwrap();
+
+        let connection_str =
+            "02cae09cf2c8842ace44068a5bf3117a494ebbf69a99e79712483c36f97cdb7b54@192.168.0.1:9735"
+                .to_string();
+
+        let backup = StaticChannelBackup {
+            monitors: vec![(outpoint, CHAIN_MONITOR_BYTES.to_vec())]
+                .into_iter()
+                .collect(),
+        };
+
+        let node_index = NodeIndex {
+            child_index: 0,
+            lsp: Some("https://signet-lsp.mutinywallet.com".to_string()),
+            archived: Some(false),
+            created_at: None,
+            alias: None,
+        };
+
+        StaticChannelBackupStorage {
+            backups: vec![(pubkey, (node_index, backup))].into_iter().collect(),
+            peer_connections: vec![(pubkey, vec![connection_str])].into_iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_golden_empty_static_channel_backup_storage() {
+        let storage = StaticChannelBackupStorage::default();
+
+        let bytes = storage.encode();
+        assert_eq!(bytes.len(), 2 * LENGTH_PREFIX_LEN);
+        assert_eq!(bytes[0], SCB_VERSION_V0);
+        assert_eq!(
+            bytes.to_hex(),
+            include_str!("test_vectors/empty_storage.hex").trim()
+        );
+
+        // the golden strings are unversioned backups, which must stay readable
+        let golden = include_str!("test_vectors/empty_scb.txt").trim();
+        let encrypted = encrypt_payload(&bytes, &golden_key().secret_bytes(), GOLDEN_IV);
+        assert_eq!(encrypted.to_string(), golden);
+
+        let decoded = EncryptedSCB::from_str(golden).unwrap();
+        assert_eq!(decoded.iv, GOLDEN_IV);
+        assert!(decoded.decrypt(&golden_key()).unwrap() == storage);
+    }
+
+    #[test]
+    fn test_golden_static_channel_backup_storage() {
+        let storage = golden_full_storage();
+
+        let bytes = storage.encode();
+        assert_eq!(bytes[0], SCB_VERSION_V0);
+        assert_eq!(
+            bytes.to_hex(),
+            include_str!("test_vectors/full_storage.hex").trim()
+        );
+
+        let golden = include_str!("test_vectors/full_scb.txt").trim();
+        assert!(golden.starts_with(SCB_HRP));
+        let encrypted = encrypt_payload(&bytes, &golden_key().secret_bytes(), GOLDEN_IV);
+        assert_eq!(encrypted.to_string(), golden);
+
+        let decoded = EncryptedSCB::from_str(golden).unwrap();
+        assert_eq!(decoded.iv, GOLDEN_IV);
+        assert_eq!(
+            decoded.encode().len(),
+            LENGTH_PREFIX_LEN + decoded.encrypted_scb.len() + IV_LEN
+        );
+        assert!(decoded.decrypt(&golden_key()).unwrap() == storage);
+    }
+
+    #[test]
+    fn test_compressed_static_channel_backup_storage() {
+        let storage = golden_full_storage();
+        let raw = storage.encode();
+
+        let compressed = compress_payload(&raw);
+        assert_eq!(compressed[0], SCB_VERSION_GZIP);
+        assert!(compressed.len() < raw.len());
+        assert_eq!(decompress_payload(compressed).unwrap(), raw);
+
+        // the compressed backup round trips through encryption
+        let encrypted = storage.encrypt(&golden_key());
+        let unversioned = encrypt_payload(&raw, &golden_key().secret_bytes(), encrypted.iv)
+            .with_mac(&golden_key().secret_bytes());
+        assert!(encrypted.encrypted_scb.len() < unversioned.encrypted_scb.len());
+        assert!(encrypted.to_string().len() < unversioned.to_string().len());
+        let decrypted = encrypted.decrypt(&golden_key()).unwrap();
+        assert!(decrypted == storage);
+    }
+
+    fn golden_node_index() -> NodeIndex {
+        golden_full_storage()
+            .backups
+            .into_values()
+            .next()
+            .unwrap()
+            .0
+    }
+
+    /// The TLV encoding of a node index from the given records.
+    fn node_index_tlv(records: &[(u16, &[u8])]) -> Vec<u8> {
+        let mut tlv = vec![];
+        for (tlv_type, value) in records {
+            tlv.extend_from_slice(&tlv_type.to_be_bytes());
+            tlv.extend_from_slice(&(value.len() as LengthPrefix).to_be_bytes());
+            tlv.extend_from_slice(value);
+        }
+        let mut bytes = vec![NODE_INDEX_TLV_MARKER];
+        bytes.extend_from_slice(&(tlv.len() as LengthPrefix).to_be_bytes());
+        bytes.extend_from_slice(&tlv);
+        bytes
+    }
+
+    #[test]
+    fn test_node_index_getters() {
+        let node_index = golden_node_index();
+        assert_eq!(node_index.child_index(), 0);
+        assert_eq!(
+            node_index.lsp(),
+            Some("https://signet-lsp.mutinywallet.com")
+        );
+        assert!(!node_index.is_archived());
+
+        // older node indexes did not have the archived flag
+        let unflagged = NodeIndex {
+            archived: None,
+            lsp: None,
+            ..node_index
+        };
+        assert_eq!(unflagged.lsp(), None);
+        assert!(!unflagged.is_archived());
+    }
+
+    #[test]
+    fn test_node_index_tlv() {
+        // without the new fields the fixed layout is kept
+        let legacy = golden_node_index();
+        let bytes = legacy.encode();
+        assert_eq!(bytes[0], 0);
+        assert_eq!(NodeIndex::read(&mut Cursor::new(&bytes)).unwrap(), legacy);
+
+        let node_index = NodeIndex {
+            created_at: Some(1_690_000_000),
+            alias: Some("satoshi".to_string()),
+            ..legacy.clone()
+        };
+        let bytes = node_index.encode();
+        assert_eq!(bytes[0], NODE_INDEX_TLV_MARKER);
+        assert_eq!(
+            NodeIndex::read(&mut Cursor::new(&bytes)).unwrap(),
+            node_index
+        );
+        assert!(node_index.same_node(&legacy));
+
+        // the backup gets a version older wallets reject instead of misreading it
+        let mut storage = golden_full_storage();
+        for (n, _) in storage.backups.values_mut() {
+            *n = node_index.clone();
+        }
+        let encrypted = storage.encrypt(&golden_key());
+        let payload = encrypted
+            .decrypt_payload(&golden_key().secret_bytes())
+            .unwrap();
+        assert_eq!(payload[0], SCB_VERSION_TLV_NODE_INDEX);
+        assert!(encrypted.decrypt(&golden_key()).unwrap() == storage);
+
+        let encrypted = golden_full_storage().encrypt(&golden_key());
+        let payload = encrypted
+            .decrypt_payload(&golden_key().secret_bytes())
+            .unwrap();
+        assert_eq!(payload[0], SCB_VERSION_GZIP);
+    }
+
+    #[test]
+    fn test_node_index_tlv_cross_version() {
+        let legacy = golden_node_index();
+        let node_index = NodeIndex {
+            created_at: Some(1_690_000_000),
+            alias: Some("satoshi".to_string()),
+            ..legacy.clone()
+        };
+
+        // a version from before the new fields skips them
+        let old_types = [
+            NODE_INDEX_CHILD_INDEX_TYPE,
+            NODE_INDEX_ARCHIVED_TYPE,
+            NODE_INDEX_LSP_TYPE,
+        ];
+        let bytes = node_index.encode();
+        let read = NodeIndex::read_with_known_types(&mut Cursor::new(&bytes), &old_types);
+        assert_eq!(read.unwrap(), legacy);
+
+        // and so does this version for fields added later
+        let lsp = legacy.lsp.clone().unwrap();
+        let bytes = node_index_tlv(&[
+            (NODE_INDEX_CHILD_INDEX_TYPE, &0u32.to_be_bytes()),
+            (NODE_INDEX_ARCHIVED_TYPE, &[0]),
+            (NODE_INDEX_LSP_TYPE, lsp.as_bytes()),
+            (9, b"from the future"),
+        ]);
+        let mut reader = Cursor::new(&bytes);
+        assert_eq!(NodeIndex::read(&mut reader).unwrap(), legacy);
+        assert_eq!(reader.position() as usize, bytes.len());
+
+        // missing optional fields are read as none
+        let bytes = node_index_tlv(&[(NODE_INDEX_CHILD_INDEX_TYPE, &7u32.to_be_bytes())]);
+        let read = NodeIndex::read(&mut Cursor::new(&bytes)).unwrap();
+        assert_eq!(read.child_index, 7);
+        assert_eq!(read.lsp, None);
+        assert_eq!(read.archived, None);
+        assert_eq!(read.created_at, None);
+
+        // fields that have to be understood can not be skipped
+        let bytes = node_index_tlv(&[
+            (NODE_INDEX_CHILD_INDEX_TYPE, &0u32.to_be_bytes()),
+            (8, b"required"),
+        ]);
+        assert!(matches!(
+            NodeIndex::read(&mut Cursor::new(&bytes)),
+            Err(DecodeError::UnknownRequiredFeature)
+        ));
+
+        // the child index is required
+        let bytes = node_index_tlv(&[(NODE_INDEX_ARCHIVED_TYPE, &[0])]);
+        assert!(NodeIndex::read(&mut Cursor::new(&bytes)).is_err());
+
+        // records are in increasing type order
+        let bytes = node_index_tlv(&[
+            (NODE_INDEX_CHILD_INDEX_TYPE, &0u32.to_be_bytes()),
+            (NODE_INDEX_LSP_TYPE, lsp.as_bytes()),
+            (NODE_INDEX_ARCHIVED_TYPE, &[0]),
+        ]);
+        assert!(NodeIndex::read(&mut Cursor::new(&bytes)).is_err());
+
+        // a value longer than the records
+        let mut bytes = node_index_tlv(&[(NODE_INDEX_CHILD_INDEX_TYPE, &0u32.to_be_bytes())]);
+        bytes[1 + LENGTH_PREFIX_LEN + 2 + LENGTH_PREFIX_LEN - 1] = 5;
+        assert!(NodeIndex::read(&mut Cursor::new(&bytes)).is_err());
+
+        // the new fields survive a json backup, and old json backups read as none
+        let json = serde_json::to_string(&node_index).unwrap();
+        assert_eq!(
+            serde_json::from_str::<NodeIndex>(&json).unwrap(),
+            node_index
+        );
+        let json = serde_json::to_string(&legacy).unwrap();
+        assert!(!json.contains("created_at"));
+        assert_eq!(serde_json::from_str::<NodeIndex>(&json).unwrap(), legacy);
+    }
+
+    #[test]
+    fn test_streaming_encryption_matches_buffered() {
+        let key = golden_key().secret_bytes();
+
+        // the cipher matches the buffered encryption for every padding length,
+        // however the plaintext is split up between writes
+        for len in 0..(3 * AES_BLOCK_LEN) {
+            let plaintext: Vec<u8> = (0..len as u8).collect();
+            let expected = encrypt_payload(&plaintext, &key, GOLDEN_IV).encrypted_scb;
+            for chunk_size in [1, 5, AES_BLOCK_LEN, 40] {
+                let mut cipher = CbcWriter::new(&key, GOLDEN_IV);
+                for chunk in plaintext.chunks(chunk_size) {
+                    std::io::Write::write_all(&mut cipher, chunk).unwrap();
+                }
+                assert_eq!(cipher.finish(), expected);
+            }
+        }
+
+        // the backups are byte identical to compressing and encrypting the encoding
+        let mut full = golden_full_storage();
+        full.created_at = 1_690_000_000;
+        full.generation = 2;
+        full.network = Some(Network::Signet);
+        let storages = [
+            StaticChannelBackupStorage::default(),
+            golden_full_storage(),
+            full,
+        ];
+        for storage in storages {
+            let mut buffered =
+                encrypt_payload(&compress_payload(&storage.encode()), &key, GOLDEN_IV);
+            buffered.network = storage.network;
+            buffered.fingerprint = Some(storage.fingerprint());
+            let buffered = buffered.with_mac(&key);
+
+            let encrypted = storage.encrypt_with_iv(&golden_key(), GOLDEN_IV);
+            assert_eq!(encrypted, buffered);
+            assert_eq!(encrypted.to_string(), buffered.to_string());
+            assert!(encrypted.decrypt(&golden_key()).unwrap() == storage);
+        }
+    }
+
+    #[test]
+    fn test_estimated_encrypted_len() {
+        let mut storage = golden_full_storage();
+        let encrypted = storage.encrypt(&golden_key());
+        assert_eq!(encrypted.encoded_len(), encrypted.to_string().len());
+        assert_eq!(
+            storage.estimated_encrypted_len(),
+            encrypted.to_string().len()
+        );
+
+        // random monitors don't compress, so the backup no longer fits a single QR
+        let (_, backup) = storage.backups.values_mut().next().unwrap();
+        for vout in 0..4 {
+            let mut monitor = vec![0u8; 1_000 + vout as usize];
+            getrandom::getrandom(&mut monitor).expect("Failed to generate entropy");
+            let outpoint = OutPoint {
+                txid: bitcoin::Txid::all_zeros(),
+                vout,
+            };
+            backup.monitors.insert(outpoint, monitor);
+        }
+        assert_eq!(backup.monitor_count(), 5);
+        assert_eq!(backup.largest_monitor_len(), 1_003);
+        assert_eq!(StaticChannelBackup::default().largest_monitor_len(), 0);
+
+        let estimate = storage.estimated_encrypted_len();
+        let encrypted = storage.encrypt(&golden_key());
+        assert_eq!(estimate, encrypted.to_string().len());
+        assert!(estimate > SCB_SINGLE_QR_MAX_LEN);
+
+        // the account and padding are not included
+        let with_account = storage.encrypt_for(&golden_key(), Some(1), true);
+        assert_eq!(with_account.encoded_len(), with_account.to_string().len());
+        // the 4 bytes of the account are at most 7 characters
+        assert!(with_account.encoded_len() - estimate <= 7);
+        let padded = encrypted.pad_to_bucket(&DEFAULT_SCB_PADDING_BUCKETS);
+        assert_eq!(padded.encoded_len(), padded.to_string().len());
+        assert!(padded.encoded_len() > estimate);
+    }
+
+    #[test]
+    fn test_encrypted_scb_fingerprint() {
+        let storage = golden_full_storage();
+        let first = storage.encrypt(&golden_key());
+        let second = storage.encrypt(&golden_key());
+        assert_ne!(first.iv, second.iv);
+        assert_ne!(first.to_string(), second.to_string());
+
+        // two encryptions of the same data share a fingerprint
+        let fingerprint = first.fingerprint().unwrap();
+        assert_eq!(second.fingerprint(), Some(fingerprint));
+        assert_eq!(storage.fingerprint(), fingerprint);
+        assert_eq!(
+            ScbFingerprint::from(&storage).fingerprint,
+            storage.fingerprint()
+        );
+        assert_eq!(
+            sha256::Hash::hash(&storage.encode())[..SCB_FINGERPRINT_LEN],
+            fingerprint
+        );
+
+        // it survives the round trip and can be read without the key
+        let parsed = EncryptedSCB::parse(&first.to_string()).unwrap();
+        assert_eq!(parsed, first);
+        let hex = inspect_scb_metadata(&first.to_string())
+            .unwrap()
+            .fingerprint
+            .unwrap();
+        assert_eq!(hex.len(), SCB_FINGERPRINT_LEN * 2);
+        assert_eq!(first.fingerprint_hex(), Some(hex));
+        let with_passphrase = storage.encrypt_with_passphrase("hunter2");
+        assert_eq!(with_passphrase.fingerprint(), Some(fingerprint));
+
+        // different data differs
+        let mut other = storage.clone();
+        other.generation += 1;
+        assert_ne!(
+            other.encrypt(&golden_key()).fingerprint(),
+            Some(fingerprint)
+        );
+
+        // older backups don't have one
+        let legacy = encrypt_payload(&storage.encode(), &golden_key().secret_bytes(), GOLDEN_IV);
+        assert_eq!(legacy.fingerprint(), None);
+        let parsed = EncryptedSCB::parse(&legacy.to_string()).unwrap();
+        assert_eq!(parsed.fingerprint(), None);
+        assert_eq!(
+            inspect_scb_metadata(&legacy.to_string())
+                .unwrap()
+                .fingerprint,
+            None
+        );
+    }
+
+    #[test]
+    fn test_read_random_bytes_does_not_panic() {
+        for len in 0..256 {
+            let mut bytes = vec![0u8; len];
+            getrandom::getrandom(&mut bytes).expect("Failed to generate entropy");
+
+            // random data should never decode, but it must not panic either
+            let _ = StaticChannelBackup::read(&mut Cursor::new(&bytes));
+            let _ = StaticChannelBackupStorage::read(&mut Cursor::new(&bytes));
+            let _ = EncryptedSCB::read(&mut Cursor::new(&bytes));
+            let _ = decompress_payload(bytes.clone());
+
+            // claim a huge length followed by random data
+            let mut huge = LengthPrefix::MAX.to_be_bytes().to_vec();
+            huge.extend_from_slice(&bytes);
+            assert!(EncryptedSCB::read(&mut Cursor::new(&huge)).is_err());
+        }
+    }
+
+    #[test]
+    fn test_read_truncated_bytes_does_not_panic() {
+        let storage = golden_full_storage();
+        let bytes = storage.encode();
+
+        for len in 0..bytes.len() {
+            let truncated = &bytes[..len];
+            assert!(StaticChannelBackupStorage::read(&mut Cursor::new(truncated)).is_err());
+        }
+
+        // an invalid public key should be an error
+        let mut invalid_pk = bytes.clone();
+        invalid_pk[LENGTH_PREFIX_LEN] = 0xff;
+        assert!(StaticChannelBackupStorage::read(&mut Cursor::new(&invalid_pk)).is_err());
+
+        // a truncated backup that is still validly encrypted should fail to decrypt
+        let encrypted = encrypt_payload(
+            &bytes[..bytes.len() / 2],
+            &golden_key().secret_bytes(),
+            GOLDEN_IV,
+        );
+        assert!(encrypted.decrypt(&golden_key()).is_err());
+
+        // a truncated encrypted backup should fail to read
+        let encoded = storage.encrypt(&golden_key()).encode();
+        for len in 0..encoded.len() {
+            assert!(EncryptedSCB::read(&mut Cursor::new(&encoded[..len])).is_err());
+        }
+    }
+
+    #[test]
+    fn test_pad_to_bucket() {
+        let encryption_key = SecretKey::from_slice(&[7; 32]).unwrap();
+        let storage = golden_full_storage();
+        let encrypted = storage.encrypt(&encryption_key);
+        let len = encrypted.encode().len() as u32;
+
+        // padded to the smallest bucket that fits
+        let buckets = [len * 4, len + 100, len / 2];
+        let padded = encrypted.clone().pad_to_bucket(&buckets);
+        assert_eq!(padded.encode().len() as u32, len + 100);
+        let other = encrypted.clone().pad_to_bucket(&buckets);
+        assert_ne!(padded.padding, other.padding);
+        assert_eq!(padded.encode().len(), other.encode().len());
+
+        // or a multiple of the largest
+        let padded_large = encrypted.clone().pad_to_bucket(&[len / 3]);
+        assert_eq!(padded_large.encode().len() as u32 % (len / 3), 0);
+
+        // no buckets, no padding
+        assert_eq!(encrypted.clone().pad_to_bucket(&[]), encrypted);
+
+        // the padding survives encoding and is ignored on decrypt
+        let parsed = EncryptedSCB::from_str(&padded.to_string()).unwrap();
+        assert_eq!(parsed, padded);
+        assert_eq!(
+            inspect_scb_metadata(&padded.to_string())
+                .unwrap()
+                .padding_len,
+            padded.padding.as_ref().unwrap().len()
+        );
+        assert!(parsed.decrypt(&encryption_key).unwrap() == storage);
+
+        // re-padding replaces the previous padding
+        assert_eq!(parsed.pad_to_bucket(&[]), encrypted);
+    }
+
+    #[test]
+    fn test_auto_backup_jitter() {
+        let mut debouncer = AutoBackupDebouncer::new(0, 30, None);
+        assert!(!debouncer.should_backup(100));
+
+        debouncer.mark_changed(100);
+        let ready_at = debouncer.ready_at;
+        assert!((100..=130).contains(&ready_at));
+        assert!(!debouncer.should_backup(99));
+
+        // a later change is batched into the same backup
+        debouncer.mark_changed(ready_at);
+        assert_eq!(debouncer.ready_at, ready_at);
+        assert!(debouncer.should_backup(ready_at));
+
+        debouncer.backed_up(ready_at);
+        assert!(!debouncer.should_backup(ready_at + 100));
+    }
+
+    #[test]
+    fn test_read_adversarial_lengths() {
+        // every length and count in a valid backup replaced by a huge one
+        let bytes = golden_full_storage().encode();
+        for i in 0..bytes.len() - LENGTH_PREFIX_LEN {
+            let mut adversarial = bytes.clone();
+            adversarial[i..i + LENGTH_PREFIX_LEN].copy_from_slice(&LengthPrefix::MAX.to_be_bytes());
+            let _ = StaticChannelBackupStorage::read(&mut Cursor::new(&adversarial));
+        }
+
+        fn invalid<T>(res: Result<T, DecodeError>) -> bool {
+            matches!(res, Err(DecodeError::InvalidValue))
+        }
+        let huge = LengthPrefix::MAX.to_be_bytes();
+
+        // counts
+        assert!(invalid(StaticChannelBackupStorage::read(&mut Cursor::new(
+            &huge
+        ))));
+        assert!(invalid(StaticChannelBackup::read(&mut Cursor::new(&huge))));
+        let mut diff = vec![0u8; 2 * SCB_GENERATION_LEN + NETWORK_MAGIC_LEN];
+        diff.extend_from_slice(&huge);
+        assert!(invalid(ScbDiff::read(&mut Cursor::new(&diff))));
+
+        // a monitor that is too long, and one that claims more than there is
+        let monitor = |len: LengthPrefix| {
+            let mut bytes = 1u32.to_be_bytes().to_vec();
+            bytes.extend_from_slice(&[0u8; TXID_LEN + VOUT_LEN]);
+            bytes.extend_from_slice(&len.to_be_bytes());
+            bytes.extend_from_slice(&[0u8; 10]);
+            bytes
+        };
+        let too_long = monitor(MAX_MONITOR_LEN + 1);
+        assert!(invalid(StaticChannelBackup::read(&mut Cursor::new(
+            &too_long
+        ))));
+        let short = monitor(MAX_MONITOR_LEN);
+        assert!(matches!(
+            StaticChannelBackup::read(&mut Cursor::new(&short)),
+            Err(DecodeError::ShortRead)
+        ));
+
+        // a connection string that is too long
+        let (pubkey, _) = golden_full_storage()
+            .peer_connections
+            .into_iter()
+            .next()
+            .unwrap();
+        let mut peer = 0u32.to_be_bytes().to_vec();
+        peer.extend_from_slice(&1u32.to_be_bytes());
+        peer.extend_from_slice(&pubkey.serialize());
+        peer.extend_from_slice(&(MAX_STRING_LEN + 1).to_be_bytes());
+        peer.extend_from_slice(&vec![b'a'; MAX_STRING_LEN as usize + 1]);
+        assert!(invalid(StaticChannelBackupStorage::read(&mut Cursor::new(
+            &peer
+        ))));
+
+        // an lsp url that is too long
+        let mut node_index = vec![0u8; 5];
+        node_index.extend_from_slice(&huge);
+        assert!(invalid(NodeIndex::read(&mut Cursor::new(&node_index))));
+
+        // a ciphertext that is too long
+        let mut encrypted = vec![SCB_MAC_FLAG];
+        encrypted.extend_from_slice(&huge);
+        assert!(invalid(EncryptedSCB::read(&mut Cursor::new(&encrypted))));
+
+        // a connection string that could not be read back is not stored
+        let mut storage = golden_full_storage();
+        let long = format!("{pubkey}@{}:9735", "a".repeat(MAX_STRING_LEN as usize));
+        assert!(storage.set_connection_string(pubkey, long).is_err());
+    }
+
+    #[test]
+    fn test_preview_scb() {
+        let storage = golden_full_storage();
+        let scb = storage.encrypt(&golden_key()).to_string();
+
+        let summary = preview_scb(&scb, &golden_key()).unwrap();
+        assert_eq!(summary.node_count(), 1);
+        let node = &summary.nodes[0];
+        assert_eq!(node.child_index, 0);
+        assert_eq!(
+            node.lsp,
+            Some("https://signet-lsp.mutinywallet.com".to_string())
+        );
+        assert_eq!(
+            node.channels,
+            vec![OutPoint {
+                txid: bitcoin::Txid::from_hex(
+                    "830b1c110ef6c78312a8f4c798da0bfbacdfc9c80c7d458ca614e7b1543f5b03",
+                )
+                .unwrap(),
+                vout: 1,
+            }]
+        );
+        assert_eq!(
+            summary.peer_connections,
+            vec![
+                "02cae09cf2c8842ace44068a5bf3117a494ebbf69a99e79712483c36f97cdb7b54@192.168.0.1:9735"
+                    .to_string()
+            ]
+        );
+
+        // wrong key
+        let wrong_key = SecretKey::from_slice(&[3u8; 32]).unwrap();
+        assert!(preview_scb(&scb, &wrong_key).is_err());
+
+        // corrupt string
+        let mut corrupt = scb.clone();
+        corrupt.truncate(scb.len() - 10);
+        assert!(preview_scb(&corrupt, &golden_key()).is_err());
+        assert!(preview_scb("not a backup", &golden_key()).is_err());
+    }
+
+    #[test]
+    fn test_scb_account() {
+        // the default key keeps the path of older backups
+        assert_eq!(
+            scb_key_derivation_path(None).unwrap().to_string(),
+            SCB_ENCRYPTION_KEY_DERIVATION_PATH
+        );
+        assert_eq!(
+            scb_key_derivation_path(Some(1)).unwrap().to_string(),
+            "m/444'/444'/444'/1'"
+        );
+        assert!(scb_key_derivation_path(Some(1 << 31)).is_err());
+
+        // the account survives the bech32 round trip and shows in the metadata
+        let encrypted = golden_full_storage().encrypt_for(&golden_key(), Some(1), true);
+        let scb = encrypted.to_string();
+        let decoded = EncryptedSCB::parse(&scb).unwrap();
+        assert_eq!(decoded, encrypted);
+        assert_eq!(decoded.account(), Some(1));
+        assert_eq!(inspect_scb_metadata(&scb).unwrap().account, Some(1));
+        assert!(decoded.decrypt(&golden_key()).unwrap() == golden_full_storage());
+
+        let scb = golden_full_storage().encrypt(&golden_key()).to_string();
+        assert_eq!(EncryptedSCB::parse(&scb).unwrap().account(), None);
+
+        // accounts that can not be derived are rejected
+        let mut bytes = vec![SCB_ACCOUNT_FLAG];
+        bytes.extend_from_slice(&(1u32 << 31).to_be_bytes());
+        assert!(matches!(
+            EncryptedSCB::read(&mut Cursor::new(&bytes)),
+            Err(DecodeError::InvalidValue)
+        ));
+    }
+
+    #[test]
+    fn test_passphrase_encrypted_static_channel_backup_storage() {
+        let storage = golden_full_storage();
+        let kdf = PassphraseKdf {
+            memory_kib: SCB_PASSPHRASE_KDF_MEMORY_KIB,
+            passes: SCB_PASSPHRASE_KDF_PASSES,
+            salt: [4u8; PASSPHRASE_SALT_LEN],
+        };
+
+        let encrypted = storage.encrypt_with_kdf("hunter2", kdf, GOLDEN_IV);
+        assert!(encrypted.is_passphrase_encrypted());
+
+        // the mode survives the bech32 round trip
+        let decoded = EncryptedSCB::from_str(&encrypted.to_string()).unwrap();
+        assert_eq!(decoded, encrypted);
+        assert_ne!(decoded.encode()[0] & SCB_PASSPHRASE_MODE, 0);
+
+        let decrypted = decoded.decrypt_with_passphrase("hunter2").unwrap();
+        assert!(decrypted == storage);
+
+        // wrong passphrase
+        assert!(decoded.decrypt_with_passphrase("hunter3").is_err());
+
+        // the seed key can not decrypt a passphrase backup
+        assert!(matches!(
+            decoded.decrypt(&golden_key()),
+            Err(MutinyError::PassphraseRequired)
+        ));
+
+        // and a passphrase can not decrypt a seed backup
+        let seed_encrypted = storage.encrypt(&golden_key());
+        assert!(!seed_encrypted.is_passphrase_encrypted());
+        assert!(seed_encrypted.decrypt_with_passphrase("hunter2").is_err());
+    }
+
+    #[test]
+    fn test_passphrase_kdf_costs_are_bounded() {
+        let encrypted = golden_full_storage().encrypt_with_kdf(
+            "hunter2",
+            PassphraseKdf {
+                memory_kib: SCB_PASSPHRASE_KDF_MEMORY_KIB,
+                passes: SCB_PASSPHRASE_KDF_PASSES,
+                salt: [4u8; PASSPHRASE_SALT_LEN],
+            },
+            GOLDEN_IV,
+        );
+
+        let with_costs = |memory_kib: u32, passes: u32| {
+            let mut encrypted = encrypted.clone();
+            encrypted.kdf = Some(PassphraseKdf {
+                memory_kib,
+                passes,
+                salt: [4u8; PASSPHRASE_SALT_LEN],
+            });
+            EncryptedSCB::read(&mut Cursor::new(encrypted.encode()))
+        };
+
+        // the bounds themselves are accepted
+        assert!(with_costs(SCB_PASSPHRASE_KDF_MEMORY_KIB, SCB_PASSPHRASE_KDF_PASSES).is_ok());
+        assert!(with_costs(
+            SCB_PASSPHRASE_KDF_MAX_MEMORY_KIB,
+            SCB_PASSPHRASE_KDF_MAX_PASSES
+        )
+        .is_ok());
+
+        // costs that are too weak, that argon2 rejects, or that would hang the wallet
+        for (memory_kib, passes) in [
+            (0, SCB_PASSPHRASE_KDF_PASSES),
+            (SCB_PASSPHRASE_KDF_MEMORY_KIB - 1, SCB_PASSPHRASE_KDF_PASSES),
+            (
+                SCB_PASSPHRASE_KDF_MAX_MEMORY_KIB + 1,
+                SCB_PASSPHRASE_KDF_PASSES,
+            ),
+            (u32::MAX, SCB_PASSPHRASE_KDF_PASSES),
+            (SCB_PASSPHRASE_KDF_MEMORY_KIB, 0),
+            (SCB_PASSPHRASE_KDF_MEMORY_KIB, SCB_PASSPHRASE_KDF_PASSES - 1),
+            (
+                SCB_PASSPHRASE_KDF_MEMORY_KIB,
+                SCB_PASSPHRASE_KDF_MAX_PASSES + 1,
+            ),
+            (SCB_PASSPHRASE_KDF_MEMORY_KIB, u32::MAX),
+        ] {
+            assert!(
+                matches!(
+                    with_costs(memory_kib, passes),
+                    Err(DecodeError::InvalidValue)
+                ),
+                "{memory_kib} KiB and {passes} passes should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn test_rotate_static_channel_backup_key() {
+        let storage = golden_full_storage();
+        let old_key = golden_key();
+        let new_key = SecretKey::from_slice(&[5u8; 32]).unwrap();
+
+        let encrypted = storage.encrypt(&old_key);
+        let rotated = encrypted.rotate_key(&old_key, &new_key).unwrap();
+        assert_ne!(rotated.iv, encrypted.iv);
+
+        // only the new key can decrypt the rotated backup
+        assert!(rotated.decrypt(&old_key).is_err());
+        assert!(rotated.decrypt(&new_key).unwrap() == storage);
+
+        // rotating again uses a fresh iv
+        let rotated_again = rotated.rotate_key(&new_key, &old_key).unwrap();
+        assert_ne!(rotated_again.iv, rotated.iv);
+        assert!(rotated_again.decrypt(&old_key).unwrap() == storage);
+
+        // rotating with the wrong old key fails
+        assert!(encrypted.rotate_key(&new_key, &old_key).is_err());
+    }
+
+    #[test]
+    fn test_verify_static_channel_backup() {
+        let storage = golden_full_storage();
+        let encrypted = storage.encrypt(&golden_key());
+        assert!(encrypted.mac.is_some());
+
+        let fingerprint = encrypted.verify(&golden_key()).unwrap();
+        assert_eq!(fingerprint.node_count, 1);
+        assert_eq!(fingerprint.monitor_count, 1);
+        assert_eq!(
+            fingerprint.fingerprint[..],
+            sha256::Hash::hash(&storage.encode())[..SCB_FINGERPRINT_LEN]
+        );
+
+        // the fingerprint does not depend on the iv
+        let encrypted_again = storage.encrypt(&golden_key());
+        assert_eq!(encrypted_again.verify(&golden_key()).unwrap(), fingerprint);
+
+        // a bit flipped ciphertext fails the mac check
+        let mut flipped = encrypted.clone();
+        flipped.encrypted_scb[0] ^= 1;
+        assert!(flipped
+            .decrypt_payload(&golden_key().secret_bytes())
+            .is_none());
+        assert!(flipped.verify(&golden_key()).is_err());
+
+        // wrong key
+        let wrong_key = SecretKey::from_slice(&[3u8; 32]).unwrap();
+        assert!(encrypted.verify(&wrong_key).is_err());
+
+        // the mac survives the bech32 round trip
+        let decoded = EncryptedSCB::from_str(&encrypted.to_string()).unwrap();
+        assert_eq!(decoded, encrypted);
+    }
+
+    #[test]
+    fn test_stripped_mac_is_rejected() {
+        let mac = [7u8; SCB_MAC_LEN];
+        assert!(mac_eq(&mac, &mac));
+        let mut other = mac;
+        other[SCB_MAC_LEN - 1] ^= 1;
+        assert!(!mac_eq(&mac, &other));
+
+        let storage = golden_full_storage();
+        let key = golden_key();
+
+        // clearing the mac flag of a flagged backup does not skip the mac check
+        let encrypted = storage.encrypt(&key);
+        let mut stripped = encrypted.clone();
+        stripped.mac = None;
+        assert!(EncryptedSCB::read(&mut Cursor::new(stripped.encode())).is_err());
+        assert!(EncryptedSCB::from_str(&stripped.to_string()).is_err());
+
+        // neither does stripping all flags to make it look like a legacy backup
+        stripped.fingerprint = None;
+        let parsed = EncryptedSCB::from_str(&stripped.to_string()).unwrap();
+        assert!(parsed.decrypt_payload(&key.secret_bytes()).is_none());
+        assert!(parsed.decrypt(&key).is_err());
+
+        let passphrase = storage.encrypt_with_passphrase("correct horse");
+        let mut stripped = passphrase.clone();
+        stripped.mac = None;
+        assert!(EncryptedSCB::from_str(&stripped.to_string()).is_err());
+        assert!(stripped.decrypt_with_passphrase("correct horse").is_err());
+
+        // legacy backups predate the mac and are still readable
+        let legacy = encrypt_payload(&storage.encode(), &key.secret_bytes(), GOLDEN_IV);
+        let parsed = EncryptedSCB::from_str(&legacy.to_string()).unwrap();
+        assert!(parsed.decrypt(&key).unwrap() == storage);
+    }
+
+    #[test]
+    fn test_mac_covers_header() {
+        let mut storage = golden_full_storage();
+        storage.network = Some(Network::Signet);
+        let key = golden_key();
+        let encrypted = storage.encrypt_for(&key, Some(1), true);
+        assert!(encrypted.decrypt(&key).unwrap() == storage);
+
+        // every cleartext field is authenticated
+        let mut tampered = [
+            encrypted.clone(),
+            encrypted.clone(),
+            encrypted.clone(),
+            encrypted.clone(),
+            encrypted.clone(),
+        ];
+        tampered[0].network = Some(Network::Testnet);
+        tampered[1].account = Some(2);
+        tampered[2].account = None;
+        tampered[3].fingerprint.as_mut().unwrap()[0] ^= 1;
+        tampered[4].fingerprint = None;
+        for tampered in tampered {
+            let parsed = EncryptedSCB::from_str(&tampered.to_string()).unwrap();
+            assert!(parsed.decrypt_payload(&key.secret_bytes()).is_none());
+            assert!(parsed.decrypt(&key).is_err());
+        }
+
+        // and so is the key derivation of a passphrase backup
+        let kdf = PassphraseKdf {
+            memory_kib: SCB_PASSPHRASE_KDF_MEMORY_KIB,
+            passes: SCB_PASSPHRASE_KDF_PASSES,
+            salt: [4u8; PASSPHRASE_SALT_LEN],
+        };
+        let passphrase = storage.encrypt_with_kdf("hunter2", kdf, GOLDEN_IV);
+        let mut tampered = passphrase.clone();
+        tampered.kdf.as_mut().unwrap().passes += 1;
+        assert!(tampered
+            .decrypt_payload(&kdf.derive_key("hunter2"))
+            .is_none());
+
+        // the padding is added after the mac and is not
+        let padded = encrypted.clone().pad_to_bucket(&[4 * 1024]);
+        let parsed = EncryptedSCB::from_str(&padded.to_string()).unwrap();
+        assert!(parsed.decrypt(&key).unwrap() == storage);
+        let unpadded = parsed.pad_to_bucket(&[]);
+        assert_eq!(unpadded.mac, encrypted.mac);
+
+        // a backup without a fingerprint can't be linked to other copies
+        let unlinked = storage.encrypt_for(&key, None, false);
+        assert_eq!(unlinked.fingerprint(), None);
+        let parsed = EncryptedSCB::from_str(&unlinked.to_string()).unwrap();
+        assert_eq!(parsed.encode()[0] & SCB_FINGERPRINT_FLAG, 0);
+        assert!(parsed.decrypt(&key).unwrap() == storage);
+    }
+
+    #[test]
+    fn test_static_channel_backup_generation() {
+        let legacy = golden_full_storage();
+        assert_eq!(legacy.generation_info(), ScbGeneration::default());
+
+        let mut first = golden_full_storage();
+        first.created_at = 1_690_000_000;
+        first.generation = 1;
+        let mut second = first.clone();
+        second.created_at = 1_690_000_100;
+        second.generation = 2;
+
+        assert!(second.is_newer_than(&first));
+        assert!(!first.is_newer_than(&second));
+        assert!(!first.is_newer_than(&first));
+        assert!(first.is_newer_than(&legacy));
+        assert!(!legacy.is_newer_than(&first));
+
+        // the generation wins over a skewed clock
+        let mut skewed = second.clone();
+        skewed.created_at = 0;
+        assert!(skewed.is_newer_than(&first));
+
+        // the generation round trips, and legacy encodings are unchanged
+        let bytes = second.encode();
+        assert_eq!(bytes.len(), legacy.encode().len() + SCB_GENERATION_LEN);
+        let decoded = StaticChannelBackupStorage::read(&mut Cursor::new(&bytes)).unwrap();
+        assert!(decoded == second);
+
+        let decoded = StaticChannelBackupStorage::read(&mut Cursor::new(&legacy.encode())).unwrap();
+        assert_eq!(decoded.generation, 0);
+        assert_eq!(decoded.created_at, 0);
+
+        // a partial generation is an error
+        let truncated = &bytes[..bytes.len() - 1];
+        assert!(StaticChannelBackupStorage::read(&mut Cursor::new(truncated)).is_err());
+
+        let decrypted = second
+            .encrypt(&golden_key())
+            .decrypt(&golden_key())
+            .unwrap();
+        assert_eq!(decrypted.generation_info(), second.generation_info());
+    }
+
+    #[test]
+    fn test_select_nodes_static_channel_backup_storage() {
+        let storage = golden_full_storage();
+        let (pubkey, (node_index, backup)) = storage.backups.iter().next().unwrap();
+
+        // add an archived node that the user does not want to recover
+        let archived_pk = PublicKey::from_str(
+            "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5",
+        )
+        .unwrap();
+        let mut full = storage.clone();
+        full.backups.insert(
+            archived_pk,
+            (
+                NodeIndex {
+                    child_index: 1,
+                    lsp: None,
+                    archived: Some(true),
+                    created_at: None,
+                    alias: None,
+                },
+                StaticChannelBackup::default(),
+            ),
+        );
+        assert_eq!(full.backups.len(), 2);
+
+        let summary = ScbSummary::from(&full);
+        assert!(!summary.nodes[0].archived);
+        assert!(summary.nodes[1].archived);
+
+        let selected = full.select_nodes(&[*pubkey]).unwrap();
+        assert_eq!(selected.backups.len(), 1);
+        assert!(selected.backups.get(&archived_pk).is_none());
+        assert!(selected.backups.get(pubkey) == Some(&(node_index.clone(), backup.clone())));
+        assert_eq!(selected.peer_connections, full.peer_connections);
+        assert_eq!(selected.generation_info(), full.generation_info());
+
+        assert!(full.select_nodes(&[]).unwrap().backups.is_empty());
+
+        // selecting a node that is not in the backup is an error
+        assert!(storage.select_nodes(&[archived_pk]).is_err());
+    }
+
+    #[test]
+    fn test_static_channel_backup_storage_json() {
+        let mut storage = golden_full_storage();
+        storage.created_at = 1_690_000_000;
+        storage.generation = 3;
+        storage.network = Some(Network::Signet);
+
+        let json = storage.to_json().unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let pubkey = "02cae09cf2c8842ace44068a5bf3117a494ebbf69a99e79712483c36f97cdb7b54";
+        let outpoint = "830b1c110ef6c78312a8f4c798da0bfbacdfc9c80c7d458ca614e7b1543f5b03:1";
+        let monitor = CHAIN_MONITOR_BYTES.to_vec().to_hex();
+        assert_eq!(
+            value["backups"][pubkey]["backup"]["monitors"][outpoint],
+            monitor
+        );
+        assert_eq!(value["network"], "signet");
+
+        let imported = StaticChannelBackupStorage::from_json(&json).unwrap();
+        assert!(imported == storage);
+        assert_eq!(imported.encode(), storage.encode());
+
+        let legacy = golden_full_storage();
+        let imported = StaticChannelBackupStorage::from_json(&legacy.to_json().unwrap()).unwrap();
+        assert_eq!(imported.encode(), legacy.encode());
+
+        // invalid values are errors, not panics
+        let invalid = [
+            // odd length monitor hex
+            json.replace(&monitor, "abc"),
+            // not hex
+            json.replace(&monitor, "zz"),
+            // missing vout
+            json.replace(outpoint, &outpoint[..64]),
+            // short txid
+            json.replace(outpoint, &outpoint[2..]),
+            // invalid pubkey
+            json.replace(pubkey, &pubkey[..64]),
+        ];
+        for json in invalid {
+            assert!(matches!(
+                StaticChannelBackupStorage::from_json(&json),
+                Err(MutinyError::InvalidArgumentsError)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_static_channel_backup_peer_addresses() {
+        let legacy = golden_full_storage();
+        let (pubkey, connections) = legacy.peer_connections.iter().next().unwrap();
+        let pubkey = *pubkey;
+        let connection_str = connections[0].clone();
+
+        // the old format has a single address per peer
+        let legacy_bytes =
+            Vec::<u8>::from_hex(include_str!("test_vectors/full_storage.hex").trim()).unwrap();
+        let decoded = StaticChannelBackupStorage::read(&mut Cursor::new(&legacy_bytes)).unwrap();
+        assert_eq!(
+            decoded.peer_connections[&pubkey],
+            vec![connection_str.clone()]
+        );
+        assert_eq!(legacy.encode(), legacy_bytes);
+
+        let addresses = vec![
+            connection_str.clone(),
+            format!("{pubkey}@[2001:db8::1]:9735"),
+            format!("{pubkey}@abcdefghijklmnopqrstuvwxyzabcdefghijklmnopqrstuvwxyz234.onion:9735"),
+        ];
+        let mut storage = golden_full_storage();
+        storage.peer_connections.insert(pubkey, addresses.clone());
+
+        // without a network the extra addresses still round trip
+        let bytes = storage.encode();
+        let decoded = StaticChannelBackupStorage::read(&mut Cursor::new(&bytes)).unwrap();
+        assert_eq!(decoded.network, None);
+        assert_eq!(decoded.peer_connections[&pubkey], addresses);
+        assert!(decoded == storage);
+
+        storage.network = Some(Network::Signet);
+        storage.generation = 1;
+        let decoded =
+            StaticChannelBackupStorage::read(&mut Cursor::new(&storage.encode())).unwrap();
+        assert!(decoded == storage);
+
+        let decrypted = storage
+            .encrypt(&golden_key())
+            .decrypt(&golden_key())
+            .unwrap();
+        assert_eq!(decrypted.peer_connections[&pubkey], addresses);
+        let mut summary_addresses = addresses.clone();
+        summary_addresses.sort();
+        assert_eq!(
+            ScbSummary::from(&decrypted).peer_connections,
+            summary_addresses
+        );
+
+        // the json supports multiple addresses, and reads older single strings
+        let json = storage.to_json().unwrap();
+        assert!(StaticChannelBackupStorage::from_json(&json).unwrap() == storage);
+        let mut single: serde_json::Value =
+            serde_json::from_str(&legacy.to_json().unwrap()).unwrap();
+        single["peer_connections"][pubkey.to_string()] = connection_str.clone().into();
+        let single = StaticChannelBackupStorage::from_json(&single.to_string()).unwrap();
+        assert!(single == legacy);
+
+        // extra addresses for a peer without a first address are invalid
+        let mut invalid = storage.encode();
+        let other = PublicKey::from_str(
+            "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5",
+        )
+        .unwrap();
+        // the public key is followed by the address count and the two extra addresses
+        let at = invalid.len()
+            - PUBKEY_LEN
+            - LENGTH_PREFIX_LEN
+            - (LENGTH_PREFIX_LEN + addresses[1].len())
+            - (LENGTH_PREFIX_LEN + addresses[2].len());
+        invalid[at..at + PUBKEY_LEN].copy_from_slice(&other.serialize());
+        assert!(StaticChannelBackupStorage::read(&mut Cursor::new(&invalid)).is_err());
+    }
+
+    #[test]
+    fn test_static_channel_backup_peer_last_connected() {
+        let legacy = golden_full_storage();
+        let pubkey = *legacy.peer_connections.keys().next().unwrap();
+
+        // older backups and exports have no timestamps
+        let legacy_bytes =
+            Vec::<u8>::from_hex(include_str!("test_vectors/full_storage.hex").trim()).unwrap();
+        let decoded = StaticChannelBackupStorage::read(&mut Cursor::new(&legacy_bytes)).unwrap();
+        assert!(decoded.peer_last_connected.is_empty());
+        let imported = StaticChannelBackupStorage::from_json(&legacy.to_json().unwrap()).unwrap();
+        assert!(imported.peer_last_connected.is_empty());
+        let summary = ScbSummary::from(&decoded);
+        assert_eq!(summary.peers.len(), 1);
+        assert_eq!(summary.peers[0].last_connected, None);
+
+        let mut storage = golden_full_storage();
+        storage.peer_last_connected.insert(pubkey, 1_690_000_000);
+        let golden =
+            Vec::<u8>::from_hex(include_str!("test_vectors/last_connected_storage.hex").trim())
+                .unwrap();
+        assert_eq!(storage.encode(), golden);
+        let decoded = StaticChannelBackupStorage::read(&mut Cursor::new(&golden)).unwrap();
+        assert_eq!(decoded.peer_last_connected[&pubkey], 1_690_000_000);
+        assert!(decoded == storage);
+
+        // the timestamps follow the legacy encoding and the empty fields 5 to 8
+        let (head, tail) = golden.split_at(legacy_bytes.len());
+        assert_eq!(head, legacy_bytes);
+        let mut expected = vec![0u8; SCB_GENERATION_LEN + NETWORK_MAGIC_LEN];
+        expected.extend_from_slice(&[0u8; 3 * LENGTH_PREFIX_LEN]);
+        let count: LengthPrefix = 1;
+        expected.extend_from_slice(&count.to_be_bytes());
+        expected.extend_from_slice(&pubkey.serialize());
+        expected.extend_from_slice(&1_690_000_000u64.to_be_bytes());
+        assert_eq!(tail, expected);
+        let json = storage.to_json().unwrap();
+        assert!(StaticChannelBackupStorage::from_json(&json).unwrap() == storage);
+
+        // the most recently connected peer is listed first
+        let other = PublicKey::from_str(
+            "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5",
+        )
+        .unwrap();
+        storage
+            .peer_connections
+            .insert(other, vec![format!("{other}@203.0.113.7:9735")]);
+        storage.peer_last_connected.insert(other, 1_700_000_000);
+        let peers: Vec<(PublicKey, Option<u64>)> = ScbSummary::from(&storage)
+            .peers
+            .iter()
+            .map(|p| (p.pubkey, p.last_connected))
+            .collect();
+        assert_eq!(
+            peers,
+            vec![(other, Some(1_700_000_000)), (pubkey, Some(1_690_000_000))]
+        );
+    }
+
+    #[test]
+    fn test_static_channel_backup_connection_string() {
+        let mut storage = golden_full_storage();
+        let (pubkey, connections) = storage.peer_connections.iter().next().unwrap();
+        let pubkey = *pubkey;
+        let old = connections[0].clone();
+        assert_eq!(storage.connection_string(&pubkey), Some(old.as_str()));
+
+        // the new address is tried first, the old one is kept as a fallback
+        let new = format!("{pubkey}@203.0.113.7:9736");
+        storage.set_connection_string(pubkey, new.clone()).unwrap();
+        assert_eq!(storage.connection_string(&pubkey), Some(new.as_str()));
+        assert_eq!(
+            storage.peer_connections[&pubkey],
+            vec![new.clone(), old.clone()]
+        );
+
+        // setting a known address moves it to the front
+        storage.set_connection_string(pubkey, old.clone()).unwrap();
+        assert_eq!(storage.peer_connections[&pubkey], vec![old, new]);
+
+        // a peer that was not in the backup
+        let secp = secp256k1::Secp256k1::new();
+        let other = PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&[3; 32]).unwrap());
+        assert_eq!(storage.connection_string(&other), None);
+        let ipv6 = format!("{other}@[2001:db8::1]:9735");
+        storage.set_connection_string(other, ipv6.clone()).unwrap();
+        assert_eq!(storage.connection_string(&other), Some(ipv6.as_str()));
+    }
+
+    #[test]
+    fn test_static_channel_backup_malformed_connection_string() {
+        let mut storage = golden_full_storage();
+        let pubkey = *storage.peer_connections.keys().next().unwrap();
+        let before = storage.clone();
+
+        let secp = secp256k1::Secp256k1::new();
+        let other = PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&[3; 32]).unwrap());
+        let malformed = [
+            String::new(),
+            "203.0.113.7:9735".to_string(),
+            format!("{pubkey}"),
+            format!("{pubkey}@"),
+            format!("{pubkey}@203.0.113.7"),
+            format!("{pubkey}@:9735"),
+            format!("{pubkey}@203.0.113.7:port"),
+            format!("{pubkey}@203.0.113.7:70000"),
+            format!("{pubkey}@203.0.113.7:9735@other"),
+            "not_a_pubkey@203.0.113.7:9735".to_string(),
+            // a valid string for another peer
+            format!("{other}@203.0.113.7:9735"),
+        ];
+        for conn in malformed {
+            assert!(
+                matches!(
+                    storage.set_connection_string(pubkey, conn.clone()),
+                    Err(MutinyError::PeerInfoParseFailed)
+                ),
+                "{conn} should be rejected"
+            );
+        }
+        assert!(storage == before);
+    }
+
+    #[test]
+    fn test_read_validates_connection_strings() {
+        let storage = golden_full_storage();
+        let pubkey = *storage.peer_connections.keys().next().unwrap();
+        let with_connections = |connections: Vec<String>| {
+            let mut storage = storage.clone();
+            storage.peer_connections.insert(pubkey, connections);
+            StaticChannelBackupStorage::read(&mut Cursor::new(storage.encode()))
+        };
+
+        let hosts = [
+            "203.0.113.7",
+            "[2001:db8::1]",
+            "vww6ybal4bd7szmgncyruucpgfkqahzddi37ktceo3ah7ngmcopnpyyd.onion",
+            "node.example.com",
+        ];
+        for host in hosts {
+            let conn = format!("{pubkey}@{host}:9735");
+            let decoded = with_connections(vec![conn.clone()]).unwrap();
+            assert_eq!(decoded.peer_connections[&pubkey], vec![conn.clone()]);
+
+            // also as one of the other addresses of the peer
+            let connections = vec![format!("{pubkey}@198.51.100.1:9735"), conn];
+            let decoded = with_connections(connections.clone()).unwrap();
+            assert_eq!(decoded.peer_connections[&pubkey], connections);
+        }
+
+        let malformed = [
+            format!("{pubkey}@203.0.113.7"),
+            format!("{pubkey}@2001:db8::1:9735"),
+            format!("{pubkey}@[2001:db8::zz]:9735"),
+            format!("{pubkey}@bad host.onion:9735"),
+            "203.0.113.7:9735".to_string(),
+        ];
+        for conn in malformed {
+            assert!(
+                matches!(
+                    with_connections(vec![conn.clone()]),
+                    Err(DecodeError::InvalidValue)
+                ),
+                "{conn} should be rejected"
+            );
+            let connections = vec![format!("{pubkey}@198.51.100.1:9735"), conn];
+            assert!(matches!(
+                with_connections(connections),
+                Err(DecodeError::InvalidValue)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_static_channel_backup_labels() {
+        // backups from before labels decode without any
+        let legacy_bytes =
+            Vec::<u8>::from_hex(include_str!("test_vectors/full_storage.hex").trim()).unwrap();
+        let decoded = StaticChannelBackupStorage::read(&mut Cursor::new(&legacy_bytes)).unwrap();
+        assert!(decoded.channel_labels.is_empty());
+        assert!(decoded.peer_metadata.is_empty());
+
+        let mut storage = golden_full_storage();
+        let (pubkey, (_, backup)) = storage.backups.iter().next().unwrap();
+        let pubkey = *pubkey;
+        let outpoint = *backup.monitors.keys().next().unwrap();
+        storage
+            .channel_labels
+            .insert(outpoint, "savings ⚡".to_string());
+        storage.peer_metadata.insert(
+            pubkey,
+            ScbPeerMetadata {
+                alias: Some("Mutiny LSP".to_string()),
+                color: Some("ff0000".to_string()),
+                label: None,
+            },
+        );
+
+        // without a network or extra addresses the labels still round trip
+        let bytes = storage.encode();
+        let decoded = StaticChannelBackupStorage::read(&mut Cursor::new(&bytes)).unwrap();
+        assert_eq!(decoded.network, None);
+        assert_eq!(decoded.channel_labels[&outpoint], "savings ⚡");
+        assert_eq!(
+            decoded.peer_metadata[&pubkey].alias.as_deref(),
+            Some("Mutiny LSP")
+        );
+        assert!(decoded == storage);
+
+        storage.network = Some(Network::Signet);
+        storage.generation = 1;
+        let decrypted = storage
+            .encrypt(&golden_key())
+            .decrypt(&golden_key())
+            .unwrap();
+        assert!(decrypted == storage);
+
+        let json = storage.to_json().unwrap();
+        assert!(StaticChannelBackupStorage::from_json(&json).unwrap() == storage);
+
+        // an invalid presence byte for the peer label
+        let mut invalid = storage.encode();
+        let last = invalid.len() - 1;
+        invalid[last] = 2;
+        assert!(StaticChannelBackupStorage::read(&mut Cursor::new(&invalid)).is_err());
+    }
+
+    #[test]
+    fn test_static_channel_backup_network() {
+        let legacy = golden_full_storage();
+        assert!(!legacy.check_network(Network::Signet).unwrap());
+
+        let mut storage = golden_full_storage();
+        storage.network = Some(Network::Signet);
+        assert!(storage.check_network(Network::Signet).unwrap());
+        assert!(matches!(
+            storage.check_network(Network::Bitcoin),
+            Err(MutinyError::NetworkMismatch {
+                expected: Network::Bitcoin,
+                found: Network::Signet,
+            })
+        ));
+
+        // the network round trips and implies the generation trailer
+        let bytes = storage.encode();
+        assert_eq!(
+            bytes.len(),
+            legacy.encode().len() + SCB_GENERATION_LEN + NETWORK_MAGIC_LEN
+        );
+        let decoded = StaticChannelBackupStorage::read(&mut Cursor::new(&bytes)).unwrap();
+        assert!(decoded == storage);
+
+        // the network can be read without the key
+        let encrypted = storage.encrypt(&golden_key());
+        let metadata = inspect_scb_metadata(&encrypted.to_string()).unwrap();
+        assert_eq!(metadata.network, Some(Network::Signet));
+        assert!(!metadata.passphrase_encrypted);
+        assert!(metadata.has_integrity_tag);
+
+        let metadata = inspect_scb_metadata(&legacy.encrypt(&golden_key()).to_string()).unwrap();
+        assert_eq!(metadata.network, None);
+
+        let decrypted = encrypted.decrypt(&golden_key()).unwrap();
+        assert_eq!(decrypted.network, Some(Network::Signet));
+
+        // the cleartext network has to match the encrypted one
+        let mut tampered = EncryptedSCB::from_str(&encrypted.to_string()).unwrap();
+        tampered.network = Some(Network::Bitcoin);
+        let tampered = EncryptedSCB::from_str(&tampered.to_string()).unwrap();
+        assert!(tampered.decrypt(&golden_key()).is_err());
+    }
+
+    #[test]
+    fn test_scb_sweep_status() {
+        let funding_txo = OutPoint::null();
+
+        // the commitment is broadcast but not confirmed yet
+        let closing = vec![Balance::ClaimableOnChannelClose {
+            claimable_amount_satoshis: 50_000,
+        }];
+        assert_eq!(
+            scb_sweep_status(funding_txo, &closing, 800_000),
+            ScbSweepStatus::PendingTimelock {
+                amount_sats: 50_000,
+                blocks_remaining: None,
+            }
+        );
+
+        // the funds are swept once the last output matured
+        let confirmed = vec![
+            Balance::ClaimableAwaitingConfirmations {
+                claimable_amount_satoshis: 40_000,
+                confirmation_height: 800_144,
+            },
+            Balance::ClaimableAwaitingConfirmations {
+                claimable_amount_satoshis: 10_000,
+                confirmation_height: 800_006,
+            },
+        ];
+        assert_eq!(
+            scb_sweep_status(funding_txo, &confirmed, 800_000),
+            ScbSweepStatus::PendingTimelock {
+                amount_sats: 50_000,
+                blocks_remaining: Some(144),
+            }
+        );
+
+        assert_eq!(
+            scb_sweep_status(funding_txo, &[], 800_200),
+            ScbSweepStatus::Swept
+        );
+    }
 }
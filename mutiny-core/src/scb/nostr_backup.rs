@@ -0,0 +1,359 @@
+//! Publishing static channel backups to Nostr relays.
+//!
+//! The [`EncryptedSCB`] is published as a parameterized replaceable event with the
+//! [`SCB_NOSTR_D_TAG`] identifier, so relays only keep the latest backup of the wallet.
+//! The event is signed by a key derived from the seed, which lets the wallet find its
+//! backups again during recovery. The content is already encrypted with the SCB key.
+
+use super::EncryptedSCB;
+use crate::error::MutinyError;
+use crate::logging::MutinyLogger;
+use lightning::util::logger::Logger;
+use lightning::{log_debug, log_warn};
+use nostr::key::XOnlyPublicKey;
+use nostr::{Event, EventBuilder, Filter, Keys, Kind, Tag};
+use nostr_sdk::Client;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The kind of the backup events, the application specific data kind of NIP-78.
+pub const SCB_NOSTR_KIND: u64 = 30078;
+
+/// The `d` tag of the backup events.
+pub const SCB_NOSTR_D_TAG: &str = "mutiny-scb";
+
+pub const SCB_NOSTR_KEY_DERIVATION_PATH: &str = "m/44'/1237'/2'/0/0";
+
+/// How long to wait for a relay to return the backup events.
+const RELAY_FETCH_TIMEOUT_SECS: u64 = 10;
+
+/// How events are sent to and fetched from a single relay.
+pub(crate) trait NostrRelayTransport {
+    async fn publish(&self, relay: &str, event: Event) -> Result<(), MutinyError>;
+
+    async fn fetch(&self, relay: &str, filter: Filter) -> Result<Vec<Event>, MutinyError>;
+}
+
+/// Talks to the relays with a [`nostr_sdk::Client`], one connection per relay so
+/// the result of each relay is known.
+pub(crate) struct NostrSdkTransport {
+    keys: Keys,
+}
+
+impl NostrSdkTransport {
+    pub fn new(keys: Keys) -> Self {
+        Self { keys }
+    }
+
+    async fn connect(&self, relay: &str) -> Result<Client, MutinyError> {
+        let client = Client::new(&self.keys);
+
+        #[cfg(target_arch = "wasm32")]
+        let add_relay_res = client.add_relay(relay).await;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let add_relay_res = client.add_relay(relay, None).await;
+
+        add_relay_res.map_err(|e| {
+            MutinyError::Other(anyhow::anyhow!("Failed to add relay {relay}: {e:?}"))
+        })?;
+        client.connect().await;
+
+        Ok(client)
+    }
+}
+
+impl NostrRelayTransport for NostrSdkTransport {
+    async fn publish(&self, relay: &str, event: Event) -> Result<(), MutinyError> {
+        let client = self.connect(relay).await?;
+        let res = client.send_event(event).await;
+        let _ = client.disconnect().await;
+
+        res.map(|_| ())
+            .map_err(|e| MutinyError::Other(anyhow::anyhow!("Failed to send backup event: {e:?}")))
+    }
+
+    async fn fetch(&self, relay: &str, filter: Filter) -> Result<Vec<Event>, MutinyError> {
+        let client = self.connect(relay).await?;
+        let timeout = Duration::from_secs(RELAY_FETCH_TIMEOUT_SECS);
+        let res = client.get_events_of(vec![filter], Some(timeout)).await;
+        let _ = client.disconnect().await;
+
+        res.map_err(|e| MutinyError::Other(anyhow::anyhow!("Failed to fetch backup events: {e:?}")))
+    }
+}
+
+/// Creates the signed backup event for the given backup.
+pub(crate) fn create_backup_event(
+    keys: &Keys,
+    backup: &EncryptedSCB,
+) -> Result<Event, MutinyError> {
+    let d_tag = Tag::Identifier(SCB_NOSTR_D_TAG.to_string());
+    EventBuilder::new(Kind::from(SCB_NOSTR_KIND), backup.to_string(), &[d_tag])
+        .to_event(keys)
+        .map_err(|e| MutinyError::Other(anyhow::anyhow!("Failed to create event: {e:?}")))
+}
+
+fn backup_filter(pubkey: XOnlyPublicKey) -> Filter {
+    Filter::new()
+        .kinds(vec![Kind::from(SCB_NOSTR_KIND)])
+        .author(pubkey.to_string())
+        .identifier(SCB_NOSTR_D_TAG)
+}
+
+/// Publishes the backup to each of the relays, succeeds if at least one of them
+/// accepted it. Returns the relays that accepted the backup.
+pub(crate) async fn publish_backup<T: NostrRelayTransport>(
+    transport: &T,
+    keys: &Keys,
+    backup: &EncryptedSCB,
+    relays: &[String],
+    logger: &Arc<MutinyLogger>,
+) -> Result<Vec<String>, MutinyError> {
+    let event = create_backup_event(keys, backup)?;
+
+    let mut accepted = Vec::with_capacity(relays.len());
+    for relay in relays {
+        match transport.publish(relay, event.clone()).await {
+            Ok(()) => accepted.push(relay.clone()),
+            Err(e) => log_warn!(logger, "relay {relay} did not accept the backup: {e}"),
+        }
+    }
+
+    if accepted.is_empty() {
+        return Err(MutinyError::NostrBackupFailed);
+    }
+
+    log_debug!(logger, "published backup to {} relays", accepted.len());
+    Ok(accepted)
+}
+
+/// Fetches the latest backup signed by the given key from the relays. Relays that
+/// fail are skipped, as are events that are not a valid backup from that key.
+pub(crate) async fn fetch_latest_backup<T: NostrRelayTransport>(
+    transport: &T,
+    pubkey: XOnlyPublicKey,
+    relays: &[String],
+    logger: &Arc<MutinyLogger>,
+) -> Result<EncryptedSCB, MutinyError> {
+    let mut latest: Option<(Event, EncryptedSCB)> = None;
+    for relay in relays {
+        let events = match transport.fetch(relay, backup_filter(pubkey)).await {
+            Ok(events) => events,
+            Err(e) => {
+                log_warn!(logger, "could not fetch backup from relay {relay}: {e}");
+                continue;
+            }
+        };
+
+        for event in events {
+            let Some(backup) = parse_backup_event(&event, pubkey) else {
+                log_warn!(logger, "relay {relay} returned an invalid backup event");
+                continue;
+            };
+            if latest
+                .as_ref()
+                .map_or(true, |(l, _)| event.created_at > l.created_at)
+            {
+                latest = Some((event, backup));
+            }
+        }
+    }
+
+    latest
+        .map(|(_, backup)| backup)
+        .ok_or(MutinyError::NostrBackupNotFound)
+}
+
+/// Returns the backup in the event if it is a backup event signed by the given key.
+fn parse_backup_event(event: &Event, pubkey: XOnlyPublicKey) -> Option<EncryptedSCB> {
+    let is_backup = event.pubkey == pubkey
+        && event.kind == Kind::from(SCB_NOSTR_KIND)
+        && event
+            .tags
+            .iter()
+            .any(|t| matches!(t, Tag::Identifier(d) if d == SCB_NOSTR_D_TAG));
+
+    if !is_backup || event.verify().is_err() {
+        return None;
+    }
+
+    EncryptedSCB::from_str(&event.content).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::scb::StaticChannelBackupStorage;
+    use crate::utils;
+    use bitcoin::secp256k1::SecretKey;
+    use futures::executor::block_on;
+    use std::collections::{HashMap, HashSet};
+
+    /// Keeps the events of each relay in memory, like a relay it only
+    /// keeps the latest event for each author.
+    struct MockRelays {
+        events: utils::Mutex<HashMap<String, HashMap<XOnlyPublicKey, Event>>>,
+        offline: HashSet<String>,
+    }
+
+    impl MockRelays {
+        fn offline(relays: &[&str]) -> Self {
+            Self {
+                events: utils::Mutex::new(HashMap::new()),
+                offline: relays.iter().map(|r| r.to_string()).collect(),
+            }
+        }
+
+        fn check_online(&self, relay: &str) -> Result<(), MutinyError> {
+            if self.offline.contains(relay) {
+                Err(MutinyError::ConnectionFailed)
+            } else {
+                Ok(())
+            }
+        }
+
+        fn insert(&self, relay: &str, event: Event) {
+            let mut events = self.events.lock().unwrap();
+            events
+                .entry(relay.to_string())
+                .or_default()
+                .insert(event.pubkey, event);
+        }
+    }
+
+    impl NostrRelayTransport for MockRelays {
+        async fn publish(&self, relay: &str, event: Event) -> Result<(), MutinyError> {
+            self.check_online(relay)?;
+            self.insert(relay, event);
+            Ok(())
+        }
+
+        async fn fetch(&self, relay: &str, _filter: Filter) -> Result<Vec<Event>, MutinyError> {
+            self.check_online(relay)?;
+            let events = self.events.lock().unwrap();
+            Ok(events
+                .get(relay)
+                .map(|e| e.values().cloned().collect())
+                .unwrap_or_default())
+        }
+    }
+
+    fn relays(relays: &[&str]) -> Vec<String> {
+        relays.iter().map(|r| r.to_string()).collect()
+    }
+
+    fn create_backup() -> EncryptedSCB {
+        let mut bytes = [0u8; 32];
+        getrandom::getrandom(&mut bytes).expect("Failed to generate entropy");
+        let encryption_key = SecretKey::from_slice(&bytes).unwrap();
+
+        StaticChannelBackupStorage::default().encrypt(&encryption_key)
+    }
+
+    #[test]
+    fn test_nostr_backup_round_trip() {
+        let logger = Arc::new(MutinyLogger::default());
+        let transport = MockRelays::offline(&["wss://down.example.com"]);
+        let keys = Keys::generate();
+        let backup = create_backup();
+
+        let all = relays(&[
+            "wss://down.example.com",
+            "wss://one.example.com",
+            "wss://two.example.com",
+        ]);
+        let accepted = block_on(publish_backup(&transport, &keys, &backup, &all, &logger)).unwrap();
+        assert_eq!(
+            accepted,
+            relays(&["wss://one.example.com", "wss://two.example.com"])
+        );
+
+        // a relay that is down or never got the backup is skipped
+        let restored = block_on(fetch_latest_backup(
+            &transport,
+            keys.public_key(),
+            &relays(&[
+                "wss://down.example.com",
+                "wss://empty.example.com",
+                "wss://two.example.com",
+            ]),
+            &logger,
+        ))
+        .unwrap();
+        assert_eq!(restored, backup);
+
+        // a newer backup replaces the old one
+        let newer = create_backup();
+        block_on(publish_backup(&transport, &keys, &newer, &all, &logger)).unwrap();
+        let restored = block_on(fetch_latest_backup(
+            &transport,
+            keys.public_key(),
+            &all,
+            &logger,
+        ))
+        .unwrap();
+        assert_eq!(restored, newer);
+    }
+
+    #[test]
+    fn test_nostr_backup_relay_failures() {
+        let logger = Arc::new(MutinyLogger::default());
+        let down = relays(&["wss://down.example.com", "wss://also-down.example.com"]);
+        let transport =
+            MockRelays::offline(&["wss://down.example.com", "wss://also-down.example.com"]);
+        let keys = Keys::generate();
+
+        let res = block_on(publish_backup(
+            &transport,
+            &keys,
+            &create_backup(),
+            &down,
+            &logger,
+        ));
+        assert!(matches!(res, Err(MutinyError::NostrBackupFailed)));
+
+        let res = block_on(fetch_latest_backup(
+            &transport,
+            keys.public_key(),
+            &down,
+            &logger,
+        ));
+        assert!(matches!(res, Err(MutinyError::NostrBackupNotFound)));
+    }
+
+    #[test]
+    fn test_nostr_backup_ignores_invalid_events() {
+        let logger = Arc::new(MutinyLogger::default());
+        let transport = MockRelays::offline(&[]);
+        let keys = Keys::generate();
+        let relay = "wss://relay.example.com";
+
+        // a backup signed by someone else
+        let other = create_backup_event(&Keys::generate(), &create_backup()).unwrap();
+        transport.insert(relay, other);
+
+        // a backup event with the content swapped out
+        let mut tampered = create_backup_event(&keys, &create_backup()).unwrap();
+        tampered.content = create_backup().to_string();
+        transport.insert(relay, tampered);
+
+        let res = block_on(fetch_latest_backup(
+            &transport,
+            keys.public_key(),
+            &relays(&[relay]),
+            &logger,
+        ));
+        assert!(matches!(res, Err(MutinyError::NostrBackupNotFound)));
+
+        // an event that is not a backup
+        let note = EventBuilder::new_text_note("hello", &[])
+            .to_event(&keys)
+            .unwrap();
+        assert!(parse_backup_event(&note, keys.public_key()).is_none());
+
+        let valid = create_backup_event(&keys, &create_backup()).unwrap();
+        assert!(parse_backup_event(&valid, keys.public_key()).is_some());
+    }
+}
@@ -0,0 +1,402 @@
+//! Storing static channel backups on an authenticated HTTPS endpoint.
+//!
+//! The [`EncryptedSCB`] is stored as its bech32 string at `<url>/<pubkey>`, under a
+//! key derived from the seed, which lets the wallet find its backup again during
+//! recovery. Each request is signed by that key. Backups are sent with their
+//! generation, so the endpoint can refuse to replace a newer backup with an older one.
+
+use super::EncryptedSCB;
+use crate::error::MutinyError;
+use crate::logging::MutinyLogger;
+use bitcoin::hashes::hex::ToHex;
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::secp256k1::ecdsa::Signature;
+use bitcoin::secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+use lightning::util::logger::Logger;
+use lightning::{log_debug, log_warn};
+use reqwest::{Client, RequestBuilder, StatusCode};
+use std::str::FromStr;
+use std::sync::Arc;
+
+pub const SCB_REMOTE_KEY_DERIVATION_PATH: &str = "m/444'/444'/445'";
+
+/// The generation of the backup sent or stored, zero when fetching.
+pub const SCB_GENERATION_HEADER: &str = "x-scb-generation";
+
+/// The epoch time in seconds the request was signed at.
+pub const SCB_TIMESTAMP_HEADER: &str = "x-scb-timestamp";
+
+/// The hex encoded compact signature of the request.
+pub const SCB_SIGNATURE_HEADER: &str = "x-scb-signature";
+
+/// How far off the timestamp of a request may be, so old requests can't be replayed.
+pub const MAX_REQUEST_AGE_SECS: u64 = 300;
+
+const SIGNATURE_TAG: &[u8] = b"mutiny-scb-remote";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RemoteMethod {
+    Get,
+    Put,
+}
+
+impl RemoteMethod {
+    fn as_str(&self) -> &'static str {
+        match self {
+            RemoteMethod::Get => "GET",
+            RemoteMethod::Put => "PUT",
+        }
+    }
+}
+
+/// A request to the backup endpoint, signed by the backup key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SignedRequest {
+    pub method: RemoteMethod,
+    pub pubkey: PublicKey,
+    pub generation: u64,
+    pub timestamp: u64,
+    /// The backup as a bech32 string, empty when fetching
+    pub body: String,
+    pub signature: Signature,
+}
+
+impl SignedRequest {
+    fn new(
+        method: RemoteMethod,
+        key: &SecretKey,
+        generation: u64,
+        timestamp: u64,
+        body: String,
+    ) -> Self {
+        let secp = Secp256k1::new();
+        let pubkey = PublicKey::from_secret_key(&secp, key);
+        let digest = request_digest(method, &pubkey, generation, timestamp, &body);
+        Self {
+            method,
+            pubkey,
+            generation,
+            timestamp,
+            body,
+            signature: secp.sign_ecdsa(&digest, key),
+        }
+    }
+
+    /// Checks the signature and that the request is recent, as the endpoint does.
+    pub fn verify(&self, now: u64) -> bool {
+        let digest = request_digest(
+            self.method,
+            &self.pubkey,
+            self.generation,
+            self.timestamp,
+            &self.body,
+        );
+        let recent = self.timestamp.abs_diff(now) <= MAX_REQUEST_AGE_SECS;
+        recent
+            && Secp256k1::verification_only()
+                .verify_ecdsa(&digest, &self.signature, &self.pubkey)
+                .is_ok()
+    }
+}
+
+/// The message a request is signed over, which covers every part of the request.
+fn request_digest(
+    method: RemoteMethod,
+    pubkey: &PublicKey,
+    generation: u64,
+    timestamp: u64,
+    body: &str,
+) -> Message {
+    let mut engine = sha256::Hash::engine();
+    engine.input(SIGNATURE_TAG);
+    engine.input(method.as_str().as_bytes());
+    engine.input(&pubkey.serialize());
+    engine.input(&generation.to_be_bytes());
+    engine.input(&timestamp.to_be_bytes());
+    engine.input(body.as_bytes());
+    let hash = sha256::Hash::from_engine(engine);
+    Message::from_slice(&hash.into_inner()).expect("a sha256 hash is 32 bytes")
+}
+
+/// A backup as the endpoint stores it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct StoredBackup {
+    pub backup: String,
+    pub generation: u64,
+}
+
+/// How backups are sent to and fetched from the endpoint.
+pub(crate) trait ScbRemoteTransport {
+    /// Stores the backup, fails with [`MutinyError::RemoteBackupStale`] if the
+    /// endpoint has a backup with a higher generation.
+    async fn put(&self, request: SignedRequest) -> Result<(), MutinyError>;
+
+    /// Returns the stored backup, None if there is none yet.
+    async fn get(&self, request: SignedRequest) -> Result<Option<StoredBackup>, MutinyError>;
+}
+
+/// Talks to the endpoint over HTTPS, a conflict means the backup was stale and
+/// not found means there is no backup yet.
+pub(crate) struct HttpScbTransport {
+    url: String,
+    http_client: Client,
+}
+
+impl HttpScbTransport {
+    pub fn new(url: &str) -> Result<Self, MutinyError> {
+        let parsed = url::Url::parse(url).map_err(|_| MutinyError::InvalidArgumentsError)?;
+        if parsed.scheme() != "https" {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+
+        Ok(Self {
+            url: url.trim_end_matches('/').to_string(),
+            http_client: Client::new(),
+        })
+    }
+
+    fn request(&self, request: SignedRequest) -> RequestBuilder {
+        let url = format!("{}/{}", self.url, request.pubkey);
+        let builder = match request.method {
+            RemoteMethod::Get => self.http_client.get(url),
+            RemoteMethod::Put => self.http_client.put(url).body(request.body),
+        };
+        builder
+            .header(SCB_GENERATION_HEADER, request.generation)
+            .header(SCB_TIMESTAMP_HEADER, request.timestamp)
+            .header(
+                SCB_SIGNATURE_HEADER,
+                request.signature.serialize_compact().to_hex(),
+            )
+    }
+}
+
+impl ScbRemoteTransport for HttpScbTransport {
+    async fn put(&self, request: SignedRequest) -> Result<(), MutinyError> {
+        let response = self
+            .request(request)
+            .send()
+            .await
+            .map_err(|_| MutinyError::RemoteBackupFailed)?;
+
+        match response.status() {
+            status if status.is_success() => Ok(()),
+            StatusCode::CONFLICT => Err(MutinyError::RemoteBackupStale),
+            _ => Err(MutinyError::RemoteBackupFailed),
+        }
+    }
+
+    async fn get(&self, request: SignedRequest) -> Result<Option<StoredBackup>, MutinyError> {
+        let response = self
+            .request(request)
+            .send()
+            .await
+            .map_err(|_| MutinyError::RemoteBackupFailed)?;
+
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => {
+                let generation = response
+                    .headers()
+                    .get(SCB_GENERATION_HEADER)
+                    .and_then(|g| g.to_str().ok())
+                    .and_then(|g| g.parse().ok())
+                    .unwrap_or_default();
+                let backup = response
+                    .text()
+                    .await
+                    .map_err(|_| MutinyError::RemoteBackupFailed)?;
+                Ok(Some(StoredBackup { backup, generation }))
+            }
+            _ => Err(MutinyError::RemoteBackupFailed),
+        }
+    }
+}
+
+/// Stores the backup of the wallet on a remote endpoint and fetches it again.
+pub(crate) struct ScbRemoteBackup<T: ScbRemoteTransport> {
+    transport: T,
+    key: SecretKey,
+    logger: Arc<MutinyLogger>,
+}
+
+impl<T: ScbRemoteTransport> ScbRemoteBackup<T> {
+    pub fn new(transport: T, key: SecretKey, logger: Arc<MutinyLogger>) -> Self {
+        Self {
+            transport,
+            key,
+            logger,
+        }
+    }
+
+    /// Stores the backup with the given generation, unless the endpoint already
+    /// has a newer one.
+    pub async fn store(
+        &self,
+        backup: &EncryptedSCB,
+        generation: u64,
+        now: u64,
+    ) -> Result<(), MutinyError> {
+        let request = SignedRequest::new(
+            RemoteMethod::Put,
+            &self.key,
+            generation,
+            now,
+            backup.to_string(),
+        );
+
+        let res = self.transport.put(request).await;
+        match res {
+            Ok(()) => log_debug!(
+                self.logger,
+                "stored backup generation {generation} remotely"
+            ),
+            Err(MutinyError::RemoteBackupStale) => log_warn!(
+                self.logger,
+                "remote endpoint has a newer backup than generation {generation}"
+            ),
+            Err(ref e) => log_warn!(self.logger, "could not store backup remotely: {e}"),
+        }
+        res
+    }
+
+    /// Fetches the stored backup and its generation.
+    pub async fn fetch(&self, now: u64) -> Result<(EncryptedSCB, u64), MutinyError> {
+        let request = SignedRequest::new(RemoteMethod::Get, &self.key, 0, now, String::new());
+        let stored = self
+            .transport
+            .get(request)
+            .await?
+            .ok_or(MutinyError::RemoteBackupNotFound)?;
+
+        let backup = EncryptedSCB::from_str(&stored.backup)
+            .map_err(|_| MutinyError::InvalidArgumentsError)?;
+        Ok((backup, stored.generation))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::scb::StaticChannelBackupStorage;
+    use crate::utils;
+    use futures::executor::block_on;
+    use std::collections::HashMap;
+
+    const NOW: u64 = 1_690_000_000;
+
+    /// Keeps the backups in memory and checks requests like an endpoint would.
+    struct MemoryEndpoint {
+        backups: utils::Mutex<HashMap<PublicKey, StoredBackup>>,
+    }
+
+    impl MemoryEndpoint {
+        fn new() -> Self {
+            Self {
+                backups: utils::Mutex::new(HashMap::new()),
+            }
+        }
+    }
+
+    impl ScbRemoteTransport for MemoryEndpoint {
+        async fn put(&self, request: SignedRequest) -> Result<(), MutinyError> {
+            if !request.verify(NOW) {
+                return Err(MutinyError::RemoteBackupFailed);
+            }
+
+            let mut backups = self.backups.lock().unwrap();
+            if backups
+                .get(&request.pubkey)
+                .map_or(false, |b| b.generation > request.generation)
+            {
+                return Err(MutinyError::RemoteBackupStale);
+            }
+            let stored = StoredBackup {
+                backup: request.body,
+                generation: request.generation,
+            };
+            backups.insert(request.pubkey, stored);
+            Ok(())
+        }
+
+        async fn get(&self, request: SignedRequest) -> Result<Option<StoredBackup>, MutinyError> {
+            if !request.verify(NOW) {
+                return Err(MutinyError::RemoteBackupFailed);
+            }
+
+            Ok(self.backups.lock().unwrap().get(&request.pubkey).cloned())
+        }
+    }
+
+    fn random_key() -> SecretKey {
+        let mut bytes = [0u8; 32];
+        getrandom::getrandom(&mut bytes).expect("Failed to generate entropy");
+        SecretKey::from_slice(&bytes).unwrap()
+    }
+
+    fn create_backup() -> EncryptedSCB {
+        StaticChannelBackupStorage::default().encrypt(&random_key())
+    }
+
+    #[test]
+    fn test_remote_backup_generations() {
+        let logger = Arc::new(MutinyLogger::default());
+        let remote = ScbRemoteBackup::new(MemoryEndpoint::new(), random_key(), logger);
+
+        // nothing was stored yet
+        let res = block_on(remote.fetch(NOW));
+        assert!(matches!(res, Err(MutinyError::RemoteBackupNotFound)));
+
+        let first = create_backup();
+        block_on(remote.store(&first, 1, NOW)).unwrap();
+        assert_eq!(block_on(remote.fetch(NOW)).unwrap(), (first.clone(), 1));
+
+        // retrying the same generation is fine, a newer one replaces it
+        block_on(remote.store(&first, 1, NOW)).unwrap();
+        let second = create_backup();
+        block_on(remote.store(&second, 2, NOW)).unwrap();
+        assert_eq!(block_on(remote.fetch(NOW)).unwrap(), (second.clone(), 2));
+
+        // another device that is behind can not replace it
+        let res = block_on(remote.store(&first, 1, NOW));
+        assert!(matches!(res, Err(MutinyError::RemoteBackupStale)));
+        assert_eq!(block_on(remote.fetch(NOW)).unwrap(), (second, 2));
+    }
+
+    #[test]
+    fn test_remote_backup_authentication() {
+        let key = random_key();
+        let request =
+            SignedRequest::new(RemoteMethod::Put, &key, 1, NOW, create_backup().to_string());
+        assert!(request.verify(NOW));
+        assert!(request.verify(NOW + MAX_REQUEST_AGE_SECS));
+
+        // replayed later
+        assert!(!request.verify(NOW + MAX_REQUEST_AGE_SECS + 1));
+
+        // any part of the request changed
+        let mut tampered = request.clone();
+        tampered.body = create_backup().to_string();
+        assert!(!tampered.verify(NOW));
+        let mut tampered = request.clone();
+        tampered.generation = 2;
+        assert!(!tampered.verify(NOW));
+        let mut tampered = request.clone();
+        tampered.method = RemoteMethod::Get;
+        assert!(!tampered.verify(NOW));
+
+        // signed by someone else for our key
+        let mut forged =
+            SignedRequest::new(RemoteMethod::Get, &random_key(), 0, NOW, String::new());
+        forged.pubkey = request.pubkey;
+        assert!(!forged.verify(NOW));
+
+        // backups are only sent over https
+        assert!(HttpScbTransport::new("https://backup.example.com/scb/").is_ok());
+        assert!(matches!(
+            HttpScbTransport::new("http://backup.example.com/scb"),
+            Err(MutinyError::InvalidArgumentsError)
+        ));
+        assert!(HttpScbTransport::new("not a url").is_err());
+    }
+}
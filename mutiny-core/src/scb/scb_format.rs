@@ -0,0 +1,254 @@
+//! The wire format of static channel backups.
+//!
+//! These constants are used by both the encoder and the test vectors, so any change to
+//! the format has to consciously update the golden files in `scb/test_vectors`.
+//!
+//! All integers are big endian. A [`super::StaticChannelBackupStorage`] is encoded as:
+//!
+//! 1. backup count ([`LengthPrefix`])
+//! 2. for each backup:
+//!     1. node public key ([`PUBKEY_LEN`] bytes, compressed)
+//!     2. node index: archived flag (1 byte), child index (u32), lsp length
+//!        ([`LengthPrefix`]) followed by the lsp url. Or, if it has fields this fixed
+//!        layout can not hold, [`NODE_INDEX_TLV_MARKER`] followed by TLV records.
+//!     3. monitor count ([`LengthPrefix`]), then for each monitor: funding txid
+//!        ([`TXID_LEN`] bytes), funding vout ([`VOUT_LEN`] bytes), monitor length
+//!        ([`LengthPrefix`]) followed by the serialized channel monitor
+//! 3. peer connection count ([`LengthPrefix`])
+//! 4. for each peer connection: public key ([`PUBKEY_LEN`] bytes), connection string
+//!    length ([`LengthPrefix`]) followed by the utf8 connection string. This is the
+//!    first address of the peer, the others are in 7.
+//! 5. optionally, the creation time (u64) and generation (u64), together
+//!    [`SCB_GENERATION_LEN`] bytes. Legacy backups end after the peer connections and
+//!    are read with a generation of zero, so they are older than any newer backup.
+//! 6. optionally, the network magic ([`NETWORK_MAGIC_LEN`] bytes) of the network the
+//!    backup was created on, or [`UNKNOWN_NETWORK_MAGIC`] if 7 follows without a network
+//! 7. optionally, the count of peers with more than one address ([`LengthPrefix`]),
+//!    then for each: public key ([`PUBKEY_LEN`] bytes), address count ([`LengthPrefix`])
+//!    and each of the other connection strings, length prefixed like in 4. Written with
+//!    a count of zero if only 8 or 9 follow.
+//! 8. optionally, the channel label count ([`LengthPrefix`]), then for each: funding txid
+//!    ([`TXID_LEN`] bytes), funding vout ([`VOUT_LEN`] bytes) and the utf8 label, length
+//!    prefixed like in 4. Followed by the peer metadata count ([`LengthPrefix`]), then for
+//!    each: public key ([`PUBKEY_LEN`] bytes) and the alias, color and label of the peer.
+//!    Each of those is a presence byte, followed by the length prefixed string if it is 1.
+//!    Written with counts of zero if only 9 follows.
+//! 9. optionally, the count of peers with a last connection time ([`LengthPrefix`]), then
+//!    for each: public key ([`PUBKEY_LEN`] bytes) and the epoch time in seconds (u64) the
+//!    peer was last connected to.
+//!
+//! Backups are untrusted input, so counts above [`MAX_ENTRIES`], monitors above
+//! [`MAX_MONITOR_LEN`] and strings above [`MAX_STRING_LEN`] are rejected when reading.
+//!
+//! A TLV encoded node index is the length of its records ([`LengthPrefix`]), then
+//! each record as its type (u16), value length ([`LengthPrefix`]) and value, in
+//! increasing type order. Like in BOLT 1, unknown odd types are skipped so fields can
+//! be added without breaking older readers, while unknown even types are rejected.
+//! The child index ([`NODE_INDEX_CHILD_INDEX_TYPE`]) is the only required record.
+//!
+//! Since [`SCB_VERSION_GZIP`] the plaintext is prefixed with the version byte and
+//! gzip compressed before encryption. Backups without a version byte are
+//! [`SCB_VERSION_V0`] and are still readable. Diffs between two backups are encrypted
+//! the same way, with [`SCB_VERSION_DIFF`] as the version byte.
+//!
+//! That plaintext is encrypted with AES-256-CBC and PKCS7 padding. The resulting
+//! [`super::EncryptedSCB`] is encoded as the ciphertext length ([`LengthPrefix`]), the
+//! ciphertext, and the iv ([`IV_LEN`] bytes), and is displayed as bech32m with the
+//! [`SCB_HRP`] human readable part.
+//!
+//! Newer backups start with a flags byte. With [`SCB_PASSPHRASE_MODE`] set the backup
+//! is encrypted with a passphrase instead of the seed key, and the flags are followed by
+//! the Argon2id memory cost in KiB (u32), the number of passes (u32) and the salt
+//! ([`PASSPHRASE_SALT_LEN`] bytes). Costs outside [`SCB_PASSPHRASE_KDF_MEMORY_KIB`] to
+//! [`SCB_PASSPHRASE_KDF_MAX_MEMORY_KIB`] and [`SCB_PASSPHRASE_KDF_PASSES`] to
+//! [`SCB_PASSPHRASE_KDF_MAX_PASSES`] are rejected when reading. With
+//! [`SCB_MAC_FLAG`] set an HMAC-SHA256 ([`SCB_MAC_LEN`] bytes) over the header, the iv
+//! and the ciphertext is appended after the iv. With [`SCB_NETWORK_FLAG`] set the flags are
+//! directly followed by a cleartext copy of the network magic, so the network can be
+//! shown before decrypting; it has to match the encrypted copy. With [`SCB_ACCOUNT_FLAG`]
+//! set the network magic, or the flags if there is none, is followed by the account (u32)
+//! of the seed key the backup is encrypted with. With [`SCB_FINGERPRINT_FLAG`] set the
+//! account, or what comes before it, is followed by the first [`SCB_FINGERPRINT_LEN`]
+//! bytes of the sha256 of the plaintext encoding, so copies of the same backup can be
+//! told apart without decrypting them. Older backups have no flags byte and start with
+//! the ciphertext length, whose first byte is always zero.
+//!
+//! The header the HMAC covers is the flags byte without [`SCB_PADDING_FLAG`], followed
+//! by every cleartext field between the flags and the ciphertext length as written, so
+//! none of them can be changed or stripped without failing the HMAC. Backups created
+//! with the privacy padding enabled leave out the fingerprint, so copies of the same
+//! backup can not be linked.
+//!
+//! Backups with a flags byte always have [`SCB_MAC_FLAG`] set, and only
+//! [`SCB_VERSION_V0`] payloads can be without the HMAC. Anything else without one
+//! is rejected, so the HMAC can not be stripped to skip the authentication.
+//!
+//! With [`SCB_PADDING_FLAG`] set the backup ends with a padding length ([`LengthPrefix`])
+//! and that many random bytes, after the HMAC if there is one, so its size only reveals
+//! the bucket it was padded to. The padding is not authenticated and ignored on decrypt.
+
+/// The human readable part used for bech32m encoded backups.
+pub const SCB_HRP: &str = "scb";
+
+/// The URI scheme a backup string can be prefixed with, like `lightning:` for invoices.
+pub const SCB_URI_SCHEME: &str = "scb:";
+
+/// The type used for every count and length prefix in the format.
+pub type LengthPrefix = u32;
+
+/// Size in bytes of a [`LengthPrefix`].
+pub const LENGTH_PREFIX_LEN: usize = core::mem::size_of::<LengthPrefix>();
+
+/// Size in bytes of a funding txid.
+pub const TXID_LEN: usize = 32;
+
+/// Size in bytes of a funding output index.
+pub const VOUT_LEN: usize = core::mem::size_of::<u32>();
+
+/// Size in bytes of a compressed public key.
+pub const PUBKEY_LEN: usize = 33;
+
+/// Size in bytes of the trailing creation time and generation.
+pub const SCB_GENERATION_LEN: usize = 2 * core::mem::size_of::<u64>();
+
+/// Size in bytes of the version prefix of a serialized LDK channel monitor, it is
+/// followed by the monitor's `latest_update_id` (u64).
+pub const MONITOR_VERSION_PREFIX_LEN: usize = 2;
+
+/// The longest channel monitor that is read from a backup. Monitors grow with every
+/// commitment of the channel, so this leaves a lot of room over typical monitors.
+pub const MAX_MONITOR_LEN: LengthPrefix = 16 * 1024 * 1024;
+
+/// The most entries that are read for any count in a backup, like the nodes, the
+/// monitors of a node or the peers.
+pub const MAX_ENTRIES: LengthPrefix = 1024;
+
+/// The longest string that is read from a backup, like a connection string or a label.
+/// Longer strings are left out when creating a backup.
+pub const MAX_STRING_LEN: LengthPrefix = 1024;
+
+/// The longest ciphertext or decompressed plaintext that is read, so a small
+/// compressed backup can not expand without bound.
+pub const MAX_PAYLOAD_LEN: LengthPrefix = 256 * 1024 * 1024;
+
+/// Size in bytes of the AES-256-CBC iv.
+pub const IV_LEN: usize = 16;
+
+/// The original format has no explicit version byte. The plaintext starts with the
+/// backup count, whose first byte is always zero for any realistic number of nodes,
+/// so non-zero first bytes are reserved for future versions.
+pub const SCB_VERSION_V0: u8 = 0;
+
+/// The plaintext is this version byte followed by the gzip compressed encoding.
+pub const SCB_VERSION_GZIP: u8 = 1;
+
+/// Like [`SCB_VERSION_GZIP`], for backups with TLV encoded node indexes. Readers
+/// from before those reject it instead of misreading the node indexes.
+pub const SCB_VERSION_TLV_NODE_INDEX: u8 = 2;
+
+/// The plaintext is this version byte followed by a gzip compressed
+/// [`super::ScbDiff`] instead of a full backup, so a diff is never restored as one.
+pub const SCB_VERSION_DIFF: u8 = 3;
+
+/// Written in place of the archived flag of a TLV encoded node index.
+pub const NODE_INDEX_TLV_MARKER: u8 = 0xff;
+
+/// The longest records of a TLV encoded node index that are read.
+pub const MAX_NODE_INDEX_LEN: LengthPrefix = 4 * MAX_STRING_LEN;
+
+/// TLV type of the child index (u32) of a node index.
+pub const NODE_INDEX_CHILD_INDEX_TYPE: u16 = 0;
+
+/// TLV type of the archived flag (1 byte) of a node index.
+pub const NODE_INDEX_ARCHIVED_TYPE: u16 = 1;
+
+/// TLV type of the utf8 lsp url of a node index.
+pub const NODE_INDEX_LSP_TYPE: u16 = 3;
+
+/// TLV type of the creation time (u64) of a node index.
+pub const NODE_INDEX_CREATED_AT_TYPE: u16 = 5;
+
+/// TLV type of the utf8 alias of a node index.
+pub const NODE_INDEX_ALIAS_TYPE: u16 = 7;
+
+/// The node index TLV types this version reads.
+pub const NODE_INDEX_KNOWN_TYPES: &[u16] = &[
+    NODE_INDEX_CHILD_INDEX_TYPE,
+    NODE_INDEX_ARCHIVED_TYPE,
+    NODE_INDEX_LSP_TYPE,
+    NODE_INDEX_CREATED_AT_TYPE,
+    NODE_INDEX_ALIAS_TYPE,
+];
+
+/// Flag for a backup encrypted with a passphrase derived key.
+pub const SCB_PASSPHRASE_MODE: u8 = 0x01;
+
+/// Flag for a backup with an HMAC over the header, iv and ciphertext.
+pub const SCB_MAC_FLAG: u8 = 0x02;
+
+/// Flag for a backup with the network in cleartext.
+pub const SCB_NETWORK_FLAG: u8 = 0x04;
+
+/// Flag bit set if the backup ends with random padding.
+pub const SCB_PADDING_FLAG: u8 = 0x08;
+
+/// Flag for a backup encrypted with the seed key of an account other than the
+/// default, see [`super::scb_key_derivation_path`].
+pub const SCB_ACCOUNT_FLAG: u8 = 0x10;
+
+/// Flag for a backup with the fingerprint of the plaintext in cleartext.
+pub const SCB_FINGERPRINT_FLAG: u8 = 0x20;
+
+/// Size in bytes of a network magic.
+pub const NETWORK_MAGIC_LEN: usize = 4;
+
+/// Written in place of the network magic when a backup without a network
+/// has later fields.
+pub const UNKNOWN_NETWORK_MAGIC: u32 = 0;
+
+/// Size in bytes of the HMAC-SHA256 integrity tag.
+pub const SCB_MAC_LEN: usize = 32;
+
+/// Tag hashed with the encryption key to derive the HMAC key.
+pub const SCB_MAC_KEY_TAG: &[u8] = b"mutiny/scb/hmac";
+
+/// Size in bytes of the fingerprint of a backup.
+pub const SCB_FINGERPRINT_LEN: usize = 8;
+
+/// Size in bytes of the salt for the passphrase key derivation.
+pub const PASSPHRASE_SALT_LEN: usize = 16;
+
+/// Argon2id memory cost in KiB used to derive the key for passphrase encrypted
+/// backups, and the least a backup is read with.
+pub const SCB_PASSPHRASE_KDF_MEMORY_KIB: u32 = 19 * 1024;
+
+/// The most Argon2id memory in KiB a backup is read with, so a crafted backup
+/// can not exhaust the memory of the wallet.
+pub const SCB_PASSPHRASE_KDF_MAX_MEMORY_KIB: u32 = 128 * 1024;
+
+/// Argon2id passes used to derive the key for passphrase encrypted backups,
+/// and the least a backup is read with.
+pub const SCB_PASSPHRASE_KDF_PASSES: u32 = 2;
+
+/// The most Argon2id passes a backup is read with, so a crafted backup
+/// can not hang the wallet.
+pub const SCB_PASSPHRASE_KDF_MAX_PASSES: u32 = 8;
+
+/// Size of the header of each part of a multi-part export:
+/// part index (u16), total parts (u16), and a 4 byte backup id.
+pub const SCB_PART_HEADER_LEN: usize = 8;
+
+/// Length of the bech32m overhead for a part: the hrp, the separator,
+/// and the 6 character checksum.
+pub const SCB_PART_BECH32_OVERHEAD: usize = SCB_HRP.len() + 1 + 6;
+
+/// First line of an armored export, which is the base64 encoded backup wrapped at
+/// [`SCB_ARMOR_LINE_LEN`] columns, followed by `=` and the base64 encoded CRC24 of
+/// the backup, like OpenPGP armor.
+pub const SCB_ARMOR_BEGIN: &str = "-----BEGIN MUTINY SCB-----";
+
+/// Last line of an armored export.
+pub const SCB_ARMOR_END: &str = "-----END MUTINY SCB-----";
+
+/// Length of the base64 lines of an armored export.
+pub const SCB_ARMOR_LINE_LEN: usize = 64;
@@ -0,0 +1,624 @@
+use crate::capabilities::Capability;
+use crate::error::MutinyError;
+use crate::labels::{Contact, LabelItem};
+use crate::nodemanager::{MutinyInvoice, TransactionDetails};
+use crate::storage::MutinyStorage;
+use bdk::chain::ConfirmationTime;
+use bitcoin::hashes::hex::ToHex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use unicode_normalization::UnicodeNormalization;
+
+pub(crate) const SEARCH_INDEX_KEY: &str = "search_index";
+
+/// Bump this when the tokenization changes, older indexes are then rebuilt
+const SEARCH_INDEX_VERSION: u32 = 1;
+
+pub(crate) const SEARCH_CAPABILITY: Capability =
+    Capability::version("search_index", SEARCH_INDEX_VERSION);
+
+/// Max number of distinct words and ids in the index,
+/// new tokens are dropped once this is reached.
+pub(crate) const MAX_INDEX_TOKENS: usize = 50_000;
+
+/// Longer words are not indexed
+const MAX_WORD_LEN: usize = 64;
+
+/// Hex query terms at least this long also match ids by prefix
+const MIN_HEX_PREFIX_LEN: usize = 4;
+
+/// The details of a payment or transaction, such as the description and amount
+const FIELD_DETAILS: &str = "details";
+/// The labels attached to a payment or transaction
+const FIELD_LABELS: &str = "labels";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SearchResultKind {
+    Lightning,
+    OnChain,
+    Contact,
+    Label,
+}
+
+impl SearchResultKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SearchResultKind::Lightning => "lightning",
+            SearchResultKind::OnChain => "onchain",
+            SearchResultKind::Contact => "contact",
+            SearchResultKind::Label => "label",
+        }
+    }
+}
+
+/// A single search hit. The id is the payment hash for lightning payments,
+/// the txid for on-chain transactions, and the label for contacts and labels.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SearchResult {
+    pub kind: SearchResultKind,
+    pub id: String,
+    pub timestamp: u64,
+}
+
+/// The searchable contents of one field of an item, used to update the index
+#[derive(Clone, Debug)]
+pub(crate) struct SearchDocument {
+    pub kind: SearchResultKind,
+    pub id: String,
+    pub field: &'static str,
+    pub timestamp: u64,
+    /// Free text, such as descriptions, labels, and names
+    pub text: Vec<String>,
+    /// Hex ids, such as payment hashes and txids, these can be matched by prefix
+    pub hex_ids: Vec<String>,
+    pub amount_sats: Option<u64>,
+}
+
+impl SearchDocument {
+    fn key(&self) -> String {
+        doc_key(self.kind, &self.id)
+    }
+
+    pub fn lightning(invoice: &MutinyInvoice) -> Self {
+        let payment_hash = invoice.payment_hash.to_hex();
+        Self {
+            kind: SearchResultKind::Lightning,
+            id: payment_hash.clone(),
+            field: FIELD_DETAILS,
+            timestamp: invoice.last_updated,
+            text: invoice.description.clone().into_iter().collect(),
+            hex_ids: vec![payment_hash],
+            amount_sats: invoice.amount_sats,
+        }
+    }
+
+    pub fn lightning_labels(payment_hash: String, labels: Vec<String>, timestamp: u64) -> Self {
+        Self {
+            kind: SearchResultKind::Lightning,
+            id: payment_hash,
+            field: FIELD_LABELS,
+            timestamp,
+            text: labels,
+            hex_ids: vec![],
+            amount_sats: None,
+        }
+    }
+
+    pub fn onchain(tx: &TransactionDetails) -> Self {
+        let txid = tx.txid.to_hex();
+        let timestamp = match tx.confirmation_time {
+            ConfirmationTime::Confirmed { time, .. } => time,
+            ConfirmationTime::Unconfirmed { last_seen } => last_seen,
+        };
+        let amount_sats = if tx.received > tx.sent {
+            tx.received - tx.sent
+        } else {
+            tx.sent - tx.received
+        };
+        Self {
+            kind: SearchResultKind::OnChain,
+            id: txid.clone(),
+            field: FIELD_DETAILS,
+            timestamp,
+            text: tx.labels.clone(),
+            hex_ids: vec![txid],
+            amount_sats: Some(amount_sats),
+        }
+    }
+
+    pub fn contact(id: String, contact: &Contact) -> Self {
+        Self {
+            kind: SearchResultKind::Contact,
+            id,
+            field: FIELD_DETAILS,
+            timestamp: contact.last_used,
+            text: vec![contact.name.clone()],
+            hex_ids: vec![],
+            amount_sats: None,
+        }
+    }
+
+    pub fn label(label: String, item: &LabelItem) -> Self {
+        Self {
+            kind: SearchResultKind::Label,
+            id: label.clone(),
+            field: FIELD_DETAILS,
+            timestamp: item.last_used_time,
+            text: vec![label],
+            hex_ids: vec![],
+            amount_sats: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+struct IndexedField {
+    words: Vec<String>,
+    hex_ids: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+struct IndexedDoc {
+    kind: SearchResultKind,
+    id: String,
+    timestamp: u64,
+    fields: BTreeMap<String, IndexedField>,
+}
+
+impl IndexedDoc {
+    fn has_word(&self, word: &str) -> bool {
+        self.fields
+            .values()
+            .any(|f| f.words.iter().any(|w| w == word))
+    }
+
+    fn has_hex_id(&self, id: &str) -> bool {
+        self.fields
+            .values()
+            .any(|f| f.hex_ids.iter().any(|i| i == id))
+    }
+}
+
+/// An inverted index over the searchable text of the wallet's activity, labels,
+/// and contacts, so searching does not need to read the whole history.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct SearchIndex {
+    version: u32,
+    docs: HashMap<String, IndexedDoc>,
+    words: BTreeMap<String, BTreeSet<String>>,
+    hex_ids: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl Default for SearchIndex {
+    fn default() -> Self {
+        Self {
+            version: SEARCH_INDEX_VERSION,
+            docs: HashMap::new(),
+            words: BTreeMap::new(),
+            hex_ids: BTreeMap::new(),
+        }
+    }
+}
+
+fn doc_key(kind: SearchResultKind, id: &str) -> String {
+    format!("{}/{id}", kind.as_str())
+}
+
+fn normalize(text: &str) -> String {
+    text.nfc().collect::<String>().to_lowercase()
+}
+
+/// Splits the text into lowercase, NFC normalized words
+fn tokenize(text: &str) -> Vec<String> {
+    normalize(text)
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty() && w.chars().count() <= MAX_WORD_LEN)
+        .map(|w| w.to_string())
+        .collect()
+}
+
+fn is_hex_prefix(term: &str) -> bool {
+    term.len() >= MIN_HEX_PREFIX_LEN && term.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn unlink(map: &mut BTreeMap<String, BTreeSet<String>>, token: &str, key: &str) {
+    if let Some(keys) = map.get_mut(token) {
+        keys.remove(key);
+        if keys.is_empty() {
+            map.remove(token);
+        }
+    }
+}
+
+impl SearchIndex {
+    fn token_count(&self) -> usize {
+        self.words.len() + self.hex_ids.len()
+    }
+
+    /// Adds the token to the map unless the vocabulary is full,
+    /// returns if the token is in the index.
+    fn link(&mut self, is_hex_id: bool, token: &str, key: &str) -> bool {
+        let full = self.token_count() >= MAX_INDEX_TOKENS;
+        let map = if is_hex_id {
+            &mut self.hex_ids
+        } else {
+            &mut self.words
+        };
+        match map.get_mut(token) {
+            Some(keys) => {
+                keys.insert(key.to_string());
+                true
+            }
+            None if full => false,
+            None => {
+                map.insert(token.to_string(), BTreeSet::from([key.to_string()]));
+                true
+            }
+        }
+    }
+
+    /// Indexes one field of an item, replacing whatever was indexed for that field before.
+    pub fn insert(&mut self, doc: SearchDocument) {
+        let key = doc.key();
+
+        let mut words: Vec<String> = doc.text.iter().flat_map(|t| tokenize(t)).collect();
+        words.extend(doc.amount_sats.map(|a| a.to_string()));
+        words.sort();
+        words.dedup();
+
+        let mut hex_ids: Vec<String> = doc.hex_ids.iter().map(|i| i.to_lowercase()).collect();
+        hex_ids.sort();
+        hex_ids.dedup();
+
+        // remove the old contents of the field
+        let old = self
+            .docs
+            .get_mut(&key)
+            .and_then(|d| d.fields.remove(doc.field));
+        if let Some(old) = old {
+            let current = self.docs.get(&key).expect("doc exists").clone();
+            for word in old.words.iter().filter(|w| !current.has_word(w)) {
+                unlink(&mut self.words, word, &key);
+            }
+            for id in old.hex_ids.iter().filter(|i| !current.has_hex_id(i)) {
+                unlink(&mut self.hex_ids, id, &key);
+            }
+        }
+
+        let words: Vec<String> = words
+            .into_iter()
+            .filter(|w| self.link(false, w, &key))
+            .collect();
+        let hex_ids: Vec<String> = hex_ids
+            .into_iter()
+            .filter(|i| self.link(true, i, &key))
+            .collect();
+
+        let entry = self.docs.entry(key).or_insert_with(|| IndexedDoc {
+            kind: doc.kind,
+            id: doc.id.clone(),
+            timestamp: doc.timestamp,
+            fields: BTreeMap::new(),
+        });
+        entry.timestamp = entry.timestamp.max(doc.timestamp);
+        entry
+            .fields
+            .insert(doc.field.to_string(), IndexedField { words, hex_ids });
+    }
+
+    /// Removes an item and all of its fields from the index
+    pub fn remove(&mut self, kind: SearchResultKind, id: &str) {
+        let key = doc_key(kind, id);
+        if let Some(doc) = self.docs.remove(&key) {
+            for field in doc.fields.values() {
+                for word in field.words.iter() {
+                    unlink(&mut self.words, word, &key);
+                }
+                for id in field.hex_ids.iter() {
+                    unlink(&mut self.hex_ids, id, &key);
+                }
+            }
+        }
+    }
+
+    /// Returns the items matching every term of the query, newest first.
+    ///
+    /// Words and amounts must match exactly, hex terms of at least
+    /// [`MIN_HEX_PREFIX_LEN`] characters also match payment hashes and txids by prefix.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<SearchResult> {
+        let terms = tokenize(query);
+        if terms.is_empty() || limit == 0 {
+            return vec![];
+        }
+
+        let mut matches: Option<BTreeSet<&String>> = None;
+        for term in terms.iter() {
+            let mut found: BTreeSet<&String> = BTreeSet::new();
+            if let Some(keys) = self.words.get(term) {
+                found.extend(keys.iter());
+            }
+            if is_hex_prefix(term) {
+                let prefixed = self
+                    .hex_ids
+                    .range(term.clone()..)
+                    .take_while(|(id, _)| id.starts_with(term.as_str()));
+                for (_, keys) in prefixed {
+                    found.extend(keys.iter());
+                }
+            }
+
+            matches = Some(match matches {
+                None => found,
+                Some(matches) => matches.intersection(&found).copied().collect(),
+            });
+        }
+
+        let mut results: Vec<SearchResult> = matches
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|key| self.docs.get(key))
+            .map(|doc| SearchResult {
+                kind: doc.kind,
+                id: doc.id.clone(),
+                timestamp: doc.timestamp,
+            })
+            .collect();
+
+        results.sort_by(|a, b| {
+            b.timestamp
+                .cmp(&a.timestamp)
+                .then_with(|| a.kind.cmp(&b.kind))
+                .then_with(|| a.id.cmp(&b.id))
+        });
+        results.truncate(limit);
+        results
+    }
+}
+
+/// Reads the search index from storage, returns None if it is missing,
+/// corrupt, or from an older version, in which case it needs to be rebuilt.
+pub(crate) fn load_search_index<S: MutinyStorage>(storage: &S) -> Option<SearchIndex> {
+    match storage.get_data::<SearchIndex>(SEARCH_INDEX_KEY) {
+        Ok(Some(index)) if index.version == SEARCH_INDEX_VERSION => Some(index),
+        _ => None,
+    }
+}
+
+/// Applies an update to the stored search index. If there is no valid index this
+/// does nothing, the index is rebuilt from scratch on the next search instead of
+/// being left with only part of the history.
+pub(crate) fn update_search_index<S: MutinyStorage>(
+    storage: &S,
+    update: impl FnOnce(&mut SearchIndex),
+) -> Result<(), MutinyError> {
+    match load_search_index(storage) {
+        Some(mut index) => {
+            update(&mut index);
+            storage.set_data(SEARCH_INDEX_KEY, index)
+        }
+        None => Ok(()),
+    }
+}
+
+/// Builds a new search index from all of the given documents and saves it.
+pub(crate) fn rebuild_search_index<S: MutinyStorage>(
+    storage: &S,
+    docs: Vec<SearchDocument>,
+) -> Result<SearchIndex, MutinyError> {
+    let mut index = SearchIndex::default();
+    for doc in docs {
+        index.insert(doc);
+    }
+    storage.set_data(SEARCH_INDEX_KEY, &index)?;
+    Ok(index)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::event::HTLCStatus;
+    use crate::labels::LabelStorage;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+    use bitcoin::hashes::{sha256, Hash};
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn invoice(preimage: u8, description: &str, amount_sats: u64, time: u64) -> MutinyInvoice {
+        MutinyInvoice {
+            bolt11: None,
+            description: Some(description.to_string()),
+            payment_hash: sha256::Hash::hash(&[preimage; 32]),
+            preimage: None,
+            payee_pubkey: None,
+            amount_sats: Some(amount_sats),
+            expire: time + 3_600,
+            status: HTLCStatus::Succeeded,
+            paid: true,
+            settled_externally: false,
+            external_settlement_note: None,
+            fees_paid: None,
+            inbound: true,
+            labels: vec![],
+            last_updated: time,
+        }
+    }
+
+    fn contact(name: &str, last_used: u64) -> Contact {
+        Contact {
+            name: name.to_string(),
+            npub: None,
+            ln_address: None,
+            lnurl: None,
+            archived: None,
+            last_used,
+        }
+    }
+
+    #[test]
+    fn test_search_index_matching() {
+        let test_name = "test_search_index_matching";
+        log!("{}", test_name);
+
+        let coffee = invoice(1, "Morning Coffee", 15_000, 100);
+        let lunch = invoice(2, "lunch with Bob", 42_000, 200);
+        let mut index = SearchIndex::default();
+        index.insert(SearchDocument::lightning(&coffee));
+        index.insert(SearchDocument::lightning(&lunch));
+
+        // words are case insensitive and must match exactly
+        let results = index.search("coffee", 10);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].kind, SearchResultKind::Lightning);
+        assert_eq!(results[0].id, coffee.payment_hash.to_hex());
+        assert!(index.search("coff", 10).is_empty());
+        assert_eq!(index.search("MORNING coffee", 10).len(), 1);
+        assert!(index.search("morning lunch", 10).is_empty());
+
+        // amounts
+        assert_eq!(
+            index.search("15000", 10)[0].id,
+            coffee.payment_hash.to_hex()
+        );
+        assert_eq!(index.search("42000", 10)[0].id, lunch.payment_hash.to_hex());
+
+        // hex ids match by prefix, short prefixes do not
+        let hash = lunch.payment_hash.to_hex();
+        assert_eq!(index.search(&format!("{}...", &hash[..8]), 10)[0].id, hash);
+        assert_eq!(index.search(&hash.to_uppercase(), 10)[0].id, hash);
+        assert!(index.search(&hash[..MIN_HEX_PREFIX_LEN - 1], 10).is_empty());
+
+        // NFC normalization, the decomposed form matches the composed one
+        index.insert(SearchDocument::lightning(&invoice(3, "caf\u{e9}", 1, 300)));
+        assert_eq!(index.search("cafe\u{301}", 10).len(), 1);
+
+        // removing the item removes its tokens
+        index.remove(SearchResultKind::Lightning, &coffee.payment_hash.to_hex());
+        assert!(index.search("coffee", 10).is_empty());
+        assert!(!index.words.contains_key("coffee"));
+    }
+
+    #[test]
+    fn test_search_index_ranking_and_fields() {
+        let test_name = "test_search_index_ranking_and_fields";
+        log!("{}", test_name);
+
+        let old = invoice(1, "pizza", 1_000, 100);
+        let new = invoice(2, "pizza again", 2_000, 300);
+        let mut index = SearchIndex::default();
+        index.insert(SearchDocument::lightning(&old));
+        index.insert(SearchDocument::lightning(&new));
+        index.insert(SearchDocument::contact(
+            "contact-id".to_string(),
+            &contact("Pizza Place", 200),
+        ));
+
+        // newest first, mixed types
+        let results = index.search("pizza", 10);
+        let ids: Vec<String> = results.iter().map(|r| r.id.clone()).collect();
+        let expected = vec![
+            new.payment_hash.to_hex(),
+            "contact-id".to_string(),
+            old.payment_hash.to_hex(),
+        ];
+        assert_eq!(ids, expected);
+        assert_eq!(results[1].kind, SearchResultKind::Contact);
+        assert_eq!(index.search("pizza", 2).len(), 2);
+
+        // labels are a separate field of the same item
+        let hash = old.payment_hash.to_hex();
+        index.insert(SearchDocument::lightning_labels(
+            hash.clone(),
+            vec!["dinner".to_string()],
+            100,
+        ));
+        assert_eq!(index.search("dinner pizza", 10)[0].id, hash);
+
+        // replacing the labels drops the old ones but keeps the details
+        index.insert(SearchDocument::lightning_labels(
+            hash.clone(),
+            vec!["party".to_string()],
+            100,
+        ));
+        assert!(index.search("dinner", 10).is_empty());
+        assert_eq!(index.search("party", 10)[0].id, hash);
+        assert_eq!(index.search("pizza", 10).len(), 3);
+    }
+
+    #[test]
+    fn test_search_index_bounded_vocabulary() {
+        let test_name = "test_search_index_bounded_vocabulary";
+        log!("{}", test_name);
+
+        let mut index = SearchIndex::default();
+        let words: Vec<String> = (0..MAX_INDEX_TOKENS + 10)
+            .map(|i| format!("w{i}"))
+            .collect();
+        index.insert(SearchDocument::lightning_labels("aa".to_string(), words, 1));
+        assert_eq!(index.token_count(), MAX_INDEX_TOKENS);
+
+        // words already in the vocabulary can still be used
+        index.insert(SearchDocument::lightning_labels(
+            "bb".to_string(),
+            vec!["w0".to_string()],
+            2,
+        ));
+        assert_eq!(index.search("w0", 10).len(), 2);
+    }
+
+    #[test]
+    fn test_search_index_updated_on_write() {
+        let test_name = "test_search_index_updated_on_write";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        rebuild_search_index(&storage, vec![]).unwrap();
+
+        let id = storage.create_new_contact(contact("Satoshi", 1)).unwrap();
+        let index = load_search_index(&storage).unwrap();
+        let results = index.search("satoshi", 10);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].kind, SearchResultKind::Contact);
+        assert_eq!(results[0].id, id);
+
+        storage.edit_contact(&id, contact("Hal", 2)).unwrap();
+        let index = load_search_index(&storage).unwrap();
+        assert!(index.search("satoshi", 10).is_empty());
+        assert_eq!(index.search("hal", 10)[0].id, id);
+
+        storage.archive_contact(&id).unwrap();
+        let index = load_search_index(&storage).unwrap();
+        assert!(index.search("hal", 10).is_empty());
+    }
+
+    #[test]
+    fn test_search_index_rebuild() {
+        let test_name = "test_search_index_rebuild";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        assert!(load_search_index(&storage).is_none());
+
+        // writes without an index do not create a partial one
+        storage.create_new_contact(contact("Satoshi", 1)).unwrap();
+        assert!(load_search_index(&storage).is_none());
+
+        // a corrupt index is treated as missing
+        storage.set_data(SEARCH_INDEX_KEY, "not an index").unwrap();
+        assert!(load_search_index(&storage).is_none());
+
+        let docs: Vec<SearchDocument> = storage
+            .get_contacts()
+            .unwrap()
+            .into_iter()
+            .map(|(id, c)| SearchDocument::contact(id, &c))
+            .collect();
+        let index = rebuild_search_index(&storage, docs).unwrap();
+        assert_eq!(index.search("satoshi", 10).len(), 1);
+
+        // the rebuilt index is saved
+        assert_eq!(load_search_index(&storage), Some(index));
+    }
+}
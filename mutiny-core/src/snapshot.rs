@@ -0,0 +1,275 @@
+//! Consistent reads of state that changes in several steps.
+//!
+//! Some changes move funds between parts of the wallet one part at a time, like
+//! funding a channel from the on-chain wallet. A balance read in between would count
+//! the funds twice. Writers wrap such changes in a [`Transition`], and readers use
+//! [`SnapshotSequence::read`], which only returns reads that no transition overlapped.
+//!
+//! A transition can last as long as a peer takes to answer, like a channel funding
+//! that waits for the peer to sign. Readers don't wait that long, they keep a
+//! [`LastSnapshot`] and get the last read back until the transition is done.
+
+use crate::error::MutinyError;
+use crate::utils;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// How long to wait for a transition to finish before reading again.
+const SNAPSHOT_RETRY_MILLIS: i32 = 50;
+
+/// How often to try before giving up, transitions only take as long as a few writes.
+const SNAPSHOT_MAX_ATTEMPTS: usize = 40;
+
+/// A read of the state between transitions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Snapshot<T> {
+    pub value: T,
+    /// Goes up with every transition, a read with a lower sequence is older
+    pub sequence: u64,
+    /// Epoch time in seconds the read was taken at
+    pub taken_at: u64,
+}
+
+/// The last read of a reader, returned while a transition outlasts the retries.
+#[derive(Debug)]
+pub(crate) struct LastSnapshot<T>(Mutex<Option<Snapshot<T>>>);
+
+impl<T> Default for LastSnapshot<T> {
+    fn default() -> Self {
+        Self(Mutex::new(None))
+    }
+}
+
+/// Counts the transitions so readers can tell if one overlapped their read.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SnapshotSequence {
+    sequence: Arc<AtomicU64>,
+    in_progress: Arc<AtomicUsize>,
+}
+
+/// A change in progress, readers retry until it is dropped.
+pub(crate) struct Transition {
+    sequence: Arc<AtomicU64>,
+    in_progress: Arc<AtomicUsize>,
+}
+
+impl Drop for Transition {
+    fn drop(&mut self) {
+        self.sequence.fetch_add(1, Ordering::SeqCst);
+        self.in_progress.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl SnapshotSequence {
+    /// Starts a change that readers must not see half of.
+    pub fn begin(&self) -> Transition {
+        self.in_progress.fetch_add(1, Ordering::SeqCst);
+        self.sequence.fetch_add(1, Ordering::SeqCst);
+        Transition {
+            sequence: self.sequence.clone(),
+            in_progress: self.in_progress.clone(),
+        }
+    }
+
+    /// The sequence if no transition is in progress.
+    fn idle_sequence(&self) -> Option<u64> {
+        let sequence = self.sequence.load(Ordering::SeqCst);
+        (self.in_progress.load(Ordering::SeqCst) == 0).then_some(sequence)
+    }
+
+    /// Reads the state, reading it again if a transition started or finished
+    /// while it was being read. If a transition does not finish in time the last
+    /// read is returned instead, the sequence and time of the snapshot tell how old
+    /// it is. Fails if there was no read before.
+    pub async fn read<T, F, Fut>(
+        &self,
+        last: &LastSnapshot<T>,
+        read: F,
+    ) -> Result<Snapshot<T>, MutinyError>
+    where
+        T: Clone,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, MutinyError>>,
+    {
+        let snapshot = self.try_read(read).await?;
+        let mut last = last.0.lock().unwrap();
+        match snapshot {
+            // a slower read can finish after a newer one, keep the newest
+            Some(snapshot)
+                if last
+                    .as_ref()
+                    .map_or(true, |l| l.sequence <= snapshot.sequence) =>
+            {
+                *last = Some(snapshot.clone());
+                Ok(snapshot)
+            }
+            Some(snapshot) => Ok(snapshot),
+            None => last.clone().ok_or(MutinyError::WalletOperationFailed),
+        }
+    }
+
+    /// Reads the state between transitions, None if a transition did not finish
+    /// within the retries.
+    async fn try_read<T, F, Fut>(&self, mut read: F) -> Result<Option<Snapshot<T>>, MutinyError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, MutinyError>>,
+    {
+        for _ in 0..SNAPSHOT_MAX_ATTEMPTS {
+            if let Some(before) = self.idle_sequence() {
+                let value = read().await?;
+                if self.idle_sequence() == Some(before) {
+                    return Ok(Some(Snapshot {
+                        value,
+                        sequence: before,
+                        taken_at: utils::now().as_secs(),
+                    }));
+                }
+            }
+            utils::sleep(SNAPSHOT_RETRY_MILLIS).await;
+        }
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::executor::block_on;
+
+    const TOTAL: u64 = 100_000;
+    const FUNDING: u64 = 40_000;
+
+    /// The on-chain and channel balances of a wallet that funds a channel.
+    struct MockWallet {
+        onchain: AtomicU64,
+        channel: AtomicU64,
+        sequence: SnapshotSequence,
+        transition: std::sync::Mutex<Option<Transition>>,
+        steps_done: AtomicUsize,
+    }
+
+    impl MockWallet {
+        fn new() -> Self {
+            Self {
+                onchain: AtomicU64::new(TOTAL),
+                channel: AtomicU64::new(0),
+                sequence: SnapshotSequence::default(),
+                transition: std::sync::Mutex::new(None),
+                steps_done: AtomicUsize::new(0),
+            }
+        }
+
+        /// Funding a channel: the channel gets the funds before the wallet sees them spent.
+        fn step(&self) {
+            match self.steps_done.fetch_add(1, Ordering::SeqCst) {
+                0 => *self.transition.lock().unwrap() = Some(self.sequence.begin()),
+                1 => {
+                    self.channel.fetch_add(FUNDING, Ordering::SeqCst);
+                }
+                2 => {
+                    self.onchain.fetch_sub(FUNDING, Ordering::SeqCst);
+                }
+                3 => *self.transition.lock().unwrap() = None,
+                _ => {}
+            }
+        }
+    }
+
+    const STEPS: usize = 4;
+
+    /// Reads the balance with the funding advanced by `during` steps between reading
+    /// the on-chain and the channel balance, like a read racing the funding would.
+    fn racing_read(wallet: &MockWallet, during: usize) -> u64 {
+        let onchain = wallet.onchain.load(Ordering::SeqCst);
+        for _ in 0..during {
+            wallet.step();
+        }
+        onchain + wallet.channel.load(Ordering::SeqCst)
+    }
+
+    #[test]
+    fn test_snapshot_never_double_counts() {
+        // without a snapshot some interleavings count the funding twice
+        let wallet = MockWallet::new();
+        wallet.step();
+        wallet.step();
+        assert_eq!(racing_read(&wallet, 1), TOTAL + FUNDING);
+
+        for before in 0..=STEPS {
+            for during in 0..=STEPS - before {
+                let wallet = Arc::new(MockWallet::new());
+                for _ in 0..before {
+                    wallet.step();
+                }
+                // the first read races the funding, the rest of it finishes meanwhile
+                let mut first = true;
+                let read = || {
+                    let during = if first { during } else { 0 };
+                    first = false;
+                    let total = racing_read(&wallet, during);
+                    async move { Ok(total) }
+                };
+                let funding = {
+                    let wallet = wallet.clone();
+                    std::thread::spawn(move || {
+                        std::thread::sleep(std::time::Duration::from_millis(10));
+                        while wallet.steps_done.load(Ordering::SeqCst) < STEPS {
+                            wallet.step();
+                        }
+                    })
+                };
+
+                let snapshot =
+                    block_on(wallet.sequence.read(&LastSnapshot::default(), read)).unwrap();
+                assert_eq!(snapshot.value, TOTAL, "before {before}, during {during}");
+                funding.join().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_snapshot_sequence() {
+        let sequence = SnapshotSequence::default();
+        let last = LastSnapshot::default();
+        let read = || async { Ok(()) };
+        let first = block_on(sequence.read(&last, read)).unwrap();
+        assert_eq!(first.sequence, 0);
+
+        // every transition moves the sequence on, so older reads can be discarded
+        drop(sequence.begin());
+        let second = block_on(sequence.read(&last, read)).unwrap();
+        assert!(second.sequence > first.sequence);
+
+        // without an earlier read, a transition that never finishes fails the read
+        // instead of returning half of it
+        let _transition = sequence.begin();
+        let res = block_on(sequence.read(&LastSnapshot::default(), read));
+        assert!(matches!(res, Err(MutinyError::WalletOperationFailed)));
+    }
+
+    #[test]
+    fn test_snapshot_outlived_by_transition() {
+        let sequence = SnapshotSequence::default();
+        let last = LastSnapshot::default();
+        let first = block_on(sequence.read(&last, || async { Ok(1) })).unwrap();
+        assert_eq!(first.value, 1);
+
+        // a channel funding waiting for the peer outlasts the retries, the last read
+        // is returned instead of an error
+        let transition = sequence.begin();
+        let during = block_on(sequence.read(&last, || async { Ok(2) })).unwrap();
+        assert_eq!(during, first);
+        drop(transition);
+
+        let after = block_on(sequence.read(&last, || async { Ok(3) })).unwrap();
+        assert_eq!(after.value, 3);
+        assert!(after.sequence > first.sequence);
+
+        // errors of the read itself are not hidden by the last read
+        let res = block_on(sequence.read(&last, || async { Err::<u64, _>(MutinyError::NotFound) }));
+        assert!(matches!(res, Err(MutinyError::NotFound)));
+    }
+}
@@ -1,6 +1,7 @@
+use crate::chunks;
 use crate::encrypt::{decrypt, encrypt};
 use crate::error::{MutinyError, MutinyStorageError};
-use crate::ldkstorage::CHANNEL_MANAGER_KEY;
+use crate::ldkstorage::{CHANNEL_MANAGER_KEY, MONITORS_PREFIX_KEY};
 use crate::lnurlauth::AuthProfile;
 use crate::nodemanager::NodeStorage;
 use anyhow::anyhow;
@@ -17,11 +18,48 @@ const NODES_KEY: &str = "nodes";
 const AUTH_PROFILES_KEY: &str = "auth_profiles";
 const FEE_ESTIMATES_KEY: &str = "fee_estimates";
 const FIRST_SYNC_KEY: &str = "first_sync";
+const LAST_CHAIN_SYNC_KEY: &str = "last_chain_sync";
+/// Corrupt records that could not be repaired are moved under this prefix,
+/// keeping their raw value for support.
+pub const QUARANTINE_PREFIX: &str = "quarantine/";
+
+/// Corruption under these keys must fail the read rather than be skipped,
+/// silently dropping them could lose funds.
+fn is_critical_key(key: &str) -> bool {
+    key.starts_with(MONITORS_PREFIX_KEY)
+}
+
+/// The keys of records that could not be read while listing them.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct QuarantineReport {
+    pub corrupt_keys: Vec<String>,
+}
+
+impl QuarantineReport {
+    pub fn count(&self) -> usize {
+        self.corrupt_keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.corrupt_keys.is_empty()
+    }
+}
+
+/// The result of trying to repair corrupt records.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    /// Keys that could be re-parsed and were rewritten in the current format
+    pub repaired: Vec<String>,
+    /// Keys that were moved under [`QUARANTINE_PREFIX`]
+    pub quarantined: Vec<String>,
+}
 
 fn needs_encryption(key: &str) -> bool {
     match key {
         MNEMONIC_KEY => true,
         str if str.starts_with(CHANNEL_MANAGER_KEY) => true,
+        // holds the secrets the webhooks are signed with
+        crate::webhooks::WEBHOOK_ENDPOINTS_KEY => true,
         _ => false,
     }
 }
@@ -82,6 +120,9 @@ pub trait MutinyStorage: Clone + Sized + 'static {
 
         let json: Value = encrypt_value(key.as_ref(), data, self.password())?;
 
+        if chunks::is_chunked_key(key.as_ref()) {
+            return chunks::set_chunked(self, key.as_ref(), json).map(|_| ());
+        }
         self.set(key, json)
     }
 
@@ -98,6 +139,7 @@ pub trait MutinyStorage: Clone + Sized + 'static {
         match self.get(&key)? {
             None => Ok(None),
             Some(value) => {
+                let value = chunks::reassemble(self, key.as_ref(), value)?;
                 let json: Value = decrypt_value(&key, value, self.password())?;
                 let data: T = serde_json::from_value(json)?;
                 Ok(Some(data))
@@ -123,20 +165,41 @@ pub trait MutinyStorage: Clone + Sized + 'static {
 
     /// Scan the storage for keys with a given prefix and suffix, and then gets their values
     fn scan<T>(&self, prefix: &str, suffix: Option<&str>) -> Result<HashMap<String, T>, MutinyError>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        self.scan_with_quarantine(prefix, suffix)
+            .map(|(values, _)| values)
+    }
+
+    /// Scan the storage for keys with a given prefix and suffix, and then gets their values.
+    ///
+    /// Values that can not be read are reported instead of failing the whole scan,
+    /// unless they are under a critical prefix like the channel monitors.
+    fn scan_with_quarantine<T>(
+        &self,
+        prefix: &str,
+        suffix: Option<&str>,
+    ) -> Result<(HashMap<String, T>, QuarantineReport), MutinyError>
     where
         T: for<'de> Deserialize<'de>,
     {
         let keys = self.scan_keys(prefix, suffix)?;
 
-        Ok(keys
-            .into_iter()
-            .filter_map(|key| {
-                self.get_data(&key)
-                    .ok()
-                    .flatten()
-                    .map(|value: T| (key, value))
-            })
-            .collect())
+        let mut values = HashMap::with_capacity(keys.len());
+        let mut report = QuarantineReport::default();
+        for key in keys {
+            match self.get_data(&key) {
+                Ok(Some(value)) => {
+                    values.insert(key, value);
+                }
+                Ok(None) => {}
+                Err(e) if is_critical_key(&key) => return Err(e),
+                Err(_) => report.corrupt_keys.push(key),
+            }
+        }
+
+        Ok((values, report))
     }
 
     /// Insert a mnemonic into the storage
@@ -216,6 +279,16 @@ pub trait MutinyStorage: Clone + Sized + 'static {
     fn set_done_first_sync(&self) -> Result<(), MutinyError> {
         self.set_data(FIRST_SYNC_KEY, true)
     }
+
+    /// Get the epoch time in seconds of the last successful chain sync
+    fn get_last_chain_sync(&self) -> Result<Option<u64>, MutinyError> {
+        self.get_data(LAST_CHAIN_SYNC_KEY)
+    }
+
+    /// Set the epoch time in seconds of the last successful chain sync
+    fn set_last_chain_sync(&self, time: u64) -> Result<(), MutinyError> {
+        self.set_data(LAST_CHAIN_SYNC_KEY, time)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -411,6 +484,7 @@ where
 
 #[cfg(test)]
 mod tests {
+    use crate::ldkstorage::MONITORS_PREFIX_KEY;
     use crate::storage::MemoryStorage;
     use crate::test_utils::*;
     use crate::{keymanager, storage::MutinyStorage};
@@ -446,4 +520,56 @@ mod tests {
         let stored_mnemonic = storage.get_mnemonic().unwrap();
         assert_eq!(mnemonic, stored_mnemonic);
     }
+
+    #[test]
+    fn scan_reports_corrupt_values() {
+        let test_name = "scan_reports_corrupt_values";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::new(None);
+        storage.set_data("prefix/a", 1u32).unwrap();
+        storage.set_data("prefix/b", 2u32).unwrap();
+        storage.set("prefix/c", "not a number").unwrap();
+
+        let (values, report) = storage
+            .scan_with_quarantine::<u32>("prefix/", None)
+            .unwrap();
+        assert_eq!(values.len(), 2);
+        assert_eq!(report.corrupt_keys, vec!["prefix/c".to_string()]);
+
+        // corrupt monitors must still fail
+        storage
+            .set(format!("{MONITORS_PREFIX_KEY}corrupt"), "not bytes")
+            .unwrap();
+        assert!(storage.scan::<Vec<u8>>(MONITORS_PREFIX_KEY, None).is_err());
+    }
+
+    #[test]
+    fn scan_keys_skips_values() {
+        let test_name = "scan_keys_skips_values";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::new(None);
+        storage.set_data("prefix/a", 1u32).unwrap();
+        storage.set_data("prefix/b", "text").unwrap();
+        storage.set("prefix/c", "not json").unwrap();
+        storage.set_data("prefix_other", 3u32).unwrap();
+        storage.set_data("other/a", 4u32).unwrap();
+
+        // values of any type, or corrupt ones, do not matter for the keys
+        let mut keys = storage.scan_keys("prefix/", None).unwrap();
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec![
+                "prefix/a".to_string(),
+                "prefix/b".to_string(),
+                "prefix/c".to_string()
+            ]
+        );
+
+        let keys = storage.scan_keys("prefix/", Some("/c")).unwrap();
+        assert_eq!(keys, vec!["prefix/c".to_string()]);
+        assert!(storage.scan_keys("missing/", None).unwrap().is_empty());
+    }
 }
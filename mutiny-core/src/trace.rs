@@ -0,0 +1,379 @@
+use crate::capabilities::Capability;
+use crate::utils;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::Arc;
+
+pub(crate) const TRACE_CAPABILITY: Capability = Capability::flag("operation_traces");
+
+/// Number of operation traces that are kept by default.
+pub const DEFAULT_TRACE_CAPACITY: usize = 100;
+
+/// How much is recorded for each traced operation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TraceVerbosity {
+    /// Nothing is recorded
+    Off,
+    /// Only the start, duration and outcome of each operation
+    #[default]
+    Coarse,
+    /// Also the phases of each operation, such as sending a payment to
+    /// the first hop and waiting for it to resolve
+    Detailed,
+}
+
+/// How an operation or one of its phases ended.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TraceOutcome {
+    Success,
+    Failure(String),
+    /// The operation was dropped before it finished, like a timed out future
+    Cancelled,
+}
+
+impl TraceOutcome {
+    fn from_result<T, E: Display>(result: &Result<T, E>) -> Self {
+        match result {
+            Ok(_) => Self::Success,
+            Err(e) => Self::Failure(e.to_string()),
+        }
+    }
+}
+
+/// A timed phase of an operation, phases can be nested.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceSpan {
+    pub name: String,
+    /// Epoch time in milliseconds
+    pub started_at: u64,
+    pub duration_ms: u64,
+    pub outcome: TraceOutcome,
+    pub children: Vec<TraceSpan>,
+}
+
+/// The trace of a finished operation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OperationTrace {
+    /// The id returned by the call that started the operation, like the payment
+    /// hash of a payment. Operations without an id use their name.
+    pub operation_id: String,
+    pub name: String,
+    /// Epoch time in milliseconds
+    pub started_at: u64,
+    pub duration_ms: u64,
+    pub outcome: TraceOutcome,
+    /// The phases of the operation, only recorded with [`TraceVerbosity::Detailed`]
+    pub phases: Vec<TraceSpan>,
+    /// Extra details of the operation, like the privacy settings applied to a payment
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+}
+
+/// Keeps the traces of the last operations in a ring buffer.
+#[derive(Clone)]
+pub(crate) struct OperationTracer {
+    verbosity: TraceVerbosity,
+    capacity: usize,
+    traces: Arc<utils::Mutex<VecDeque<OperationTrace>>>,
+}
+
+impl OperationTracer {
+    pub fn new(verbosity: TraceVerbosity, capacity: usize) -> Self {
+        Self {
+            verbosity,
+            capacity,
+            traces: Arc::new(utils::Mutex::new(VecDeque::with_capacity(capacity))),
+        }
+    }
+
+    /// Starts tracing an operation, the returned span records it when finished
+    pub fn start(&self, name: &str, operation_id: Option<String>) -> OperationSpan {
+        self.start_at(name, operation_id, now_ms())
+    }
+
+    fn start_at(&self, name: &str, operation_id: Option<String>, now: u64) -> OperationSpan {
+        let trace = (self.verbosity != TraceVerbosity::Off).then(|| OperationTrace {
+            operation_id: operation_id.unwrap_or_else(|| name.to_string()),
+            name: name.to_string(),
+            started_at: now,
+            duration_ms: 0,
+            outcome: TraceOutcome::Cancelled,
+            phases: vec![],
+            annotations: BTreeMap::new(),
+        });
+
+        OperationSpan {
+            tracer: self.clone(),
+            trace,
+            open_phases: vec![],
+        }
+    }
+
+    /// Returns the latest trace of the operation with the given id
+    pub fn get(&self, operation_id: &str) -> Option<OperationTrace> {
+        let traces = self.traces.lock().expect("Failed to lock traces");
+        traces
+            .iter()
+            .rev()
+            .find(|t| t.operation_id == operation_id)
+            .cloned()
+    }
+
+    /// Returns the traces that are kept, oldest first
+    pub fn recent(&self) -> Vec<OperationTrace> {
+        let traces = self.traces.lock().expect("Failed to lock traces");
+        traces.iter().cloned().collect()
+    }
+
+    fn record(&self, trace: OperationTrace) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut traces = self.traces.lock().expect("Failed to lock traces");
+        while traces.len() >= self.capacity {
+            traces.pop_front();
+        }
+        traces.push_back(trace);
+    }
+}
+
+impl Default for OperationTracer {
+    fn default() -> Self {
+        Self::new(TraceVerbosity::default(), DEFAULT_TRACE_CAPACITY)
+    }
+}
+
+/// An operation that is being traced. It is recorded when finished, or as
+/// [`TraceOutcome::Cancelled`] if it is dropped before that.
+pub(crate) struct OperationSpan {
+    tracer: OperationTracer,
+    trace: Option<OperationTrace>,
+    open_phases: Vec<TraceSpan>,
+}
+
+impl OperationSpan {
+    /// Sets the id for operations that only get one once they are underway,
+    /// like the payment hash of a new invoice
+    pub fn set_operation_id(&mut self, operation_id: String) {
+        if let Some(trace) = self.trace.as_mut() {
+            trace.operation_id = operation_id;
+        }
+    }
+
+    /// Adds a detail to the operation, replacing an earlier value for the key
+    pub fn annotate(&mut self, key: &str, value: String) {
+        if let Some(trace) = self.trace.as_mut() {
+            trace.annotations.insert(key.to_string(), value);
+        }
+    }
+
+    fn is_detailed(&self) -> bool {
+        self.trace.is_some() && self.tracer.verbosity == TraceVerbosity::Detailed
+    }
+
+    /// Starts a phase, nested in the current phase if there is one
+    pub fn enter(&mut self, phase: &str) {
+        // coarse spans skip the clock entirely
+        if self.is_detailed() {
+            self.enter_at(phase, now_ms())
+        }
+    }
+
+    fn enter_at(&mut self, phase: &str, now: u64) {
+        if !self.is_detailed() {
+            return;
+        }
+        self.open_phases.push(TraceSpan {
+            name: phase.to_string(),
+            started_at: now,
+            duration_ms: 0,
+            outcome: TraceOutcome::Cancelled,
+            children: vec![],
+        });
+    }
+
+    /// Ends the current phase with the outcome of the given result
+    pub fn exit<T, E: Display>(&mut self, result: &Result<T, E>) {
+        if !self.open_phases.is_empty() {
+            self.exit_at(TraceOutcome::from_result(result), now_ms())
+        }
+    }
+
+    fn exit_at(&mut self, outcome: TraceOutcome, now: u64) {
+        let Some(mut phase) = self.open_phases.pop() else {
+            return;
+        };
+        phase.duration_ms = now.saturating_sub(phase.started_at);
+        phase.outcome = outcome;
+
+        match (self.open_phases.last_mut(), self.trace.as_mut()) {
+            (Some(parent), _) => parent.children.push(phase),
+            (None, Some(trace)) => trace.phases.push(phase),
+            (None, None) => {}
+        }
+    }
+
+    /// Runs the given future as a phase
+    pub async fn phase<T, E: Display>(
+        &mut self,
+        phase: &str,
+        fut: impl Future<Output = Result<T, E>>,
+    ) -> Result<T, E> {
+        self.enter(phase);
+        let res = fut.await;
+        self.exit(&res);
+        res
+    }
+
+    /// Records the operation with the outcome of the given result
+    pub fn finish<T, E: Display>(mut self, result: &Result<T, E>) {
+        self.finish_at(TraceOutcome::from_result(result), now_ms())
+    }
+
+    fn finish_at(&mut self, outcome: TraceOutcome, now: u64) {
+        // phases that were not exited did not finish
+        while !self.open_phases.is_empty() {
+            self.exit_at(TraceOutcome::Cancelled, now);
+        }
+
+        if let Some(mut trace) = self.trace.take() {
+            trace.duration_ms = now.saturating_sub(trace.started_at);
+            trace.outcome = outcome;
+            self.tracer.record(trace);
+        }
+    }
+}
+
+impl Drop for OperationSpan {
+    fn drop(&mut self) {
+        if self.trace.is_some() {
+            self.finish_at(TraceOutcome::Cancelled, now_ms());
+        }
+    }
+}
+
+fn now_ms() -> u64 {
+    utils::now().as_millis() as u64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::MutinyError;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    const NOW: u64 = 1_700_000_000_000;
+
+    #[test]
+    fn test_trace_payment_phases() {
+        let test_name = "test_trace_payment_phases";
+        log!("{}", test_name);
+
+        let tracer = OperationTracer::new(TraceVerbosity::Detailed, 10);
+        let mut span = tracer.start_at("pay_invoice", Some("hash".to_string()), NOW);
+        span.enter_at("send_payment", NOW + 5);
+        span.enter_at("find_route", NOW + 10);
+        span.exit_at(TraceOutcome::Success, NOW + 40);
+        span.enter_at("send_to_peer", NOW + 40);
+        span.exit_at(TraceOutcome::Success, NOW + 45);
+        span.exit_at(TraceOutcome::Success, NOW + 50);
+        span.enter_at("await_payment", NOW + 50);
+        span.exit_at(
+            TraceOutcome::from_result::<(), _>(&Err(MutinyError::PaymentTimeout)),
+            NOW + 9_000,
+        );
+        span.finish_at(TraceOutcome::Failure("timeout".to_string()), NOW + 9_001);
+        drop(span);
+
+        let trace = tracer.get("hash").unwrap();
+        assert_eq!(trace.name, "pay_invoice");
+        assert_eq!(trace.started_at, NOW);
+        assert_eq!(trace.duration_ms, 9_001);
+        assert_eq!(trace.outcome, TraceOutcome::Failure("timeout".to_string()));
+
+        let names: Vec<&str> = trace.phases.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["send_payment", "await_payment"]);
+
+        let send = &trace.phases[0];
+        assert_eq!(send.duration_ms, 45);
+        assert_eq!(send.outcome, TraceOutcome::Success);
+        let children: Vec<(&str, u64)> = send
+            .children
+            .iter()
+            .map(|c| (c.name.as_str(), c.duration_ms))
+            .collect();
+        assert_eq!(children, vec![("find_route", 30), ("send_to_peer", 5)]);
+
+        let wait = &trace.phases[1];
+        assert_eq!(wait.duration_ms, 8_950);
+        assert_eq!(
+            wait.outcome,
+            TraceOutcome::Failure(MutinyError::PaymentTimeout.to_string())
+        );
+        assert!(wait.children.is_empty());
+
+        // only the recorded trace is kept
+        assert_eq!(tracer.recent(), vec![trace]);
+        assert!(tracer.get("other").is_none());
+    }
+
+    #[test]
+    fn test_trace_verbosity() {
+        let test_name = "test_trace_verbosity";
+        log!("{}", test_name);
+
+        // coarse spans do not record phases, but keep annotations
+        let tracer = OperationTracer::default();
+        let mut span = tracer.start_at("sync", None, NOW);
+        span.enter_at("lightning_sync", NOW);
+        span.exit_at(TraceOutcome::Success, NOW + 10);
+        span.annotate("nodes", "2".to_string());
+        span.finish_at(TraceOutcome::Success, NOW + 20);
+        drop(span);
+        let trace = tracer.get("sync").unwrap();
+        assert_eq!(trace.duration_ms, 20);
+        assert!(trace.phases.is_empty());
+        assert_eq!(trace.annotations.get("nodes"), Some(&"2".to_string()));
+
+        // dropped spans are cancelled, and the id can be set late
+        let mut span = tracer.start("create_invoice", None);
+        span.set_operation_id("hash".to_string());
+        drop(span);
+        assert_eq!(tracer.get("hash").unwrap().outcome, TraceOutcome::Cancelled);
+
+        // nothing is recorded when tracing is off
+        let tracer = OperationTracer::new(TraceVerbosity::Off, 10);
+        tracer
+            .start("sync", None)
+            .finish::<(), MutinyError>(&Ok(()));
+        assert!(tracer.recent().is_empty());
+    }
+
+    #[test]
+    fn test_trace_ring_buffer_eviction() {
+        let test_name = "test_trace_ring_buffer_eviction";
+        log!("{}", test_name);
+
+        let tracer = OperationTracer::new(TraceVerbosity::Coarse, 3);
+        for i in 0..5 {
+            let mut span = tracer.start_at("pay_invoice", Some(i.to_string()), NOW + i);
+            span.finish_at(TraceOutcome::Success, NOW + i + 1);
+        }
+
+        let ids: Vec<String> = tracer
+            .recent()
+            .into_iter()
+            .map(|t| t.operation_id)
+            .collect();
+        assert_eq!(ids, vec!["2", "3", "4"]);
+        assert!(tracer.get("0").is_none());
+        assert!(tracer.get("1").is_none());
+        assert!(tracer.get("4").is_some());
+    }
+}
@@ -1,6 +1,7 @@
 use bitcoin::Network;
 use lightning_invoice::ParseOrSemanticError;
 use mutiny_core::error::{MutinyError, MutinyStorageError};
+use mutiny_core::lifecycle::WalletComponent;
 use thiserror::Error;
 use wasm_bindgen::JsValue;
 
@@ -21,9 +22,15 @@ pub enum MutinyJsError {
     /// A network connection has been closed.
     #[error("Network connection closed.")]
     ConnectionFailed,
+    /// A host name could not be resolved
+    #[error("Failed to resolve the host name.")]
+    DnsResolutionFailed,
     /// The invoice or address is on a different network
     #[error("The invoice or address is on a different network.")]
     IncorrectNetwork(Network),
+    /// The backup was created on a different network than the wallet
+    #[error("The backup was created on {found} but this wallet is on {expected}.")]
+    NetworkMismatch { expected: Network, found: Network },
     /// Payment of the given invoice has already been initiated.
     #[error("An invoice must not get payed twice.")]
     NonUniquePaymentHash,
@@ -33,6 +40,12 @@ pub enum MutinyJsError {
     /// The given invoice is invalid.
     #[error("The given invoice is invalid.")]
     InvoiceInvalid,
+    /// The given preimage does not hash to the payment hash.
+    #[error("The preimage does not match the payment hash.")]
+    PreimageMismatch,
+    /// The invoice was already paid.
+    #[error("The invoice has already been settled.")]
+    InvoiceAlreadySettled,
     /// Invoice creation failed.
     #[error("Failed to create invoice.")]
     InvoiceCreationFailed,
@@ -67,6 +80,9 @@ pub enum MutinyJsError {
     /// No route for the given target could be found.
     #[error("Failed to find route.")]
     RoutingFailed,
+    /// The payee has no public channels and none of the hinted peers are routable.
+    #[error("Destination unreachable: no public channels and hinted peers not routable.")]
+    DestinationUnreachable,
     /// A given peer info could not be parsed.
     #[error("Failed to parse the given peer information.")]
     PeerInfoParseFailed,
@@ -89,6 +105,16 @@ pub enum MutinyJsError {
     /// User provided invalid mnemonic.
     #[error("Invalid mnemonic")]
     InvalidMnemonic,
+    /// The backup is encrypted with a passphrase instead of the seed.
+    #[error("This backup requires a passphrase to decrypt.")]
+    PassphraseRequired,
+    /// User provided the wrong passphrase for a backup.
+    #[error("Incorrect passphrase")]
+    IncorrectPassphrase,
+    /// An imported backup could not be decrypted, the key is wrong or the backup
+    /// was modified.
+    #[error("Failed to decrypt the backup.")]
+    BackupDecryptionFailed,
     /// A wallet operation failed.
     #[error("Failed to conduct wallet operation.")]
     WalletOperationFailed,
@@ -101,6 +127,70 @@ pub enum MutinyJsError {
     /// A failure to sync the on-chain wallet
     #[error("Failed to to sync on-chain wallet.")]
     WalletSyncError,
+    /// Strict mode requires a more recent chain sync for this operation
+    #[error("The chain has not been synced recently, try again after the next sync.")]
+    ChainSyncStale,
+    /// The congestion breaker deferred this operation until fees come down
+    #[error("On-chain fees are too high right now, try again once they come down.")]
+    ChannelOperationDeferred,
+    /// Too many messages are queued for a disconnected peer
+    #[error("Too many messages are queued for this peer, try again once it reconnects.")]
+    PeerMessageQueueFull,
+    /// The backup does not fit in a message to a peer
+    #[error("The backup is too large to be stored with a peer.")]
+    PeerBackupTooLarge,
+    /// None of the peers had a backup for us
+    #[error("No backup was found with the given peer.")]
+    PeerBackupNotFound,
+    /// Backups are not exchanged with the peer
+    #[error("Backups are not exchanged with this peer.")]
+    PeerBackupNotPermitted,
+    /// None of the relays accepted the backup
+    #[error("No relay accepted the backup.")]
+    NostrBackupFailed,
+    /// None of the relays had a backup for us
+    #[error("No backup was found on the given relays.")]
+    NostrBackupNotFound,
+    /// The remote backup endpoint did not store or return the backup
+    #[error("The remote backup endpoint failed.")]
+    RemoteBackupFailed,
+    /// The remote backup endpoint has no backup for us yet
+    #[error("No backup was found on the remote endpoint.")]
+    RemoteBackupNotFound,
+    /// The remote backup endpoint has a newer backup than the one sent
+    #[error("The remote endpoint has a newer backup.")]
+    RemoteBackupStale,
+    /// The webhook endpoint is not a public HTTPS url
+    #[error("Webhooks can only be sent to public HTTPS endpoints.")]
+    WebhookUrlRejected,
+    /// The webhook endpoint could not be reached or did not accept the event
+    #[error("Failed to deliver the webhook.")]
+    WebhookFailed,
+    /// The backup diff was not created from this backup
+    #[error("The backup diff does not apply to this backup.")]
+    ScbDiffConflict,
+    /// The backup could not be parsed, with the position of the first invalid
+    /// character if a single one is to blame
+    #[error(
+        "The backup could not be parsed{}.",
+        .position.map(|p| format!(", check the character at position {p}")).unwrap_or_default()
+    )]
+    ScbParse { position: Option<usize> },
+    /// The wallet has not finished starting up
+    #[error("The wallet is still starting up ({completed_steps}/{total_steps} steps done).")]
+    WalletInitializing {
+        completed_steps: usize,
+        total_steps: usize,
+    },
+    /// The wallet is being stopped
+    #[error("The wallet is shutting down.")]
+    WalletStopping,
+    /// The wallet was stopped and has to be started again
+    #[error("The wallet has been stopped, start it again first.")]
+    WalletStopped,
+    /// A part of the wallet failed to start, the rest is still usable
+    #[error("{0} is unavailable because it failed to start.")]
+    ComponentUnavailable(WalletComponent),
     /// An error with rapid gossip sync
     #[error("Failed to execute a rapid gossip sync function")]
     RapidGossipSyncError,
@@ -138,18 +228,25 @@ impl From<MutinyError> for MutinyJsError {
             MutinyError::NotFound => MutinyJsError::NotFound,
             MutinyError::FundingTxCreationFailed => MutinyJsError::FundingTxCreationFailed,
             MutinyError::ConnectionFailed => MutinyJsError::ConnectionFailed,
+            MutinyError::DnsResolutionFailed => MutinyJsError::DnsResolutionFailed,
             MutinyError::IncorrectNetwork(net) => MutinyJsError::IncorrectNetwork(net),
+            MutinyError::NetworkMismatch { expected, found } => {
+                MutinyJsError::NetworkMismatch { expected, found }
+            }
             MutinyError::NonUniquePaymentHash => MutinyJsError::NonUniquePaymentHash,
             MutinyError::PaymentTimeout => MutinyJsError::PaymentTimeout,
             MutinyError::InvoiceInvalid => MutinyJsError::InvoiceInvalid,
+            MutinyError::PreimageMismatch => MutinyJsError::PreimageMismatch,
+            MutinyError::InvoiceAlreadySettled => MutinyJsError::InvoiceAlreadySettled,
             MutinyError::InvoiceCreationFailed => MutinyJsError::InvoiceCreationFailed,
             MutinyError::ReserveAmountError => MutinyJsError::ReserveAmountError,
-            MutinyError::InsufficientBalance => MutinyJsError::InsufficientBalance,
+            MutinyError::InsufficientBalance { .. } => MutinyJsError::InsufficientBalance,
             MutinyError::LnUrlFailure => MutinyJsError::LnUrlFailure,
             MutinyError::LspGenericError => MutinyJsError::LspGenericError,
             MutinyError::LspFundingError => MutinyJsError::LspFundingError,
             MutinyError::LspConnectionError => MutinyJsError::LspConnectionError,
             MutinyError::RoutingFailed => MutinyJsError::RoutingFailed,
+            MutinyError::DestinationUnreachable => MutinyJsError::DestinationUnreachable,
             MutinyError::PeerInfoParseFailed => MutinyJsError::PeerInfoParseFailed,
             MutinyError::ChannelCreationFailed => MutinyJsError::ChannelCreationFailed,
             MutinyError::ChannelClosingFailed => MutinyJsError::ChannelClosingFailed,
@@ -159,9 +256,37 @@ impl From<MutinyError> for MutinyJsError {
             MutinyError::SeedGenerationFailed => MutinyJsError::SeedGenerationFailed,
             MutinyError::WalletOperationFailed => MutinyJsError::WalletOperationFailed,
             MutinyError::InvalidMnemonic => MutinyJsError::InvalidMnemonic,
+            MutinyError::PassphraseRequired => MutinyJsError::PassphraseRequired,
+            MutinyError::IncorrectPassphrase => MutinyJsError::IncorrectPassphrase,
+            MutinyError::BackupDecryptionFailed => MutinyJsError::BackupDecryptionFailed,
             MutinyError::WalletSigningFailed => MutinyJsError::WalletSigningFailed,
             MutinyError::ChainAccessFailed => MutinyJsError::ChainAccessFailed,
             MutinyError::WalletSyncError => MutinyJsError::WalletSyncError,
+            MutinyError::ChainSyncStale => MutinyJsError::ChainSyncStale,
+            MutinyError::ChannelOperationDeferred => MutinyJsError::ChannelOperationDeferred,
+            MutinyError::PeerMessageQueueFull { .. } => MutinyJsError::PeerMessageQueueFull,
+            MutinyError::PeerBackupTooLarge { .. } => MutinyJsError::PeerBackupTooLarge,
+            MutinyError::PeerBackupNotFound => MutinyJsError::PeerBackupNotFound,
+            MutinyError::PeerBackupNotPermitted => MutinyJsError::PeerBackupNotPermitted,
+            MutinyError::NostrBackupFailed => MutinyJsError::NostrBackupFailed,
+            MutinyError::NostrBackupNotFound => MutinyJsError::NostrBackupNotFound,
+            MutinyError::RemoteBackupFailed => MutinyJsError::RemoteBackupFailed,
+            MutinyError::RemoteBackupNotFound => MutinyJsError::RemoteBackupNotFound,
+            MutinyError::RemoteBackupStale => MutinyJsError::RemoteBackupStale,
+            MutinyError::WebhookUrlRejected => MutinyJsError::WebhookUrlRejected,
+            MutinyError::WebhookFailed => MutinyJsError::WebhookFailed,
+            MutinyError::ScbDiffConflict => MutinyJsError::ScbDiffConflict,
+            MutinyError::ScbParse { position } => MutinyJsError::ScbParse { position },
+            MutinyError::WalletInitializing {
+                completed_steps,
+                total_steps,
+            } => MutinyJsError::WalletInitializing {
+                completed_steps,
+                total_steps,
+            },
+            MutinyError::WalletStopping => MutinyJsError::WalletStopping,
+            MutinyError::WalletStopped => MutinyJsError::WalletStopped,
+            MutinyError::ComponentUnavailable(c) => MutinyJsError::ComponentUnavailable(c),
             MutinyError::RapidGossipSyncError => MutinyJsError::RapidGossipSyncError,
             MutinyError::DLCManagerError => MutinyJsError::DLCManagerError,
             MutinyError::PubkeyInvalid => MutinyJsError::PubkeyInvalid,
@@ -4,6 +4,7 @@ use gloo_utils::format::JsValueSerdeExt;
 use lightning::util::logger::Logger;
 use lightning::{log_debug, log_error};
 use log::error;
+use mutiny_core::chunks::is_chunk_key;
 use mutiny_core::error::{MutinyError, MutinyStorageError};
 use mutiny_core::logging::MutinyLogger;
 use mutiny_core::storage::{MutinyStorage, KEYCHAIN_STORE_KEY};
@@ -225,17 +226,20 @@ impl IndexedDbStorage {
 /// to save memory.
 ///
 /// We also need to skip writing them to the in memory storage on updates.
+///
+/// The network graph and scorer are split into chunks, only the chunks are dropped.
+/// Their keys hold the manifest of the chunks, which is needed for the next write.
 fn used_once(key: &str) -> bool {
-    matches!(
-        key,
-        NETWORK_GRAPH_KEY | PROB_SCORER_KEY | GOSSIP_SYNC_TIME_KEY | KEYCHAIN_STORE_KEY
-    )
+    matches!(key, GOSSIP_SYNC_TIME_KEY | KEYCHAIN_STORE_KEY) || is_chunk_key(key)
 }
 
 /// To help prevent force closes we save to local storage as well as indexed db.
 /// This is because indexed db is not always reliable.
 ///
-/// We need to do this for the channel manager and channel monitors.
+/// We need to do this for the channel manager and channel monitors. Both slots of
+/// the channel manager are mirrored, which doubles the space it takes in local
+/// storage. That is on purpose: the newest slot is loaded and the other one is
+/// the fallback if it can not be decoded, so both have to survive indexed db.
 fn write_to_local_storage(key: &str) -> bool {
     match key {
         str if str.starts_with(CHANNEL_MANAGER_KEY) => true,
@@ -18,6 +18,7 @@ use bitcoin::consensus::deserialize;
 use bitcoin::hashes::hex::FromHex;
 use bitcoin::hashes::sha256;
 use bitcoin::secp256k1::PublicKey;
+use bitcoin::util::bip32::ExtendedPrivKey;
 use bitcoin::{Address, Network, OutPoint, Transaction, Txid};
 use gloo_utils::format::JsValueSerdeExt;
 use lightning::routing::gossip::NodeId;
@@ -25,8 +26,10 @@ use lightning_invoice::Invoice;
 use lnurl::lnurl::LnUrl;
 use mutiny_core::nostr::nwc::NwcProfile;
 use mutiny_core::redshift::RedshiftManager;
-use mutiny_core::scb::EncryptedSCB;
+use mutiny_core::scb::message_handler::PeerBackupPermissions;
+use mutiny_core::scb::{EncryptedSCB, ScbRestoreProgress};
 use mutiny_core::storage::MutinyStorage;
+use mutiny_core::webhooks::WebhookEventKind;
 use mutiny_core::{labels::LabelStorage, nodemanager::NodeManager};
 use mutiny_core::{logging::MutinyLogger, nostr::ProfileType};
 use mutiny_core::{nodemanager, redshift::RedshiftRecipient};
@@ -43,6 +46,18 @@ pub struct MutinyWallet {
     inner: mutiny_core::MutinyWallet<IndexedDbStorage>,
 }
 
+impl MutinyWallet {
+    /// Fails fast with the lifecycle state unless the wallet is running
+    fn check_running(&self) -> Result<(), MutinyJsError> {
+        Ok(self.inner.node_manager.check_running()?)
+    }
+
+    /// Fails fast unless the wallet is running and lightning started
+    fn check_lightning(&self) -> Result<(), MutinyJsError> {
+        Ok(self.inner.node_manager.check_lightning()?)
+    }
+}
+
 /// The [MutinyWallet] is the main entry point for interacting with the Mutiny Wallet.
 /// It is responsible for managing the on-chain wallet and the lightning nodes.
 ///
@@ -55,6 +70,9 @@ impl MutinyWallet {
     /// Creates a new [MutinyWallet] with the given parameters.
     /// The mnemonic seed is read from storage, unless one is provided.
     /// If no mnemonic is provided, a new one is generated and stored.
+    ///
+    /// Pass a [`WalletLifecycle::handle`] to follow the startup progress
+    /// while the wallet is being constructed.
     #[wasm_bindgen(constructor)]
     #[allow(clippy::too_many_arguments)]
     pub async fn new(
@@ -68,6 +86,8 @@ impl MutinyWallet {
         auth_url: Option<String>,
         subscription_url: Option<String>,
         do_not_connect_peers: Option<bool>,
+        scb_lsp_backup: Option<bool>,
+        lifecycle: Option<WalletLifecycle>,
     ) -> Result<MutinyWallet, MutinyJsError> {
         utils::set_panic_hook();
 
@@ -96,6 +116,15 @@ impl MutinyWallet {
             config = config.with_do_not_connect_peers();
         }
 
+        // the static channel backup is only stored with the LSP if opted in
+        if let Some(true) = scb_lsp_backup {
+            config = config.with_scb_lsp_backup();
+        }
+
+        if let Some(lifecycle) = lifecycle {
+            config = config.with_lifecycle(lifecycle.into());
+        }
+
         let inner = mutiny_core::MutinyWallet::new(storage, config).await?;
         Ok(MutinyWallet { inner })
     }
@@ -125,10 +154,23 @@ impl MutinyWallet {
         Ok(self.inner.node_manager.stop().await?)
     }
 
+    /// Returns where the wallet is in its lifecycle. Calls fail fast with
+    /// [`MutinyJsError::WalletStopping`] or [`MutinyJsError::WalletStopped`] while it is
+    /// not running, and lightning calls with [`MutinyJsError::ComponentUnavailable`] if
+    /// lightning failed to start. Startup can only be followed with a [`WalletLifecycle`],
+    /// since the wallet can not be called before it is constructed.
+    #[wasm_bindgen]
+    pub fn get_lifecycle_state(&self) -> Result<JsValue /* LifecycleState */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.node_manager.lifecycle_state(),
+        )?)
+    }
+
     /// Broadcast a transaction to the network.
     /// The transaction is broadcast through the configured esplora server.
     #[wasm_bindgen]
     pub async fn broadcast_transaction(&self, str: String) -> Result<(), MutinyJsError> {
+        self.check_running()?;
         let tx_bytes =
             Vec::from_hex(str.as_str()).map_err(|_| MutinyJsError::WalletOperationFailed)?;
         let tx: Transaction =
@@ -157,6 +199,7 @@ impl MutinyWallet {
         &self,
         labels: JsValue, /* Vec<String> */
     ) -> Result<String, MutinyJsError> {
+        self.check_running()?;
         let labels: Vec<String> = labels
             .into_serde()
             .map_err(|_| MutinyJsError::InvalidArgumentsError)?;
@@ -166,6 +209,7 @@ impl MutinyWallet {
     /// Gets the current balance of the on-chain wallet.
     #[wasm_bindgen]
     pub fn get_wallet_balance(&self) -> Result<u64, MutinyJsError> {
+        self.check_running()?;
         Ok(self.inner.node_manager.get_wallet_balance()?)
     }
 
@@ -197,6 +241,7 @@ impl MutinyWallet {
         amount: Option<u64>,
         labels: JsValue, /* Vec<String> */
     ) -> Result<MutinyBip21RawMaterials, MutinyJsError> {
+        self.check_lightning()?;
         let labels: Vec<String> = labels
             .into_serde()
             .map_err(|_| MutinyJsError::InvalidArgumentsError)?;
@@ -220,6 +265,7 @@ impl MutinyWallet {
         labels: JsValue, /* Vec<String> */
         fee_rate: Option<f32>,
     ) -> Result<String, MutinyJsError> {
+        self.check_running()?;
         let send_to = Address::from_str(&destination_address)?;
         let labels: Vec<String> = labels
             .into_serde()
@@ -243,6 +289,7 @@ impl MutinyWallet {
         labels: JsValue, /* Vec<String> */
         fee_rate: Option<f32>,
     ) -> Result<String, MutinyJsError> {
+        self.check_running()?;
         let send_to = Address::from_str(&destination_address)?;
         let labels: Vec<String> = labels
             .into_serde()
@@ -263,6 +310,7 @@ impl MutinyWallet {
         amount: u64,
         fee_rate: Option<f32>,
     ) -> Result<u64, MutinyJsError> {
+        self.check_running()?;
         let addr = Address::from_str(&destination_address)?;
         Ok(self
             .inner
@@ -279,6 +327,7 @@ impl MutinyWallet {
         destination_address: String,
         fee_rate: Option<f32>,
     ) -> Result<u64, MutinyJsError> {
+        self.check_running()?;
         let addr = Address::from_str(&destination_address)?;
         Ok(self
             .inner
@@ -293,6 +342,7 @@ impl MutinyWallet {
         amount: u64,
         fee_rate: Option<f32>,
     ) -> Result<u64, MutinyJsError> {
+        self.check_running()?;
         Ok(self
             .inner
             .node_manager
@@ -305,6 +355,7 @@ impl MutinyWallet {
         &self,
         fee_rate: Option<f32>,
     ) -> Result<u64, MutinyJsError> {
+        self.check_running()?;
         Ok(self
             .inner
             .node_manager
@@ -320,6 +371,7 @@ impl MutinyWallet {
         &self,
         address: String,
     ) -> Result<JsValue /* Option<TransactionDetails> */, MutinyJsError> {
+        self.check_running()?;
         let address = Address::from_str(&address)?;
         Ok(JsValue::from_serde(
             &self.inner.node_manager.check_address(&address).await?,
@@ -330,6 +382,7 @@ impl MutinyWallet {
     /// These are sorted by confirmation time.
     #[wasm_bindgen]
     pub fn list_onchain(&self) -> Result<JsValue /* Vec<TransactionDetails> */, MutinyJsError> {
+        self.check_running()?;
         Ok(JsValue::from_serde(
             &self.inner.node_manager.list_onchain()?,
         )?)
@@ -341,6 +394,7 @@ impl MutinyWallet {
         &self,
         txid: String,
     ) -> Result<JsValue /* Option<TransactionDetails> */, MutinyJsError> {
+        self.check_running()?;
         let txid = Txid::from_str(&txid)?;
         Ok(JsValue::from_serde(
             &self.inner.node_manager.get_transaction(txid)?,
@@ -353,12 +407,26 @@ impl MutinyWallet {
     /// This will not include any funds in an unconfirmed lightning channel.
     #[wasm_bindgen]
     pub async fn get_balance(&self) -> Result<MutinyBalance, MutinyJsError> {
+        self.check_running()?;
         Ok(self.inner.node_manager.get_balance().await?.into())
     }
 
+    /// Lists the funds of closed channels that are not spendable on-chain yet,
+    /// with how many blocks are left until they are available.
+    #[wasm_bindgen]
+    pub async fn list_pending_recoveries(
+        &self,
+    ) -> Result<JsValue /* Vec<PendingRecovery> */, MutinyJsError> {
+        self.check_running()?;
+        Ok(JsValue::from_serde(
+            &self.inner.node_manager.list_pending_recoveries().await?,
+        )?)
+    }
+
     /// Lists all the UTXOs in the wallet.
     #[wasm_bindgen]
     pub fn list_utxos(&self) -> Result<JsValue, MutinyJsError> {
+        self.check_running()?;
         Ok(JsValue::from_serde(&self.inner.node_manager.list_utxos()?)?)
     }
 
@@ -376,15 +444,36 @@ impl MutinyWallet {
         self.inner.node_manager.estimate_fee_high()
     }
 
+    /// Returns the state of the congestion breaker, which defers new channels
+    /// while on-chain fees are high, and the operations it deferred.
+    #[wasm_bindgen]
+    pub fn congestion_status(&self) -> Result<JsValue /* CongestionStatus */, MutinyJsError> {
+        self.check_running()?;
+        Ok(JsValue::from_serde(
+            &self.inner.node_manager.congestion_status(),
+        )?)
+    }
+
+    /// Returns the last automatic consolidation of small utxos, if any.
+    #[wasm_bindgen]
+    pub fn last_consolidation(&self) -> Result<JsValue /* Option<Consolidation> */, MutinyJsError> {
+        self.check_running()?;
+        Ok(JsValue::from_serde(
+            &self.inner.node_manager.last_consolidation()?,
+        )?)
+    }
+
     /// Creates a new lightning node and adds it to the manager.
     #[wasm_bindgen]
     pub async fn new_node(&self) -> Result<NodeIdentity, MutinyJsError> {
+        self.check_lightning()?;
         Ok(self.inner.node_manager.new_node().await?.into())
     }
 
     /// Lists the pubkeys of the lightning node in the manager.
     #[wasm_bindgen]
     pub async fn list_nodes(&self) -> Result<JsValue /* Vec<String> */, MutinyJsError> {
+        self.check_lightning()?;
         Ok(JsValue::from_serde(
             &self.inner.node_manager.list_nodes().await?,
         )?)
@@ -398,6 +487,7 @@ impl MutinyWallet {
         connection_string: String,
         label: Option<String>,
     ) -> Result<(), MutinyJsError> {
+        self.check_lightning()?;
         let self_node_pubkey = PublicKey::from_str(&self_node_pubkey)?;
         Ok(self
             .inner
@@ -413,6 +503,7 @@ impl MutinyWallet {
         self_node_pubkey: String,
         peer: String,
     ) -> Result<(), MutinyJsError> {
+        self.check_lightning()?;
         let self_node_pubkey = PublicKey::from_str(&self_node_pubkey)?;
         let peer = PublicKey::from_str(&peer)?;
         Ok(self
@@ -431,6 +522,7 @@ impl MutinyWallet {
         self_node_pubkey: String,
         peer: String,
     ) -> Result<(), MutinyJsError> {
+        self.check_lightning()?;
         let self_node_pubkey = PublicKey::from_str(&self_node_pubkey)?;
         let peer = NodeId::from_str(&peer)?;
         Ok(self
@@ -443,6 +535,7 @@ impl MutinyWallet {
     /// Sets the label of a peer from the selected node.
     #[wasm_bindgen]
     pub fn label_peer(&self, node_id: String, label: Option<String>) -> Result<(), MutinyJsError> {
+        self.check_running()?;
         let node_id = NodeId::from_str(&node_id)?;
         self.inner.node_manager.label_peer(&node_id, label)?;
         Ok(())
@@ -460,6 +553,7 @@ impl MutinyWallet {
         amount: Option<u64>,
         labels: JsValue, /* Vec<String> */
     ) -> Result<MutinyInvoice, MutinyJsError> {
+        self.check_lightning()?;
         let labels: Vec<String> = labels
             .into_serde()
             .map_err(|_| MutinyJsError::InvalidArgumentsError)?;
@@ -482,6 +576,7 @@ impl MutinyWallet {
         amt_sats: Option<u64>,
         labels: JsValue, /* Vec<String> */
     ) -> Result<MutinyInvoice, MutinyJsError> {
+        self.check_lightning()?;
         let from_node = PublicKey::from_str(&from_node)?;
         let invoice = Invoice::from_str(&invoice_str)?;
         let labels: Vec<String> = labels
@@ -505,6 +600,7 @@ impl MutinyWallet {
         amt_sats: u64,
         labels: JsValue, /* Vec<String> */
     ) -> Result<MutinyInvoice, MutinyJsError> {
+        self.check_lightning()?;
         let from_node = PublicKey::from_str(&from_node)?;
         let to_node = PublicKey::from_str(&to_node)?;
         let labels: Vec<String> = labels
@@ -522,6 +618,7 @@ impl MutinyWallet {
     /// Will return an error if the invoice is for a different network.
     #[wasm_bindgen]
     pub async fn decode_invoice(&self, invoice: String) -> Result<MutinyInvoice, MutinyJsError> {
+        self.check_running()?;
         let invoice = Invoice::from_str(&invoice)?;
         Ok(self
             .inner
@@ -535,6 +632,7 @@ impl MutinyWallet {
     /// This contains what kind of LNURL it is (pay, withdrawal, auth, etc).
     #[wasm_bindgen]
     pub async fn decode_lnurl(&self, lnurl: String) -> Result<LnUrlParams, MutinyJsError> {
+        self.check_running()?;
         let lnurl = LnUrl::from_str(&lnurl)?;
         Ok(self.inner.node_manager.decode_lnurl(lnurl).await?.into())
     }
@@ -549,6 +647,7 @@ impl MutinyWallet {
         amount_sats: u64,
         labels: JsValue, /* Vec<String> */
     ) -> Result<MutinyInvoice, MutinyJsError> {
+        self.check_lightning()?;
         let from_node = PublicKey::from_str(&from_node)?;
         let lnurl = LnUrl::from_str(&lnurl)?;
         let labels: Vec<String> = labels
@@ -570,6 +669,7 @@ impl MutinyWallet {
         lnurl: String,
         amount_sats: u64,
     ) -> Result<bool, MutinyJsError> {
+        self.check_lightning()?;
         let lnurl = LnUrl::from_str(&lnurl)?;
         Ok(self
             .inner
@@ -581,12 +681,14 @@ impl MutinyWallet {
     /// Creates a new LNURL-auth profile.
     #[wasm_bindgen]
     pub fn create_lnurl_auth_profile(&self, name: String) -> Result<u32, MutinyJsError> {
+        self.check_running()?;
         Ok(self.inner.node_manager.create_lnurl_auth_profile(name)?)
     }
 
     /// Gets all the LNURL-auth profiles.
     #[wasm_bindgen]
     pub fn get_lnurl_auth_profiles(&self) -> Result<JsValue /*<Vec<AuthProfile> */, MutinyJsError> {
+        self.check_running()?;
         Ok(JsValue::from_serde(
             &self.inner.node_manager.get_lnurl_auth_profiles()?,
         )?)
@@ -599,6 +701,7 @@ impl MutinyWallet {
         profile_index: usize,
         lnurl: String,
     ) -> Result<(), MutinyJsError> {
+        self.check_running()?;
         let lnurl = LnUrl::from_str(&lnurl)?;
         Ok(self
             .inner
@@ -611,6 +714,7 @@ impl MutinyWallet {
     /// This includes sent and received invoices.
     #[wasm_bindgen]
     pub async fn get_invoice(&self, invoice: String) -> Result<MutinyInvoice, MutinyJsError> {
+        self.check_lightning()?;
         let invoice = Invoice::from_str(&invoice)?;
         Ok(self.inner.node_manager.get_invoice(&invoice).await?.into())
     }
@@ -619,6 +723,7 @@ impl MutinyWallet {
     /// This includes sent and received invoices.
     #[wasm_bindgen]
     pub async fn get_invoice_by_hash(&self, hash: String) -> Result<MutinyInvoice, MutinyJsError> {
+        self.check_lightning()?;
         let hash: sha256::Hash = sha256::Hash::from_str(&hash)?;
         Ok(self
             .inner
@@ -628,10 +733,48 @@ impl MutinyWallet {
             .into())
     }
 
+    /// Marks one of our invoices as paid out of band. The preimage has to match
+    /// the payment hash and the invoice must not already be paid.
+    #[wasm_bindgen]
+    pub async fn mark_invoice_settled_external(
+        &self,
+        payment_hash: String,
+        preimage: String,
+        note: String,
+    ) -> Result<MutinyInvoice, MutinyJsError> {
+        self.check_lightning()?;
+        let payment_hash: sha256::Hash = sha256::Hash::from_str(&payment_hash)?;
+        let preimage: [u8; 32] =
+            FromHex::from_hex(&preimage).map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+        Ok(self
+            .inner
+            .node_manager
+            .mark_invoice_settled_external(&payment_hash, preimage, note)
+            .await?
+            .into())
+    }
+
+    /// Gets the totals of the settled lightning payments.
+    /// Invoices settled out of band are counted separately.
+    #[wasm_bindgen]
+    pub async fn get_lightning_payment_totals(
+        &self,
+    ) -> Result<JsValue /* LightningPaymentTotals */, MutinyJsError> {
+        self.check_lightning()?;
+        Ok(JsValue::from_serde(
+            &self
+                .inner
+                .node_manager
+                .get_lightning_payment_totals()
+                .await?,
+        )?)
+    }
+
     /// Gets an invoice from the node manager.
     /// This includes sent and received invoices.
     #[wasm_bindgen]
     pub async fn list_invoices(&self) -> Result<JsValue /* Vec<MutinyInvoice> */, MutinyJsError> {
+        self.check_lightning()?;
         Ok(JsValue::from_serde(
             &self.inner.node_manager.list_invoices().await?,
         )?)
@@ -643,6 +786,7 @@ impl MutinyWallet {
         &self,
         user_channel_id: String,
     ) -> Result<ChannelClosure, MutinyJsError> {
+        self.check_lightning()?;
         let user_channel_id: [u8; 16] = FromHex::from_hex(&user_channel_id)?;
         Ok(self
             .inner
@@ -659,6 +803,7 @@ impl MutinyWallet {
     pub async fn list_channel_closures(
         &self,
     ) -> Result<JsValue /* Vec<ChannelClosure> */, MutinyJsError> {
+        self.check_lightning()?;
         let mut channel_closures = self.inner.node_manager.list_channel_closures().await?;
         channel_closures.sort();
         Ok(JsValue::from_serde(&channel_closures)?)
@@ -677,6 +822,7 @@ impl MutinyWallet {
         amount: u64,
         fee_rate: Option<f32>,
     ) -> Result<MutinyChannel, MutinyJsError> {
+        self.check_lightning()?;
         let from_node = PublicKey::from_str(&from_node)?;
 
         let to_pubkey = match to_pubkey {
@@ -703,6 +849,7 @@ impl MutinyWallet {
         from_node: String,
         to_pubkey: Option<String>,
     ) -> Result<MutinyChannel, MutinyJsError> {
+        self.check_lightning()?;
         let from_node = PublicKey::from_str(&from_node)?;
 
         let to_pubkey = match to_pubkey {
@@ -736,6 +883,7 @@ impl MutinyWallet {
         force: bool,
         abandon: bool,
     ) -> Result<(), MutinyJsError> {
+        self.check_lightning()?;
         let outpoint: OutPoint =
             OutPoint::from_str(&outpoint).map_err(|_| MutinyJsError::InvalidArgumentsError)?;
         Ok(self
@@ -748,30 +896,422 @@ impl MutinyWallet {
     /// Lists all the channels for all the nodes in the node manager.
     #[wasm_bindgen]
     pub async fn list_channels(&self) -> Result<JsValue /* Vec<MutinyChannel> */, MutinyJsError> {
+        self.check_lightning()?;
         Ok(JsValue::from_serde(
             &self.inner.node_manager.list_channels().await?,
         )?)
     }
 
+    /// Decrypts an encrypted static channel backup and returns a summary of what
+    /// would be recovered from it, without modifying any state.
+    #[wasm_bindgen]
+    pub fn preview_static_channel_backup(
+        &self,
+        scb: String,
+    ) -> Result<JsValue /* ScbSummary */, MutinyJsError> {
+        self.check_running()?;
+        Ok(JsValue::from_serde(
+            &self
+                .inner
+                .node_manager
+                .preview_static_channel_backup(&scb)?,
+        )?)
+    }
+
+    /// Decrypts an encrypted static channel backup and reports for each channel its
+    /// peer, the update id of its monitor and whether the backup has an address of
+    /// the peer, without modifying any state.
+    #[wasm_bindgen]
+    pub fn static_channel_backup_recovery_report(
+        &self,
+        scb: String,
+    ) -> Result<JsValue /* Vec<ChannelRecoveryInfo> */, MutinyJsError> {
+        self.check_running()?;
+        Ok(JsValue::from_serde(
+            &self
+                .inner
+                .node_manager
+                .static_channel_backup_recovery_report(&scb)?,
+        )?)
+    }
+
+    /// Decrypts an encrypted static channel backup and returns its nodes with the
+    /// LSP each of them was using, sorted by child index.
+    #[wasm_bindgen]
+    pub fn list_scb_node_indexes(
+        &self,
+        scb: String,
+    ) -> Result<JsValue /* Vec<NodeIndex> */, MutinyJsError> {
+        self.check_running()?;
+        let node_indexes: Vec<NodeIndex> = self
+            .inner
+            .node_manager
+            .list_scb_node_indexes(&scb)?
+            .into_iter()
+            .map(|n| n.into())
+            .collect();
+        Ok(JsValue::from_serde(&node_indexes)?)
+    }
+
+    /// Returns the metadata of an encrypted static channel backup that can be read
+    /// without decrypting it, such as the network it was created on.
+    #[wasm_bindgen]
+    pub fn inspect_scb_metadata(
+        &self,
+        scb: String,
+    ) -> Result<JsValue /* ScbMetadata */, MutinyJsError> {
+        self.check_running()?;
+        Ok(JsValue::from_serde(
+            &self.inner.node_manager.inspect_scb_metadata(&scb)?,
+        )?)
+    }
+
+    /// Returns the fingerprint of an encrypted static channel backup as a short hex
+    /// string for display. Every copy of the same backup has the same fingerprint,
+    /// even when encrypted again. Older backups do not have one.
+    #[wasm_bindgen]
+    pub fn scb_fingerprint(&self, scb: String) -> Result<Option<String>, MutinyJsError> {
+        self.check_running()?;
+        Ok(EncryptedSCB::parse(&scb)?.fingerprint_hex())
+    }
+
+    /// Returns the epoch time in seconds of the last automatic static channel backup,
+    /// which is created whenever a channel changes.
+    #[wasm_bindgen]
+    pub fn last_auto_backup_time(&self) -> Result<Option<u64>, MutinyJsError> {
+        self.check_running()?;
+        Ok(self.inner.node_manager.last_auto_backup_time()?)
+    }
+
+    /// Returns which static channel backups are exchanged with the peer,
+    /// as `{ send_backup_to, accept_backup_from }`.
+    #[wasm_bindgen]
+    pub fn get_peer_backup_permissions(
+        &self,
+        peer: String,
+    ) -> Result<JsValue /* PeerBackupPermissions */, MutinyJsError> {
+        self.check_running()?;
+        let peer = PublicKey::from_str(&peer)?;
+        Ok(JsValue::from_serde(
+            &self.inner.node_manager.get_peer_backup_permissions(&peer)?,
+        )?)
+    }
+
+    /// Sets which static channel backups are exchanged with the peer, nothing is
+    /// exchanged unless opted in. Revoking deletes the backup we store for the peer
+    /// and asks the peer to delete ours.
+    #[wasm_bindgen]
+    pub async fn set_peer_backup_permissions(
+        &self,
+        peer: String,
+        send_backup_to: bool,
+        accept_backup_from: bool,
+    ) -> Result<(), MutinyJsError> {
+        self.check_running()?;
+        let peer = PublicKey::from_str(&peer)?;
+        let permissions = PeerBackupPermissions {
+            send_backup_to,
+            accept_backup_from,
+        };
+        Ok(self
+            .inner
+            .node_manager
+            .set_peer_backup_permissions(peer, permissions)
+            .await?)
+    }
+
+    /// Designates a peer to store our encrypted static channel backup.
+    /// The backup is sent to it whenever a channel changes.
+    #[wasm_bindgen]
+    pub async fn add_backup_peer(&self, peer: String) -> Result<(), MutinyJsError> {
+        self.check_running()?;
+        let peer = PublicKey::from_str(&peer)?;
+        Ok(self.inner.node_manager.add_backup_peer(peer).await?)
+    }
+
+    /// Stops sending our static channel backup to the given peer,
+    /// and asks it to delete the last one it was sent.
+    #[wasm_bindgen]
+    pub async fn remove_backup_peer(&self, peer: String) -> Result<(), MutinyJsError> {
+        self.check_running()?;
+        let peer = PublicKey::from_str(&peer)?;
+        Ok(self.inner.node_manager.remove_backup_peer(&peer).await?)
+    }
+
+    /// Lists the peers designated to store our static channel backup.
+    #[wasm_bindgen]
+    pub fn list_backup_peers(&self) -> Result<JsValue /* Vec<String> */, MutinyJsError> {
+        self.check_running()?;
+        Ok(JsValue::from_serde(
+            &self.inner.node_manager.list_backup_peers()?,
+        )?)
+    }
+
+    /// Lists the peers that confirmed storing a static channel backup for us.
+    #[wasm_bindgen]
+    pub async fn list_backup_holders(&self) -> Result<JsValue /* Vec<String> */, MutinyJsError> {
+        self.check_lightning()?;
+        Ok(JsValue::from_serde(
+            &self.inner.node_manager.list_backup_holders().await,
+        )?)
+    }
+
+    /// Connects to the given peer and asks it for the static channel backup it stores for us.
+    /// The returned backup can be restored with `recover_from_static_channel_backup`.
+    #[wasm_bindgen]
+    pub async fn retrieve_backup_from_peer(
+        &self,
+        connection_string: String,
+    ) -> Result<String, MutinyJsError> {
+        self.check_lightning()?;
+        Ok(self
+            .inner
+            .node_manager
+            .retrieve_backup_from_peer(&connection_string)
+            .await?
+            .to_string())
+    }
+
+    /// Creates a static channel backup and publishes it to the given Nostr relays.
+    /// Succeeds if at least one relay accepted it, and returns the relays that did.
+    #[wasm_bindgen]
+    pub async fn backup_scb_to_nostr(
+        &self,
+        relays: JsValue, /* Vec<String> */
+    ) -> Result<JsValue /* Vec<String> */, MutinyJsError> {
+        self.check_lightning()?;
+        let relays: Vec<String> = relays
+            .into_serde()
+            .map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+        let accepted = self.inner.node_manager.backup_scb_to_nostr(relays).await?;
+        Ok(JsValue::from_serde(&accepted)?)
+    }
+
+    /// Fetches the latest static channel backup of this wallet from the given Nostr relays.
+    /// The returned backup can be restored with `recover_from_static_channel_backup`.
+    #[wasm_bindgen]
+    pub async fn restore_scb_from_nostr(
+        &self,
+        relays: JsValue, /* Vec<String> */
+    ) -> Result<String, MutinyJsError> {
+        self.check_running()?;
+        let relays: Vec<String> = relays
+            .into_serde()
+            .map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+        Ok(self
+            .inner
+            .node_manager
+            .restore_scb_from_nostr(relays)
+            .await?
+            .to_string())
+    }
+
+    /// Creates a static channel backup and stores it on the given HTTPS endpoint.
+    /// Fails if the endpoint already has a newer backup.
+    #[wasm_bindgen]
+    pub async fn backup_scb_to_remote(&self, url: String) -> Result<(), MutinyJsError> {
+        self.check_lightning()?;
+        Ok(self.inner.node_manager.backup_scb_to_remote(&url).await?)
+    }
+
+    /// Fetches the static channel backup of this wallet from the given HTTPS endpoint.
+    /// The returned backup can be restored with `recover_from_static_channel_backup`.
+    #[wasm_bindgen]
+    pub async fn restore_scb_from_remote(&self, url: String) -> Result<String, MutinyJsError> {
+        self.check_running()?;
+        Ok(self
+            .inner
+            .node_manager
+            .restore_scb_from_remote(&url)
+            .await?
+            .to_string())
+    }
+
+    /// Reads the channels from the `channel.backup` file of an LND node, with the
+    /// LND root key as printed by `chantools showrootkey`. Channels that can't be
+    /// read are listed as unsupported.
+    #[wasm_bindgen]
+    pub fn import_lnd_channel_backup(
+        &self,
+        backup: Vec<u8>,
+        root_key: String,
+    ) -> Result<JsValue /* LndBackupImport */, MutinyJsError> {
+        self.check_running()?;
+        let root_key =
+            ExtendedPrivKey::from_str(&root_key).map_err(|_| MutinyJsError::InvalidMnemonic)?;
+        let import = self
+            .inner
+            .node_manager
+            .import_lnd_channel_backup(&backup, &root_key)?;
+        Ok(JsValue::from_serde(&import)?)
+    }
+
+    /// Lists the webhook endpoints, with the secrets their deliveries are signed with.
+    #[wasm_bindgen]
+    pub fn list_webhooks(&self) -> Result<JsValue /* Vec<WebhookEndpoint> */, MutinyJsError> {
+        self.check_running()?;
+        Ok(JsValue::from_serde(
+            &self.inner.node_manager.list_webhooks()?,
+        )?)
+    }
+
+    /// Adds a webhook endpoint that is sent the given kinds of events, one of
+    /// payment_received, payment_sent, channel_closed or backup_stale.
+    /// Returns the endpoint with the secret deliveries are signed with.
+    #[wasm_bindgen]
+    pub fn add_webhook(
+        &self,
+        url: String,
+        kinds: JsValue, /* Vec<String> */
+    ) -> Result<JsValue /* WebhookEndpoint */, MutinyJsError> {
+        self.check_running()?;
+        let kinds: Vec<WebhookEventKind> = kinds
+            .into_serde()
+            .map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+        let endpoint = self.inner.node_manager.add_webhook(&url, kinds)?;
+        Ok(JsValue::from_serde(&endpoint)?)
+    }
+
+    /// Removes the webhook endpoint, dropping the events still queued for it.
+    #[wasm_bindgen]
+    pub fn remove_webhook(&self, id: String) -> Result<(), MutinyJsError> {
+        self.check_running()?;
+        Ok(self.inner.node_manager.remove_webhook(&id)?)
+    }
+
+    /// Sends a ping to the webhook endpoint, failing if it did not accept it.
+    #[wasm_bindgen]
+    pub async fn test_webhook(&self, id: String) -> Result<(), MutinyJsError> {
+        self.check_running()?;
+        Ok(self.inner.node_manager.test_webhook(&id).await?)
+    }
+
+    /// Returns how the deliveries to the webhook endpoint went.
+    #[wasm_bindgen]
+    pub fn get_webhook_stats(
+        &self,
+        id: String,
+    ) -> Result<JsValue /* WebhookStats */, MutinyJsError> {
+        self.check_running()?;
+        Ok(JsValue::from_serde(
+            &self.inner.node_manager.get_webhook_stats(&id)?,
+        )?)
+    }
+
+    /// Returns the events that could not be delivered to the webhook endpoint.
+    #[wasm_bindgen]
+    pub fn get_webhook_dead_letters(
+        &self,
+        id: String,
+    ) -> Result<JsValue /* Vec<WebhookDelivery> */, MutinyJsError> {
+        self.check_running()?;
+        Ok(JsValue::from_serde(
+            &self.inner.node_manager.get_webhook_dead_letters(&id)?,
+        )?)
+    }
+
     /// Takes an encrypted static channel backup and recovers the channels from it.
     /// If the backup is encrypted with a different key than the current key, it will fail.
+    /// The result is flagged as stale if a newer backup was already created on this device.
+    /// Channels with a newer state on this device are skipped and listed in the result,
+    /// unless `force` is set.
     #[wasm_bindgen]
     pub async fn recover_from_static_channel_backup(
         &self,
         scb: String,
-    ) -> Result<(), MutinyJsError> {
-        let scb = EncryptedSCB::from_str(&scb).map_err(|_| MutinyJsError::InvalidArgumentsError)?;
-        self.inner
+        force: Option<bool>,
+    ) -> Result<JsValue /* ScbRestoreResult */, MutinyJsError> {
+        self.check_running()?;
+        let scb = EncryptedSCB::parse(&scb)?;
+        let result = self
+            .inner
             .node_manager
-            .recover_from_static_channel_backup(scb)
+            .recover_from_static_channel_backup(scb, force.unwrap_or(false))
             .await?;
-        Ok(())
+        Ok(JsValue::from_serde(&result)?)
+    }
+
+    /// Like `recover_from_static_channel_backup`, calling `on_progress` with each
+    /// `ScbRestoreProgress` event as the restore runs, ending with `Done`.
+    #[wasm_bindgen]
+    pub async fn recover_from_static_channel_backup_with_progress(
+        &self,
+        scb: String,
+        on_progress: js_sys::Function,
+        force: Option<bool>,
+    ) -> Result<JsValue /* ScbRestoreResult */, MutinyJsError> {
+        self.check_running()?;
+        let scb = EncryptedSCB::parse(&scb)?;
+        let on_progress = |event: ScbRestoreProgress| {
+            if let Ok(event) = JsValue::from_serde(&event) {
+                let _ = on_progress.call1(&JsValue::NULL, &event);
+            }
+        };
+        let result = self
+            .inner
+            .node_manager
+            .recover_from_static_channel_backup_with_progress(
+                scb,
+                force.unwrap_or(false),
+                &on_progress,
+            )
+            .await?;
+        Ok(JsValue::from_serde(&result)?)
+    }
+
+    /// Recovers only the funds of the channels in an encrypted static channel backup,
+    /// closing the channels and sweeping them to the on-chain wallet.
+    /// Returns the status of each channel.
+    ///
+    /// Peers that can not be reached are asked to close once they reconnect. Setting
+    /// `broadcast_unreachable` broadcasts our commitment from the backup for them
+    /// instead. Only use it when the backup is known to be the latest: if the channel
+    /// was updated after the backup was made, the commitment is revoked and the peer
+    /// can take all the funds of the channel as a penalty.
+    #[wasm_bindgen]
+    pub async fn sweep_from_scb(
+        &self,
+        scb: String,
+        broadcast_unreachable: Option<bool>,
+    ) -> Result<JsValue /* Vec<ScbSweepResult> */, MutinyJsError> {
+        self.check_lightning()?;
+        let results = self
+            .inner
+            .node_manager
+            .sweep_from_scb(&scb, broadcast_unreachable.unwrap_or(false))
+            .await?;
+        Ok(JsValue::from_serde(&results)?)
+    }
+
+    /// Recovers only the selected nodes from an encrypted static channel backup.
+    /// The nodes in a backup can be listed with `preview_static_channel_backup`.
+    #[wasm_bindgen]
+    pub async fn restore_scb_selected(
+        &self,
+        scb: String,
+        selected_nodes: JsValue, /* Vec<String> */
+    ) -> Result<JsValue /* ScbRestoreResult */, MutinyJsError> {
+        self.check_running()?;
+        let selected_nodes: Vec<String> = selected_nodes
+            .into_serde()
+            .map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+        let selected_nodes = selected_nodes
+            .iter()
+            .map(|pk| PublicKey::from_str(pk))
+            .collect::<Result<Vec<_>, _>>()?;
+        let result = self
+            .inner
+            .node_manager
+            .restore_scb_selected(&scb, &selected_nodes)
+            .await?;
+        Ok(JsValue::from_serde(&result)?)
     }
 
     /// Creates a static channel backup for all the nodes in the node manager.
     /// The backup is encrypted with the SCB key.
     #[wasm_bindgen]
     pub async fn create_static_channel_backup(&self) -> Result<String, MutinyJsError> {
+        self.check_lightning()?;
         let scb = self
             .inner
             .node_manager
@@ -780,17 +1320,119 @@ impl MutinyWallet {
         Ok(scb.to_string())
     }
 
+    /// Creates a static channel backup and returns only what changed since the given
+    /// previous backup, encrypted like a full backup. A client can keep one full
+    /// backup and the diffs after it, see `apply_static_channel_backup_diff`.
+    #[wasm_bindgen]
+    pub async fn create_static_channel_backup_diff(
+        &self,
+        previous: String,
+    ) -> Result<String, MutinyJsError> {
+        self.check_lightning()?;
+        let previous = EncryptedSCB::parse(&previous)?;
+        let diff = self
+            .inner
+            .node_manager
+            .create_static_channel_backup_diff(&previous)
+            .await?;
+        Ok(diff.to_string())
+    }
+
+    /// Applies a diff from `create_static_channel_backup_diff` to the backup it was
+    /// created from, and returns the updated full backup.
+    #[wasm_bindgen]
+    pub fn apply_static_channel_backup_diff(
+        &self,
+        previous: String,
+        diff: String,
+    ) -> Result<String, MutinyJsError> {
+        self.check_running()?;
+        let previous = EncryptedSCB::parse(&previous)?;
+        let diff = EncryptedSCB::parse(&diff)?;
+        Ok(self
+            .inner
+            .node_manager
+            .apply_static_channel_backup_diff(&previous, &diff)?
+            .to_string())
+    }
+
+    /// Generates a recovery kit as a JSON document, with everything needed to recover
+    /// the wallet besides the seed. It contains no secrets and is meant to be printed.
+    /// With `include_scb` a new static channel backup is included.
+    #[wasm_bindgen]
+    pub async fn generate_recovery_kit(
+        &self,
+        include_scb: bool,
+    ) -> Result<JsValue /* RecoveryKit */, MutinyJsError> {
+        if include_scb {
+            self.check_lightning()?;
+        } else {
+            self.check_running()?;
+        }
+        let kit = self
+            .inner
+            .node_manager
+            .generate_recovery_kit(include_scb)
+            .await?;
+        Ok(JsValue::from_serde(&kit)?)
+    }
+
+    /// Checks the integrity hash of a recovery kit JSON document, and reports which
+    /// of its components can be used for recovery.
+    #[wasm_bindgen]
+    pub fn validate_recovery_kit(
+        &self,
+        json: String,
+    ) -> Result<JsValue /* RecoveryKitValidation */, MutinyJsError> {
+        self.check_running()?;
+        Ok(JsValue::from_serde(
+            &self.inner.node_manager.validate_recovery_kit(&json)?,
+        )?)
+    }
+
+    /// Creates a static channel backup as an armored text block to save as a file.
+    /// It can be restored with `recover_from_static_channel_backup` like the bech32 string.
+    #[wasm_bindgen]
+    pub async fn create_static_channel_backup_armored(&self) -> Result<String, MutinyJsError> {
+        self.check_lightning()?;
+        Ok(self
+            .inner
+            .node_manager
+            .create_static_channel_backup_armored()
+            .await?)
+    }
+
     /// Lists all the peers for all the nodes in the node manager.
     #[wasm_bindgen]
     pub async fn list_peers(&self) -> Result<JsValue /* Vec<MutinyPeer> */, MutinyJsError> {
+        self.check_lightning()?;
         Ok(JsValue::from_serde(
             &self.inner.node_manager.list_peers().await?,
         )?)
     }
 
+    /// Searches the on-chain and lightning activity, labels, and contacts of the wallet.
+    /// Returns the matching items, newest first, tagged with their type.
+    #[wasm_bindgen]
+    pub async fn search_activity(
+        &self,
+        query: String,
+        limit: usize,
+    ) -> Result<JsValue /* Vec<SearchResult> */, MutinyJsError> {
+        self.check_running()?;
+        Ok(JsValue::from_serde(
+            &self
+                .inner
+                .node_manager
+                .search_activity(&query, limit)
+                .await?,
+        )?)
+    }
+
     /// Returns all the on-chain and lightning activity from the wallet.
     #[wasm_bindgen]
     pub async fn get_activity(&self) -> Result<JsValue /* Vec<ActivityItem> */, MutinyJsError> {
+        self.check_running()?;
         // get activity from the node manager
         let activity = self.inner.node_manager.get_activity().await?;
         let mut activity: Vec<ActivityItem> = activity.into_iter().map(|a| a.into()).collect();
@@ -820,6 +1462,7 @@ impl MutinyWallet {
         lightning_recipient_connection_string: Option<String>,
         onchain_recipient: Option<String>,
     ) -> Result<Redshift, MutinyJsError> {
+        self.check_lightning()?;
         let outpoint: OutPoint =
             OutPoint::from_str(&outpoint).map_err(|_| MutinyJsError::InvalidArgumentsError)?;
         let introduction_node = match lightning_recipient_pubkey.clone() {
@@ -856,6 +1499,7 @@ impl MutinyWallet {
     /// Get all redshift attempts for a given utxo
     #[wasm_bindgen]
     pub fn get_redshift(&self, id: String) -> Result<Option<Redshift>, MutinyJsError> {
+        self.check_running()?;
         let id: [u8; 16] =
             FromHex::from_hex(&id).map_err(|_| MutinyJsError::InvalidArgumentsError)?;
         Ok(self.inner.node_manager.get_redshift(&id)?.map(|r| r.into()))
@@ -864,6 +1508,7 @@ impl MutinyWallet {
     pub fn get_address_labels(
         &self,
     ) -> Result<JsValue /* Map<Address, Vec<String>> */, MutinyJsError> {
+        self.check_running()?;
         Ok(JsValue::from_serde(
             &self.inner.node_manager.get_address_labels()?,
         )?)
@@ -877,6 +1522,7 @@ impl MutinyWallet {
         address: String,
         labels: JsValue, /* Vec<String> */
     ) -> Result<(), MutinyJsError> {
+        self.check_running()?;
         let address = Address::from_str(&address)?;
         let labels: Vec<String> = labels
             .into_serde()
@@ -890,6 +1536,7 @@ impl MutinyWallet {
     pub fn get_invoice_labels(
         &self,
     ) -> Result<JsValue /* Map<Invoice, Vec<String>> */, MutinyJsError> {
+        self.check_running()?;
         Ok(JsValue::from_serde(
             &self.inner.node_manager.get_invoice_labels()?,
         )?)
@@ -903,6 +1550,7 @@ impl MutinyWallet {
         invoice: String,
         labels: JsValue, /* Vec<String> */
     ) -> Result<(), MutinyJsError> {
+        self.check_running()?;
         let invoice = Invoice::from_str(&invoice)?;
         let labels: Vec<String> = labels
             .into_serde()
@@ -913,7 +1561,35 @@ impl MutinyWallet {
             .set_invoice_labels(invoice, labels)?)
     }
 
+    /// Gets a map of channel outpoints to the label of the channel.
+    pub fn get_channel_labels(&self) -> Result<JsValue /* Map<String, String> */, MutinyJsError> {
+        self.check_running()?;
+        Ok(JsValue::from_serde(
+            &self
+                .inner
+                .node_manager
+                .get_channel_labels()?
+                .into_iter()
+                .map(|(outpoint, label)| (outpoint.to_string(), label))
+                .collect::<HashMap<String, String>>(),
+        )?)
+    }
+
+    /// Sets the label of the channel with the given outpoint, an empty label removes it.
+    /// Channel labels are included in the static channel backup.
+    pub fn set_channel_label(
+        &self,
+        outpoint: String,
+        label: Option<String>,
+    ) -> Result<(), MutinyJsError> {
+        self.check_running()?;
+        let outpoint: OutPoint =
+            OutPoint::from_str(&outpoint).map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+        Ok(self.inner.node_manager.set_channel_label(outpoint, label)?)
+    }
+
     pub fn get_contacts(&self) -> Result<JsValue /* Map<String, Contact>*/, MutinyJsError> {
+        self.check_running()?;
         Ok(JsValue::from_serde(
             &self
                 .inner
@@ -926,6 +1602,7 @@ impl MutinyWallet {
     }
 
     pub fn get_contact(&self, label: String) -> Result<Option<TagItem>, MutinyJsError> {
+        self.check_running()?;
         Ok(self
             .inner
             .node_manager
@@ -939,6 +1616,7 @@ impl MutinyWallet {
         label: String,
         contact: Contact,
     ) -> Result<String, MutinyJsError> {
+        self.check_running()?;
         Ok(self
             .inner
             .node_manager
@@ -946,18 +1624,22 @@ impl MutinyWallet {
     }
 
     pub fn create_new_contact(&self, contact: Contact) -> Result<String, MutinyJsError> {
+        self.check_running()?;
         Ok(self.inner.node_manager.create_new_contact(contact.into())?)
     }
 
     pub fn archive_contact(&self, id: String) -> Result<(), MutinyJsError> {
+        self.check_running()?;
         Ok(self.inner.node_manager.archive_contact(id)?)
     }
 
     pub fn edit_contact(&self, id: String, contact: Contact) -> Result<(), MutinyJsError> {
+        self.check_running()?;
         Ok(self.inner.node_manager.edit_contact(id, contact.into())?)
     }
 
     pub fn get_tag_items(&self) -> Result<JsValue /* Vec<TagItem> */, MutinyJsError> {
+        self.check_running()?;
         Ok(JsValue::from_serde(
             &self
                 .inner
@@ -972,6 +1654,7 @@ impl MutinyWallet {
     /// Gets the current bitcoin price in USD.
     #[wasm_bindgen]
     pub async fn get_bitcoin_price(&self) -> Result<f32, MutinyJsError> {
+        self.check_running()?;
         Ok(self.inner.node_manager.get_bitcoin_price().await?)
     }
 
@@ -988,9 +1671,32 @@ impl MutinyWallet {
         Ok(res)
     }
 
+    /// Returns the latest trace of the operation with the given id, like the
+    /// payment hash of a payment. Operations without an id, like a sync, use their name.
+    #[wasm_bindgen]
+    pub fn get_operation_trace(
+        &self,
+        operation_id: String,
+    ) -> Result<JsValue /* Option<OperationTrace> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.node_manager.get_operation_trace(&operation_id),
+        )?)
+    }
+
+    /// Returns the traces of the most recent operations, oldest first.
+    #[wasm_bindgen]
+    pub fn recent_operation_traces(
+        &self,
+    ) -> Result<JsValue /* Vec<OperationTrace> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.node_manager.recent_operation_traces(),
+        )?)
+    }
+
     /// Get nostr wallet connect profiles
     #[wasm_bindgen]
     pub fn get_nwc_profiles(&self) -> Result<JsValue /* Vec<NwcProfile> */, MutinyJsError> {
+        self.check_running()?;
         Ok(JsValue::from_serde(&self.inner.nostr.profiles())?)
     }
 
@@ -1001,6 +1707,7 @@ impl MutinyWallet {
         name: String,
         max_single_amt_sats: u64,
     ) -> Result<models::NwcProfile, MutinyJsError> {
+        self.check_running()?;
         Ok(self
             .inner
             .nostr
@@ -1015,6 +1722,7 @@ impl MutinyWallet {
         &self,
         profile: JsValue,
     ) -> Result<models::NwcProfile, MutinyJsError> {
+        self.check_running()?;
         let profile: NwcProfile = profile
             .into_serde()
             .map_err(|_| MutinyJsError::InvalidArgumentsError)?;
@@ -1025,6 +1733,7 @@ impl MutinyWallet {
     /// Get nostr wallet connect URI
     #[wasm_bindgen]
     pub fn get_nwc_uri(&self, index: u32) -> Result<String, MutinyJsError> {
+        self.check_running()?;
         self.inner
             .nostr
             .get_nwc_uri(index)
@@ -1035,6 +1744,7 @@ impl MutinyWallet {
     pub fn get_pending_nwc_invoices(
         &self,
     ) -> Result<JsValue /* Vec<PendingNwcInvoice> */, MutinyJsError> {
+        self.check_running()?;
         let pending: Vec<PendingNwcInvoice> = self
             .inner
             .nostr
@@ -1052,6 +1762,7 @@ impl MutinyWallet {
         hash: String,
         from_node: String,
     ) -> Result<(), MutinyJsError> {
+        self.check_lightning()?;
         let from_node = PublicKey::from_str(&from_node)?;
 
         self.inner
@@ -1064,6 +1775,7 @@ impl MutinyWallet {
 
     /// Removes an invoice from the pending list, will also remove expired invoices
     pub async fn deny_invoice(&self, hash: String) -> Result<(), MutinyJsError> {
+        self.check_running()?;
         let hash: sha256::Hash = hash
             .parse()
             .map_err(|_| MutinyJsError::InvalidArgumentsError)?;
@@ -1079,12 +1791,14 @@ impl MutinyWallet {
     /// past or in the future, depending on whether or not it is currently active.
     #[wasm_bindgen]
     pub async fn check_subscribed(&self) -> Result<Option<u64>, MutinyJsError> {
+        self.check_running()?;
         Ok(self.inner.node_manager.check_subscribed().await?)
     }
 
     /// Gets the subscription plans for Mutiny+ subscriptions
     #[wasm_bindgen]
     pub async fn get_subscription_plans(&self) -> Result<JsValue /* Vec<Plan> */, MutinyJsError> {
+        self.check_running()?;
         let plans = self.inner.node_manager.get_subscription_plans().await?;
 
         Ok(JsValue::from_serde(&plans)?)
@@ -1095,19 +1809,44 @@ impl MutinyWallet {
     /// Returns a lightning invoice so that the plan can be paid for to start it.
     #[wasm_bindgen]
     pub async fn subscribe_to_plan(&self, id: u8) -> Result<MutinyInvoice, MutinyJsError> {
+        self.check_running()?;
         Ok(self.inner.node_manager.subscribe_to_plan(id).await?.into())
     }
 
     /// Pay the subscription invoice. This will post a NWC automatically afterwards.
     pub async fn pay_subscription_invoice(&self, invoice_str: String) -> Result<(), MutinyJsError> {
+        self.check_lightning()?;
         let invoice = Invoice::from_str(&invoice_str)?;
         self.inner.pay_subscription_invoice(&invoice).await?;
         Ok(())
     }
 
+    /// Returns the keys of payment records that could not be read and are
+    /// hidden from the activity.
+    #[wasm_bindgen]
+    pub async fn get_quarantine_report(
+        &self,
+    ) -> Result<JsValue /* QuarantineReport */, MutinyJsError> {
+        self.check_running()?;
+        Ok(JsValue::from_serde(
+            &self.inner.node_manager.get_quarantine_report().await?,
+        )?)
+    }
+
+    /// Tries to repair payment records that could not be read. Records that can not
+    /// be repaired are moved under a quarantine prefix with their raw value preserved.
+    #[wasm_bindgen]
+    pub async fn repair_storage(&self) -> Result<JsValue /* RepairReport */, MutinyJsError> {
+        self.check_running()?;
+        Ok(JsValue::from_serde(
+            &self.inner.node_manager.repair_storage().await?,
+        )?)
+    }
+
     /// Resets the scorer and network graph. This can be useful if you get stuck in a bad state.
     #[wasm_bindgen]
     pub async fn reset_router(&self) -> Result<(), MutinyJsError> {
+        self.check_lightning()?;
         self.inner.node_manager.reset_router().await?;
         // Sleep to wait for indexed db to finish writing
         sleep(500).await;
@@ -1119,6 +1858,7 @@ impl MutinyWallet {
     /// This can be useful if you get stuck in a bad state.
     #[wasm_bindgen]
     pub async fn reset_onchain_tracker(&mut self) -> Result<(), MutinyJsError> {
+        self.check_running()?;
         Ok(self.inner.reset_onchain_tracker().await?)
     }
 
@@ -1158,6 +1898,22 @@ impl MutinyWallet {
         Ok(())
     }
 
+    /// Returns the features this version supports, by name with their flag, version
+    /// or list. Capabilities are only ever added, so they can be used for feature detection.
+    #[wasm_bindgen]
+    pub fn capabilities() -> Result<JsValue /* Map<String, any> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &mutiny_core::capabilities::capabilities(),
+        )?)
+    }
+
+    /// Returns the templates of the messages for errors and events, with their
+    /// English text and parameters, so they can be translated.
+    #[wasm_bindgen]
+    pub fn message_catalog() -> Result<JsValue /* Vec<MessageTemplate> */, MutinyJsError> {
+        Ok(JsValue::from_serde(&mutiny_core::messages::CATALOG)?)
+    }
+
     /// Converts a bitcoin amount in BTC to satoshis.
     #[wasm_bindgen]
     pub fn convert_btc_to_sats(btc: f64) -> Result<u64, MutinyJsError> {
@@ -1182,11 +1938,22 @@ impl MutinyWallet {
 
 #[cfg(test)]
 mod tests {
+    use crate::error::MutinyJsError;
+    use crate::models::WalletLifecycle;
+    use crate::utils::sleep;
     use crate::utils::test::*;
     use crate::MutinyWallet;
+    use futures::{pin_mut, poll};
+    use gloo_utils::format::JsValueSerdeExt;
+    use std::sync::Arc;
+    use std::task::Poll;
+    use wasm_bindgen::JsValue;
 
     use crate::indexed_db::IndexedDbStorage;
+    use mutiny_core::lifecycle::{LifecycleState, WalletComponent};
+    use mutiny_core::logging::MutinyLogger;
     use mutiny_core::storage::MutinyStorage;
+    use mutiny_core::{CHANNEL_MANAGER_ALT_KEY, CHANNEL_MANAGER_KEY};
     use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
 
     wasm_bindgen_test_configure!(run_in_browser);
@@ -1208,6 +1975,8 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
         )
         .await
         .expect("mutiny wallet should initialize");
@@ -1238,6 +2007,8 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
         )
         .await
         .unwrap();
@@ -1271,6 +2042,8 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
         )
         .await
         .expect("mutiny wallet should initialize");
@@ -1291,4 +2064,169 @@ mod tests {
             .await
             .expect("failed to clear storage");
     }
+
+    /// Constructs a wallet with a handle on its lifecycle, checking that the
+    /// handle reports the startup progress until the constructor returns.
+    async fn new_wallet_with_lifecycle() -> (MutinyWallet, WalletLifecycle) {
+        let lifecycle = WalletLifecycle::new();
+        let startup = MutinyWallet::new(
+            Some("password".to_string()),
+            None,
+            None,
+            Some("regtest".to_owned()),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(lifecycle.handle()),
+        );
+        pin_mut!(startup);
+
+        let mut completed = 0;
+        let mut initializing_seen = false;
+        loop {
+            match lifecycle.check_running() {
+                Err(MutinyJsError::WalletInitializing {
+                    completed_steps,
+                    total_steps,
+                }) => {
+                    assert!(completed_steps <= total_steps);
+                    assert!(completed_steps >= completed, "progress went back");
+                    completed = completed_steps;
+                    initializing_seen = true;
+                }
+                // the nodes started, the constructor still sets up the rest
+                Ok(()) => assert!(initializing_seen),
+                Err(e) => panic!("unexpected lifecycle error while starting: {e}"),
+            }
+
+            if let Poll::Ready(nm) = poll!(&mut startup) {
+                let nm = nm.expect("mutiny wallet should initialize");
+                return (nm, lifecycle);
+            }
+            sleep(10).await;
+        }
+    }
+
+    #[test]
+    async fn fail_fast_in_every_lifecycle_state() {
+        log!("failing fast in every lifecycle state");
+
+        let (nm, lifecycle) = new_wallet_with_lifecycle().await;
+
+        // ready
+        let state: LifecycleState = nm.get_lifecycle_state().unwrap().into_serde().unwrap();
+        assert_eq!(state, LifecycleState::Ready);
+        assert!(lifecycle.check_running().is_ok());
+        assert!(nm.get_wallet_balance().is_ok());
+        assert!(nm.list_nodes().await.is_ok());
+
+        // stopping, the nodes take a moment to shut down
+        let stop = nm.stop();
+        pin_mut!(stop);
+        assert!(poll!(&mut stop).is_pending());
+        let state: LifecycleState = lifecycle.state().unwrap().into_serde().unwrap();
+        assert_eq!(state, LifecycleState::Stopping);
+        assert!(matches!(
+            nm.get_wallet_balance(),
+            Err(MutinyJsError::WalletStopping)
+        ));
+        assert!(matches!(
+            nm.list_nodes().await,
+            Err(MutinyJsError::WalletStopping)
+        ));
+        assert!(matches!(
+            nm.create_invoice(Some(1_000), JsValue::NULL).await,
+            Err(MutinyJsError::WalletStopping)
+        ));
+        stop.await.expect("should stop");
+
+        // stopped
+        let state: LifecycleState = nm.get_lifecycle_state().unwrap().into_serde().unwrap();
+        assert_eq!(state, LifecycleState::Stopped);
+        assert!(matches!(
+            lifecycle.check_running(),
+            Err(MutinyJsError::WalletStopped)
+        ));
+        assert!(matches!(
+            nm.get_wallet_balance(),
+            Err(MutinyJsError::WalletStopped)
+        ));
+        assert!(matches!(
+            nm.list_nodes().await,
+            Err(MutinyJsError::WalletStopped)
+        ));
+        assert!(matches!(
+            nm.create_invoice(Some(1_000), JsValue::NULL).await,
+            Err(MutinyJsError::WalletStopped)
+        ));
+        // calls that do not need the wallet to be running still work
+        assert!(!nm.show_seed().is_empty());
+
+        IndexedDbStorage::clear()
+            .await
+            .expect("failed to clear storage");
+    }
+
+    #[test]
+    async fn degraded_when_a_node_fails_to_start() {
+        log!("starting degraded when a node fails to start");
+
+        let (nm, _) = new_wallet_with_lifecycle().await;
+        nm.stop().await.expect("should stop");
+
+        // make the channel manager of the node unreadable
+        let logger = Arc::new(MutinyLogger::default());
+        let storage = IndexedDbStorage::new(Some("password".to_string()), logger)
+            .await
+            .unwrap();
+        let node_id = storage
+            .get_nodes()
+            .unwrap()
+            .nodes
+            .into_keys()
+            .next()
+            .unwrap();
+        for key in [CHANNEL_MANAGER_KEY, CHANNEL_MANAGER_ALT_KEY] {
+            storage
+                .set_data(format!("{key}_{node_id}"), "not a channel manager")
+                .unwrap();
+        }
+        // wait for the storage to be persisted
+        sleep(1_000).await;
+
+        let (nm, lifecycle) = new_wallet_with_lifecycle().await;
+        let state: LifecycleState = lifecycle.state().unwrap().into_serde().unwrap();
+        let LifecycleState::Degraded(failures) = state else {
+            panic!("expected the wallet to be degraded, got {state:?}");
+        };
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].component, WalletComponent::Lightning);
+        assert_eq!(failures[0].node_id, Some(node_id));
+        assert!(!failures[0].error.is_empty());
+
+        // on-chain still works while lightning errors specifically
+        assert!(lifecycle.check_running().is_ok());
+        assert!(nm.get_wallet_balance().is_ok());
+        assert!(matches!(
+            nm.list_nodes().await,
+            Err(MutinyJsError::ComponentUnavailable(
+                WalletComponent::Lightning
+            ))
+        ));
+        assert!(matches!(
+            nm.create_invoice(Some(1_000), JsValue::NULL).await,
+            Err(MutinyJsError::ComponentUnavailable(
+                WalletComponent::Lightning
+            ))
+        ));
+
+        nm.stop().await.expect("should stop");
+        IndexedDbStorage::clear()
+            .await
+            .expect("failed to clear storage");
+    }
 }
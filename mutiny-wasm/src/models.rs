@@ -6,6 +6,7 @@ use lightning_invoice::{Invoice, InvoiceDescription};
 use lnurl::lightning_address::LightningAddress;
 use lnurl::lnurl::LnUrl;
 use mutiny_core::labels::Contact as MutinyContact;
+use mutiny_core::lifecycle::Lifecycle;
 use mutiny_core::redshift::{RedshiftRecipient, RedshiftStatus};
 use mutiny_core::*;
 use serde::{Deserialize, Serialize};
@@ -23,11 +24,34 @@ pub enum ActivityType {
     ChannelClose,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[wasm_bindgen]
+pub enum PaymentStatus {
+    Pending,
+    InFlight,
+    Succeeded,
+    Failed,
+    /// An unpaid invoice that is past its expiry
+    Expired,
+}
+
+impl From<HTLCStatus> for PaymentStatus {
+    fn from(status: HTLCStatus) -> Self {
+        match status {
+            HTLCStatus::Pending => PaymentStatus::Pending,
+            HTLCStatus::InFlight => PaymentStatus::InFlight,
+            HTLCStatus::Succeeded => PaymentStatus::Succeeded,
+            HTLCStatus::Failed => PaymentStatus::Failed,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[wasm_bindgen]
 pub struct ActivityItem {
     pub kind: ActivityType,
     id: String,
+    #[serde(with = "safe_int::option")]
     pub amount_sats: Option<u64>,
     pub inbound: bool,
     pub(crate) labels: Vec<String>,
@@ -56,6 +80,12 @@ impl ActivityItem {
     pub fn contacts(&self) -> JsValue /* Vec<Contact> */ {
         JsValue::from_serde(&self.contacts).unwrap()
     }
+
+    /// The amount as a string, it can be too large for a JS number.
+    #[wasm_bindgen(getter)]
+    pub fn amount_sats_string(&self) -> Option<String> {
+        self.amount_sats.map(|a| a.to_string())
+    }
 }
 
 impl From<nodemanager::ActivityItem> for ActivityItem {
@@ -114,9 +144,15 @@ pub struct MutinyInvoice {
     payment_hash: String,
     preimage: Option<String>,
     payee_pubkey: Option<String>,
+    #[serde(with = "safe_int::option")]
     pub amount_sats: Option<u64>,
+    #[serde(with = "safe_int")]
     pub expire: u64,
+    status: PaymentStatus,
     pub paid: bool,
+    pub settled_externally: bool,
+    external_settlement_note: Option<String>,
+    #[serde(with = "safe_int::option")]
     pub fees_paid: Option<u64>,
     pub inbound: bool,
     pub last_updated: u64,
@@ -155,10 +191,91 @@ impl MutinyInvoice {
         self.payee_pubkey.clone()
     }
 
+    /// The status of the payment, pending invoices are expired once past their expiry.
+    #[wasm_bindgen(getter)]
+    pub fn status(&self) -> PaymentStatus {
+        self.status_at(utils::now().as_secs())
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn external_settlement_note(&self) -> Option<String> {
+        self.external_settlement_note.clone()
+    }
+
     #[wasm_bindgen(getter)]
     pub fn labels(&self) -> JsValue /* Vec<String> */ {
         JsValue::from_serde(&self.labels).unwrap()
     }
+
+    /// If the invoice is past its expiry without being paid, see `status`.
+    #[wasm_bindgen(getter)]
+    pub fn is_expired(&self) -> bool {
+        self.status() == PaymentStatus::Expired
+    }
+
+    /// The seconds left until the invoice expires, None once it expired or if it
+    /// can no longer expire because it was paid or the payment is underway.
+    #[wasm_bindgen(getter)]
+    pub fn seconds_until_expiry(&self) -> Option<u64> {
+        self.seconds_until_expiry_at(utils::now().as_secs())
+    }
+
+    /// The amount as a string, invoices can ask for more than a JS number holds.
+    #[wasm_bindgen(getter)]
+    pub fn amount_sats_string(&self) -> Option<String> {
+        self.amount_sats.map(|a| a.to_string())
+    }
+
+    /// The expiry as a string, invoices can set one too large for a JS number.
+    #[wasm_bindgen(getter)]
+    pub fn expire_string(&self) -> String {
+        self.expire.to_string()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn fees_paid_string(&self) -> Option<String> {
+        self.fees_paid.map(|f| f.to_string())
+    }
+
+    /// The network the invoice is for, like `bitcoin` or `signet`.
+    #[wasm_bindgen(getter)]
+    pub fn network(&self) -> Option<String> {
+        self.bolt11.as_ref().map(|b| b.network().to_string())
+    }
+
+    /// Decodes a bolt11 invoice that was not created or paid by this wallet, for
+    /// example one pasted by the user, so it can be shown. Invoices for any
+    /// network are accepted, see `network`. The invoice is unpaid and inbound.
+    #[wasm_bindgen]
+    pub fn from_bolt11(invoice: String) -> Result<MutinyInvoice, MutinyJsError> {
+        let invoice = Invoice::from_str(invoice.trim())?;
+        // most invoices leave out the payee, it is recovered from the signature
+        let payee_pubkey = invoice
+            .payee_pub_key()
+            .copied()
+            .unwrap_or_else(|| invoice.recover_payee_pub_key());
+
+        let mut invoice: nodemanager::MutinyInvoice = invoice.into();
+        invoice.payee_pubkey = Some(payee_pubkey);
+        Ok(invoice.into())
+    }
+}
+
+impl MutinyInvoice {
+    fn status_at(&self, now: u64) -> PaymentStatus {
+        if self.status == PaymentStatus::Pending && self.expire < now {
+            PaymentStatus::Expired
+        } else {
+            self.status
+        }
+    }
+
+    fn seconds_until_expiry_at(&self, now: u64) -> Option<u64> {
+        match self.status_at(now) {
+            PaymentStatus::Pending => Some(self.expire - now),
+            _ => None,
+        }
+    }
 }
 
 impl From<nodemanager::MutinyInvoice> for MutinyInvoice {
@@ -171,7 +288,10 @@ impl From<nodemanager::MutinyInvoice> for MutinyInvoice {
             payee_pubkey: m.payee_pubkey.map(|p| p.to_hex()),
             amount_sats: m.amount_sats,
             expire: m.expire,
+            status: m.status.into(),
             paid: m.paid,
+            settled_externally: m.settled_externally,
+            external_settlement_note: m.external_settlement_note,
             fees_paid: m.fees_paid,
             inbound: m.inbound,
             last_updated: m.last_updated,
@@ -189,6 +309,7 @@ pub struct MutinyPeer {
     color: Option<String>,
     label: Option<String>,
     pub is_connected: bool,
+    pub queued_messages: usize,
 }
 
 #[wasm_bindgen]
@@ -233,6 +354,7 @@ impl From<nodemanager::MutinyPeer> for MutinyPeer {
             color: m.color,
             label: m.label,
             is_connected: m.is_connected,
+            queued_messages: m.queued_messages,
         }
     }
 }
@@ -352,6 +474,9 @@ pub struct MutinyBalance {
     pub unconfirmed: u64,
     pub lightning: u64,
     pub force_close: u64,
+    pub pending_recovery: u64,
+    pub sequence: u64,
+    pub taken_at: u64,
 }
 
 #[wasm_bindgen]
@@ -369,6 +494,9 @@ impl From<nodemanager::MutinyBalance> for MutinyBalance {
             unconfirmed: m.unconfirmed,
             lightning: m.lightning,
             force_close: m.force_close,
+            pending_recovery: m.pending_recovery,
+            sequence: m.sequence,
+            taken_at: m.taken_at,
         }
     }
 }
@@ -376,7 +504,9 @@ impl From<nodemanager::MutinyBalance> for MutinyBalance {
 #[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
 #[wasm_bindgen]
 pub struct LnUrlParams {
+    #[serde(with = "safe_int")]
     pub max: u64,
+    #[serde(with = "safe_int")]
     pub min: u64,
     tag: String,
 }
@@ -392,6 +522,17 @@ impl LnUrlParams {
     pub fn tag(&self) -> String {
         self.tag.clone()
     }
+
+    /// The maximum as a string, the LNURL service can send one too large for a JS number.
+    #[wasm_bindgen(getter)]
+    pub fn max_string(&self) -> String {
+        self.max.to_string()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn min_string(&self) -> String {
+        self.min.to_string()
+    }
 }
 
 impl From<nodemanager::LnUrlParams> for LnUrlParams {
@@ -440,6 +581,51 @@ impl From<nodemanager::NodeIdentity> for NodeIdentity {
     }
 }
 
+/// A node in a static channel backup and how it was set up.
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+#[wasm_bindgen]
+pub struct NodeIndex {
+    pubkey: PublicKey,
+    pub child_index: u32,
+    lsp: Option<String>,
+    archived: bool,
+}
+
+#[wasm_bindgen]
+impl NodeIndex {
+    #[wasm_bindgen(getter)]
+    pub fn value(&self) -> JsValue {
+        JsValue::from_serde(&serde_json::to_value(self).unwrap()).unwrap()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn pubkey(&self) -> String {
+        self.pubkey.to_string()
+    }
+
+    /// The url of the LSP the node was using
+    #[wasm_bindgen(getter)]
+    pub fn lsp(&self) -> Option<String> {
+        self.lsp.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn is_archived(&self) -> bool {
+        self.archived
+    }
+}
+
+impl From<(PublicKey, nodemanager::NodeIndex)> for NodeIndex {
+    fn from((pubkey, n): (PublicKey, nodemanager::NodeIndex)) -> Self {
+        NodeIndex {
+            pubkey,
+            child_index: n.child_index(),
+            lsp: n.lsp().map(|l| l.to_string()),
+            archived: n.is_archived(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
 #[wasm_bindgen]
 pub struct MutinyBip21RawMaterials {
@@ -807,6 +993,7 @@ pub struct NwcProfile {
     name: String,
     pub index: u32,
     /// Maximum amount of sats that can be sent in a single payment
+    #[serde(with = "safe_int")]
     pub max_single_amt_sats: u64,
     relay: String,
     pub enabled: bool,
@@ -836,6 +1023,12 @@ impl NwcProfile {
     pub fn nwc_uri(&self) -> String {
         self.nwc_uri.clone()
     }
+
+    /// The maximum as a string, it can be set too large for a JS number.
+    #[wasm_bindgen(getter)]
+    pub fn max_single_amt_sats_string(&self) -> String {
+        self.max_single_amt_sats.to_string()
+    }
 }
 
 impl From<nostr::nwc::NwcProfile> for NwcProfile {
@@ -862,10 +1055,12 @@ pub struct PendingNwcInvoice {
     /// The id of the invoice, this is the payment hash
     pub id: String,
     /// The amount of sats that the invoice is for
+    #[serde(with = "safe_int")]
     pub amount_sats: u64,
     /// The description of the invoice
     pub invoice_description: Option<String>,
     /// Invoice expire time in seconds since epoch
+    #[serde(with = "safe_int")]
     pub expiry: u64,
 }
 
@@ -890,6 +1085,52 @@ impl From<nostr::nwc::PendingNwcInvoice> for PendingNwcInvoice {
     }
 }
 
+/// A handle on the lifecycle of a wallet, created before the wallet so its startup
+/// can be followed while [`crate::MutinyWallet::new`] has not returned yet.
+#[wasm_bindgen]
+pub struct WalletLifecycle {
+    inner: Lifecycle,
+}
+
+#[wasm_bindgen]
+impl WalletLifecycle {
+    #[wasm_bindgen(constructor)]
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> WalletLifecycle {
+        WalletLifecycle {
+            inner: Lifecycle::new(),
+        }
+    }
+
+    /// Another handle on the same lifecycle, to pass to [`crate::MutinyWallet::new`]
+    /// while keeping this one.
+    #[wasm_bindgen]
+    pub fn handle(&self) -> WalletLifecycle {
+        WalletLifecycle {
+            inner: self.inner.clone(),
+        }
+    }
+
+    /// Where the wallet is in its lifecycle, with the progress while initializing.
+    #[wasm_bindgen]
+    pub fn state(&self) -> Result<JsValue /* LifecycleState */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.state())?)
+    }
+
+    /// Fails with the same typed error the wallet calls fail with while it is
+    /// not running, such as [`MutinyJsError::WalletInitializing`] with its progress.
+    #[wasm_bindgen]
+    pub fn check_running(&self) -> Result<(), MutinyJsError> {
+        Ok(self.inner.check_running()?)
+    }
+}
+
+impl From<WalletLifecycle> for Lifecycle {
+    fn from(l: WalletLifecycle) -> Self {
+        l.inner
+    }
+}
+
 // This is a subscription plan for Mutiny+
 #[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
 #[wasm_bindgen]
@@ -914,3 +1155,377 @@ impl From<nodemanager::Plan> for Plan {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::test::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    const BOLT_11: &str = "lntbs1m1pjrmuu3pp52hk0j956d7s8azaps87amadshnrcvqtkvk06y2nue2w69g6e5vasdqqcqzpgxqyz5vqsp5wu3py6257pa3yzarw0et2200c08r5fu6k3u94yfwmlnc8skdkc9s9qyyssqc783940p82c64qq9pu3xczt4tdxzex9wpjn54486y866aayft2cxxusl9eags4cs3kcmuqdrvhvs0gudpj5r2a6awu4wcq29crpesjcqhdju55";
+
+    #[test]
+    fn test_expired_invoice_status() {
+        log!("test expired invoice status");
+
+        let invoice: nodemanager::MutinyInvoice = Invoice::from_str(BOLT_11).unwrap().into();
+        let expire = invoice.expire;
+        let invoice: MutinyInvoice = invoice.into();
+
+        assert!(!invoice.paid);
+        assert_eq!(invoice.status_at(expire - 1), PaymentStatus::Pending);
+        assert_eq!(invoice.status_at(expire), PaymentStatus::Pending);
+        assert_eq!(invoice.status_at(expire + 1), PaymentStatus::Expired);
+        // the invoice expired long ago
+        assert_eq!(invoice.status(), PaymentStatus::Expired);
+        assert!(invoice.is_expired());
+    }
+
+    #[test]
+    fn test_seconds_until_expiry() {
+        log!("test seconds until expiry");
+
+        let invoice: nodemanager::MutinyInvoice = Invoice::from_str(BOLT_11).unwrap().into();
+        let expire = invoice.expire;
+        let pending: MutinyInvoice = invoice.clone().into();
+
+        assert_eq!(pending.seconds_until_expiry_at(expire - 60), Some(60));
+        assert_eq!(pending.seconds_until_expiry_at(expire - 1), Some(1));
+        assert_eq!(pending.seconds_until_expiry_at(expire), Some(0));
+        assert_eq!(pending.seconds_until_expiry_at(expire + 1), None);
+        assert_eq!(pending.seconds_until_expiry(), None);
+
+        // a paid invoice is not expired, even past its expiry
+        let paid: MutinyInvoice = nodemanager::MutinyInvoice {
+            status: HTLCStatus::Succeeded,
+            paid: true,
+            ..invoice
+        }
+        .into();
+        assert!(!paid.is_expired());
+        assert_eq!(paid.seconds_until_expiry_at(expire - 1), None);
+        assert_eq!(paid.seconds_until_expiry_at(expire + 1), None);
+        assert_eq!(paid.seconds_until_expiry(), None);
+    }
+
+    #[test]
+    fn test_invoice_from_bolt11() {
+        log!("test invoice from bolt11");
+
+        let invoice = MutinyInvoice::from_bolt11(BOLT_11.to_string()).unwrap();
+        let parsed = Invoice::from_str(BOLT_11).unwrap();
+        assert_eq!(invoice.bolt11(), Some(BOLT_11.to_string()));
+        assert_eq!(invoice.network(), Some("signet".to_string()));
+        assert_eq!(invoice.amount_sats, Some(100_000));
+        assert_eq!(invoice.payment_hash(), parsed.payment_hash().to_hex());
+        assert_eq!(
+            invoice.payee_pubkey(),
+            Some(parsed.recover_payee_pub_key().to_hex())
+        );
+        assert_eq!(
+            invoice.expire,
+            parsed.duration_since_epoch().as_secs() + parsed.expiry_time().as_secs()
+        );
+        assert_eq!(invoice.description(), None);
+        assert_eq!(invoice.status, PaymentStatus::Pending);
+        assert!(!invoice.paid);
+        assert!(invoice.inbound);
+        assert_eq!(invoice.fees_paid, None);
+
+        // mainnet, with a description hash
+        let mainnet = "lnbc923720n1pj9nr6zpp5xmvlq2u5253htn52mflh2e6gn7pk5ht0d4qyhc62fadytccxw7hqhp5l4s6qwh57a7cwr7zrcz706qx0qy4eykcpr8m8dwz08hqf362egfscqzzsxqzfvsp5pr7yjvcn4ggrf6fq090zey0yvf8nqvdh2kq7fue0s0gnm69evy6s9qyyssqjyq0fwjr22eeg08xvmz88307yqu8tqqdjpycmermks822fpqyxgshj8hvnl9mkh6srclnxx0uf4ugfq43d66ak3rrz4dqcqd23vxwpsqf7dmhm";
+        let invoice = MutinyInvoice::from_bolt11(format!(" {mainnet}\n")).unwrap();
+        assert_eq!(invoice.network(), Some("bitcoin".to_string()));
+        assert_eq!(invoice.amount_sats, Some(92_372));
+        assert!(invoice.payee_pubkey().is_some());
+
+        // a truncated invoice, and something else entirely
+        assert!(MutinyInvoice::from_bolt11(BOLT_11[..BOLT_11.len() - 1].to_string()).is_err());
+        assert!(MutinyInvoice::from_bolt11("not an invoice".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_in_flight_payment_status() {
+        log!("test in flight payment status");
+
+        let invoice: nodemanager::MutinyInvoice = Invoice::from_str(BOLT_11).unwrap().into();
+        let expire = invoice.expire;
+        let sending = nodemanager::MutinyInvoice {
+            status: HTLCStatus::InFlight,
+            inbound: false,
+            ..invoice.clone()
+        };
+        let sending: MutinyInvoice = sending.into();
+
+        // payments that are underway never expire
+        assert!(!sending.paid);
+        assert_eq!(sending.status_at(expire + 1), PaymentStatus::InFlight);
+
+        let failed: MutinyInvoice = nodemanager::MutinyInvoice {
+            status: HTLCStatus::Failed,
+            inbound: false,
+            ..invoice.clone()
+        }
+        .into();
+        assert_eq!(failed.status_at(expire + 1), PaymentStatus::Failed);
+
+        let paid: MutinyInvoice = nodemanager::MutinyInvoice {
+            status: HTLCStatus::Succeeded,
+            paid: true,
+            ..invoice
+        }
+        .into();
+        assert!(paid.paid);
+        assert_eq!(paid.status_at(expire + 1), PaymentStatus::Succeeded);
+    }
+
+    /// Integers around where JS numbers stop being exact.
+    const BOUNDARY: [u64; 3] = [
+        safe_int::MAX_SAFE_INTEGER,
+        safe_int::MAX_SAFE_INTEGER + 1,
+        safe_int::MAX_SAFE_INTEGER + 2,
+    ];
+
+    /// The u64 fields of the models, and if they can get too large for a JS number.
+    /// Large fields have to be serialized with `safe_int` and get a string getter.
+    const U64_FIELDS: &[(&str, &str, bool)] = &[
+        ("ActivityItem", "amount_sats", true),
+        ("ActivityItem", "last_updated", false),
+        ("MutinyInvoice", "amount_sats", true),
+        ("MutinyInvoice", "expire", true),
+        ("MutinyInvoice", "fees_paid", true),
+        ("MutinyInvoice", "last_updated", false),
+        ("MutinyChannel", "balance", false),
+        ("MutinyChannel", "size", false),
+        ("MutinyChannel", "reserve", false),
+        ("ChannelClosure", "timestamp", false),
+        ("MutinyBalance", "confirmed", false),
+        ("MutinyBalance", "unconfirmed", false),
+        ("MutinyBalance", "lightning", false),
+        ("MutinyBalance", "force_close", false),
+        ("MutinyBalance", "pending_recovery", false),
+        ("MutinyBalance", "sequence", false),
+        ("MutinyBalance", "taken_at", false),
+        ("LnUrlParams", "max", true),
+        ("LnUrlParams", "min", true),
+        ("Redshift", "amount_sats", false),
+        ("Redshift", "sats_sent", false),
+        ("Redshift", "change_amt", false),
+        ("Redshift", "fees_paid", false),
+        ("TagItem", "last_used_time", false),
+        ("Contact", "last_used", false),
+        ("NwcProfile", "max_single_amt_sats", true),
+        ("PendingNwcInvoice", "amount_sats", true),
+        ("PendingNwcInvoice", "expiry", true),
+        ("Plan", "amount_sat", false),
+    ];
+
+    fn assert_safe_json(json: &serde_json::Value, field: &str, n: u64) {
+        assert_eq!(json[field], safe_int::to_json_value(n), "{field} at {n}");
+        if n > safe_int::MAX_SAFE_INTEGER {
+            assert_eq!(json[field], n.to_string());
+        }
+    }
+
+    #[test]
+    fn test_u64_fields_audited() {
+        log!("test u64 fields audited");
+
+        // a new u64 field fails this until it is added to U64_FIELDS
+        let mut found = vec![];
+        let mut model = None;
+        let mut previous = "";
+        for line in include_str!("models.rs").lines() {
+            if line.starts_with("mod test") {
+                break;
+            }
+            if let Some(name) = line.strip_prefix("pub struct ") {
+                model = name.strip_suffix(" {");
+            } else if line == "}" {
+                model = None;
+            } else if let Some(model) = model {
+                let field = line.trim().trim_start_matches("pub ");
+                if let Some((name, ty)) = field.split_once(": ") {
+                    if ty == "u64," || ty == "Option<u64>," {
+                        found.push((model, name, previous.contains("safe_int")));
+                    }
+                }
+            }
+            previous = line;
+        }
+        assert_eq!(found, U64_FIELDS);
+    }
+
+    #[test]
+    fn test_node_index_conversion() {
+        log!("test node index conversion");
+
+        let pubkey = PublicKey::from_str(
+            "02cae09cf2c8842ace44068a5bf3117a494ebbf69a99e79712483c36f97cdb7b54",
+        )
+        .unwrap();
+        let node_index = nodemanager::NodeIndex {
+            child_index: 1,
+            lsp: Some("https://signet-lsp.mutinywallet.com".to_string()),
+            archived: None,
+            created_at: None,
+            alias: None,
+        };
+
+        let converted: NodeIndex = (pubkey, node_index.clone()).into();
+        assert_eq!(converted.pubkey(), pubkey.to_string());
+        assert_eq!(converted.child_index, 1);
+        assert_eq!(
+            converted.lsp(),
+            Some("https://signet-lsp.mutinywallet.com".to_string())
+        );
+        assert!(!converted.is_archived());
+
+        let archived = nodemanager::NodeIndex {
+            archived: Some(true),
+            lsp: None,
+            ..node_index
+        };
+        let converted: NodeIndex = (pubkey, archived).into();
+        assert_eq!(converted.lsp(), None);
+        assert!(converted.is_archived());
+    }
+
+    #[test]
+    fn test_balance_and_channel_serde() {
+        log!("test balance and channel serde");
+
+        let balance: MutinyBalance = nodemanager::MutinyBalance {
+            confirmed: 100_000,
+            unconfirmed: 2_000,
+            lightning: 50_000,
+            force_close: 1_000,
+            pending_recovery: 1_500,
+            sequence: 7,
+            taken_at: 1_700_000_000,
+        }
+        .into();
+        let json = serde_json::to_string(&balance).unwrap();
+        assert!(serde_json::from_str::<MutinyBalance>(&json).unwrap() == balance);
+
+        let outpoint = OutPoint::from_str(
+            "fd13d0d6d1a7e2eab7ed5a7d2a5b4b1d8c1f3b0c0e2c5f3a9d7b6e4f2a1c0b9d:1",
+        )
+        .unwrap();
+        let peer = PublicKey::from_str(
+            "02cae09cf2c8842ace44068a5bf3117a494ebbf69a99e79712483c36f97cdb7b54",
+        )
+        .unwrap();
+        let channel: MutinyChannel = nodemanager::MutinyChannel {
+            user_chan_id: "0".to_string(),
+            balance: 40_000,
+            size: 100_000,
+            reserve: 1_000,
+            outpoint: Some(outpoint),
+            peer,
+            confirmations_required: Some(3),
+            confirmations: 1,
+        }
+        .into();
+        let json = serde_json::to_value(&channel).unwrap();
+        // the outpoint and peer are written in their string form
+        assert_eq!(json["outpoint"], outpoint.to_string());
+        assert_eq!(json["peer"], peer.to_string());
+        assert!(serde_json::from_value::<MutinyChannel>(json).unwrap() == channel);
+
+        let pending = MutinyChannel {
+            outpoint: None,
+            confirmations_required: None,
+            ..channel
+        };
+        let json = serde_json::to_string(&pending).unwrap();
+        assert!(serde_json::from_str::<MutinyChannel>(&json).unwrap() == pending);
+    }
+
+    #[test]
+    fn test_invoice_large_integers() {
+        log!("test invoice large integers");
+
+        let invoice: nodemanager::MutinyInvoice = Invoice::from_str(BOLT_11).unwrap().into();
+        for n in BOUNDARY {
+            let large = nodemanager::MutinyInvoice {
+                amount_sats: Some(n),
+                expire: n,
+                fees_paid: Some(n),
+                ..invoice.clone()
+            };
+
+            let activity: ActivityItem =
+                nodemanager::ActivityItem::Lightning(Box::new(large.clone())).into();
+            let json = serde_json::to_value(&activity).unwrap();
+            assert_safe_json(&json, "amount_sats", n);
+            assert_eq!(activity.amount_sats_string(), Some(n.to_string()));
+            assert_eq!(
+                serde_json::from_value::<ActivityItem>(json).unwrap(),
+                activity
+            );
+
+            let large: MutinyInvoice = large.into();
+            let json = serde_json::to_value(&large).unwrap();
+            assert_safe_json(&json, "amount_sats", n);
+            assert_safe_json(&json, "expire", n);
+            assert_safe_json(&json, "fees_paid", n);
+            assert_eq!(large.amount_sats_string(), Some(n.to_string()));
+            assert_eq!(large.expire_string(), n.to_string());
+            assert_eq!(large.fees_paid_string(), Some(n.to_string()));
+            assert!(serde_json::from_value::<MutinyInvoice>(json).unwrap() == large);
+        }
+    }
+
+    #[test]
+    fn test_lnurl_and_nwc_large_integers() {
+        log!("test lnurl and nwc large integers");
+
+        for n in BOUNDARY {
+            let params = LnUrlParams {
+                max: n,
+                min: n,
+                tag: "payRequest".to_string(),
+            };
+            let json = serde_json::to_value(&params).unwrap();
+            assert_safe_json(&json, "max", n);
+            assert_safe_json(&json, "min", n);
+            assert_eq!(params.max_string(), n.to_string());
+            assert_eq!(params.min_string(), n.to_string());
+            assert!(serde_json::from_value::<LnUrlParams>(json).unwrap() == params);
+
+            let profile = NwcProfile {
+                name: "test".to_string(),
+                index: 0,
+                max_single_amt_sats: n,
+                relay: "wss://nostr.mutinywallet.com".to_string(),
+                enabled: true,
+                require_approval: false,
+                nwc_uri: String::new(),
+            };
+            let json = serde_json::to_value(&profile).unwrap();
+            assert_safe_json(&json, "max_single_amt_sats", n);
+            assert_eq!(profile.max_single_amt_sats_string(), n.to_string());
+            assert_eq!(serde_json::from_value::<NwcProfile>(json).unwrap(), profile);
+
+            let pending = PendingNwcInvoice {
+                index: 0,
+                invoice: BOLT_11.to_string(),
+                id: String::new(),
+                amount_sats: n,
+                invoice_description: None,
+                expiry: n,
+            };
+            let json = serde_json::to_value(&pending).unwrap();
+            assert_safe_json(&json, "amount_sats", n);
+            assert_safe_json(&json, "expiry", n);
+            assert_eq!(
+                serde_json::from_value::<PendingNwcInvoice>(json).unwrap(),
+                pending
+            );
+        }
+    }
+}